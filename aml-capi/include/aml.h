@@ -0,0 +1,37 @@
+/* The C ABI of aml, declared by hand to match src/lib.rs. */
+
+#ifndef AML_H
+#define AML_H
+
+#include <stddef.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+/* The same values as CBLAS's CBLAS_ORDER and CBLAS_TRANSPOSE */
+enum AML_ORDER {
+    AML_ROW_MAJOR = 101,
+    AML_COL_MAJOR = 102,
+};
+
+enum AML_TRANSPOSE {
+    AML_NO_TRANS = 111,
+    AML_TRANS = 112,
+    AML_CONJ_TRANS = 113,
+};
+
+/* c = alpha * op(a) * op(b) + beta * c, as cblas_sgemm */
+void aml_sgemm(int order, int transa, int transb, int m, int n, int k, float alpha,
+               const float *a, int lda, const float *b, int ldb, float beta, float *c, int ldc);
+
+/* len zeroed floats, freed with aml_free_f32; for callers such as JavaScript that cannot
+   allocate in a WebAssembly module's memory themselves */
+float *aml_alloc_f32(size_t len);
+void aml_free_f32(float *ptr, size_t len);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif
@@ -0,0 +1,180 @@
+//! A C ABI with the CBLAS signatures, so C and C++ code can link aml as a BLAS subset.
+//!
+//! Building this crate produces `libaml_capi.a` and `libaml_capi.so`, which hold aml itself,
+//! and `include/aml.h` declares everything here. The enum values are CBLAS's own, so code written
+//! against `cblas.h` only needs the `cblas_` prefix changed to `aml_`. Invalid arguments are
+//! reported on stderr and leave the output untouched, as CBLAS's `xerbla` does; a panic does
+//! not unwind into C.
+
+use aml::{try_sgemm_with, GemmParams, Layout, TensorMut, TensorRef};
+use std::ffi::c_int;
+use std::panic::{catch_unwind, AssertUnwindSafe};
+
+mod tests;
+
+pub const AML_ROW_MAJOR: c_int = 101;
+pub const AML_COL_MAJOR: c_int = 102;
+pub const AML_NO_TRANS: c_int = 111;
+pub const AML_TRANS: c_int = 112;
+/// The same as `AML_TRANS` for real matrices
+pub const AML_CONJ_TRANS: c_int = 113;
+
+/// `c = alpha * op(a) * op(b) + beta * c`, as `cblas_sgemm`. `c` is `m` by `n` and `op(a)`
+/// `m` by `k`, each stored in `order` with its leading dimension, and `c` is not read when
+/// `beta` is zero.
+///
+/// # Safety
+/// Each pointer must address every value its matrix spans, from the first up to the last,
+/// and `c` must not overlap `a` or `b`. Pointers of matrices with no values are not read.
+#[no_mangle]
+pub unsafe extern "C" fn aml_sgemm(
+    order: c_int,
+    transa: c_int,
+    transb: c_int,
+    m: c_int,
+    n: c_int,
+    k: c_int,
+    alpha: f32,
+    a: *const f32,
+    lda: c_int,
+    b: *const f32,
+    ldb: c_int,
+    beta: f32,
+    c: *mut f32,
+    ldc: c_int,
+) {
+    let layout = match order {
+        AML_ROW_MAJOR => Layout::RowMajor,
+        AML_COL_MAJOR => Layout::ColMajor,
+        _ => return xerbla(1, "aml_sgemm"),
+    };
+    let Some(a_transpose) = transpose(transa) else {
+        return xerbla(2, "aml_sgemm");
+    };
+    let Some(b_transpose) = transpose(transb) else {
+        return xerbla(3, "aml_sgemm");
+    };
+    let dims = [m, n, k, lda, ldb, ldc].map(|dim| usize::try_from(dim).ok());
+    let [Some(m), Some(n), Some(k), Some(lda), Some(ldb), Some(ldc)] = dims else {
+        let bad = dims.iter().position(Option::is_none).unwrap();
+        return xerbla([4, 5, 6, 9, 11, 14][bad], "aml_sgemm");
+    };
+    let a_shape = match a_transpose {
+        true => [k, m],
+        false => [m, k],
+    };
+    let b_shape = match b_transpose {
+        true => [n, k],
+        false => [k, n],
+    };
+    // the first argument a leading dimension is too short for, as CBLAS numbers them
+    let bad_ld = [(9, a_shape, lda), (11, b_shape, ldb), (14, [m, n], ldc)]
+        .into_iter()
+        .find(|(_, shape, ld)| *ld < inner(*shape, layout).max(1));
+    if let Some((argument, _, _)) = bad_ld {
+        return xerbla(argument, "aml_sgemm");
+    }
+    if m == 0 || n == 0 {
+        return;
+    }
+
+    let result = catch_unwind(AssertUnwindSafe(|| {
+        let a = TensorRef::try_new_with_ld(
+            slice(a, span(a_shape, layout, lda)),
+            a_shape.to_vec(),
+            layout,
+            lda,
+        )?;
+        let b = TensorRef::try_new_with_ld(
+            slice(b, span(b_shape, layout, ldb)),
+            b_shape.to_vec(),
+            layout,
+            ldb,
+        )?;
+        let c_len = span([m, n], layout, ldc);
+        let mut c = TensorMut::try_new_with_ld(
+            std::slice::from_raw_parts_mut(c, c_len),
+            vec![m, n],
+            layout,
+            ldc,
+        )?;
+        try_sgemm_with(
+            &a,
+            a_transpose,
+            &b,
+            b_transpose,
+            GemmParams::new(alpha, beta),
+            &mut c,
+        )
+    }));
+    match result {
+        Ok(Ok(())) => {}
+        Ok(Err(e)) => eprintln!("aml_sgemm: {}", e),
+        // the panic hook has already printed the message
+        Err(_) => eprintln!("aml_sgemm: aborted by a panic"),
+    }
+}
+
+/// `len` zeroed values, to fill and pass to `aml_sgemm`, and free with `aml_free_f32`. This is
+/// how JavaScript gets matrices into a WebAssembly build: it cannot allocate in the module's
+/// memory itself, but can view what this returns as
+/// `new Float32Array(memory.buffer, ptr, len)`.
+#[no_mangle]
+pub extern "C" fn aml_alloc_f32(len: usize) -> *mut f32 {
+    Box::into_raw(vec![0f32; len].into_boxed_slice()).cast()
+}
+
+/// Free values from `aml_alloc_f32`, given the same `len`. Null is ignored.
+///
+/// # Safety
+/// `ptr` must be null or from `aml_alloc_f32(len)`, and not freed already.
+#[no_mangle]
+pub unsafe extern "C" fn aml_free_f32(ptr: *mut f32, len: usize) {
+    if !ptr.is_null() {
+        drop(Box::from_raw(std::ptr::slice_from_raw_parts_mut(ptr, len)));
+    }
+}
+
+fn transpose(trans: c_int) -> Option<bool> {
+    match trans {
+        AML_NO_TRANS => Some(false),
+        AML_TRANS | AML_CONJ_TRANS => Some(true),
+        _ => None,
+    }
+}
+
+/// The length of a stored row, or column if column-major
+fn inner([rows, cols]: [usize; 2], layout: Layout) -> usize {
+    match layout {
+        Layout::RowMajor => cols,
+        Layout::ColMajor => rows,
+    }
+}
+
+/// Values from the first of a matrix to its last
+fn span([rows, cols]: [usize; 2], layout: Layout, ld: usize) -> usize {
+    let (outer, inner) = match layout {
+        Layout::RowMajor => (rows, cols),
+        Layout::ColMajor => (cols, rows),
+    };
+    match outer == 0 || inner == 0 {
+        true => 0,
+        false => (outer - 1) * ld + inner,
+    }
+}
+
+/// `len` values from `ptr`, which is not read when `len` is zero and may then be null
+unsafe fn slice<'a>(ptr: *const f32, len: usize) -> &'a [f32] {
+    match len {
+        0 => &[],
+        _ => std::slice::from_raw_parts(ptr, len),
+    }
+}
+
+/// Report an invalid argument, numbered from 1, as the reference CBLAS does
+fn xerbla(argument: usize, routine: &str) {
+    eprintln!(
+        "Parameter {} to routine {} was incorrect",
+        argument, routine
+    );
+}
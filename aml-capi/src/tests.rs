@@ -0,0 +1,172 @@
+#[cfg(test)]
+use crate::*;
+#[cfg(test)]
+use aml::*;
+
+#[test]
+pub fn c_abi_sgemm() {
+    let (m, n, k) = (5, 7, 4);
+    let a = F32Tensor::rand_uniform(vec![m, k], 19);
+    let b = F32Tensor::rand_uniform(vec![k, n], 20);
+    let c0 = F32Tensor::rand_uniform(vec![m, n], 21);
+    let mut expected = c0.to_contiguous();
+    sgemm_with(
+        &a,
+        false,
+        &b,
+        false,
+        GemmParams::new(0.5, 2.),
+        &mut expected,
+    );
+
+    // row-major, a inside a wider buffer
+    let lda = k + 3;
+    let mut wide = vec![f32::NAN; (m - 1) * lda + k];
+    for i in 0..m {
+        wide[i * lda..i * lda + k].copy_from_slice(&a.values[i * k..(i + 1) * k]);
+    }
+    let mut c = c0.to_contiguous();
+    unsafe {
+        aml_sgemm(
+            AML_ROW_MAJOR,
+            AML_NO_TRANS,
+            AML_NO_TRANS,
+            m as i32,
+            n as i32,
+            k as i32,
+            0.5,
+            wide.as_ptr(),
+            lda as i32,
+            b.values.as_ptr(),
+            n as i32,
+            2.,
+            c.values.as_mut_ptr(),
+            n as i32,
+        )
+    };
+    assert!(c.values == expected.values);
+
+    // column-major with b transposed: b^T stored column-major is b's row-major values
+    let col_major = |t: &F32Tensor| {
+        let rows = t.shape[0];
+        let values = (0..t.values.len())
+            .map(|i| t[[i % rows, i / rows]])
+            .collect();
+        F32Tensor::new(values, t.shape.clone()).with_layout(Layout::ColMajor)
+    };
+    let a_col = col_major(&a);
+    let mut c = col_major(&c0);
+    unsafe {
+        aml_sgemm(
+            AML_COL_MAJOR,
+            AML_NO_TRANS,
+            AML_CONJ_TRANS,
+            m as i32,
+            n as i32,
+            k as i32,
+            0.5,
+            a_col.values.as_ptr(),
+            m as i32,
+            b.values.as_ptr(),
+            n as i32,
+            2.,
+            c.values.as_mut_ptr(),
+            m as i32,
+        )
+    };
+    assert!(allclose(&c, &expected, 1e-5, 1e-6).is_ok());
+
+    // bad arguments leave c untouched, and empty matrices need no pointers
+    let mut c = c0.to_contiguous();
+    for (order, trans, lda) in [
+        (100, AML_NO_TRANS, k),
+        (AML_ROW_MAJOR, 0, k),
+        (AML_ROW_MAJOR, AML_NO_TRANS, k - 1),
+    ] {
+        unsafe {
+            aml_sgemm(
+                order,
+                trans,
+                AML_NO_TRANS,
+                m as i32,
+                n as i32,
+                k as i32,
+                1.,
+                a.values.as_ptr(),
+                lda as i32,
+                b.values.as_ptr(),
+                n as i32,
+                0.,
+                c.values.as_mut_ptr(),
+                n as i32,
+            )
+        };
+    }
+    unsafe {
+        aml_sgemm(
+            AML_ROW_MAJOR,
+            AML_NO_TRANS,
+            AML_NO_TRANS,
+            -1,
+            n as i32,
+            k as i32,
+            1.,
+            a.values.as_ptr(),
+            k as i32,
+            b.values.as_ptr(),
+            n as i32,
+            0.,
+            c.values.as_mut_ptr(),
+            n as i32,
+        );
+        aml_sgemm(
+            AML_ROW_MAJOR,
+            AML_NO_TRANS,
+            AML_NO_TRANS,
+            0,
+            n as i32,
+            k as i32,
+            1.,
+            std::ptr::null(),
+            k as i32,
+            b.values.as_ptr(),
+            n as i32,
+            0.,
+            std::ptr::null_mut(),
+            n as i32,
+        );
+    }
+    assert!(c.values == c0.values);
+
+    // with k 0, c is only scaled
+    unsafe {
+        aml_sgemm(
+            AML_ROW_MAJOR,
+            AML_NO_TRANS,
+            AML_NO_TRANS,
+            m as i32,
+            n as i32,
+            0,
+            1.,
+            std::ptr::null(),
+            1,
+            std::ptr::null(),
+            n as i32,
+            3.,
+            c.values.as_mut_ptr(),
+            n as i32,
+        )
+    };
+    assert!(c.values.iter().zip(&c0.values).all(|(c, c0)| *c == 3. * c0));
+
+    // buffers allocated for the caller, as JavaScript needs under WebAssembly
+    let ptr = aml_alloc_f32(m * n);
+    unsafe {
+        let values = std::slice::from_raw_parts_mut(ptr, m * n);
+        assert!(values.iter().all(|v| *v == 0.));
+        values.copy_from_slice(&c0.values);
+        aml_free_f32(ptr, m * n);
+        aml_free_f32(aml_alloc_f32(0), 0);
+        aml_free_f32(std::ptr::null_mut(), 3);
+    }
+}
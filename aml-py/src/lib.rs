@@ -0,0 +1,233 @@
+//! Python bindings for the f32 BLAS routines, over anything with the buffer protocol: NumPy
+//! arrays, `array.array` and `memoryview`s of them.
+//!
+//! `pip install ./aml-py` builds the `aml_py` module with maturin. Its functions write their
+//! result into an output array the caller allocates, as BLAS does, so nothing is copied: the
+//! operands are read where they are, at any nonnegative strides, and the output written in
+//! place. Only an operand with a negative stride (`x[::-1]`) is copied first. Mismatched
+//! shapes raise `ValueError` with aml's message, and a buffer that is not float32 a
+//! `BufferError`. The GIL is held throughout, so no other Python thread can change the arrays
+//! while they are read.
+
+use aml::{try_sgemm_with, try_sgemv_with, AmlError, CowTensor, GemmParams, Layout, Tensor};
+use aml::{TensorMut, TensorRef};
+use pyo3::buffer::PyBuffer;
+use pyo3::exceptions::PyValueError;
+use pyo3::prelude::*;
+use std::borrow::Cow;
+use std::ops::Range;
+
+mod tests;
+
+/// `c = alpha * op(a) @ op(b) + beta * c`, where `op` transposes its matrix when `trans_a` or
+/// `trans_b` is set. `c` must be writable, with its rows or columns contiguous, and does not
+/// need to be initialized when `beta` is 0.
+#[pyfunction]
+#[pyo3(signature = (a, b, c, trans_a = false, trans_b = false, alpha = 1.0, beta = 0.0))]
+#[allow(clippy::too_many_arguments)]
+fn sgemm(
+    py: Python<'_>,
+    a: &Bound<'_, PyAny>,
+    b: &Bound<'_, PyAny>,
+    c: &Bound<'_, PyAny>,
+    trans_a: bool,
+    trans_b: bool,
+    alpha: f32,
+    beta: f32,
+) -> PyResult<()> {
+    let (a, b, mut c) = (buffer(a)?, buffer(b)?, buffer(c)?);
+    check_disjoint(&c, "c", &[(&a, "a"), (&b, "b")])?;
+    let (a, b) = (operand(py, &a)?, operand(py, &b)?);
+    let mut c = output(&mut c, "c")?;
+    try_sgemm_with(
+        &a,
+        trans_a,
+        &b,
+        trans_b,
+        GemmParams::new(alpha, beta),
+        &mut c,
+    )
+    .map_err(value_error)
+}
+
+/// `y = alpha * op(a) @ x + beta * y` for vectors `x` and `y`, where `op` transposes `a` when
+/// `trans` is set. `y` must be writable and contiguous.
+#[pyfunction]
+#[pyo3(signature = (a, x, y, trans = false, alpha = 1.0, beta = 0.0))]
+fn sgemv(
+    py: Python<'_>,
+    a: &Bound<'_, PyAny>,
+    x: &Bound<'_, PyAny>,
+    y: &Bound<'_, PyAny>,
+    trans: bool,
+    alpha: f32,
+    beta: f32,
+) -> PyResult<()> {
+    let (a, x, mut y) = (buffer(a)?, buffer(x)?, buffer(y)?);
+    check_disjoint(&y, "y", &[(&a, "a"), (&x, "x")])?;
+    let (a, x) = (operand(py, &a)?, vector(py, &x, "x")?);
+    let y = vector_mut(&mut y, "y")?;
+    try_sgemv_with(&a, trans, &x, GemmParams::new(alpha, beta), y).map_err(value_error)
+}
+
+#[pymodule]
+fn aml_py(module: &Bound<'_, PyModule>) -> PyResult<()> {
+    module.add_function(wrap_pyfunction!(sgemm, module)?)?;
+    module.add_function(wrap_pyfunction!(sgemv, module)?)?;
+    Ok(())
+}
+
+fn buffer(object: &Bound<'_, PyAny>) -> PyResult<PyBuffer<f32>> {
+    PyBuffer::get(object)
+}
+
+/// The strides of `buffer` in values, or `None` if one is negative
+fn strides(buffer: &PyBuffer<f32>) -> Option<Vec<usize>> {
+    // PyBuffer checked the values are aligned, so every stride is a whole number of them
+    buffer
+        .strides()
+        .iter()
+        .map(|s| usize::try_from(*s).ok().map(|s| s / size_of::<f32>()))
+        .collect()
+}
+
+/// The bytes `buffer` spans from its first value to its last, as addresses
+fn span(buffer: &PyBuffer<f32>) -> Range<usize> {
+    let start = buffer.buf_ptr() as usize;
+    let (mut low, mut high) = (start, start);
+    if buffer.shape().contains(&0) {
+        return start..start;
+    }
+    for (n, s) in buffer.shape().iter().zip(buffer.strides()) {
+        let step = (n - 1) as isize * s;
+        match step < 0 {
+            true => low = low.wrapping_add_signed(step),
+            false => high = high.wrapping_add_signed(step),
+        }
+    }
+    low..high + size_of::<f32>()
+}
+
+/// The output is written while the operands are read, so it may not share memory with them
+fn check_disjoint(
+    output: &PyBuffer<f32>,
+    name: &str,
+    operands: &[(&PyBuffer<f32>, &str)],
+) -> PyResult<()> {
+    let written = span(output);
+    for (operand, operand_name) in operands {
+        let read = span(operand);
+        if read.start < written.end && written.start < read.end {
+            return Err(PyValueError::new_err(format!(
+                "`{}` shares memory with `{}`, which it would overwrite while it is read.",
+                name, operand_name
+            )));
+        }
+    }
+    Ok(())
+}
+
+/// `buffer` borrowed as a tensor of its shape, or copied if it has a negative stride
+fn operand<'a>(py: Python<'_>, buffer: &'a PyBuffer<f32>) -> PyResult<CowTensor<'a, f32>> {
+    let shape = buffer.shape().to_vec();
+    Ok(match strides(buffer) {
+        // the buffer stays exported, and unchanged while the GIL is held, for as long as it lives
+        Some(strides) => CowTensor::Borrowed(unsafe {
+            TensorRef::from_raw_parts_with_strides(buffer.buf_ptr().cast(), shape, strides)
+        }),
+        None => CowTensor::Owned(Tensor::new(buffer.to_vec(py)?, shape)),
+    })
+}
+
+/// `buffer` as a matrix to write in place, which needs its rows or columns contiguous
+fn output<'a>(buffer: &'a mut PyBuffer<f32>, name: &'static str) -> PyResult<TensorMut<'a, f32>> {
+    check_writable(buffer, name)?;
+    let unsupported = || {
+        value_error(AmlError::UnsupportedStrides {
+            operand: name,
+            strides: buffer.strides().iter().map(|s| *s as usize).collect(),
+        })
+    };
+    let (shape, strides) = match (buffer.shape(), strides(buffer)) {
+        (&[rows, cols], Some(strides)) => ([rows, cols], [strides[0], strides[1]]),
+        (shape, Some(_)) => {
+            return Err(value_error(AmlError::RankMismatch {
+                operand: name,
+                expected: 2,
+                found: shape.len(),
+            }))
+        }
+        (_, None) => return Err(unsupported()),
+    };
+    // a single row or column may have any stride between its values' neighbours
+    let (layout, outer, inner, ld) = match strides {
+        [_, 1] => (Layout::RowMajor, shape[0], shape[1], strides[0]),
+        _ if shape[1] <= 1 => (Layout::RowMajor, shape[0], shape[1], strides[0]),
+        [1, _] => (Layout::ColMajor, shape[1], shape[0], strides[1]),
+        _ if shape[0] <= 1 => (Layout::ColMajor, shape[1], shape[0], strides[1]),
+        _ => return Err(unsupported()),
+    };
+    let ld = match outer > 1 {
+        true => ld,
+        false => inner.max(1),
+    };
+    let len = match outer == 0 || inner == 0 {
+        true => 0,
+        false => (outer - 1) * ld + inner,
+    };
+    // exported writable, and nothing else reads or writes it while the GIL is held
+    let values = unsafe { std::slice::from_raw_parts_mut(buffer.buf_ptr().cast(), len) };
+    TensorMut::try_new_with_ld(values, shape, layout, ld).map_err(value_error)
+}
+
+/// `buffer` as a vector, borrowed if its values are back to back
+fn vector<'a>(
+    py: Python<'_>,
+    buffer: &'a PyBuffer<f32>,
+    name: &'static str,
+) -> PyResult<Cow<'a, [f32]>> {
+    check_rank(buffer, name, 1)?;
+    Ok(match buffer.is_c_contiguous() {
+        // exported, and unchanged while the GIL is held, for as long as `buffer` lives
+        true => Cow::Borrowed(unsafe {
+            std::slice::from_raw_parts(buffer.buf_ptr().cast(), buffer.item_count())
+        }),
+        false => Cow::Owned(buffer.to_vec(py)?),
+    })
+}
+
+/// `buffer` as a vector to write in place, which needs its values back to back
+fn vector_mut<'a>(buffer: &'a mut PyBuffer<f32>, name: &'static str) -> PyResult<&'a mut [f32]> {
+    check_rank(buffer, name, 1)?;
+    check_writable(buffer, name)?;
+    if !buffer.is_c_contiguous() {
+        return Err(value_error(AmlError::NotContiguous {
+            shape: buffer.shape().to_vec(),
+            strides: buffer.strides().iter().map(|s| *s as usize).collect(),
+        }));
+    }
+    // exported writable, and nothing else reads or writes it while the GIL is held
+    Ok(unsafe { std::slice::from_raw_parts_mut(buffer.buf_ptr().cast(), buffer.item_count()) })
+}
+
+fn check_rank(buffer: &PyBuffer<f32>, name: &'static str, rank: usize) -> PyResult<()> {
+    match buffer.dimensions() == rank {
+        true => Ok(()),
+        false => Err(value_error(AmlError::RankMismatch {
+            operand: name,
+            expected: rank,
+            found: buffer.dimensions(),
+        })),
+    }
+}
+
+fn check_writable(buffer: &PyBuffer<f32>, name: &str) -> PyResult<()> {
+    match buffer.readonly() {
+        true => Err(PyValueError::new_err(format!("`{}` is read-only.", name))),
+        false => Ok(()),
+    }
+}
+
+fn value_error(e: AmlError) -> PyErr {
+    PyValueError::new_err(e.to_string())
+}
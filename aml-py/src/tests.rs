@@ -0,0 +1,100 @@
+#[cfg(test)]
+use crate::*;
+#[cfg(test)]
+use pyo3::types::{PyDict, PyModule};
+
+/// Run `script` with the module imported as `aml_py`
+#[cfg(test)]
+fn run(script: &str) {
+    Python::with_gil(|py| {
+        let module = PyModule::new(py, "aml_py").unwrap();
+        aml_py(&module).unwrap();
+        let globals = PyDict::new(py);
+        globals.set_item("aml_py", module).unwrap();
+        let script = std::ffi::CString::new(script).unwrap();
+        if let Err(e) = py.run(&script, Some(&globals), None) {
+            e.display(py);
+            panic!("{}", e);
+        }
+    })
+}
+
+#[test]
+pub fn python_sgemm() {
+    run(r#"
+from array import array
+
+def matrix(rows, cols, seed=0, fmt='f'):
+    values = [float((i * 7 + seed) % 5 - 2) for i in range(rows * cols)]
+    return memoryview(array(fmt, values)).cast('B').cast(fmt, (rows, cols))
+
+def product(a, b, rows, cols, inner):
+    return [[sum(a(i, p) * b(p, j) for p in range(inner)) for j in range(cols)] for i in range(rows)]
+
+a, b, bt = matrix(3, 4), matrix(4, 2, 1), matrix(2, 4, 2)
+expected = product(lambda i, p: a[i, p], lambda p, j: b[p, j], 3, 2, 4)
+
+# written in place, scaled and accumulated
+c = matrix(3, 2, 3)
+before = c.tolist()
+aml_py.sgemm(a, b, c, alpha=2.0, beta=0.5)
+assert c.tolist() == [[2 * e + 0.5 * o for e, o in zip(*rows)] for rows in zip(expected, before)]
+aml_py.sgemm(a, bt, c, trans_b=True)
+assert c.tolist() == product(lambda i, p: a[i, p], lambda p, j: bt[j, p], 3, 2, 4)
+c = memoryview(array('f', [0.0] * 6)).cast('B').cast('f', (2, 3))
+aml_py.sgemm(bt, a, c, trans_b=True)
+assert c.tolist() == product(lambda i, p: bt[i, p], lambda p, j: a[j, p], 2, 3, 4)
+
+# bad arguments raise, and leave the output alone
+def raises(error, message, call):
+    try:
+        call()
+    except error as e:
+        assert message in str(e), str(e)
+    else:
+        raise AssertionError('no ' + error.__name__)
+
+c = matrix(3, 2, 3)
+raises(ValueError, 'read-only', lambda: aml_py.sgemm(a, b, memoryview(bytes(24)).cast('f', (3, 2))))
+raises(BufferError, 'f32', lambda: aml_py.sgemm(matrix(3, 4, fmt='d'), b, c))
+raises(ValueError, 'Inner dimensions', lambda: aml_py.sgemm(a, bt, c))
+raises(ValueError, 'must have 2', lambda: aml_py.sgemm(a, b, array('f', [0.0] * 6)))
+square = matrix(3, 3)
+raises(ValueError, 'shares memory with `b`', lambda: aml_py.sgemm(matrix(3, 3, 1), square, square))
+assert c.tolist() == matrix(3, 2, 3).tolist()
+"#);
+}
+
+#[test]
+pub fn python_sgemv() {
+    run(r#"
+from array import array
+
+a = memoryview(array('f', [float(i % 4 - 1) for i in range(12)])).cast('B').cast('f', (3, 4))
+x = array('f', [1.0, -2.0, 0.5, 3.0])
+y = array('f', [1.0, 1.0, 1.0])
+aml_py.sgemv(a, x, y, beta=2.0)
+assert list(y) == [sum(a[i, j] * x[j] for j in range(4)) + 2.0 for i in range(3)]
+
+# strided or reversed vectors are copied, and the transpose reads the columns
+wide = array('f', [1.0, 9.0, -1.0, 9.0, 2.0, 9.0])
+z = array('f', [0.0] * 4)
+aml_py.sgemv(a, memoryview(wide)[::2], z, trans=True)
+assert list(z) == [sum(a[i, j] * wide[2 * i] for i in range(3)) for j in range(4)]
+aml_py.sgemv(a, memoryview(wide)[::-2], z, trans=True, alpha=-1.0)
+assert list(z) == [-sum(a[i, j] * wide[5 - 2 * i] for i in range(3)) for j in range(4)]
+
+try:
+    aml_py.sgemv(a, x, memoryview(array('f', [0.0] * 6))[::2])
+except ValueError as e:
+    assert 'contiguous' in str(e), str(e)
+else:
+    raise AssertionError('strided y')
+try:
+    aml_py.sgemv(a, a, y)
+except ValueError as e:
+    assert 'must have 1' in str(e), str(e)
+else:
+    raise AssertionError('matrix x')
+"#);
+}
@@ -0,0 +1,113 @@
+//! JavaScript bindings for the f32 BLAS routines, for WebAssembly builds of aml.
+//!
+//! `wasm-pack build aml-wasm` produces a module whose functions take `Float32Array`s: matrices
+//! are row-major, with their shapes passed alongside, and results come back as new
+//! `Float32Array`s. The `&mut [f32]` arguments (`c` of `sgemm_with`, `y` of `saxpy` and `x` of
+//! `sscal`) are written back into the array passed. Mismatched shapes throw an `Error` with
+//! aml's message.
+//!
+//! Every call runs on the calling thread, as aml is built without its `parallel` feature: a
+//! `wasm32-unknown-unknown` module cannot spawn threads. Pages that want GEMMs off the main
+//! thread can load the module in a Web Worker and post the arrays to it.
+
+use aml::{try_saxpy, try_sdot, try_sgemm_with, try_sgemv, AmlError, GemmParams, Layout};
+use aml::{TensorMut, TensorRef};
+use wasm_bindgen::prelude::*;
+
+mod tests;
+
+/// `op(a) @ op(b)`, `m` by `n`, where `op(a)` is `m` by `k` and `op` transposes its matrix
+/// when `trans_a` or `trans_b` is set
+#[wasm_bindgen]
+pub fn sgemm(
+    a: &[f32],
+    b: &[f32],
+    m: usize,
+    n: usize,
+    k: usize,
+    trans_a: bool,
+    trans_b: bool,
+) -> Result<Vec<f32>, JsError> {
+    let mut c = vec![0f32; m * n];
+    sgemm_with(a, b, &mut c, m, n, k, trans_a, trans_b, 1.0, 0.0)?;
+    Ok(c)
+}
+
+/// `c = alpha * op(a) @ op(b) + beta * c`, as `sgemm`, into the `m` by `n` matrix `c`. `c` is
+/// not read when `beta` is 0.
+#[wasm_bindgen]
+#[allow(clippy::too_many_arguments)]
+pub fn sgemm_with(
+    a: &[f32],
+    b: &[f32],
+    c: &mut [f32],
+    m: usize,
+    n: usize,
+    k: usize,
+    trans_a: bool,
+    trans_b: bool,
+    alpha: f32,
+    beta: f32,
+) -> Result<(), JsError> {
+    let a = matrix(a, [m, k], trans_a)?;
+    let b = matrix(b, [k, n], trans_b)?;
+    let mut c = TensorMut::try_new(c, [m, n])?;
+    try_sgemm_with(
+        &a,
+        trans_a,
+        &b,
+        trans_b,
+        GemmParams::new(alpha, beta),
+        &mut c,
+    )?;
+    Ok(())
+}
+
+/// `op(a) @ x`, where `a` is `m` by `n` and `op` transposes it when `trans` is set
+#[wasm_bindgen]
+pub fn sgemv(a: &[f32], x: &[f32], m: usize, n: usize, trans: bool) -> Result<Vec<f32>, JsError> {
+    Ok(try_sgemv(&matrix(a, [m, n], false)?, trans, x)?)
+}
+
+/// Dot product `x . y`
+#[wasm_bindgen]
+pub fn sdot(x: &[f32], y: &[f32]) -> Result<f32, JsError> {
+    Ok(try_sdot(x, y)?)
+}
+
+/// `y += alpha * x`
+#[wasm_bindgen]
+pub fn saxpy(alpha: f32, x: &[f32], y: &mut [f32]) -> Result<(), JsError> {
+    Ok(try_saxpy(alpha, x, y)?)
+}
+
+/// `x *= alpha`
+#[wasm_bindgen]
+pub fn sscal(alpha: f32, x: &mut [f32]) {
+    aml::sscal(alpha, x)
+}
+
+/// Euclidean norm `sqrt(x . x)`
+#[wasm_bindgen]
+pub fn snrm2(x: &[f32]) -> f32 {
+    aml::snrm2(x)
+}
+
+/// Sum of absolute values
+#[wasm_bindgen]
+pub fn sasum(x: &[f32]) -> f32 {
+    aml::sasum(x)
+}
+
+/// The row-major matrix that is `shape` once transposed if `transpose` is set
+pub(crate) fn matrix(
+    values: &[f32],
+    [rows, cols]: [usize; 2],
+    transpose: bool,
+) -> Result<TensorRef<'_, f32>, AmlError> {
+    let shape = match transpose {
+        true => [cols, rows],
+        false => [rows, cols],
+    };
+    TensorRef::try_new_with_ld(values, shape, Layout::RowMajor, shape[1].max(1))
+}
@@ -0,0 +1,57 @@
+#[cfg(test)]
+use crate::*;
+
+// JsError can only be made inside a JavaScript host, so native tests stay on the Ok paths and
+// check the errors before they are converted
+
+#[test]
+pub fn wasm_gemm() {
+    let (m, n, k) = (3, 2, 4);
+    let a: Vec<f32> = (0..m * k).map(|v| (v % 5) as f32 - 2.0).collect();
+    let b: Vec<f32> = (0..k * n).map(|v| (v % 3) as f32 * 0.5).collect();
+    let expected: Vec<f32> = (0..m * n)
+        .map(|i| (0..k).map(|p| a[i / n * k + p] * b[p * n + i % n]).sum())
+        .collect();
+    assert!(sgemm(&a, &b, m, n, k, false, false).unwrap() == expected);
+
+    // transposed operands, and c scaled and accumulated in place
+    let transpose = |values: &[f32], rows: usize, cols: usize| -> Vec<f32> {
+        (0..rows * cols)
+            .map(|i| values[(i % rows) * cols + i / rows])
+            .collect()
+    };
+    let (a_t, b_t) = (transpose(&a, m, k), transpose(&b, k, n));
+    assert!(sgemm(&a_t, &b_t, m, n, k, true, true).unwrap() == expected);
+    let mut c = vec![1f32; m * n];
+    sgemm_with(&a, &b_t, &mut c, m, n, k, false, true, 2.0, 0.5).unwrap();
+    assert!(c.iter().zip(&expected).all(|(c, e)| *c == 2.0 * e + 0.5));
+
+    let x = [1.0, -1.0, 0.5, 2.0];
+    let y: Vec<f32> = (0..m)
+        .map(|i| (0..k).map(|p| a[i * k + p] * x[p]).sum())
+        .collect();
+    assert!(sgemv(&a, &x, m, k, false).unwrap() == y);
+    assert!(sgemv(&a_t, &x, k, m, true).unwrap() == y);
+    assert!(sgemm(&[], &[], 0, 0, 5, false, false).unwrap().is_empty());
+
+    let e = matrix(&a, [m, k + 1], false).err();
+    assert!(
+        e == Some(AmlError::SizeMismatch {
+            expected: 15,
+            found: 12
+        })
+    );
+    assert!(matrix(&a, [k, m], true).unwrap().shape == [m, k]);
+}
+
+#[test]
+pub fn wasm_vector_ops() {
+    let x = [3f32, -4.0, 0.0, 1.0];
+    let mut y = vec![1f32; 4];
+    assert!(sdot(&x, &y).unwrap() == 0.0);
+    saxpy(2.0, &x, &mut y).unwrap();
+    assert!(y == [7.0, -7.0, 1.0, 3.0]);
+    sscal(-0.5, &mut y);
+    assert!(y == [-3.5, 3.5, -0.5, -1.5]);
+    assert!(snrm2(&x[..2]) == 5.0 && sasum(&x) == 8.0);
+}
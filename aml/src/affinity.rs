@@ -0,0 +1,100 @@
+//! Pinning worker threads to physical cores, for `AmlContext::pin_threads`.
+//!
+//! The topology comes from sysfs, and threads are bound with libc's `sched_setaffinity`, which
+//! the `parallel` feature brings in on Linux. Elsewhere binding does nothing.
+
+use std::sync::OnceLock;
+
+/// One logical CPU per online physical core: the first of each set of SMT siblings. Empty where
+/// the topology cannot be read.
+pub(crate) fn physical_cores() -> &'static [usize] {
+    static CORES: OnceLock<Vec<usize>> = OnceLock::new();
+
+    CORES.get_or_init(|| {
+        let read = |path: &str| std::fs::read_to_string(path).ok();
+        let Some(online) = read("/sys/devices/system/cpu/online") else {
+            return Vec::new();
+        };
+
+        parse_cpu_list(&online)
+            .into_iter()
+            .filter(|cpu| {
+                let siblings = format!(
+                    "/sys/devices/system/cpu/cpu{}/topology/thread_siblings_list",
+                    cpu
+                );
+                read(&siblings)
+                    .is_some_and(|siblings| parse_cpu_list(&siblings).first() == Some(cpu))
+            })
+            .collect()
+    })
+}
+
+/// CPUs in a sysfs list such as `0-3,8,10-11`. Malformed entries are skipped.
+pub(crate) fn parse_cpu_list(list: &str) -> Vec<usize> {
+    let mut cpus = Vec::new();
+    for entry in list.trim().split(',') {
+        let (first, last) = entry.split_once('-').unwrap_or((entry, entry));
+        if let (Ok(first), Ok(last)) = (first.parse::<usize>(), last.parse()) {
+            cpus.extend(first..=last);
+        }
+    }
+
+    cpus
+}
+
+/// Pin the calling thread to the `worker`th physical core, wrapping around when there are more
+/// workers than cores. Does nothing where pinning is unsupported.
+pub(crate) fn pin_to_core(worker: usize) {
+    let cores = physical_cores();
+    if !cores.is_empty() {
+        pin_current_thread(cores[worker % cores.len()]);
+    }
+}
+
+/// CPUs of each NUMA node that has any, from sysfs. Empty where the topology cannot be read.
+pub(crate) fn numa_nodes() -> &'static [Vec<usize>] {
+    static NODES: OnceLock<Vec<Vec<usize>>> = OnceLock::new();
+
+    NODES.get_or_init(|| {
+        let read = |path: String| std::fs::read_to_string(path).ok();
+        let Some(online) = read("/sys/devices/system/node/online".to_string()) else {
+            return Vec::new();
+        };
+
+        parse_cpu_list(&online)
+            .into_iter()
+            .filter_map(|node| read(format!("/sys/devices/system/node/node{}/cpulist", node)))
+            .map(|cpus| parse_cpu_list(&cpus))
+            .filter(|cpus| !cpus.is_empty())
+            .collect()
+    })
+}
+
+/// Restrict the calling thread to `cpu`. Returns whether the kernel accepted it.
+pub(crate) fn pin_current_thread(cpu: usize) -> bool {
+    bind_current_thread(&[cpu])
+}
+
+/// `sched_setaffinity(0, ..)` with a mask of `cpus`, which threads spawned afterwards inherit.
+/// Returns whether the kernel accepted it.
+#[cfg(all(target_os = "linux", feature = "parallel"))]
+pub(crate) fn bind_current_thread(cpus: &[usize]) -> bool {
+    let mut set: libc::cpu_set_t = unsafe { std::mem::zeroed() };
+    let mut any = false;
+    for cpu in cpus.iter().filter(|cpu| **cpu < libc::CPU_SETSIZE as usize) {
+        unsafe { libc::CPU_SET(*cpu, &mut set) };
+        any = true;
+    }
+    if !any {
+        return false;
+    }
+
+    unsafe { libc::sched_setaffinity(0, std::mem::size_of_val(&set), &set) == 0 }
+}
+
+/// Binding is only supported on Linux with the `parallel` feature.
+#[cfg(not(all(target_os = "linux", feature = "parallel")))]
+pub(crate) fn bind_current_thread(_cpus: &[usize]) -> bool {
+    false
+}
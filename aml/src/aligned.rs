@@ -0,0 +1,108 @@
+//! Owned f32 buffers on a chosen alignment, so SIMD kernels can use aligned loads.
+//!
+//! A `Vec<f32>` only promises 4 byte alignment and must be freed with the layout it was
+//! allocated with, so the aligned buffer is its own type. It reads as a `&[f32]` and
+//! `&mut [f32]`, so tensor views borrow it like any other slice.
+
+use crate::AmlError;
+use std::alloc::{self, Layout};
+use std::fmt;
+use std::ops::{Deref, DerefMut};
+use std::ptr::NonNull;
+
+/// `len` zeroed f32 values, the first at an address that is a multiple of `align`.
+pub struct AlignedBuffer {
+    ptr: NonNull<f32>,
+    len: usize,
+    align: usize,
+}
+
+// the buffer owns its values as a `Vec` would
+unsafe impl Send for AlignedBuffer {}
+unsafe impl Sync for AlignedBuffer {}
+
+/// Allocate `len` zeroed values aligned to `align` bytes, e.g. `alloc_aligned_f32(n, 64)` for
+/// one cache line or AVX-512 vector. `align` must be a power of two of at least 4.
+pub fn alloc_aligned_f32(len: usize, align: usize) -> AlignedBuffer {
+    try_alloc_aligned_f32(len, align).unwrap_or_else(|e| panic!("{}", e))
+}
+
+/// Fallible version of `alloc_aligned_f32`. Returns `ShapeOverflow` when `len` values are more
+/// bytes than an allocation can hold and `AllocationFailed` when the allocator runs out,
+/// rather than aborting as a `Vec` would.
+pub fn try_alloc_aligned_f32(len: usize, align: usize) -> Result<AlignedBuffer, AmlError> {
+    if !align.is_power_of_two() || align < std::mem::align_of::<f32>() {
+        return Err(AmlError::InvalidAlignment { align });
+    }
+    let layout = Layout::array::<f32>(len)
+        .and_then(|layout| layout.align_to(align))
+        .map_err(|_| AmlError::ShapeOverflow { shape: vec![len] })?;
+
+    let ptr = match layout.size() {
+        // nothing to allocate, but the pointer still has to be aligned
+        0 => NonNull::new(align as *mut f32),
+        _ => NonNull::new(unsafe { alloc::alloc_zeroed(layout) } as *mut f32),
+    };
+    match ptr {
+        Some(ptr) => Ok(AlignedBuffer { ptr, len, align }),
+        None => Err(AmlError::AllocationFailed {
+            bytes: layout.size(),
+            align,
+        }),
+    }
+}
+
+impl AlignedBuffer {
+    /// Alignment in bytes the buffer was allocated with
+    pub fn align(&self) -> usize {
+        self.align
+    }
+
+    fn layout(&self) -> Layout {
+        Layout::from_size_align(self.len * std::mem::size_of::<f32>(), self.align).unwrap()
+    }
+}
+
+impl Deref for AlignedBuffer {
+    type Target = [f32];
+
+    fn deref(&self) -> &[f32] {
+        unsafe { std::slice::from_raw_parts(self.ptr.as_ptr(), self.len) }
+    }
+}
+
+impl DerefMut for AlignedBuffer {
+    fn deref_mut(&mut self) -> &mut [f32] {
+        unsafe { std::slice::from_raw_parts_mut(self.ptr.as_ptr(), self.len) }
+    }
+}
+
+impl Drop for AlignedBuffer {
+    fn drop(&mut self) {
+        if self.len > 0 {
+            unsafe { alloc::dealloc(self.ptr.as_ptr() as *mut u8, self.layout()) }
+        }
+    }
+}
+
+impl Clone for AlignedBuffer {
+    fn clone(&self) -> AlignedBuffer {
+        let mut copy = alloc_aligned_f32(self.len, self.align);
+        copy.copy_from_slice(self);
+        copy
+    }
+}
+
+impl fmt::Debug for AlignedBuffer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("AlignedBuffer")
+            .field("align", &self.align)
+            .field("values", &self.deref())
+            .finish()
+    }
+}
+
+/// Whether `values` starts at a multiple of `align` bytes
+pub(crate) fn is_aligned<T>(values: &[T], align: usize) -> bool {
+    (values.as_ptr() as usize).is_multiple_of(align)
+}
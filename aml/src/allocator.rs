@@ -0,0 +1,38 @@
+//! Pluggable storage for owned tensors.
+//!
+//! `Tensor` keeps its values in a `Vec`, so an allocator here hands out and takes back `Vec`s:
+//! an arena or pool recycles them between calls instead of going back to the system. Memory
+//! the global allocator does not own, such as pinned staging buffers, is borrowed through
+//! `TensorRef` and `TensorMut` instead.
+
+use crate::Element;
+
+/// Source of the storage behind `Tensor::zeros_in` and `Tensor::full_in`.
+///
+/// Any `Fn(usize) -> Vec<T>` is one, so a plain callback works where a pool would be overkill.
+pub trait TensorAllocator<T: Element> {
+    /// Storage for `len` values. The contents are overwritten, so a recycled buffer can be
+    /// returned as is.
+    fn allocate(&self, len: usize) -> Vec<T>;
+
+    /// Take back the storage of a tensor that is no longer needed. Dropped by default.
+    fn release(&self, values: Vec<T>) {
+        drop(values)
+    }
+}
+
+/// The global allocator, what `Tensor::zeros` and the other constructors use.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemAllocator;
+
+impl<T: Element> TensorAllocator<T> for SystemAllocator {
+    fn allocate(&self, len: usize) -> Vec<T> {
+        vec![T::ZERO; len]
+    }
+}
+
+impl<T: Element, F: Fn(usize) -> Vec<T>> TensorAllocator<T> for F {
+    fn allocate(&self, len: usize) -> Vec<T> {
+        self(len)
+    }
+}
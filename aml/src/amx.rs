@@ -0,0 +1,187 @@
+//! Intel AMX tile kernels for `sbgemm` and `igemm`, behind the `amx` feature.
+//!
+//! The AMX intrinsics are not stable yet, so the tile instructions are written as inline asm and
+//! the CPU is probed with `cpuid` instead of `is_x86_feature_detected!`. Linux also requires each
+//! process to ask for the tile register state before first use (`arch_prctl`), done once in
+//! `available`.
+//!
+//! Every tile is configured as 16 rows of 64 bytes: `tmm0` holds a 16 x 16 block of 32 bit
+//! accumulators, `tmm1` 16 rows of `a`, and `tmm2` the matching rows of `b` in the interleaved
+//! layout the dot product instructions expect (see `pack_b`). Edges are zero padded, so one tile
+//! shape serves every problem size.
+
+use half::bf16;
+use std::arch::asm;
+use std::arch::x86_64::__cpuid_count;
+use std::mem::size_of;
+use std::sync::OnceLock;
+
+/// Rows in every tile, and columns of the accumulator tile.
+const TILE: usize = 16;
+/// Bytes per tile row.
+const TILE_BYTES: usize = 64;
+
+/// Input types with an AMX dot product: bf16 pairs into f32, i8 quads into i32.
+pub(crate) trait TileElement: Copy + Default {
+    type Acc: Copy + Default;
+
+    /// `c += a . b` on `tmm0`, `tmm1`, `tmm2`
+    unsafe fn tile_dp();
+}
+
+impl TileElement for bf16 {
+    type Acc = f32;
+
+    unsafe fn tile_dp() {
+        asm!("tdpbf16ps tmm0, tmm1, tmm2", options(nostack));
+    }
+}
+
+impl TileElement for i8 {
+    type Acc = i32;
+
+    unsafe fn tile_dp() {
+        asm!("tdpbssd tmm0, tmm1, tmm2", options(nostack));
+    }
+}
+
+/// Whether the CPU has AMX-TILE, -BF16 and -INT8 and the kernel granted this process the tile
+/// state. Checked once.
+pub(crate) fn available() -> bool {
+    static AVAILABLE: OnceLock<bool> = OnceLock::new();
+
+    *AVAILABLE.get_or_init(|| {
+        // CPUID.(EAX=7, ECX=0):EDX bits 22 (AMX-BF16), 24 (AMX-TILE), 25 (AMX-INT8)
+        let edx = __cpuid_count(7, 0).edx;
+        let cpu = edx & (1 << 22) != 0 && edx & (1 << 24) != 0 && edx & (1 << 25) != 0;
+
+        cpu && request_tile_permission()
+    })
+}
+
+/// `arch_prctl(ARCH_REQ_XCOMP_PERM, XFEATURE_XTILEDATA)`, as a raw syscall since the crate does
+/// not link libc. Fails on kernels older than 5.16.
+fn request_tile_permission() -> bool {
+    const SYS_ARCH_PRCTL: i64 = 158;
+    const ARCH_REQ_XCOMP_PERM: i64 = 0x1023;
+    const XFEATURE_XTILEDATA: i64 = 18;
+
+    let ret: i64;
+    unsafe {
+        asm!(
+            "syscall",
+            inlateout("rax") SYS_ARCH_PRCTL => ret,
+            in("rdi") ARCH_REQ_XCOMP_PERM,
+            in("rsi") XFEATURE_XTILEDATA,
+            lateout("rcx") _,
+            lateout("r11") _,
+            options(nostack),
+        );
+    }
+
+    ret == 0
+}
+
+/// Elements of `T` per k step of one tile: 32 bf16 or 64 i8.
+fn k_block<T>() -> usize {
+    TILE_BYTES / size_of::<T>()
+}
+
+/// Rearrange the (n, k) columns of op(b) into `b` tiles, zero padded to whole tiles.
+///
+/// Tile `(j_block, p_block)` starts at `(j_block * k_blocks + p_block) * TILE * k_block`. Its
+/// row `r` holds, for each of the 16 columns in turn, the block's k values `r g .. (r + 1) g`
+/// with `g = 4 / size_of::<T>()`: the layout `tdpbf16ps` and `tdpbssd` read `b` in.
+pub(crate) fn pack_b<T: TileElement>(b_cols: &[T], n: usize, k: usize) -> Vec<T> {
+    let kb = k_block::<T>();
+    let group = 4 / size_of::<T>();
+    let (n_blocks, k_blocks) = (n.div_ceil(TILE), k.div_ceil(kb));
+    let mut packed = vec![T::default(); n_blocks * k_blocks * TILE * kb];
+
+    for (j, b_j) in b_cols.chunks_exact(k).enumerate() {
+        let (j_block, col) = (j / TILE, j % TILE);
+        for (p, b_jp) in b_j.iter().enumerate() {
+            let (p_block, row, g) = (p / kb, p % kb / group, p % group);
+            let tile = (j_block * k_blocks + p_block) * TILE * kb;
+            packed[tile + row * TILE * group + col * group + g] = *b_jp;
+        }
+    }
+
+    packed
+}
+
+#[repr(C, align(64))]
+struct TileConfig([u8; 64]);
+
+/// `acc = a_rows @ b` for rows of op(a), `b` from `pack_b`. Needs `available()`.
+pub(crate) fn tile_gemm<T: TileElement>(
+    a_rows: &[T],
+    packed_b: &[T],
+    n: usize,
+    k: usize,
+    acc: &mut [T::Acc],
+) {
+    let kb = k_block::<T>();
+    let k_padded = k.div_ceil(kb) * kb;
+    let k_blocks = k_padded / kb;
+
+    // palette 1, three tiles of 16 rows x 64 bytes
+    let mut config = TileConfig([0; 64]);
+    config.0[0] = 1;
+    for tmm in 0..3 {
+        config.0[16 + 2 * tmm] = TILE_BYTES as u8;
+        config.0[48 + tmm] = TILE as u8;
+    }
+
+    let mut a_strip = vec![T::default(); TILE * k_padded];
+    let mut c_tile = [T::Acc::default(); TILE * TILE];
+    let a_stride = k_padded * size_of::<T>();
+    let c_stride = TILE * size_of::<T::Acc>();
+
+    unsafe { asm!("ldtilecfg [{}]", in(reg) config.0.as_ptr(), options(nostack)) };
+
+    for (i_block, acc_block) in acc.chunks_mut(TILE * n).enumerate() {
+        let strip_rows = acc_block.len() / n;
+        a_strip.fill(T::default());
+        for (a_i, strip_i) in a_rows[i_block * TILE * k..]
+            .chunks_exact(k)
+            .take(strip_rows)
+            .zip(a_strip.chunks_exact_mut(k_padded))
+        {
+            strip_i[..k].copy_from_slice(a_i);
+        }
+
+        for j_block in 0..n.div_ceil(TILE) {
+            unsafe {
+                asm!("tilezero tmm0", options(nostack));
+                for p_block in 0..k_blocks {
+                    let b_tile = packed_b[(j_block * k_blocks + p_block) * TILE * kb..].as_ptr();
+                    asm!(
+                        "tileloadd tmm1, [{a} + {a_stride} * 1]",
+                        "tileloadd tmm2, [{b} + {b_stride} * 1]",
+                        a = in(reg) a_strip[p_block * kb..].as_ptr(),
+                        a_stride = in(reg) a_stride,
+                        b = in(reg) b_tile,
+                        b_stride = in(reg) TILE_BYTES,
+                        options(nostack),
+                    );
+                    T::tile_dp();
+                }
+                asm!(
+                    "tilestored [{c} + {c_stride} * 1], tmm0",
+                    c = in(reg) c_tile.as_mut_ptr(),
+                    c_stride = in(reg) c_stride,
+                    options(nostack),
+                );
+            }
+
+            let j0 = j_block * TILE;
+            let cols = (n - j0).min(TILE);
+            for (acc_row, c_row) in acc_block.chunks_exact_mut(n).zip(c_tile.chunks_exact(TILE)) {
+                acc_row[j0..j0 + cols].copy_from_slice(&c_row[..cols]);
+            }
+        }
+    }
+
+    unsafe { asm!("tilerelease", options(nostack)) };
+}
@@ -0,0 +1,237 @@
+//! Opt-in autotuning of `sgemm` blocking and threading per problem shape.
+//!
+//! With autotuning on, the first `sgemm` of each (m, n, k) times a handful of block size and
+//! thread count candidates on scratch matrices of that shape and keeps the fastest for the rest
+//! of the process. The decisions can be saved to a file and loaded by a later process; entries
+//! are keyed by CPU model, so one file can serve a mixed fleet.
+
+use crate::blocking::{self, block_sizes, cache_info, BlockSizes};
+use crate::dispatch::kernels;
+use crate::parallel::{self, num_threads};
+use crate::sgemm::{sgemm_kernel, OpB};
+use crate::{AsTensorRef, F32Tensor, GemmParams};
+use std::collections::BTreeMap;
+use std::io;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Blocking and threading for one GEMM shape.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TuneConfig {
+    pub block_sizes: BlockSizes,
+    pub threads: usize,
+}
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+static DECISIONS: Mutex<BTreeMap<(usize, usize, usize), TuneConfig>> = Mutex::new(BTreeMap::new());
+
+/// Turn autotuning of `sgemm` on or off for every later call. Off by default.
+///
+/// Block sizes set with `set_block_sizes` take precedence over tuned ones, and a deterministic
+/// `AmlContext` turns tuning off for its scope.
+pub fn set_autotune(enabled: bool) {
+    ENABLED.store(enabled, Ordering::Relaxed);
+}
+
+/// Whether `set_autotune` has turned autotuning on
+pub fn autotune_enabled() -> bool {
+    ENABLED.load(Ordering::Relaxed)
+}
+
+/// Config `sgemm` runs (m, n, k) with: the tuned one when autotuning is on, else the current
+/// block sizes on every thread. Never tuned inside a deterministic `AmlContext`.
+pub(crate) fn sgemm_config(m: usize, n: usize, k: usize) -> TuneConfig {
+    let tuned = autotune_enabled() && !blocking::is_overridden() && !parallel::deterministic();
+    match tuned {
+        true => autotune(m, n, k),
+        false => TuneConfig {
+            block_sizes: block_sizes(),
+            threads: num_threads(),
+        },
+    }
+}
+
+/// Fastest config for an (m, n, k) `sgemm`, benchmarking the candidates now if this shape has
+/// not been seen. Works whether or not autotuning is on, so shapes can be tuned at startup.
+///
+/// Without a clock (wasm32-unknown-unknown) nothing is timed and the detected sizes are used.
+pub fn autotune(m: usize, n: usize, k: usize) -> TuneConfig {
+    if let Some(config) = decisions().get(&(m, n, k)) {
+        return *config;
+    }
+
+    // tuned outside the lock, so other shapes are not held up; a race only tunes twice
+    let config = match cfg!(all(target_arch = "wasm32", target_os = "unknown")) {
+        true => candidates()[0],
+        false => fastest(m, n, k),
+    };
+    *decisions().entry((m, n, k)).or_insert(config)
+}
+
+fn decisions() -> std::sync::MutexGuard<'static, BTreeMap<(usize, usize, usize), TuneConfig>> {
+    DECISIONS.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// The detected block sizes first, then `kc` and `mc` halved and doubled, each single threaded
+/// and on every thread.
+fn candidates() -> Vec<TuneConfig> {
+    let base = BlockSizes::for_cache(&cache_info());
+    let scaled = |mc: usize, kc: usize| BlockSizes {
+        mc: mc.max(16),
+        kc: kc.max(16),
+        nc: base.nc,
+    };
+
+    let mut configs: Vec<TuneConfig> = Vec::new();
+    for threads in [num_threads(), 1] {
+        for block_sizes in [
+            base,
+            scaled(base.mc, base.kc / 2),
+            scaled(base.mc, base.kc * 2),
+            scaled(base.mc / 2, base.kc),
+            scaled(base.mc * 2, base.kc),
+        ] {
+            let config = TuneConfig {
+                block_sizes,
+                threads,
+            };
+            if !configs.contains(&config) {
+                configs.push(config);
+            }
+        }
+    }
+
+    configs
+}
+
+/// Best of two runs of each candidate on constant scratch matrices
+fn fastest(m: usize, n: usize, k: usize) -> TuneConfig {
+    let a = F32Tensor::new(vec![1f32; m * k], vec![m, k]);
+    let b = F32Tensor::new(vec![1f32; k * n], vec![k, n]);
+    let mut c = F32Tensor::zeros(vec![m, n]);
+
+    let mut best = (Duration::MAX, candidates()[0]);
+    for config in candidates() {
+        for _ in 0..2 {
+            let start = Instant::now();
+            sgemm_kernel(
+                &a.as_tensor_ref(),
+                false,
+                OpB::Tensor(&b.as_tensor_ref(), false),
+                GemmParams::default(),
+                Some(config),
+                kernels(),
+                &mut c.view_mut(),
+            );
+            let elapsed = start.elapsed();
+            if elapsed < best.0 {
+                best = (elapsed, config);
+            }
+        }
+    }
+
+    best.1
+}
+
+/// Write every decision made so far to `path`, one tab separated line per shape:
+/// CPU model, m, n, k, mc, kc, nc, threads. Lines for other CPU models already in the file are
+/// kept.
+pub fn save_autotune(path: impl AsRef<Path>) -> io::Result<()> {
+    let model = cpu_model();
+    let mut lines: Vec<String> = match std::fs::read_to_string(&path) {
+        Ok(text) => text
+            .lines()
+            .filter(|line| line.split('\t').next() != Some(model.as_str()))
+            .map(String::from)
+            .collect(),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Vec::new(),
+        Err(e) => return Err(e),
+    };
+
+    for ((m, n, k), config) in decisions().iter() {
+        let BlockSizes { mc, kc, nc } = config.block_sizes;
+        lines.push(format!(
+            "{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}",
+            model, m, n, k, mc, kc, nc, config.threads
+        ));
+    }
+
+    std::fs::write(path, lines.join("\n") + "\n")
+}
+
+/// Load the decisions `save_autotune` wrote on this CPU model, replacing any already made for
+/// the same shapes. Returns how many were loaded.
+pub fn load_autotune(path: impl AsRef<Path>) -> io::Result<usize> {
+    let model = cpu_model();
+    let invalid = |line: &str| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("bad autotune line: {:?}", line),
+        )
+    };
+
+    let mut loaded = Vec::new();
+    for line in std::fs::read_to_string(path)?.lines() {
+        let mut fields = line.split('\t');
+        if fields.next() != Some(model.as_str()) {
+            continue;
+        }
+        let numbers: Vec<usize> = fields
+            .map(|field| field.parse())
+            .collect::<Result<_, _>>()
+            .map_err(|_| invalid(line))?;
+        let [m, n, k, mc, kc, nc, threads] = numbers[..] else {
+            return Err(invalid(line));
+        };
+        if mc == 0 || kc == 0 || nc == 0 || threads == 0 {
+            return Err(invalid(line));
+        }
+
+        let block_sizes = BlockSizes { mc, kc, nc };
+        let config = TuneConfig {
+            block_sizes,
+            threads,
+        };
+        loaded.push(((m, n, k), config));
+    }
+
+    let count = loaded.len();
+    decisions().extend(loaded);
+    Ok(count)
+}
+
+/// Brand string from `cpuid` on x86_64, else the model line of /proc/cpuinfo, else the
+/// architecture name.
+pub(crate) fn cpu_model() -> String {
+    #[cfg(target_arch = "x86_64")]
+    {
+        use std::arch::x86_64::__cpuid;
+
+        if __cpuid(0x8000_0000).eax >= 0x8000_0004 {
+            let mut brand = Vec::with_capacity(48);
+            for leaf in 0x8000_0002..=0x8000_0004 {
+                let regs = __cpuid(leaf);
+                for reg in [regs.eax, regs.ebx, regs.ecx, regs.edx] {
+                    brand.extend_from_slice(&reg.to_le_bytes());
+                }
+            }
+            let brand = String::from_utf8_lossy(&brand);
+            let brand = brand.trim_matches(|c: char| c == '\0' || c.is_whitespace());
+            if !brand.is_empty() {
+                return brand.replace('\t', " ");
+            }
+        }
+    }
+
+    std::fs::read_to_string("/proc/cpuinfo")
+        .ok()
+        .and_then(|info| {
+            info.lines()
+                .find(|line| line.starts_with("model name") || line.starts_with("Model"))
+                .and_then(|line| line.split_once(':'))
+                .map(|(_, model)| model.trim().replace('\t', " "))
+        })
+        .unwrap_or_else(|| std::env::consts::ARCH.to_string())
+}
@@ -0,0 +1,212 @@
+//! Running `sgemm` and `dgemm` on a linked CBLAS instead of aml's own kernels.
+//!
+//! With the `backend-blas` feature, `set_backend(Backend::Blas)` sends `sgemm`, `sgemm_with`,
+//! `dgemm` and `dgemm_with` to `cblas_sgemm` and `cblas_dgemm`, e.g. to check aml against
+//! OpenBLAS or MKL on production shapes, or to use them where aml has no fast kernel. Their
+//! arguments are checked and laid out by aml first, so errors are the same either way.
+//! `Accuracy::High` calls stay on aml, which BLAS cannot match.
+//!
+//! The crate does not pick a BLAS to link: the application does, e.g. with
+//! `cargo:rustc-link-lib=openblas` in its build script.
+
+use crate::{Accuracy, Element, GemmParams, Layout, TensorMut, TensorRef};
+use std::ffi::c_int;
+use std::sync::{OnceLock, RwLock};
+
+/// Where the `sgemm` and `dgemm` families run
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Backend {
+    /// aml's own kernels
+    #[default]
+    Aml,
+    /// The linked CBLAS
+    Blas,
+}
+
+static OVERRIDE: RwLock<Option<Backend>> = RwLock::new(None);
+
+/// The backend GEMMs run on: the one `set_backend` chose if any, else `AML_BACKEND` (`aml` or
+/// `blas`) if the process started with it, else aml.
+pub fn backend() -> Backend {
+    static DEFAULT: OnceLock<Backend> = OnceLock::new();
+
+    match *OVERRIDE.read().unwrap_or_else(|e| e.into_inner()) {
+        Some(backend) => backend,
+        None => *DEFAULT.get_or_init(|| {
+            std::env::var("AML_BACKEND")
+                .ok()
+                .and_then(|backend| parse_backend(&backend))
+                .unwrap_or_default()
+        }),
+    }
+}
+
+/// Run every later GEMM call on `backend`, or go back to the default with `None`.
+pub fn set_backend(backend: Option<Backend>) {
+    *OVERRIDE.write().unwrap_or_else(|e| e.into_inner()) = backend;
+}
+
+/// `aml` or `blas`, in any case
+pub(crate) fn parse_backend(backend: &str) -> Option<Backend> {
+    match backend.trim().to_ascii_lowercase().as_str() {
+        "aml" => Some(Backend::Aml),
+        "blas" => Some(Backend::Blas),
+        _ => None,
+    }
+}
+
+/// Whether a GEMM with `params` should go to BLAS
+pub(crate) fn use_blas<T>(params: &GemmParams<T>) -> bool {
+    params.accuracy == Accuracy::Fast && backend() == Backend::Blas
+}
+
+const ROW_MAJOR: c_int = 101;
+const NO_TRANS: c_int = 111;
+const TRANS: c_int = 112;
+
+extern "C" {
+    fn cblas_sgemm(
+        order: c_int,
+        transa: c_int,
+        transb: c_int,
+        m: c_int,
+        n: c_int,
+        k: c_int,
+        alpha: f32,
+        a: *const f32,
+        lda: c_int,
+        b: *const f32,
+        ldb: c_int,
+        beta: f32,
+        c: *mut f32,
+        ldc: c_int,
+    );
+    fn cblas_dgemm(
+        order: c_int,
+        transa: c_int,
+        transb: c_int,
+        m: c_int,
+        n: c_int,
+        k: c_int,
+        alpha: f64,
+        a: *const f64,
+        lda: c_int,
+        b: *const f64,
+        ldb: c_int,
+        beta: f64,
+        c: *mut f64,
+        ldc: c_int,
+    );
+}
+
+/// The arguments a row-major CBLAS GEMM takes for these checked operands, or `None` where a
+/// dimension does not fit in a C `int`
+#[allow(clippy::type_complexity)]
+fn arguments<T: Element>(
+    a: &TensorRef<T>,
+    a_transpose: bool,
+    b: &TensorRef<T>,
+    b_transpose: bool,
+    c: &TensorMut<T>,
+) -> Option<([c_int; 2], [c_int; 3], [c_int; 3])> {
+    debug_assert!(c.layout == Layout::RowMajor);
+    let k = match a_transpose {
+        true => a.shape[0],
+        false => a.shape[1],
+    };
+    let trans = |transpose: bool| match transpose {
+        true => TRANS,
+        false => NO_TRANS,
+    };
+    let int = |value: usize| c_int::try_from(value).ok();
+    // a kernel reading row-major sees a column-major operand transposed
+    let trans = [
+        trans(a.stored_transpose(a_transpose)),
+        trans(b.stored_transpose(b_transpose)),
+    ];
+    let dims = [int(c.shape[0])?, int(c.shape[1])?, int(k)?];
+    let lds = [
+        int(a.ld().max(1))?,
+        int(b.ld().max(1))?,
+        int(c.ld().max(1))?,
+    ];
+    Some((trans, dims, lds))
+}
+
+/// `c = alpha * op(a) @ op(b) + beta * c` on `cblas_sgemm`, for operands `try_sgemm_with`
+/// has checked and `c` in row-major form. `false` if a dimension is too large for CBLAS, so
+/// aml should run it instead.
+pub(crate) fn sgemm(
+    a: &TensorRef<f32>,
+    a_transpose: bool,
+    b: &TensorRef<f32>,
+    b_transpose: bool,
+    params: GemmParams,
+    c: &mut TensorMut<f32>,
+) -> bool {
+    let Some(([transa, transb], [m, n, k], [lda, ldb, ldc])) =
+        arguments(a, a_transpose, b, b_transpose, c)
+    else {
+        return false;
+    };
+    if m > 0 && n > 0 {
+        // the checks above make every slice span its matrix at its leading dimension
+        unsafe {
+            cblas_sgemm(
+                ROW_MAJOR,
+                transa,
+                transb,
+                m,
+                n,
+                k,
+                params.alpha,
+                a.values.as_ptr(),
+                lda,
+                b.values.as_ptr(),
+                ldb,
+                params.beta,
+                c.values.as_mut_ptr(),
+                ldc,
+            )
+        };
+    }
+    true
+}
+
+/// `sgemm` for f64, on `cblas_dgemm`
+pub(crate) fn dgemm(
+    a: &TensorRef<f64>,
+    a_transpose: bool,
+    b: &TensorRef<f64>,
+    b_transpose: bool,
+    params: GemmParams<f64>,
+    c: &mut TensorMut<f64>,
+) -> bool {
+    let Some(([transa, transb], [m, n, k], [lda, ldb, ldc])) =
+        arguments(a, a_transpose, b, b_transpose, c)
+    else {
+        return false;
+    };
+    if m > 0 && n > 0 {
+        // as in `sgemm`
+        unsafe {
+            cblas_dgemm(
+                ROW_MAJOR,
+                transa,
+                transb,
+                m,
+                n,
+                k,
+                params.alpha,
+                a.values.as_ptr(),
+                lda,
+                b.values.as_ptr(),
+                ldb,
+                params.beta,
+                c.values.as_mut_ptr(),
+                ldc,
+            )
+        };
+    }
+    true
+}
@@ -0,0 +1,608 @@
+//! BLAS level 1: f32 (and the f64 ones `dgemm` needs) vector operations, with AVX versions
+//! and SVE or NEON versions of `sdot` and `saxpy` on aarch64. `dispatch` picks one per
+//! operation.
+//!
+//! wasm32 has no runtime feature detection: the SIMD128 versions are compiled in when the build
+//! enables `simd128` (`RUSTFLAGS="-C target-feature=+simd128"`), and use fused multiply-adds
+//! when it also enables `relaxed-simd`. Otherwise the scalar code runs.
+
+#[cfg(target_arch = "x86_64")]
+use crate::aligned::is_aligned;
+use crate::dispatch::kernels;
+use crate::AmlError;
+
+#[cfg(target_arch = "aarch64")]
+use std::arch::aarch64::*;
+#[cfg(all(target_arch = "wasm32", target_feature = "simd128"))]
+use std::arch::wasm32::*;
+#[cfg(target_arch = "x86_64")]
+use std::arch::x86_64::*;
+
+/// Dot product `x . y`
+pub fn sdot(x: &[f32], y: &[f32]) -> f32 {
+    try_sdot(x, y).unwrap_or_else(|e| panic!("{}", e))
+}
+
+/// Fallible version of `sdot`
+pub fn try_sdot(x: &[f32], y: &[f32]) -> Result<f32, AmlError> {
+    check_len(x, y)?;
+
+    Ok((kernels().sdot)(x, y))
+}
+
+/// `y += alpha * x`
+pub fn saxpy(alpha: f32, x: &[f32], y: &mut [f32]) {
+    try_saxpy(alpha, x, y).unwrap_or_else(|e| panic!("{}", e))
+}
+
+/// Fallible version of `saxpy`. `y` is left untouched on error.
+pub fn try_saxpy(alpha: f32, x: &[f32], y: &mut [f32]) -> Result<(), AmlError> {
+    check_len(x, y)?;
+
+    (kernels().saxpy)(alpha, x, y);
+    Ok(())
+}
+
+/// `x *= alpha`
+pub fn sscal(alpha: f32, x: &mut [f32]) {
+    (kernels().sscal)(alpha, x)
+}
+
+/// Euclidean norm `sqrt(x . x)`.
+///
+/// Squares are summed in f64, so the result neither overflows nor underflows for any finite f32
+/// input that has a representable norm.
+pub fn snrm2(x: &[f32]) -> f32 {
+    (kernels().snrm2)(x)
+}
+
+/// Sum of absolute values
+pub fn sasum(x: &[f32]) -> f32 {
+    (kernels().sasum)(x)
+}
+
+/// Dot product `x . y` in f64
+pub fn ddot(x: &[f64], y: &[f64]) -> f64 {
+    try_ddot(x, y).unwrap_or_else(|e| panic!("{}", e))
+}
+
+/// Fallible version of `ddot`
+pub fn try_ddot(x: &[f64], y: &[f64]) -> Result<f64, AmlError> {
+    check_len(x, y)?;
+
+    Ok((kernels().ddot)(x, y))
+}
+
+/// Dot product of f32 vectors summed in f64
+pub fn dsdot(x: &[f32], y: &[f32]) -> f64 {
+    try_dsdot(x, y).unwrap_or_else(|e| panic!("{}", e))
+}
+
+/// Fallible version of `dsdot`
+pub fn try_dsdot(x: &[f32], y: &[f32]) -> Result<f64, AmlError> {
+    check_len(x, y)?;
+
+    Ok((kernels().dsdot)(x, y))
+}
+
+/// `y += alpha * x` in f64
+pub fn daxpy(alpha: f64, x: &[f64], y: &mut [f64]) {
+    try_daxpy(alpha, x, y).unwrap_or_else(|e| panic!("{}", e))
+}
+
+/// Fallible version of `daxpy`. `y` is left untouched on error.
+pub fn try_daxpy(alpha: f64, x: &[f64], y: &mut [f64]) -> Result<(), AmlError> {
+    check_len(x, y)?;
+
+    (kernels().daxpy)(alpha, x, y);
+    Ok(())
+}
+
+fn check_len<T>(x: &[T], y: &[T]) -> Result<(), AmlError> {
+    match x.len() == y.len() {
+        true => Ok(()),
+        false => Err(AmlError::SizeMismatch {
+            expected: x.len(),
+            found: y.len(),
+        }),
+    }
+}
+
+/// 8 independent accumulators, so the compiler can keep them in one vector register and the
+/// adds do not serialize on a single sum.
+pub(crate) fn sdot_scalar(x: &[f32], y: &[f32]) -> f32 {
+    let mut lanes = [0f32; 8];
+    let x_chunks = x.chunks_exact(8);
+    let y_chunks = y.chunks_exact(8);
+    let tail: f32 = x_chunks
+        .remainder()
+        .iter()
+        .zip(y_chunks.remainder())
+        .map(|(x, y)| x * y)
+        .sum();
+
+    for (x8, y8) in x_chunks.zip(y_chunks) {
+        for lane in 0..8 {
+            lanes[lane] += x8[lane] * y8[lane];
+        }
+    }
+
+    lanes.iter().sum::<f32>() + tail
+}
+
+pub(crate) fn saxpy_scalar(alpha: f32, x: &[f32], y: &mut [f32]) {
+    for (y_val, x_val) in y.iter_mut().zip(x) {
+        *y_val += alpha * x_val;
+    }
+}
+
+pub(crate) fn sscal_scalar(alpha: f32, x: &mut [f32]) {
+    for x_val in x.iter_mut() {
+        *x_val *= alpha;
+    }
+}
+
+pub(crate) fn snrm2_scalar(x: &[f32]) -> f32 {
+    x.iter()
+        .map(|v| (*v as f64) * (*v as f64))
+        .sum::<f64>()
+        .sqrt() as f32
+}
+
+pub(crate) fn sasum_scalar(x: &[f32]) -> f32 {
+    x.iter().map(|v| v.abs()).sum()
+}
+
+/// 4 independent accumulators, the f64 counterpart of `sdot_scalar`.
+pub(crate) fn ddot_scalar(x: &[f64], y: &[f64]) -> f64 {
+    let mut lanes = [0f64; 4];
+    let x_chunks = x.chunks_exact(4);
+    let y_chunks = y.chunks_exact(4);
+    let tail: f64 = x_chunks
+        .remainder()
+        .iter()
+        .zip(y_chunks.remainder())
+        .map(|(x, y)| x * y)
+        .sum();
+
+    for (x4, y4) in x_chunks.zip(y_chunks) {
+        for lane in 0..4 {
+            lanes[lane] += x4[lane] * y4[lane];
+        }
+    }
+
+    lanes.iter().sum::<f64>() + tail
+}
+
+pub(crate) fn daxpy_scalar(alpha: f64, x: &[f64], y: &mut [f64]) {
+    for (y_val, x_val) in y.iter_mut().zip(x) {
+        *y_val += alpha * x_val;
+    }
+}
+
+pub(crate) fn dsdot_scalar(x: &[f32], y: &[f32]) -> f64 {
+    x.iter().zip(y).map(|(x, y)| *x as f64 * *y as f64).sum()
+}
+
+pub(crate) fn dsaxpy_scalar(alpha: f64, x: &[f32], y: &mut [f64]) {
+    for (y_val, x_val) in y.iter_mut().zip(x) {
+        *y_val += alpha * *x_val as f64;
+    }
+}
+
+/// Sum of the 8 lanes of `v`
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "avx")]
+unsafe fn hsum_avx(v: __m256) -> f32 {
+    let sum4 = _mm_add_ps(_mm256_castps256_ps128(v), _mm256_extractf128_ps(v, 1));
+    let sum2 = _mm_add_ps(sum4, _mm_movehl_ps(sum4, sum4));
+    let sum1 = _mm_add_ss(sum2, _mm_shuffle_ps(sum2, sum2, 1));
+    _mm_cvtss_f32(sum1)
+}
+
+/// Load 8 values, with `_mm256_load_ps` when `ALIGNED` promises `p` sits on 32 bytes.
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "avx")]
+unsafe fn load_avx<const ALIGNED: bool>(p: *const f32) -> __m256 {
+    match ALIGNED {
+        true => _mm256_load_ps(p),
+        false => _mm256_loadu_ps(p),
+    }
+}
+
+/// Store 8 values, aligned as `load_avx`
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "avx")]
+unsafe fn store_avx<const ALIGNED: bool>(p: *mut f32, v: __m256) {
+    match ALIGNED {
+        true => _mm256_store_ps(p, v),
+        false => _mm256_storeu_ps(p, v),
+    }
+}
+
+/// Whether every slice starts on 32 bytes. The kernels step 8 values at a time from there,
+/// so then every load is aligned.
+#[cfg(target_arch = "x86_64")]
+fn aligned_avx(slices: &[&[f32]]) -> bool {
+    slices.iter().all(|values| is_aligned(values, 32))
+}
+
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "avx")]
+pub(crate) unsafe fn sdot_avx(x: &[f32], y: &[f32]) -> f32 {
+    assert_eq!(x.len(), y.len());
+    match aligned_avx(&[x, y]) {
+        true => sdot_avx_loads::<true>(x, y),
+        false => sdot_avx_loads::<false>(x, y),
+    }
+}
+
+/// Two accumulators so consecutive adds do not wait on each other.
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "avx")]
+unsafe fn sdot_avx_loads<const ALIGNED: bool>(x: &[f32], y: &[f32]) -> f32 {
+    let n16 = x.len() / 16 * 16;
+    let mut acc0 = _mm256_setzero_ps();
+    let mut acc1 = _mm256_setzero_ps();
+
+    for i in (0..n16).step_by(16) {
+        let x0 = load_avx::<ALIGNED>(x.as_ptr().add(i));
+        let y0 = load_avx::<ALIGNED>(y.as_ptr().add(i));
+        let x1 = load_avx::<ALIGNED>(x.as_ptr().add(i + 8));
+        let y1 = load_avx::<ALIGNED>(y.as_ptr().add(i + 8));
+        acc0 = _mm256_add_ps(acc0, _mm256_mul_ps(x0, y0));
+        acc1 = _mm256_add_ps(acc1, _mm256_mul_ps(x1, y1));
+    }
+
+    hsum_avx(_mm256_add_ps(acc0, acc1)) + sdot_scalar(&x[n16..], &y[n16..])
+}
+
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "avx")]
+pub(crate) unsafe fn saxpy_avx(alpha: f32, x: &[f32], y: &mut [f32]) {
+    assert_eq!(x.len(), y.len());
+    match aligned_avx(&[x, y]) {
+        true => saxpy_avx_loads::<true>(alpha, x, y),
+        false => saxpy_avx_loads::<false>(alpha, x, y),
+    }
+}
+
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "avx")]
+unsafe fn saxpy_avx_loads<const ALIGNED: bool>(alpha: f32, x: &[f32], y: &mut [f32]) {
+    let n8 = x.len() / 8 * 8;
+    let alpha8 = _mm256_set1_ps(alpha);
+
+    for i in (0..n8).step_by(8) {
+        let x8 = load_avx::<ALIGNED>(x.as_ptr().add(i));
+        let y8 = load_avx::<ALIGNED>(y.as_ptr().add(i));
+        store_avx::<ALIGNED>(
+            y.as_mut_ptr().add(i),
+            _mm256_add_ps(y8, _mm256_mul_ps(alpha8, x8)),
+        );
+    }
+
+    saxpy_scalar(alpha, &x[n8..], &mut y[n8..]);
+}
+
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "avx")]
+pub(crate) unsafe fn sscal_avx(alpha: f32, x: &mut [f32]) {
+    match aligned_avx(&[x]) {
+        true => sscal_avx_loads::<true>(alpha, x),
+        false => sscal_avx_loads::<false>(alpha, x),
+    }
+}
+
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "avx")]
+unsafe fn sscal_avx_loads<const ALIGNED: bool>(alpha: f32, x: &mut [f32]) {
+    let n8 = x.len() / 8 * 8;
+    let alpha8 = _mm256_set1_ps(alpha);
+
+    for i in (0..n8).step_by(8) {
+        let x8 = load_avx::<ALIGNED>(x.as_ptr().add(i));
+        store_avx::<ALIGNED>(x.as_mut_ptr().add(i), _mm256_mul_ps(alpha8, x8));
+    }
+
+    sscal_scalar(alpha, &mut x[n8..]);
+}
+
+/// Widens each half of a vector to f64 before squaring, matching `snrm2_scalar`.
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "avx")]
+pub(crate) unsafe fn snrm2_avx(x: &[f32]) -> f32 {
+    let n4 = x.len() / 4 * 4;
+    let mut acc = _mm256_setzero_pd();
+
+    for i in (0..n4).step_by(4) {
+        let x4 = _mm256_cvtps_pd(_mm_loadu_ps(x.as_ptr().add(i)));
+        acc = _mm256_add_pd(acc, _mm256_mul_pd(x4, x4));
+    }
+
+    let tail: f64 = x[n4..].iter().map(|v| (*v as f64) * (*v as f64)).sum();
+
+    (hsum_pd_avx(acc) + tail).sqrt() as f32
+}
+
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "avx")]
+pub(crate) unsafe fn sasum_avx(x: &[f32]) -> f32 {
+    match aligned_avx(&[x]) {
+        true => sasum_avx_loads::<true>(x),
+        false => sasum_avx_loads::<false>(x),
+    }
+}
+
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "avx")]
+unsafe fn sasum_avx_loads<const ALIGNED: bool>(x: &[f32]) -> f32 {
+    let n8 = x.len() / 8 * 8;
+    let sign_mask = _mm256_set1_ps(-0f32);
+    let mut acc = _mm256_setzero_ps();
+
+    for i in (0..n8).step_by(8) {
+        let x8 = load_avx::<ALIGNED>(x.as_ptr().add(i));
+        acc = _mm256_add_ps(acc, _mm256_andnot_ps(sign_mask, x8));
+    }
+
+    hsum_avx(acc) + sasum_scalar(&x[n8..])
+}
+
+/// Sum of the 4 lanes of `v`
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "avx")]
+unsafe fn hsum_pd_avx(v: __m256d) -> f64 {
+    let sum2 = _mm_add_pd(_mm256_castpd256_pd128(v), _mm256_extractf128_pd(v, 1));
+    let sum1 = _mm_add_sd(sum2, _mm_unpackhi_pd(sum2, sum2));
+    _mm_cvtsd_f64(sum1)
+}
+
+/// 4-wide `__m256d` version of `sdot_avx`
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "avx")]
+pub(crate) unsafe fn ddot_avx(x: &[f64], y: &[f64]) -> f64 {
+    assert_eq!(x.len(), y.len());
+    let n8 = x.len() / 8 * 8;
+    let mut acc0 = _mm256_setzero_pd();
+    let mut acc1 = _mm256_setzero_pd();
+
+    for i in (0..n8).step_by(8) {
+        let x0 = _mm256_loadu_pd(x.as_ptr().add(i));
+        let y0 = _mm256_loadu_pd(y.as_ptr().add(i));
+        let x1 = _mm256_loadu_pd(x.as_ptr().add(i + 4));
+        let y1 = _mm256_loadu_pd(y.as_ptr().add(i + 4));
+        acc0 = _mm256_add_pd(acc0, _mm256_mul_pd(x0, y0));
+        acc1 = _mm256_add_pd(acc1, _mm256_mul_pd(x1, y1));
+    }
+
+    hsum_pd_avx(_mm256_add_pd(acc0, acc1)) + ddot_scalar(&x[n8..], &y[n8..])
+}
+
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "avx")]
+pub(crate) unsafe fn daxpy_avx(alpha: f64, x: &[f64], y: &mut [f64]) {
+    assert_eq!(x.len(), y.len());
+    let n4 = x.len() / 4 * 4;
+    let alpha4 = _mm256_set1_pd(alpha);
+
+    for i in (0..n4).step_by(4) {
+        let x4 = _mm256_loadu_pd(x.as_ptr().add(i));
+        let y4 = _mm256_loadu_pd(y.as_ptr().add(i));
+        _mm256_storeu_pd(
+            y.as_mut_ptr().add(i),
+            _mm256_add_pd(y4, _mm256_mul_pd(alpha4, x4)),
+        );
+    }
+
+    daxpy_scalar(alpha, &x[n4..], &mut y[n4..]);
+}
+
+/// `ddot_avx` on f32 inputs widened 4 at a time
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "avx")]
+pub(crate) unsafe fn dsdot_avx(x: &[f32], y: &[f32]) -> f64 {
+    assert_eq!(x.len(), y.len());
+    let n8 = x.len() / 8 * 8;
+    let mut acc0 = _mm256_setzero_pd();
+    let mut acc1 = _mm256_setzero_pd();
+
+    for i in (0..n8).step_by(8) {
+        let x0 = _mm256_cvtps_pd(_mm_loadu_ps(x.as_ptr().add(i)));
+        let y0 = _mm256_cvtps_pd(_mm_loadu_ps(y.as_ptr().add(i)));
+        let x1 = _mm256_cvtps_pd(_mm_loadu_ps(x.as_ptr().add(i + 4)));
+        let y1 = _mm256_cvtps_pd(_mm_loadu_ps(y.as_ptr().add(i + 4)));
+        acc0 = _mm256_add_pd(acc0, _mm256_mul_pd(x0, y0));
+        acc1 = _mm256_add_pd(acc1, _mm256_mul_pd(x1, y1));
+    }
+
+    hsum_pd_avx(_mm256_add_pd(acc0, acc1)) + dsdot_scalar(&x[n8..], &y[n8..])
+}
+
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "avx")]
+pub(crate) unsafe fn dsaxpy_avx(alpha: f64, x: &[f32], y: &mut [f64]) {
+    assert_eq!(x.len(), y.len());
+    let n4 = x.len() / 4 * 4;
+    let alpha4 = _mm256_set1_pd(alpha);
+
+    for i in (0..n4).step_by(4) {
+        let x4 = _mm256_cvtps_pd(_mm_loadu_ps(x.as_ptr().add(i)));
+        let y4 = _mm256_loadu_pd(y.as_ptr().add(i));
+        _mm256_storeu_pd(
+            y.as_mut_ptr().add(i),
+            _mm256_add_pd(y4, _mm256_mul_pd(alpha4, x4)),
+        );
+    }
+
+    dsaxpy_scalar(alpha, &x[n4..], &mut y[n4..]);
+}
+
+/// Two 4 lane `fmla` accumulators per iteration.
+#[cfg(target_arch = "aarch64")]
+#[target_feature(enable = "neon")]
+pub(crate) unsafe fn sdot_neon(x: &[f32], y: &[f32]) -> f32 {
+    assert_eq!(x.len(), y.len());
+    let n8 = x.len() / 8 * 8;
+    let mut acc0 = vdupq_n_f32(0f32);
+    let mut acc1 = vdupq_n_f32(0f32);
+
+    for i in (0..n8).step_by(8) {
+        acc0 = vfmaq_f32(
+            acc0,
+            vld1q_f32(x.as_ptr().add(i)),
+            vld1q_f32(y.as_ptr().add(i)),
+        );
+        acc1 = vfmaq_f32(
+            acc1,
+            vld1q_f32(x.as_ptr().add(i + 4)),
+            vld1q_f32(y.as_ptr().add(i + 4)),
+        );
+    }
+
+    vaddvq_f32(vaddq_f32(acc0, acc1)) + sdot_scalar(&x[n8..], &y[n8..])
+}
+
+#[cfg(target_arch = "aarch64")]
+#[target_feature(enable = "neon")]
+pub(crate) unsafe fn saxpy_neon(alpha: f32, x: &[f32], y: &mut [f32]) {
+    assert_eq!(x.len(), y.len());
+    let n4 = x.len() / 4 * 4;
+    let alpha4 = vdupq_n_f32(alpha);
+
+    for i in (0..n4).step_by(4) {
+        let y4 = vld1q_f32(y.as_ptr().add(i));
+        vst1q_f32(
+            y.as_mut_ptr().add(i),
+            vfmaq_f32(y4, alpha4, vld1q_f32(x.as_ptr().add(i))),
+        );
+    }
+
+    saxpy_scalar(alpha, &x[n4..], &mut y[n4..]);
+}
+
+/// Vector length agnostic: `whilelo` predicates each pass, so one loop covers any SVE width and
+/// the tail with no scalar cleanup.
+///
+/// Written as inline asm because the SVE intrinsics are not stable yet. The z registers it uses
+/// are named one by one rather than with `clobber_abi("C")`, which would also claim the
+/// reserved first-fault register.
+#[cfg(target_arch = "aarch64")]
+#[target_feature(enable = "sve")]
+pub(crate) unsafe fn sdot_sve(x: &[f32], y: &[f32]) -> f32 {
+    assert_eq!(x.len(), y.len());
+    let sum: f32;
+    std::arch::asm!(
+        "dup z0.s, #0",
+        "whilelo p0.s, {i}, {n}",
+        "b.none 2f",
+        "1:",
+        "ld1w {{ z1.s }}, p0/z, [{x}, {i}, lsl #2]",
+        "ld1w {{ z2.s }}, p0/z, [{y}, {i}, lsl #2]",
+        "fmla z0.s, p0/m, z1.s, z2.s",
+        "incw {i}",
+        "whilelo p0.s, {i}, {n}",
+        "b.first 1b",
+        "2:",
+        "ptrue p0.s",
+        "faddv s0, p0, z0.s",
+        i = inout(reg) 0usize => _,
+        n = in(reg) x.len(),
+        x = in(reg) x.as_ptr(),
+        y = in(reg) y.as_ptr(),
+        lateout("v0") sum,
+        out("v1") _,
+        out("v2") _,
+        out("p0") _,
+        options(nostack, readonly),
+    );
+    sum
+}
+
+/// `saxpy` with the same predicated loop as `sdot_sve`.
+#[cfg(target_arch = "aarch64")]
+#[target_feature(enable = "sve")]
+pub(crate) unsafe fn saxpy_sve(alpha: f32, x: &[f32], y: &mut [f32]) {
+    assert_eq!(x.len(), y.len());
+    std::arch::asm!(
+        "mov z3.s, {alpha:s}",
+        "whilelo p0.s, {i}, {n}",
+        "b.none 2f",
+        "1:",
+        "ld1w {{ z1.s }}, p0/z, [{x}, {i}, lsl #2]",
+        "ld1w {{ z2.s }}, p0/z, [{y}, {i}, lsl #2]",
+        "fmla z2.s, p0/m, z1.s, z3.s",
+        "st1w {{ z2.s }}, p0, [{y}, {i}, lsl #2]",
+        "incw {i}",
+        "whilelo p0.s, {i}, {n}",
+        "b.first 1b",
+        "2:",
+        alpha = in(vreg) alpha,
+        i = inout(reg) 0usize => _,
+        n = in(reg) x.len(),
+        x = in(reg) x.as_ptr(),
+        y = in(reg) y.as_mut_ptr(),
+        out("v1") _,
+        out("v2") _,
+        out("v3") _,
+        out("p0") _,
+        options(nostack),
+    );
+}
+
+/// `a * b + c`, fused with relaxed-simd and a separate multiply and add without.
+#[cfg(all(target_arch = "wasm32", target_feature = "simd128"))]
+#[inline(always)]
+pub(crate) fn madd_simd128(a: v128, b: v128, c: v128) -> v128 {
+    #[cfg(target_feature = "relaxed-simd")]
+    {
+        f32x4_relaxed_madd(a, b, c)
+    }
+    #[cfg(not(target_feature = "relaxed-simd"))]
+    {
+        f32x4_add(f32x4_mul(a, b), c)
+    }
+}
+
+#[cfg(all(target_arch = "wasm32", target_feature = "simd128"))]
+pub(crate) fn sdot_simd128(x: &[f32], y: &[f32]) -> f32 {
+    assert_eq!(x.len(), y.len());
+    let n8 = x.len() / 8 * 8;
+    let mut acc0 = f32x4_splat(0f32);
+    let mut acc1 = f32x4_splat(0f32);
+
+    for i in (0..n8).step_by(8) {
+        unsafe {
+            let x0 = v128_load(x.as_ptr().add(i).cast());
+            let y0 = v128_load(y.as_ptr().add(i).cast());
+            let x1 = v128_load(x.as_ptr().add(i + 4).cast());
+            let y1 = v128_load(y.as_ptr().add(i + 4).cast());
+            acc0 = madd_simd128(x0, y0, acc0);
+            acc1 = madd_simd128(x1, y1, acc1);
+        }
+    }
+
+    let acc = f32x4_add(acc0, acc1);
+    f32x4_extract_lane::<0>(acc)
+        + f32x4_extract_lane::<1>(acc)
+        + f32x4_extract_lane::<2>(acc)
+        + f32x4_extract_lane::<3>(acc)
+        + sdot_scalar(&x[n8..], &y[n8..])
+}
+
+#[cfg(all(target_arch = "wasm32", target_feature = "simd128"))]
+pub(crate) fn saxpy_simd128(alpha: f32, x: &[f32], y: &mut [f32]) {
+    assert_eq!(x.len(), y.len());
+    let n4 = x.len() / 4 * 4;
+    let alpha4 = f32x4_splat(alpha);
+
+    for i in (0..n4).step_by(4) {
+        unsafe {
+            let x4 = v128_load(x.as_ptr().add(i).cast());
+            let y4 = v128_load(y.as_ptr().add(i).cast());
+            v128_store(y.as_mut_ptr().add(i).cast(), madd_simd128(alpha4, x4, y4));
+        }
+    }
+
+    saxpy_scalar(alpha, &x[n4..], &mut y[n4..]);
+}
@@ -0,0 +1,136 @@
+//! BLAS level 2: f32 matrix-vector operations.
+
+use crate::{
+    blas1, check_inner, check_rank, parallel, AmlError, AsTensorRef, F32Tensor, GemmParams, Layout,
+};
+
+/// Matrix-vector product `op(a) @ x` as a new vector.
+///
+/// `op(a)` (m, n), `x` (n,), returning (m,). Cheaper than an `sgemm` with `x` as a one-column
+/// `b`, which packs `x` and pads it out to the width of a microkernel tile.
+pub fn sgemv(a: &impl AsTensorRef<f32>, a_transpose: bool, x: &[f32]) -> Vec<f32> {
+    try_sgemv(a, a_transpose, x).unwrap_or_else(|e| panic!("{}", e))
+}
+
+/// Fallible version of `sgemv`
+pub fn try_sgemv(
+    a: &impl AsTensorRef<f32>,
+    a_transpose: bool,
+    x: &[f32],
+) -> Result<Vec<f32>, AmlError> {
+    let a = a.as_tensor_ref();
+    check_rank("a", &a.shape, 2)?;
+    let m = match a_transpose {
+        true => a.shape[1],
+        false => a.shape[0],
+    };
+
+    let mut y = vec![0f32; m];
+    try_sgemv_with(&a, a_transpose, x, GemmParams::default(), &mut y)?;
+    Ok(y)
+}
+
+/// `y = alpha * (op(a) @ x) + beta * y`
+///
+/// The values of `y` are split across threads. When the rows of `op(a)` are rows in memory (a
+/// row-major `a`, or a transposed column-major one) each value is an `sdot` of one with `x`;
+/// otherwise each thread adds up its block of `y` one `saxpy` per column, so `a` is still read
+/// in the order it is stored.
+pub fn sgemv_with(
+    a: &impl AsTensorRef<f32>,
+    a_transpose: bool,
+    x: &[f32],
+    params: GemmParams,
+    y: &mut [f32],
+) {
+    try_sgemv_with(a, a_transpose, x, params, y).unwrap_or_else(|e| panic!("{}", e))
+}
+
+/// Fallible version of `sgemv_with`. `y` is left untouched on error.
+pub fn try_sgemv_with(
+    a: &impl AsTensorRef<f32>,
+    a_transpose: bool,
+    x: &[f32],
+    params: GemmParams,
+    y: &mut [f32],
+) -> Result<(), AmlError> {
+    let a = a.as_tensor_ref();
+    check_rank("a", &a.shape, 2)?;
+    let (m, n) = match a_transpose {
+        true => (a.shape[1], a.shape[0]),
+        false => (a.shape[0], a.shape[1]),
+    };
+    check_inner(n, x.len())?;
+    if y.len() != m {
+        return Err(AmlError::SizeMismatch {
+            expected: m,
+            found: y.len(),
+        });
+    }
+    if n == 0 {
+        y.iter_mut().for_each(|y| *y = params.apply(0f32, *y));
+        return Ok(());
+    }
+
+    let a_copy = a.blas_copy();
+    let a = a_copy.as_ref().map_or(a, AsTensorRef::as_tensor_ref);
+    let lda = a.ld();
+    match a.stored_transpose(a_transpose) {
+        false => parallel::for_each_row_chunk(y, 1, n, |first_row, y| {
+            for (i, y) in (first_row..).zip(y.iter_mut()) {
+                *y = params.apply(blas1::sdot(&a.values[i * lda..][..n], x), *y);
+            }
+        }),
+        true => parallel::for_each_row_chunk(y, 1, n, |first_row, y| {
+            let mut acc = vec![0f32; y.len()];
+            for (j, x) in x.iter().enumerate() {
+                blas1::saxpy(*x, &a.values[j * lda + first_row..][..y.len()], &mut acc);
+            }
+            for (y, acc) in y.iter_mut().zip(acc) {
+                *y = params.apply(acc, *y);
+            }
+        }),
+    }
+    Ok(())
+}
+
+/// Rank-1 update `a += alpha * x @ y^T`
+///
+/// `a` (m, n), `x` (m,), `y` (n,). Rows of `a` are split across threads, and each row is a single
+/// `saxpy` with `y`, so `y` stays in cache while a whole block of rows is updated. A column-major
+/// `a` is updated as `a^T += alpha * y @ x^T`, one `saxpy` with `x` per column.
+pub fn sger(alpha: f32, x: &[f32], y: &[f32], a: &mut F32Tensor) {
+    try_sger(alpha, x, y, a).unwrap_or_else(|e| panic!("{}", e))
+}
+
+/// Fallible version of `sger`. `a` is left untouched on error.
+pub fn try_sger(alpha: f32, x: &[f32], y: &[f32], a: &mut F32Tensor) -> Result<(), AmlError> {
+    check_rank("a", &a.shape, 2)?;
+    a.check_blas("a")?;
+    let (m, n) = (a.shape[0], a.shape[1]);
+    if x.len() != m {
+        return Err(AmlError::SizeMismatch {
+            expected: m,
+            found: x.len(),
+        });
+    }
+    if y.len() != n {
+        return Err(AmlError::SizeMismatch {
+            expected: n,
+            found: y.len(),
+        });
+    }
+
+    let (x, y, n) = match a.layout {
+        Layout::RowMajor => (x, y, n),
+        Layout::ColMajor => (y, x, m),
+    };
+    let lda = a.ld();
+    parallel::for_each_row_chunk(&mut a.values, lda, n, |first_row, a_rows| {
+        for (x_val, a_row) in x[first_row..].iter().zip(a_rows.chunks_mut(lda)) {
+            blas1::saxpy(alpha * x_val, y, &mut a_row[..n]);
+        }
+    });
+
+    Ok(())
+}
@@ -0,0 +1,324 @@
+//! BLAS level 3: f32 matrix-matrix operations beyond plain GEMM.
+
+use crate::{
+    blas1, check_rank, check_row_major, parallel, strided_rows, AmlError, F32Tensor, GemmParams,
+    Layout,
+};
+
+/// Which triangle of a square matrix an operation reads or writes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Uplo {
+    /// On and above the diagonal
+    Upper,
+    /// On and below the diagonal
+    Lower,
+}
+
+/// Whether the diagonal of a triangular matrix is read, or taken to be all ones.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Diag {
+    /// Use the stored diagonal
+    NonUnit,
+    /// Treat the diagonal as ones without reading it
+    Unit,
+}
+
+/// Which side of the unknown a triangular matrix sits on in `strsm`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Side {
+    /// `a @ x = alpha * b`
+    Left,
+    /// `x @ a = alpha * b`
+    Right,
+}
+
+impl Uplo {
+    /// Columns of row `i` (of `n`) inside the triangle.
+    fn cols(self, i: usize, n: usize) -> std::ops::Range<usize> {
+        match self {
+            Uplo::Upper => i..n,
+            Uplo::Lower => 0..i + 1,
+        }
+    }
+}
+
+/// Symmetric rank-k update `c = alpha * a @ a^T + beta * c`, or `a^T @ a` if `a_transpose`.
+///
+/// `a` (n, k), or (k, n) when transposed, and `c` (n, n). Only the `uplo` triangle of `c` is
+/// computed or written, which is half the work of the equivalent `qgemm`; the other triangle
+/// keeps whatever it held before. The `uplo` triangle of a column-major `c` is the other one
+/// of its values read row-major, which is what gets written.
+pub fn ssyrk(uplo: Uplo, a: &F32Tensor, a_transpose: bool, params: GemmParams, c: &mut F32Tensor) {
+    try_ssyrk(uplo, a, a_transpose, params, c).unwrap_or_else(|e| panic!("{}", e))
+}
+
+/// Fallible version of `ssyrk`. `c` is left untouched on error.
+pub fn try_ssyrk(
+    uplo: Uplo,
+    a: &F32Tensor,
+    a_transpose: bool,
+    params: GemmParams,
+    c: &mut F32Tensor,
+) -> Result<(), AmlError> {
+    check_rank("a", &a.shape, 2)?;
+    check_rank("c", &c.shape, 2)?;
+
+    let (n, k) = match a_transpose {
+        true => (a.shape[1], a.shape[0]),
+        false => (a.shape[0], a.shape[1]),
+    };
+    if c.shape != [n, n] {
+        return Err(AmlError::OutputShapeMismatch {
+            expected: vec![n, n],
+            found: c.shape.to_vec(),
+        });
+    }
+    c.check_blas("c")?;
+    let a_copy = a.blas_copy();
+    let a = a_copy.as_ref().unwrap_or(a);
+    let a_transpose = a.stored_transpose(a_transpose);
+    let uplo = match (c.layout, uplo) {
+        (Layout::RowMajor, _) => uplo,
+        (Layout::ColMajor, Uplo::Upper) => Uplo::Lower,
+        (Layout::ColMajor, Uplo::Lower) => Uplo::Upper,
+    };
+
+    let (lda, ldc) = (a.ld(), c.ld());
+    parallel::for_each_row_chunk(&mut c.values, ldc, n * k / 2, |first_row, c_rows| {
+        match a_transpose {
+            // every entry is a dot product of two rows of `a`
+            false => {
+                for (i, c_row) in (first_row..).zip(c_rows.chunks_mut(ldc)) {
+                    let a_i = &a.values[i * lda..][..k];
+                    for j in uplo.cols(i, n) {
+                        let a_j = &a.values[j * lda..][..k];
+                        c_row[j] = params.apply(blas1::sdot(a_i, a_j), c_row[j]);
+                    }
+                }
+            }
+            // sum the outer product of each row of `a` with itself, clipped to the triangle
+            true => {
+                let rows = c_rows.len().div_ceil(ldc);
+                let mut acc = vec![0f32; rows * n];
+                for a_p in strided_rows(&a.values, lda, n) {
+                    for (i, acc_row) in (first_row..).zip(acc.chunks_exact_mut(n)) {
+                        let cols = uplo.cols(i, n);
+                        blas1::saxpy(a_p[i], &a_p[cols.clone()], &mut acc_row[cols]);
+                    }
+                }
+                for (i, (c_row, acc_row)) in
+                    (first_row..).zip(c_rows.chunks_mut(ldc).zip(acc.chunks_exact(n)))
+                {
+                    for j in uplo.cols(i, n) {
+                        c_row[j] = params.apply(acc_row[j], c_row[j]);
+                    }
+                }
+            }
+        }
+    });
+
+    Ok(())
+}
+
+/// Triangular matrix multiply, in place: `b = alpha * a @ b`.
+///
+/// `a` (m, m) is triangular: only its `uplo` triangle is read (and not its diagonal when `diag` is
+/// `Unit`), so the zero half costs nothing. `b` (m, n) is overwritten row by row, in the order
+/// that leaves the rows still needed by later rows untouched. Both must be row-major, and `b`
+/// unpadded.
+pub fn strmm(uplo: Uplo, diag: Diag, alpha: f32, a: &F32Tensor, b: &mut F32Tensor) {
+    try_strmm(uplo, diag, alpha, a, b).unwrap_or_else(|e| panic!("{}", e))
+}
+
+/// Fallible version of `strmm`. `b` is left untouched on error.
+pub fn try_strmm(
+    uplo: Uplo,
+    diag: Diag,
+    alpha: f32,
+    a: &F32Tensor,
+    b: &mut F32Tensor,
+) -> Result<(), AmlError> {
+    let (m, n) = check_triangular(a, b)?;
+    let a_values = a.dense();
+
+    let mut update_row = |i: usize| {
+        let a_row = &a_values[i * m..(i + 1) * m];
+        let (before, rest) = b.values.split_at_mut(i * n);
+        let (b_row, after) = rest.split_at_mut(n);
+
+        if diag == Diag::NonUnit {
+            blas1::sscal(a_row[i], b_row);
+        }
+        let others = match uplo {
+            Uplo::Lower => before.chunks_exact(n).zip(&a_row[..i]),
+            Uplo::Upper => after.chunks_exact(n).zip(&a_row[i + 1..]),
+        };
+        for (b_p, a_ip) in others {
+            blas1::saxpy(*a_ip, b_p, b_row);
+        }
+        blas1::sscal(alpha, b_row);
+    };
+
+    // lower rows read the rows above them, upper rows the rows below
+    match uplo {
+        Uplo::Lower => (0..m).rev().for_each(&mut update_row),
+        Uplo::Upper => (0..m).for_each(&mut update_row),
+    }
+
+    Ok(())
+}
+
+/// Rows of the diagonal block `strsm` solves before updating the rest of `b` with it.
+const TRSM_BLOCK: usize = 64;
+
+/// Triangular solve, in place: `b` is overwritten with `x` where `a @ x = alpha * b` (`Side::Left`)
+/// or `x @ a = alpha * b` (`Side::Right`).
+///
+/// `a` is read like in `strmm`, and both must be row-major, `b` unpadded. The left side solve works through
+/// `a` in diagonal blocks of rows: each block is back-substituted, then subtracted from every row
+/// still unsolved, split across threads, while the freshly solved block is hot in cache. On the
+/// right side every row of `b` is an independent solve, so rows are split across threads
+/// directly.
+///
+/// Like BLAS, the diagonal is not checked for zeros; a singular `a` yields infinities or NaNs.
+pub fn strsm(side: Side, uplo: Uplo, diag: Diag, alpha: f32, a: &F32Tensor, b: &mut F32Tensor) {
+    try_strsm(side, uplo, diag, alpha, a, b).unwrap_or_else(|e| panic!("{}", e))
+}
+
+/// Fallible version of `strsm`. `b` is left untouched on error.
+pub fn try_strsm(
+    side: Side,
+    uplo: Uplo,
+    diag: Diag,
+    alpha: f32,
+    a: &F32Tensor,
+    b: &mut F32Tensor,
+) -> Result<(), AmlError> {
+    match side {
+        Side::Left => {
+            check_triangular(a, b)?;
+            strsm_left(uplo, diag, alpha, a, b);
+        }
+        Side::Right => {
+            check_rank("a", &a.shape, 2)?;
+            check_rank("b", &b.shape, 2)?;
+            check_row_major("a", a.layout)?;
+            check_row_major("b", b.layout)?;
+            b.check_contiguous()?;
+            if a.shape[0] != a.shape[1] {
+                return Err(AmlError::NotSquare {
+                    shape: a.shape.to_vec(),
+                });
+            }
+            crate::check_inner(b.shape[1], a.shape[0])?;
+            strsm_right(uplo, diag, alpha, a, b);
+        }
+    }
+
+    Ok(())
+}
+
+fn strsm_left(uplo: Uplo, diag: Diag, alpha: f32, a: &F32Tensor, b: &mut F32Tensor) {
+    let (m, n) = (b.shape[0], b.shape[1]);
+    let a_values = a.dense();
+    blas1::sscal(alpha, &mut b.values);
+
+    let mut block_starts: Vec<usize> = (0..m).step_by(TRSM_BLOCK).collect();
+    if uplo == Uplo::Upper {
+        block_starts.reverse();
+    }
+
+    for i0 in block_starts {
+        let i1 = (i0 + TRSM_BLOCK).min(m);
+
+        // substitution inside the diagonal block
+        let mut solve_row = |i: usize| {
+            let a_row = &a_values[i * m..(i + 1) * m];
+            let (before, rest) = b.values.split_at_mut(i * n);
+            let (b_row, after) = rest.split_at_mut(n);
+
+            let solved = match uplo {
+                Uplo::Lower => before[i0 * n..].chunks_exact(n).zip(&a_row[i0..i]),
+                Uplo::Upper => after[..(i1 - i - 1) * n]
+                    .chunks_exact(n)
+                    .zip(&a_row[i + 1..i1]),
+            };
+            for (x_p, a_ip) in solved {
+                blas1::saxpy(-a_ip, x_p, b_row);
+            }
+            if diag == Diag::NonUnit {
+                blas1::sscal(1f32 / a_row[i], b_row);
+            }
+        };
+        match uplo {
+            Uplo::Lower => (i0..i1).for_each(&mut solve_row),
+            Uplo::Upper => (i0..i1).rev().for_each(&mut solve_row),
+        }
+
+        // remove the solved block from the rows that still depend on it
+        let (x_block, trailing, first_trailing) = match uplo {
+            Uplo::Lower => {
+                let (solved, trailing) = b.values.split_at_mut(i1 * n);
+                (&solved[i0 * n..], trailing, i1)
+            }
+            Uplo::Upper => {
+                let (trailing, solved) = b.values.split_at_mut(i0 * n);
+                (&solved[..(i1 - i0) * n], trailing, 0)
+            }
+        };
+        parallel::for_each_row_chunk(trailing, n, (i1 - i0) * n, |first_row, b_rows| {
+            for (i, b_row) in (first_trailing + first_row..).zip(b_rows.chunks_exact_mut(n)) {
+                let a_block = &a_values[i * m + i0..i * m + i1];
+                for (x_p, a_ip) in x_block.chunks_exact(n).zip(a_block) {
+                    blas1::saxpy(-a_ip, x_p, b_row);
+                }
+            }
+        });
+    }
+}
+
+fn strsm_right(uplo: Uplo, diag: Diag, alpha: f32, a: &F32Tensor, b: &mut F32Tensor) {
+    let n = b.shape[1];
+    let a_values = a.dense();
+
+    parallel::for_each_row_chunk(&mut b.values, n, n * n / 2, |_, b_rows| {
+        for b_row in b_rows.chunks_exact_mut(n) {
+            blas1::sscal(alpha, b_row);
+
+            // x_p is final once every x_q feeding into column p has been subtracted from it
+            let mut solve_col = |p: usize| {
+                let a_row = &a_values[p * n..(p + 1) * n];
+                if diag == Diag::NonUnit {
+                    b_row[p] /= a_row[p];
+                }
+                let x_p = b_row[p];
+                match uplo {
+                    Uplo::Lower => blas1::saxpy(-x_p, &a_row[..p], &mut b_row[..p]),
+                    Uplo::Upper => blas1::saxpy(-x_p, &a_row[p + 1..], &mut b_row[p + 1..]),
+                }
+            };
+            match uplo {
+                Uplo::Lower => (0..n).rev().for_each(&mut solve_col),
+                Uplo::Upper => (0..n).for_each(&mut solve_col),
+            }
+        }
+    });
+}
+
+/// Check `a` is square and matches the rows of `b`, both row-major and `b` unpadded, returning
+/// the shape of `b`.
+fn check_triangular(a: &F32Tensor, b: &F32Tensor) -> Result<(usize, usize), AmlError> {
+    check_rank("a", &a.shape, 2)?;
+    check_rank("b", &b.shape, 2)?;
+    check_row_major("a", a.layout)?;
+    check_row_major("b", b.layout)?;
+    b.check_contiguous()?;
+    if a.shape[0] != a.shape[1] {
+        return Err(AmlError::NotSquare {
+            shape: a.shape.to_vec(),
+        });
+    }
+    crate::check_inner(a.shape[1], b.shape[0])?;
+
+    Ok((b.shape[0], b.shape[1]))
+}
@@ -0,0 +1,204 @@
+//! GEMM cache blocking derived from the cache sizes of the machine.
+//!
+//! The blocked GEMMs sweep `kc` deep panels of `b`, so that a `kc` x 16 strip of `b` stays in
+//! L1 while a `mc` x `kc` block of `a` stays in L2 and a `kc` x `nc` panel of `b` in L3.
+
+use crate::AmlError;
+use std::sync::{OnceLock, RwLock};
+
+/// Data cache sizes in bytes, per core for L1 and L2.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CacheInfo {
+    pub l1d: usize,
+    pub l2: usize,
+    pub l3: usize,
+}
+
+impl CacheInfo {
+    /// Sizes assumed when the machine cannot be queried: a typical desktop core.
+    pub const FALLBACK: CacheInfo = CacheInfo {
+        l1d: 32 << 10,
+        l2: 256 << 10,
+        l3: 8 << 20,
+    };
+
+    /// Read the cache sizes from sysfs on Linux, or `cpuid` leaf 4 on other x86_64 systems,
+    /// falling back to `CacheInfo::FALLBACK` for anything not found.
+    pub fn detect() -> CacheInfo {
+        let mut info = CacheInfo {
+            l1d: 0,
+            l2: 0,
+            l3: 0,
+        };
+        for (level, size) in sysfs_caches().into_iter().chain(cpuid_caches()) {
+            let slot = match level {
+                1 => &mut info.l1d,
+                2 => &mut info.l2,
+                3 => &mut info.l3,
+                _ => continue,
+            };
+            if *slot == 0 {
+                *slot = size;
+            }
+        }
+
+        let fallback = CacheInfo::FALLBACK;
+        CacheInfo {
+            l1d: nonzero_or(info.l1d, fallback.l1d),
+            l2: nonzero_or(info.l2, fallback.l2),
+            l3: nonzero_or(info.l3, fallback.l3),
+        }
+    }
+}
+
+fn nonzero_or(value: usize, default: usize) -> usize {
+    match value {
+        0 => default,
+        _ => value,
+    }
+}
+
+/// `(level, bytes)` of the data and unified caches of cpu0, e.g. `index0/size` = "48K".
+fn sysfs_caches() -> Vec<(usize, usize)> {
+    let mut caches = Vec::new();
+    for index in 0.. {
+        let dir = format!("/sys/devices/system/cpu/cpu0/cache/index{}", index);
+        let read = |file: &str| std::fs::read_to_string(format!("{}/{}", dir, file));
+        let (Ok(level), Ok(kind), Ok(size)) = (read("level"), read("type"), read("size")) else {
+            break;
+        };
+        if kind.trim() == "Instruction" {
+            continue;
+        }
+
+        let size = size.trim();
+        let (digits, unit) = match size.strip_suffix('K') {
+            Some(digits) => (digits, 1 << 10),
+            None => match size.strip_suffix('M') {
+                Some(digits) => (digits, 1 << 20),
+                None => (size, 1),
+            },
+        };
+        if let (Ok(level), Ok(digits)) = (level.trim().parse(), digits.parse::<usize>()) {
+            caches.push((level, digits * unit));
+        }
+    }
+
+    caches
+}
+
+/// `(level, bytes)` of the data and unified caches from `cpuid` leaf 4 (Intel's deterministic
+/// cache parameters). Empty on other architectures and on CPUs without the leaf.
+fn cpuid_caches() -> Vec<(usize, usize)> {
+    #[allow(unused_mut)]
+    let mut caches = Vec::new();
+
+    #[cfg(target_arch = "x86_64")]
+    {
+        use std::arch::x86_64::__cpuid_count;
+
+        if __cpuid_count(0, 0).eax >= 4 {
+            for sub in 0.. {
+                let leaf = __cpuid_count(4, sub);
+                let kind = leaf.eax & 0x1f;
+                if kind == 0 {
+                    break;
+                }
+                if kind == 2 {
+                    continue;
+                }
+                let level = (leaf.eax >> 5 & 0x7) as usize;
+                let ways = (leaf.ebx >> 22) as usize + 1;
+                let partitions = (leaf.ebx >> 12 & 0x3ff) as usize + 1;
+                let line = (leaf.ebx & 0xfff) as usize + 1;
+                let sets = leaf.ecx as usize + 1;
+                caches.push((level, ways * partitions * line * sets));
+            }
+        }
+    }
+
+    caches
+}
+
+/// Cache blocking for the f32 GEMMs, in elements: rows of `a` (`mc`), depth (`kc`) and columns
+/// of `b` (`nc`) per block. `dgemm` halves `kc` and `hgemm` doubles it, keeping the bytes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BlockSizes {
+    pub mc: usize,
+    pub kc: usize,
+    pub nc: usize,
+}
+
+impl BlockSizes {
+    /// Half of each cache level for the block that should live there, the other half left for
+    /// `c` and whatever else is running.
+    pub fn for_cache(cache: &CacheInfo) -> BlockSizes {
+        const F32: usize = 4;
+        const STRIP: usize = 16;
+
+        let kc = (cache.l1d / 2 / (STRIP * F32)).clamp(64, 1024);
+        let mc = (cache.l2 / 2 / (kc * F32)).clamp(16, 4096);
+        let nc = (cache.l3 / 2 / (kc * F32)).clamp(64, 1 << 16);
+
+        BlockSizes { mc, kc, nc }
+    }
+}
+
+static OVERRIDE: RwLock<Option<BlockSizes>> = RwLock::new(None);
+
+/// Cache sizes of this machine, detected on first use.
+pub fn cache_info() -> CacheInfo {
+    static CACHE: OnceLock<CacheInfo> = OnceLock::new();
+
+    *CACHE.get_or_init(CacheInfo::detect)
+}
+
+/// Block sizes the GEMMs use: the override if one is set, else `AML_BLOCK_SIZE` if the process
+/// started with it, else derived from `cache_info()`.
+pub fn block_sizes() -> BlockSizes {
+    static DETECTED: OnceLock<BlockSizes> = OnceLock::new();
+
+    match *OVERRIDE.read().unwrap_or_else(|e| e.into_inner()) {
+        Some(sizes) => sizes,
+        None => *DETECTED.get_or_init(|| {
+            std::env::var("AML_BLOCK_SIZE")
+                .ok()
+                .and_then(|sizes| parse_block_sizes(&sizes))
+                .unwrap_or_else(|| BlockSizes::for_cache(&cache_info()))
+        }),
+    }
+}
+
+/// `mc,kc,nc` as three positive integers, e.g. `AML_BLOCK_SIZE=128,256,4096`
+pub(crate) fn parse_block_sizes(sizes: &str) -> Option<BlockSizes> {
+    let sizes: Vec<usize> = sizes
+        .split(',')
+        .map(|size| size.trim().parse().ok().filter(|size| *size > 0))
+        .collect::<Option<_>>()?;
+    match sizes[..] {
+        [mc, kc, nc] => Some(BlockSizes { mc, kc, nc }),
+        _ => None,
+    }
+}
+
+/// Whether `set_block_sizes` has replaced the detected sizes
+pub(crate) fn is_overridden() -> bool {
+    OVERRIDE.read().unwrap_or_else(|e| e.into_inner()).is_some()
+}
+
+/// Replace the detected block sizes for every later GEMM call, or go back to them with `None`.
+pub fn set_block_sizes(sizes: Option<BlockSizes>) {
+    try_set_block_sizes(sizes).unwrap_or_else(|e| panic!("{}", e))
+}
+
+/// Fallible version of `set_block_sizes`. The current sizes are kept on error.
+pub fn try_set_block_sizes(sizes: Option<BlockSizes>) -> Result<(), AmlError> {
+    if let Some(BlockSizes { mc, kc, nc }) = sizes {
+        if mc == 0 || kc == 0 || nc == 0 {
+            return Err(AmlError::ZeroBlockSize { mc, kc, nc });
+        }
+    }
+
+    *OVERRIDE.write().unwrap_or_else(|e| e.into_inner()) = sizes;
+    Ok(())
+}
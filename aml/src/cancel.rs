@@ -0,0 +1,29 @@
+//! Stopping a long GEMM from another thread.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+/// A flag that `sgemm_cancellable` checks before each macro-tile it computes, for servers that
+/// abort a request while its GEMM is running.
+///
+/// Clones share the flag: keep one, hand another to the thread running the GEMM, and `cancel`
+/// from anywhere. A token stays cancelled.
+#[derive(Debug, Clone, Default)]
+pub struct CancelToken {
+    cancelled: Arc<AtomicBool>,
+}
+
+impl CancelToken {
+    pub fn new() -> CancelToken {
+        CancelToken::default()
+    }
+
+    /// Stop every GEMM checking this token at its next macro-tile
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed)
+    }
+}
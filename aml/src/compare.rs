@@ -0,0 +1,118 @@
+//! Approximate equality of tensors, for checking one kernel against another.
+
+use crate::{offset, AsTensorRef, Element, TensorRef};
+use std::fmt;
+
+/// One value of `a` too far from its counterpart in `b`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Mismatch {
+    pub index: Vec<usize>,
+    pub a: f64,
+    pub b: f64,
+}
+
+/// Why `allclose` failed.
+#[derive(Debug, Clone, PartialEq)]
+pub enum MismatchReport {
+    /// The tensors differ in shape, so no values were compared.
+    Shape { a: Vec<usize>, b: Vec<usize> },
+    /// `count` values are out of tolerance. `first` is the first of them in row-major order,
+    /// `worst` the one furthest outside its tolerance, NaNs first.
+    Values {
+        count: usize,
+        first: Mismatch,
+        worst: Mismatch,
+    },
+}
+
+impl fmt::Display for MismatchReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MismatchReport::Shape { a, b } => {
+                write!(f, "Shapes {:?} and {:?} differ.", a, b)
+            }
+            MismatchReport::Values {
+                count,
+                first,
+                worst,
+            } => write!(
+                f,
+                "{} values differ; first at {:?}: {} vs {}, worst at {:?}: {} vs {}.",
+                count, first.index, first.a, first.b, worst.index, worst.a, worst.b
+            ),
+        }
+    }
+}
+
+impl std::error::Error for MismatchReport {}
+
+/// Check every value of `a` is within `atol + rtol * |b|` of the value of `b` at the same index,
+/// as NumPy's `allclose` does. NaN matches nothing, not even NaN. The tensors may differ in
+/// layout and strides; values are compared by index.
+pub fn allclose<T: Element>(
+    a: &impl AsTensorRef<T>,
+    b: &impl AsTensorRef<T>,
+    rtol: f64,
+    atol: f64,
+) -> Result<(), MismatchReport> {
+    let (a, b) = (a.as_tensor_ref(), b.as_tensor_ref());
+    if a.shape != b.shape {
+        return Err(MismatchReport::Shape {
+            a: a.shape.to_vec(),
+            b: b.shape.to_vec(),
+        });
+    }
+
+    let mut count = 0;
+    let mut found: Option<(Mismatch, (f64, Mismatch))> = None;
+    for index in indices(&a.shape) {
+        let (a_val, b_val) = (at(&a, &index), at(&b, &index));
+        let excess = (a_val - b_val).abs() - (atol + rtol * b_val.abs());
+        if excess <= 0f64 {
+            continue;
+        }
+
+        count += 1;
+        let excess = match excess.is_nan() {
+            true => f64::INFINITY,
+            false => excess,
+        };
+        let mismatch = Mismatch {
+            index,
+            a: a_val,
+            b: b_val,
+        };
+        found = match found {
+            None => Some((mismatch.clone(), (excess, mismatch))),
+            Some((first, worst)) if excess > worst.0 => Some((first, (excess, mismatch))),
+            some => some,
+        };
+    }
+
+    match found {
+        None => Ok(()),
+        Some((first, (_, worst))) => Err(MismatchReport::Values {
+            count,
+            first,
+            worst,
+        }),
+    }
+}
+
+/// Value of `t` at `index`, widened to f64
+fn at<T: Element>(t: &TensorRef<T>, index: &[usize]) -> f64 {
+    t.values[offset(index, &t.shape, &t.strides)].to_f64()
+}
+
+/// Every index of `shape`, the last axis fastest
+fn indices(shape: &[usize]) -> impl Iterator<Item = Vec<usize>> + '_ {
+    let count = shape.iter().product::<usize>();
+    (0..count).map(move |mut flat| {
+        let mut index = vec![0; shape.len()];
+        for (i, n) in index.iter_mut().zip(shape).rev() {
+            *i = flat % n;
+            flat /= n;
+        }
+        index
+    })
+}
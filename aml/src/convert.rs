@@ -0,0 +1,240 @@
+//! Conversions between element types, for the casts around every mixed-precision GEMM.
+//!
+//! f16 and bf16 go through f32, which holds both exactly, so each conversion rounds once, to
+//! nearest even; anything involving f64 converts value by value. The SIMD kernels round exactly
+//! as the scalar ones do, so results do not depend on the CPU.
+
+use crate::dispatch::kernels;
+use crate::{AmlError, Element};
+use half::{bf16, f16};
+use std::any::TypeId;
+#[cfg(target_arch = "x86_64")]
+use std::arch::x86_64::*;
+
+/// Values converted per pass through the f32 staging buffer
+const CHUNK: usize = 256;
+
+/// Convert every value of `src` into `dst`, e.g. `convert::<f32, bf16>(&weights, &mut packed)`.
+/// Lengths must match.
+pub fn convert<S: Element, D: Element>(src: &[S], dst: &mut [D]) {
+    try_convert(src, dst).unwrap_or_else(|e| panic!("{}", e))
+}
+
+/// Fallible version of `convert`. `dst` is left untouched on error.
+pub fn try_convert<S: Element, D: Element>(src: &[S], dst: &mut [D]) -> Result<(), AmlError> {
+    check_len(src.len(), dst.len())?;
+
+    let f64 = TypeId::of::<f64>();
+    if TypeId::of::<S>() == f64 || TypeId::of::<D>() == f64 {
+        for (src_val, dst_val) in src.iter().zip(dst.iter_mut()) {
+            *dst_val = D::from_f64(src_val.to_f64());
+        }
+        return Ok(());
+    }
+
+    let mut staged = [0f32; CHUNK];
+    for (src_part, dst_part) in src.chunks(CHUNK).zip(dst.chunks_mut(CHUNK)) {
+        let staged = &mut staged[..src_part.len()];
+        S::to_f32_slice(src_part, staged);
+        D::from_f32_slice(staged, dst_part);
+    }
+    Ok(())
+}
+
+/// `dst = clamp(round(src / scale) + zero_point)`, the inverse of `I8Tensor`'s
+/// `scale * (q - zero_point)`. Halves round to even, and NaN becomes -128. Lengths must match.
+pub fn quantize_i8(src: &[f32], scale: f32, zero_point: i8, dst: &mut [i8]) {
+    try_quantize_i8(src, scale, zero_point, dst).unwrap_or_else(|e| panic!("{}", e))
+}
+
+/// Fallible version of `quantize_i8`. `dst` is left untouched on error.
+pub fn try_quantize_i8(
+    src: &[f32],
+    scale: f32,
+    zero_point: i8,
+    dst: &mut [i8],
+) -> Result<(), AmlError> {
+    check_len(src.len(), dst.len())?;
+    (kernels().quantize_i8)(src, scale, zero_point, dst);
+    Ok(())
+}
+
+/// `dst = scale * (src - zero_point)`, the values an `I8Tensor` stands for. Lengths must match.
+pub fn dequantize_i8(src: &[i8], scale: f32, zero_point: i8, dst: &mut [f32]) {
+    try_dequantize_i8(src, scale, zero_point, dst).unwrap_or_else(|e| panic!("{}", e))
+}
+
+/// Fallible version of `dequantize_i8`. `dst` is left untouched on error.
+pub fn try_dequantize_i8(
+    src: &[i8],
+    scale: f32,
+    zero_point: i8,
+    dst: &mut [f32],
+) -> Result<(), AmlError> {
+    check_len(src.len(), dst.len())?;
+    (kernels().dequantize_i8)(src, scale, zero_point, dst);
+    Ok(())
+}
+
+fn check_len(src: usize, dst: usize) -> Result<(), AmlError> {
+    match src == dst {
+        true => Ok(()),
+        false => Err(AmlError::SizeMismatch {
+            expected: src,
+            found: dst,
+        }),
+    }
+}
+
+pub(crate) fn h_to_s_scalar(src: &[f16], dst: &mut [f32]) {
+    for (src_val, dst_val) in src.iter().zip(dst.iter_mut()) {
+        *dst_val = src_val.to_f32();
+    }
+}
+
+pub(crate) fn s_to_h_scalar(src: &[f32], dst: &mut [f16]) {
+    for (src_val, dst_val) in src.iter().zip(dst.iter_mut()) {
+        *dst_val = f16::from_f32(*src_val);
+    }
+}
+
+pub(crate) fn bf_to_s_scalar(src: &[bf16], dst: &mut [f32]) {
+    for (src_val, dst_val) in src.iter().zip(dst.iter_mut()) {
+        *dst_val = src_val.to_f32();
+    }
+}
+
+pub(crate) fn s_to_bf_scalar(src: &[f32], dst: &mut [bf16]) {
+    for (src_val, dst_val) in src.iter().zip(dst.iter_mut()) {
+        *dst_val = bf16::from_f32(*src_val);
+    }
+}
+
+pub(crate) fn quantize_i8_scalar(src: &[f32], scale: f32, zero_point: i8, dst: &mut [i8]) {
+    for (src_val, dst_val) in src.iter().zip(dst.iter_mut()) {
+        let q = (src_val / scale).round_ties_even() + zero_point as f32;
+        // `maxps` picks the bound over a NaN, so NaN saturates low
+        *dst_val = match q.is_nan() {
+            true => i8::MIN,
+            false => q.clamp(-128f32, 127f32) as i8,
+        };
+    }
+}
+
+pub(crate) fn dequantize_i8_scalar(src: &[i8], scale: f32, zero_point: i8, dst: &mut [f32]) {
+    for (src_val, dst_val) in src.iter().zip(dst.iter_mut()) {
+        *dst_val = scale * (*src_val as i32 - zero_point as i32) as f32;
+    }
+}
+
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "avx,f16c")]
+pub(crate) unsafe fn h_to_s_f16c(src: &[f16], dst: &mut [f32]) {
+    assert_eq!(src.len(), dst.len());
+    let n8 = src.len() / 8 * 8;
+    for i in (0..n8).step_by(8) {
+        let halves = _mm_loadu_si128(src.as_ptr().add(i) as *const __m128i);
+        _mm256_storeu_ps(dst.as_mut_ptr().add(i), _mm256_cvtph_ps(halves));
+    }
+    h_to_s_scalar(&src[n8..], &mut dst[n8..]);
+}
+
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "avx,f16c")]
+pub(crate) unsafe fn s_to_h_f16c(src: &[f32], dst: &mut [f16]) {
+    assert_eq!(src.len(), dst.len());
+    let n8 = src.len() / 8 * 8;
+    for i in (0..n8).step_by(8) {
+        let values = _mm256_loadu_ps(src.as_ptr().add(i));
+        let halves = _mm256_cvtps_ph::<_MM_FROUND_TO_NEAREST_INT>(values);
+        _mm_storeu_si128(dst.as_mut_ptr().add(i) as *mut __m128i, halves);
+    }
+    s_to_h_scalar(&src[n8..], &mut dst[n8..]);
+}
+
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "avx2")]
+pub(crate) unsafe fn bf_to_s_avx2(src: &[bf16], dst: &mut [f32]) {
+    assert_eq!(src.len(), dst.len());
+    let n8 = src.len() / 8 * 8;
+    for i in (0..n8).step_by(8) {
+        let halves = _mm_loadu_si128(src.as_ptr().add(i) as *const __m128i);
+        let bits = _mm256_slli_epi32(_mm256_cvtepu16_epi32(halves), 16);
+        _mm256_storeu_ps(dst.as_mut_ptr().add(i), _mm256_castsi256_ps(bits));
+    }
+    bf_to_s_scalar(&src[n8..], &mut dst[n8..]);
+}
+
+/// Rounds on the bits as `bf16::from_f32` does: add `0x7fff` plus the lowest kept bit, so ties
+/// go to even, and keep the top half. NaNs are kept quiet instead.
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "avx2")]
+pub(crate) unsafe fn s_to_bf_avx2(src: &[f32], dst: &mut [bf16]) {
+    assert_eq!(src.len(), dst.len());
+    let n8 = src.len() / 8 * 8;
+    let (one, bias, quiet) = (
+        _mm256_set1_epi32(1),
+        _mm256_set1_epi32(0x7fff),
+        _mm256_set1_epi32(0x40),
+    );
+    for i in (0..n8).step_by(8) {
+        let values = _mm256_loadu_ps(src.as_ptr().add(i));
+        let bits = _mm256_castps_si256(values);
+        let top = _mm256_srli_epi32(bits, 16);
+        let lsb = _mm256_and_si256(top, one);
+        let rounded = _mm256_srli_epi32(_mm256_add_epi32(_mm256_add_epi32(bits, bias), lsb), 16);
+        let nan = _mm256_castps_si256(_mm256_cmp_ps::<_CMP_UNORD_Q>(values, values));
+        let halves = _mm256_blendv_epi8(rounded, _mm256_or_si256(top, quiet), nan);
+        // pack within each 128 bit lane, then bring the two low quarters together
+        let packed = _mm256_permute4x64_epi64::<0b1000>(_mm256_packus_epi32(halves, halves));
+        _mm_storeu_si128(
+            dst.as_mut_ptr().add(i) as *mut __m128i,
+            _mm256_castsi256_si128(packed),
+        );
+    }
+    s_to_bf_scalar(&src[n8..], &mut dst[n8..]);
+}
+
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "avx2")]
+pub(crate) unsafe fn quantize_i8_avx2(src: &[f32], scale: f32, zero_point: i8, dst: &mut [i8]) {
+    assert_eq!(src.len(), dst.len());
+    let n8 = src.len() / 8 * 8;
+    let scale8 = _mm256_set1_ps(scale);
+    let zero8 = _mm256_set1_ps(zero_point as f32);
+    let (low, high) = (_mm256_set1_ps(-128f32), _mm256_set1_ps(127f32));
+    let gather = _mm256_setr_epi32(0, 4, 0, 0, 0, 0, 0, 0);
+    for i in (0..n8).step_by(8) {
+        let scaled = _mm256_div_ps(_mm256_loadu_ps(src.as_ptr().add(i)), scale8);
+        let rounded = _mm256_round_ps::<{ _MM_FROUND_TO_NEAREST_INT | _MM_FROUND_NO_EXC }>(scaled);
+        let clamped = _mm256_min_ps(_mm256_max_ps(_mm256_add_ps(rounded, zero8), low), high);
+        // already in range, so the saturating packs only narrow; each lane ends up holding
+        // its four values in its first four bytes
+        let q16 = _mm256_packs_epi32(_mm256_cvtps_epi32(clamped), _mm256_setzero_si256());
+        let q8 = _mm256_packs_epi16(q16, _mm256_setzero_si256());
+        let packed = _mm256_permutevar8x32_epi32(q8, gather);
+        _mm_storel_epi64(
+            dst.as_mut_ptr().add(i) as *mut __m128i,
+            _mm256_castsi256_si128(packed),
+        );
+    }
+    quantize_i8_scalar(&src[n8..], scale, zero_point, &mut dst[n8..]);
+}
+
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "avx2")]
+pub(crate) unsafe fn dequantize_i8_avx2(src: &[i8], scale: f32, zero_point: i8, dst: &mut [f32]) {
+    assert_eq!(src.len(), dst.len());
+    let n8 = src.len() / 8 * 8;
+    let scale8 = _mm256_set1_ps(scale);
+    let zero8 = _mm256_set1_epi32(zero_point as i32);
+    for i in (0..n8).step_by(8) {
+        let bytes = _mm_loadl_epi64(src.as_ptr().add(i) as *const __m128i);
+        let q = _mm256_sub_epi32(_mm256_cvtepi8_epi32(bytes), zero8);
+        _mm256_storeu_ps(
+            dst.as_mut_ptr().add(i),
+            _mm256_mul_ps(scale8, _mm256_cvtepi32_ps(q)),
+        );
+    }
+    dequantize_i8_scalar(&src[n8..], scale, zero_point, &mut dst[n8..]);
+}
@@ -0,0 +1,80 @@
+//! Tensors that borrow their values until first written, for weights that are usually read
+//! but sometimes patched.
+
+use crate::{AsTensorMut, AsTensorRef, Element, Tensor, TensorMut, TensorRef};
+
+/// A tensor borrowed until something writes it, then copied once into an owned one.
+///
+/// A pipeline can hold every weight as a `CowTensor` over the loaded model and pay for a copy
+/// only for the few it changes, e.g. by merging a LoRA update into them or tying them to
+/// another. Reads, and the kernels through `AsTensorRef`, never copy. Writing through
+/// `to_mut`, or passing it as the output of a kernel, copies a borrowed tensor into a
+/// contiguous one of the same layout first.
+pub enum CowTensor<'a, T: Element> {
+    Borrowed(TensorRef<'a, T>),
+    Owned(Tensor<T>),
+}
+
+impl<'a, T: Element> CowTensor<'a, T> {
+    /// Whether no copy has been made yet
+    pub fn is_borrowed(&self) -> bool {
+        matches!(self, CowTensor::Borrowed(_))
+    }
+
+    pub fn is_owned(&self) -> bool {
+        !self.is_borrowed()
+    }
+
+    /// The owned tensor, copied from the borrowed one on first call
+    pub fn to_mut(&mut self) -> &mut Tensor<T> {
+        if let CowTensor::Borrowed(borrowed) = self {
+            *self = CowTensor::Owned(borrowed.to_contiguous());
+        }
+        match self {
+            CowTensor::Owned(owned) => owned,
+            CowTensor::Borrowed(_) => unreachable!("copied above"),
+        }
+    }
+
+    /// The owned tensor, copying a borrowed one
+    pub fn into_owned(self) -> Tensor<T> {
+        match self {
+            CowTensor::Borrowed(borrowed) => borrowed.to_contiguous(),
+            CowTensor::Owned(owned) => owned,
+        }
+    }
+}
+
+impl<'a, T: Element> From<TensorRef<'a, T>> for CowTensor<'a, T> {
+    fn from(tensor: TensorRef<'a, T>) -> CowTensor<'a, T> {
+        CowTensor::Borrowed(tensor)
+    }
+}
+
+impl<'a, T: Element> From<&'a Tensor<T>> for CowTensor<'a, T> {
+    fn from(tensor: &'a Tensor<T>) -> CowTensor<'a, T> {
+        CowTensor::Borrowed(tensor.as_tensor_ref())
+    }
+}
+
+impl<T: Element> From<Tensor<T>> for CowTensor<'_, T> {
+    fn from(tensor: Tensor<T>) -> Self {
+        CowTensor::Owned(tensor)
+    }
+}
+
+impl<T: Element> AsTensorRef<T> for CowTensor<'_, T> {
+    fn as_tensor_ref(&self) -> TensorRef<'_, T> {
+        match self {
+            CowTensor::Borrowed(borrowed) => borrowed.clone(),
+            CowTensor::Owned(owned) => owned.as_tensor_ref(),
+        }
+    }
+}
+
+/// Copies a borrowed tensor before the kernel writes it
+impl<T: Element> AsTensorMut<T> for CowTensor<'_, T> {
+    fn as_tensor_mut(&mut self) -> TensorMut<'_, T> {
+        self.to_mut().view_mut()
+    }
+}
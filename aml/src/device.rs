@@ -0,0 +1,307 @@
+//! Where tensors live and GEMMs run: host memory and aml's kernels, or a GPU.
+//!
+//! A `DeviceTensor` is an f32 tensor stored on one `Device`, made by `Tensor::to_device` and
+//! copied back by `DeviceTensor::to_host`; those two are the only transfers. `device_sgemm`
+//! runs on the device its operands share, and refuses operands on different devices rather
+//! than copying them, so no transfer is ever hidden inside a GEMM. Every `Device` variant
+//! exists in every build, and one whose backend was not compiled in is `DeviceUnavailable`.
+//!
+//! GPUs sum in f32 as they go, so `Accuracy::High` runs only on the CPU.
+
+#[cfg(feature = "gpu-cuda")]
+use crate::gpu_cuda;
+#[cfg(all(feature = "gpu-metal", target_os = "macos"))]
+use crate::gpu_metal;
+#[cfg(feature = "gpu-opencl")]
+use crate::gpu_opencl;
+#[cfg(feature = "gpu-wgpu")]
+use crate::gpu_wgpu;
+use crate::{
+    check_gemm, try_sgemm_with, Accuracy, AmlError, AsTensorRef, GemmParams, Layout, Shape, Tensor,
+    TensorMut, TensorRef,
+};
+
+/// A place to store tensors and run GEMMs on
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+#[non_exhaustive]
+pub enum Device {
+    /// Host memory and aml's own kernels
+    #[default]
+    Cpu,
+    /// The GPU of the `n`th adapter wgpu lists, through Vulkan, Metal, DX12 or GL, with the
+    /// `gpu-wgpu` feature
+    Wgpu(usize),
+    /// The `n`th NVIDIA GPU, in CUDA's order, through cuBLAS with the `gpu-cuda` feature
+    Cuda(usize),
+    /// The `n`th GPU Metal lists on a Mac, with the `gpu-metal` feature
+    Metal(usize),
+    /// The `n`th GPU of any OpenCL 1.2 platform, in platform order, with the `gpu-opencl`
+    /// feature
+    OpenCl(usize),
+}
+
+/// An f32 tensor stored on a `Device`, row-major.
+///
+/// Its values can only be read by copying them to the host with `to_host`.
+pub struct DeviceTensor {
+    shape: Shape,
+    storage: Storage,
+}
+
+/// The values of a `DeviceTensor`, in the memory of its device
+enum Storage {
+    Cpu(Vec<f32>),
+    #[cfg(feature = "gpu-wgpu")]
+    Wgpu(gpu_wgpu::Buffer),
+    #[cfg(feature = "gpu-cuda")]
+    Cuda(gpu_cuda::Buffer),
+    #[cfg(all(feature = "gpu-metal", target_os = "macos"))]
+    Metal(gpu_metal::Buffer),
+    #[cfg(feature = "gpu-opencl")]
+    OpenCl(gpu_opencl::Buffer),
+}
+
+impl Device {
+    /// Whether tensors can be made on this device: its backend was compiled in, and its driver
+    /// found the hardware
+    pub fn is_available(self) -> bool {
+        match self {
+            Device::Cpu => true,
+            #[cfg(feature = "gpu-wgpu")]
+            Device::Wgpu(ordinal) => gpu_wgpu::is_available(ordinal),
+            #[cfg(feature = "gpu-cuda")]
+            Device::Cuda(ordinal) => gpu_cuda::is_available(ordinal),
+            #[cfg(all(feature = "gpu-metal", target_os = "macos"))]
+            Device::Metal(ordinal) => gpu_metal::is_available(ordinal),
+            #[cfg(feature = "gpu-opencl")]
+            Device::OpenCl(ordinal) => gpu_opencl::is_available(ordinal),
+            #[allow(unreachable_patterns)]
+            _ => false,
+        }
+    }
+
+    /// `DeviceUnavailable` for a device whose backend is not compiled in
+    #[allow(dead_code)]
+    fn not_built(self, feature: &str) -> AmlError {
+        AmlError::DeviceUnavailable {
+            device: self,
+            reason: format!("aml was built without the `{}` feature", feature),
+        }
+    }
+}
+
+impl DeviceTensor {
+    /// A copy of `tensor` on `device`
+    pub fn from_host(tensor: &impl AsTensorRef<f32>, device: Device) -> DeviceTensor {
+        DeviceTensor::try_from_host(tensor, device).unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    /// Fallible version of `from_host`
+    pub fn try_from_host(
+        tensor: &impl AsTensorRef<f32>,
+        device: Device,
+    ) -> Result<DeviceTensor, AmlError> {
+        let tensor = tensor.as_tensor_ref();
+        let values = tensor.dense_in(Layout::RowMajor);
+        let storage = match device {
+            Device::Cpu => Storage::Cpu(values.into_owned()),
+            #[cfg(feature = "gpu-wgpu")]
+            Device::Wgpu(ordinal) => Storage::Wgpu(gpu_wgpu::upload(ordinal, &values)?),
+            #[cfg(not(feature = "gpu-wgpu"))]
+            Device::Wgpu(_) => return Err(device.not_built("gpu-wgpu")),
+            #[cfg(feature = "gpu-cuda")]
+            Device::Cuda(ordinal) => Storage::Cuda(gpu_cuda::upload(ordinal, &values)?),
+            #[cfg(not(feature = "gpu-cuda"))]
+            Device::Cuda(_) => return Err(device.not_built("gpu-cuda")),
+            #[cfg(all(feature = "gpu-metal", target_os = "macos"))]
+            Device::Metal(ordinal) => Storage::Metal(gpu_metal::upload(ordinal, &values)?),
+            #[cfg(all(feature = "gpu-metal", not(target_os = "macos")))]
+            Device::Metal(_) => {
+                return Err(AmlError::DeviceUnavailable {
+                    device,
+                    reason: "Metal runs only on macOS".to_string(),
+                })
+            }
+            #[cfg(not(feature = "gpu-metal"))]
+            Device::Metal(_) => return Err(device.not_built("gpu-metal")),
+            #[cfg(feature = "gpu-opencl")]
+            Device::OpenCl(ordinal) => Storage::OpenCl(gpu_opencl::upload(ordinal, &values)?),
+            #[cfg(not(feature = "gpu-opencl"))]
+            Device::OpenCl(_) => return Err(device.not_built("gpu-opencl")),
+        };
+        Ok(DeviceTensor {
+            shape: tensor.shape.clone(),
+            storage,
+        })
+    }
+
+    /// Zeros of `shape` on `device`, e.g. the output of `device_sgemm`
+    pub fn zeros(shape: impl Into<Shape>, device: Device) -> DeviceTensor {
+        DeviceTensor::try_zeros(shape, device).unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    /// Fallible version of `zeros`
+    pub fn try_zeros(shape: impl Into<Shape>, device: Device) -> Result<DeviceTensor, AmlError> {
+        DeviceTensor::try_from_host(&Tensor::<f32>::zeros(shape), device)
+    }
+
+    pub fn device(&self) -> Device {
+        match &self.storage {
+            Storage::Cpu(_) => Device::Cpu,
+            #[cfg(feature = "gpu-wgpu")]
+            Storage::Wgpu(buffer) => Device::Wgpu(buffer.ordinal()),
+            #[cfg(feature = "gpu-cuda")]
+            Storage::Cuda(buffer) => Device::Cuda(buffer.ordinal()),
+            #[cfg(all(feature = "gpu-metal", target_os = "macos"))]
+            Storage::Metal(buffer) => Device::Metal(buffer.ordinal()),
+            #[cfg(feature = "gpu-opencl")]
+            Storage::OpenCl(buffer) => Device::OpenCl(buffer.ordinal()),
+        }
+    }
+
+    pub fn shape(&self) -> &Shape {
+        &self.shape
+    }
+
+    /// A copy of the values in host memory, row-major
+    pub fn to_host(&self) -> Tensor<f32> {
+        self.try_to_host().unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    /// Fallible version of `to_host`
+    pub fn try_to_host(&self) -> Result<Tensor<f32>, AmlError> {
+        let values = match &self.storage {
+            Storage::Cpu(values) => values.clone(),
+            #[cfg(feature = "gpu-wgpu")]
+            Storage::Wgpu(buffer) => gpu_wgpu::download(buffer)?,
+            #[cfg(feature = "gpu-cuda")]
+            Storage::Cuda(buffer) => gpu_cuda::download(buffer)?,
+            #[cfg(all(feature = "gpu-metal", target_os = "macos"))]
+            Storage::Metal(buffer) => gpu_metal::download(buffer)?,
+            #[cfg(feature = "gpu-opencl")]
+            Storage::OpenCl(buffer) => gpu_opencl::download(buffer)?,
+        };
+        Tensor::try_new(values, self.shape.clone())
+    }
+
+    /// A copy on `device`, through host memory
+    pub fn to_device(&self, device: Device) -> DeviceTensor {
+        self.try_to_device(device)
+            .unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    /// Fallible version of `to_device`
+    pub fn try_to_device(&self, device: Device) -> Result<DeviceTensor, AmlError> {
+        DeviceTensor::try_from_host(&self.try_to_host()?, device)
+    }
+}
+
+impl Tensor<f32> {
+    /// A copy on `device`, as `DeviceTensor::from_host`
+    pub fn to_device(&self, device: Device) -> DeviceTensor {
+        DeviceTensor::from_host(self, device)
+    }
+
+    /// Fallible version of `to_device`
+    pub fn try_to_device(&self, device: Device) -> Result<DeviceTensor, AmlError> {
+        DeviceTensor::try_from_host(self, device)
+    }
+}
+
+/// `c = op(a) @ op(b)` on the device of the operands, as `sgemm`
+pub fn device_sgemm(
+    a: &DeviceTensor,
+    a_transpose: bool,
+    b: &DeviceTensor,
+    b_transpose: bool,
+    c: &mut DeviceTensor,
+) {
+    try_device_sgemm(a, a_transpose, b, b_transpose, c).unwrap_or_else(|e| panic!("{}", e))
+}
+
+/// Fallible version of `device_sgemm`
+pub fn try_device_sgemm(
+    a: &DeviceTensor,
+    a_transpose: bool,
+    b: &DeviceTensor,
+    b_transpose: bool,
+    c: &mut DeviceTensor,
+) -> Result<(), AmlError> {
+    try_device_sgemm_with(a, a_transpose, b, b_transpose, GemmParams::default(), c)
+}
+
+/// `c = alpha * op(a) @ op(b) + beta * c` on the device of the operands, as `sgemm_with`
+pub fn device_sgemm_with(
+    a: &DeviceTensor,
+    a_transpose: bool,
+    b: &DeviceTensor,
+    b_transpose: bool,
+    params: GemmParams,
+    c: &mut DeviceTensor,
+) {
+    try_device_sgemm_with(a, a_transpose, b, b_transpose, params, c)
+        .unwrap_or_else(|e| panic!("{}", e))
+}
+
+/// Fallible version of `device_sgemm_with`. `c` is left untouched on error.
+pub fn try_device_sgemm_with(
+    a: &DeviceTensor,
+    a_transpose: bool,
+    b: &DeviceTensor,
+    b_transpose: bool,
+    params: GemmParams,
+    c: &mut DeviceTensor,
+) -> Result<(), AmlError> {
+    for (operand, tensor) in [("b", b), ("c", &*c)] {
+        if tensor.device() != a.device() {
+            return Err(AmlError::DeviceMismatch {
+                operand,
+                expected: a.device(),
+                found: tensor.device(),
+            });
+        }
+    }
+    check_gemm(&a.shape, a_transpose, &b.shape, b_transpose, &c.shape)?;
+    if params.accuracy == Accuracy::High && a.device() != Device::Cpu {
+        return Err(AmlError::UnsupportedOnDevice {
+            operation: "`Accuracy::High`",
+            device: a.device(),
+        });
+    }
+    #[allow(unused_variables)]
+    let dims = (c.shape[0], c.shape[1], a.shape[(!a_transpose) as usize]);
+
+    #[allow(unreachable_patterns)]
+    match (&a.storage, &b.storage, &mut c.storage) {
+        (Storage::Cpu(a_values), Storage::Cpu(b_values), Storage::Cpu(c_values)) => try_sgemm_with(
+            &row_major(a_values, &a.shape),
+            a_transpose,
+            &row_major(b_values, &b.shape),
+            b_transpose,
+            params,
+            &mut TensorMut::try_new(c_values, c.shape.clone())?,
+        ),
+        #[cfg(feature = "gpu-wgpu")]
+        (Storage::Wgpu(a), Storage::Wgpu(b), Storage::Wgpu(c)) => {
+            gpu_wgpu::sgemm(a, a_transpose, b, b_transpose, dims, params, c)
+        }
+        #[cfg(feature = "gpu-cuda")]
+        (Storage::Cuda(a), Storage::Cuda(b), Storage::Cuda(c)) => {
+            gpu_cuda::sgemm(a, a_transpose, b, b_transpose, dims, params, c)
+        }
+        #[cfg(all(feature = "gpu-metal", target_os = "macos"))]
+        (Storage::Metal(a), Storage::Metal(b), Storage::Metal(c)) => {
+            gpu_metal::sgemm(a, a_transpose, b, b_transpose, dims, params, c)
+        }
+        #[cfg(feature = "gpu-opencl")]
+        (Storage::OpenCl(a), Storage::OpenCl(b), Storage::OpenCl(c)) => {
+            gpu_opencl::sgemm(a, a_transpose, b, b_transpose, dims, params, c)
+        }
+        _ => unreachable!("operands were checked to share a device"),
+    }
+}
+
+/// `values` as the row-major matrix `shape`
+fn row_major<'a>(values: &'a [f32], shape: &Shape) -> TensorRef<'a, f32> {
+    TensorRef::new_with_ld(values, shape.clone(), Layout::RowMajor, shape[1].max(1))
+}
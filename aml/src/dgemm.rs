@@ -0,0 +1,121 @@
+//! Dense f64 matrix multiply.
+
+#[cfg(feature = "backend-blas")]
+use crate::{backend, AsTensorRef};
+use crate::{
+    blas1, block_sizes, check_gemm, gemm_operands, op_a_rows, parallel, store_rows, strided_rows,
+    AmlError, F64Tensor, GemmParams,
+};
+
+/// Matrix multiply in f64: `op(a) @ op(b)` stored in `c`, overwriting it.
+///
+/// op(F64(m, k)) @ op(F64(k, n)) --> F64(m, n). Rows of `c` are split across threads; each
+/// thread packs its rows of `op(a)` contiguously if `a` is transposed, then either takes AVX dot
+/// products against the rows of a transposed `b`, or sweeps `kc` rows of `b` at a time with AVX
+/// axpys into its rows of `c` (`block_sizes().kc / 2`, the same bytes as `sgemm`).
+pub fn dgemm(
+    a: &F64Tensor,
+    a_transpose: bool,
+    b: &F64Tensor,
+    b_transpose: bool,
+    c: &mut F64Tensor,
+) {
+    try_dgemm(a, a_transpose, b, b_transpose, c).unwrap_or_else(|e| panic!("{}", e))
+}
+
+/// Fallible version of `dgemm`. `c` is left untouched on error.
+pub fn try_dgemm(
+    a: &F64Tensor,
+    a_transpose: bool,
+    b: &F64Tensor,
+    b_transpose: bool,
+    c: &mut F64Tensor,
+) -> Result<(), AmlError> {
+    try_dgemm_with(a, a_transpose, b, b_transpose, GemmParams::default(), c)
+}
+
+/// `c = alpha * (op(a) @ op(b)) + beta * c`
+pub fn dgemm_with(
+    a: &F64Tensor,
+    a_transpose: bool,
+    b: &F64Tensor,
+    b_transpose: bool,
+    params: GemmParams<f64>,
+    c: &mut F64Tensor,
+) {
+    try_dgemm_with(a, a_transpose, b, b_transpose, params, c).unwrap_or_else(|e| panic!("{}", e))
+}
+
+/// Fallible version of `dgemm_with`. `c` is left untouched on error.
+pub fn try_dgemm_with(
+    a: &F64Tensor,
+    a_transpose: bool,
+    b: &F64Tensor,
+    b_transpose: bool,
+    params: GemmParams<f64>,
+    c: &mut F64Tensor,
+) -> Result<(), AmlError> {
+    check_gemm(&a.shape, a_transpose, &b.shape, b_transpose, &c.shape)?;
+    c.check_blas("c")?;
+    let (a_copy, b_copy) = (a.blas_copy(), b.blas_copy());
+    let (a, b) = (a_copy.as_ref().unwrap_or(a), b_copy.as_ref().unwrap_or(b));
+    let (a, a_transpose, b, b_transpose) = gemm_operands(c.layout, a, a_transpose, b, b_transpose);
+    #[cfg_attr(not(feature = "backend-blas"), allow(unused_mut))]
+    let mut c = c.view_mut().row_major();
+    #[cfg(feature = "backend-blas")]
+    if backend::use_blas(&params)
+        && backend::dgemm(
+            &a.as_tensor_ref(),
+            a_transpose,
+            &b.as_tensor_ref(),
+            b_transpose,
+            params,
+            &mut c,
+        )
+    {
+        return Ok(());
+    }
+
+    let n = c.shape[1];
+    let k = match a_transpose {
+        true => a.shape[0],
+        false => a.shape[1],
+    };
+    let a_transpose = a.stored_transpose(a_transpose);
+    let b_transpose = b.stored_transpose(b_transpose);
+    let ldc = c.ld();
+
+    parallel::for_each_row_chunk(c.values, ldc, n * k, |first_row, c_rows| {
+        let rows = c_rows.len().div_ceil(ldc);
+        let a_rows = op_a_rows(&a.values, a_transpose, k, a.ld(), first_row, rows);
+        let mut acc = vec![0f64; rows * n];
+
+        match b_transpose {
+            true => {
+                for (a_i, acc_row) in a_rows.chunks_exact(k).zip(acc.chunks_exact_mut(n)) {
+                    for (acc_val, b_j) in acc_row.iter_mut().zip(strided_rows(&b.values, b.ld(), k))
+                    {
+                        *acc_val = blas1::ddot(a_i, b_j);
+                    }
+                }
+            }
+            false => {
+                let kc = (block_sizes().kc / 2).max(1);
+                for p0 in (0..k).step_by(kc) {
+                    let p1 = (p0 + kc).min(k);
+                    let b_panel = &b.values[p0 * b.ld()..];
+                    for (a_i, acc_row) in a_rows.chunks_exact(k).zip(acc.chunks_exact_mut(n)) {
+                        for (a_ip, b_p) in a_i[p0..p1].iter().zip(strided_rows(b_panel, b.ld(), n))
+                        {
+                            blas1::daxpy(*a_ip, b_p, acc_row);
+                        }
+                    }
+                }
+            }
+        }
+
+        store_rows(&acc, n, c_rows, ldc, |acc, c| *c = params.apply(acc, *c));
+    });
+
+    Ok(())
+}
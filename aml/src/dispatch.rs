@@ -0,0 +1,209 @@
+//! Kernel selection. The CPU is probed once, on first use, and the best kernel for each
+//! operation is kept as a plain function pointer, so the hot paths make one indirect call
+//! instead of re-checking features.
+
+#[cfg(all(feature = "amx", target_arch = "x86_64", target_os = "linux"))]
+use crate::amx;
+use crate::elementwise::{self, Binary};
+use crate::microkernel::Microkernel;
+use crate::reduce::{self, Reduce};
+use crate::{blas1, convert, hgemm, i4, igemm, sbgemm};
+use crate::{norm, softmax};
+use half::{bf16, f16};
+use std::sync::OnceLock;
+
+/// The kernel chosen for every dispatched operation.
+pub(crate) struct Kernels {
+    pub(crate) sdot: fn(&[f32], &[f32]) -> f32,
+    pub(crate) saxpy: fn(f32, &[f32], &mut [f32]),
+    pub(crate) sscal: fn(f32, &mut [f32]),
+    pub(crate) snrm2: fn(&[f32]) -> f32,
+    pub(crate) sasum: fn(&[f32]) -> f32,
+    pub(crate) ddot: fn(&[f64], &[f64]) -> f64,
+    pub(crate) daxpy: fn(f64, &[f64], &mut [f64]),
+    pub(crate) dsdot: fn(&[f32], &[f32]) -> f64,
+    pub(crate) dsaxpy: fn(f64, &[f32], &mut [f64]),
+    pub(crate) hdot: fn(&[f16], &[f16]) -> f32,
+    pub(crate) haxpy: fn(f32, &[f16], &mut [f32]),
+    pub(crate) sbdot: fn(&[bf16], &[bf16]) -> f32,
+    pub(crate) idot: fn(&[i8], &[i8]) -> i32,
+    pub(crate) i4_block_dot: fn(&[f16], &[i8], i8) -> f32,
+    pub(crate) h_to_s: fn(&[f16], &mut [f32]),
+    pub(crate) s_to_h: fn(&[f32], &mut [f16]),
+    pub(crate) bf_to_s: fn(&[bf16], &mut [f32]),
+    pub(crate) s_to_bf: fn(&[f32], &mut [bf16]),
+    pub(crate) quantize_i8: fn(&[f32], f32, i8, &mut [i8]),
+    pub(crate) dequantize_i8: fn(&[i8], f32, i8, &mut [f32]),
+    pub(crate) binary: fn(Binary, &[f32], &[f32], &mut [f32]),
+    pub(crate) binary_splat: fn(Binary, &[f32], f32, &mut [f32]),
+    #[allow(clippy::type_complexity)]
+    pub(crate) fma: fn(&[f32], &[f32], &[f32], &mut [f32]),
+    pub(crate) reduce: fn(Reduce, &[f32]) -> f32,
+    pub(crate) reduce_into: fn(Reduce, &[f32], &mut [f32]),
+    pub(crate) exp_sum: fn(f32, &mut [f32]) -> f32,
+    pub(crate) sum_sq_dev: fn(f32, &[f32]) -> f32,
+    #[allow(clippy::type_complexity)]
+    pub(crate) normalize: fn(&[f32], f32, f32, &[f32], &[f32], &mut [f32]),
+    /// Register tiled `sgemm` kernel, if the target has one
+    pub(crate) sgemm: Option<Microkernel>,
+    /// Whether `sbgemm` and `igemm` run on AMX tiles
+    #[cfg(all(feature = "amx", target_arch = "x86_64", target_os = "linux"))]
+    pub(crate) amx: bool,
+}
+
+/// The kernels for this CPU, probed on the first call. Portable only if the process started
+/// with `AML_DISABLE_SIMD` set to anything but empty or `0`.
+pub(crate) fn kernels() -> &'static Kernels {
+    static KERNELS: OnceLock<Kernels> = OnceLock::new();
+
+    KERNELS.get_or_init(|| {
+        let disabled = std::env::var("AML_DISABLE_SIMD").is_ok_and(|flag| parse_flag(&flag));
+        match disabled {
+            true => Kernels::scalar(),
+            false => Kernels::detect(),
+        }
+    })
+}
+
+/// An environment flag is on unless it is empty or `0`
+pub(crate) fn parse_flag(flag: &str) -> bool {
+    !matches!(flag.trim(), "" | "0")
+}
+
+/// Portable kernels only, for calls that switch SIMD off.
+pub(crate) fn scalar_kernels() -> &'static Kernels {
+    static SCALAR: OnceLock<Kernels> = OnceLock::new();
+
+    SCALAR.get_or_init(Kernels::scalar)
+}
+
+impl Kernels {
+    /// Portable code only.
+    pub(crate) fn scalar() -> Kernels {
+        Kernels {
+            sdot: blas1::sdot_scalar,
+            saxpy: blas1::saxpy_scalar,
+            sscal: blas1::sscal_scalar,
+            snrm2: blas1::snrm2_scalar,
+            sasum: blas1::sasum_scalar,
+            ddot: blas1::ddot_scalar,
+            daxpy: blas1::daxpy_scalar,
+            dsdot: blas1::dsdot_scalar,
+            dsaxpy: blas1::dsaxpy_scalar,
+            hdot: hgemm::hdot_scalar,
+            haxpy: hgemm::haxpy_scalar,
+            sbdot: sbgemm::sbdot_scalar,
+            idot: igemm::idot_scalar,
+            i4_block_dot: i4::block_dot_scalar,
+            h_to_s: convert::h_to_s_scalar,
+            s_to_h: convert::s_to_h_scalar,
+            bf_to_s: convert::bf_to_s_scalar,
+            s_to_bf: convert::s_to_bf_scalar,
+            quantize_i8: convert::quantize_i8_scalar,
+            dequantize_i8: convert::dequantize_i8_scalar,
+            binary: elementwise::binary_scalar,
+            binary_splat: elementwise::binary_splat_scalar,
+            fma: elementwise::fma_scalar,
+            reduce: reduce::reduce_scalar,
+            reduce_into: reduce::reduce_into_scalar,
+            exp_sum: softmax::exp_sum_scalar,
+            sum_sq_dev: norm::sum_sq_dev_scalar,
+            normalize: norm::normalize_scalar,
+            sgemm: None,
+            #[cfg(all(feature = "amx", target_arch = "x86_64", target_os = "linux"))]
+            amx: false,
+        }
+    }
+
+    /// The fastest kernel per operation that this CPU supports.
+    ///
+    /// The SIMD kernels are `unsafe` only because they need their target features; each is
+    /// wrapped here right after those features were confirmed, and checks the lengths of its
+    /// slices before any raw loads, as `Microkernel::run` does.
+    fn detect() -> Kernels {
+        #[allow(unused_mut)]
+        let mut kernels = Kernels::scalar();
+
+        #[cfg(target_arch = "x86_64")]
+        {
+            if is_x86_feature_detected!("avx") {
+                kernels.sdot = |x, y| unsafe { blas1::sdot_avx(x, y) };
+                kernels.saxpy = |alpha, x, y| unsafe { blas1::saxpy_avx(alpha, x, y) };
+                kernels.sscal = |alpha, x| unsafe { blas1::sscal_avx(alpha, x) };
+                kernels.snrm2 = |x| unsafe { blas1::snrm2_avx(x) };
+                kernels.sasum = |x| unsafe { blas1::sasum_avx(x) };
+                kernels.ddot = |x, y| unsafe { blas1::ddot_avx(x, y) };
+                kernels.daxpy = |alpha, x, y| unsafe { blas1::daxpy_avx(alpha, x, y) };
+                kernels.dsdot = |x, y| unsafe { blas1::dsdot_avx(x, y) };
+                kernels.dsaxpy = |alpha, x, y| unsafe { blas1::dsaxpy_avx(alpha, x, y) };
+                kernels.binary = |op, x, y, out| unsafe { elementwise::binary_avx(op, x, y, out) };
+                kernels.binary_splat =
+                    |op, x, y, out| unsafe { elementwise::binary_splat_avx(op, x, y, out) };
+                kernels.fma = |x, y, z, out| unsafe { elementwise::fma_avx(x, y, z, out) };
+                kernels.reduce = |op, x| unsafe { reduce::reduce_avx(op, x) };
+                kernels.reduce_into = |op, x, acc| unsafe { reduce::reduce_into_avx(op, x, acc) };
+                kernels.sum_sq_dev = |mean, x| unsafe { norm::sum_sq_dev_avx(mean, x) };
+                kernels.normalize = |x, mean, scale, gamma, beta, out| unsafe {
+                    norm::normalize_avx(x, mean, scale, gamma, beta, out)
+                };
+            }
+            if is_x86_feature_detected!("avx") && is_x86_feature_detected!("f16c") {
+                kernels.hdot = |x, y| unsafe { hgemm::hdot_f16c(x, y) };
+                kernels.haxpy = |alpha, x, y| unsafe { hgemm::haxpy_f16c(alpha, x, y) };
+                kernels.h_to_s = |src, dst| unsafe { convert::h_to_s_f16c(src, dst) };
+                kernels.s_to_h = |src, dst| unsafe { convert::s_to_h_f16c(src, dst) };
+            }
+            if is_x86_feature_detected!("avx2") && is_x86_feature_detected!("fma") {
+                kernels.sbdot = |x, y| unsafe { sbgemm::sbdot_avx2(x, y) };
+            }
+            if is_x86_feature_detected!("avx512bf16") && is_x86_feature_detected!("avx512f") {
+                kernels.sbdot = |x, y| unsafe { sbgemm::sbdot_avx512bf16(x, y) };
+            }
+            if is_x86_feature_detected!("avx2") {
+                kernels.exp_sum = |max, x| unsafe { softmax::exp_sum_avx2(max, x) };
+                kernels.idot = |x, y| unsafe { igemm::idot_avx2(x, y) };
+                kernels.bf_to_s = |src, dst| unsafe { convert::bf_to_s_avx2(src, dst) };
+                kernels.s_to_bf = |src, dst| unsafe { convert::s_to_bf_avx2(src, dst) };
+                kernels.quantize_i8 = |src, scale, zero, dst| unsafe {
+                    convert::quantize_i8_avx2(src, scale, zero, dst)
+                };
+                kernels.dequantize_i8 = |src, scale, zero, dst| unsafe {
+                    convert::dequantize_i8_avx2(src, scale, zero, dst)
+                };
+            }
+            if is_x86_feature_detected!("avx512vnni") && is_x86_feature_detected!("avx512f") {
+                kernels.idot = |x, y| unsafe { igemm::idot_vnni(x, y) };
+            }
+            if i4::has_avx2() {
+                kernels.i4_block_dot =
+                    |a, nibbles, zero| unsafe { i4::block_dot_avx2(a, nibbles, zero) };
+            }
+        }
+
+        #[cfg(target_arch = "aarch64")]
+        {
+            if std::arch::is_aarch64_feature_detected!("neon") {
+                kernels.sdot = |x, y| unsafe { blas1::sdot_neon(x, y) };
+                kernels.saxpy = |alpha, x, y| unsafe { blas1::saxpy_neon(alpha, x, y) };
+            }
+            if std::arch::is_aarch64_feature_detected!("sve") {
+                kernels.sdot = |x, y| unsafe { blas1::sdot_sve(x, y) };
+                kernels.saxpy = |alpha, x, y| unsafe { blas1::saxpy_sve(alpha, x, y) };
+            }
+        }
+
+        #[cfg(all(target_arch = "wasm32", target_feature = "simd128"))]
+        {
+            kernels.sdot = blas1::sdot_simd128;
+            kernels.saxpy = blas1::saxpy_simd128;
+        }
+
+        kernels.sgemm = Microkernel::detect();
+        #[cfg(all(feature = "amx", target_arch = "x86_64", target_os = "linux"))]
+        {
+            kernels.amx = amx::available();
+        }
+
+        kernels
+    }
+}
@@ -0,0 +1,164 @@
+//! NumPy style printing of tensors.
+//!
+//! Tensors of more than `SUMMARY_THRESHOLD` values print only `EDGE_ITEMS` rows and columns at
+//! each end of every axis, with `...` for the rest, so a 1024 x 1024 matrix takes seven short
+//! lines. `{:.2}` sets the digits after the point (4 by default) and `{:#}` prints every value.
+
+use crate::{
+    offset, AsTensorRef, CowTensor, Element, MappedTensor, Pod, SharedTensor, Tensor, TensorRef,
+};
+use std::fmt;
+
+const SUMMARY_THRESHOLD: usize = 1000;
+const EDGE_ITEMS: usize = 3;
+const DEFAULT_PRECISION: usize = 4;
+
+impl<T: Element> fmt::Display for Tensor<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(&self.as_tensor_ref(), f)
+    }
+}
+
+impl<T: Element> fmt::Display for SharedTensor<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(&self.as_tensor_ref(), f)
+    }
+}
+
+impl<T: Element> fmt::Display for CowTensor<'_, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(&self.as_tensor_ref(), f)
+    }
+}
+
+impl<T: Element + Pod> fmt::Display for MappedTensor<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(&self.as_tensor_ref(), f)
+    }
+}
+
+impl<T: Element> fmt::Display for TensorRef<'_, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let edge = match f.alternate() || self.shape.numel() <= SUMMARY_THRESHOLD {
+            true => None,
+            false => Some(EDGE_ITEMS),
+        };
+        let printer = Printer {
+            tensor: self,
+            edge,
+            precision: f.precision().unwrap_or(DEFAULT_PRECISION),
+        };
+
+        // every value printed is padded to the widest, so columns line up
+        let mut width = 0;
+        printer.visit(&mut vec![0; self.shape.len()], 0, &mut |value| {
+            width = width.max(value.len())
+        });
+        let mut out = String::new();
+        printer.write(&mut out, &mut vec![0; self.shape.len()], 0, width);
+        f.write_str(&out)
+    }
+}
+
+impl<T: Element> fmt::Debug for Tensor<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(&self.as_tensor_ref(), f)
+    }
+}
+
+impl<T: Element> fmt::Debug for SharedTensor<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(&self.as_tensor_ref(), f)
+    }
+}
+
+impl<T: Element> fmt::Debug for CowTensor<'_, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(&self.as_tensor_ref(), f)
+    }
+}
+
+impl<T: Element + Pod> fmt::Debug for MappedTensor<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(&self.as_tensor_ref(), f)
+    }
+}
+
+impl<T: Element> fmt::Debug for TensorRef<'_, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Tensor")
+            .field("shape", &self.shape.to_vec())
+            .field("layout", &self.layout)
+            .field("strides", &self.strides)
+            .field("values", &format_args!("\n{}", self))
+            .finish()
+    }
+}
+
+struct Printer<'t, 'a, T: Element> {
+    tensor: &'t TensorRef<'a, T>,
+    edge: Option<usize>,
+    precision: usize,
+}
+
+impl<T: Element> Printer<'_, '_, T> {
+    /// Indices of `axis` to print, `None` standing for the ones left out
+    fn shown(&self, axis: usize) -> Vec<Option<usize>> {
+        let n = self.tensor.shape[axis];
+        match self.edge {
+            Some(edge) if n > 2 * edge => (0..edge)
+                .map(Some)
+                .chain([None])
+                .chain((n - edge..n).map(Some))
+                .collect(),
+            _ => (0..n).map(Some).collect(),
+        }
+    }
+
+    fn value(&self, index: &[usize]) -> String {
+        let value = self.tensor.values[offset(index, &self.tensor.shape, &self.tensor.strides)];
+        format!("{:.*}", self.precision, value.to_f64())
+    }
+
+    /// Call `f` on every value that will be printed
+    fn visit(&self, index: &mut Vec<usize>, axis: usize, f: &mut impl FnMut(String)) {
+        if axis == index.len() {
+            return f(self.value(index));
+        }
+        for i in self.shown(axis).into_iter().flatten() {
+            index[axis] = i;
+            self.visit(index, axis + 1, f);
+        }
+    }
+
+    fn write(&self, out: &mut String, index: &mut Vec<usize>, axis: usize, width: usize) {
+        let rank = index.len();
+        if axis == rank {
+            out.push_str(&format!("{:>1$}", self.value(index), width));
+            return;
+        }
+
+        out.push('[');
+        for (k, i) in self.shown(axis).into_iter().enumerate() {
+            if k > 0 {
+                // one line per row, and a blank line more for each axis further out
+                match axis + 1 == rank {
+                    true => out.push_str(", "),
+                    false => {
+                        out.push(',');
+                        out.push_str(&"\n".repeat(rank - axis - 1));
+                        out.push_str(&" ".repeat(axis + 1));
+                    }
+                }
+            }
+            match i {
+                Some(i) => {
+                    index[axis] = i;
+                    self.write(out, index, axis + 1, width);
+                }
+                None => out.push_str("..."),
+            }
+        }
+        out.push(']');
+    }
+}
@@ -0,0 +1,167 @@
+//! Scalar types the dense `Tensor` can hold.
+
+use crate::dispatch::kernels;
+use crate::{blas1, hgemm, sbgemm};
+use half::{bf16, f16};
+use std::fmt::Debug;
+
+/// A dense tensor element: conversions to and from the f32/f64 accumulator types, plus a hook
+/// that routes to the type's SIMD kernels.
+///
+/// Implemented for `f16`, `bf16`, `f32` and `f64`. Quantized storage (`I4Tensor`, `I8Tensor`)
+/// carries per block or per tensor parameters and keeps its own types.
+pub trait Element: Copy + Default + PartialEq + Debug + Send + Sync + 'static {
+    const ZERO: Self;
+    const ONE: Self;
+
+    fn to_f32(self) -> f32;
+    fn from_f32(value: f32) -> Self;
+    fn to_f64(self) -> f64;
+    fn from_f64(value: f64) -> Self;
+
+    /// `x . y`, dispatched to the fastest kernel for the type and CPU. The scalar fallback sums
+    /// in f64. Lengths must match.
+    fn dot(x: &[Self], y: &[Self]) -> f64 {
+        x.iter().zip(y).map(|(x, y)| x.to_f64() * y.to_f64()).sum()
+    }
+
+    /// Widen `src` into `dst`, dispatched like `dot`. Lengths must match.
+    fn to_f32_slice(src: &[Self], dst: &mut [f32]) {
+        for (src_val, dst_val) in src.iter().zip(dst.iter_mut()) {
+            *dst_val = src_val.to_f32();
+        }
+    }
+
+    /// Round `src` into `dst`, dispatched like `dot`. Lengths must match.
+    fn from_f32_slice(src: &[f32], dst: &mut [Self]) {
+        for (src_val, dst_val) in src.iter().zip(dst.iter_mut()) {
+            *dst_val = Self::from_f32(*src_val);
+        }
+    }
+}
+
+impl Element for f16 {
+    const ZERO: f16 = f16::ZERO;
+    const ONE: f16 = f16::ONE;
+
+    fn to_f32(self) -> f32 {
+        f16::to_f32(self)
+    }
+    fn from_f32(value: f32) -> f16 {
+        f16::from_f32(value)
+    }
+    fn to_f64(self) -> f64 {
+        f16::to_f64(self)
+    }
+    fn from_f64(value: f64) -> f16 {
+        f16::from_f64(value)
+    }
+
+    fn dot(x: &[f16], y: &[f16]) -> f64 {
+        hgemm::hdot(x, y) as f64
+    }
+
+    fn to_f32_slice(src: &[f16], dst: &mut [f32]) {
+        (kernels().h_to_s)(src, dst)
+    }
+
+    fn from_f32_slice(src: &[f32], dst: &mut [f16]) {
+        (kernels().s_to_h)(src, dst)
+    }
+}
+
+impl Element for bf16 {
+    const ZERO: bf16 = bf16::ZERO;
+    const ONE: bf16 = bf16::ONE;
+
+    fn to_f32(self) -> f32 {
+        bf16::to_f32(self)
+    }
+    fn from_f32(value: f32) -> bf16 {
+        bf16::from_f32(value)
+    }
+    fn to_f64(self) -> f64 {
+        bf16::to_f64(self)
+    }
+    fn from_f64(value: f64) -> bf16 {
+        bf16::from_f64(value)
+    }
+
+    fn dot(x: &[bf16], y: &[bf16]) -> f64 {
+        sbgemm::sbdot(x, y) as f64
+    }
+
+    fn to_f32_slice(src: &[bf16], dst: &mut [f32]) {
+        (kernels().bf_to_s)(src, dst)
+    }
+
+    fn from_f32_slice(src: &[f32], dst: &mut [bf16]) {
+        (kernels().s_to_bf)(src, dst)
+    }
+}
+
+impl Element for f32 {
+    const ZERO: f32 = 0f32;
+    const ONE: f32 = 1f32;
+
+    fn to_f32(self) -> f32 {
+        self
+    }
+    fn from_f32(value: f32) -> f32 {
+        value
+    }
+    fn to_f64(self) -> f64 {
+        self as f64
+    }
+    fn from_f64(value: f64) -> f32 {
+        value as f32
+    }
+
+    fn dot(x: &[f32], y: &[f32]) -> f64 {
+        blas1::sdot(x, y) as f64
+    }
+
+    fn to_f32_slice(src: &[f32], dst: &mut [f32]) {
+        dst.copy_from_slice(src)
+    }
+
+    fn from_f32_slice(src: &[f32], dst: &mut [f32]) {
+        dst.copy_from_slice(src)
+    }
+}
+
+impl Element for f64 {
+    const ZERO: f64 = 0f64;
+    const ONE: f64 = 1f64;
+
+    fn to_f32(self) -> f32 {
+        self as f32
+    }
+    fn from_f32(value: f32) -> f64 {
+        value as f64
+    }
+    fn to_f64(self) -> f64 {
+        self
+    }
+    fn from_f64(value: f64) -> f64 {
+        value
+    }
+
+    fn dot(x: &[f64], y: &[f64]) -> f64 {
+        blas1::ddot(x, y)
+    }
+}
+
+/// Plain old data: every bit pattern of `size_of::<Self>()` bytes is a valid value, so a byte
+/// buffer can be read as values of this type in place, as `TensorRef::from_bytes` does.
+///
+/// # Safety
+///
+/// The type must have no padding, no invalid bit patterns and no pointers. Implemented for the
+/// float types `Element` covers.
+pub unsafe trait Pod: Copy + 'static {}
+
+unsafe impl Pod for f16 {}
+unsafe impl Pod for bf16 {}
+unsafe impl Pod for f32 {}
+unsafe impl Pod for f64 {}
@@ -0,0 +1,394 @@
+//! Elementwise arithmetic on f32 tensors: `add`, `sub`, `mul`, `div` and `fma` of two (or
+//! three) tensors, and the same with a scalar for the second operand.
+//!
+//! Operands broadcast NumPy style, as `Shape::broadcast`: a `[1, n]` or `[n]` bias adds to
+//! every row of an `[m, n]` matrix, and a `[m, 1]` column to every column. A stretched operand
+//! is never copied out to the full shape; its axes step by 0 instead, and a value repeated
+//! along a whole run of the result is passed to the kernel as one scalar.
+//!
+//! The result is a new tensor in the layout of `a`; operands stored otherwise are read in that
+//! order first. Each runs as one pass of AVX on x86_64 when the CPU has it, and of code the
+//! compiler vectorizes for the target's baseline SIMD everywhere else, split across threads
+//! once the tensors are large enough to be worth it.
+
+use crate::dispatch::kernels;
+use crate::{parallel, AmlError, AsTensorRef, Layout, Shape, Tensor, TensorRef};
+use std::borrow::Cow;
+
+#[cfg(target_arch = "x86_64")]
+use std::arch::x86_64::*;
+
+/// An arithmetic operation on two values
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Binary {
+    Add,
+    Sub,
+    Mul,
+    Div,
+}
+
+impl Binary {
+    fn apply(self, x: f32, y: f32) -> f32 {
+        match self {
+            Binary::Add => x + y,
+            Binary::Sub => x - y,
+            Binary::Mul => x * y,
+            Binary::Div => x / y,
+        }
+    }
+
+    #[cfg(target_arch = "x86_64")]
+    #[target_feature(enable = "avx")]
+    unsafe fn apply_avx(self, x: __m256, y: __m256) -> __m256 {
+        match self {
+            Binary::Add => _mm256_add_ps(x, y),
+            Binary::Sub => _mm256_sub_ps(x, y),
+            Binary::Mul => _mm256_mul_ps(x, y),
+            Binary::Div => _mm256_div_ps(x, y),
+        }
+    }
+}
+
+/// `a + b`, broadcast together
+pub fn add(a: &impl AsTensorRef<f32>, b: &impl AsTensorRef<f32>) -> Tensor<f32> {
+    try_add(a, b).unwrap_or_else(|e| panic!("{}", e))
+}
+
+/// Fallible version of `add`.
+pub fn try_add(
+    a: &impl AsTensorRef<f32>,
+    b: &impl AsTensorRef<f32>,
+) -> Result<Tensor<f32>, AmlError> {
+    binary(Binary::Add, a, b)
+}
+
+/// `a - b`, broadcast together
+pub fn sub(a: &impl AsTensorRef<f32>, b: &impl AsTensorRef<f32>) -> Tensor<f32> {
+    try_sub(a, b).unwrap_or_else(|e| panic!("{}", e))
+}
+
+/// Fallible version of `sub`.
+pub fn try_sub(
+    a: &impl AsTensorRef<f32>,
+    b: &impl AsTensorRef<f32>,
+) -> Result<Tensor<f32>, AmlError> {
+    binary(Binary::Sub, a, b)
+}
+
+/// `a * b`, value by value, broadcast together
+pub fn mul(a: &impl AsTensorRef<f32>, b: &impl AsTensorRef<f32>) -> Tensor<f32> {
+    try_mul(a, b).unwrap_or_else(|e| panic!("{}", e))
+}
+
+/// Fallible version of `mul`.
+pub fn try_mul(
+    a: &impl AsTensorRef<f32>,
+    b: &impl AsTensorRef<f32>,
+) -> Result<Tensor<f32>, AmlError> {
+    binary(Binary::Mul, a, b)
+}
+
+/// `a / b`, value by value, broadcast together
+pub fn div(a: &impl AsTensorRef<f32>, b: &impl AsTensorRef<f32>) -> Tensor<f32> {
+    try_div(a, b).unwrap_or_else(|e| panic!("{}", e))
+}
+
+/// Fallible version of `div`.
+pub fn try_div(
+    a: &impl AsTensorRef<f32>,
+    b: &impl AsTensorRef<f32>,
+) -> Result<Tensor<f32>, AmlError> {
+    binary(Binary::Div, a, b)
+}
+
+/// `a * b + c`, value by value and all three broadcast together, in one pass instead of two.
+///
+/// The product is rounded before `c` is added, as `a * b + c` would be, so the result is the
+/// same on every CPU.
+pub fn fma(
+    a: &impl AsTensorRef<f32>,
+    b: &impl AsTensorRef<f32>,
+    c: &impl AsTensorRef<f32>,
+) -> Tensor<f32> {
+    try_fma(a, b, c).unwrap_or_else(|e| panic!("{}", e))
+}
+
+/// Fallible version of `fma`.
+pub fn try_fma(
+    a: &impl AsTensorRef<f32>,
+    b: &impl AsTensorRef<f32>,
+    c: &impl AsTensorRef<f32>,
+) -> Result<Tensor<f32>, AmlError> {
+    let (a, b, c) = (a.as_tensor_ref(), b.as_tensor_ref(), c.as_tensor_ref());
+    let shape = a.shape.try_broadcast(&b.shape)?.try_broadcast(&c.shape)?;
+    let layout = a.layout;
+    let operands = Broadcast::new(&shape, layout, [a, b, c]);
+
+    let fma = kernels().fma;
+    let values = map_chunks(shape.iter().product(), |first, out| {
+        let mut splats = [Vec::new(), Vec::new(), Vec::new()];
+        operands.for_each_run(first, out, |at, out| {
+            let ([x, y, z], len) = (&mut splats, out.len());
+            let x = operands.run(0, at, len).values(x, len);
+            let y = operands.run(1, at, len).values(y, len);
+            let z = operands.run(2, at, len).values(z, len);
+            fma(x, y, z, out)
+        })
+    });
+    Ok(Tensor::new(values, shape).with_layout(layout))
+}
+
+/// `a + value`
+pub fn add_scalar(a: &impl AsTensorRef<f32>, value: f32) -> Tensor<f32> {
+    binary_splat(Binary::Add, a, value)
+}
+
+/// `a - value`
+pub fn sub_scalar(a: &impl AsTensorRef<f32>, value: f32) -> Tensor<f32> {
+    binary_splat(Binary::Sub, a, value)
+}
+
+/// `a * value`
+pub fn mul_scalar(a: &impl AsTensorRef<f32>, value: f32) -> Tensor<f32> {
+    binary_splat(Binary::Mul, a, value)
+}
+
+/// `a / value`, divided rather than multiplied by `1 / value`, so exactly as `div` would
+pub fn div_scalar(a: &impl AsTensorRef<f32>, value: f32) -> Tensor<f32> {
+    binary_splat(Binary::Div, a, value)
+}
+
+fn binary(
+    op: Binary,
+    a: &impl AsTensorRef<f32>,
+    b: &impl AsTensorRef<f32>,
+) -> Result<Tensor<f32>, AmlError> {
+    let (a, b) = (a.as_tensor_ref(), b.as_tensor_ref());
+    let shape = a.shape.try_broadcast(&b.shape)?;
+    let layout = a.layout;
+    let operands = Broadcast::new(&shape, layout, [a, b]);
+
+    let kernels = kernels();
+    let values = map_chunks(shape.iter().product(), |first, out| {
+        let mut splats = [Vec::new(), Vec::new()];
+        operands.for_each_run(first, out, |at, out| {
+            let ([x_splat, y_splat], len) = (&mut splats, out.len());
+            match (operands.run(0, at, len), operands.run(1, at, len)) {
+                (Run::Values(x), Run::Splat(y)) => (kernels.binary_splat)(op, x, y, out),
+                (x, y) => (kernels.binary)(op, x.values(x_splat, len), y.values(y_splat, len), out),
+            }
+        })
+    });
+    Ok(Tensor::new(values, shape).with_layout(layout))
+}
+
+fn binary_splat(op: Binary, a: &impl AsTensorRef<f32>, value: f32) -> Tensor<f32> {
+    let a = a.as_tensor_ref();
+    let x = a.dense();
+
+    let binary_splat = kernels().binary_splat;
+    let values = map_chunks(a.shape.iter().product(), |first, out| {
+        binary_splat(op, &x[first..first + out.len()], value, out)
+    });
+    Tensor::new(values, a.shape.clone()).with_layout(a.layout)
+}
+
+/// Operands broadcast to one shape, read in the order the result is stored
+struct Broadcast<'a, const N: usize> {
+    /// Lengths of the result's axes, slowest first. Axes of length 1 are dropped, and
+    /// neighbours that every operand steps through as one are merged, so operands of the
+    /// result's own shape are one long run.
+    dims: Vec<usize>,
+    /// Each operand's values back to back in the result's layout
+    values: [Cow<'a, [f32]>; N],
+    /// Steps through each operand's `values` along `dims`, 0 where it is stretched
+    strides: [Vec<usize>; N],
+}
+
+/// An operand's values along a run of the result
+enum Run<'a> {
+    Values(&'a [f32]),
+    /// One value, repeated along the whole run
+    Splat(f32),
+}
+
+impl<'a, const N: usize> Broadcast<'a, N> {
+    /// `operands` read as `shape`, which they broadcast to, stored in `layout`
+    fn new(shape: &Shape, layout: Layout, operands: [TensorRef<'a, f32>; N]) -> Broadcast<'a, N> {
+        let rank = shape.rank();
+        // the result's axes as stored, slowest first
+        let axes: Vec<usize> = match layout {
+            Layout::RowMajor => (0..rank).collect(),
+            Layout::ColMajor => (0..rank).rev().collect(),
+        };
+        // each operand's dense strides along the result's axes, 0 along those it lacks
+        let dense = operands.each_ref().map(|operand| {
+            let offset = rank - operand.shape.rank();
+            let mut strides = vec![0; rank];
+            let mut stride = 1;
+            for &axis in axes.iter().rev().filter(|axis| **axis >= offset) {
+                let len = operand.shape[axis - offset];
+                if len != 1 {
+                    strides[axis] = stride;
+                    stride *= len;
+                }
+            }
+            strides
+        });
+
+        let mut dims: Vec<usize> = Vec::new();
+        let mut strides: [Vec<usize>; N] = std::array::from_fn(|_| Vec::new());
+        for &axis in axes.iter().filter(|axis| shape[**axis] != 1) {
+            let len = shape[axis];
+            let merge = !dims.is_empty()
+                && strides
+                    .iter()
+                    .zip(&dense)
+                    .all(|(strides, dense)| strides.last() == Some(&(dense[axis] * len)));
+            match merge {
+                true => {
+                    *dims.last_mut().expect("merged into an axis") *= len;
+                    for (strides, dense) in strides.iter_mut().zip(&dense) {
+                        *strides.last_mut().expect("one per axis") = dense[axis];
+                    }
+                }
+                false => {
+                    dims.push(len);
+                    for (strides, dense) in strides.iter_mut().zip(&dense) {
+                        strides.push(dense[axis]);
+                    }
+                }
+            }
+        }
+        // a single value, of every operand
+        if dims.is_empty() {
+            dims.push(1);
+            strides.iter_mut().for_each(|strides| strides.push(0));
+        }
+
+        Broadcast {
+            dims,
+            values: operands.map(|operand| operand.dense_in(layout)),
+            strides,
+        }
+    }
+
+    /// Run `f(at, run)` over the pieces of `out`, the result from position `first`, that lie
+    /// in one run of the result's last axis each
+    fn for_each_run(&self, first: usize, out: &mut [f32], mut f: impl FnMut(usize, &mut [f32])) {
+        let inner = *self.dims.last().expect("at least one axis");
+        let mut done = 0;
+        while done < out.len() {
+            let at = first + done;
+            let len = (inner - at % inner).min(out.len() - done);
+            f(at, &mut out[done..done + len]);
+            done += len;
+        }
+    }
+
+    /// `len` values of `operand` from position `at` of the result, all in one run
+    fn run(&self, operand: usize, at: usize, len: usize) -> Run<'_> {
+        let (strides, values) = (&self.strides[operand], &self.values[operand]);
+        let last = self.dims.len() - 1;
+        let (mut run, col) = (at / self.dims[last], at % self.dims[last]);
+        let mut offset = 0;
+        for axis in (0..last).rev() {
+            offset += run % self.dims[axis] * strides[axis];
+            run /= self.dims[axis];
+        }
+        match strides[last] {
+            0 => Run::Splat(values[offset]),
+            _ => Run::Values(&values[offset + col..offset + col + len]),
+        }
+    }
+}
+
+impl<'a> Run<'a> {
+    /// The run as a slice, a splatted value written out to `buffer` first
+    fn values(self, buffer: &'a mut Vec<f32>, len: usize) -> &'a [f32] {
+        match self {
+            Run::Values(values) => values,
+            Run::Splat(value) => {
+                buffer.clear();
+                buffer.resize(len, value);
+                buffer
+            }
+        }
+    }
+}
+
+/// `len` new values, written by `f(first, out)` over contiguous chunks of them, one per thread
+/// once there are enough to split
+fn map_chunks(len: usize, f: impl Fn(usize, &mut [f32]) + Sync) -> Vec<f32> {
+    let mut values = vec![0f32; len];
+    parallel::for_each_row_chunk(&mut values, 1, 1, f);
+    values
+}
+
+pub(crate) fn binary_scalar(op: Binary, x: &[f32], y: &[f32], out: &mut [f32]) {
+    for ((out, x), y) in out.iter_mut().zip(x).zip(y) {
+        *out = op.apply(*x, *y);
+    }
+}
+
+pub(crate) fn binary_splat_scalar(op: Binary, x: &[f32], y: f32, out: &mut [f32]) {
+    for (out, x) in out.iter_mut().zip(x) {
+        *out = op.apply(*x, y);
+    }
+}
+
+pub(crate) fn fma_scalar(x: &[f32], y: &[f32], z: &[f32], out: &mut [f32]) {
+    for (((out, x), y), z) in out.iter_mut().zip(x).zip(y).zip(z) {
+        *out = x * y + z;
+    }
+}
+
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "avx")]
+pub(crate) unsafe fn binary_avx(op: Binary, x: &[f32], y: &[f32], out: &mut [f32]) {
+    assert!(x.len() == out.len() && y.len() == out.len());
+    let n8 = out.len() / 8 * 8;
+
+    for i in (0..n8).step_by(8) {
+        let x8 = _mm256_loadu_ps(x.as_ptr().add(i));
+        let y8 = _mm256_loadu_ps(y.as_ptr().add(i));
+        _mm256_storeu_ps(out.as_mut_ptr().add(i), op.apply_avx(x8, y8));
+    }
+
+    binary_scalar(op, &x[n8..], &y[n8..], &mut out[n8..]);
+}
+
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "avx")]
+pub(crate) unsafe fn binary_splat_avx(op: Binary, x: &[f32], y: f32, out: &mut [f32]) {
+    assert_eq!(x.len(), out.len());
+    let n8 = out.len() / 8 * 8;
+    let y8 = _mm256_set1_ps(y);
+
+    for i in (0..n8).step_by(8) {
+        let x8 = _mm256_loadu_ps(x.as_ptr().add(i));
+        _mm256_storeu_ps(out.as_mut_ptr().add(i), op.apply_avx(x8, y8));
+    }
+
+    binary_splat_scalar(op, &x[n8..], y, &mut out[n8..]);
+}
+
+/// A multiply then an add, not `_mm256_fmadd_ps`, to round as `fma_scalar` does.
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "avx")]
+pub(crate) unsafe fn fma_avx(x: &[f32], y: &[f32], z: &[f32], out: &mut [f32]) {
+    assert!(x.len() == out.len() && y.len() == out.len() && z.len() == out.len());
+    let n8 = out.len() / 8 * 8;
+
+    for i in (0..n8).step_by(8) {
+        let x8 = _mm256_loadu_ps(x.as_ptr().add(i));
+        let y8 = _mm256_loadu_ps(y.as_ptr().add(i));
+        let z8 = _mm256_loadu_ps(z.as_ptr().add(i));
+        _mm256_storeu_ps(
+            out.as_mut_ptr().add(i),
+            _mm256_add_ps(_mm256_mul_ps(x8, y8), z8),
+        );
+    }
+
+    fma_scalar(&x[n8..], &y[n8..], &z[n8..], &mut out[n8..]);
+}
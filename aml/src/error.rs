@@ -0,0 +1,248 @@
+use crate::io::DType;
+use crate::Device;
+use std::fmt;
+use std::ops::Range;
+
+/// Error returned by the fallible `try_*` entry points instead of panicking.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AmlError {
+    /// The number of values does not match the product of the shape.
+    SizeMismatch { expected: usize, found: usize },
+    /// I4 tensors need one scale per block and one zero nibble per scale.
+    ScalesZerosMismatch { scales: usize, zeros: usize },
+    /// The values of an I4 tensor cannot be split into equally sized blocks.
+    UnevenBlocks { values: usize, blocks: usize },
+    /// An operand has the wrong number of dimensions.
+    RankMismatch {
+        operand: &'static str,
+        expected: usize,
+        found: usize,
+    },
+    /// A matrix that must be square is not.
+    NotSquare { shape: Vec<usize> },
+    /// The contracted dimensions of the two operands differ.
+    InnerDimMismatch { a: usize, b: usize },
+    /// The batched operands do not all hold the same number of matrices.
+    BatchSizeMismatch { a: usize, b: usize, c: usize },
+    /// The output tensor does not have the shape of the result.
+    OutputShapeMismatch {
+        expected: Vec<usize>,
+        found: Vec<usize>,
+    },
+    /// GEMM block sizes must all be nonzero.
+    ZeroBlockSize { mc: usize, kc: usize, nc: usize },
+    /// A byte buffer is not a packed `b` written by `PackedB::to_bytes`.
+    InvalidPackedB { reason: &'static str },
+    /// The kernel only reads or writes this operand row-major.
+    UnsupportedLayout { operand: &'static str },
+    /// A leading dimension is shorter than the rows (columns, if column-major) it separates.
+    InvalidLeadingDim { ld: usize, min: usize },
+    /// The operation needs the values of a tensor back to back, without gaps between them.
+    NotContiguous {
+        shape: Vec<usize>,
+        strides: Vec<usize>,
+    },
+    /// Strides would read two indices from one value, or do not match the rank of the shape.
+    InvalidStrides {
+        shape: Vec<usize>,
+        strides: Vec<usize>,
+    },
+    /// The kernel writes this operand in place, so its rows (columns, if column-major) must be
+    /// contiguous.
+    UnsupportedStrides {
+        operand: &'static str,
+        strides: Vec<usize>,
+    },
+    /// An axis to squeeze is missing or longer than 1, one to insert is past the rank, or one
+    /// to reduce is missing (or empty, for `argmax_axis`).
+    InvalidAxis { axis: usize, shape: Vec<usize> },
+    /// A byte buffer does not start on a multiple of the alignment of the values it holds.
+    MisalignedBytes { align: usize },
+    /// An alignment is not a power of two at least as large as the values' own.
+    InvalidAlignment { align: usize },
+    /// Two shapes differ along an axis where neither has length 1.
+    BroadcastMismatch { a: Vec<usize>, b: Vec<usize> },
+    /// An index has the wrong rank for the tensor, or is past the end of an axis.
+    IndexOutOfBounds {
+        index: Vec<usize>,
+        shape: Vec<usize>,
+    },
+    /// Stored values are not of the type asked for.
+    DTypeMismatch { expected: DType, found: DType },
+    /// A view asks for rows or columns outside the matrix it borrows from.
+    ViewOutOfBounds {
+        rows: Range<usize>,
+        cols: Range<usize>,
+        shape: Vec<usize>,
+    },
+    /// A `CancelToken` stopped the call after the first `rows` rows of the output were written.
+    Cancelled { rows: usize },
+    /// The values of a shape, or the bytes they take up, are too many to count in a `usize`.
+    ShapeOverflow { shape: Vec<usize> },
+    /// The allocator could not provide a buffer of this many bytes on this alignment.
+    AllocationFailed { bytes: usize, align: usize },
+    /// Rows of a packed I4 matrix do not start on a block, or on a byte of `nibbles` and
+    /// `zeros`, so they cannot be borrowed on their own.
+    UnalignedRows { n: usize, block_size: usize },
+    /// Rows can only be handed out in chunks of at least one.
+    ZeroChunkSize,
+    /// An operand is on another device than the first, and would have to be copied.
+    DeviceMismatch {
+        operand: &'static str,
+        expected: Device,
+        found: Device,
+    },
+    /// A device's backend was not compiled in, or its driver or hardware is missing.
+    DeviceUnavailable { device: Device, reason: String },
+    /// A device's driver refused a transfer or a kernel, e.g. for want of memory.
+    DeviceFailed { device: Device, reason: String },
+    /// The operation only runs on some devices.
+    UnsupportedOnDevice {
+        operation: &'static str,
+        device: Device,
+    },
+}
+
+impl fmt::Display for AmlError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AmlError::SizeMismatch { expected, found } => write!(
+                f,
+                "Shape holds {} values but {} were provided.",
+                expected, found
+            ),
+            AmlError::ScalesZerosMismatch { scales, zeros } => write!(
+                f,
+                "{} scales need {} packed zero bytes, found {}.",
+                scales,
+                scales.div_ceil(2),
+                zeros
+            ),
+            AmlError::UnevenBlocks { values, blocks } => write!(
+                f,
+                "{} values cannot be split evenly into {} blocks.",
+                values, blocks
+            ),
+            AmlError::RankMismatch {
+                operand,
+                expected,
+                found,
+            } => write!(
+                f,
+                "`{}` must have {} dimensions. Found {}.",
+                operand, expected, found
+            ),
+            AmlError::NotSquare { shape } => {
+                write!(f, "Expected a square matrix, found {:?}.", shape)
+            }
+            AmlError::InnerDimMismatch { a, b } => {
+                write!(f, "Inner dimensions {}, {} do not match", a, b)
+            }
+            AmlError::BatchSizeMismatch { a, b, c } => write!(
+                f,
+                "Batch sizes of `a`, `b` and `c` differ: {}, {}, {}.",
+                a, b, c
+            ),
+            AmlError::OutputShapeMismatch { expected, found } => write!(
+                f,
+                "`c` has the wrong shape. Expected {:?}, found {:?}.",
+                expected, found
+            ),
+            AmlError::ZeroBlockSize { mc, kc, nc } => write!(
+                f,
+                "Block sizes must be nonzero, found mc = {}, kc = {}, nc = {}.",
+                mc, kc, nc
+            ),
+            AmlError::InvalidPackedB { reason } => {
+                write!(f, "Not a valid packed `b` buffer: {}.", reason)
+            }
+            AmlError::UnsupportedLayout { operand } => {
+                write!(f, "`{}` must be row-major for this kernel.", operand)
+            }
+            AmlError::InvalidLeadingDim { ld, min } => write!(
+                f,
+                "Leading dimension {} is shorter than the {} values it must skip.",
+                ld, min
+            ),
+            AmlError::NotContiguous { shape, strides } => write!(
+                f,
+                "Shape {:?} with strides {:?} is not contiguous.",
+                shape, strides
+            ),
+            AmlError::InvalidStrides { shape, strides } => write!(
+                f,
+                "Strides {:?} do not give each index of shape {:?} its own value.",
+                strides, shape
+            ),
+            AmlError::UnsupportedStrides { operand, strides } => write!(
+                f,
+                "`{}` with strides {:?} cannot be written in place by this kernel.",
+                operand, strides
+            ),
+            AmlError::InvalidAxis { axis, shape } => {
+                write!(f, "Axis {} is not valid for shape {:?}.", axis, shape)
+            }
+            AmlError::MisalignedBytes { align } => {
+                write!(
+                    f,
+                    "Bytes must start on a multiple of {} to hold these values.",
+                    align
+                )
+            }
+            AmlError::InvalidAlignment { align } => {
+                write!(
+                    f,
+                    "Alignment {} is not a power of two of at least 4.",
+                    align
+                )
+            }
+            AmlError::BroadcastMismatch { a, b } => {
+                write!(f, "Shapes {:?} and {:?} do not broadcast together.", a, b)
+            }
+            AmlError::IndexOutOfBounds { index, shape } => {
+                write!(f, "Index {:?} is not inside shape {:?}.", index, shape)
+            }
+            AmlError::DTypeMismatch { expected, found } => {
+                write!(f, "Expected {:?} values, found {:?}.", expected, found)
+            }
+            AmlError::ViewOutOfBounds { rows, cols, shape } => write!(
+                f,
+                "Rows {:?} and columns {:?} are not inside shape {:?}.",
+                rows, cols, shape
+            ),
+            AmlError::Cancelled { rows } => write!(f, "Cancelled after {} rows.", rows),
+            AmlError::ShapeOverflow { shape } => {
+                write!(f, "Shape {:?} holds more values than fit in memory.", shape)
+            }
+            AmlError::AllocationFailed { bytes, align } => {
+                write!(f, "Cannot allocate {} bytes aligned to {}.", bytes, align)
+            }
+            AmlError::UnalignedRows { n, block_size } => write!(
+                f,
+                "Rows of {} values in blocks of {} do not start on a byte boundary.",
+                n, block_size
+            ),
+            AmlError::ZeroChunkSize => write!(f, "Chunks must hold at least one row."),
+            AmlError::DeviceMismatch {
+                operand,
+                expected,
+                found,
+            } => write!(
+                f,
+                "`{}` is on {:?} but `a` is on {:?}; copy it with `to_device` first.",
+                operand, found, expected
+            ),
+            AmlError::DeviceUnavailable { device, reason } => {
+                write!(f, "{:?} is not available: {}.", device, reason)
+            }
+            AmlError::DeviceFailed { device, reason } => {
+                write!(f, "{:?} failed: {}.", device, reason)
+            }
+            AmlError::UnsupportedOnDevice { operation, device } => {
+                write!(f, "{} is not supported on {:?}.", operation, device)
+            }
+        }
+    }
+}
+
+impl std::error::Error for AmlError {}
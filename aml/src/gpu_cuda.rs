@@ -0,0 +1,174 @@
+//! `Device::Cuda`: buffers and GEMMs on NVIDIA GPUs through cuBLAS, behind the `gpu-cuda`
+//! feature.
+//!
+//! The driver and cuBLAS libraries are loaded when a tensor first goes to a GPU, not linked,
+//! so a build with the feature still runs where CUDA is not installed, and reports the GPUs as
+//! unavailable. Each GPU gets one context, stream and cuBLAS handle, kept for the life of the
+//! process; all work on a GPU is queued on its stream, and `download` waits for it.
+
+use crate::{AmlError, Device, GemmParams};
+use cudarc::cublas::sys::cublasOperation_t;
+use cudarc::cublas::{CudaBlas, Gemm, GemmConfig};
+use cudarc::driver::{CudaContext, CudaSlice, CudaStream};
+use std::ffi::c_int;
+use std::sync::{Arc, Mutex};
+
+/// A buffer of f32 values on one GPU
+pub(crate) struct Buffer {
+    context: Arc<Context>,
+    slice: CudaSlice<f32>,
+    len: usize,
+}
+
+/// One GPU's context, the stream all its work goes on, and a cuBLAS handle bound to it
+struct Context {
+    ordinal: usize,
+    stream: Arc<CudaStream>,
+    blas: CudaBlas,
+    _context: Arc<CudaContext>,
+}
+
+impl Context {
+    fn new(ordinal: usize) -> Result<Context, AmlError> {
+        let unavailable = |reason: String| AmlError::DeviceUnavailable {
+            device: Device::Cuda(ordinal),
+            reason,
+        };
+        // cudarc panics on the first call into a library it cannot load
+        let loaded = unsafe {
+            cudarc::driver::sys::is_culib_present() && cudarc::cublas::sys::is_culib_present()
+        };
+        if !loaded {
+            return Err(unavailable(
+                "the CUDA driver or cuBLAS library was not found".to_string(),
+            ));
+        }
+        let found = CudaContext::device_count().map_err(|e| unavailable(e.to_string()))?;
+        if ordinal >= found as usize {
+            return Err(unavailable(format!("CUDA found {} GPUs", found)));
+        }
+        let context = CudaContext::new(ordinal).map_err(|e| unavailable(e.to_string()))?;
+        let stream = context.default_stream();
+        let blas = CudaBlas::new(stream.clone()).map_err(|e| unavailable(e.to_string()))?;
+        Ok(Context {
+            ordinal,
+            stream,
+            blas,
+            _context: context,
+        })
+    }
+
+    fn failed(&self, reason: impl ToString) -> AmlError {
+        AmlError::DeviceFailed {
+            device: Device::Cuda(self.ordinal),
+            reason: reason.to_string(),
+        }
+    }
+}
+
+/// The context of GPU `ordinal`, made on first use
+fn context(ordinal: usize) -> Result<Arc<Context>, AmlError> {
+    static CONTEXTS: Mutex<Vec<Arc<Context>>> = Mutex::new(Vec::new());
+
+    let mut contexts = CONTEXTS.lock().unwrap_or_else(|e| e.into_inner());
+    if let Some(context) = contexts.iter().find(|c| c.ordinal == ordinal) {
+        return Ok(context.clone());
+    }
+    let context = Arc::new(Context::new(ordinal)?);
+    contexts.push(context.clone());
+    Ok(context)
+}
+
+pub(crate) fn is_available(ordinal: usize) -> bool {
+    context(ordinal).is_ok()
+}
+
+impl Buffer {
+    pub(crate) fn ordinal(&self) -> usize {
+        self.context.ordinal
+    }
+}
+
+/// `values` copied into a new buffer on GPU `ordinal`
+pub(crate) fn upload(ordinal: usize, values: &[f32]) -> Result<Buffer, AmlError> {
+    let context = context(ordinal)?;
+    // empty allocations are invalid, so an empty tensor still takes one value
+    let slice = match values.is_empty() {
+        true => context.stream.clone_htod(&[0.0f32]),
+        false => context.stream.clone_htod(values),
+    }
+    .map_err(|e| context.failed(e))?;
+    Ok(Buffer {
+        context,
+        slice,
+        len: values.len(),
+    })
+}
+
+/// The values of `buffer`, once the work queued on it is done
+pub(crate) fn download(buffer: &Buffer) -> Result<Vec<f32>, AmlError> {
+    let context = &buffer.context;
+    let mut values = context
+        .stream
+        .clone_dtoh(&buffer.slice)
+        .map_err(|e| context.failed(e))?;
+    context
+        .stream
+        .synchronize()
+        .map_err(|e| context.failed(e))?;
+    values.truncate(buffer.len);
+    Ok(values)
+}
+
+/// `c = alpha * op(a) @ op(b) + beta * c` for row-major `a`, `b` and `c`, whose shapes were
+/// checked to make `c` `m` by `n` with `k` products per value
+pub(crate) fn sgemm(
+    a: &Buffer,
+    a_transpose: bool,
+    b: &Buffer,
+    b_transpose: bool,
+    (m, n, k): (usize, usize, usize),
+    params: GemmParams,
+    c: &mut Buffer,
+) -> Result<(), AmlError> {
+    let context = c.context.clone();
+    if m == 0 || n == 0 {
+        return Ok(());
+    }
+    let (Ok(m), Ok(n), Ok(k)) = (c_int::try_from(m), c_int::try_from(n), c_int::try_from(k)) else {
+        return Err(context.failed(format!(
+            "a {} by {} product over {} values is too large for cuBLAS",
+            m, n, k
+        )));
+    };
+    let operation = |transpose: bool| match transpose {
+        true => cublasOperation_t::CUBLAS_OP_T,
+        false => cublasOperation_t::CUBLAS_OP_N,
+    };
+    // cuBLAS is column-major, where row-major `c` reads as its transpose, so it computes
+    // `c^T = op(b)^T @ op(a)^T` with `b` and `a` swapped; leading dimensions are the row
+    // lengths as stored, at least 1 as cuBLAS requires even when `k` is 0
+    let config = GemmConfig {
+        transa: operation(b_transpose),
+        transb: operation(a_transpose),
+        m: n,
+        n: m,
+        k,
+        alpha: params.alpha,
+        lda: match b_transpose {
+            true => k,
+            false => n,
+        }
+        .max(1),
+        ldb: match a_transpose {
+            true => m,
+            false => k,
+        }
+        .max(1),
+        beta: params.beta,
+        ldc: n,
+    };
+    // the shapes were checked against the buffers' lengths, so cuBLAS stays inside them
+    unsafe { context.blas.gemm(config, &b.slice, &a.slice, &mut c.slice) }
+        .map_err(|e| context.failed(e))
+}
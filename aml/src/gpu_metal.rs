@@ -0,0 +1,198 @@
+//! `Device::Metal`: buffers and GEMMs on the GPUs of a Mac through Metal compute, behind the
+//! `gpu-metal` feature (macOS only).
+//!
+//! Each GPU gets one command queue, made the first time a tensor goes to it and kept for the
+//! life of the process, with the GEMM pipeline compiled once on it from `shaders/sgemm.metal`,
+//! the same 16 by 16 tiling as the wgpu shader. Buffers use shared storage, which on Apple
+//! Silicon is the memory the CPU kernels use too, so transfers are plain copies; each GEMM
+//! waits for its command buffer, so a buffer's contents are always current when read.
+
+use crate::{AmlError, Device, GemmParams};
+use metal::objc::rc::autoreleasepool;
+use metal::{MTLCommandBufferStatus, MTLResourceOptions, MTLSize};
+use std::sync::{Arc, Mutex};
+
+/// Rows and columns of `c` per threadgroup, as in the shader
+const TILE: usize = 16;
+
+/// A buffer of f32 values on one GPU
+pub(crate) struct Buffer {
+    context: Arc<Context>,
+    buffer: metal::Buffer,
+    len: usize,
+}
+
+/// One GPU's device and command queue, and the GEMM pipeline compiled for it
+struct Context {
+    ordinal: usize,
+    device: metal::Device,
+    queue: metal::CommandQueue,
+    pipeline: metal::ComputePipelineState,
+}
+
+impl Context {
+    fn new(ordinal: usize) -> Result<Context, AmlError> {
+        let unavailable = |reason: String| AmlError::DeviceUnavailable {
+            device: Device::Metal(ordinal),
+            reason,
+        };
+        autoreleasepool(|| {
+            let devices = metal::Device::all();
+            let found = devices.len();
+            let device = devices
+                .into_iter()
+                .nth(ordinal)
+                .ok_or_else(|| unavailable(format!("Metal found {} GPUs", found)))?;
+            let library = device
+                .new_library_with_source(
+                    include_str!("shaders/sgemm.metal"),
+                    &metal::CompileOptions::new(),
+                )
+                .map_err(unavailable)?;
+            let function = library.get_function("sgemm", None).map_err(unavailable)?;
+            let pipeline = device
+                .new_compute_pipeline_state_with_function(&function)
+                .map_err(unavailable)?;
+            if (pipeline.max_total_threads_per_threadgroup() as usize) < TILE * TILE {
+                return Err(unavailable(format!(
+                    "the GEMM pipeline runs at most {} threads per threadgroup",
+                    pipeline.max_total_threads_per_threadgroup()
+                )));
+            }
+            let queue = device.new_command_queue();
+            Ok(Context {
+                ordinal,
+                device,
+                queue,
+                pipeline,
+            })
+        })
+    }
+
+    fn failed(&self, reason: String) -> AmlError {
+        AmlError::DeviceFailed {
+            device: Device::Metal(self.ordinal),
+            reason,
+        }
+    }
+}
+
+/// The context of GPU `ordinal`, made on first use
+fn context(ordinal: usize) -> Result<Arc<Context>, AmlError> {
+    static CONTEXTS: Mutex<Vec<Arc<Context>>> = Mutex::new(Vec::new());
+
+    let mut contexts = CONTEXTS.lock().unwrap_or_else(|e| e.into_inner());
+    if let Some(context) = contexts.iter().find(|c| c.ordinal == ordinal) {
+        return Ok(context.clone());
+    }
+    let context = Arc::new(Context::new(ordinal)?);
+    contexts.push(context.clone());
+    Ok(context)
+}
+
+pub(crate) fn is_available(ordinal: usize) -> bool {
+    context(ordinal).is_ok()
+}
+
+impl Buffer {
+    pub(crate) fn ordinal(&self) -> usize {
+        self.context.ordinal
+    }
+}
+
+/// `values` copied into a new buffer on GPU `ordinal`
+pub(crate) fn upload(ordinal: usize, values: &[f32]) -> Result<Buffer, AmlError> {
+    let context = context(ordinal)?;
+    let len = values.len();
+    let bytes = size_of_val(values);
+    let max = context.device.max_buffer_length() as usize;
+    if bytes > max {
+        return Err(context.failed(format!(
+            "{} bytes do not fit in one buffer, which holds at most {}",
+            bytes, max
+        )));
+    }
+    // empty buffers are invalid, so an empty tensor still takes one value
+    let padded = [0.0f32];
+    let values = match values.is_empty() {
+        true => &padded[..],
+        false => values,
+    };
+    let buffer = context.device.new_buffer_with_data(
+        values.as_ptr().cast(),
+        size_of_val(values) as u64,
+        MTLResourceOptions::StorageModeShared,
+    );
+    Ok(Buffer {
+        context,
+        buffer,
+        len,
+    })
+}
+
+/// The values of `buffer`, read from its shared storage
+pub(crate) fn download(buffer: &Buffer) -> Result<Vec<f32>, AmlError> {
+    // shared storage is coherent once the command buffers writing it have completed, which
+    // `sgemm` waits for
+    let values =
+        unsafe { std::slice::from_raw_parts(buffer.buffer.contents() as *const f32, buffer.len) };
+    Ok(values.to_vec())
+}
+
+/// `c = alpha * op(a) @ op(b) + beta * c` for row-major `a`, `b` and `c`, whose shapes were
+/// checked to make `c` `m` by `n` with `k` products per value
+pub(crate) fn sgemm(
+    a: &Buffer,
+    a_transpose: bool,
+    b: &Buffer,
+    b_transpose: bool,
+    (m, n, k): (usize, usize, usize),
+    params: GemmParams,
+    c: &mut Buffer,
+) -> Result<(), AmlError> {
+    let context = &c.context;
+    if m == 0 || n == 0 {
+        return Ok(());
+    }
+    let (Ok(m_word), Ok(n_word), Ok(k_word)) =
+        (u32::try_from(m), u32::try_from(n), u32::try_from(k))
+    else {
+        return Err(context.failed(format!(
+            "a {} by {} product over {} values is too large for the shader",
+            m, n, k
+        )));
+    };
+
+    let words = [
+        m_word,
+        n_word,
+        k_word,
+        a_transpose as u32,
+        b_transpose as u32,
+        params.alpha.to_bits(),
+        params.beta.to_bits(),
+        0,
+    ];
+    let status = autoreleasepool(|| {
+        let commands = context.queue.new_command_buffer();
+        let encoder = commands.new_compute_command_encoder();
+        encoder.set_compute_pipeline_state(&context.pipeline);
+        encoder.set_buffer(0, Some(&a.buffer), 0);
+        encoder.set_buffer(1, Some(&b.buffer), 0);
+        encoder.set_buffer(2, Some(&c.buffer), 0);
+        encoder.set_bytes(3, size_of_val(&words) as u64, words.as_ptr().cast());
+        let groups = |len: usize| len.div_ceil(TILE) as u64;
+        encoder.dispatch_thread_groups(
+            MTLSize::new(groups(n), groups(m), 1),
+            MTLSize::new(TILE as u64, TILE as u64, 1),
+        );
+        encoder.end_encoding();
+        commands.commit();
+        commands.wait_until_completed();
+        commands.status()
+    });
+    match status {
+        MTLCommandBufferStatus::Completed => Ok(()),
+        status => Err(context.failed(format!("the GEMM ended as {:?}", status))),
+    }
+}
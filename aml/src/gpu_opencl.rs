@@ -0,0 +1,214 @@
+//! `Device::OpenCl`: buffers and GEMMs on OpenCL 1.2 GPUs, behind the `gpu-opencl` feature.
+//!
+//! The OpenCL library is loaded when a tensor first goes to a GPU, not linked, so a build with
+//! the feature still runs where no OpenCL driver is installed, and reports the GPUs as
+//! unavailable. Each GPU gets one context and in-order command queue, kept for the life of the
+//! process, with the GEMM kernel built once on it from `shaders/sgemm.cl`, the same 16 by 16
+//! tiling as the wgpu shader. Reads block, so they see every GEMM queued before them.
+
+use crate::{AmlError, Device, GemmParams};
+use opencl3::command_queue::CommandQueue;
+use opencl3::device::{get_all_devices, CL_DEVICE_TYPE_GPU};
+use opencl3::kernel::Kernel;
+use opencl3::memory::{CL_MEM_COPY_HOST_PTR, CL_MEM_READ_WRITE};
+use opencl3::program::Program;
+use opencl3::types::{cl_uint, CL_BLOCKING};
+use std::sync::{Arc, Mutex};
+
+/// Rows and columns of `c` per work-group, as in the kernel
+const TILE: usize = 16;
+
+/// A buffer of f32 values on one GPU
+pub(crate) struct Buffer {
+    context: Arc<Context>,
+    buffer: opencl3::memory::Buffer<f32>,
+    len: usize,
+}
+
+/// One GPU's context and command queue, and the GEMM kernel built for it
+struct Context {
+    ordinal: usize,
+    max_alloc: usize,
+    context: opencl3::context::Context,
+    queue: CommandQueue,
+    /// Kernel arguments are set on the kernel itself, so calls from several threads take turns
+    kernel: Mutex<Kernel>,
+    _program: Program,
+}
+
+impl Context {
+    fn new(ordinal: usize) -> Result<Context, AmlError> {
+        let unavailable = |reason: String| AmlError::DeviceUnavailable {
+            device: Device::OpenCl(ordinal),
+            reason,
+        };
+        let devices =
+            get_all_devices(CL_DEVICE_TYPE_GPU).map_err(|e| unavailable(e.to_string()))?;
+        let found = devices.len();
+        let device = opencl3::device::Device::new(
+            *devices
+                .get(ordinal)
+                .ok_or_else(|| unavailable(format!("OpenCL found {} GPUs", found)))?,
+        );
+        let max_alloc = device
+            .max_mem_alloc_size()
+            .map_err(|e| unavailable(e.to_string()))? as usize;
+        let context = opencl3::context::Context::from_device(&device)
+            .map_err(|e| unavailable(e.to_string()))?;
+        let queue =
+            CommandQueue::create_default(&context, 0).map_err(|e| unavailable(e.to_string()))?;
+        let program =
+            Program::create_and_build_from_source(&context, include_str!("shaders/sgemm.cl"), "")
+                .map_err(unavailable)?;
+        let kernel = Kernel::create(&program, "sgemm").map_err(|e| unavailable(e.to_string()))?;
+        let group = kernel
+            .get_work_group_size(device.id())
+            .map_err(|e| unavailable(e.to_string()))?;
+        if group < TILE * TILE {
+            return Err(unavailable(format!(
+                "the GEMM kernel runs at most {} work-items per work-group",
+                group
+            )));
+        }
+        Ok(Context {
+            ordinal,
+            max_alloc,
+            context,
+            queue,
+            kernel: Mutex::new(kernel),
+            _program: program,
+        })
+    }
+
+    fn failed(&self, reason: impl ToString) -> AmlError {
+        AmlError::DeviceFailed {
+            device: Device::OpenCl(self.ordinal),
+            reason: reason.to_string(),
+        }
+    }
+}
+
+/// The context of GPU `ordinal`, made on first use
+fn context(ordinal: usize) -> Result<Arc<Context>, AmlError> {
+    static CONTEXTS: Mutex<Vec<Arc<Context>>> = Mutex::new(Vec::new());
+
+    let mut contexts = CONTEXTS.lock().unwrap_or_else(|e| e.into_inner());
+    if let Some(context) = contexts.iter().find(|c| c.ordinal == ordinal) {
+        return Ok(context.clone());
+    }
+    let context = Arc::new(Context::new(ordinal)?);
+    contexts.push(context.clone());
+    Ok(context)
+}
+
+pub(crate) fn is_available(ordinal: usize) -> bool {
+    context(ordinal).is_ok()
+}
+
+impl Buffer {
+    pub(crate) fn ordinal(&self) -> usize {
+        self.context.ordinal
+    }
+}
+
+/// `values` copied into a new buffer on GPU `ordinal`
+pub(crate) fn upload(ordinal: usize, values: &[f32]) -> Result<Buffer, AmlError> {
+    let context = context(ordinal)?;
+    let len = values.len();
+    if size_of_val(values) > context.max_alloc {
+        return Err(context.failed(format!(
+            "{} bytes do not fit in one buffer, which holds at most {}",
+            size_of_val(values),
+            context.max_alloc
+        )));
+    }
+    // empty buffers are invalid, so an empty tensor still takes one value
+    let padded = [0.0f32];
+    let values = match values.is_empty() {
+        true => &padded[..],
+        false => values,
+    };
+    // OpenCL copies from the host pointer before returning, and never writes through it
+    let buffer = unsafe {
+        opencl3::memory::Buffer::<f32>::create(
+            &context.context,
+            CL_MEM_READ_WRITE | CL_MEM_COPY_HOST_PTR,
+            values.len(),
+            values.as_ptr() as *mut _,
+        )
+    }
+    .map_err(|e| context.failed(e))?;
+    Ok(Buffer {
+        context,
+        buffer,
+        len,
+    })
+}
+
+/// The values of `buffer`, once the work queued on it is done
+pub(crate) fn download(buffer: &Buffer) -> Result<Vec<f32>, AmlError> {
+    let context = &buffer.context;
+    let mut values = vec![0.0; buffer.len];
+    if !values.is_empty() {
+        unsafe {
+            context
+                .queue
+                .enqueue_read_buffer(&buffer.buffer, CL_BLOCKING, 0, &mut values, &[])
+        }
+        .map_err(|e| context.failed(e))?;
+    }
+    Ok(values)
+}
+
+/// `c = alpha * op(a) @ op(b) + beta * c` for row-major `a`, `b` and `c`, whose shapes were
+/// checked to make `c` `m` by `n` with `k` products per value
+pub(crate) fn sgemm(
+    a: &Buffer,
+    a_transpose: bool,
+    b: &Buffer,
+    b_transpose: bool,
+    (m, n, k): (usize, usize, usize),
+    params: GemmParams,
+    c: &mut Buffer,
+) -> Result<(), AmlError> {
+    let context = &c.context;
+    if m == 0 || n == 0 {
+        return Ok(());
+    }
+    let (Ok(m_arg), Ok(n_arg), Ok(k_arg)) = (
+        cl_uint::try_from(m),
+        cl_uint::try_from(n),
+        cl_uint::try_from(k),
+    ) else {
+        return Err(context.failed(format!(
+            "a {} by {} product over {} values is too large for the kernel",
+            m, n, k
+        )));
+    };
+
+    let kernel = context.kernel.lock().unwrap_or_else(|e| e.into_inner());
+    let global = [n.div_ceil(TILE) * TILE, m.div_ceil(TILE) * TILE];
+    let local = [TILE, TILE];
+    // the arguments match the kernel's parameters in order and type
+    let run = || unsafe {
+        kernel.set_arg(0, &a.buffer)?;
+        kernel.set_arg(1, &b.buffer)?;
+        kernel.set_arg(2, &c.buffer)?;
+        kernel.set_arg(3, &m_arg)?;
+        kernel.set_arg(4, &n_arg)?;
+        kernel.set_arg(5, &k_arg)?;
+        kernel.set_arg(6, &(a_transpose as cl_uint))?;
+        kernel.set_arg(7, &(b_transpose as cl_uint))?;
+        kernel.set_arg(8, &params.alpha)?;
+        kernel.set_arg(9, &params.beta)?;
+        context.queue.enqueue_nd_range_kernel(
+            kernel.get(),
+            2,
+            std::ptr::null(),
+            global.as_ptr(),
+            local.as_ptr(),
+            &[],
+        )
+    };
+    run().map(|_| ()).map_err(|e| context.failed(e))
+}
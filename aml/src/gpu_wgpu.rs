@@ -0,0 +1,270 @@
+//! `Device::Wgpu`: buffers and GEMMs on any GPU wgpu drives (Vulkan, Metal, DX12 or GL),
+//! behind the `gpu-wgpu` feature.
+//!
+//! Each adapter gets one wgpu device and queue, made the first time a tensor goes to it and
+//! kept for the life of the process, with the GEMM pipeline compiled once on it. The shader
+//! (`shaders/sgemm.wgsl`) tiles `c` in 16 by 16 blocks, staged through workgroup memory.
+//! wgpu reports validation and out of memory errors through error scopes, which turn them into
+//! `DeviceFailed` here instead of the panic of its default handler.
+
+use crate::io::{self, DType};
+use crate::{AmlError, Device, GemmParams};
+use std::sync::mpsc;
+use std::sync::{Arc, Mutex};
+
+/// Rows and columns of `c` per workgroup, as in the shader
+const TILE: usize = 16;
+
+/// A buffer of f32 values on one adapter
+pub(crate) struct Buffer {
+    context: Arc<Context>,
+    buffer: wgpu::Buffer,
+    len: usize,
+}
+
+/// One adapter's device and queue, and the GEMM pipeline compiled for it
+struct Context {
+    ordinal: usize,
+    device: wgpu::Device,
+    queue: wgpu::Queue,
+    pipeline: wgpu::ComputePipeline,
+    /// Error scopes belong to the whole device, so calls from several threads take turns
+    lock: Mutex<()>,
+}
+
+impl Context {
+    fn new(ordinal: usize) -> Result<Context, AmlError> {
+        let unavailable = |reason: String| AmlError::DeviceUnavailable {
+            device: Device::Wgpu(ordinal),
+            reason,
+        };
+        let instance = wgpu::Instance::new(&wgpu::InstanceDescriptor::default());
+        let adapters = instance.enumerate_adapters(wgpu::Backends::all());
+        let found = adapters.len();
+        let adapter = adapters
+            .into_iter()
+            .nth(ordinal)
+            .ok_or_else(|| unavailable(format!("wgpu found {} adapters", found)))?;
+        // the adapter's own limits, so buffers can be as large as it allows
+        let descriptor = wgpu::DeviceDescriptor {
+            label: Some("aml"),
+            required_features: wgpu::Features::empty(),
+            required_limits: adapter.limits(),
+            memory_hints: wgpu::MemoryHints::Performance,
+        };
+        let (device, queue) = pollster::block_on(adapter.request_device(&descriptor, None))
+            .map_err(|e| unavailable(e.to_string()))?;
+
+        let module = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("aml sgemm"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("shaders/sgemm.wgsl").into()),
+        });
+        let pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some("aml sgemm"),
+            layout: None,
+            module: &module,
+            entry_point: Some("main"),
+            compilation_options: Default::default(),
+            cache: None,
+        });
+        Ok(Context {
+            ordinal,
+            device,
+            queue,
+            pipeline,
+            lock: Mutex::new(()),
+        })
+    }
+
+    fn failed(&self, reason: String) -> AmlError {
+        AmlError::DeviceFailed {
+            device: Device::Wgpu(self.ordinal),
+            reason,
+        }
+    }
+
+    /// `f`, with any validation or out of memory error it raises as `DeviceFailed`
+    fn scoped<T>(&self, f: impl FnOnce() -> Result<T, AmlError>) -> Result<T, AmlError> {
+        let _turn = self.lock.lock().unwrap_or_else(|e| e.into_inner());
+        self.device.push_error_scope(wgpu::ErrorFilter::OutOfMemory);
+        self.device.push_error_scope(wgpu::ErrorFilter::Validation);
+        let result = f();
+        let validation = pollster::block_on(self.device.pop_error_scope());
+        let memory = pollster::block_on(self.device.pop_error_scope());
+        match validation.or(memory) {
+            Some(e) => Err(self.failed(e.to_string())),
+            None => result,
+        }
+    }
+
+    /// A storage buffer of `bytes` bytes, or `DeviceFailed` if it is too large to bind
+    fn storage(&self, bytes: usize) -> Result<wgpu::Buffer, AmlError> {
+        let max = self.device.limits().max_storage_buffer_binding_size as usize;
+        if bytes > max {
+            return Err(self.failed(format!(
+                "{} bytes do not fit in one buffer, which holds at most {}",
+                bytes, max
+            )));
+        }
+        Ok(self.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("aml tensor"),
+            // empty bindings are invalid, so an empty tensor still takes one value
+            size: bytes.max(size_of::<f32>()) as u64,
+            usage: wgpu::BufferUsages::STORAGE
+                | wgpu::BufferUsages::COPY_SRC
+                | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        }))
+    }
+}
+
+/// The context of adapter `ordinal`, made on first use
+fn context(ordinal: usize) -> Result<Arc<Context>, AmlError> {
+    static CONTEXTS: Mutex<Vec<Arc<Context>>> = Mutex::new(Vec::new());
+
+    let mut contexts = CONTEXTS.lock().unwrap_or_else(|e| e.into_inner());
+    if let Some(context) = contexts.iter().find(|c| c.ordinal == ordinal) {
+        return Ok(context.clone());
+    }
+    let context = Arc::new(Context::new(ordinal)?);
+    contexts.push(context.clone());
+    Ok(context)
+}
+
+pub(crate) fn is_available(ordinal: usize) -> bool {
+    context(ordinal).is_ok()
+}
+
+impl Buffer {
+    pub(crate) fn ordinal(&self) -> usize {
+        self.context.ordinal
+    }
+}
+
+/// `values` copied into a new buffer on adapter `ordinal`
+pub(crate) fn upload(ordinal: usize, values: &[f32]) -> Result<Buffer, AmlError> {
+    let context = context(ordinal)?;
+    let buffer = context.scoped(|| {
+        let buffer = context.storage(size_of_val(values))?;
+        context.queue.write_buffer(&buffer, 0, &io::encode(values));
+        Ok(buffer)
+    })?;
+    Ok(Buffer {
+        context,
+        buffer,
+        len: values.len(),
+    })
+}
+
+/// The values of `buffer`, copied back through a mappable staging buffer
+pub(crate) fn download(buffer: &Buffer) -> Result<Vec<f32>, AmlError> {
+    let context = &buffer.context;
+    let bytes = buffer.len * size_of::<f32>();
+    context.scoped(|| {
+        let staging = context.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("aml download"),
+            size: buffer.buffer.size(),
+            usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let mut encoder = context.device.create_command_encoder(&Default::default());
+        encoder.copy_buffer_to_buffer(&buffer.buffer, 0, &staging, 0, buffer.buffer.size());
+        context.queue.submit([encoder.finish()]);
+
+        let (sender, receiver) = mpsc::channel();
+        let slice = staging.slice(..);
+        slice.map_async(wgpu::MapMode::Read, move |mapped| {
+            let _ = sender.send(mapped);
+        });
+        context.device.poll(wgpu::Maintain::Wait);
+        match receiver.recv() {
+            Ok(Ok(())) => Ok(io::decode(
+                &slice.get_mapped_range()[..bytes],
+                DType::F32,
+                true,
+            )),
+            Ok(Err(e)) => Err(context.failed(e.to_string())),
+            Err(e) => Err(context.failed(e.to_string())),
+        }
+    })
+}
+
+/// `c = alpha * op(a) @ op(b) + beta * c` for row-major `a`, `b` and `c`, whose shapes were
+/// checked to make `c` `m` by `n` with `k` products per value
+pub(crate) fn sgemm(
+    a: &Buffer,
+    a_transpose: bool,
+    b: &Buffer,
+    b_transpose: bool,
+    (m, n, k): (usize, usize, usize),
+    params: GemmParams,
+    c: &mut Buffer,
+) -> Result<(), AmlError> {
+    let context = &c.context;
+    if m == 0 || n == 0 {
+        return Ok(());
+    }
+    let max_groups = context.device.limits().max_compute_workgroups_per_dimension;
+    let groups = |len: usize| {
+        u32::try_from(len.div_ceil(TILE))
+            .ok()
+            .filter(|g| *g <= max_groups)
+    };
+    let (Some(x_groups), Some(y_groups)) = (groups(n), groups(m)) else {
+        return Err(context.failed(format!(
+            "a {} by {} product needs more than {} workgroups along an axis",
+            m, n, max_groups
+        )));
+    };
+
+    // every dimension is below the values of a buffer, which fit in a u32
+    let words = [
+        m as u32,
+        n as u32,
+        k as u32,
+        a_transpose as u32,
+        b_transpose as u32,
+        params.alpha.to_bits(),
+        params.beta.to_bits(),
+        0,
+    ];
+    let bytes: Vec<u8> = words.iter().flat_map(|w| w.to_le_bytes()).collect();
+    context.scoped(|| {
+        let uniforms = context.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("aml sgemm params"),
+            size: bytes.len() as u64,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        context.queue.write_buffer(&uniforms, 0, &bytes);
+        let bind_group = context
+            .device
+            .create_bind_group(&wgpu::BindGroupDescriptor {
+                label: Some("aml sgemm"),
+                layout: &context.pipeline.get_bind_group_layout(0),
+                entries: &[
+                    entry(0, &a.buffer),
+                    entry(1, &b.buffer),
+                    entry(2, &c.buffer),
+                    entry(3, &uniforms),
+                ],
+            });
+
+        let mut encoder = context.device.create_command_encoder(&Default::default());
+        {
+            let mut pass = encoder.begin_compute_pass(&Default::default());
+            pass.set_pipeline(&context.pipeline);
+            pass.set_bind_group(0, &bind_group, &[]);
+            pass.dispatch_workgroups(x_groups, y_groups, 1);
+        }
+        context.queue.submit([encoder.finish()]);
+        Ok(())
+    })
+}
+
+fn entry(binding: u32, buffer: &wgpu::Buffer) -> wgpu::BindGroupEntry<'_> {
+    wgpu::BindGroupEntry {
+        binding,
+        resource: buffer.as_entire_binding(),
+    }
+}
@@ -0,0 +1,218 @@
+//! Lazy expressions over f32 matrices, evaluated with their elementwise steps fused.
+//!
+//! `(Expr::new(&x).matmul(Expr::new(&w)) + Expr::new(&bias)).relu()` only records the
+//! operations; `eval` then runs them without materializing a tensor per step. Adding a tensor
+//! of the GEMM's own shape is folded into the GEMM itself, as `beta = 1`, and a bias right
+//! after it and an activation after that into `sgemm_fused`'s epilogue; any steps left are
+//! applied in one pass over the result.
+
+use crate::{
+    check_rank, try_sgemm_fused, Activation, AmlError, AsTensorRef, CowTensor, Epilogue,
+    GemmParams, Tensor, TensorRef,
+};
+use std::ops::Add;
+
+/// A recorded computation over borrowed tensors, run by `eval`.
+pub struct Expr<'a> {
+    op: Op<'a>,
+}
+
+enum Op<'a> {
+    Input(TensorRef<'a, f32>),
+    MatMul(Box<Expr<'a>>, Box<Expr<'a>>),
+    Add(Box<Expr<'a>>, Box<Expr<'a>>),
+    Activation(Box<Expr<'a>>, Activation),
+}
+
+/// An elementwise step applied to a result as it is finished
+enum Step<'a> {
+    Add(CowTensor<'a, f32>),
+    Activation(Activation),
+}
+
+impl<'a> Expr<'a> {
+    /// `tensor`, read when the expression is evaluated
+    pub fn new(tensor: &'a impl AsTensorRef<f32>) -> Expr<'a> {
+        Expr {
+            op: Op::Input(tensor.as_tensor_ref()),
+        }
+    }
+
+    /// `self @ rhs`, both matrices
+    pub fn matmul(self, rhs: Expr<'a>) -> Expr<'a> {
+        Expr {
+            op: Op::MatMul(Box::new(self), Box::new(rhs)),
+        }
+    }
+
+    /// `max(self, 0)`
+    pub fn relu(self) -> Expr<'a> {
+        self.activation(Activation::Relu)
+    }
+
+    /// GELU of each value, as `Activation::Gelu`
+    pub fn gelu(self) -> Expr<'a> {
+        self.activation(Activation::Gelu)
+    }
+
+    /// SiLU of each value, as `Activation::Silu`
+    pub fn silu(self) -> Expr<'a> {
+        self.activation(Activation::Silu)
+    }
+
+    /// `activation` of each value
+    pub fn activation(self, activation: Activation) -> Expr<'a> {
+        Expr {
+            op: Op::Activation(Box::new(self), activation),
+        }
+    }
+
+    /// Run the expression into a new tensor
+    pub fn eval(&self) -> Tensor<f32> {
+        self.try_eval().unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    /// Fallible version of `eval`.
+    pub fn try_eval(&self) -> Result<Tensor<f32>, AmlError> {
+        Ok(self.evaluate()?.into_owned())
+    }
+
+    /// The value of this expression, borrowed if it is an input
+    fn evaluate(&self) -> Result<CowTensor<'a, f32>, AmlError> {
+        // elementwise steps over the result of another expression, outermost first
+        let mut steps = Vec::new();
+        let mut base = self;
+        loop {
+            match &base.op {
+                Op::Add(lhs, rhs) => {
+                    steps.push(Step::Add(rhs.evaluate()?));
+                    base = lhs;
+                }
+                Op::Activation(operand, activation) => {
+                    steps.push(Step::Activation(*activation));
+                    base = operand;
+                }
+                _ => break,
+            }
+        }
+
+        let mut result = match &base.op {
+            Op::Input(tensor) if steps.is_empty() => return Ok(tensor.clone().into()),
+            Op::Input(tensor) => row_major(tensor)?,
+            Op::MatMul(lhs, rhs) => {
+                let (a, b) = (lhs.evaluate()?, rhs.evaluate()?);
+                let (a, b) = (a.as_tensor_ref(), b.as_tensor_ref());
+                let shape = a.shape.try_matmul(false, &b.shape, false)?;
+                // an addend as large as the product is the GEMM's `c`, with `beta = 1`
+                let fold = matches!(
+                    steps.last(),
+                    Some(Step::Add(addend)) if addend.as_tensor_ref().shape[..] == shape[..]
+                );
+                let (mut c, beta) = match (fold, steps.last()) {
+                    (true, Some(Step::Add(addend))) => (row_major(&addend.as_tensor_ref())?, 1f32),
+                    _ => (Tensor::zeros(shape.to_vec()), 0f32),
+                };
+                if fold {
+                    steps.pop();
+                }
+                // and a bias over the columns after it is added as the GEMM stores each row
+                let bias = match steps.last() {
+                    Some(Step::Add(addend)) => {
+                        let addend = addend.as_tensor_ref();
+                        match addend.shape[..] {
+                            [cols] | [1, cols] if cols == shape[1] => Some(addend.dense().to_vec()),
+                            _ => None,
+                        }
+                    }
+                    _ => None,
+                };
+                let mut epilogue = match &bias {
+                    Some(bias) => {
+                        steps.pop();
+                        Epilogue::new().with_bias(bias)
+                    }
+                    None => Epilogue::new(),
+                };
+                // and an activation after that, in the same place
+                if let Some(&Step::Activation(activation)) = steps.last() {
+                    steps.pop();
+                    epilogue = epilogue.with_activation(activation);
+                }
+                let params = GemmParams::new(1f32, beta);
+                try_sgemm_fused(&a, false, &b, false, params, epilogue, &mut c)?;
+                c
+            }
+            Op::Add(..) | Op::Activation(..) => unreachable!("taken as steps"),
+        };
+        steps.reverse();
+        apply(&mut result, &steps)?;
+        Ok(result.into())
+    }
+}
+
+/// `self + rhs`, where `rhs` has the shape of `self` or is a bias of one value per column,
+/// `[n]` or `[1, n]`, added to every row
+impl<'a> Add for Expr<'a> {
+    type Output = Expr<'a>;
+
+    fn add(self, rhs: Expr<'a>) -> Expr<'a> {
+        Expr {
+            op: Op::Add(Box::new(self), Box::new(rhs)),
+        }
+    }
+}
+
+/// A row-major copy of the matrix `tensor`
+fn row_major(tensor: &TensorRef<f32>) -> Result<Tensor<f32>, AmlError> {
+    check_rank("operand", &tensor.shape, 2)?;
+    let (rows, cols) = (tensor.shape[0], tensor.shape[1]);
+    let values = (0..rows)
+        .flat_map(|i| (0..cols).map(move |j| tensor[[i, j]]))
+        .collect();
+    Ok(Tensor::new(values, vec![rows, cols]))
+}
+
+/// Apply `steps` in order to each row of the row-major matrix `result`, so it is read and
+/// written once
+fn apply(result: &mut Tensor<f32>, steps: &[Step]) -> Result<(), AmlError> {
+    let (rows, cols) = (result.shape[0], result.shape[1]);
+    // each addend as whole rows, or as the one row a bias adds to all of them
+    let addends = steps
+        .iter()
+        .filter_map(|step| match step {
+            Step::Add(addend) => Some(addend.as_tensor_ref()),
+            Step::Activation(_) => None,
+        })
+        .map(|addend| match addend.shape[..] {
+            [r, c] if r == rows && c == cols => Ok(row_major(&addend)?.values),
+            [c] if c == cols => Ok((0..cols).map(|j| addend[[j]]).collect()),
+            [1, c] if c == cols => Ok((0..cols).map(|j| addend[[0, j]]).collect()),
+            _ => Err(AmlError::BroadcastMismatch {
+                a: vec![rows, cols],
+                b: addend.shape.to_vec(),
+            }),
+        })
+        .collect::<Result<Vec<Vec<f32>>, AmlError>>()?;
+
+    for (i, row) in result.values.chunks_exact_mut(cols.max(1)).enumerate() {
+        let mut addends = addends.iter();
+        for step in steps {
+            match step {
+                Step::Add(_) => {
+                    let addend = addends.next().expect("one per Add step");
+                    let addend = match addend.len() == cols {
+                        true => &addend[..],
+                        false => &addend[i * cols..(i + 1) * cols],
+                    };
+                    row.iter_mut()
+                        .zip(addend)
+                        .for_each(|(value, add)| *value += add);
+                }
+                Step::Activation(activation) => row
+                    .iter_mut()
+                    .for_each(|value| *value = activation.apply(*value)),
+            }
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,142 @@
+//! GEMMs that run in the background while the caller gets on with other work.
+
+use crate::parallel;
+use crate::{try_sgemm_with, AmlError, AsTensorRef, GemmParams, Tensor};
+use std::future::Future;
+use std::panic::{self, AssertUnwindSafe};
+use std::pin::Pin;
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+use std::task::{Context, Poll, Waker};
+use std::thread;
+
+/// `sgemm_with` started by `sgemm_async`, with `c` to come back when it is done.
+///
+/// Either `wait` for it, check on it with `is_finished` and `try_wait`, or `.await` it: it is a
+/// `Future` whose waker is woken from the GEMM's own thread, so it needs no particular runtime.
+/// Dropping the handle detaches the GEMM, which still runs to the end.
+#[derive(Debug)]
+pub struct GemmHandle {
+    shared: Arc<Shared>,
+}
+
+#[derive(Debug, Default)]
+struct Shared {
+    slot: Mutex<Slot>,
+    done: Condvar,
+}
+
+#[derive(Debug, Default)]
+struct Slot {
+    /// The GEMM's result, or the panic it ended in, until it is taken
+    result: Option<thread::Result<Result<Tensor<f32>, AmlError>>>,
+    /// Whether the result has been taken, after which the handle has nothing left to give
+    taken: bool,
+    waker: Option<Waker>,
+}
+
+/// Start `c = alpha * (op(a) @ op(b)) + beta * c` on a thread of its own and return at once.
+///
+/// The operands are moved onto that thread, so `a` and `b` are owned, e.g. `SharedTensor`s
+/// of weights the caller keeps using, and `c` comes back from the handle. The GEMM runs under
+/// the `AmlContext` installed where it was started, splitting across threads as `sgemm_with`
+/// would. Without the `parallel` feature nothing is spawned: the GEMM runs before this returns
+/// and the handle is already finished.
+pub fn sgemm_async(
+    a: impl AsTensorRef<f32> + Send + 'static,
+    a_transpose: bool,
+    b: impl AsTensorRef<f32> + Send + 'static,
+    b_transpose: bool,
+    params: GemmParams,
+    mut c: Tensor<f32>,
+) -> GemmHandle {
+    let shared = Arc::new(Shared::default());
+    let context = parallel::installed();
+    let worker = Arc::clone(&shared);
+    let run = move || {
+        let result = panic::catch_unwind(AssertUnwindSafe(|| {
+            let mut gemm = || try_sgemm_with(&a, a_transpose, &b, b_transpose, params, &mut c);
+            match context {
+                Some(context) => context.install(gemm),
+                None => gemm(),
+            }?;
+            Ok(c)
+        }));
+        worker.finish(result);
+    };
+    match cfg!(feature = "parallel") {
+        true => drop(thread::spawn(run)),
+        false => run(),
+    }
+    GemmHandle { shared }
+}
+
+impl Shared {
+    fn slot(&self) -> MutexGuard<'_, Slot> {
+        self.slot.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn finish(&self, result: thread::Result<Result<Tensor<f32>, AmlError>>) {
+        let mut slot = self.slot();
+        slot.result = Some(result);
+        let waker = slot.waker.take();
+        drop(slot);
+        self.done.notify_all();
+        if let Some(waker) = waker {
+            waker.wake();
+        }
+    }
+}
+
+impl GemmHandle {
+    /// Whether the GEMM has ended, so `try_wait` returns its result
+    pub fn is_finished(&self) -> bool {
+        let slot = self.shared.slot();
+        slot.result.is_some() || slot.taken
+    }
+
+    /// `c` if the GEMM has ended, without blocking. A panic on the GEMM's thread resumes here.
+    ///
+    /// # Panics
+    /// If the result was already taken, by an earlier `try_wait` or by awaiting the handle.
+    pub fn try_wait(&mut self) -> Option<Result<Tensor<f32>, AmlError>> {
+        take(&mut self.shared.slot())
+    }
+
+    /// Block until the GEMM has ended and return `c`, as `try_wait`.
+    pub fn wait(self) -> Result<Tensor<f32>, AmlError> {
+        let mut slot = self.shared.slot();
+        loop {
+            if let Some(result) = take(&mut slot) {
+                return result;
+            }
+            slot = self
+                .shared
+                .done
+                .wait(slot)
+                .unwrap_or_else(|e| e.into_inner());
+        }
+    }
+}
+
+/// The result out of `slot` if it is there, resuming a panic
+fn take(slot: &mut Slot) -> Option<Result<Tensor<f32>, AmlError>> {
+    assert!(!slot.taken, "the GEMM's result was already taken");
+    let result = slot.result.take()?;
+    slot.taken = true;
+    Some(result.unwrap_or_else(|payload| panic::resume_unwind(payload)))
+}
+
+impl Future for GemmHandle {
+    type Output = Result<Tensor<f32>, AmlError>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let mut slot = self.shared.slot();
+        match take(&mut slot) {
+            Some(result) => Poll::Ready(result),
+            None => {
+                slot.waker = Some(cx.waker().clone());
+                Poll::Pending
+            }
+        }
+    }
+}
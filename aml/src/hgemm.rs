@@ -0,0 +1,192 @@
+//! Dense f16 matrix multiply, accumulating in f32.
+
+use half::f16;
+use half::slice::HalfFloatSliceExt;
+
+#[cfg(target_arch = "x86_64")]
+use std::arch::x86_64::*;
+
+use crate::dispatch::kernels;
+use crate::{
+    block_sizes, check_gemm, gemm_operands, op_a_rows, parallel, store_rows, strided_rows,
+    AmlError, F16Tensor, F16TensorMut, GemmParams,
+};
+
+/// Matrix multiply in f16: `op(a) @ op(b)` stored in `c`, overwriting it.
+///
+/// op(F16(m, k)) @ op(F16(k, n)) --> F16(m, n). Values stay f16 in memory, halving the bandwidth
+/// of an f32 GEMM, and are widened with F16C as they are loaded; every sum is kept in f32 and
+/// rounded to f16 once, on store. Threading follows `dgemm`; panels are `2 * block_sizes().kc`
+/// deep, the same bytes as `sgemm`.
+pub fn hgemm(
+    a: &F16Tensor,
+    a_transpose: bool,
+    b: &F16Tensor,
+    b_transpose: bool,
+    c: &mut F16Tensor,
+) {
+    try_hgemm(a, a_transpose, b, b_transpose, c).unwrap_or_else(|e| panic!("{}", e))
+}
+
+/// Fallible version of `hgemm`. `c` is left untouched on error.
+pub fn try_hgemm(
+    a: &F16Tensor,
+    a_transpose: bool,
+    b: &F16Tensor,
+    b_transpose: bool,
+    c: &mut F16Tensor,
+) -> Result<(), AmlError> {
+    try_hgemm_with(
+        a,
+        a_transpose,
+        b,
+        b_transpose,
+        GemmParams::default(),
+        &mut c.view_mut(),
+    )
+}
+
+/// `c = alpha * (op(a) @ op(b)) + beta * c`
+pub fn hgemm_with(
+    a: &F16Tensor,
+    a_transpose: bool,
+    b: &F16Tensor,
+    b_transpose: bool,
+    params: GemmParams,
+    c: &mut F16TensorMut,
+) {
+    try_hgemm_with(a, a_transpose, b, b_transpose, params, c).unwrap_or_else(|e| panic!("{}", e))
+}
+
+/// Fallible version of `hgemm_with`. `c` is left untouched on error.
+pub fn try_hgemm_with(
+    a: &F16Tensor,
+    a_transpose: bool,
+    b: &F16Tensor,
+    b_transpose: bool,
+    params: GemmParams,
+    c: &mut F16TensorMut,
+) -> Result<(), AmlError> {
+    check_gemm(&a.shape, a_transpose, &b.shape, b_transpose, &c.shape)?;
+    c.check_blas("c")?;
+    let (a_copy, b_copy) = (a.blas_copy(), b.blas_copy());
+    let (a, b) = (a_copy.as_ref().unwrap_or(a), b_copy.as_ref().unwrap_or(b));
+    let (a, a_transpose, b, b_transpose) = gemm_operands(c.layout, a, a_transpose, b, b_transpose);
+    let c = c.view_mut().row_major();
+
+    let n = c.shape[1];
+    let k = match a_transpose {
+        true => a.shape[0],
+        false => a.shape[1],
+    };
+    let a_transpose = a.stored_transpose(a_transpose);
+    let b_transpose = b.stored_transpose(b_transpose);
+    let ldc = c.ld();
+
+    parallel::for_each_row_chunk(c.values, ldc, n * k, |first_row, c_rows| {
+        let rows = c_rows.len().div_ceil(ldc);
+        let a_rows = op_a_rows(&a.values, a_transpose, k, a.ld(), first_row, rows);
+        let mut acc = vec![0f32; rows * n];
+
+        match b_transpose {
+            true => {
+                for (a_i, acc_row) in a_rows.chunks_exact(k).zip(acc.chunks_exact_mut(n)) {
+                    for (acc_val, b_j) in acc_row.iter_mut().zip(strided_rows(&b.values, b.ld(), k))
+                    {
+                        *acc_val = hdot(a_i, b_j);
+                    }
+                }
+            }
+            false => {
+                let kc = block_sizes().kc * 2;
+                for p0 in (0..k).step_by(kc) {
+                    let p1 = (p0 + kc).min(k);
+                    let b_panel = &b.values[p0 * b.ld()..];
+                    for (a_i, acc_row) in a_rows.chunks_exact(k).zip(acc.chunks_exact_mut(n)) {
+                        for (a_ip, b_p) in a_i[p0..p1].iter().zip(strided_rows(b_panel, b.ld(), n))
+                        {
+                            haxpy(a_ip.to_f32(), b_p, acc_row);
+                        }
+                    }
+                }
+            }
+        }
+
+        store_rows(&acc, n, c_rows, ldc, |acc, c| params.store(acc, c));
+    });
+
+    Ok(())
+}
+
+/// f16 dot product summed in f32
+pub(crate) fn hdot(x: &[f16], y: &[f16]) -> f32 {
+    (kernels().hdot)(x, y)
+}
+
+/// `y += alpha * x`, widening `x` from f16
+pub(crate) fn haxpy(alpha: f32, x: &[f16], y: &mut [f32]) {
+    (kernels().haxpy)(alpha, x, y)
+}
+
+pub(crate) fn hdot_scalar(x: &[f16], y: &[f16]) -> f32 {
+    x.iter().zip(y).map(|(x, y)| x.to_f32() * y.to_f32()).sum()
+}
+
+pub(crate) fn haxpy_scalar(alpha: f32, x: &[f16], y: &mut [f32]) {
+    let mut x_f32 = [0f32; 64];
+    for (x_part, y_part) in x.chunks(64).zip(y.chunks_mut(64)) {
+        let x_f32 = &mut x_f32[..x_part.len()];
+        x_part.convert_to_f32_slice(x_f32);
+        for (y_val, x_val) in y_part.iter_mut().zip(x_f32.iter()) {
+            *y_val += alpha * x_val;
+        }
+    }
+}
+
+/// Widen 8 f16 values starting at `ptr`
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "avx,f16c")]
+unsafe fn load_ph(ptr: *const f16) -> __m256 {
+    _mm256_cvtph_ps(_mm_loadu_si128(ptr as *const __m128i))
+}
+
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "avx,f16c")]
+pub(crate) unsafe fn hdot_f16c(x: &[f16], y: &[f16]) -> f32 {
+    assert_eq!(x.len(), y.len());
+    let n16 = x.len() / 16 * 16;
+    let mut acc0 = _mm256_setzero_ps();
+    let mut acc1 = _mm256_setzero_ps();
+
+    for i in (0..n16).step_by(16) {
+        let x0 = load_ph(x.as_ptr().add(i));
+        let y0 = load_ph(y.as_ptr().add(i));
+        let x1 = load_ph(x.as_ptr().add(i + 8));
+        let y1 = load_ph(y.as_ptr().add(i + 8));
+        acc0 = _mm256_add_ps(acc0, _mm256_mul_ps(x0, y0));
+        acc1 = _mm256_add_ps(acc1, _mm256_mul_ps(x1, y1));
+    }
+
+    let mut lanes = [0f32; 8];
+    _mm256_storeu_ps(lanes.as_mut_ptr(), _mm256_add_ps(acc0, acc1));
+    lanes.iter().sum::<f32>() + hdot_scalar(&x[n16..], &y[n16..])
+}
+
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "avx,f16c")]
+pub(crate) unsafe fn haxpy_f16c(alpha: f32, x: &[f16], y: &mut [f32]) {
+    assert_eq!(x.len(), y.len());
+    let n8 = x.len() / 8 * 8;
+    let alpha8 = _mm256_set1_ps(alpha);
+
+    for i in (0..n8).step_by(8) {
+        let x8 = load_ph(x.as_ptr().add(i));
+        let y8 = _mm256_loadu_ps(y.as_ptr().add(i));
+        _mm256_storeu_ps(
+            y.as_mut_ptr().add(i),
+            _mm256_add_ps(y8, _mm256_mul_ps(alpha8, x8)),
+        );
+    }
+
+    haxpy_scalar(alpha, &x[n8..], &mut y[n8..]);
+}
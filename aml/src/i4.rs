@@ -0,0 +1,59 @@
+//! SIMD kernels for the packed 4 bit `I4Tensor` format.
+
+use half::f16;
+
+#[cfg(target_arch = "x86_64")]
+use std::arch::x86_64::*;
+
+/// Whether `block_dot_avx2` can run on this CPU.
+#[cfg(target_arch = "x86_64")]
+pub(crate) fn has_avx2() -> bool {
+    is_x86_feature_detected!("avx2")
+        && is_x86_feature_detected!("fma")
+        && is_x86_feature_detected!("f16c")
+}
+
+/// `sum a[i] * (q[i] - zero)` over the first `a.len()` nibbles of `nibbles`, starting on a high
+/// nibble. Left unscaled so the caller applies the block scale once.
+pub(crate) fn block_dot_scalar(a: &[f16], nibbles: &[i8], zero: i8) -> f32 {
+    a.iter()
+        .enumerate()
+        .map(|(i, a_val)| {
+            let byte = nibbles[i / 2];
+            let q = match i % 2 {
+                0 => byte >> 4,
+                _ => (byte << 4) >> 4,
+            };
+            a_val.to_f32() * (q - zero) as f32
+        })
+        .sum()
+}
+
+/// `block_dot_scalar`, unpacking 8 nibbles at a time straight into a ymm register.
+///
+/// Each byte is copied into two i32 lanes, then shifted up to the top of the lane (by 24 for
+/// the high nibble, 28 for the low one) and arithmetically back down by 28, which sign extends
+/// the nibble in place. The values never touch memory in unpacked form.
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "avx2,fma,f16c")]
+pub(crate) unsafe fn block_dot_avx2(a: &[f16], nibbles: &[i8], zero: i8) -> f32 {
+    assert!(nibbles.len() >= a.len().div_ceil(2));
+    let n8 = a.len() / 8 * 8;
+    let duplicate = _mm_setr_epi8(0, 0, 1, 1, 2, 2, 3, 3, -1, -1, -1, -1, -1, -1, -1, -1);
+    let to_top = _mm256_setr_epi32(24, 28, 24, 28, 24, 28, 24, 28);
+    let zero8 = _mm256_set1_ps(zero as f32);
+    let mut acc = _mm256_setzero_ps();
+
+    for i in (0..n8).step_by(8) {
+        let bytes = _mm_cvtsi32_si128((nibbles.as_ptr().add(i / 2) as *const i32).read_unaligned());
+        let pairs = _mm256_cvtepi8_epi32(_mm_shuffle_epi8(bytes, duplicate));
+        let q = _mm256_srai_epi32(_mm256_sllv_epi32(pairs, to_top), 28);
+        let b8 = _mm256_sub_ps(_mm256_cvtepi32_ps(q), zero8);
+        let a8 = _mm256_cvtph_ps(_mm_loadu_si128(a.as_ptr().add(i) as *const __m128i));
+        acc = _mm256_fmadd_ps(a8, b8, acc);
+    }
+
+    let mut lanes = [0f32; 8];
+    _mm256_storeu_ps(lanes.as_mut_ptr(), acc);
+    lanes.iter().sum::<f32>() + block_dot_scalar(&a[n8..], &nibbles[n8 / 2..], zero)
+}
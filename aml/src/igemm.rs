@@ -0,0 +1,208 @@
+//! int8 matrix multiply, accumulating in i32.
+
+#[cfg(all(feature = "amx", target_arch = "x86_64", target_os = "linux"))]
+use crate::amx;
+use crate::dispatch::kernels;
+use crate::{
+    check_gemm, gemm_operands, op_a_rows, parallel, AmlError, F32Tensor, GemmParams, I8Tensor,
+};
+
+#[cfg(target_arch = "x86_64")]
+use std::arch::x86_64::*;
+
+/// Quantized matrix multiply: `op(a) @ op(b)` dequantized into an f32 `c`, overwriting it.
+///
+/// op(I8(m, k)) @ op(I8(k, n)) --> F32(m, n). The zero points are factored out of the inner
+/// loop,
+///
+/// `sum (qa - za)(qb - zb) = sum qa qb - zb sum qa - za sum qb + k za zb`,
+///
+/// so the hot loop is a plain i8 dot product summed exactly in i32, and the row/column sums are
+/// computed once. The i32 result is scaled by `a.scale * b.scale` on store. Layout follows
+/// `sbgemm`: `b` is gathered into (n, k) once so every output is one contiguous dot product.
+/// With the `amx` feature on a CPU with AMX, the dot products of whole 16 x 16 blocks of `c`
+/// run on tile registers with `tdpbssd` instead.
+///
+/// The dot products are summed in i32 over runs of at most `K_BLOCK = 2^16` values, which
+/// cannot overflow (`128 * 128 * 2^16 = 2^30`), and the runs and zero point terms in i64, so
+/// the result is exact for any `k` until it is rounded to f32 on store. The AMX tiles sum all
+/// of `k` in i32, so longer `k` stays off them.
+pub fn igemm(a: &I8Tensor, a_transpose: bool, b: &I8Tensor, b_transpose: bool, c: &mut F32Tensor) {
+    try_igemm(a, a_transpose, b, b_transpose, c).unwrap_or_else(|e| panic!("{}", e))
+}
+
+/// Fallible version of `igemm`. `c` is left untouched on error.
+pub fn try_igemm(
+    a: &I8Tensor,
+    a_transpose: bool,
+    b: &I8Tensor,
+    b_transpose: bool,
+    c: &mut F32Tensor,
+) -> Result<(), AmlError> {
+    try_igemm_with(a, a_transpose, b, b_transpose, GemmParams::default(), c)
+}
+
+/// `c = alpha * dequantize(op(a) @ op(b)) + beta * c`
+pub fn igemm_with(
+    a: &I8Tensor,
+    a_transpose: bool,
+    b: &I8Tensor,
+    b_transpose: bool,
+    params: GemmParams,
+    c: &mut F32Tensor,
+) {
+    try_igemm_with(a, a_transpose, b, b_transpose, params, c).unwrap_or_else(|e| panic!("{}", e))
+}
+
+/// Fallible version of `igemm_with`. `c` is left untouched on error.
+pub fn try_igemm_with(
+    a: &I8Tensor,
+    a_transpose: bool,
+    b: &I8Tensor,
+    b_transpose: bool,
+    params: GemmParams,
+    c: &mut F32Tensor,
+) -> Result<(), AmlError> {
+    check_gemm(&a.shape, a_transpose, &b.shape, b_transpose, &c.shape)?;
+    c.check_blas("c")?;
+    let (a, a_transpose, b, b_transpose) = gemm_operands(c.layout, a, a_transpose, b, b_transpose);
+    let c = c.view_mut().row_major();
+
+    let (m, n) = (c.shape[0], c.shape[1]);
+    let k = match a_transpose {
+        true => a.shape[0],
+        false => a.shape[1],
+    };
+    let (za, zb) = (a.zero_point as i64, b.zero_point as i64);
+    let scale = a.scale * b.scale;
+
+    // i8 tensors are always dense, so rows are as long as their stored shape
+    let lda = match a_transpose {
+        true => m,
+        false => k,
+    };
+    let ldb = match b_transpose {
+        true => k,
+        false => n,
+    };
+    let ldc = c.ld();
+    let b_cols = op_a_rows(&b.values, !b_transpose, k, ldb, 0, n);
+    let b_sums: Vec<i64> = b_cols.chunks_exact(k).map(isum).collect();
+
+    #[cfg(all(feature = "amx", target_arch = "x86_64", target_os = "linux"))]
+    if kernels().amx && k <= K_BLOCK {
+        let packed_b = amx::pack_b(&b_cols, n, k);
+        parallel::for_each_row_chunk(c.values, ldc, n * k, |first_row, c_rows| {
+            let rows = c_rows.len().div_ceil(ldc);
+            let a_rows = op_a_rows(&a.values, a_transpose, k, lda, first_row, rows);
+            let mut dots = vec![0i32; rows * n];
+            amx::tile_gemm(&a_rows, &packed_b, n, k, &mut dots);
+
+            for ((a_i, c_row), dot_row) in a_rows
+                .chunks_exact(k)
+                .zip(c_rows.chunks_mut(ldc))
+                .zip(dots.chunks_exact(n))
+            {
+                let a_sum = isum(a_i);
+                for ((c_val, dot), b_sum) in c_row.iter_mut().zip(dot_row).zip(&b_sums) {
+                    let acc = *dot as i64 - zb * a_sum - za * b_sum + k as i64 * za * zb;
+                    *c_val = params.apply(scale * acc as f32, *c_val);
+                }
+            }
+        });
+        return Ok(());
+    }
+
+    parallel::for_each_row_chunk(c.values, ldc, n * k, |first_row, c_rows| {
+        let rows = c_rows.len().div_ceil(ldc);
+        let a_rows = op_a_rows(&a.values, a_transpose, k, lda, first_row, rows);
+
+        for (a_i, c_row) in a_rows.chunks_exact(k).zip(c_rows.chunks_mut(ldc)) {
+            let a_sum = isum(a_i);
+            for ((c_val, b_j), b_sum) in c_row.iter_mut().zip(b_cols.chunks_exact(k)).zip(&b_sums) {
+                let acc = idot_wide(a_i, b_j) - zb * a_sum - za * b_sum + k as i64 * za * zb;
+                *c_val = params.apply(scale * acc as f32, *c_val);
+            }
+        }
+    });
+
+    Ok(())
+}
+
+/// Longest run of values an i8 dot product sums exactly in i32
+const K_BLOCK: usize = 1 << 16;
+
+/// `idot` over runs of `K_BLOCK`, summed in i64
+fn idot_wide(x: &[i8], y: &[i8]) -> i64 {
+    x.chunks(K_BLOCK)
+        .zip(y.chunks(K_BLOCK))
+        .map(|(x, y)| idot(x, y) as i64)
+        .sum()
+}
+
+/// i8 dot product summed in i32, with AVX-512 VNNI and AVX2 versions.
+pub(crate) fn idot(x: &[i8], y: &[i8]) -> i32 {
+    (kernels().idot)(x, y)
+}
+
+/// Written so the compiler widens and sums whole vectors at once.
+pub(crate) fn idot_scalar(x: &[i8], y: &[i8]) -> i32 {
+    x.iter().zip(y).map(|(x, y)| *x as i32 * *y as i32).sum()
+}
+
+/// 64 products per iteration with `vpdpbusd`, 4 per i32 lane.
+///
+/// `vpdpbusd` multiplies unsigned by signed bytes, so `x` is biased into u8 by flipping its sign
+/// bit (`x + 128`) and the bias is taken back out at the end: `x . y = (x + 128) . y - 128 sum y`.
+/// `sum y` comes from a second `vpdpbusd` against ones. Neither accumulator saturates.
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "avx512f,avx512vnni")]
+pub(crate) unsafe fn idot_vnni(x: &[i8], y: &[i8]) -> i32 {
+    assert_eq!(x.len(), y.len());
+    let n64 = x.len() / 64 * 64;
+    let bias = _mm512_set1_epi8(i8::MIN);
+    let ones = _mm512_set1_epi8(1);
+    let mut acc = _mm512_setzero_si512();
+    let mut y_sum = _mm512_setzero_si512();
+
+    for i in (0..n64).step_by(64) {
+        let x_u8 = _mm512_xor_si512(_mm512_loadu_si512(x.as_ptr().add(i).cast()), bias);
+        let y64 = _mm512_loadu_si512(y.as_ptr().add(i).cast());
+        acc = _mm512_dpbusd_epi32(acc, x_u8, y64);
+        y_sum = _mm512_dpbusd_epi32(y_sum, ones, y64);
+    }
+
+    _mm512_reduce_add_epi32(acc) - 128 * _mm512_reduce_add_epi32(y_sum)
+        + idot_scalar(&x[n64..], &y[n64..])
+}
+
+/// 16 products per iteration: sign extend to i16, then `vpmaddwd` into i32.
+///
+/// The usual `vpmaddubsw` route adds pairs of u8 x i8 products in i16 and saturates on full
+/// range inputs, which `igemm` cannot rule out, so this widens first and stays exact.
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "avx2")]
+pub(crate) unsafe fn idot_avx2(x: &[i8], y: &[i8]) -> i32 {
+    assert_eq!(x.len(), y.len());
+    let n16 = x.len() / 16 * 16;
+    let mut acc = _mm256_setzero_si256();
+
+    for i in (0..n16).step_by(16) {
+        let x16 = _mm256_cvtepi8_epi16(_mm_loadu_si128(x.as_ptr().add(i).cast()));
+        let y16 = _mm256_cvtepi8_epi16(_mm_loadu_si128(y.as_ptr().add(i).cast()));
+        acc = _mm256_add_epi32(acc, _mm256_madd_epi16(x16, y16));
+    }
+
+    let sum128 = _mm_add_epi32(
+        _mm256_castsi256_si128(acc),
+        _mm256_extracti128_si256(acc, 1),
+    );
+    let sum64 = _mm_add_epi32(sum128, _mm_unpackhi_epi64(sum128, sum128));
+    let sum32 = _mm_add_epi32(sum64, _mm_shuffle_epi32(sum64, 1));
+
+    _mm_cvtsi128_si32(sum32) + idot_scalar(&x[n16..], &y[n16..])
+}
+
+fn isum(x: &[i8]) -> i64 {
+    x.iter().map(|x| *x as i64).sum()
+}
@@ -0,0 +1,102 @@
+//! Reading and writing tensors in the file formats other tools use.
+//!
+//! Values are converted to the element type asked for on reading, so an f64 array saved by
+//! NumPy loads straight into an `F32Tensor`. Format errors are `InvalidData`.
+
+pub mod csv;
+pub mod mtx;
+pub mod npy;
+pub mod npz;
+pub mod onnx;
+pub mod safetensors;
+mod zip;
+
+use crate::{convert, Element, Pod};
+use half::{bf16, f16};
+use std::any::TypeId;
+use std::io;
+
+/// Element types as stored in files
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "lowercase")
+)]
+pub enum DType {
+    F16,
+    BF16,
+    F32,
+    F64,
+}
+
+impl DType {
+    /// The type of `T`
+    pub fn of<T: Element>() -> DType {
+        match TypeId::of::<T>() {
+            id if id == TypeId::of::<f16>() => DType::F16,
+            id if id == TypeId::of::<bf16>() => DType::BF16,
+            id if id == TypeId::of::<f32>() => DType::F32,
+            _ => DType::F64,
+        }
+    }
+
+    /// Bytes per value
+    pub fn size(self) -> usize {
+        match self {
+            DType::F16 | DType::BF16 => 2,
+            DType::F32 => 4,
+            DType::F64 => 8,
+        }
+    }
+}
+
+/// Values of `dtype` stored back to back in `bytes`, converted to `T`
+pub(crate) fn decode<T: Element>(bytes: &[u8], dtype: DType, little_endian: bool) -> Vec<T> {
+    fn values<S: Element, const N: usize>(
+        bytes: &[u8],
+        little_endian: bool,
+        from_bits: impl Fn([u8; N]) -> S,
+    ) -> Vec<S> {
+        bytes
+            .chunks_exact(N)
+            .map(|chunk| {
+                let mut bits: [u8; N] = chunk.try_into().unwrap();
+                // stored little endian: reverse for a big endian file, on any machine
+                if !little_endian {
+                    bits.reverse();
+                }
+                from_bits(bits)
+            })
+            .collect()
+    }
+
+    let mut out = vec![T::ZERO; bytes.len() / dtype.size()];
+    match dtype {
+        DType::F16 => convert(&values(bytes, little_endian, f16::from_le_bytes), &mut out),
+        DType::BF16 => convert(&values(bytes, little_endian, bf16::from_le_bytes), &mut out),
+        DType::F32 => convert(&values(bytes, little_endian, f32::from_le_bytes), &mut out),
+        DType::F64 => convert(&values(bytes, little_endian, f64::from_le_bytes), &mut out),
+    }
+    out
+}
+
+/// `values` as little endian bytes
+pub(crate) fn encode<T: Element + Pod>(values: &[T]) -> Vec<u8> {
+    let size = std::mem::size_of::<T>();
+    // any `Pod` value is its bytes
+    let bytes = unsafe {
+        std::slice::from_raw_parts(values.as_ptr().cast::<u8>(), std::mem::size_of_val(values))
+    };
+    let mut bytes = bytes.to_vec();
+    if cfg!(target_endian = "big") {
+        for value in bytes.chunks_exact_mut(size) {
+            value.reverse();
+        }
+    }
+    bytes
+}
+
+pub(crate) fn invalid_data(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
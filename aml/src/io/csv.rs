@@ -0,0 +1,106 @@
+//! Delimited text, CSV or TSV: one row of the matrix per line.
+//!
+//! Lines are parsed as they are read, straight into the tensor's values, so the text is
+//! never held whole. Fields may be padded with spaces or quoted; an empty field is NaN, as
+//! pandas reads it. Blank lines are skipped. Every row must have as many fields as the first.
+
+use super::invalid_data;
+use crate::{Element, Tensor};
+use std::fs::File;
+use std::io::{self, BufRead, BufReader};
+use std::path::Path;
+
+/// How the text is laid out
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Options {
+    /// Between fields: `b','` for CSV, `b'\t'` for TSV
+    pub delimiter: u8,
+    /// Lines at the top to skip, such as a row of column names
+    pub header_rows: usize,
+}
+
+impl Options {
+    pub fn new(delimiter: u8) -> Options {
+        Options {
+            delimiter,
+            header_rows: 0,
+        }
+    }
+
+    /// Tab separated
+    pub fn tsv() -> Options {
+        Options::new(b'\t')
+    }
+
+    /// Same delimiter, skipping `header_rows` lines first.
+    pub fn with_header_rows(self, header_rows: usize) -> Options {
+        Options {
+            header_rows,
+            ..self
+        }
+    }
+}
+
+/// Comma separated, without a header
+impl Default for Options {
+    fn default() -> Options {
+        Options::new(b',')
+    }
+}
+
+/// Read the delimited file at `path` as a row-major `[rows, columns]` tensor
+pub fn load<T: Element>(path: impl AsRef<Path>, options: Options) -> io::Result<Tensor<T>> {
+    read(BufReader::new(File::open(path)?), options)
+}
+
+/// Read delimited text from `reader`, as `load`
+pub fn read<T: Element>(mut reader: impl BufRead, options: Options) -> io::Result<Tensor<T>> {
+    let delimiter = options.delimiter as char;
+    let mut line = String::new();
+    let mut number = 0;
+    let mut values = Vec::new();
+    let mut rows = 0;
+    let mut cols = None;
+    loop {
+        line.clear();
+        if reader.read_line(&mut line)? == 0 {
+            break;
+        }
+        number += 1;
+        if number <= options.header_rows || line.trim().is_empty() {
+            continue;
+        }
+
+        let start = values.len();
+        for field in line.trim_end_matches(['\n', '\r']).split(delimiter) {
+            let field = field.trim();
+            let field = field
+                .strip_prefix('"')
+                .and_then(|field| field.strip_suffix('"'))
+                .map_or(field, str::trim);
+            let value = match field.is_empty() {
+                true => f64::NAN,
+                false => field.parse().map_err(|_| {
+                    invalid_data(format!(
+                        "Field {:?} on line {} is not a number.",
+                        field, number
+                    ))
+                })?,
+            };
+            values.push(T::from_f64(value));
+        }
+        let found = values.len() - start;
+        match cols {
+            None => cols = Some(found),
+            Some(cols) if cols != found => {
+                return Err(invalid_data(format!(
+                    "Line {} has {} fields, not {}.",
+                    number, found, cols
+                )))
+            }
+            Some(_) => {}
+        }
+        rows += 1;
+    }
+    Ok(Tensor::new(values, vec![rows, cols.unwrap_or(0)]))
+}
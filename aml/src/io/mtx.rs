@@ -0,0 +1,302 @@
+//! Matrix Market `.mtx` files: a banner, comments, a size line and then the entries as text.
+//!
+//! Reads real, integer and pattern matrices in either format, general, symmetric or
+//! skew-symmetric. Array files list every value column by column, so load as a column-major
+//! tensor; coordinate files list the nonzeros as 1-based `row col value` lines, kept as
+//! `Coordinates` or scattered into a row-major tensor. Complex and hermitian matrices are
+//! `InvalidData`.
+
+use super::invalid_data;
+use super::npy::READ_CHUNK;
+use crate::{AmlError, Element, Layout, Tensor};
+use std::fs::File;
+use std::io::{self, BufRead, BufReader};
+use std::path::Path;
+
+/// The nonzeros of a coordinate file, 0-based and with the mirrored half of a symmetric
+/// matrix filled in, in file order
+#[derive(Debug, Clone, PartialEq)]
+pub struct Coordinates<T> {
+    pub shape: [usize; 2],
+    pub entries: Vec<(usize, usize, T)>,
+}
+
+impl<T: Element> Coordinates<T> {
+    /// The dense matrix, row-major, with entries listed more than once summed
+    pub fn to_tensor(&self) -> Tensor<T> {
+        self.try_to_tensor().unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    /// Fallible version of `to_tensor`: `ShapeOverflow` if the matrix has too many values to
+    /// count, `AllocationFailed` if they do not fit in memory, and `IndexOutOfBounds` for an
+    /// entry outside it.
+    pub fn try_to_tensor(&self) -> Result<Tensor<T>, AmlError> {
+        let [rows, cols] = self.shape;
+        let len = rows
+            .checked_mul(cols)
+            .ok_or_else(|| AmlError::ShapeOverflow {
+                shape: vec![rows, cols],
+            })?;
+        let mut values = Vec::new();
+        values
+            .try_reserve_exact(len)
+            .map_err(|_| AmlError::AllocationFailed {
+                bytes: len.saturating_mul(size_of::<f64>()),
+                align: align_of::<f64>(),
+            })?;
+        values.resize(len, 0f64);
+        for &(row, col, value) in &self.entries {
+            if row >= rows || col >= cols {
+                return Err(AmlError::IndexOutOfBounds {
+                    index: vec![row, col],
+                    shape: vec![rows, cols],
+                });
+            }
+            values[row * cols + col] += value.to_f64();
+        }
+        Tensor::try_new(
+            values.into_iter().map(T::from_f64).collect(),
+            vec![rows, cols],
+        )
+    }
+}
+
+/// Read the matrix in the `.mtx` file at `path` as a dense tensor, converting its values to
+/// `T`
+pub fn load<T: Element>(path: impl AsRef<Path>) -> io::Result<Tensor<T>> {
+    read(BufReader::new(File::open(path)?))
+}
+
+/// Read one `.mtx` matrix from `reader`, as `load`
+pub fn read<T: Element>(reader: impl BufRead) -> io::Result<Tensor<T>> {
+    let mut lines = Lines::new(reader);
+    let banner = Banner::parse(&lines.banner()?)?;
+    match banner.coordinate {
+        true => coordinates(&mut lines, &banner)?
+            .try_to_tensor()
+            .map_err(|e| match e {
+                AmlError::AllocationFailed { .. } => io::Error::new(io::ErrorKind::OutOfMemory, e),
+                e => invalid_data(e.to_string()),
+            }),
+        false => array(&mut lines, &banner),
+    }
+}
+
+/// Read the nonzeros of the coordinate `.mtx` file at `path`, without making the matrix
+/// dense. An array file is `InvalidData`.
+pub fn load_coordinates<T: Element>(path: impl AsRef<Path>) -> io::Result<Coordinates<T>> {
+    read_coordinates(BufReader::new(File::open(path)?))
+}
+
+/// Read the nonzeros of one coordinate `.mtx` matrix from `reader`, as `load_coordinates`
+pub fn read_coordinates<T: Element>(reader: impl BufRead) -> io::Result<Coordinates<T>> {
+    let mut lines = Lines::new(reader);
+    let banner = Banner::parse(&lines.banner()?)?;
+    match banner.coordinate {
+        true => coordinates(&mut lines, &banner),
+        false => Err(invalid_data("Not a coordinate format file.".into())),
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Symmetry {
+    General,
+    Symmetric,
+    SkewSymmetric,
+}
+
+struct Banner {
+    coordinate: bool,
+    pattern: bool,
+    symmetry: Symmetry,
+}
+
+impl Banner {
+    /// `%%MatrixMarket matrix <format> <field> <symmetry>`, in any case
+    fn parse(line: &str) -> io::Result<Banner> {
+        let words: Vec<String> = line.split_whitespace().map(str::to_lowercase).collect();
+        let words: Vec<&str> = words.iter().map(String::as_str).collect();
+        let [banner, "matrix", format, field, symmetry] = words[..] else {
+            return Err(invalid_data("Not a Matrix Market matrix.".into()));
+        };
+        if banner != "%%matrixmarket" {
+            return Err(invalid_data("Not a Matrix Market matrix.".into()));
+        }
+        let coordinate = match format {
+            "coordinate" => true,
+            "array" => false,
+            _ => return Err(invalid_data(format!("Unknown format {}.", format))),
+        };
+        let pattern = match field {
+            "real" | "double" | "integer" => false,
+            "pattern" if coordinate => true,
+            _ => return Err(invalid_data(format!("Unsupported field {}.", field))),
+        };
+        let symmetry = match symmetry {
+            "general" => Symmetry::General,
+            "symmetric" => Symmetry::Symmetric,
+            "skew-symmetric" => Symmetry::SkewSymmetric,
+            _ => return Err(invalid_data(format!("Unsupported symmetry {}.", symmetry))),
+        };
+        Ok(Banner {
+            coordinate,
+            pattern,
+            symmetry,
+        })
+    }
+}
+
+/// The lines of a file after the banner, without comments or blank lines
+struct Lines<R> {
+    reader: R,
+    line: String,
+    number: usize,
+}
+
+impl<R: BufRead> Lines<R> {
+    fn new(reader: R) -> Lines<R> {
+        Lines {
+            reader,
+            line: String::new(),
+            number: 0,
+        }
+    }
+
+    fn banner(&mut self) -> io::Result<String> {
+        self.number += 1;
+        self.reader.read_line(&mut self.line)?;
+        Ok(std::mem::take(&mut self.line))
+    }
+
+    /// The next line's `COUNT` words, and its line number
+    fn words<const COUNT: usize>(&mut self) -> io::Result<(usize, [&str; COUNT])> {
+        loop {
+            self.line.clear();
+            self.number += 1;
+            if self.reader.read_line(&mut self.line)? == 0 {
+                return Err(invalid_data(format!(
+                    "File ends at line {}, before the last entry.",
+                    self.number
+                )));
+            }
+            let line = self.line.trim_start();
+            if !line.is_empty() && !line.starts_with('%') {
+                break;
+            }
+        }
+        let words: Vec<&str> = self.line.split_whitespace().collect();
+        let words = words.try_into().map_err(|_| bad_line(self.number))?;
+        Ok((self.number, words))
+    }
+}
+
+fn bad_line(number: usize) -> io::Error {
+    invalid_data(format!("Bad line {}.", number))
+}
+
+fn parse<N: std::str::FromStr>(word: &str, line: usize) -> io::Result<N> {
+    word.parse().map_err(|_| bad_line(line))
+}
+
+/// Values in a `rows` x `cols` matrix, `InvalidData` if there are too many to count
+fn matrix_len(rows: usize, cols: usize) -> io::Result<usize> {
+    rows.checked_mul(cols).ok_or_else(|| {
+        let e = AmlError::ShapeOverflow {
+            shape: vec![rows, cols],
+        };
+        invalid_data(e.to_string())
+    })
+}
+
+fn coordinates<T: Element>(
+    lines: &mut Lines<impl BufRead>,
+    banner: &Banner,
+) -> io::Result<Coordinates<T>> {
+    let (line, [rows, cols, nonzeros]) = lines.words()?;
+    let (rows, cols, nonzeros): (usize, usize, usize) = (
+        parse(rows, line)?,
+        parse(cols, line)?,
+        parse(nonzeros, line)?,
+    );
+    matrix_len(rows, cols)?;
+    let mut entries = Vec::new();
+    for _ in 0..nonzeros {
+        let (line, row, col, value) = match banner.pattern {
+            true => {
+                let (line, [row, col]) = lines.words()?;
+                (line, parse(row, line)?, parse(col, line)?, 1.)
+            }
+            false => {
+                let (line, [row, col, value]) = lines.words()?;
+                (
+                    line,
+                    parse(row, line)?,
+                    parse(col, line)?,
+                    parse(value, line)?,
+                )
+            }
+        };
+        if !(1..=rows).contains(&row) || !(1..=cols).contains(&col) {
+            return Err(invalid_data(format!(
+                "Entry ({}, {}) on line {} is outside the {}x{} matrix.",
+                row, col, line, rows, cols
+            )));
+        }
+        let (row, col) = (row - 1, col - 1);
+        entries.push((row, col, T::from_f64(value)));
+        match banner.symmetry {
+            Symmetry::General => {}
+            _ if row == col => {}
+            Symmetry::Symmetric => entries.push((col, row, T::from_f64(value))),
+            Symmetry::SkewSymmetric => entries.push((col, row, T::from_f64(-value))),
+        }
+    }
+    Ok(Coordinates {
+        shape: [rows, cols],
+        entries,
+    })
+}
+
+fn array<T: Element>(lines: &mut Lines<impl BufRead>, banner: &Banner) -> io::Result<Tensor<T>> {
+    let (line, [rows, cols]) = lines.words()?;
+    let (rows, cols): (usize, usize) = (parse(rows, line)?, parse(cols, line)?);
+    if banner.symmetry != Symmetry::General && rows != cols {
+        return Err(invalid_data("A symmetric matrix must be square.".into()));
+    }
+    let len = matrix_len(rows, cols)?;
+    // column by column; a symmetric matrix lists only its lower triangle, with the diagonal
+    // unless it is skew-symmetric, whose diagonal is zero
+    let symmetry = banner.symmetry;
+    let listed = (0..cols).flat_map(move |col| {
+        let first = match symmetry {
+            Symmetry::General => 0,
+            Symmetry::Symmetric => col,
+            Symmetry::SkewSymmetric => col + 1,
+        };
+        (first..rows).map(move |row| (row, col))
+    });
+
+    // grown as the values arrive, as in `npy`, so a size line the file does not back cannot ask
+    // for the memory of the whole matrix
+    let mut read = Vec::with_capacity(len.min(READ_CHUNK));
+    for _ in listed.clone() {
+        let (line, [value]) = lines.words()?;
+        let value: f64 = parse(value, line)?;
+        read.push(T::from_f64(value));
+    }
+    let values = match symmetry {
+        Symmetry::General => read,
+        _ => {
+            let mut values = vec![T::ZERO; len];
+            for ((row, col), value) in listed.zip(read) {
+                values[col * rows + row] = value;
+                values[row * rows + col] = match symmetry {
+                    Symmetry::SkewSymmetric => T::from_f64(-value.to_f64()),
+                    _ => value,
+                };
+            }
+            values
+        }
+    };
+    Ok(Tensor::new(values, vec![rows, cols]).with_layout(Layout::ColMajor))
+}
@@ -0,0 +1,211 @@
+//! NumPy `.npy` files: one array, a short text header and the raw values.
+//!
+//! Reads format versions 1 to 3, f2, f4 and f8 arrays of either byte order, in C or Fortran
+//! order; a Fortran order array loads as a column-major tensor. Writes version 1 (2 for huge
+//! headers), little endian, in the tensor's own layout, as `numpy.save` would.
+
+use super::{decode, encode, invalid_data, DType};
+use crate::{AsTensorRef, Element, Layout, Pod, Shape, Tensor};
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::Path;
+
+const MAGIC: &[u8; 6] = b"\x93NUMPY";
+/// The whole preamble and header is padded to a multiple of this, so the values are aligned
+const HEADER_ALIGN: usize = 64;
+/// The values are read into a buffer no larger than this to start with, growing as they
+/// arrive, so a header claiming a huge shape cannot ask for memory the file does not back
+pub(crate) const READ_CHUNK: usize = 1 << 20;
+
+/// Read the array in the `.npy` file at `path`, converting its values to `T`
+pub fn load<T: Element>(path: impl AsRef<Path>) -> io::Result<Tensor<T>> {
+    read(BufReader::new(File::open(path)?))
+}
+
+/// Read one `.npy` array from `reader`, as `load`
+pub fn read<T: Element>(mut reader: impl Read) -> io::Result<Tensor<T>> {
+    let mut preamble = [0u8; 8];
+    reader.read_exact(&mut preamble)?;
+    if &preamble[..6] != MAGIC {
+        return Err(invalid_data("Not a .npy file.".to_string()));
+    }
+    let header_len = match preamble[6] {
+        1 => {
+            let mut len = [0u8; 2];
+            reader.read_exact(&mut len)?;
+            u16::from_le_bytes(len) as usize
+        }
+        2 | 3 => {
+            let mut len = [0u8; 4];
+            reader.read_exact(&mut len)?;
+            u32::from_le_bytes(len) as usize
+        }
+        version => {
+            return Err(invalid_data(format!(
+                "Unsupported .npy version {}.",
+                version
+            )))
+        }
+    };
+    let mut header = vec![0u8; header_len];
+    reader.read_exact(&mut header)?;
+    let header =
+        String::from_utf8(header).map_err(|_| invalid_data("Header is not text.".into()))?;
+    let Header {
+        dtype,
+        little_endian,
+        fortran_order,
+        shape,
+    } = parse_header(&header)?;
+
+    let len = shape
+        .byte_len(dtype.size())
+        .map_err(|e| invalid_data(e.to_string()))?;
+    let mut bytes = Vec::with_capacity(len.min(READ_CHUNK));
+    reader.take(len as u64).read_to_end(&mut bytes)?;
+    if bytes.len() != len {
+        return Err(io::Error::new(
+            io::ErrorKind::UnexpectedEof,
+            format!("Expected {} bytes of values, found {}.", len, bytes.len()),
+        ));
+    }
+    let tensor = Tensor::new(decode(&bytes, dtype, little_endian), shape);
+    Ok(match fortran_order {
+        true => tensor.with_layout(Layout::ColMajor),
+        false => tensor,
+    })
+}
+
+/// Write `tensor` to a new `.npy` file at `path`. bf16 has no NumPy type, so is
+/// `InvalidInput`.
+pub fn save<T: Element + Pod>(
+    path: impl AsRef<Path>,
+    tensor: &impl AsTensorRef<T>,
+) -> io::Result<()> {
+    let mut writer = BufWriter::new(File::create(path)?);
+    write(&mut writer, tensor)?;
+    writer.flush()
+}
+
+/// Write `tensor` to `writer` as a `.npy` file, as `save`
+pub fn write<T: Element + Pod>(
+    mut writer: impl Write,
+    tensor: &impl AsTensorRef<T>,
+) -> io::Result<()> {
+    let descr = match DType::of::<T>() {
+        DType::F16 => "<f2",
+        DType::F32 => "<f4",
+        DType::F64 => "<f8",
+        DType::BF16 => {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "NumPy has no bfloat16 type.",
+            ))
+        }
+    };
+    let tensor = tensor.as_tensor_ref();
+    let gathered = match tensor.is_contiguous() {
+        true => None,
+        false => Some(tensor.to_contiguous()),
+    };
+    let values = match &gathered {
+        Some(gathered) => &gathered.values[..],
+        None => &tensor.values[..tensor.shape.numel()],
+    };
+
+    let shape = match tensor.shape.rank() {
+        1 => format!("({},)", tensor.shape[0]),
+        _ => format!(
+            "({})",
+            tensor
+                .shape
+                .iter()
+                .map(usize::to_string)
+                .collect::<Vec<_>>()
+                .join(", ")
+        ),
+    };
+    let fortran_order = match tensor.layout {
+        Layout::RowMajor => "False",
+        Layout::ColMajor => "True",
+    };
+    let mut header = format!(
+        "{{'descr': '{}', 'fortran_order': {}, 'shape': {}, }}",
+        descr, fortran_order, shape
+    );
+
+    // version 1 counts the header in 2 bytes, version 2 in 4
+    let (version, len_bytes) = match header.len() + HEADER_ALIGN < u16::MAX as usize {
+        true => (1u8, 2),
+        false => (2u8, 4),
+    };
+    let preamble = MAGIC.len() + 2 + len_bytes;
+    let padded = (preamble + header.len() + 1).div_ceil(HEADER_ALIGN) * HEADER_ALIGN;
+    header.push_str(&" ".repeat(padded - preamble - header.len() - 1));
+    header.push('\n');
+
+    writer.write_all(MAGIC)?;
+    writer.write_all(&[version, 0])?;
+    match version {
+        1 => writer.write_all(&(header.len() as u16).to_le_bytes())?,
+        _ => writer.write_all(&(header.len() as u32).to_le_bytes())?,
+    }
+    writer.write_all(header.as_bytes())?;
+    writer.write_all(&encode(values))
+}
+
+struct Header {
+    dtype: DType,
+    little_endian: bool,
+    fortran_order: bool,
+    shape: Shape,
+}
+
+/// Parse the Python dict literal NumPy writes, e.g.
+/// `{'descr': '<f4', 'fortran_order': False, 'shape': (3, 4), }`
+fn parse_header(header: &str) -> io::Result<Header> {
+    let value = |key: &str| {
+        let start = header
+            .find(&format!("'{}'", key))
+            .or_else(|| header.find(&format!("\"{}\"", key)))
+            .ok_or_else(|| invalid_data(format!("Header has no '{}'.", key)))?;
+        let rest = header[start + key.len() + 2..].trim_start();
+        Ok::<&str, io::Error>(rest.strip_prefix(':').unwrap_or(rest).trim_start())
+    };
+
+    let descr = value("descr")?
+        .strip_prefix(['\'', '"'])
+        .ok_or_else(|| invalid_data("Descr is not a string.".into()))?;
+    let descr = descr.split(['\'', '"']).next().unwrap_or("");
+    let little_endian = match descr.chars().next() {
+        Some('<') => true,
+        Some('>') => false,
+        _ => cfg!(target_endian = "little"),
+    };
+    let dtype = match descr.trim_start_matches(['<', '>', '=', '|']) {
+        "f2" => DType::F16,
+        "f4" => DType::F32,
+        "f8" => DType::F64,
+        other => return Err(invalid_data(format!("Unsupported .npy dtype '{}'.", other))),
+    };
+
+    let fortran_order = value("fortran_order")?.starts_with("True");
+    let shape = value("shape")?
+        .strip_prefix('(')
+        .and_then(|shape| shape.get(..shape.find(')')?))
+        .ok_or_else(|| invalid_data("Shape is not a tuple.".into()))?;
+    let shape = shape
+        .split(',')
+        .map(str::trim)
+        .filter(|dim| !dim.is_empty())
+        .map(|dim| dim.trim_end_matches('L').parse::<usize>())
+        .collect::<Result<Vec<usize>, _>>()
+        .map_err(|_| invalid_data("Shape is not a tuple of lengths.".into()))?;
+
+    Ok(Header {
+        dtype,
+        little_endian,
+        fortran_order,
+        shape: shape.into(),
+    })
+}
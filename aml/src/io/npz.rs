@@ -0,0 +1,94 @@
+//! NumPy `.npz` archives: a zip of `.npy` files, one per named array.
+//!
+//! Reads what `numpy.savez` and `numpy.savez_compressed` write, stored or deflated, zip64
+//! included. Members are named without their `.npy` suffix, as `numpy.load` names them.
+//! Writes stored archives, as `numpy.savez`.
+
+use super::{invalid_data, npy, zip};
+use crate::{AsTensorRef, Element, Pod, Tensor};
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Read, Seek, Write};
+use std::path::Path;
+
+/// Read every array in the `.npz` archive at `path`, converting their values to `T`
+pub fn load<T: Element>(path: impl AsRef<Path>) -> io::Result<HashMap<String, Tensor<T>>> {
+    read(BufReader::new(File::open(path)?))
+}
+
+/// Read every array in the `.npz` archive in `reader`, as `load`
+pub fn read<T: Element>(mut reader: impl Read + Seek) -> io::Result<HashMap<String, Tensor<T>>> {
+    zip::entries(&mut reader)?
+        .into_iter()
+        .map(|entry| {
+            let contents = zip::read_entry(&mut reader, &entry)?;
+            Ok((
+                array_name(&entry.name).to_string(),
+                npy::read(&contents[..])?,
+            ))
+        })
+        .collect()
+}
+
+/// Read just the array called `name` in the `.npz` archive at `path`
+pub fn load_array<T: Element>(path: impl AsRef<Path>, name: &str) -> io::Result<Tensor<T>> {
+    read_array(BufReader::new(File::open(path)?), name)
+}
+
+/// Read just the array called `name` in the `.npz` archive in `reader`. A missing array is
+/// `NotFound`.
+pub fn read_array<T: Element>(mut reader: impl Read + Seek, name: &str) -> io::Result<Tensor<T>> {
+    let entry = zip::entries(&mut reader)?
+        .into_iter()
+        .find(|entry| array_name(&entry.name) == name)
+        .ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("No array {} in the archive.", name),
+            )
+        })?;
+    npy::read(&zip::read_entry(&mut reader, &entry)?[..])
+}
+
+/// The names of the arrays in the `.npz` archive in `reader`, in archive order
+pub fn names(mut reader: impl Read + Seek) -> io::Result<Vec<String>> {
+    Ok(zip::entries(&mut reader)?
+        .into_iter()
+        .map(|entry| array_name(&entry.name).to_string())
+        .collect())
+}
+
+/// Write `arrays` to a new `.npz` file at `path`. bf16 has no NumPy type, so is
+/// `InvalidInput`.
+pub fn save<T: Element + Pod>(
+    path: impl AsRef<Path>,
+    arrays: &[(&str, impl AsTensorRef<T>)],
+) -> io::Result<()> {
+    let mut writer = BufWriter::new(File::create(path)?);
+    write(&mut writer, arrays)?;
+    writer.flush()
+}
+
+/// Write `arrays` to `writer` as a `.npz` archive, as `save`
+pub fn write<T: Element + Pod>(
+    writer: impl Write,
+    arrays: &[(&str, impl AsTensorRef<T>)],
+) -> io::Result<()> {
+    let mut names = Vec::with_capacity(arrays.len());
+    let mut members = Vec::with_capacity(arrays.len());
+    for (name, tensor) in arrays {
+        let member = format!("{}.npy", name);
+        if names.contains(&member) {
+            return Err(invalid_data(format!("Array {} is named twice.", name)));
+        }
+        let mut contents = Vec::new();
+        npy::write(&mut contents, tensor)?;
+        names.push(member);
+        members.push(contents);
+    }
+    zip::write_stored(writer, names.iter().map(String::as_str).zip(members))
+}
+
+fn array_name(member: &str) -> &str {
+    member.strip_suffix(".npy").unwrap_or(member)
+}
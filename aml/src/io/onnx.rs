@@ -0,0 +1,323 @@
+//! Weights out of ONNX models: the initializers of the main graph, by name.
+//!
+//! Only the protobuf wire format is parsed, and only as far as the graph's initializers, so
+//! no schema or protobuf library is needed. float, double, float16 and bfloat16 initializers
+//! are read, from `raw_data` or the typed fields, or from external data files next to the
+//! model. Initializers of other types (the int64 shapes `Reshape` takes, say) are not weights
+//! and are skipped.
+
+use super::{decode, invalid_data, DType};
+use crate::{Element, Shape, Tensor};
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, Read, Seek, SeekFrom};
+use std::path::{Component, Path};
+
+// field numbers, from onnx.proto
+const MODEL_GRAPH: u64 = 7;
+const GRAPH_INITIALIZER: u64 = 5;
+const TENSOR_DIMS: u64 = 1;
+const TENSOR_DATA_TYPE: u64 = 2;
+const TENSOR_FLOAT_DATA: u64 = 4;
+const TENSOR_INT32_DATA: u64 = 5;
+const TENSOR_NAME: u64 = 8;
+const TENSOR_RAW_DATA: u64 = 9;
+const TENSOR_DOUBLE_DATA: u64 = 10;
+const TENSOR_EXTERNAL_DATA: u64 = 13;
+const TENSOR_DATA_LOCATION: u64 = 14;
+const ENTRY_KEY: u64 = 1;
+const ENTRY_VALUE: u64 = 2;
+
+// `TensorProto.DataType`
+const FLOAT: u64 = 1;
+const FLOAT16: u64 = 10;
+const DOUBLE: u64 = 11;
+const BFLOAT16: u64 = 16;
+
+const EXTERNAL: u64 = 1;
+
+/// Read the initializers of the ONNX model at `path`, converting their values to `T`.
+/// External data is read from files in the model's directory.
+pub fn load<T: Element>(path: impl AsRef<Path>) -> io::Result<HashMap<String, Tensor<T>>> {
+    let path = path.as_ref();
+    let mut model = Vec::new();
+    File::open(path)?.read_to_end(&mut model)?;
+    initializers(&model, path.parent())
+}
+
+/// Read the initializers of the ONNX model in `reader`, as `load`. With no directory to find
+/// them in, initializers stored as external data are `InvalidData`.
+pub fn read<T: Element>(mut reader: impl Read) -> io::Result<HashMap<String, Tensor<T>>> {
+    let mut model = Vec::new();
+    reader.read_to_end(&mut model)?;
+    initializers(&model, None)
+}
+
+fn initializers<T: Element>(
+    model: &[u8],
+    dir: Option<&Path>,
+) -> io::Result<HashMap<String, Tensor<T>>> {
+    let mut tensors = HashMap::new();
+    for field in Fields(model) {
+        let graph = match field? {
+            (MODEL_GRAPH, Value::Bytes(graph)) => graph,
+            _ => continue,
+        };
+        for field in Fields(graph) {
+            let initializer = match field? {
+                (GRAPH_INITIALIZER, Value::Bytes(initializer)) => initializer,
+                _ => continue,
+            };
+            if let Some((name, tensor)) = tensor(initializer, dir)? {
+                if tensors.insert(name.clone(), tensor).is_some() {
+                    return Err(invalid_data(format!(
+                        "Initializer {} is named twice.",
+                        name
+                    )));
+                }
+            }
+        }
+    }
+    Ok(tensors)
+}
+
+/// The name and values of a `TensorProto`, unless it holds values of a type that is skipped
+fn tensor<T: Element>(
+    message: &[u8],
+    dir: Option<&Path>,
+) -> io::Result<Option<(String, Tensor<T>)>> {
+    let mut dims = Vec::new();
+    let mut data_type = 0;
+    let mut name = String::new();
+    let mut raw_data = None;
+    let mut floats = Vec::new();
+    let mut doubles = Vec::new();
+    let mut int32s = Vec::new();
+    let mut external = false;
+    let mut entries = Vec::new();
+    for field in Fields(message) {
+        match field? {
+            (TENSOR_DIMS, value) => value.each_varint(|dim| dims.push(dim))?,
+            (TENSOR_DATA_TYPE, Value::Varint(value)) => data_type = value,
+            (TENSOR_FLOAT_DATA, value) => value.each_fixed::<4>(|v| {
+                floats.push(f32::from_le_bytes(v));
+            })?,
+            (TENSOR_DOUBLE_DATA, value) => value.each_fixed::<8>(|v| {
+                doubles.push(f64::from_le_bytes(v));
+            })?,
+            (TENSOR_INT32_DATA, value) => value.each_varint(|v| int32s.push(v))?,
+            (TENSOR_NAME, Value::Bytes(value)) => name = String::from_utf8_lossy(value).into(),
+            (TENSOR_RAW_DATA, Value::Bytes(value)) => raw_data = Some(value),
+            (TENSOR_EXTERNAL_DATA, Value::Bytes(entry)) => entries.push(entry),
+            (TENSOR_DATA_LOCATION, Value::Varint(location)) => external = location == EXTERNAL,
+            _ => {}
+        }
+    }
+    let dtype = match data_type {
+        FLOAT => DType::F32,
+        FLOAT16 => DType::F16,
+        DOUBLE => DType::F64,
+        BFLOAT16 => DType::BF16,
+        _ => return Ok(None),
+    };
+    let dims: Option<Vec<usize>> = dims
+        .iter()
+        .map(|&dim| usize::try_from(dim as i64).ok())
+        .collect();
+    let shape: Shape = dims
+        .ok_or_else(|| invalid_data(format!("Negative dimension in initializer {}.", name)))?
+        .into();
+    let len = shape
+        .byte_len(dtype.size())
+        .map_err(|e| invalid_data(format!("Initializer {} is too large: {}", name, e)))?;
+
+    let owned;
+    let bytes = match (external, raw_data) {
+        (true, _) => {
+            owned = read_external(&entries, dir, &name)?;
+            &owned[..]
+        }
+        (false, Some(raw_data)) => raw_data,
+        (false, None) => {
+            // typed fields: float16 and bfloat16 bits are stored one per int32
+            owned = match dtype {
+                DType::F32 => le_bytes(&floats, f32::to_le_bytes),
+                DType::F64 => le_bytes(&doubles, f64::to_le_bytes),
+                DType::F16 | DType::BF16 => le_bytes(&int32s, |bits| (bits as u16).to_le_bytes()),
+            };
+            &owned[..]
+        }
+    };
+    if bytes.len() != len {
+        return Err(invalid_data(format!(
+            "Initializer {} holds {} bytes, not the {} of shape {:?}.",
+            name,
+            bytes.len(),
+            len,
+            shape.to_vec()
+        )));
+    }
+    let tensor = Tensor::new(decode(bytes, dtype, true), shape);
+    Ok(Some((name, tensor)))
+}
+
+/// `values` as little endian bytes
+fn le_bytes<V: Copy, const N: usize>(values: &[V], to_bytes: impl Fn(V) -> [u8; N]) -> Vec<u8> {
+    values.iter().flat_map(|&v| to_bytes(v)).collect()
+}
+
+/// The bytes an initializer's `external_data` entries point at: `location` relative to the
+/// model's directory, from `offset` (0 if missing) for `length` bytes (the rest of the file).
+/// As in onnxruntime, a `location` that is absolute or climbs out of the directory with `..`
+/// is `InvalidData`, so a model cannot read files other than its own.
+fn read_external(entries: &[&[u8]], dir: Option<&Path>, name: &str) -> io::Result<Vec<u8>> {
+    let mut location = None;
+    let mut offset = 0;
+    let mut length = None;
+    for entry in entries {
+        let (mut key, mut value) = (&[][..], &[][..]);
+        for field in Fields(entry) {
+            match field? {
+                (ENTRY_KEY, Value::Bytes(bytes)) => key = bytes,
+                (ENTRY_VALUE, Value::Bytes(bytes)) => value = bytes,
+                _ => {}
+            }
+        }
+        let value = String::from_utf8_lossy(value);
+        let number = || {
+            value
+                .parse::<u64>()
+                .map_err(|_| invalid_data(format!("Bad external data for {}.", name)))
+        };
+        match key {
+            b"location" => location = Some(value.to_string()),
+            b"offset" => offset = number()?,
+            b"length" => length = Some(number()?),
+            _ => {}
+        }
+    }
+    let (Some(dir), Some(location)) = (dir, location) else {
+        return Err(invalid_data(format!(
+            "Initializer {} is external data, which needs the model's path.",
+            name
+        )));
+    };
+
+    let inside = Path::new(&location)
+        .components()
+        .all(|part| matches!(part, Component::Normal(_) | Component::CurDir));
+    if !inside {
+        return Err(invalid_data(format!(
+            "External data {} of initializer {} is outside the model's directory.",
+            location, name
+        )));
+    }
+
+    let mut file = File::open(dir.join(location))?;
+    file.seek(SeekFrom::Start(offset))?;
+    let mut bytes = Vec::new();
+    match length {
+        Some(length) => file.take(length).read_to_end(&mut bytes)?,
+        None => file.read_to_end(&mut bytes)?,
+    };
+    Ok(bytes)
+}
+
+/// A field's value, by wire type
+enum Value<'a> {
+    Varint(u64),
+    Fixed64([u8; 8]),
+    Bytes(&'a [u8]),
+    Fixed32([u8; 4]),
+}
+
+impl Value<'_> {
+    /// A repeated varint field, packed or not
+    fn each_varint(self, mut f: impl FnMut(u64)) -> io::Result<()> {
+        match self {
+            Value::Varint(value) => f(value),
+            Value::Bytes(mut packed) => {
+                while !packed.is_empty() {
+                    f(varint(&mut packed)?);
+                }
+            }
+            _ => return Err(wire_error()),
+        }
+        Ok(())
+    }
+
+    /// A repeated fixed width field, packed or not
+    fn each_fixed<const N: usize>(self, mut f: impl FnMut([u8; N])) -> io::Result<()> {
+        match self {
+            Value::Fixed32(value) if N == 4 => f(value[..].try_into().unwrap()),
+            Value::Fixed64(value) if N == 8 => f(value[..].try_into().unwrap()),
+            Value::Bytes(packed) if packed.len().is_multiple_of(N) => {
+                for value in packed.chunks_exact(N) {
+                    f(value.try_into().unwrap());
+                }
+            }
+            _ => return Err(wire_error()),
+        }
+        Ok(())
+    }
+}
+
+fn wire_error() -> io::Error {
+    invalid_data("Bad protobuf message.".into())
+}
+
+/// Take a varint off the front of `bytes`
+fn varint(bytes: &mut &[u8]) -> io::Result<u64> {
+    let mut value = 0u64;
+    for shift in (0..64).step_by(7) {
+        let (&byte, rest) = bytes.split_first().ok_or_else(wire_error)?;
+        *bytes = rest;
+        value |= ((byte & 0x7f) as u64) << shift;
+        if byte & 0x80 == 0 {
+            return Ok(value);
+        }
+    }
+    Err(wire_error())
+}
+
+/// Take `len` bytes off the front of `bytes`
+fn take<'a>(bytes: &mut &'a [u8], len: usize) -> io::Result<&'a [u8]> {
+    if len > bytes.len() {
+        return Err(wire_error());
+    }
+    let (taken, rest) = bytes.split_at(len);
+    *bytes = rest;
+    Ok(taken)
+}
+
+/// The fields of a protobuf message, as field number and value, in the order written
+struct Fields<'a>(&'a [u8]);
+
+impl<'a> Iterator for Fields<'a> {
+    type Item = io::Result<(u64, Value<'a>)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.0.is_empty() {
+            return None;
+        }
+        let field = (|| {
+            let key = varint(&mut self.0)?;
+            let value = match key & 7 {
+                0 => Value::Varint(varint(&mut self.0)?),
+                1 => Value::Fixed64(take(&mut self.0, 8)?.try_into().unwrap()),
+                2 => {
+                    let len = usize::try_from(varint(&mut self.0)?).map_err(|_| wire_error())?;
+                    Value::Bytes(take(&mut self.0, len)?)
+                }
+                5 => Value::Fixed32(take(&mut self.0, 4)?.try_into().unwrap()),
+                _ => return Err(wire_error()),
+            };
+            Ok((key >> 3, value))
+        })();
+        // stop after an error, rather than parse the rest out of step
+        if field.is_err() {
+            self.0 = &[];
+        }
+        Some(field)
+    }
+}
@@ -0,0 +1,363 @@
+//! safetensors files: a JSON header naming each tensor, then every tensor's raw values.
+//!
+//! The file is memory-mapped (see `Tensor::from_mmap`) and each tensor is a view into the
+//! one mapping, so loading reads only the header. Values are little endian and row-major.
+//! F16, BF16, F32 and F64 tensors are supported; any other type is `InvalidData`.
+
+use super::{decode, invalid_data, DType};
+use crate::mmap::FileBytes;
+use crate::{AmlError, Element, Pod, Shape, Tensor, TensorRef};
+use std::collections::HashMap;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, Read};
+use std::ops::Range;
+use std::path::Path;
+use std::sync::Arc;
+
+/// The header is bounded, so a corrupt length cannot ask for a huge allocation
+const MAX_HEADER_LEN: usize = 100 << 20;
+
+/// One tensor of a safetensors file, borrowing its bytes from the file's mapping. Views are
+/// cheap to clone, and the mapping lives until the last view of the file is dropped.
+#[derive(Clone)]
+pub struct TensorView {
+    file: Arc<FileBytes>,
+    range: Range<usize>,
+    dtype: DType,
+    shape: Shape,
+}
+
+/// Map the safetensors file at `path` and view each tensor in it by name. The header's
+/// `__metadata__` is skipped. As with `Tensor::from_mmap`, the file must not be truncated or
+/// written to while any of its views are alive.
+pub fn load(path: impl AsRef<Path>) -> io::Result<HashMap<String, TensorView>> {
+    let mut file = File::open(path)?;
+    let file_len = file.metadata()?.len() as usize;
+    let mut len = [0u8; 8];
+    file.read_exact(&mut len)?;
+    let header_len = u64::from_le_bytes(len);
+    if header_len > MAX_HEADER_LEN.min(file_len - 8) as u64 {
+        return Err(invalid_data("Bad safetensors header length.".into()));
+    }
+    let mut header = vec![0u8; header_len as usize];
+    file.read_exact(&mut header)?;
+    let header =
+        String::from_utf8(header).map_err(|_| invalid_data("Header is not text.".into()))?;
+    let entries = match Json::parse(&header)? {
+        Json::Object(entries) => entries,
+        _ => return Err(invalid_data("Header is not a JSON object.".into())),
+    };
+
+    // offsets count from the end of the header; mapping the whole file keeps every value
+    // exactly as aligned as its offset in the file
+    let data_start = 8 + header_len as usize;
+    let data_len = file_len - data_start;
+    let bytes = Arc::new(FileBytes::open(&file, 0, file_len)?);
+    let mut views = HashMap::with_capacity(entries.len());
+    for (name, entry) in entries {
+        if name == "__metadata__" {
+            continue;
+        }
+        let bad = |what: &str| invalid_data(format!("Bad {} for tensor {}.", what, name));
+        let dtype = match entry.get("dtype") {
+            Some(Json::String(dtype)) => match dtype.as_str() {
+                "F16" => DType::F16,
+                "BF16" => DType::BF16,
+                "F32" => DType::F32,
+                "F64" => DType::F64,
+                other => {
+                    return Err(invalid_data(format!(
+                        "Unsupported dtype {} for tensor {}.",
+                        other, name
+                    )))
+                }
+            },
+            _ => return Err(bad("dtype")),
+        };
+        let shape: Shape = entry
+            .get("shape")
+            .and_then(Json::as_usizes)
+            .ok_or_else(|| bad("shape"))?
+            .into();
+        let (begin, end) = match entry.get("data_offsets").and_then(Json::as_usizes) {
+            Some(offsets) if offsets.len() == 2 => (offsets[0], offsets[1]),
+            _ => return Err(bad("data_offsets")),
+        };
+        let expected = shape.byte_len(dtype.size()).ok();
+        if begin > end || end > data_len || Some(end - begin) != expected {
+            return Err(bad("data_offsets"));
+        }
+        let view = TensorView {
+            file: bytes.clone(),
+            range: data_start + begin..data_start + end,
+            dtype,
+            shape,
+        };
+        if views.insert(name.clone(), view).is_some() {
+            return Err(invalid_data(format!("Tensor {} is named twice.", name)));
+        }
+    }
+    Ok(views)
+}
+
+impl TensorView {
+    pub fn dtype(&self) -> DType {
+        self.dtype
+    }
+
+    pub fn shape(&self) -> &Shape {
+        &self.shape
+    }
+
+    /// The stored values, little endian
+    pub fn bytes(&self) -> &[u8] {
+        &self.file.bytes()[self.range.clone()]
+    }
+
+    /// Whether the values are mapped from the file rather than read into memory
+    pub fn is_mapped(&self) -> bool {
+        self.file.is_mapped()
+    }
+
+    /// The values in place, without a copy. Panics unless `T` is the stored type and the
+    /// values are aligned for it in the file.
+    pub fn tensor_ref<T: Element + Pod>(&self) -> TensorRef<'_, T> {
+        self.try_tensor_ref().unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    /// Fallible version of `tensor_ref`, as `DTypeMismatch` or `MisalignedBytes`
+    pub fn try_tensor_ref<T: Element + Pod>(&self) -> Result<TensorRef<'_, T>, AmlError> {
+        if DType::of::<T>() != self.dtype {
+            return Err(AmlError::DTypeMismatch {
+                expected: DType::of::<T>(),
+                found: self.dtype,
+            });
+        }
+        TensorRef::try_from_bytes(self.bytes(), self.shape.clone())
+    }
+
+    /// A copy of the values converted to `T`, whatever the stored type and alignment
+    pub fn to_tensor<T: Element>(&self) -> Tensor<T> {
+        Tensor::new(decode(self.bytes(), self.dtype, true), self.shape.clone())
+    }
+}
+
+impl fmt::Debug for TensorView {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TensorView")
+            .field("dtype", &self.dtype)
+            .field("shape", &self.shape.to_vec())
+            .field("bytes", &self.range)
+            .finish()
+    }
+}
+
+/// Just enough JSON for the header
+enum Json {
+    /// `null`, `true` or `false`, which the header has no use for
+    Literal,
+    /// The number as written, parsed where it is used
+    Number(String),
+    String(String),
+    Array(Vec<Json>),
+    Object(Vec<(String, Json)>),
+}
+
+impl Json {
+    fn parse(text: &str) -> io::Result<Json> {
+        let mut parser = Parser {
+            text: text.as_bytes(),
+            at: 0,
+        };
+        let value = parser.value(0)?;
+        parser.whitespace();
+        match parser.at == parser.text.len() {
+            true => Ok(value),
+            false => Err(parser.error()),
+        }
+    }
+
+    fn get(&self, key: &str) -> Option<&Json> {
+        match self {
+            Json::Object(entries) => entries.iter().find(|(k, _)| k == key).map(|(_, v)| v),
+            _ => None,
+        }
+    }
+
+    fn as_usizes(&self) -> Option<Vec<usize>> {
+        match self {
+            Json::Array(values) => values
+                .iter()
+                .map(|value| match value {
+                    Json::Number(number) => number.parse().ok(),
+                    _ => None,
+                })
+                .collect(),
+            _ => None,
+        }
+    }
+}
+
+struct Parser<'a> {
+    text: &'a [u8],
+    at: usize,
+}
+
+impl Parser<'_> {
+    /// Nesting deeper than this is not a header, and would overflow the stack
+    const MAX_DEPTH: usize = 64;
+
+    fn error(&self) -> io::Error {
+        invalid_data(format!("Bad JSON in header at byte {}.", self.at))
+    }
+
+    fn whitespace(&mut self) {
+        while let Some(b' ' | b'\t' | b'\n' | b'\r') = self.text.get(self.at) {
+            self.at += 1;
+        }
+    }
+
+    fn eat(&mut self, byte: u8) -> bool {
+        self.whitespace();
+        let found = self.text.get(self.at) == Some(&byte);
+        if found {
+            self.at += 1;
+        }
+        found
+    }
+
+    fn literal(&mut self, word: &str) -> io::Result<Json> {
+        match self.text[self.at..].starts_with(word.as_bytes()) {
+            true => {
+                self.at += word.len();
+                Ok(Json::Literal)
+            }
+            false => Err(self.error()),
+        }
+    }
+
+    fn value(&mut self, depth: usize) -> io::Result<Json> {
+        if depth > Self::MAX_DEPTH {
+            return Err(self.error());
+        }
+        self.whitespace();
+        match self.text.get(self.at) {
+            Some(b'{') => {
+                self.at += 1;
+                let mut entries = Vec::new();
+                if !self.eat(b'}') {
+                    loop {
+                        self.whitespace();
+                        let key = self.string()?;
+                        if !self.eat(b':') {
+                            return Err(self.error());
+                        }
+                        entries.push((key, self.value(depth + 1)?));
+                        match (self.eat(b','), self.eat(b'}')) {
+                            (true, false) => continue,
+                            (false, true) => break,
+                            _ => return Err(self.error()),
+                        }
+                    }
+                }
+                Ok(Json::Object(entries))
+            }
+            Some(b'[') => {
+                self.at += 1;
+                let mut values = Vec::new();
+                if !self.eat(b']') {
+                    loop {
+                        values.push(self.value(depth + 1)?);
+                        match (self.eat(b','), self.eat(b']')) {
+                            (true, false) => continue,
+                            (false, true) => break,
+                            _ => return Err(self.error()),
+                        }
+                    }
+                }
+                Ok(Json::Array(values))
+            }
+            Some(b'"') => Ok(Json::String(self.string()?)),
+            Some(b'n') => self.literal("null"),
+            Some(b't') => self.literal("true"),
+            Some(b'f') => self.literal("false"),
+            Some(b'-' | b'0'..=b'9') => {
+                let start = self.at;
+                while let Some(b'-' | b'+' | b'.' | b'e' | b'E' | b'0'..=b'9') =
+                    self.text.get(self.at)
+                {
+                    self.at += 1;
+                }
+                // only ASCII was skipped
+                let number = std::str::from_utf8(&self.text[start..self.at]).unwrap();
+                Ok(Json::Number(number.to_string()))
+            }
+            _ => Err(self.error()),
+        }
+    }
+
+    fn string(&mut self) -> io::Result<String> {
+        if self.text.get(self.at) != Some(&b'"') {
+            return Err(self.error());
+        }
+        self.at += 1;
+        let mut out = Vec::new();
+        loop {
+            match *self.text.get(self.at).ok_or_else(|| self.error())? {
+                b'"' => break,
+                b'\\' => {
+                    self.at += 1;
+                    let escaped = match *self.text.get(self.at).ok_or_else(|| self.error())? {
+                        b'"' => '"',
+                        b'\\' => '\\',
+                        b'/' => '/',
+                        b'b' => '\u{8}',
+                        b'f' => '\u{c}',
+                        b'n' => '\n',
+                        b'r' => '\r',
+                        b't' => '\t',
+                        b'u' => {
+                            let high = self.hex4()?;
+                            // a pair of UTF-16 surrogates for characters past the BMP
+                            let code = match high {
+                                0xd800..=0xdbff => {
+                                    if !self.text[self.at + 1..].starts_with(b"\\u") {
+                                        return Err(self.error());
+                                    }
+                                    self.at += 2;
+                                    let low = self.hex4()?;
+                                    if !(0xdc00..=0xdfff).contains(&low) {
+                                        return Err(self.error());
+                                    }
+                                    0x10000 + ((high - 0xd800) << 10) + (low - 0xdc00)
+                                }
+                                code => code,
+                            };
+                            char::from_u32(code).ok_or_else(|| self.error())?
+                        }
+                        _ => return Err(self.error()),
+                    };
+                    out.extend(escaped.encode_utf8(&mut [0; 4]).as_bytes());
+                }
+                byte => out.push(byte),
+            }
+            self.at += 1;
+        }
+        self.at += 1;
+        // the header was checked to be UTF-8, and escapes only add whole characters
+        Ok(String::from_utf8(out).unwrap())
+    }
+
+    /// The four hex digits after the `u` at `at`, leaving `at` on the last
+    fn hex4(&mut self) -> io::Result<u32> {
+        let digits = self
+            .text
+            .get(self.at + 1..self.at + 5)
+            .and_then(|digits| std::str::from_utf8(digits).ok())
+            .and_then(|digits| u32::from_str_radix(digits, 16).ok())
+            .ok_or_else(|| self.error())?;
+        self.at += 4;
+        Ok(digits)
+    }
+}
@@ -0,0 +1,501 @@
+//! Just enough of the zip format for `.npz` archives: reading stored and deflated members,
+//! zip64 included, and writing stored ones.
+
+use super::invalid_data;
+use std::io::{self, Read, Seek, SeekFrom, Write};
+
+const LOCAL_HEADER: u32 = 0x0403_4b50;
+const CENTRAL_HEADER: u32 = 0x0201_4b50;
+const END_OF_DIRECTORY: u32 = 0x0605_4b50;
+const ZIP64_END_OF_DIRECTORY: u32 = 0x0606_4b50;
+const ZIP64_LOCATOR: u32 = 0x0706_4b50;
+const ZIP64_EXTRA: u16 = 0x0001;
+const STORED: u16 = 0;
+const DEFLATED: u16 = 8;
+
+/// A member as listed in the central directory
+#[derive(Debug, Clone)]
+pub(crate) struct Entry {
+    pub(crate) name: String,
+    method: u16,
+    crc: u32,
+    compressed_size: u64,
+    size: u64,
+    offset: u64,
+}
+
+fn u16_at(bytes: &[u8], at: usize) -> u16 {
+    u16::from_le_bytes(bytes[at..at + 2].try_into().unwrap())
+}
+
+fn u32_at(bytes: &[u8], at: usize) -> u32 {
+    u32::from_le_bytes(bytes[at..at + 4].try_into().unwrap())
+}
+
+fn u64_at(bytes: &[u8], at: usize) -> u64 {
+    u64::from_le_bytes(bytes[at..at + 8].try_into().unwrap())
+}
+
+fn truncated() -> io::Error {
+    invalid_data("Truncated zip archive.".into())
+}
+
+/// The members of the archive in `reader`, in directory order
+pub(crate) fn entries(reader: &mut (impl Read + Seek)) -> io::Result<Vec<Entry>> {
+    // the end record is the last thing in the file, after a comment of up to 64 KiB
+    let len = reader.seek(SeekFrom::End(0))?;
+    let tail_len = len.min(22 + u16::MAX as u64);
+    let mut tail = vec![0u8; tail_len as usize];
+    reader.seek(SeekFrom::Start(len - tail_len))?;
+    reader.read_exact(&mut tail)?;
+    let end = (0..tail.len().saturating_sub(21))
+        .rev()
+        .find(|&at| u32_at(&tail, at) == END_OF_DIRECTORY)
+        .ok_or_else(|| invalid_data("Not a zip archive.".into()))?;
+    let mut count = u16_at(&tail, end + 10) as u64;
+    let mut directory_len = u32_at(&tail, end + 12) as u64;
+    let mut directory_offset = u32_at(&tail, end + 16) as u64;
+
+    let locator = end.checked_sub(20);
+    if let Some(locator) = locator.filter(|&at| u32_at(&tail, at) == ZIP64_LOCATOR) {
+        let mut record = [0u8; 56];
+        reader.seek(SeekFrom::Start(u64_at(&tail, locator + 8)))?;
+        reader.read_exact(&mut record)?;
+        if u32_at(&record, 0) != ZIP64_END_OF_DIRECTORY {
+            return Err(invalid_data("Bad zip64 end of directory record.".into()));
+        }
+        count = u64_at(&record, 32);
+        directory_len = u64_at(&record, 40);
+        directory_offset = u64_at(&record, 48);
+    }
+    if directory_offset.saturating_add(directory_len) > len {
+        return Err(truncated());
+    }
+
+    let mut directory = vec![0u8; directory_len as usize];
+    reader.seek(SeekFrom::Start(directory_offset))?;
+    reader.read_exact(&mut directory)?;
+    let mut entries = Vec::new();
+    let mut at = 0;
+    for _ in 0..count {
+        if at + 46 > directory.len() || u32_at(&directory, at) != CENTRAL_HEADER {
+            return Err(invalid_data("Bad zip central directory.".into()));
+        }
+        let name_len = u16_at(&directory, at + 28) as usize;
+        let extra_len = u16_at(&directory, at + 30) as usize;
+        let comment_len = u16_at(&directory, at + 32) as usize;
+        let next = at + 46 + name_len + extra_len + comment_len;
+        if next > directory.len() {
+            return Err(truncated());
+        }
+        let name = &directory[at + 46..at + 46 + name_len];
+        let mut entry = Entry {
+            name: String::from_utf8_lossy(name).into_owned(),
+            method: u16_at(&directory, at + 10),
+            crc: u32_at(&directory, at + 16),
+            compressed_size: u32_at(&directory, at + 20) as u64,
+            size: u32_at(&directory, at + 24) as u64,
+            offset: u32_at(&directory, at + 42) as u64,
+        };
+
+        // fields too big for 32 bits are u32::MAX, with the real values in a zip64 extra
+        // field, in this order, only for those that overflowed
+        let mut extra = &directory[at + 46 + name_len..at + 46 + name_len + extra_len];
+        while extra.len() >= 4 {
+            let (id, field_len) = (u16_at(extra, 0), u16_at(extra, 2) as usize);
+            let field = &extra[4..(4 + field_len).min(extra.len())];
+            if id == ZIP64_EXTRA {
+                let mut values = field.chunks_exact(8).map(|v| u64_at(v, 0));
+                for value in [
+                    &mut entry.size,
+                    &mut entry.compressed_size,
+                    &mut entry.offset,
+                ] {
+                    if *value == u32::MAX as u64 {
+                        *value = values.next().ok_or_else(truncated)?;
+                    }
+                }
+            }
+            extra = &extra[(4 + field_len).min(extra.len())..];
+        }
+        entries.push(entry);
+        at = next;
+    }
+    Ok(entries)
+}
+
+/// The uncompressed contents of `entry`, checked against its CRC
+pub(crate) fn read_entry(reader: &mut (impl Read + Seek), entry: &Entry) -> io::Result<Vec<u8>> {
+    let mut header = [0u8; 30];
+    reader.seek(SeekFrom::Start(entry.offset))?;
+    reader.read_exact(&mut header)?;
+    if u32_at(&header, 0) != LOCAL_HEADER {
+        return Err(invalid_data(format!("Bad zip header for {}.", entry.name)));
+    }
+    // the local name and extra field can differ in length from the directory's
+    let skip = u16_at(&header, 26) as i64 + u16_at(&header, 28) as i64;
+    reader.seek(SeekFrom::Current(skip))?;
+
+    let mut compressed = Vec::new();
+    reader
+        .by_ref()
+        .take(entry.compressed_size)
+        .read_to_end(&mut compressed)?;
+    if compressed.len() as u64 != entry.compressed_size {
+        return Err(truncated());
+    }
+    let contents = match entry.method {
+        STORED => compressed,
+        DEFLATED => inflate(&compressed, entry.size as usize)?,
+        method => {
+            return Err(invalid_data(format!(
+                "Unsupported zip compression method {} for {}.",
+                method, entry.name
+            )))
+        }
+    };
+    if contents.len() as u64 != entry.size || crc32(&contents) != entry.crc {
+        return Err(invalid_data(format!("Corrupt zip member {}.", entry.name)));
+    }
+    Ok(contents)
+}
+
+/// Write `members` as an uncompressed zip archive, as `numpy.savez` does
+pub(crate) fn write_stored<'a>(
+    mut writer: impl Write,
+    members: impl IntoIterator<Item = (&'a str, Vec<u8>)>,
+) -> io::Result<()> {
+    let mut directory = Vec::new();
+    let mut offset = 0u64;
+    let mut count = 0u64;
+    for (name, contents) in members {
+        let crc = crc32(&contents);
+        let size = contents.len() as u64;
+        let name = name.as_bytes();
+        let big = size >= u32::MAX as u64 || offset >= u32::MAX as u64;
+        let (size32, offset32) = match big {
+            true => (u32::MAX, u32::MAX),
+            false => (size as u32, offset as u32),
+        };
+        // version 4.5 is the first with zip64
+        let version: u16 = match big {
+            true => 45,
+            false => 20,
+        };
+
+        let mut local_extra = Vec::new();
+        let mut central_extra = Vec::new();
+        if big {
+            local_extra.extend(ZIP64_EXTRA.to_le_bytes());
+            local_extra.extend(16u16.to_le_bytes());
+            local_extra.extend(size.to_le_bytes());
+            local_extra.extend(size.to_le_bytes());
+            central_extra.extend(ZIP64_EXTRA.to_le_bytes());
+            central_extra.extend(24u16.to_le_bytes());
+            central_extra.extend(size.to_le_bytes());
+            central_extra.extend(size.to_le_bytes());
+            central_extra.extend(offset.to_le_bytes());
+        }
+
+        let mut local = Vec::with_capacity(30 + name.len() + local_extra.len());
+        local.extend(LOCAL_HEADER.to_le_bytes());
+        local.extend(version.to_le_bytes());
+        local.extend([0u8; 2]); // flags
+        local.extend(STORED.to_le_bytes());
+        local.extend([0, 0, 0x21, 0]); // 1980-01-01 00:00
+        local.extend(crc.to_le_bytes());
+        local.extend(size32.to_le_bytes());
+        local.extend(size32.to_le_bytes());
+        local.extend((name.len() as u16).to_le_bytes());
+        local.extend((local_extra.len() as u16).to_le_bytes());
+        local.extend(name);
+        local.extend(&local_extra);
+        writer.write_all(&local)?;
+        writer.write_all(&contents)?;
+
+        directory.extend(CENTRAL_HEADER.to_le_bytes());
+        directory.extend(version.to_le_bytes());
+        directory.extend(local[4..28].iter());
+        directory.extend((central_extra.len() as u16).to_le_bytes());
+        directory.extend([0u8; 6]); // comment length, disk, internal attributes
+        directory.extend([0u8; 4]); // external attributes
+        directory.extend(offset32.to_le_bytes());
+        directory.extend(name);
+        directory.extend(&central_extra);
+
+        offset += local.len() as u64 + size;
+        count += 1;
+    }
+    writer.write_all(&directory)?;
+
+    let directory_len = directory.len() as u64;
+    let big = count >= u16::MAX as u64 || offset >= u32::MAX as u64;
+    if big {
+        let mut record = Vec::with_capacity(56 + 20);
+        record.extend(ZIP64_END_OF_DIRECTORY.to_le_bytes());
+        record.extend(44u64.to_le_bytes());
+        record.extend(45u16.to_le_bytes());
+        record.extend(45u16.to_le_bytes());
+        record.extend([0u8; 8]); // this disk, directory disk
+        record.extend(count.to_le_bytes());
+        record.extend(count.to_le_bytes());
+        record.extend(directory_len.to_le_bytes());
+        record.extend(offset.to_le_bytes());
+        record.extend(ZIP64_LOCATOR.to_le_bytes());
+        record.extend(0u32.to_le_bytes());
+        record.extend((offset + directory_len).to_le_bytes());
+        record.extend(1u32.to_le_bytes());
+        writer.write_all(&record)?;
+    }
+    let (count16, offset32) = match big {
+        true => (u16::MAX, u32::MAX),
+        false => (count as u16, offset as u32),
+    };
+    let mut end = Vec::with_capacity(22);
+    end.extend(END_OF_DIRECTORY.to_le_bytes());
+    end.extend([0u8; 4]); // this disk, directory disk
+    end.extend(count16.to_le_bytes());
+    end.extend(count16.to_le_bytes());
+    end.extend((directory_len.min(u32::MAX as u64) as u32).to_le_bytes());
+    end.extend(offset32.to_le_bytes());
+    end.extend([0u8; 2]); // comment length
+    writer.write_all(&end)
+}
+
+/// The CRC-32 zip (and gzip, and PNG) use
+pub(crate) fn crc32(bytes: &[u8]) -> u32 {
+    const TABLE: [u32; 256] = {
+        let mut table = [0u32; 256];
+        let mut i = 0;
+        while i < 256 {
+            let mut crc = i as u32;
+            let mut bit = 0;
+            while bit < 8 {
+                crc = match crc & 1 {
+                    1 => (crc >> 1) ^ 0xedb8_8320,
+                    _ => crc >> 1,
+                };
+                bit += 1;
+            }
+            table[i] = crc;
+            i += 1;
+        }
+        table
+    };
+    !bytes.iter().fold(!0u32, |crc, &byte| {
+        TABLE[((crc ^ byte as u32) & 0xff) as usize] ^ (crc >> 8)
+    })
+}
+
+/// Bits read least significant first, as deflate packs them
+struct Bits<'a> {
+    bytes: &'a [u8],
+    at: usize,
+    buffer: u32,
+    count: u32,
+}
+
+impl Bits<'_> {
+    fn bits(&mut self, n: u32) -> io::Result<u32> {
+        while self.count < n {
+            let byte = *self.bytes.get(self.at).ok_or_else(truncated)?;
+            self.buffer |= (byte as u32) << self.count;
+            self.at += 1;
+            self.count += 8;
+        }
+        let value = self.buffer & ((1u32 << n) - 1);
+        self.buffer >>= n;
+        self.count -= n;
+        Ok(value)
+    }
+}
+
+const MAX_BITS: usize = 15;
+
+/// A canonical Huffman code: how many codes there are of each length, and the symbols in
+/// code order
+struct Huffman {
+    counts: [u16; MAX_BITS + 1],
+    symbols: Vec<u16>,
+}
+
+impl Huffman {
+    fn new(lengths: &[u8]) -> Huffman {
+        let mut counts = [0u16; MAX_BITS + 1];
+        for &len in lengths {
+            counts[len as usize] += 1;
+        }
+        counts[0] = 0;
+        let mut offsets = [0u16; MAX_BITS + 2];
+        for len in 1..=MAX_BITS {
+            offsets[len + 1] = offsets[len] + counts[len];
+        }
+        let mut symbols = vec![0u16; offsets[MAX_BITS + 1] as usize];
+        for (symbol, &len) in lengths.iter().enumerate() {
+            if len != 0 {
+                symbols[offsets[len as usize] as usize] = symbol as u16;
+                offsets[len as usize] += 1;
+            }
+        }
+        Huffman { counts, symbols }
+    }
+
+    fn decode(&self, bits: &mut Bits) -> io::Result<u16> {
+        // codes of each length follow on from the last of the one shorter, doubled
+        let (mut code, mut first, mut index) = (0i32, 0i32, 0i32);
+        for len in 1..=MAX_BITS {
+            code |= bits.bits(1)? as i32;
+            let count = self.counts[len] as i32;
+            if code - first < count {
+                return Ok(self.symbols[(index + code - first) as usize]);
+            }
+            index += count;
+            first = (first + count) << 1;
+            code <<= 1;
+        }
+        Err(invalid_data("Bad deflate code.".into()))
+    }
+}
+
+const LENGTH_BASE: [u16; 29] = [
+    3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131,
+    163, 195, 227, 258,
+];
+const LENGTH_EXTRA: [u8; 29] = [
+    0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0,
+];
+const DISTANCE_BASE: [u16; 30] = [
+    1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537,
+    2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577,
+];
+const DISTANCE_EXTRA: [u8; 30] = [
+    0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13,
+    13,
+];
+/// The order code length code lengths are sent in
+const CODE_LENGTH_ORDER: [usize; 19] = [
+    16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15,
+];
+
+/// Most a header's size is trusted for, as a multiple of the compressed length, when reserving
+/// room for the output up front; a member that expands more grows its buffer as it goes
+const INFLATE_RESERVE_RATIO: usize = 8;
+
+/// Decompress a raw deflate stream (RFC 1951). `size` is only a capacity hint, and a header
+/// can claim any, so it is capped at `INFLATE_RESERVE_RATIO` times the compressed length.
+pub(crate) fn inflate(bytes: &[u8], size: usize) -> io::Result<Vec<u8>> {
+    let mut bits = Bits {
+        bytes,
+        at: 0,
+        buffer: 0,
+        count: 0,
+    };
+    let mut out = Vec::with_capacity(size.min(bytes.len().saturating_mul(INFLATE_RESERVE_RATIO)));
+    loop {
+        let last = bits.bits(1)? == 1;
+        match bits.bits(2)? {
+            0 => {
+                // stored: byte aligned, a length and its complement, then the bytes
+                bits.buffer = 0;
+                bits.count = 0;
+                let header = bits.bytes.get(bits.at..bits.at + 4).ok_or_else(truncated)?;
+                let len = u16_at(header, 0);
+                if len != !u16_at(header, 2) {
+                    return Err(invalid_data("Bad stored deflate block.".into()));
+                }
+                bits.at += 4;
+                let block = bits
+                    .bytes
+                    .get(bits.at..bits.at + len as usize)
+                    .ok_or_else(truncated)?;
+                out.extend_from_slice(block);
+                bits.at += len as usize;
+            }
+            1 => {
+                let mut lengths = [8u8; 288];
+                lengths[144..256].fill(9);
+                lengths[256..280].fill(7);
+                inflate_block(
+                    &mut bits,
+                    &mut out,
+                    &Huffman::new(&lengths),
+                    &Huffman::new(&[5; 30]),
+                )?;
+            }
+            2 => {
+                let literals = bits.bits(5)? as usize + 257;
+                let distances = bits.bits(5)? as usize + 1;
+                let code_lengths = bits.bits(4)? as usize + 4;
+                let mut lengths = [0u8; 19];
+                for &symbol in &CODE_LENGTH_ORDER[..code_lengths] {
+                    lengths[symbol] = bits.bits(3)? as u8;
+                }
+                let code = Huffman::new(&lengths);
+
+                let mut lengths = vec![0u8; literals + distances];
+                let mut i = 0;
+                while i < lengths.len() {
+                    let (len, repeat) = match code.decode(&mut bits)? {
+                        symbol @ 0..=15 => (symbol as u8, 1),
+                        16 if i > 0 => (lengths[i - 1], 3 + bits.bits(2)? as usize),
+                        17 => (0, 3 + bits.bits(3)? as usize),
+                        18 => (0, 11 + bits.bits(7)? as usize),
+                        _ => return Err(invalid_data("Bad deflate code lengths.".into())),
+                    };
+                    let run = lengths
+                        .get_mut(i..i + repeat)
+                        .ok_or_else(|| invalid_data("Bad deflate code lengths.".into()))?;
+                    run.fill(len);
+                    i += repeat;
+                }
+                inflate_block(
+                    &mut bits,
+                    &mut out,
+                    &Huffman::new(&lengths[..literals]),
+                    &Huffman::new(&lengths[literals..]),
+                )?;
+            }
+            _ => return Err(invalid_data("Bad deflate block type.".into())),
+        }
+        if last {
+            return Ok(out);
+        }
+    }
+}
+
+/// One Huffman coded block, up to its end of block code
+fn inflate_block(
+    bits: &mut Bits,
+    out: &mut Vec<u8>,
+    literals: &Huffman,
+    distances: &Huffman,
+) -> io::Result<()> {
+    loop {
+        match literals.decode(bits)? as usize {
+            literal @ 0..=255 => out.push(literal as u8),
+            256 => return Ok(()),
+            symbol => {
+                let symbol = symbol - 257;
+                if symbol >= LENGTH_BASE.len() {
+                    return Err(invalid_data("Bad deflate length.".into()));
+                }
+                let len =
+                    LENGTH_BASE[symbol] as usize + bits.bits(LENGTH_EXTRA[symbol] as u32)? as usize;
+                let symbol = distances.decode(bits)? as usize;
+                if symbol >= DISTANCE_BASE.len() {
+                    return Err(invalid_data("Bad deflate distance.".into()));
+                }
+                let distance = DISTANCE_BASE[symbol] as usize
+                    + bits.bits(DISTANCE_EXTRA[symbol] as u32)? as usize;
+                if distance > out.len() {
+                    return Err(invalid_data("Bad deflate distance.".into()));
+                }
+                // the copy can overlap what it writes, so byte at a time
+                let start = out.len() - distance;
+                for i in 0..len {
+                    out.push(out[start + i]);
+                }
+            }
+        }
+    }
+}
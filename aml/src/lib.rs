@@ -1,6 +1,126 @@
+#![cfg_attr(feature = "portable-simd", feature(portable_simd))]
+
+#[cfg(feature = "parallel")]
+mod affinity;
+mod aligned;
+mod allocator;
+#[cfg(all(feature = "amx", target_arch = "x86_64", target_os = "linux"))]
+mod amx;
+mod autotune;
+#[cfg(feature = "backend-blas")]
+mod backend;
+mod blas1;
+mod blas2;
+mod blas3;
+mod blocking;
+mod cancel;
+mod compare;
+mod convert;
+mod cow;
+mod device;
+mod dgemm;
+mod dispatch;
+mod display;
+mod element;
+mod elementwise;
+mod error;
+#[cfg(feature = "gpu-cuda")]
+mod gpu_cuda;
+#[cfg(all(feature = "gpu-metal", target_os = "macos"))]
+mod gpu_metal;
+#[cfg(feature = "gpu-opencl")]
+mod gpu_opencl;
+#[cfg(feature = "gpu-wgpu")]
+mod gpu_wgpu;
+mod graph;
+mod handle;
+mod hgemm;
+mod i4;
+mod igemm;
+pub mod io;
+mod microkernel;
+mod mmap;
+#[cfg(feature = "nalgebra")]
+mod nalgebra_interop;
+#[cfg(feature = "ndarray")]
+mod ndarray_interop;
+mod norm;
+mod parallel;
+pub mod pool;
+mod reduce;
+mod sbgemm;
+#[cfg(feature = "serde")]
+mod serialize;
+mod sgemm;
+mod shape;
+mod shared;
+mod softmax;
+mod stream;
 mod tests;
+mod workspace;
 
-use half::f16;
+pub use aligned::{alloc_aligned_f32, try_alloc_aligned_f32, AlignedBuffer};
+pub use allocator::{SystemAllocator, TensorAllocator};
+pub use autotune::{
+    autotune, autotune_enabled, load_autotune, save_autotune, set_autotune, TuneConfig,
+};
+#[cfg(feature = "backend-blas")]
+pub use backend::{backend, set_backend, Backend};
+pub use blas1::{
+    daxpy, ddot, dsdot, sasum, saxpy, sdot, snrm2, sscal, try_daxpy, try_ddot, try_dsdot,
+    try_saxpy, try_sdot,
+};
+pub use blas2::{sgemv, sgemv_with, sger, try_sgemv, try_sgemv_with, try_sger};
+pub use blas3::{ssyrk, strmm, strsm, try_ssyrk, try_strmm, try_strsm, Diag, Side, Uplo};
+pub use blocking::{
+    block_sizes, cache_info, set_block_sizes, try_set_block_sizes, BlockSizes, CacheInfo,
+};
+pub use cancel::CancelToken;
+pub use compare::{allclose, Mismatch, MismatchReport};
+pub use convert::{
+    convert, dequantize_i8, quantize_i8, try_convert, try_dequantize_i8, try_quantize_i8,
+};
+pub use cow::CowTensor;
+pub use device::{
+    device_sgemm, device_sgemm_with, try_device_sgemm, try_device_sgemm_with, Device, DeviceTensor,
+};
+pub use dgemm::{dgemm, dgemm_with, try_dgemm, try_dgemm_with};
+pub use element::{Element, Pod};
+pub use elementwise::{
+    add, add_scalar, div, div_scalar, fma, mul, mul_scalar, sub, sub_scalar, try_add, try_div,
+    try_fma, try_mul, try_sub,
+};
+pub use error::AmlError;
+pub use graph::Expr;
+use half::slice::HalfFloatSliceExt;
+use half::{bf16, f16};
+pub use handle::{sgemm_async, GemmHandle};
+pub use hgemm::{hgemm, hgemm_with, try_hgemm, try_hgemm_with};
+pub use igemm::{igemm, igemm_with, try_igemm, try_igemm_with};
+pub use mmap::MappedTensor;
+pub use norm::{layer_norm, rms_norm, try_layer_norm, try_rms_norm};
+pub use parallel::AmlContext;
+pub use reduce::{
+    argmax, argmax_axis, max, max_axis, mean, mean_axis, min, min_axis, sum, sum_axis,
+    try_argmax_axis, try_max_axis, try_mean_axis, try_min_axis, try_sum_axis,
+};
+pub use sbgemm::{sbgemm, sbgemm_with, try_sbgemm, try_sbgemm_with};
+pub use sgemm::{
+    pack_b, sgemm, sgemm_batched, sgemm_cancellable, sgemm_fused, sgemm_in, sgemm_prepacked,
+    sgemm_prepacked_in, sgemm_prepacked_with, sgemm_with, sgemm_with_progress, try_pack_b,
+    try_sgemm, try_sgemm_batched, try_sgemm_fused, try_sgemm_in, try_sgemm_prepacked,
+    try_sgemm_prepacked_in, try_sgemm_prepacked_with, try_sgemm_with, try_sgemm_with_progress,
+    Activation, Epilogue, Gemm, PackedB,
+};
+pub use shape::Shape;
+pub use shared::SharedTensor;
+pub use softmax::{
+    sgemm_softmax, softmax, softmax_in_place, try_sgemm_softmax, try_softmax_in_place,
+};
+use std::borrow::Cow;
+use std::ops::{Index, IndexMut, Range};
+pub use stream::{sgemm_streamed, MatrixReader, MatrixSource};
+pub use workspace::Workspace;
 
 /// Compressed representation of f32/f16 tensor in 4 bits.
 ///
@@ -14,7 +134,7 @@ pub struct I4Tensor<'a> {
     pub nibbles: &'a [i8],
     /// number of i4 values per scale/zero value value
     pub block_size: usize,
-    pub shape: Vec<usize>,
+    pub shape: Shape,
 }
 
 impl I4Tensor<'_> {
@@ -23,198 +143,2141 @@ impl I4Tensor<'_> {
         scales: &'a [f16],
         zeros: &'a [i8],
         nibbles: &'a [i8],
-        shape: Vec<usize>,
+        shape: impl Into<Shape>,
     ) -> I4Tensor<'a> {
-        assert!(scales.len() == zeros.len() * 2);
-        assert!(shape.iter().fold(1 as usize, |a, v| a * v) == nibbles.len() * 2);
+        I4Tensor::try_new(scales, zeros, nibbles, shape).unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    /// Fallible version of `new`
+    pub fn try_new<'a>(
+        scales: &'a [f16],
+        zeros: &'a [i8],
+        nibbles: &'a [i8],
+        shape: impl Into<Shape>,
+    ) -> Result<I4Tensor<'a>, AmlError> {
+        let shape: Shape = shape.into();
+        if zeros.len() != scales.len().div_ceil(2) {
+            return Err(AmlError::ScalesZerosMismatch {
+                scales: scales.len(),
+                zeros: zeros.len(),
+            });
+        }
+        let n_elements = shape.iter().product::<usize>();
+        if nibbles.len() != n_elements.div_ceil(2) {
+            return Err(AmlError::SizeMismatch {
+                expected: n_elements,
+                found: nibbles.len() * 2,
+            });
+        }
+        if scales.is_empty() || n_elements % scales.len() != 0 {
+            return Err(AmlError::UnevenBlocks {
+                values: n_elements,
+                blocks: scales.len(),
+            });
+        }
 
-        let block_size = nibbles.len() * 2 / scales.len();
+        let block_size = n_elements / scales.len();
 
-        I4Tensor {
+        Ok(I4Tensor {
             scales,
             zeros,
             nibbles,
             block_size,
             shape,
-        }
+        })
     }
 
     /// Get row of I4Tensor (m, n) --> I4Tensor (n, ) without copying.
-    pub fn get_row(self: &Self, i: usize) -> I4Tensor {
-        assert!(self.shape.len() == 2);
+    ///
+    /// Rows must start on a byte boundary of both `nibbles` and `zeros`, i.e. `n` is even and
+    /// each row holds a whole, even number of blocks. The kernels index rows by flat offset
+    /// instead, so they have no such restriction.
+    pub fn get_row(&self, i: usize) -> I4Tensor<'_> {
+        self.try_get_row(i).unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    /// Fallible version of `get_row`
+    pub fn try_get_row(&self, i: usize) -> Result<I4Tensor<'_>, AmlError> {
+        check_rank("b", &self.shape, 2)?;
+        if i >= self.shape[0] {
+            return Err(AmlError::IndexOutOfBounds {
+                index: vec![i],
+                shape: self.shape.to_vec(),
+            });
+        }
 
         let n = self.shape[1];
+        if self.block_size == 0 || !n.is_multiple_of(2 * self.block_size) {
+            return Err(AmlError::UnalignedRows {
+                n,
+                block_size: self.block_size,
+            });
+        }
         let blocks = n / self.block_size;
 
-        I4Tensor::new(
+        I4Tensor::try_new(
             &self.scales[i * blocks..(i + 1) * blocks],
             &self.zeros[(i * blocks) / 2..((i + 1) * blocks) / 2],
             &self.nibbles[(i * n) / 2..((i + 1) * n) / 2],
             vec![n],
         )
     }
+
+    /// Signed 4 bit value at flat index `idx`. Even indices are the high nibble.
+    fn nibble(&self, idx: usize) -> i8 {
+        let byte = self.nibbles[idx / 2];
+        match idx % 2 {
+            0 => byte >> 4,
+            _ => (byte << 4) >> 4,
+        }
+    }
+
+    /// Zero point of `block`, packed like the values.
+    fn zero(&self, block: usize) -> i8 {
+        let byte = self.zeros[block / 2];
+        match block % 2 {
+            0 => byte >> 4,
+            _ => (byte << 4) >> 4,
+        }
+    }
+
+    /// Unpack and rescale `out.len()` values starting at flat index `start`.
+    ///
+    /// `start` need not sit on a block or byte boundary, which is what lets rows of any length
+    /// be read straight out of the packed buffers.
+    fn dequantize_range_into(&self, start: usize, out: &mut [f32]) {
+        let end = start + out.len();
+        let mut idx = start;
+        while idx < end {
+            let block = idx / self.block_size;
+            let block_end = end.min((block + 1) * self.block_size);
+            let scale = self.scales[block].to_f32();
+            let zero = self.zero(block);
+
+            for i in idx..block_end {
+                out[i - start] = scale * (self.nibble(i) - zero) as f32;
+            }
+            idx = block_end;
+        }
+    }
 }
 
-pub struct F16Tensor {
-    pub values: Vec<f16>,
-    pub shape: Vec<usize>,
+/// Owned counterpart of `I4Tensor`, for when the packed buffers have to outlive their source.
+///
+/// The kernels take the borrowed form; `view` hands one out without copying.
+pub struct I4TensorOwned {
+    pub scales: Vec<f16>,
+    pub zeros: Vec<i8>,
+    pub nibbles: Vec<i8>,
+    pub block_size: usize,
+    pub shape: Shape,
 }
 
-impl F16Tensor {
-    pub fn new(values: Vec<f16>, shape: Vec<usize>) -> F16Tensor {
-        assert!(values.len() == shape.iter().fold(1 as usize, |a, v| a * v));
+impl I4TensorOwned {
+    pub fn from_vec(
+        scales: Vec<f16>,
+        zeros: Vec<i8>,
+        nibbles: Vec<i8>,
+        shape: impl Into<Shape>,
+    ) -> I4TensorOwned {
+        I4TensorOwned::try_from_vec(scales, zeros, nibbles, shape)
+            .unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    /// Fallible version of `from_vec`
+    pub fn try_from_vec(
+        scales: Vec<f16>,
+        zeros: Vec<i8>,
+        nibbles: Vec<i8>,
+        shape: impl Into<Shape>,
+    ) -> Result<I4TensorOwned, AmlError> {
+        let shape: Shape = shape.into();
+        let block_size = I4Tensor::try_new(&scales, &zeros, &nibbles, shape.clone())?.block_size;
+
+        Ok(I4TensorOwned {
+            scales,
+            zeros,
+            nibbles,
+            block_size,
+            shape,
+        })
+    }
+
+    /// All values zero, in blocks of `block_size`.
+    pub fn zeros(shape: impl Into<Shape>, block_size: usize) -> I4TensorOwned {
+        let shape: Shape = shape.into();
+        let n_elements = shape.iter().product::<usize>();
+        let n_blocks = n_elements / block_size.max(1);
 
-        F16Tensor { values, shape }
+        I4TensorOwned::from_vec(
+            vec![f16::from_f32(1f32); n_blocks],
+            vec![0; n_blocks.div_ceil(2)],
+            vec![0; n_elements.div_ceil(2)],
+            shape,
+        )
     }
 
-    pub fn zeros(shape: Vec<usize>) -> F16Tensor {
-        let n_elements = shape.iter().fold(1 as usize, |a, v| a * v);
-        let values: Vec<f16> = vec![f16::from_f32(0f32); n_elements];
+    /// Random nibbles and zero points with scales in (0, 1], reproducible from `seed`.
+    pub fn random(shape: impl Into<Shape>, block_size: usize, seed: u64) -> I4TensorOwned {
+        let shape: Shape = shape.into();
+        let n_elements = shape.iter().product::<usize>();
+        let n_blocks = n_elements / block_size.max(1);
+        let mut state = seed;
 
-        F16Tensor { values, shape }
+        let scales = (0..n_blocks)
+            .map(|_| f16::from_f32((splitmix64(&mut state) % 64 + 1) as f32 / 64f32))
+            .collect();
+        let zeros = (0..n_blocks.div_ceil(2))
+            .map(|_| splitmix64(&mut state) as i8)
+            .collect();
+        let nibbles = (0..n_elements.div_ceil(2))
+            .map(|_| splitmix64(&mut state) as i8)
+            .collect();
+
+        I4TensorOwned::from_vec(scales, zeros, nibbles, shape)
     }
 
-    pub fn reshape(self: &mut Self, new_shape: Vec<usize>) {
-        assert!(self.values.len() == new_shape.iter().fold(1 as usize, |a, v| a * v));
-        self.shape = new_shape;
+    /// Borrow as an `I4Tensor` without copying.
+    pub fn view(&self) -> I4Tensor<'_> {
+        I4Tensor {
+            scales: &self.scales,
+            zeros: &self.zeros,
+            nibbles: &self.nibbles,
+            block_size: self.block_size,
+            shape: self.shape.clone(),
+        }
     }
 }
 
-/// Dot product between an F16 Tensor and a I4 tensor
+/// Small, fast PRNG for reproducible test and benchmark data. Not for anything security related.
+fn splitmix64(state: &mut u64) -> u64 {
+    *state = state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+    let mut z = *state;
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    z ^ (z >> 31)
+}
+
+/// `splitmix64` as a uniform f64 in [0, 1), from its top 53 bits.
+fn uniform(state: &mut u64) -> f64 {
+    (splitmix64(state) >> 11) as f64 / (1u64 << 53) as f64
+}
+
+/// Order of the values of a dense tensor in memory.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Layout {
+    /// The last index varies fastest: C order, and numpy's default
+    #[default]
+    RowMajor,
+    /// The first index varies fastest: Fortran order, as in LAPACK and numpy with `order='F'`
+    ColMajor,
+}
+
+/// Dense tensor of any `Element` type, row-major unless `layout` says otherwise.
 ///
-/// Expects `a` (n, ) and `b` (n, )
-pub fn qdot(a: &F16Tensor, b: &I4Tensor) -> f32 {
-    assert!(a.shape.len() == 1);
-    assert!(b.shape.len() == 1);
-    assert!(a.shape[0] == b.shape[0]);
+/// The kernels name the concrete aliases: `F16Tensor`, `BF16Tensor`, `F32Tensor`, `F64Tensor`.
+/// A column-major matrix holds the same values as the row-major transpose of its shape, so the
+/// GEMMs read one by flipping its transpose flag rather than copying it.
+///
+/// Value `[i, j, ..]` sits at `i * strides[0] + j * strides[1] + ..` in `values`, so any rank
+/// and any non-overlapping arrangement can be described. The GEMMs run in place on matrices
+/// whose rows (columns, if column-major) are contiguous, with any leading dimension; other
+/// strides are copied into that form first.
+pub struct Tensor<T: Element> {
+    pub values: Vec<T>,
+    pub shape: Shape,
+    pub layout: Layout,
+    /// Values between neighbours along each axis of `shape`
+    pub strides: Vec<usize>,
+}
 
-    let mut acc = 0f32;
+/// f16 activations, the `a` operand of the quantized kernels.
+pub type F16Tensor = Tensor<f16>;
+/// Dense bfloat16 tensor, the input type of `sbgemm`.
+pub type BF16Tensor = Tensor<bf16>;
+/// Dense f32 tensor, used by the full precision BLAS routines.
+pub type F32Tensor = Tensor<f32>;
+/// Dense f64 tensor, used by `dgemm`.
+pub type F64Tensor = Tensor<f64>;
+
+impl<T: Element> Tensor<T> {
+    pub fn new(values: Vec<T>, shape: impl Into<Shape>) -> Tensor<T> {
+        Tensor::try_new(values, shape).unwrap_or_else(|e| panic!("{}", e))
+    }
 
-    let num_blocks = (b.nibbles.len() * 2) / b.block_size;
+    /// Fallible version of `new`
+    pub fn try_new(values: Vec<T>, shape: impl Into<Shape>) -> Result<Tensor<T>, AmlError> {
+        let shape: Shape = shape.into();
+        let n_elements = shape.iter().product::<usize>();
+        if values.len() != n_elements {
+            return Err(AmlError::SizeMismatch {
+                expected: n_elements,
+                found: values.len(),
+            });
+        }
 
-    for block in 0..num_blocks {
-        let scale = b.scales[block].to_f32();
-        let zero = match block % 2 {
-            0 => b.zeros[block / 2] >> 4,
-            _ => (b.zeros[block / 2] << 4) >> 4,
-        };
+        Ok(Tensor {
+            strides: dense_strides(&shape, Layout::RowMajor),
+            values,
+            shape,
+            layout: Layout::RowMajor,
+        })
+    }
 
-        let mut b_idx = 0;
-        let mut a_idx = 0;
-        loop {
-            if b_idx * 2 == b.block_size {
-                break;
+    /// A (rows, cols) matrix whose rows (columns, if column-major) start `ld` values apart, e.g.
+    /// a block of a larger matrix. `values` runs from the first value of the first row to the
+    /// last value of the last, `(rows - 1) * ld + cols` in all for row-major.
+    pub fn new_with_ld(
+        values: Vec<T>,
+        shape: impl Into<Shape>,
+        layout: Layout,
+        ld: usize,
+    ) -> Tensor<T> {
+        Tensor::try_new_with_ld(values, shape, layout, ld).unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    /// Fallible version of `new_with_ld`
+    pub fn try_new_with_ld(
+        values: Vec<T>,
+        shape: impl Into<Shape>,
+        layout: Layout,
+        ld: usize,
+    ) -> Result<Tensor<T>, AmlError> {
+        let shape: Shape = shape.into();
+        let strides = ld_strides(values.len(), &shape, layout, ld)?;
+
+        Ok(Tensor {
+            values,
+            shape,
+            layout,
+            strides,
+        })
+    }
+
+    /// A tensor of any rank whose value `[i, j, ..]` is `values[i * strides[0] + j * strides[1]
+    /// + ..]`, e.g. every other column of a matrix, or a permutation of the axes of another
+    /// tensor. No two indices may share a value, and `values` must end at the last one.
+    ///
+    /// The layout is column-major when the first axis is the one with unit stride, so a matrix
+    /// stored by columns keeps the GEMM fast path.
+    pub fn new_with_strides(
+        values: Vec<T>,
+        shape: impl Into<Shape>,
+        strides: Vec<usize>,
+    ) -> Tensor<T> {
+        Tensor::try_new_with_strides(values, shape, strides).unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    /// Fallible version of `new_with_strides`
+    pub fn try_new_with_strides(
+        values: Vec<T>,
+        shape: impl Into<Shape>,
+        strides: Vec<usize>,
+    ) -> Result<Tensor<T>, AmlError> {
+        let shape: Shape = shape.into();
+        check_strides(values.len(), &shape, &strides)?;
+
+        Ok(Tensor {
+            values,
+            layout: strides_layout(&shape, &strides),
+            shape,
+            strides,
+        })
+    }
+
+    pub fn zeros(shape: impl Into<Shape>) -> Tensor<T> {
+        Tensor::full(shape, T::ZERO)
+    }
+
+    pub fn ones(shape: impl Into<Shape>) -> Tensor<T> {
+        Tensor::full(shape, T::ONE)
+    }
+
+    /// Every value `value`
+    pub fn full(shape: impl Into<Shape>, value: T) -> Tensor<T> {
+        let shape: Shape = shape.into();
+
+        Tensor {
+            values: vec![value; shape.numel()],
+            strides: dense_strides(&shape, Layout::RowMajor),
+            shape,
+            layout: Layout::RowMajor,
+        }
+    }
+
+    /// `zeros` with storage from `allocator`
+    pub fn zeros_in(shape: impl Into<Shape>, allocator: &impl TensorAllocator<T>) -> Tensor<T> {
+        Tensor::full_in(shape, T::ZERO, allocator)
+    }
+
+    /// Fallible version of `zeros_in`
+    pub fn try_zeros_in(
+        shape: impl Into<Shape>,
+        allocator: &impl TensorAllocator<T>,
+    ) -> Result<Tensor<T>, AmlError> {
+        Tensor::try_full_in(shape, T::ZERO, allocator)
+    }
+
+    /// `full` with storage from `allocator`
+    pub fn full_in(
+        shape: impl Into<Shape>,
+        value: T,
+        allocator: &impl TensorAllocator<T>,
+    ) -> Tensor<T> {
+        Tensor::try_full_in(shape, value, allocator).unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    /// Fallible version of `full_in`. Fails with `SizeMismatch` if the allocator hands back
+    /// the wrong number of values.
+    pub fn try_full_in(
+        shape: impl Into<Shape>,
+        value: T,
+        allocator: &impl TensorAllocator<T>,
+    ) -> Result<Tensor<T>, AmlError> {
+        let shape: Shape = shape.into();
+        let strides = dense_strides(&shape, Layout::RowMajor);
+        let mut values = allocator.allocate(shape.numel());
+        check_len(values.len(), &shape, &strides)?;
+        values.fill(value);
+
+        Ok(Tensor {
+            values,
+            shape,
+            layout: Layout::RowMajor,
+            strides,
+        })
+    }
+
+    /// Hand the storage back to `allocator`, e.g. the one it came from, for reuse.
+    pub fn release_in(self, allocator: &impl TensorAllocator<T>) {
+        allocator.release(self.values)
+    }
+
+    /// Values drawn uniformly from [0, 1), reproducible from `seed` on any machine.
+    pub fn rand_uniform(shape: impl Into<Shape>, seed: u64) -> Tensor<T> {
+        let shape: Shape = shape.into();
+        let mut state = seed;
+        let values = (0..shape.numel())
+            .map(|_| T::from_f64(uniform(&mut state)))
+            .collect();
+        Tensor::new(values, shape)
+    }
+
+    /// Values drawn from a normal distribution of `mean` and standard deviation `std`,
+    /// reproducible from `seed` on any machine.
+    pub fn rand_normal(shape: impl Into<Shape>, mean: f64, std: f64, seed: u64) -> Tensor<T> {
+        let shape: Shape = shape.into();
+        let mut state = seed;
+        let mut values = Vec::with_capacity(shape.numel() + 1);
+        while values.len() < shape.numel() {
+            // Box-Muller: two independent normals from two uniforms, the first kept off 0
+            let radius = (-2f64 * (1f64 - uniform(&mut state)).ln()).sqrt();
+            let angle = std::f64::consts::TAU * uniform(&mut state);
+            for z in [radius * angle.cos(), radius * angle.sin()] {
+                values.push(T::from_f64(mean + std * z));
             }
+        }
+        values.truncate(shape.numel());
+        Tensor::new(values, shape)
+    }
+
+    /// The (n, n) identity matrix
+    pub fn eye(n: usize) -> Tensor<T> {
+        let mut eye = Tensor::zeros(vec![n, n]);
+        for i in 0..n {
+            eye.values[i * n + i] = T::ONE;
+        }
+        eye
+    }
+
+    /// `0, 1, .., n - 1` as an (n,) tensor, rounded to `T`. `reshape` it for a matrix of
+    /// distinct values.
+    pub fn arange(n: usize) -> Tensor<T> {
+        let values = (0..n).map(|v| T::from_f64(v as f64)).collect();
+        Tensor::new(values, vec![n])
+    }
+
+    /// The same values read in `layout` order. Nothing moves, so this relabels the values
+    /// rather than transposing them. Panics unless the tensor is contiguous; build others with
+    /// `new_with_ld` or `new_with_strides` instead.
+    pub fn with_layout(self, layout: Layout) -> Tensor<T> {
+        self.check_contiguous().unwrap_or_else(|e| panic!("{}", e));
+        Tensor {
+            strides: dense_strides(&self.shape, layout),
+            layout,
+            ..self
+        }
+    }
+
+    /// Whether `values` holds the tensor back to back in `layout` order, without gaps
+    pub fn is_contiguous(&self) -> bool {
+        self.as_tensor_ref().is_contiguous()
+    }
+
+    /// Whether the first value sits at a multiple of `align` bytes, as `TensorRef::is_aligned`
+    pub fn is_aligned(&self, align: usize) -> bool {
+        self.as_tensor_ref().is_aligned(align)
+    }
+
+    /// Leading dimension of a matrix, as `TensorRef::ld`
+    pub fn ld(&self) -> usize {
+        self.as_tensor_ref().ld()
+    }
+
+    /// Copy into a contiguous tensor of the same shape and layout
+    pub fn to_contiguous(&self) -> Tensor<T> {
+        self.as_tensor_ref().to_contiguous()
+    }
+
+    pub(crate) fn check_contiguous(&self) -> Result<(), AmlError> {
+        self.as_tensor_ref().check_contiguous()
+    }
+
+    /// `UnsupportedStrides` unless a kernel can write this tensor in place
+    pub(crate) fn check_blas(&self, operand: &'static str) -> Result<(), AmlError> {
+        check_blas(operand, &self.shape, &self.strides, self.layout)
+    }
+
+    pub(crate) fn blas_copy(&self) -> Option<Tensor<T>> {
+        self.as_tensor_ref().blas_copy()
+    }
+
+    pub(crate) fn stored_transpose(&self, transpose: bool) -> bool {
+        self.as_tensor_ref().stored_transpose(transpose)
+    }
+
+    pub(crate) fn stored_shape(&self) -> Vec<usize> {
+        self.as_tensor_ref().stored_shape()
+    }
+
+    /// Borrow rows `rows` and columns `cols` of a matrix without copying: the window keeps this
+    /// tensor's layout and strides, so e.g. `a.view(0..64, 64..128)` is the second 64 x 64 tile
+    /// of the first block row. Any GEMM of the `sgemm` family reads it like a tensor.
+    pub fn view(&self, rows: Range<usize>, cols: Range<usize>) -> TensorRef<'_, T> {
+        self.try_view(rows, cols)
+            .unwrap_or_else(|e| panic!("{}", e))
+    }
 
-            let b_nibble = b.nibbles[(block * b.block_size + b_idx) / 2];
-            let b1 = scale * ((b_nibble >> 4) - zero) as f32;
-            let b2 = scale * (((b_nibble << 4) >> 4) - zero) as f32;
+    /// Fallible version of `view`
+    pub fn try_view(
+        &self,
+        rows: Range<usize>,
+        cols: Range<usize>,
+    ) -> Result<TensorRef<'_, T>, AmlError> {
+        self.as_tensor_ref().try_view(rows, cols)
+    }
+
+    /// Each row of a matrix in turn, as a 1-D view of `shape[1]` values, in either layout.
+    pub fn rows(&self) -> impl Iterator<Item = TensorRef<'_, T>> {
+        self.try_rows().unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    /// Fallible version of `rows`
+    pub fn try_rows(&self) -> Result<impl Iterator<Item = TensorRef<'_, T>>, AmlError> {
+        self.as_tensor_ref().try_rows()
+    }
+
+    /// Each column of a matrix in turn, as a 1-D view of `shape[0]` values, in either layout.
+    pub fn cols(&self) -> impl Iterator<Item = TensorRef<'_, T>> {
+        self.try_cols().unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    /// Fallible version of `cols`
+    pub fn try_cols(&self) -> Result<impl Iterator<Item = TensorRef<'_, T>>, AmlError> {
+        self.as_tensor_ref().try_cols()
+    }
+
+    /// Blocks of `block` whole rows of a matrix, the last one shorter if `block` does not divide
+    /// them, the way the kernels hand rows out to threads. `block` must be at least 1.
+    pub fn row_chunks(&self, block: usize) -> impl Iterator<Item = TensorRef<'_, T>> {
+        self.try_row_chunks(block)
+            .unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    /// Fallible version of `row_chunks`
+    pub fn try_row_chunks(
+        &self,
+        block: usize,
+    ) -> Result<impl Iterator<Item = TensorRef<'_, T>>, AmlError> {
+        self.as_tensor_ref().try_row_chunks(block)
+    }
+
+    /// `view` as an output, to write one block of `c` in place
+    pub fn window_mut(&mut self, rows: Range<usize>, cols: Range<usize>) -> TensorMut<'_, T> {
+        self.try_window_mut(rows, cols)
+            .unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    /// Fallible version of `window_mut`
+    pub fn try_window_mut(
+        &mut self,
+        rows: Range<usize>,
+        cols: Range<usize>,
+    ) -> Result<TensorMut<'_, T>, AmlError> {
+        let (range, shape) = window(&self.shape, &self.strides, rows, cols)?;
+        Ok(TensorMut {
+            values: &mut self.values[range],
+            shape,
+            layout: self.layout,
+            strides: self.strides.clone(),
+        })
+    }
+
+    pub fn reshape(&mut self, new_shape: impl Into<Shape>) {
+        self.try_reshape(new_shape)
+            .unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    /// Fallible version of `reshape`. The shape is left untouched on error, which includes any
+    /// tensor that is not contiguous.
+    pub fn try_reshape(&mut self, new_shape: impl Into<Shape>) -> Result<(), AmlError> {
+        let TensorRef { shape, strides, .. } = self.as_tensor_ref().try_reshape(new_shape)?;
+        (self.shape, self.strides) = (shape, strides);
+        Ok(())
+    }
+
+    /// Drop `axis`, which must have length 1. Only the shape changes, so any strides work.
+    pub fn squeeze(&mut self, axis: usize) {
+        self.try_squeeze(axis).unwrap_or_else(|e| panic!("{}", e))
+    }
 
-            let a1 = a.values[block * b.block_size + a_idx].to_f32();
-            let a2 = a.values[block * b.block_size + a_idx + 1].to_f32();
+    /// Fallible version of `squeeze`. The shape is left untouched on error.
+    pub fn try_squeeze(&mut self, axis: usize) -> Result<(), AmlError> {
+        (self.shape, self.strides) = squeezed(&self.shape, &self.strides, axis)?;
+        Ok(())
+    }
+
+    /// Insert an axis of length 1 before `axis`, or after the last if `axis` is the rank.
+    pub fn unsqueeze(&mut self, axis: usize) {
+        self.try_unsqueeze(axis).unwrap_or_else(|e| panic!("{}", e))
+    }
 
-            acc += b1 * a1 + b2 * a2;
+    /// Fallible version of `unsqueeze`. The shape is left untouched on error.
+    pub fn try_unsqueeze(&mut self, axis: usize) -> Result<(), AmlError> {
+        (self.shape, self.strides) = unsqueezed(&self.shape, &self.strides, self.layout, axis)?;
+        Ok(())
+    }
 
-            b_idx += 1;
-            a_idx += 2;
+    /// Borrow as a `TensorMut` output without copying.
+    pub fn view_mut(&mut self) -> TensorMut<'_, T> {
+        TensorMut {
+            values: &mut self.values,
+            shape: self.shape.clone(),
+            layout: self.layout,
+            strides: self.strides.clone(),
         }
     }
 
-    acc
+    pub(crate) fn dense(&self) -> Cow<'_, [T]> {
+        self.as_tensor_ref().dense()
+    }
 }
 
-/// Dot product between `a` (F16) and each row of `b` (I4)
-///
-/// I4 (m, n) @ F16 (n,) --> F16 (m,)
-pub fn qgemv(a: &F16Tensor, b: &I4Tensor) -> F16Tensor {
-    assert!(a.shape.len() == 1);
-    assert!(b.shape.len() == 2);
-    assert!(
-        a.shape[0] == b.shape[1],
-        "a is not the same length as b rows"
-    );
+/// Borrowed matrix over values owned elsewhere, e.g. a window of a larger `Tensor` made by
+/// `Tensor::view`. The `sgemm` family takes one anywhere it takes a `&F32Tensor`.
+#[derive(Clone)]
+pub struct TensorRef<'a, T: Element> {
+    pub values: &'a [T],
+    pub shape: Shape,
+    pub layout: Layout,
+    /// Values between neighbours along each axis, as `Tensor::strides`
+    pub strides: Vec<usize>,
+}
 
-    let mut out = Vec::with_capacity(b.shape[0]);
+/// Borrowed f32 matrix, the operand type of the `sgemm` family.
+pub type F32TensorRef<'a> = TensorRef<'a, f32>;
 
-    for row_idx in 0..b.shape[0] {
-        let b_row = b.get_row(row_idx);
-        let b_row_dot = qdot(&a, &b_row);
-        out.push(f16::from_f32(b_row_dot));
+impl<'a, T: Element> TensorRef<'a, T> {
+    /// A matrix inside a larger buffer, as `Tensor::new_with_ld`
+    pub fn new_with_ld(
+        values: &'a [T],
+        shape: impl Into<Shape>,
+        layout: Layout,
+        ld: usize,
+    ) -> TensorRef<'a, T> {
+        TensorRef::try_new_with_ld(values, shape, layout, ld).unwrap_or_else(|e| panic!("{}", e))
     }
 
-    F16Tensor::new(out, vec![b.shape[0]])
-}
+    /// Fallible version of `new_with_ld`
+    pub fn try_new_with_ld(
+        values: &'a [T],
+        shape: impl Into<Shape>,
+        layout: Layout,
+        ld: usize,
+    ) -> Result<TensorRef<'a, T>, AmlError> {
+        let shape: Shape = shape.into();
+        let strides = ld_strides(values.len(), &shape, layout, ld)?;
 
-/// Matrix Muliply between an `F16Tensor` and an `I4Tensor`. Result stored in `c` (F16)
-///
-/// I4(m, n) @ F16(k, n)**T --> F16(m, k)
-pub fn qgemm(a: &F16Tensor, a_transpose: bool, b: &I4Tensor, b_transpose: bool, c: &mut F16Tensor) {
-    assert!(
-        a.shape.len() == 2,
-        "`a` must have 2 dimensions. Found {}.",
-        a.shape.len()
-    );
-    assert!(
-        b.shape.len() == 2,
-        "`b` must have 2 dimensions. Found {}.",
-        b.shape.len()
-    );
+        Ok(TensorRef {
+            values,
+            shape,
+            layout,
+            strides,
+        })
+    }
 
-    let out_shape = vec![
-        match a_transpose {
-            true => a.shape[1],
-            false => a.shape[0],
-        },
-        match b_transpose {
-            true => b.shape[0],
-            false => b.shape[1],
-        },
-    ];
+    /// A strided tensor over borrowed values, as `Tensor::new_with_strides`
+    pub fn new_with_strides(
+        values: &'a [T],
+        shape: impl Into<Shape>,
+        strides: Vec<usize>,
+    ) -> TensorRef<'a, T> {
+        TensorRef::try_new_with_strides(values, shape, strides).unwrap_or_else(|e| panic!("{}", e))
+    }
 
-    if a_transpose && b_transpose {
-        assert!(
-            a.shape[0] == b.shape[1],
-            "Inner dimensions {}, {} do not match",
-            a.shape[0],
-            b.shape[1]
-        );
-    } else if a_transpose {
-        assert!(
-            a.shape[0] == b.shape[0],
-            "Inner dimensions {}, {} do not match",
-            a.shape[0],
-            b.shape[0]
-        );
-    } else if b_transpose {
-        assert!(
-            a.shape[1] == b.shape[1],
-            "Inner dimensions {}, {} do not match",
-            a.shape[1],
-            b.shape[1]
-        );
-    } else {
-        assert!(
-            a.shape[1] == b.shape[0],
-            "Inner dimensions {}, {} do not match",
-            a.shape[1],
-            b.shape[0]
-        );
+    /// Fallible version of `new_with_strides`
+    pub fn try_new_with_strides(
+        values: &'a [T],
+        shape: impl Into<Shape>,
+        strides: Vec<usize>,
+    ) -> Result<TensorRef<'a, T>, AmlError> {
+        let shape: Shape = shape.into();
+        check_strides(values.len(), &shape, &strides)?;
+
+        Ok(TensorRef {
+            values,
+            layout: strides_layout(&shape, &strides),
+            shape,
+            strides,
+        })
     }
 
-    assert!(
-        out_shape == c.shape,
-        "`c` has the wrong shape. Expected {:?}, found {:?}.",
-        out_shape,
-        c.shape
-    );
+    /// The values stored in `bytes`, in native byte order, without copying them: e.g. a weight
+    /// matrix inside a memory-mapped model file. `bytes` must hold exactly `shape` values and
+    /// start on a multiple of their alignment, which a file mapping at a page boundary does for
+    /// any offset that is a multiple of the value size.
+    pub fn from_bytes(bytes: &'a [u8], shape: impl Into<Shape>) -> TensorRef<'a, T>
+    where
+        T: Pod,
+    {
+        TensorRef::try_from_bytes(bytes, shape).unwrap_or_else(|e| panic!("{}", e))
+    }
 
-    if a_transpose && b_transpose {
-        todo!();
-    } else if a_transpose {
-        todo!();
-    } else if b_transpose {
-        todo!();
-    } else {
-        todo!();
+    /// Fallible version of `from_bytes`
+    pub fn try_from_bytes(
+        bytes: &'a [u8],
+        shape: impl Into<Shape>,
+    ) -> Result<TensorRef<'a, T>, AmlError>
+    where
+        T: Pod,
+    {
+        let shape: Shape = shape.into();
+        let expected = shape.byte_len(std::mem::size_of::<T>())?;
+        if bytes.len() != expected {
+            return Err(AmlError::SizeMismatch {
+                expected,
+                found: bytes.len(),
+            });
+        }
+        let align = std::mem::align_of::<T>();
+        if bytes.as_ptr().align_offset(align) != 0 {
+            return Err(AmlError::MisalignedBytes { align });
+        }
+
+        // aligned, exactly `numel` values long, and any bit pattern is a valid `Pod` value
+        let values =
+            unsafe { std::slice::from_raw_parts(bytes.as_ptr().cast::<T>(), shape.numel()) };
+        Ok(TensorRef {
+            values,
+            strides: dense_strides(&shape, Layout::RowMajor),
+            shape,
+            layout: Layout::RowMajor,
+        })
+    }
+
+    /// A view whose strides may revisit values: a stride of 0 repeats one value along its axis
+    /// (broadcasting a row across a matrix, say), and strides shorter than the axes they step
+    /// over make overlapping windows, e.g. `[1, 1]` over a signal gives every length-`k` window
+    /// of it as a row. Value `[i, j, ..]` is read from `ptr.add(i * strides[0] + j * strides[1]
+    /// + ..)`, and is only ever read. The GEMMs copy such a view before multiplying by it.
+    ///
+    /// # Safety
+    ///
+    /// `ptr` must be non-null, aligned, and valid for reads of every offset the strides reach,
+    /// `1 + (shape[0] - 1) * strides[0] + ..` values in all, for the whole of `'a`. Nothing may
+    /// write those values during `'a`. `strides` must have one entry per axis of `shape`.
+    pub unsafe fn from_raw_parts_with_strides(
+        ptr: *const T,
+        shape: impl Into<Shape>,
+        strides: Vec<usize>,
+    ) -> TensorRef<'a, T> {
+        let shape: Shape = shape.into();
+        debug_assert!(strides.len() == shape.len());
+        let len = strided_len(&shape, &strides);
+
+        TensorRef {
+            values: std::slice::from_raw_parts(ptr, len),
+            layout: strides_layout(&shape, &strides),
+            shape,
+            strides,
+        }
+    }
+
+    /// A window of this window, as `Tensor::view`
+    pub fn view(&self, rows: Range<usize>, cols: Range<usize>) -> TensorRef<'a, T> {
+        self.try_view(rows, cols)
+            .unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    /// Fallible version of `view`
+    pub fn try_view(
+        &self,
+        rows: Range<usize>,
+        cols: Range<usize>,
+    ) -> Result<TensorRef<'a, T>, AmlError> {
+        let (range, shape) = window(&self.shape, &self.strides, rows, cols)?;
+        Ok(TensorRef {
+            values: &self.values[range],
+            shape,
+            layout: self.layout,
+            strides: self.strides.clone(),
+        })
+    }
+
+    /// Each row of a matrix, as `Tensor::rows`
+    pub fn rows(&self) -> impl Iterator<Item = TensorRef<'a, T>> {
+        self.try_rows().unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    /// Fallible version of `rows`
+    pub fn try_rows(&self) -> Result<impl Iterator<Item = TensorRef<'a, T>>, AmlError> {
+        check_rank("values", &self.shape, 2)?;
+        let (matrix, cols) = (self.clone(), self.shape[1]);
+        Ok((0..self.shape[0]).map(move |i| matrix.view(i..i + 1, 0..cols).squeeze(0)))
+    }
+
+    /// Each column of a matrix, as `Tensor::cols`
+    pub fn cols(&self) -> impl Iterator<Item = TensorRef<'a, T>> {
+        self.try_cols().unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    /// Fallible version of `cols`
+    pub fn try_cols(&self) -> Result<impl Iterator<Item = TensorRef<'a, T>>, AmlError> {
+        check_rank("values", &self.shape, 2)?;
+        let (matrix, rows) = (self.clone(), self.shape[0]);
+        Ok((0..self.shape[1]).map(move |j| matrix.view(0..rows, j..j + 1).squeeze(1)))
+    }
+
+    /// Blocks of whole rows of a matrix, as `Tensor::row_chunks`
+    pub fn row_chunks(&self, block: usize) -> impl Iterator<Item = TensorRef<'a, T>> {
+        self.try_row_chunks(block)
+            .unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    /// Fallible version of `row_chunks`
+    pub fn try_row_chunks(
+        &self,
+        block: usize,
+    ) -> Result<impl Iterator<Item = TensorRef<'a, T>>, AmlError> {
+        check_rank("values", &self.shape, 2)?;
+        if block == 0 {
+            return Err(AmlError::ZeroChunkSize);
+        }
+        let (matrix, [rows, cols]) = (self.clone(), [self.shape[0], self.shape[1]]);
+        Ok((0..rows)
+            .step_by(block)
+            .map(move |i| matrix.view(i..(i + block).min(rows), 0..cols)))
+    }
+
+    /// The same values in another shape, as `Tensor::reshape`. Errors rather than copying
+    /// unless this tensor is contiguous.
+    pub fn reshape(&self, new_shape: impl Into<Shape>) -> TensorRef<'a, T> {
+        self.try_reshape(new_shape)
+            .unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    /// Fallible version of `reshape`
+    pub fn try_reshape(&self, new_shape: impl Into<Shape>) -> Result<TensorRef<'a, T>, AmlError> {
+        let new_shape: Shape = new_shape.into();
+        self.check_contiguous()?;
+        let n_elements = new_shape.iter().product::<usize>();
+        if self.values.len() != n_elements {
+            return Err(AmlError::SizeMismatch {
+                expected: n_elements,
+                found: self.values.len(),
+            });
+        }
+
+        Ok(TensorRef {
+            values: self.values,
+            strides: dense_strides(&new_shape, self.layout),
+            shape: new_shape,
+            layout: self.layout,
+        })
+    }
+
+    /// Without `axis`, as `Tensor::squeeze`
+    pub fn squeeze(&self, axis: usize) -> TensorRef<'a, T> {
+        self.try_squeeze(axis).unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    /// Fallible version of `squeeze`
+    pub fn try_squeeze(&self, axis: usize) -> Result<TensorRef<'a, T>, AmlError> {
+        let (shape, strides) = squeezed(&self.shape, &self.strides, axis)?;
+        Ok(TensorRef {
+            values: self.values,
+            shape,
+            layout: self.layout,
+            strides,
+        })
+    }
+
+    /// With a new axis of length 1 before `axis`, as `Tensor::unsqueeze`
+    pub fn unsqueeze(&self, axis: usize) -> TensorRef<'a, T> {
+        self.try_unsqueeze(axis).unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    /// Fallible version of `unsqueeze`
+    pub fn try_unsqueeze(&self, axis: usize) -> Result<TensorRef<'a, T>, AmlError> {
+        let (shape, strides) = unsqueezed(&self.shape, &self.strides, self.layout, axis)?;
+        Ok(TensorRef {
+            values: self.values,
+            shape,
+            layout: self.layout,
+            strides,
+        })
+    }
+
+    /// Whether `values` holds the tensor back to back in `layout` order, without gaps
+    pub fn is_contiguous(&self) -> bool {
+        is_contiguous(&self.shape, &self.strides, self.layout)
+    }
+
+    /// Whether the first value sits at a multiple of `align` bytes, e.g. 32 for AVX aligned
+    /// loads. Each row (or column if column-major) starts aligned too when `ld()` values span a
+    /// multiple of `align` bytes.
+    pub fn is_aligned(&self, align: usize) -> bool {
+        aligned::is_aligned(self.values, align)
+    }
+
+    /// Leading dimension of a matrix: the stride between its rows, or its columns if
+    /// column-major, as BLAS `lda`.
+    pub fn ld(&self) -> usize {
+        outer_stride(&self.strides, self.layout)
+    }
+
+    /// Copy into a contiguous tensor of the same shape and layout
+    pub fn to_contiguous(&self) -> Tensor<T> {
+        Tensor {
+            values: gather(self.values, &self.shape, &self.strides, self.layout),
+            shape: self.shape.clone(),
+            layout: self.layout,
+            strides: dense_strides(&self.shape, self.layout),
+        }
+    }
+
+    /// `NotContiguous` unless `is_contiguous`
+    pub(crate) fn check_contiguous(&self) -> Result<(), AmlError> {
+        match self.is_contiguous() {
+            true => Ok(()),
+            false => Err(AmlError::NotContiguous {
+                shape: self.shape.to_vec(),
+                strides: self.strides.clone(),
+            }),
+        }
+    }
+
+    /// A copy the kernels can read with `ld`, or `None` if this tensor already is one.
+    pub(crate) fn blas_copy(&self) -> Option<Tensor<T>> {
+        match is_blas(&self.shape, &self.strides, self.layout) {
+            true => None,
+            false => Some(self.to_contiguous()),
+        }
+    }
+
+    /// `transpose` as a kernel reading `values` row-major has to apply it.
+    pub(crate) fn stored_transpose(&self, transpose: bool) -> bool {
+        transpose != (self.layout == Layout::ColMajor)
+    }
+
+    /// Shape of `values` read row-major: `shape`, reversed for column-major.
+    pub(crate) fn stored_shape(&self) -> Vec<usize> {
+        let mut shape = self.shape.to_vec();
+        if self.layout == Layout::ColMajor {
+            shape.reverse();
+        }
+        shape
+    }
+
+    /// `values` without gaps, read row-major in the stored order (see `stored_shape`).
+    /// Borrowed unless there are gaps to drop.
+    pub(crate) fn dense(&self) -> Cow<'a, [T]> {
+        self.dense_in(self.layout)
+    }
+
+    /// `values` without gaps in `layout` order, which need not be the tensor's own, so the
+    /// values of tensors stored differently line up
+    pub(crate) fn dense_in(&self, layout: Layout) -> Cow<'a, [T]> {
+        match is_contiguous(&self.shape, &self.strides, layout) {
+            true => Cow::Borrowed(self.values),
+            false => Cow::Owned(gather(self.values, &self.shape, &self.strides, layout)),
+        }
+    }
+}
+
+/// A matrix the `sgemm` family can read: a `Tensor`, or a `TensorRef` window of one.
+pub trait AsTensorRef<T: Element> {
+    fn as_tensor_ref(&self) -> TensorRef<'_, T>;
+}
+
+impl<T: Element> AsTensorRef<T> for Tensor<T> {
+    fn as_tensor_ref(&self) -> TensorRef<'_, T> {
+        TensorRef {
+            values: &self.values,
+            shape: self.shape.clone(),
+            layout: self.layout,
+            strides: self.strides.clone(),
+        }
+    }
+}
+
+impl<T: Element> AsTensorRef<T> for TensorRef<'_, T> {
+    fn as_tensor_ref(&self) -> TensorRef<'_, T> {
+        self.clone()
+    }
+}
+
+/// A matrix the `sgemm` family can write: a `Tensor`, or a `TensorMut` window of one.
+pub trait AsTensorMut<T: Element> {
+    fn as_tensor_mut(&mut self) -> TensorMut<'_, T>;
+}
+
+impl<T: Element> AsTensorMut<T> for Tensor<T> {
+    fn as_tensor_mut(&mut self) -> TensorMut<'_, T> {
+        self.view_mut()
+    }
+}
+
+impl<T: Element> AsTensorMut<T> for TensorMut<'_, T> {
+    fn as_tensor_mut(&mut self) -> TensorMut<'_, T> {
+        self.view_mut()
+    }
+}
+
+/// `t[[i, j]]` reads value (i, j) of a matrix wherever its strides put it, and likewise for any
+/// rank. Debug builds check the index against the shape; release builds only check it lands
+/// inside `values`, so an index past the end of a row may read the next one.
+impl<T: Element, const N: usize> Index<[usize; N]> for Tensor<T> {
+    type Output = T;
+
+    fn index(&self, index: [usize; N]) -> &T {
+        &self.values[offset(&index, &self.shape, &self.strides)]
+    }
+}
+
+impl<T: Element, const N: usize> IndexMut<[usize; N]> for Tensor<T> {
+    fn index_mut(&mut self, index: [usize; N]) -> &mut T {
+        &mut self.values[offset(&index, &self.shape, &self.strides)]
+    }
+}
+
+impl<T: Element, const N: usize> Index<[usize; N]> for TensorRef<'_, T> {
+    type Output = T;
+
+    fn index(&self, index: [usize; N]) -> &T {
+        &self.values[offset(&index, &self.shape, &self.strides)]
+    }
+}
+
+impl<T: Element, const N: usize> Index<[usize; N]> for TensorMut<'_, T> {
+    type Output = T;
+
+    fn index(&self, index: [usize; N]) -> &T {
+        &self.values[offset(&index, &self.shape, &self.strides)]
+    }
+}
+
+impl<T: Element, const N: usize> IndexMut<[usize; N]> for TensorMut<'_, T> {
+    fn index_mut(&mut self, index: [usize; N]) -> &mut T {
+        &mut self.values[offset(&index, &self.shape, &self.strides)]
+    }
+}
+
+/// Position of `index` in `values`, checked against `shape` in debug builds only
+pub(crate) fn offset(index: &[usize], shape: &[usize], strides: &[usize]) -> usize {
+    debug_assert!(
+        index.len() == shape.len() && index.iter().zip(shape).all(|(i, n)| i < n),
+        "{}",
+        AmlError::IndexOutOfBounds {
+            index: index.to_vec(),
+            shape: shape.to_vec(),
+        }
+    );
+    index.iter().zip(strides).map(|(i, s)| i * s).sum()
+}
+
+/// Values and shape of rows `rows` and columns `cols` of a (rows, cols) matrix with `strides`.
+pub(crate) fn window(
+    shape: &[usize],
+    strides: &[usize],
+    rows: Range<usize>,
+    cols: Range<usize>,
+) -> Result<(Range<usize>, Shape), AmlError> {
+    check_rank("values", shape, 2)?;
+    let inside = |range: &Range<usize>, len: usize| range.start <= range.end && range.end <= len;
+    if !inside(&rows, shape[0]) || !inside(&cols, shape[1]) {
+        return Err(AmlError::ViewOutOfBounds {
+            rows,
+            cols,
+            shape: shape.to_vec(),
+        });
+    }
+
+    let shape = vec![rows.len(), cols.len()];
+    let start = rows.start * strides[0] + cols.start * strides[1];
+    let range = match strided_len(&shape, strides) {
+        0 => 0..0,
+        len => start..start + len,
+    };
+    Ok((range, shape.into()))
+}
+
+/// Strides of a contiguous tensor of `shape` in `layout`: the last axis varies fastest for
+/// row-major, the first for column-major. At least 1, as BLAS leading dimensions are.
+pub(crate) fn dense_strides(shape: &[usize], layout: Layout) -> Vec<usize> {
+    let stride = |axes: &[usize]| axes.iter().product::<usize>().max(1);
+    (0..shape.len())
+        .map(|axis| match layout {
+            Layout::RowMajor => stride(&shape[axis + 1..]),
+            Layout::ColMajor => stride(&shape[..axis]),
+        })
+        .collect()
+}
+
+/// Shape and strides without `axis`, which must have length 1
+fn squeezed(
+    shape: &[usize],
+    strides: &[usize],
+    axis: usize,
+) -> Result<(Shape, Vec<usize>), AmlError> {
+    if shape.get(axis) != Some(&1) {
+        return Err(AmlError::InvalidAxis {
+            axis,
+            shape: shape.to_vec(),
+        });
+    }
+
+    let (mut shape, mut strides) = (shape.to_vec(), strides.to_vec());
+    shape.remove(axis);
+    strides.remove(axis);
+    Ok((shape.into(), strides))
+}
+
+/// Shape and strides with an axis of length 1 inserted at `axis`. Its stride is what a
+/// contiguous tensor would have there, so a contiguous tensor stays one.
+fn unsqueezed(
+    shape: &[usize],
+    strides: &[usize],
+    layout: Layout,
+    axis: usize,
+) -> Result<(Shape, Vec<usize>), AmlError> {
+    if axis > shape.len() {
+        return Err(AmlError::InvalidAxis {
+            axis,
+            shape: shape.to_vec(),
+        });
+    }
+
+    // the span of the next faster axis
+    let inner = match layout {
+        Layout::RowMajor => Some(axis).filter(|i| *i < shape.len()),
+        Layout::ColMajor => axis.checked_sub(1),
+    };
+    let stride = inner.map_or(1, |i| (shape[i] * strides[i]).max(1));
+
+    let (mut shape, mut strides) = (shape.to_vec(), strides.to_vec());
+    shape.insert(axis, 1);
+    strides.insert(axis, stride);
+    Ok((shape.into(), strides))
+}
+
+/// Values from the first index of a tensor to just past its last
+fn strided_len(shape: &[usize], strides: &[usize]) -> usize {
+    match shape.contains(&0) {
+        true => 0,
+        false => {
+            1 + shape
+                .iter()
+                .zip(strides)
+                .map(|(n, s)| (n - 1) * s)
+                .sum::<usize>()
+        }
+    }
+}
+
+/// Stride of the rows of a row-major matrix, or the columns of a column-major one
+fn outer_stride(strides: &[usize], layout: Layout) -> usize {
+    let outer = match layout {
+        Layout::RowMajor => strides.first(),
+        Layout::ColMajor => strides.last(),
+    };
+    outer.copied().unwrap_or(1)
+}
+
+fn is_contiguous(shape: &[usize], strides: &[usize], layout: Layout) -> bool {
+    // axes of one value have no neighbour, so their stride is never used
+    shape.contains(&0)
+        || shape
+            .iter()
+            .zip(strides)
+            .zip(dense_strides(shape, layout))
+            .all(|((n, s), dense)| *n == 1 || *s == dense)
+}
+
+/// Whether the kernels can index a tensor through `ld` alone: a matrix whose rows (columns,
+/// if column-major) are contiguous and do not overlap, or any contiguous tensor.
+fn is_blas(shape: &[usize], strides: &[usize], layout: Layout) -> bool {
+    if shape.len() != 2 {
+        return is_contiguous(shape, strides, layout);
+    }
+    let (outer, inner) = match layout {
+        Layout::RowMajor => ((shape[0], strides[0]), (shape[1], strides[1])),
+        Layout::ColMajor => ((shape[1], strides[1]), (shape[0], strides[0])),
+    };
+    (inner.0 <= 1 || inner.1 == 1) && outer.1 >= inner.0.max(1)
+}
+
+fn check_blas(
+    operand: &'static str,
+    shape: &[usize],
+    strides: &[usize],
+    layout: Layout,
+) -> Result<(), AmlError> {
+    match is_blas(shape, strides, layout) {
+        true => Ok(()),
+        false => Err(AmlError::UnsupportedStrides {
+            operand,
+            strides: strides.to_vec(),
+        }),
+    }
+}
+
+/// Layout a strided tensor is read in: column-major when its first axis has unit stride and its
+/// last does not, so the GEMMs need not copy it.
+fn strides_layout(shape: &[usize], strides: &[usize]) -> Layout {
+    let unit = |axis: usize| shape[axis] <= 1 || strides[axis] == 1;
+    match shape.len() >= 2 && unit(0) && !unit(shape.len() - 1) {
+        true => Layout::ColMajor,
+        false => Layout::RowMajor,
+    }
+}
+
+/// The values of a strided tensor back to back in `layout` order.
+fn gather<T: Copy>(values: &[T], shape: &[usize], strides: &[usize], layout: Layout) -> Vec<T> {
+    let len = shape.iter().product::<usize>();
+    let mut out = Vec::with_capacity(len);
+    if len == 0 {
+        return out;
+    }
+
+    // the fastest axis first, then an odometer over the rest
+    let axes: Vec<usize> = match layout {
+        Layout::RowMajor => (0..shape.len()).rev().collect(),
+        Layout::ColMajor => (0..shape.len()).collect(),
+    };
+    let mut index = vec![0; shape.len()];
+    let mut offset = 0;
+    loop {
+        out.push(values[offset]);
+        let mut carry = true;
+        for &axis in &axes {
+            index[axis] += 1;
+            offset += strides[axis];
+            if index[axis] < shape[axis] {
+                carry = false;
+                break;
+            }
+            offset -= strides[axis] * shape[axis];
+            index[axis] = 0;
+        }
+        if carry {
+            return out;
+        }
+    }
+}
+
+/// Strides of a (rows, cols) matrix in `layout` with rows (or columns) `ld` apart, checking
+/// `len` values hold it.
+fn ld_strides(
+    len: usize,
+    shape: &[usize],
+    layout: Layout,
+    ld: usize,
+) -> Result<Vec<usize>, AmlError> {
+    check_rank("values", shape, 2)?;
+    let (strides, inner) = match layout {
+        Layout::RowMajor => (vec![ld, 1], shape[1]),
+        Layout::ColMajor => (vec![1, ld], shape[0]),
+    };
+    if ld < inner.max(1) {
+        return Err(AmlError::InvalidLeadingDim {
+            ld,
+            min: inner.max(1),
+        });
+    }
+    check_len(len, shape, &strides)?;
+    Ok(strides)
+}
+
+/// Check `strides` give every index of `shape` its own value, the last of them at the end of
+/// `len` values.
+fn check_strides(len: usize, shape: &[usize], strides: &[usize]) -> Result<(), AmlError> {
+    let invalid = || AmlError::InvalidStrides {
+        shape: shape.to_vec(),
+        strides: strides.to_vec(),
+    };
+    if strides.len() != shape.len() {
+        return Err(invalid());
+    }
+
+    // from the fastest axis out, each must step past everything the faster ones span
+    let mut axes: Vec<(usize, usize)> = shape
+        .iter()
+        .zip(strides)
+        .filter(|(n, _)| **n > 1)
+        .map(|(n, s)| (*s, *n))
+        .collect();
+    axes.sort_unstable();
+    let mut span = 1;
+    for (stride, n) in axes {
+        if stride < span {
+            return Err(invalid());
+        }
+        span = stride * n;
+    }
+
+    check_len(len, shape, strides)
+}
+
+pub(crate) fn check_len(len: usize, shape: &[usize], strides: &[usize]) -> Result<(), AmlError> {
+    let expected = strided_len(shape, strides);
+    match len == expected {
+        true => Ok(()),
+        false => Err(AmlError::SizeMismatch {
+            expected,
+            found: len,
+        }),
+    }
+}
+
+/// Shaped output buffer over caller owned storage, e.g. a slice of a larger allocation.
+///
+/// The `_into` kernels check the shape before writing, so a buffer of the right length but the
+/// wrong shape is rejected.
+pub struct TensorMut<'a, T: Element> {
+    pub values: &'a mut [T],
+    pub shape: Shape,
+    pub layout: Layout,
+    /// Values between neighbours along each axis, as `Tensor::strides`
+    pub strides: Vec<usize>,
+}
+
+/// f16 output view, written by the `_into` and `_with` kernels.
+pub type F16TensorMut<'a> = TensorMut<'a, f16>;
+
+impl<T: Element> TensorMut<'_, T> {
+    pub fn new(values: &mut [T], shape: impl Into<Shape>) -> TensorMut<'_, T> {
+        TensorMut::try_new(values, shape).unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    /// Fallible version of `new`
+    pub fn try_new(
+        values: &mut [T],
+        shape: impl Into<Shape>,
+    ) -> Result<TensorMut<'_, T>, AmlError> {
+        let shape: Shape = shape.into();
+        let n_elements = shape.iter().product::<usize>();
+        if values.len() != n_elements {
+            return Err(AmlError::SizeMismatch {
+                expected: n_elements,
+                found: values.len(),
+            });
+        }
+
+        Ok(TensorMut {
+            strides: dense_strides(&shape, Layout::RowMajor),
+            values,
+            shape,
+            layout: Layout::RowMajor,
+        })
+    }
+
+    /// A matrix inside a larger buffer, as `Tensor::new_with_ld`, e.g. a block of `c`
+    pub fn new_with_ld(
+        values: &mut [T],
+        shape: impl Into<Shape>,
+        layout: Layout,
+        ld: usize,
+    ) -> TensorMut<'_, T> {
+        TensorMut::try_new_with_ld(values, shape, layout, ld).unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    /// Fallible version of `new_with_ld`
+    pub fn try_new_with_ld(
+        values: &mut [T],
+        shape: impl Into<Shape>,
+        layout: Layout,
+        ld: usize,
+    ) -> Result<TensorMut<'_, T>, AmlError> {
+        let shape: Shape = shape.into();
+        let strides = ld_strides(values.len(), &shape, layout, ld)?;
+
+        Ok(TensorMut {
+            values,
+            shape,
+            layout,
+            strides,
+        })
+    }
+}
+
+impl<'a, T: Element> TensorMut<'a, T> {
+    /// The same values read in `layout` order, as `Tensor::with_layout`.
+    pub fn with_layout(self, layout: Layout) -> TensorMut<'a, T> {
+        assert!(
+            is_contiguous(&self.shape, &self.strides, self.layout),
+            "{}",
+            AmlError::NotContiguous {
+                shape: self.shape.to_vec(),
+                strides: self.strides.clone(),
+            }
+        );
+        TensorMut {
+            strides: dense_strides(&self.shape, layout),
+            layout,
+            ..self
+        }
+    }
+
+    /// Leading dimension of a matrix, as `TensorRef::ld`
+    pub fn ld(&self) -> usize {
+        outer_stride(&self.strides, self.layout)
+    }
+
+    /// A window of this output, as `Tensor::window_mut`
+    pub fn window_mut(&mut self, rows: Range<usize>, cols: Range<usize>) -> TensorMut<'_, T> {
+        self.try_window_mut(rows, cols)
+            .unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    /// Fallible version of `window_mut`
+    pub fn try_window_mut(
+        &mut self,
+        rows: Range<usize>,
+        cols: Range<usize>,
+    ) -> Result<TensorMut<'_, T>, AmlError> {
+        let (range, shape) = window(&self.shape, &self.strides, rows, cols)?;
+        Ok(TensorMut {
+            values: &mut self.values[range],
+            shape,
+            layout: self.layout,
+            strides: self.strides.clone(),
+        })
+    }
+
+    /// `UnsupportedStrides` unless a kernel can write this output in place
+    pub(crate) fn check_blas(&self, operand: &'static str) -> Result<(), AmlError> {
+        check_blas(operand, &self.shape, &self.strides, self.layout)
+    }
+
+    /// Reborrow for a call that should not consume this view.
+    pub(crate) fn view_mut(&mut self) -> TensorMut<'_, T> {
+        TensorMut {
+            values: self.values,
+            shape: self.shape.clone(),
+            layout: self.layout,
+            strides: self.strides.clone(),
+        }
+    }
+
+    /// The output a kernel that only writes row-major should fill. A column-major (m, n) `c`
+    /// holds the row-major (n, m) `c^T = op(b)^T @ op(a)^T`, so its kernel swaps `a` and `b`.
+    pub(crate) fn row_major(self) -> TensorMut<'a, T> {
+        let (mut shape, mut strides) = (self.shape.to_vec(), self.strides.clone());
+        if self.layout == Layout::ColMajor {
+            shape.reverse();
+            strides.reverse();
+        }
+
+        TensorMut {
+            values: self.values,
+            shape: shape.into(),
+            layout: Layout::RowMajor,
+            strides,
+        }
+    }
+}
+
+/// Per-tensor affine quantized int8 tensor: each value stands for `scale * (q - zero_point)`.
+pub struct I8Tensor {
+    pub values: Vec<i8>,
+    pub shape: Shape,
+    pub scale: f32,
+    pub zero_point: i8,
+}
+
+impl I8Tensor {
+    pub fn new(values: Vec<i8>, shape: impl Into<Shape>, scale: f32, zero_point: i8) -> I8Tensor {
+        I8Tensor::try_new(values, shape, scale, zero_point).unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    /// Fallible version of `new`
+    pub fn try_new(
+        values: Vec<i8>,
+        shape: impl Into<Shape>,
+        scale: f32,
+        zero_point: i8,
+    ) -> Result<I8Tensor, AmlError> {
+        let shape: Shape = shape.into();
+        let n_elements = shape.iter().product::<usize>();
+        if values.len() != n_elements {
+            return Err(AmlError::SizeMismatch {
+                expected: n_elements,
+                found: values.len(),
+            });
+        }
+
+        Ok(I8Tensor {
+            values,
+            shape,
+            scale,
+            zero_point,
+        })
+    }
+}
+
+pub(crate) fn check_output(expected: Vec<usize>, found: &[usize]) -> Result<(), AmlError> {
+    match expected == found {
+        true => Ok(()),
+        false => Err(AmlError::OutputShapeMismatch {
+            expected,
+            found: found.to_vec(),
+        }),
+    }
+}
+
+/// Dot product between an F16 Tensor and a I4 tensor
+///
+/// Expects `a` (n, ) and `b` (n, )
+pub fn qdot(a: &F16Tensor, b: &I4Tensor) -> f32 {
+    try_qdot(a, b).unwrap_or_else(|e| panic!("{}", e))
+}
+
+/// Fallible version of `qdot`
+pub fn try_qdot(a: &F16Tensor, b: &I4Tensor) -> Result<f32, AmlError> {
+    check_rank("a", &a.shape, 1)?;
+    check_rank("b", &b.shape, 1)?;
+    check_inner(a.shape[0], b.shape[0])?;
+
+    Ok(qdot_range(&a.dense(), b, 0))
+}
+
+pub(crate) fn check_rank(
+    operand: &'static str,
+    shape: &[usize],
+    expected: usize,
+) -> Result<(), AmlError> {
+    match shape.len() == expected {
+        true => Ok(()),
+        false => Err(AmlError::RankMismatch {
+            operand,
+            expected,
+            found: shape.len(),
+        }),
+    }
+}
+
+pub(crate) fn check_row_major(operand: &'static str, layout: Layout) -> Result<(), AmlError> {
+    match layout {
+        Layout::RowMajor => Ok(()),
+        Layout::ColMajor => Err(AmlError::UnsupportedLayout { operand }),
+    }
+}
+
+/// `a` and `b` in the order a GEMM writing the row-major form of a `c` in `layout` takes them:
+/// as given, or traded and transposed for a column-major `c` (see `TensorMut::row_major`).
+pub(crate) fn gemm_operands<T>(
+    layout: Layout,
+    a: T,
+    a_transpose: bool,
+    b: T,
+    b_transpose: bool,
+) -> (T, bool, T, bool) {
+    match layout {
+        Layout::RowMajor => (a, a_transpose, b, b_transpose),
+        Layout::ColMajor => (b, !b_transpose, a, !a_transpose),
+    }
+}
+
+pub(crate) fn check_inner(a: usize, b: usize) -> Result<(), AmlError> {
+    match a == b {
+        true => Ok(()),
+        false => Err(AmlError::InnerDimMismatch { a, b }),
+    }
+}
+
+/// `qdot` over a raw slice of `a` against `a.len()` values of `b` starting at flat index `start`,
+/// so rows of 2D tensors can be used without copying or lining up with blocks.
+///
+/// Block runs that start on a high nibble go through the dispatched block kernel, which
+/// dequantizes in registers where the CPU allows; a run starting mid-byte (odd row lengths or block sizes) takes the
+/// scalar path.
+fn qdot_range(a: &[f16], b: &I4Tensor, start: usize) -> f32 {
+    let mut acc = 0f32;
+    let block_dot = dispatch::kernels().i4_block_dot;
+
+    let end = start + a.len();
+    let mut idx = start;
+    while idx < end {
+        let block = idx / b.block_size;
+        let block_end = end.min((block + 1) * b.block_size);
+        let scale = b.scales[block].to_f32();
+        let zero = b.zero(block);
+
+        let a_run = &a[idx - start..block_end - start];
+        let block_acc = match idx % 2 {
+            0 => block_dot(a_run, &b.nibbles[idx / 2..], zero),
+            _ => (idx..block_end)
+                .map(|i| a[i - start].to_f32() * (b.nibble(i) - zero) as f32)
+                .sum(),
+        };
+        acc += scale * block_acc;
+
+        idx = block_end;
+    }
+
+    acc
+}
+
+/// Dot product between `a` (F16) and each row of `b` (I4)
+///
+/// I4 (m, n) @ F16 (n,) --> F16 (m,)
+pub fn qgemv(a: &F16Tensor, b: &I4Tensor) -> F16Tensor {
+    try_qgemv(a, b).unwrap_or_else(|e| panic!("{}", e))
+}
+
+/// Fallible version of `qgemv`
+pub fn try_qgemv(a: &F16Tensor, b: &I4Tensor) -> Result<F16Tensor, AmlError> {
+    check_rank("b", &b.shape, 2)?;
+
+    let mut out = F16Tensor::zeros(vec![b.shape[0]]);
+    try_qgemv_into(a, b, &mut out.view_mut())?;
+
+    Ok(out)
+}
+
+/// `qgemv` writing into an existing `c` (m,) instead of allocating.
+pub fn qgemv_into(a: &F16Tensor, b: &I4Tensor, c: &mut F16TensorMut) {
+    try_qgemv_into(a, b, c).unwrap_or_else(|e| panic!("{}", e))
+}
+
+/// Fallible version of `qgemv_into`. `c` is left untouched on error.
+pub fn try_qgemv_into(a: &F16Tensor, b: &I4Tensor, c: &mut F16TensorMut) -> Result<(), AmlError> {
+    try_qgemv_with(a, b, GemmParams::default(), c)
+}
+
+/// `c = alpha * (b @ a) + beta * c`
+pub fn qgemv_with(a: &F16Tensor, b: &I4Tensor, params: GemmParams, c: &mut F16TensorMut) {
+    try_qgemv_with(a, b, params, c).unwrap_or_else(|e| panic!("{}", e))
+}
+
+/// Fallible version of `qgemv_with`. `c` is left untouched on error.
+pub fn try_qgemv_with(
+    a: &F16Tensor,
+    b: &I4Tensor,
+    params: GemmParams,
+    c: &mut F16TensorMut,
+) -> Result<(), AmlError> {
+    check_rank("a", &a.shape, 1)?;
+    check_rank("b", &b.shape, 2)?;
+    check_inner(a.shape[0], b.shape[1])?;
+    check_output(vec![b.shape[0]], &c.shape)?;
+    c.check_blas("c")?;
+
+    let (a, n) = (a.dense(), b.shape[1]);
+    parallel::for_each_row_chunk(c.values, 1, n, |first_row, c_rows| {
+        for (row_idx, c_val) in (first_row..).zip(c_rows.iter_mut()) {
+            let b_row_dot = qdot_range(&a, b, row_idx * n);
+            params.store(b_row_dot, c_val);
+        }
+    });
+
+    Ok(())
+}
+
+/// Dot product between `a` (F16) and each row of a dense `b` (F16)
+///
+/// F16 (m, n) @ F16 (n,) --> F16 (m,). `a` is widened to f32 once, and each row of `b` is
+/// widened in short runs that stay in L1 while the dot product is taken, so `b` must be
+/// row-major.
+pub fn hgemv(a: &F16Tensor, b: &F16Tensor) -> F16Tensor {
+    try_hgemv(a, b).unwrap_or_else(|e| panic!("{}", e))
+}
+
+/// Fallible version of `hgemv`
+pub fn try_hgemv(a: &F16Tensor, b: &F16Tensor) -> Result<F16Tensor, AmlError> {
+    check_rank("b", &b.shape, 2)?;
+
+    let mut out = F16Tensor::zeros(vec![b.shape[0]]);
+    try_hgemv_with(a, b, GemmParams::default(), &mut out.view_mut())?;
+
+    Ok(out)
+}
+
+/// `c = alpha * (b @ a) + beta * c`
+pub fn hgemv_with(a: &F16Tensor, b: &F16Tensor, params: GemmParams, c: &mut F16TensorMut) {
+    try_hgemv_with(a, b, params, c).unwrap_or_else(|e| panic!("{}", e))
+}
+
+/// Fallible version of `hgemv_with`. `c` is left untouched on error.
+pub fn try_hgemv_with(
+    a: &F16Tensor,
+    b: &F16Tensor,
+    params: GemmParams,
+    c: &mut F16TensorMut,
+) -> Result<(), AmlError> {
+    check_rank("a", &a.shape, 1)?;
+    check_rank("b", &b.shape, 2)?;
+    check_row_major("b", b.layout)?;
+    check_inner(a.shape[0], b.shape[1])?;
+    check_output(vec![b.shape[0]], &c.shape)?;
+    c.check_blas("c")?;
+
+    let n = b.shape[1];
+    let mut a_f32 = vec![0f32; n];
+    a.dense().convert_to_f32_slice(&mut a_f32);
+    let b_copy = b.blas_copy();
+    let b = b_copy.as_ref().unwrap_or(b);
+
+    parallel::for_each_row_chunk(c.values, 1, n, |first_row, c_rows| {
+        let mut b_chunk = [0f32; HDOT_CHUNK];
+        for (row_idx, c_val) in (first_row..).zip(c_rows.iter_mut()) {
+            let b_row = &b.values[row_idx * b.ld()..][..n];
+            let mut acc = 0f32;
+            for (a_part, b_part) in a_f32.chunks(HDOT_CHUNK).zip(b_row.chunks(HDOT_CHUNK)) {
+                let b_part_f32 = &mut b_chunk[..b_part.len()];
+                b_part.convert_to_f32_slice(b_part_f32);
+                acc += sdot(a_part, b_part_f32);
+            }
+            params.store(acc, c_val);
+        }
+    });
+
+    Ok(())
+}
+
+/// Number of f16 values widened at a time by `hgemv`.
+const HDOT_CHUNK: usize = 256;
+
+/// Matrix Muliply between an `F16Tensor` and an `I4Tensor`. Result stored in `c` (F16)
+///
+/// op(F16(m, k)) @ op(I4(k, n)) --> F16(m, n), where `op` transposes the operand if requested.
+/// The previous contents of `c` are overwritten; use `qgemm_with` and `Accumulate::Add` to sum
+/// into them instead.
+///
+/// Each transpose combination walks `a` and `b` along their rows, so no transposed copy is made,
+/// and a column-major `a` is walked as its transpose. `c` must be row-major. `I4Tensor` rows are
+/// dequantized once each and reused for every row/column of `a` they meet.
+pub fn qgemm(a: &F16Tensor, a_transpose: bool, b: &I4Tensor, b_transpose: bool, c: &mut F16Tensor) {
+    try_qgemm(a, a_transpose, b, b_transpose, c).unwrap_or_else(|e| panic!("{}", e))
+}
+
+/// Fallible version of `qgemm`. `c` is left untouched on error.
+pub fn try_qgemm(
+    a: &F16Tensor,
+    a_transpose: bool,
+    b: &I4Tensor,
+    b_transpose: bool,
+    c: &mut F16Tensor,
+) -> Result<(), AmlError> {
+    try_qgemm_into(a, a_transpose, b, b_transpose, &mut c.view_mut())
+}
+
+/// `qgemm` writing into any shaped output buffer, not just an owned `F16Tensor`.
+pub fn qgemm_into(
+    a: &F16Tensor,
+    a_transpose: bool,
+    b: &I4Tensor,
+    b_transpose: bool,
+    c: &mut F16TensorMut,
+) {
+    try_qgemm_into(a, a_transpose, b, b_transpose, c).unwrap_or_else(|e| panic!("{}", e))
+}
+
+/// Fallible version of `qgemm_into`. `c` is left untouched on error.
+pub fn try_qgemm_into(
+    a: &F16Tensor,
+    a_transpose: bool,
+    b: &I4Tensor,
+    b_transpose: bool,
+    c: &mut F16TensorMut,
+) -> Result<(), AmlError> {
+    try_qgemm_with(a, a_transpose, b, b_transpose, GemmParams::default(), c)
+}
+
+/// `c = alpha * (op(a) @ op(b)) + beta * c`, the full BLAS form of `qgemm`.
+pub fn qgemm_with(
+    a: &F16Tensor,
+    a_transpose: bool,
+    b: &I4Tensor,
+    b_transpose: bool,
+    params: GemmParams,
+    c: &mut F16TensorMut,
+) {
+    try_qgemm_with(a, a_transpose, b, b_transpose, params, c).unwrap_or_else(|e| panic!("{}", e))
+}
+
+/// Fallible version of `qgemm_with`. `c` is left untouched on error.
+pub fn try_qgemm_with(
+    a: &F16Tensor,
+    a_transpose: bool,
+    b: &I4Tensor,
+    b_transpose: bool,
+    params: GemmParams,
+    c: &mut F16TensorMut,
+) -> Result<(), AmlError> {
+    check_qgemm(a, a_transpose, b, b_transpose, c)?;
+    let a_copy = a.blas_copy();
+    let a = a_copy.as_ref().unwrap_or(a);
+    qgemm_kernel(a, a_transpose, b, b_transpose, params, c.values, c.ld());
+
+    Ok(())
+}
+
+/// All of the shape and layout checks of `try_qgemm_with`, without touching `c`.
+fn check_qgemm(
+    a: &F16Tensor,
+    a_transpose: bool,
+    b: &I4Tensor,
+    b_transpose: bool,
+    c: &F16TensorMut,
+) -> Result<(), AmlError> {
+    check_gemm(&a.shape, a_transpose, &b.shape, b_transpose, &c.shape)?;
+    check_row_major("c", c.layout)?;
+    c.check_blas("c")
+}
+
+/// Rows `first_row..first_row + rows` of `op(a)` (m, k), contiguous, from an `a` whose stored
+/// rows, (m, k) or (k, m) if transposed, start `ld` values apart. Borrowed unless `a` is transposed or padded, in which case just
+/// those rows are gathered, so a thread working on a block of output rows never walks `a` down
+/// its columns in the hot loop.
+pub(crate) fn op_a_rows<T: Copy + Default>(
+    a: &[T],
+    a_transpose: bool,
+    k: usize,
+    ld: usize,
+    first_row: usize,
+    rows: usize,
+) -> Cow<'_, [T]> {
+    match a_transpose {
+        false if ld == k || rows == 0 => Cow::Borrowed(&a[first_row * k..(first_row + rows) * k]),
+        false => {
+            let mut packed = vec![T::default(); rows * k];
+            for (a_i, packed_i) in a[first_row * ld..]
+                .chunks(ld)
+                .zip(packed.chunks_exact_mut(k.max(1)))
+            {
+                packed_i.copy_from_slice(&a_i[..k]);
+            }
+            Cow::Owned(packed)
+        }
+        true => {
+            let mut packed = vec![T::default(); rows * k];
+            for (p, a_p) in a.chunks(ld.max(1)).enumerate() {
+                for (i, a_pi) in a_p[first_row..first_row + rows].iter().enumerate() {
+                    packed[i * k + p] = *a_pi;
+                }
+            }
+            Cow::Owned(packed)
+        }
+    }
+}
+
+/// The first `len` values of each row of `values`, rows starting `ld` apart.
+pub(crate) fn strided_rows<T>(values: &[T], ld: usize, len: usize) -> impl Iterator<Item = &[T]> {
+    values.chunks(ld.max(1)).map(move |row| &row[..len])
+}
+
+/// Each `n` long row of `acc` through `store(acc, c)` into its row of `c`, rows of `c` starting
+/// `ldc` apart.
+pub(crate) fn store_rows<A: Copy, C>(
+    acc: &[A],
+    n: usize,
+    c: &mut [C],
+    ldc: usize,
+    store: impl Fn(A, &mut C),
+) {
+    for (c_row, acc_row) in c.chunks_mut(ldc.max(1)).zip(acc.chunks(n.max(1))) {
+        for (c_val, acc_val) in c_row.iter_mut().zip(acc_row) {
+            store(*acc_val, c_val);
+        }
+    }
+}
+
+/// Shape checks shared by every GEMM: `op(a) @ op(b)` must be defined and shaped like `c`.
+pub(crate) fn check_gemm(
+    a_shape: &Shape,
+    a_transpose: bool,
+    b_shape: &Shape,
+    b_transpose: bool,
+    c_shape: &[usize],
+) -> Result<(), AmlError> {
+    let out_shape = a_shape.try_matmul(a_transpose, b_shape, b_transpose)?;
+    check_output(out_shape.into(), c_shape)
+}
+
+/// Unchecked body of `qgemm`. Shapes must already have passed `check_qgemm`, and the rows of
+/// `c` start `ldc` apart.
+fn qgemm_kernel(
+    a: &F16Tensor,
+    a_transpose: bool,
+    b: &I4Tensor,
+    b_transpose: bool,
+    params: GemmParams,
+    c: &mut [f16],
+    ldc: usize,
+) {
+    let m = match a_transpose {
+        true => a.shape[1],
+        false => a.shape[0],
+    };
+    let n = match b_transpose {
+        true => b.shape[0],
+        false => b.shape[1],
+    };
+    let a_transpose = a.stored_transpose(a_transpose);
+    let a_shape = a.stored_shape();
+    let (lda, a_cols) = (a.ld(), a_shape[1]);
+
+    if a_transpose && b_transpose {
+        // a (k, m), b (n, k): dequantize one row of b, then sweep the rows of a against it.
+        let k = a_shape[0];
+        let mut acc = vec![0f32; m * n];
+        let mut b_row = vec![0f32; k];
+        for j in 0..n {
+            b.dequantize_range_into(j * k, &mut b_row);
+            for (p, b_val) in b_row.iter().enumerate() {
+                let a_row = &a.values[p * lda..][..a_cols];
+                for (i, a_val) in a_row.iter().enumerate() {
+                    acc[i * n + j] += a_val.to_f32() * b_val;
+                }
+            }
+        }
+        params.store_all(&acc, n, c, ldc);
+    } else if a_transpose {
+        // a (k, m), b (k, n): sum of outer products of matching rows.
+        let k = a_shape[0];
+        let mut acc = vec![0f32; m * n];
+        let mut b_row = vec![0f32; n];
+        for p in 0..k {
+            b.dequantize_range_into(p * n, &mut b_row);
+            let a_row = &a.values[p * lda..][..a_cols];
+            for (a_val, acc_row) in a_row.iter().zip(acc.chunks_exact_mut(n)) {
+                let a_val = a_val.to_f32();
+                for (acc_val, b_val) in acc_row.iter_mut().zip(&b_row) {
+                    *acc_val += a_val * b_val;
+                }
+            }
+        }
+        params.store_all(&acc, n, c, ldc);
+    } else if b_transpose {
+        // a (m, k), b (n, k): every output is a row-row dot product.
+        for i in 0..m {
+            let a_row = &a.values[i * lda..][..a_cols];
+            for j in 0..n {
+                params.store(qdot_range(a_row, b, j * a_cols), &mut c[i * ldc + j]);
+            }
+        }
+    } else {
+        // a (m, k), b (k, n): scale each dequantized row of b into the rows of c.
+        let k = a_shape[1];
+        let mut acc = vec![0f32; m * n];
+        let mut b_row = vec![0f32; n];
+        for p in 0..k {
+            b.dequantize_range_into(p * n, &mut b_row);
+            for (i, acc_row) in acc.chunks_exact_mut(n).enumerate() {
+                let a_val = a.values[i * lda + p].to_f32();
+                for (acc_val, b_val) in acc_row.iter_mut().zip(&b_row) {
+                    *acc_val += a_val * b_val;
+                }
+            }
+        }
+        params.store_all(&acc, n, c, ldc);
+    }
+}
+
+/// Independent `qgemm`s over matching lists of `a`, `b` and `c`, split across threads by batch
+/// entry once there is enough work in all, as `sgemm_batched`.
+///
+/// Every entry is checked before any is computed, so on error no `c` has been written.
+pub fn qgemm_batched(
+    a: &[F16Tensor],
+    a_transpose: bool,
+    b: &[I4Tensor],
+    b_transpose: bool,
+    c: &mut [F16Tensor],
+) {
+    try_qgemm_batched(a, a_transpose, b, b_transpose, c).unwrap_or_else(|e| panic!("{}", e))
+}
+
+/// Fallible version of `qgemm_batched`
+pub fn try_qgemm_batched(
+    a: &[F16Tensor],
+    a_transpose: bool,
+    b: &[I4Tensor],
+    b_transpose: bool,
+    c: &mut [F16Tensor],
+) -> Result<(), AmlError> {
+    if a.len() != b.len() || a.len() != c.len() {
+        return Err(AmlError::BatchSizeMismatch {
+            a: a.len(),
+            b: b.len(),
+            c: c.len(),
+        });
+    }
+    let mut work = 0usize;
+    for ((a, b), c) in a.iter().zip(b).zip(c.iter_mut()) {
+        check_qgemm(a, a_transpose, b, b_transpose, &c.view_mut())?;
+        let k = match a_transpose {
+            true => a.shape[0],
+            false => a.shape[1],
+        };
+        work = work.saturating_add((c.shape[0] * c.shape[1]).saturating_mul(k));
+    }
+
+    parallel::for_each_chunk(c, work, |first, c| {
+        for ((a, b), c) in a[first..].iter().zip(&b[first..]).zip(c) {
+            let a_copy = a.blas_copy();
+            let a = a_copy.as_ref().unwrap_or(a);
+            let ldc = c.ld();
+            qgemm_kernel(
+                a,
+                a_transpose,
+                b,
+                b_transpose,
+                GemmParams::default(),
+                &mut c.values,
+                ldc,
+            );
+        }
+    });
+
+    Ok(())
+}
+
+/// Scaling applied when a kernel writes its result: `c = alpha * result + beta * c`.
+///
+/// The default (`alpha = 1`, `beta = 0`) overwrites `c`. As in BLAS, `beta = 0` never reads `c`,
+/// so NaNs or garbage already in the output do not leak into the result.
+///
+/// The scalar type follows the output: `GemmParams` (f32) for the f16 and f32 kernels,
+/// `GemmParams<f64>` for `dgemm`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct GemmParams<T = f32> {
+    pub alpha: T,
+    pub beta: T,
+    /// How `sgemm` sums each output; every other kernel ignores this.
+    pub accuracy: Accuracy,
+}
+
+impl<T> GemmParams<T> {
+    pub fn new(alpha: T, beta: T) -> GemmParams<T> {
+        GemmParams {
+            alpha,
+            beta,
+            accuracy: Accuracy::Fast,
+        }
+    }
+
+    /// Same scaling with a different `accuracy`.
+    pub fn with_accuracy(self, accuracy: Accuracy) -> GemmParams<T> {
+        GemmParams { accuracy, ..self }
+    }
+}
+
+/// Precision of the running sums inside `sgemm`.
+///
+/// f32 sums lose digits as `k` grows, and badly so for ill-conditioned inputs where large terms
+/// cancel. `High` sums every output element in f64 and rounds to f32 once, at about half the
+/// throughput.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Accuracy {
+    /// f32 accumulators
+    #[default]
+    Fast,
+    /// f64 accumulators, one rounding per output
+    High,
+}
+
+impl GemmParams<f32> {
+    /// Combine one f32 result with the existing value of `c`.
+    fn store(&self, acc: f32, c: &mut f16) {
+        *c = f16::from_f32(self.apply(acc, c.to_f32()));
+    }
+
+    /// `alpha * acc + beta * c`, skipping `c` entirely when `beta` is zero.
+    pub(crate) fn apply(&self, acc: f32, c: f32) -> f32 {
+        match self.beta == 0f32 {
+            true => self.alpha * acc,
+            false => self.alpha * acc + self.beta * c,
+        }
+    }
+
+    /// `store` over a whole f32 accumulator of `n` long rows, into rows of `c` `ldc` apart.
+    fn store_all(&self, acc: &[f32], n: usize, c: &mut [f16], ldc: usize) {
+        store_rows(acc, n, c, ldc, |acc, c| self.store(acc, c));
+    }
+}
+
+impl Default for GemmParams<f64> {
+    fn default() -> GemmParams<f64> {
+        GemmParams::new(1f64, 0f64)
+    }
+}
+
+/// Whether a kernel replaces the contents of its output or adds its result onto them.
+///
+/// Every kernel that writes into a caller provided output accepts this via `GemmParams`, and
+/// the plain entry points (`qgemm`, `qgemm_into`, `qgemv_into`) always `Overwrite`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Accumulate {
+    /// `c = result`
+    #[default]
+    Overwrite,
+    /// `c += result`
+    Add,
+}
+
+impl From<Accumulate> for GemmParams<f32> {
+    fn from(mode: Accumulate) -> GemmParams<f32> {
+        match mode {
+            Accumulate::Overwrite => GemmParams::new(1f32, 0f32),
+            Accumulate::Add => GemmParams::new(1f32, 1f32),
+        }
+    }
+}
+
+impl From<Accumulate> for GemmParams<f64> {
+    fn from(mode: Accumulate) -> GemmParams<f64> {
+        match mode {
+            Accumulate::Overwrite => GemmParams::new(1f64, 0f64),
+            Accumulate::Add => GemmParams::new(1f64, 1f64),
+        }
+    }
+}
+
+impl GemmParams<f64> {
+    /// `alpha * acc + beta * c`, skipping `c` entirely when `beta` is zero.
+    pub(crate) fn apply(&self, acc: f64, c: f64) -> f64 {
+        match self.beta == 0f64 {
+            true => self.alpha * acc,
+            false => self.alpha * acc + self.beta * c,
+        }
+    }
+}
+
+impl Default for GemmParams<f32> {
+    fn default() -> GemmParams<f32> {
+        GemmParams::new(1f32, 0f32)
     }
 }
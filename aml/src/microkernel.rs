@@ -0,0 +1,449 @@
+//! Register tiled f32 microkernels over packed panels, the innermost loop of `sgemm`.
+//!
+//! Every kernel computes `c += a @ b` for one `mr` x `nr` tile of `c` over `kc` steps, with `a`
+//! packed as `kc` columns of `mr` values and `b` as `kc` rows of `nr` values (see
+//! `sgemm::PackedB`), so both stream through memory contiguously.
+
+#[cfg(all(target_arch = "wasm32", target_feature = "simd128"))]
+use crate::blas1;
+
+#[cfg(target_arch = "aarch64")]
+use std::arch::aarch64::*;
+#[cfg(all(target_arch = "wasm32", target_feature = "simd128"))]
+use std::arch::wasm32::*;
+#[cfg(target_arch = "x86_64")]
+use std::arch::x86_64::*;
+
+/// Names of every microkernel on any target, plus `blas1` for packings made without one, so a
+/// header written on one machine can be read on another.
+pub(crate) const ISA_NAMES: [&str; 8] = [
+    "blas1", "avx512", "fma", "sve", "neon", "simd128", "portable", "generic",
+];
+
+/// Register tiled inner kernels, best first. Only the current architecture's exist.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Microkernel {
+    /// 14 x 32: 28 zmm accumulators plus 2 `b` loads and a broadcast fill the 32 zmm registers.
+    #[cfg(target_arch = "x86_64")]
+    Avx512,
+    /// 6 x 16: 12 ymm accumulators plus 2 `b` loads and a broadcast fit the 16 ymm registers.
+    #[cfg(target_arch = "x86_64")]
+    Fma,
+    /// 8 x 2 vectors, whatever the SVE vector length: 16 z register accumulators plus 2 `b`
+    /// loads and a broadcast leave the callee-saved z8-z15 alone. Chosen over `Neon` only when
+    /// the vectors are wider than NEON's 128 bits, where 8 x 8 would be a smaller tile.
+    #[cfg(target_arch = "aarch64")]
+    Sve,
+    /// 8 x 12: 24 q register accumulators plus 3 `b` loads and a broadcast fit the 32 NEON
+    /// registers.
+    #[cfg(target_arch = "aarch64")]
+    Neon,
+    /// 4 x 8 on wasm32 with `simd128`, chosen at compile time. The engine maps v128 values onto
+    /// native registers, so the tile stays small.
+    #[cfg(all(target_arch = "wasm32", target_feature = "simd128"))]
+    Simd128,
+    /// 4 x 16 in `core::simd` f32x8 vectors, for targets with no kernel of their own. Needs the
+    /// `portable-simd` feature and a nightly compiler.
+    #[cfg(feature = "portable-simd")]
+    Portable,
+    /// 4 x 16 in plain arrays, which the compiler vectorizes for whatever target it builds
+    /// (RISC-V V, say), as the `matrixmultiply` crate's kernels do. Needs the `generic-kernel`
+    /// feature and builds on stable.
+    #[cfg(feature = "generic-kernel")]
+    Generic,
+}
+
+impl Microkernel {
+    // the wasm32 choice is made at compile time and returns early
+    #[allow(unreachable_code)]
+    pub(crate) fn detect() -> Option<Microkernel> {
+        #[cfg(target_arch = "x86_64")]
+        {
+            if is_x86_feature_detected!("avx512f") {
+                return Some(Microkernel::Avx512);
+            }
+            if is_x86_feature_detected!("avx2") && is_x86_feature_detected!("fma") {
+                return Some(Microkernel::Fma);
+            }
+        }
+        #[cfg(target_arch = "aarch64")]
+        {
+            if std::arch::is_aarch64_feature_detected!("sve") && unsafe { sve_lanes() } > 4 {
+                return Some(Microkernel::Sve);
+            }
+            if std::arch::is_aarch64_feature_detected!("neon") {
+                return Some(Microkernel::Neon);
+            }
+        }
+        #[cfg(all(target_arch = "wasm32", target_feature = "simd128"))]
+        return Some(Microkernel::Simd128);
+        #[cfg(feature = "portable-simd")]
+        return Some(Microkernel::Portable);
+        #[cfg(feature = "generic-kernel")]
+        return Some(Microkernel::Generic);
+
+        None
+    }
+
+    /// Short name recorded in serialized `PackedB` headers, one of `ISA_NAMES`
+    pub(crate) fn name(self) -> &'static str {
+        match self {
+            #[cfg(target_arch = "x86_64")]
+            Microkernel::Avx512 => "avx512",
+            #[cfg(target_arch = "x86_64")]
+            Microkernel::Fma => "fma",
+            #[cfg(target_arch = "aarch64")]
+            Microkernel::Sve => "sve",
+            #[cfg(target_arch = "aarch64")]
+            Microkernel::Neon => "neon",
+            #[cfg(all(target_arch = "wasm32", target_feature = "simd128"))]
+            Microkernel::Simd128 => "simd128",
+            #[cfg(feature = "portable-simd")]
+            Microkernel::Portable => "portable",
+            #[cfg(feature = "generic-kernel")]
+            Microkernel::Generic => "generic",
+        }
+    }
+
+    /// Rows and columns of the `c` tile. `Sve`'s width is read from the CPU, so a packing cut
+    /// for it is only native on machines with the same vector length.
+    pub(crate) fn tile(self) -> (usize, usize) {
+        match self {
+            #[cfg(target_arch = "x86_64")]
+            Microkernel::Avx512 => (14, 32),
+            #[cfg(target_arch = "x86_64")]
+            Microkernel::Fma => (6, 16),
+            // `Sve` only comes from `detect`, which checked for SVE
+            #[cfg(target_arch = "aarch64")]
+            Microkernel::Sve => (8, 2 * unsafe { sve_lanes() }),
+            #[cfg(target_arch = "aarch64")]
+            Microkernel::Neon => (8, 12),
+            #[cfg(all(target_arch = "wasm32", target_feature = "simd128"))]
+            Microkernel::Simd128 => (4, 8),
+            #[cfg(feature = "portable-simd")]
+            Microkernel::Portable => (4, 16),
+            #[cfg(feature = "generic-kernel")]
+            Microkernel::Generic => (4, 16),
+        }
+    }
+
+    /// `c += a @ b` for one whole tile, `c` with row stride `ldc`.
+    ///
+    /// The kernel must come from `detect` on this machine; the slices are checked to cover the
+    /// tile before any raw loads.
+    pub(crate) fn run(self, kc: usize, a: &[f32], b: &[f32], c: &mut [f32], ldc: usize) {
+        let (mr, nr) = self.tile();
+        assert!(a.len() >= kc * mr && b.len() >= kc * nr);
+        assert!(ldc >= nr && c.len() >= (mr - 1) * ldc + nr);
+
+        // unread where no kernel is compiled in, e.g. on wasm32 without simd128
+        #[allow(unused_variables)]
+        let (a, b, c) = (a.as_ptr(), b.as_ptr(), c.as_mut_ptr());
+        match self {
+            #[cfg(target_arch = "x86_64")]
+            Microkernel::Avx512 => unsafe { kernel_14x32_avx512(kc, a, b, c, ldc) },
+            #[cfg(target_arch = "x86_64")]
+            Microkernel::Fma => unsafe { kernel_6x16_fma(kc, a, b, c, ldc) },
+            #[cfg(target_arch = "aarch64")]
+            Microkernel::Sve => unsafe { kernel_8xv_sve(kc, a, b, c, ldc) },
+            #[cfg(target_arch = "aarch64")]
+            Microkernel::Neon => unsafe { kernel_8x12_neon(kc, a, b, c, ldc) },
+            #[cfg(all(target_arch = "wasm32", target_feature = "simd128"))]
+            Microkernel::Simd128 => unsafe { kernel_4x8_simd128(kc, a, b, c, ldc) },
+            #[cfg(feature = "portable-simd")]
+            Microkernel::Portable => unsafe { kernel_4x16_portable(kc, a, b, c, ldc) },
+            #[cfg(feature = "generic-kernel")]
+            Microkernel::Generic => unsafe { kernel_4x16_generic(kc, a, b, c, ldc) },
+        }
+    }
+}
+
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "avx512f")]
+unsafe fn kernel_14x32_avx512(kc: usize, a: *const f32, b: *const f32, c: *mut f32, ldc: usize) {
+    let mut c_tile = [[_mm512_setzero_ps(); 2]; 14];
+    for (i, c_i) in c_tile.iter_mut().enumerate() {
+        c_i[0] = _mm512_loadu_ps(c.add(i * ldc));
+        c_i[1] = _mm512_loadu_ps(c.add(i * ldc + 16));
+    }
+
+    for p in 0..kc {
+        let b0 = _mm512_loadu_ps(b.add(p * 32));
+        let b1 = _mm512_loadu_ps(b.add(p * 32 + 16));
+        for (i, c_i) in c_tile.iter_mut().enumerate() {
+            let a_ip = _mm512_set1_ps(*a.add(p * 14 + i));
+            c_i[0] = _mm512_fmadd_ps(a_ip, b0, c_i[0]);
+            c_i[1] = _mm512_fmadd_ps(a_ip, b1, c_i[1]);
+        }
+    }
+
+    for (i, c_i) in c_tile.iter().enumerate() {
+        _mm512_storeu_ps(c.add(i * ldc), c_i[0]);
+        _mm512_storeu_ps(c.add(i * ldc + 16), c_i[1]);
+    }
+}
+
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "avx2,fma")]
+unsafe fn kernel_6x16_fma(kc: usize, a: *const f32, b: *const f32, c: *mut f32, ldc: usize) {
+    let mut c_tile = [[_mm256_setzero_ps(); 2]; 6];
+    for (i, c_i) in c_tile.iter_mut().enumerate() {
+        c_i[0] = _mm256_loadu_ps(c.add(i * ldc));
+        c_i[1] = _mm256_loadu_ps(c.add(i * ldc + 8));
+    }
+
+    for p in 0..kc {
+        let b0 = _mm256_loadu_ps(b.add(p * 16));
+        let b1 = _mm256_loadu_ps(b.add(p * 16 + 8));
+        for (i, c_i) in c_tile.iter_mut().enumerate() {
+            let a_ip = _mm256_broadcast_ss(&*a.add(p * 6 + i));
+            c_i[0] = _mm256_fmadd_ps(a_ip, b0, c_i[0]);
+            c_i[1] = _mm256_fmadd_ps(a_ip, b1, c_i[1]);
+        }
+    }
+
+    for (i, c_i) in c_tile.iter().enumerate() {
+        _mm256_storeu_ps(c.add(i * ldc), c_i[0]);
+        _mm256_storeu_ps(c.add(i * ldc + 8), c_i[1]);
+    }
+}
+
+/// Lanes in an SVE vector of f32, 4 per 128 bits
+#[cfg(target_arch = "aarch64")]
+#[target_feature(enable = "sve")]
+unsafe fn sve_lanes() -> usize {
+    let lanes: usize;
+    std::arch::asm!("cntw {lanes}", lanes = out(reg) lanes, options(nomem, nostack, pure));
+    lanes
+}
+
+/// Vector length agnostic, as `blas1::sdot_sve`: each row of the tile is two whole vectors, so
+/// `nr` follows the CPU and no lane is predicated off. `a` is broadcast a value at a time with
+/// `ld1rw`, alternating between two registers so each load can overlap the last row's FMAs.
+///
+/// Written as inline asm because the SVE intrinsics are not stable yet, naming its z registers
+/// as `blas1::sdot_sve` does.
+#[cfg(target_arch = "aarch64")]
+#[target_feature(enable = "sve")]
+unsafe fn kernel_8xv_sve(kc: usize, a: *const f32, b: *const f32, c: *mut f32, ldc: usize) {
+    std::arch::asm!(
+        "ptrue p0.s",
+        "mov {row}, {c}",
+        "ld1w {{ z16.s }}, p0/z, [{row}]",
+        "ld1w {{ z17.s }}, p0/z, [{row}, #1, mul vl]",
+        "add {row}, {row}, {ldc}",
+        "ld1w {{ z18.s }}, p0/z, [{row}]",
+        "ld1w {{ z19.s }}, p0/z, [{row}, #1, mul vl]",
+        "add {row}, {row}, {ldc}",
+        "ld1w {{ z20.s }}, p0/z, [{row}]",
+        "ld1w {{ z21.s }}, p0/z, [{row}, #1, mul vl]",
+        "add {row}, {row}, {ldc}",
+        "ld1w {{ z22.s }}, p0/z, [{row}]",
+        "ld1w {{ z23.s }}, p0/z, [{row}, #1, mul vl]",
+        "add {row}, {row}, {ldc}",
+        "ld1w {{ z24.s }}, p0/z, [{row}]",
+        "ld1w {{ z25.s }}, p0/z, [{row}, #1, mul vl]",
+        "add {row}, {row}, {ldc}",
+        "ld1w {{ z26.s }}, p0/z, [{row}]",
+        "ld1w {{ z27.s }}, p0/z, [{row}, #1, mul vl]",
+        "add {row}, {row}, {ldc}",
+        "ld1w {{ z28.s }}, p0/z, [{row}]",
+        "ld1w {{ z29.s }}, p0/z, [{row}, #1, mul vl]",
+        "add {row}, {row}, {ldc}",
+        "ld1w {{ z30.s }}, p0/z, [{row}]",
+        "ld1w {{ z31.s }}, p0/z, [{row}, #1, mul vl]",
+        "cbz {kc}, 2f",
+        "1:",
+        "ld1w {{ z0.s }}, p0/z, [{b}]",
+        "ld1w {{ z1.s }}, p0/z, [{b}, #1, mul vl]",
+        "ld1rw {{ z2.s }}, p0/z, [{a}, #0]",
+        "fmla z16.s, p0/m, z0.s, z2.s",
+        "fmla z17.s, p0/m, z1.s, z2.s",
+        "ld1rw {{ z3.s }}, p0/z, [{a}, #4]",
+        "fmla z18.s, p0/m, z0.s, z3.s",
+        "fmla z19.s, p0/m, z1.s, z3.s",
+        "ld1rw {{ z2.s }}, p0/z, [{a}, #8]",
+        "fmla z20.s, p0/m, z0.s, z2.s",
+        "fmla z21.s, p0/m, z1.s, z2.s",
+        "ld1rw {{ z3.s }}, p0/z, [{a}, #12]",
+        "fmla z22.s, p0/m, z0.s, z3.s",
+        "fmla z23.s, p0/m, z1.s, z3.s",
+        "ld1rw {{ z2.s }}, p0/z, [{a}, #16]",
+        "fmla z24.s, p0/m, z0.s, z2.s",
+        "fmla z25.s, p0/m, z1.s, z2.s",
+        "ld1rw {{ z3.s }}, p0/z, [{a}, #20]",
+        "fmla z26.s, p0/m, z0.s, z3.s",
+        "fmla z27.s, p0/m, z1.s, z3.s",
+        "ld1rw {{ z2.s }}, p0/z, [{a}, #24]",
+        "fmla z28.s, p0/m, z0.s, z2.s",
+        "fmla z29.s, p0/m, z1.s, z2.s",
+        "ld1rw {{ z3.s }}, p0/z, [{a}, #28]",
+        "fmla z30.s, p0/m, z0.s, z3.s",
+        "fmla z31.s, p0/m, z1.s, z3.s",
+        "add {a}, {a}, #32",
+        "addvl {b}, {b}, #2",
+        "subs {kc}, {kc}, #1",
+        "b.ne 1b",
+        "2:",
+        "mov {row}, {c}",
+        "st1w {{ z16.s }}, p0, [{row}]",
+        "st1w {{ z17.s }}, p0, [{row}, #1, mul vl]",
+        "add {row}, {row}, {ldc}",
+        "st1w {{ z18.s }}, p0, [{row}]",
+        "st1w {{ z19.s }}, p0, [{row}, #1, mul vl]",
+        "add {row}, {row}, {ldc}",
+        "st1w {{ z20.s }}, p0, [{row}]",
+        "st1w {{ z21.s }}, p0, [{row}, #1, mul vl]",
+        "add {row}, {row}, {ldc}",
+        "st1w {{ z22.s }}, p0, [{row}]",
+        "st1w {{ z23.s }}, p0, [{row}, #1, mul vl]",
+        "add {row}, {row}, {ldc}",
+        "st1w {{ z24.s }}, p0, [{row}]",
+        "st1w {{ z25.s }}, p0, [{row}, #1, mul vl]",
+        "add {row}, {row}, {ldc}",
+        "st1w {{ z26.s }}, p0, [{row}]",
+        "st1w {{ z27.s }}, p0, [{row}, #1, mul vl]",
+        "add {row}, {row}, {ldc}",
+        "st1w {{ z28.s }}, p0, [{row}]",
+        "st1w {{ z29.s }}, p0, [{row}, #1, mul vl]",
+        "add {row}, {row}, {ldc}",
+        "st1w {{ z30.s }}, p0, [{row}]",
+        "st1w {{ z31.s }}, p0, [{row}, #1, mul vl]",
+        c = in(reg) c,
+        ldc = in(reg) ldc * 4,
+        a = inout(reg) a => _,
+        b = inout(reg) b => _,
+        row = out(reg) _,
+        kc = inout(reg) kc => _,
+        out("v0") _,
+        out("v1") _,
+        out("v2") _,
+        out("v3") _,
+        out("v16") _,
+        out("v17") _,
+        out("v18") _,
+        out("v19") _,
+        out("v20") _,
+        out("v21") _,
+        out("v22") _,
+        out("v23") _,
+        out("v24") _,
+        out("v25") _,
+        out("v26") _,
+        out("v27") _,
+        out("v28") _,
+        out("v29") _,
+        out("v30") _,
+        out("v31") _,
+        out("p0") _,
+        options(nostack),
+    );
+}
+
+#[cfg(target_arch = "aarch64")]
+#[target_feature(enable = "neon")]
+unsafe fn kernel_8x12_neon(kc: usize, a: *const f32, b: *const f32, c: *mut f32, ldc: usize) {
+    let mut c_tile = [[vdupq_n_f32(0f32); 3]; 8];
+    for (i, c_i) in c_tile.iter_mut().enumerate() {
+        for (v, c_iv) in c_i.iter_mut().enumerate() {
+            *c_iv = vld1q_f32(c.add(i * ldc + 4 * v));
+        }
+    }
+
+    for p in 0..kc {
+        let b_p = [
+            vld1q_f32(b.add(p * 12)),
+            vld1q_f32(b.add(p * 12 + 4)),
+            vld1q_f32(b.add(p * 12 + 8)),
+        ];
+        for (i, c_i) in c_tile.iter_mut().enumerate() {
+            let a_ip = vdupq_n_f32(*a.add(p * 8 + i));
+            for (c_iv, b_pv) in c_i.iter_mut().zip(b_p) {
+                *c_iv = vfmaq_f32(*c_iv, a_ip, b_pv);
+            }
+        }
+    }
+
+    for (i, c_i) in c_tile.iter().enumerate() {
+        for (v, c_iv) in c_i.iter().enumerate() {
+            vst1q_f32(c.add(i * ldc + 4 * v), *c_iv);
+        }
+    }
+}
+
+#[cfg(all(target_arch = "wasm32", target_feature = "simd128"))]
+unsafe fn kernel_4x8_simd128(kc: usize, a: *const f32, b: *const f32, c: *mut f32, ldc: usize) {
+    let mut c_tile = [[f32x4_splat(0f32); 2]; 4];
+    for (i, c_i) in c_tile.iter_mut().enumerate() {
+        c_i[0] = v128_load(c.add(i * ldc).cast());
+        c_i[1] = v128_load(c.add(i * ldc + 4).cast());
+    }
+
+    for p in 0..kc {
+        let b0 = v128_load(b.add(p * 8).cast());
+        let b1 = v128_load(b.add(p * 8 + 4).cast());
+        for (i, c_i) in c_tile.iter_mut().enumerate() {
+            let a_ip = f32x4_splat(*a.add(p * 4 + i));
+            c_i[0] = blas1::madd_simd128(a_ip, b0, c_i[0]);
+            c_i[1] = blas1::madd_simd128(a_ip, b1, c_i[1]);
+        }
+    }
+
+    for (i, c_i) in c_tile.iter().enumerate() {
+        v128_store(c.add(i * ldc).cast(), c_i[0]);
+        v128_store(c.add(i * ldc + 4).cast(), c_i[1]);
+    }
+}
+
+#[cfg(feature = "portable-simd")]
+unsafe fn kernel_4x16_portable(kc: usize, a: *const f32, b: *const f32, c: *mut f32, ldc: usize) {
+    use std::simd::{f32x8, StdFloat};
+    use std::slice::{from_raw_parts, from_raw_parts_mut};
+
+    let mut c_tile = [[f32x8::splat(0f32); 2]; 4];
+    for (i, c_i) in c_tile.iter_mut().enumerate() {
+        let c_row = from_raw_parts(c.add(i * ldc), 16);
+        c_i[0] = f32x8::from_slice(&c_row[..8]);
+        c_i[1] = f32x8::from_slice(&c_row[8..]);
+    }
+
+    for p in 0..kc {
+        let b_p = from_raw_parts(b.add(p * 16), 16);
+        let (b0, b1) = (f32x8::from_slice(&b_p[..8]), f32x8::from_slice(&b_p[8..]));
+        for (i, c_i) in c_tile.iter_mut().enumerate() {
+            let a_ip = f32x8::splat(*a.add(p * 4 + i));
+            c_i[0] = a_ip.mul_add(b0, c_i[0]);
+            c_i[1] = a_ip.mul_add(b1, c_i[1]);
+        }
+    }
+
+    for (i, c_i) in c_tile.iter().enumerate() {
+        let c_row = from_raw_parts_mut(c.add(i * ldc), 16);
+        c_i[0].copy_to_slice(&mut c_row[..8]);
+        c_i[1].copy_to_slice(&mut c_row[8..]);
+    }
+}
+
+#[cfg(feature = "generic-kernel")]
+unsafe fn kernel_4x16_generic(kc: usize, a: *const f32, b: *const f32, c: *mut f32, ldc: usize) {
+    use std::slice::{from_raw_parts, from_raw_parts_mut};
+
+    let mut c_tile = [[0f32; 16]; 4];
+    for (i, c_i) in c_tile.iter_mut().enumerate() {
+        c_i.copy_from_slice(from_raw_parts(c.add(i * ldc), 16));
+    }
+
+    // fixed trip counts over arrays keep the tile in registers, one vector op per row chunk
+    let (a, b) = (from_raw_parts(a, kc * 4), from_raw_parts(b, kc * 16));
+    for (a_p, b_p) in a.chunks_exact(4).zip(b.chunks_exact(16)) {
+        for (c_i, a_ip) in c_tile.iter_mut().zip(a_p) {
+            for (c_ij, b_pj) in c_i.iter_mut().zip(b_p) {
+                *c_ij += a_ip * b_pj;
+            }
+        }
+    }
+
+    for (i, c_i) in c_tile.iter().enumerate() {
+        from_raw_parts_mut(c.add(i * ldc), 16).copy_from_slice(c_i);
+    }
+}
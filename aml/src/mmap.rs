@@ -0,0 +1,191 @@
+//! Tensors read straight out of memory-mapped files.
+//!
+//! With the `mmap` feature (on by default), Unix targets map the file with libc's `mmap` and
+//! `munmap`. Elsewhere, or without the feature, the tensor's bytes are read into memory
+//! instead, which costs one copy but keeps the same interface.
+
+use crate::{AmlError, AsTensorRef, Element, Pod, Shape, Tensor, TensorRef};
+use std::fs::File;
+use std::io;
+use std::marker::PhantomData;
+use std::path::Path;
+
+const MAPPED: bool = cfg!(all(unix, feature = "mmap"));
+
+/// A row-major tensor whose values are part of a memory-mapped file, made by
+/// `Tensor::from_mmap`. Pages are read from disk as the kernels touch them and shared with
+/// every other mapping of the file, so a weight file many times larger than the free memory
+/// can still be loaded. The mapping is private and read-only, and is dropped with the tensor.
+pub struct MappedTensor<T: Pod> {
+    bytes: FileBytes,
+    shape: Shape,
+    values: PhantomData<T>,
+}
+
+impl<T: Element + Pod> Tensor<T> {
+    /// Map `shape` values stored at byte `offset` of the file at `path`, in native byte order,
+    /// without reading the file into memory. `offset` must be a multiple of the value size and
+    /// the file must hold every value; otherwise the error is `InvalidData` wrapping the
+    /// `AmlError`, as `MisalignedBytes`, `SizeMismatch` in bytes or `ShapeOverflow`.
+    ///
+    /// # Safety
+    ///
+    /// The values are read from the file's pages for as long as the tensor lives, so the file
+    /// must not be truncated or written to, by this process or any other, until it is dropped.
+    /// A truncated file faults on the next read of a page past its end, and a rewritten one
+    /// changes values the kernels have assumed fixed.
+    pub unsafe fn from_mmap(
+        path: impl AsRef<Path>,
+        shape: impl Into<Shape>,
+        offset: usize,
+    ) -> io::Result<MappedTensor<T>> {
+        let shape: Shape = shape.into();
+        let file = File::open(path)?;
+        let file_len = file.metadata()?.len() as usize;
+        let invalid = |e: AmlError| io::Error::new(io::ErrorKind::InvalidData, e);
+
+        let align = std::mem::align_of::<T>();
+        if !offset.is_multiple_of(align) {
+            return Err(invalid(AmlError::MisalignedBytes { align }));
+        }
+        let bytes = shape.byte_len(std::mem::size_of::<T>()).map_err(invalid)?;
+        if offset.saturating_add(bytes) > file_len {
+            return Err(invalid(AmlError::SizeMismatch {
+                expected: offset.saturating_add(bytes),
+                found: file_len,
+            }));
+        }
+        Ok(MappedTensor {
+            bytes: FileBytes::open(&file, offset, bytes)?,
+            shape,
+            values: PhantomData,
+        })
+    }
+}
+
+impl<T: Pod> MappedTensor<T> {
+    pub fn shape(&self) -> &Shape {
+        &self.shape
+    }
+
+    /// Whether the values are mapped from the file rather than read into memory
+    pub fn is_mapped(&self) -> bool {
+        self.bytes.is_mapped()
+    }
+}
+
+impl<T: Element + Pod> AsTensorRef<T> for MappedTensor<T> {
+    fn as_tensor_ref(&self) -> TensorRef<'_, T> {
+        TensorRef::from_bytes(self.bytes.bytes(), self.shape.clone())
+    }
+}
+
+/// `len` bytes from byte `offset` of a file, mapped where `mmap` is available and read into
+/// memory elsewhere. Either way the first byte is at least as aligned as `offset`, up to 8.
+pub(crate) struct FileBytes {
+    storage: Storage,
+}
+
+enum Storage {
+    /// `len` bytes mapped from the start of the file, the wanted bytes `offset` bytes in
+    Mapped {
+        ptr: *const u8,
+        len: usize,
+        offset: usize,
+    },
+    /// The wanted bytes, the first at the start of the buffer
+    Read { buffer: Vec<u64>, len: usize },
+}
+
+// the mapping is read-only and owned by the bytes, as a `Vec` would be
+unsafe impl Send for FileBytes {}
+unsafe impl Sync for FileBytes {}
+
+impl FileBytes {
+    /// The caller checks the file holds `offset + len` bytes
+    pub(crate) fn open(file: &File, offset: usize, len: usize) -> io::Result<FileBytes> {
+        // a mapping starts on a page, so mapping from the start of the file keeps offsets'
+        // alignment; reading starts the buffer at `offset`, which is aligned when it is
+        let storage = match MAPPED && len > 0 {
+            true => Storage::Mapped {
+                ptr: map(file, offset + len)?,
+                len: offset + len,
+                offset,
+            },
+            false => {
+                use std::io::{Read, Seek, SeekFrom};
+
+                let mut buffer = vec![0u64; len.div_ceil(8)];
+                let mut file = file;
+                file.seek(SeekFrom::Start(offset as u64))?;
+                // a `u64` buffer for the alignment, read as bytes
+                let bytes = unsafe {
+                    std::slice::from_raw_parts_mut(buffer.as_mut_ptr().cast::<u8>(), len)
+                };
+                file.read_exact(bytes)?;
+                Storage::Read { buffer, len }
+            }
+        };
+        Ok(FileBytes { storage })
+    }
+
+    pub(crate) fn bytes(&self) -> &[u8] {
+        match &self.storage {
+            Storage::Mapped { ptr, len, offset } => {
+                let bytes = unsafe { std::slice::from_raw_parts(*ptr, *len) };
+                &bytes[*offset..]
+            }
+            Storage::Read { buffer, len } => unsafe {
+                std::slice::from_raw_parts(buffer.as_ptr().cast::<u8>(), *len)
+            },
+        }
+    }
+
+    pub(crate) fn is_mapped(&self) -> bool {
+        matches!(self.storage, Storage::Mapped { .. })
+    }
+}
+
+impl Drop for FileBytes {
+    fn drop(&mut self) {
+        if let Storage::Mapped { ptr, len, .. } = self.storage {
+            unmap(ptr, len);
+        }
+    }
+}
+
+/// `mmap(NULL, len, PROT_READ, MAP_PRIVATE, fd, 0)`
+#[cfg(all(unix, feature = "mmap"))]
+fn map(file: &File, len: usize) -> io::Result<*const u8> {
+    use std::os::fd::AsRawFd;
+
+    let ptr = unsafe {
+        libc::mmap(
+            std::ptr::null_mut(),
+            len,
+            libc::PROT_READ,
+            libc::MAP_PRIVATE,
+            file.as_raw_fd(),
+            0,
+        )
+    };
+    match ptr == libc::MAP_FAILED {
+        true => Err(io::Error::last_os_error()),
+        false => Ok(ptr as *const u8),
+    }
+}
+
+/// `munmap(ptr, len)`. A failure leaves the mapping in place, which only leaks it.
+#[cfg(all(unix, feature = "mmap"))]
+fn unmap(ptr: *const u8, len: usize) {
+    unsafe { libc::munmap(ptr as *mut libc::c_void, len) };
+}
+
+/// Never called: without `mmap`, `MAPPED` is false and every file is read
+#[cfg(not(all(unix, feature = "mmap")))]
+fn map(_file: &File, _len: usize) -> io::Result<*const u8> {
+    Err(io::ErrorKind::Unsupported.into())
+}
+
+#[cfg(not(all(unix, feature = "mmap")))]
+fn unmap(_ptr: *const u8, _len: usize) {}
@@ -0,0 +1,63 @@
+//! Conversions between tensors and `nalgebra`'s `DMatrix`, behind the `nalgebra` feature, so
+//! code built on nalgebra's matrix types can run its large products through aml's GEMMs.
+//!
+//! A `DMatrix` stores its columns back to back, which is a column-major tensor with a leading
+//! dimension of `nrows`, so matrices are borrowed and handed over without a copy. Only a tensor
+//! in another arrangement is copied on its way into a `DMatrix`.
+
+use crate::{AmlError, Element, Layout, Tensor, TensorMut, TensorRef};
+use nalgebra::DMatrix;
+
+/// The BLAS rule that `ld` is at least 1, which a matrix with no rows would otherwise break
+fn ld<T>(matrix: &DMatrix<T>) -> usize {
+    matrix.nrows().max(1)
+}
+
+impl<'a, T: Element> From<&'a DMatrix<T>> for TensorRef<'a, T> {
+    fn from(matrix: &'a DMatrix<T>) -> TensorRef<'a, T> {
+        let shape = [matrix.nrows(), matrix.ncols()];
+        TensorRef::new_with_ld(matrix.as_slice(), shape, Layout::ColMajor, ld(matrix))
+    }
+}
+
+/// `matrix` as the output of a kernel, written in place
+impl<'a, T: Element> From<&'a mut DMatrix<T>> for TensorMut<'a, T> {
+    fn from(matrix: &'a mut DMatrix<T>) -> TensorMut<'a, T> {
+        let (shape, ld) = ([matrix.nrows(), matrix.ncols()], ld(matrix));
+        TensorMut::new_with_ld(matrix.as_mut_slice(), shape, Layout::ColMajor, ld)
+    }
+}
+
+/// Takes over the buffer of `matrix`
+impl<T: Element> From<DMatrix<T>> for Tensor<T> {
+    fn from(matrix: DMatrix<T>) -> Tensor<T> {
+        let (shape, ld) = ([matrix.nrows(), matrix.ncols()], ld(&matrix));
+        Tensor::new_with_ld(matrix.data.into(), shape, Layout::ColMajor, ld)
+    }
+}
+
+impl<T: Element> Tensor<T> {
+    /// The matrix as a `DMatrix`, over the same values if they are column-major and back to
+    /// back, and copied into that order otherwise
+    pub fn into_dmatrix(self) -> DMatrix<T> {
+        self.try_into_dmatrix().unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    /// Fallible version of `into_dmatrix`
+    pub fn try_into_dmatrix(self) -> Result<DMatrix<T>, AmlError> {
+        if self.shape.len() != 2 {
+            return Err(AmlError::RankMismatch {
+                operand: "tensor",
+                expected: 2,
+                found: self.shape.len(),
+            });
+        }
+        let (rows, cols) = (self.shape[0], self.shape[1]);
+        Ok(
+            match self.layout == Layout::ColMajor && self.is_contiguous() {
+                true => DMatrix::from_vec(rows, cols, self.values),
+                false => DMatrix::from_fn(rows, cols, |i, j| self[[i, j]]),
+            },
+        )
+    }
+}
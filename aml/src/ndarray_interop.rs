@@ -0,0 +1,78 @@
+//! Conversions between tensors and `ndarray`'s two dimensional arrays, behind the `ndarray`
+//! feature, so a prototype written against ndarray can hand its hot GEMMs to aml.
+//!
+//! Both sides describe a matrix by a buffer and a stride per axis, so a view with nonnegative
+//! strides is borrowed as it is, gaps and all, and an owned tensor becomes an `Array2` over the
+//! same `Vec`. Only negative strides (a reversed axis, say) are copied, as ndarray counts them
+//! from the other end of the buffer and aml cannot.
+
+use crate::{AmlError, CowTensor, Element, Tensor, TensorRef};
+use ndarray::{Array2, ArrayView2, ShapeBuilder};
+
+/// Borrows `view` unless one of its strides is negative
+impl<'a, T: Element> From<ArrayView2<'a, T>> for CowTensor<'a, T> {
+    fn from(view: ArrayView2<'a, T>) -> CowTensor<'a, T> {
+        let (rows, cols) = view.dim();
+        match view.strides().iter().all(|s| *s >= 0) {
+            true => {
+                let strides = view.strides().iter().map(|s| *s as usize).collect();
+                // the view reads every offset its strides reach, and is shared for all of `'a`
+                CowTensor::Borrowed(unsafe {
+                    TensorRef::from_raw_parts_with_strides(view.as_ptr(), [rows, cols], strides)
+                })
+            }
+            false => CowTensor::Owned(Tensor::new(view.iter().copied().collect(), [rows, cols])),
+        }
+    }
+}
+
+/// Copies `view`, in the same layout when its values are back to back
+impl<T: Element> From<ArrayView2<'_, T>> for Tensor<T> {
+    fn from(view: ArrayView2<'_, T>) -> Tensor<T> {
+        CowTensor::from(view).into_owned()
+    }
+}
+
+/// Takes over the buffer of `array` when its values are back to back, in either order
+impl<T: Element> From<Array2<T>> for Tensor<T> {
+    fn from(array: Array2<T>) -> Tensor<T> {
+        let (rows, cols) = array.dim();
+        let strides: Option<Vec<usize>> = array
+            .strides()
+            .iter()
+            .map(|s| usize::try_from(*s).ok())
+            .collect();
+        match (array.as_slice_memory_order().map(|v| v.len()), strides) {
+            (Some(len), Some(strides)) => {
+                // a sliced array keeps the rest of its buffer around the values
+                let (mut values, offset) = array.into_raw_vec_and_offset();
+                let offset = offset.unwrap_or(0);
+                values.truncate(offset + len);
+                values.drain(..offset);
+                Tensor::new_with_strides(values, [rows, cols], strides)
+            }
+            _ => Tensor::from(array.view()),
+        }
+    }
+}
+
+impl<T: Element> Tensor<T> {
+    /// The matrix as an `Array2` over the same values, in the same layout, without copying them
+    pub fn into_ndarray(self) -> Array2<T> {
+        self.try_into_ndarray().unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    /// Fallible version of `into_ndarray`
+    pub fn try_into_ndarray(self) -> Result<Array2<T>, AmlError> {
+        if self.shape.len() != 2 {
+            return Err(AmlError::RankMismatch {
+                operand: "tensor",
+                expected: 2,
+                found: self.shape.len(),
+            });
+        }
+        let shape = (self.shape[0], self.shape[1]).strides((self.strides[0], self.strides[1]));
+        // the strides were checked against the values when the tensor was made
+        Ok(Array2::from_shape_vec(shape, self.values).expect("strides fit the values"))
+    }
+}
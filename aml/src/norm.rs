@@ -0,0 +1,184 @@
+//! LayerNorm and RMSNorm over the last axis of f32 tensors, as they bracket the GEMMs of a
+//! transformer block.
+//!
+//! Each row is normalized on its own while it is in cache: its mean in one pass, the mean of
+//! its squared deviations from that in a second, rather than the mean of its squares less the
+//! squared mean, which cancels badly once the mean dwarfs the spread, and then the scale and
+//! shift as the result is written. Each pass runs with AVX on x86_64 when the CPU has it, and
+//! rows are split across threads once there are enough of them.
+
+use crate::dispatch::kernels;
+use crate::reduce::Reduce;
+use crate::{parallel, AmlError, AsTensorRef, Layout, Tensor};
+
+#[cfg(target_arch = "x86_64")]
+use std::arch::x86_64::*;
+
+/// `(x - mean(x)) / sqrt(var(x) + eps) * gamma + beta` over each row of `x`, its last axis, as
+/// a new row-major tensor of the same shape. `gamma` and `beta` hold one value per column.
+pub fn layer_norm(x: &impl AsTensorRef<f32>, gamma: &[f32], beta: &[f32], eps: f32) -> Tensor<f32> {
+    try_layer_norm(x, gamma, beta, eps).unwrap_or_else(|e| panic!("{}", e))
+}
+
+/// Fallible version of `layer_norm`.
+pub fn try_layer_norm(
+    x: &impl AsTensorRef<f32>,
+    gamma: &[f32],
+    beta: &[f32],
+    eps: f32,
+) -> Result<Tensor<f32>, AmlError> {
+    check_params(x, beta)?;
+    norm(x, gamma, beta, eps, true)
+}
+
+/// `x / sqrt(mean(x^2) + eps) * gamma` over each row of `x`, as `layer_norm` but neither
+/// centred nor shifted.
+pub fn rms_norm(x: &impl AsTensorRef<f32>, gamma: &[f32], eps: f32) -> Tensor<f32> {
+    try_rms_norm(x, gamma, eps).unwrap_or_else(|e| panic!("{}", e))
+}
+
+/// Fallible version of `rms_norm`.
+pub fn try_rms_norm(
+    x: &impl AsTensorRef<f32>,
+    gamma: &[f32],
+    eps: f32,
+) -> Result<Tensor<f32>, AmlError> {
+    norm(x, gamma, &[], eps, false)
+}
+
+/// `SizeMismatch` unless `params` holds one value per column of `x`
+fn check_params(x: &impl AsTensorRef<f32>, params: &[f32]) -> Result<(), AmlError> {
+    let n = x.as_tensor_ref().shape.last().copied().unwrap_or(1);
+    match params.len() == n {
+        true => Ok(()),
+        false => Err(AmlError::SizeMismatch {
+            expected: n,
+            found: params.len(),
+        }),
+    }
+}
+
+/// Each row less its mean if `center`, over its root mean square from there, scaled by `gamma`
+/// and shifted by `beta` unless that is empty
+fn norm(
+    x: &impl AsTensorRef<f32>,
+    gamma: &[f32],
+    beta: &[f32],
+    eps: f32,
+    center: bool,
+) -> Result<Tensor<f32>, AmlError> {
+    check_params(x, gamma)?;
+    let x = x.as_tensor_ref();
+    let values = x.dense_in(Layout::RowMajor);
+    let n = gamma.len();
+
+    let mut out = vec![0f32; values.len()];
+    if n != 0 {
+        let kernels = kernels();
+        parallel::for_each_row_chunk(&mut out, n, 3 * n, |first_row, out| {
+            let rows = values[first_row * n..].chunks_exact(n);
+            for (row, out) in rows.zip(out.chunks_exact_mut(n)) {
+                let mean = match center {
+                    true => (kernels.reduce)(Reduce::Sum, row) / n as f32,
+                    false => 0f32,
+                };
+                let variance = (kernels.sum_sq_dev)(mean, row) / n as f32;
+                let scale = 1f32 / (variance + eps).sqrt();
+                (kernels.normalize)(row, mean, scale, gamma, beta, out);
+            }
+        });
+    }
+    Ok(Tensor::new(out, x.shape.clone()))
+}
+
+/// Sum of `(x - mean)^2` in 8 independent lanes, as `sdot_scalar`
+pub(crate) fn sum_sq_dev_scalar(mean: f32, x: &[f32]) -> f32 {
+    let mut lanes = [0f32; 8];
+    let chunks = x.chunks_exact(8);
+    let tail: f32 = chunks
+        .remainder()
+        .iter()
+        .map(|v| (v - mean) * (v - mean))
+        .sum();
+
+    for x8 in chunks {
+        for (lane, v) in lanes.iter_mut().zip(x8) {
+            *lane += (v - mean) * (v - mean);
+        }
+    }
+
+    lanes.iter().sum::<f32>() + tail
+}
+
+/// `out = (x - mean) * scale * gamma + beta`, without `beta` if it is empty
+pub(crate) fn normalize_scalar(
+    x: &[f32],
+    mean: f32,
+    scale: f32,
+    gamma: &[f32],
+    beta: &[f32],
+    out: &mut [f32],
+) {
+    for (j, (out, x)) in out.iter_mut().zip(x).enumerate() {
+        let y = (x - mean) * scale * gamma[j];
+        *out = match beta.is_empty() {
+            true => y,
+            false => y + beta[j],
+        };
+    }
+}
+
+/// Two accumulators so consecutive adds do not wait on each other, as `sdot_avx`.
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "avx")]
+pub(crate) unsafe fn sum_sq_dev_avx(mean: f32, x: &[f32]) -> f32 {
+    let n16 = x.len() / 16 * 16;
+    let mean8 = _mm256_set1_ps(mean);
+    let mut acc0 = _mm256_setzero_ps();
+    let mut acc1 = _mm256_setzero_ps();
+
+    for i in (0..n16).step_by(16) {
+        let d0 = _mm256_sub_ps(_mm256_loadu_ps(x.as_ptr().add(i)), mean8);
+        let d1 = _mm256_sub_ps(_mm256_loadu_ps(x.as_ptr().add(i + 8)), mean8);
+        acc0 = _mm256_add_ps(acc0, _mm256_mul_ps(d0, d0));
+        acc1 = _mm256_add_ps(acc1, _mm256_mul_ps(d1, d1));
+    }
+
+    let mut lanes = [0f32; 8];
+    _mm256_storeu_ps(lanes.as_mut_ptr(), _mm256_add_ps(acc0, acc1));
+    lanes.iter().sum::<f32>() + sum_sq_dev_scalar(mean, &x[n16..])
+}
+
+/// The same steps as `normalize_scalar`, so the result is the same bit for bit
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "avx")]
+pub(crate) unsafe fn normalize_avx(
+    x: &[f32],
+    mean: f32,
+    scale: f32,
+    gamma: &[f32],
+    beta: &[f32],
+    out: &mut [f32],
+) {
+    assert!(x.len() == out.len() && gamma.len() == out.len());
+    assert!(beta.is_empty() || beta.len() == out.len());
+    let n8 = out.len() / 8 * 8;
+    let (mean8, scale8) = (_mm256_set1_ps(mean), _mm256_set1_ps(scale));
+
+    for i in (0..n8).step_by(8) {
+        let x8 = _mm256_loadu_ps(x.as_ptr().add(i));
+        let gamma8 = _mm256_loadu_ps(gamma.as_ptr().add(i));
+        let y8 = _mm256_mul_ps(_mm256_mul_ps(_mm256_sub_ps(x8, mean8), scale8), gamma8);
+        let y8 = match beta.is_empty() {
+            true => y8,
+            false => _mm256_add_ps(y8, _mm256_loadu_ps(beta.as_ptr().add(i))),
+        };
+        _mm256_storeu_ps(out.as_mut_ptr().add(i), y8);
+    }
+
+    let beta = match beta.is_empty() {
+        true => beta,
+        false => &beta[n8..],
+    };
+    normalize_scalar(&x[n8..], mean, scale, &gamma[n8..], beta, &mut out[n8..]);
+}
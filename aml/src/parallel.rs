@@ -0,0 +1,289 @@
+//! Helpers for splitting kernels across threads.
+
+use std::cell::Cell;
+use std::sync::OnceLock;
+
+thread_local! {
+    /// The innermost `AmlContext::install` on this thread
+    static INSTALLED: Cell<Option<AmlContext>> = const { Cell::new(None) };
+}
+
+/// Threading for the kernels called inside `install`, so the library can share the machine
+/// with an application that has its own parallelism.
+///
+/// Kernels split their work over scoped threads spawned per call and joined before returning,
+/// so nothing outlives a call. A context with one thread runs every kernel on the caller's
+/// thread, e.g. when the application already runs one inference per core. Without the
+/// `parallel` feature that is always the case and contexts change nothing.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AmlContext {
+    threads: usize,
+    pin_threads: bool,
+    numa: bool,
+    deterministic: bool,
+}
+
+impl AmlContext {
+    /// At most `threads` threads per kernel call. 0 is taken as 1.
+    pub fn new(threads: usize) -> AmlContext {
+        AmlContext {
+            threads: threads.max(1),
+            pin_threads: false,
+            numa: false,
+            deterministic: false,
+        }
+    }
+
+    /// Pin each worker thread to its own physical core, skipping SMT siblings, which keeps a
+    /// GEMM's threads from sharing a core's caches and FMA units on many-core servers.
+    ///
+    /// Linux on x86_64 and aarch64 only; elsewhere, or if the topology cannot be read, threads
+    /// are left where the OS puts them. The calling thread is never pinned.
+    pub fn pin_threads(self, pin_threads: bool) -> AmlContext {
+        AmlContext {
+            pin_threads,
+            ..self
+        }
+    }
+
+    /// On machines with more than one NUMA node, give each node a share of the threads and of
+    /// the rows of `c`, with its own copy of the packed `b` in local memory, so no thread reads
+    /// `b` across the interconnect. `sgemm` only; Linux only, like `pin_threads`.
+    pub fn numa(self, numa: bool) -> AmlContext {
+        AmlContext { numa, ..self }
+    }
+
+    /// Bit identical `sgemm` results across runs, thread counts and machines with the same
+    /// kernels, for reproducible CI and audits.
+    ///
+    /// Every output is summed by one thread in a fixed order, so thread count, pinning, NUMA
+    /// placement and the grid never change a result. What can is the panel depth, so this
+    /// skips autotuning and keeps to `block_sizes()`.
+    pub fn deterministic(self, deterministic: bool) -> AmlContext {
+        AmlContext {
+            deterministic,
+            ..self
+        }
+    }
+
+    /// Thread limit of this context
+    pub fn threads(&self) -> usize {
+        self.threads
+    }
+
+    /// This context with its thread limit changed, for the kernels run inside a split
+    pub(crate) fn with_threads(self, threads: usize) -> AmlContext {
+        AmlContext {
+            threads: threads.max(1),
+            ..self
+        }
+    }
+
+    /// Run `f` with every kernel it calls on this thread limited to this context's threads.
+    /// Contexts nest; the previous one is restored when `f` returns or panics. The threads a
+    /// kernel spawns run inside the context too, so its policies hold in every one of them.
+    pub fn install<R>(&self, f: impl FnOnce() -> R) -> R {
+        struct Restore(Option<AmlContext>);
+        impl Drop for Restore {
+            fn drop(&mut self) {
+                INSTALLED.with(|installed| installed.set(self.0));
+            }
+        }
+
+        let _restore = Restore(INSTALLED.with(|installed| installed.replace(Some(*self))));
+        f()
+    }
+}
+
+/// The innermost `AmlContext` installed on this thread, to carry over to another
+pub(crate) fn installed() -> Option<AmlContext> {
+    INSTALLED.with(|installed| installed.get())
+}
+
+/// Whether the installed `AmlContext` asks for reproducible results
+pub(crate) fn deterministic() -> bool {
+    INSTALLED.with(|installed| installed.get().is_some_and(|context| context.deterministic))
+}
+
+/// Whether the installed `AmlContext` asks for NUMA placement
+pub(crate) fn numa() -> bool {
+    INSTALLED.with(|installed| installed.get().is_some_and(|context| context.numa))
+}
+
+/// Number of threads a kernel should split its work across: the installed `AmlContext` if any,
+/// else `AML_NUM_THREADS` if it is set to a positive integer, else one per core. Always 1
+/// without the `parallel` feature.
+pub(crate) fn num_threads() -> usize {
+    match cfg!(feature = "parallel") {
+        true => INSTALLED
+            .with(|installed| installed.get())
+            .map_or_else(default_threads, |context| context.threads),
+        false => 1,
+    }
+}
+
+/// Thread count outside any `AmlContext`. The environment is read once, on first use.
+fn default_threads() -> usize {
+    static THREADS: OnceLock<usize> = OnceLock::new();
+
+    *THREADS.get_or_init(|| {
+        std::env::var("AML_NUM_THREADS")
+            .ok()
+            .and_then(|threads| parse_threads(&threads))
+            .unwrap_or_else(|| {
+                std::thread::available_parallelism()
+                    .map(|n| n.get())
+                    .unwrap_or(1)
+            })
+    })
+}
+
+/// A positive thread count, as in `OMP_NUM_THREADS`
+pub(crate) fn parse_threads(threads: &str) -> Option<usize> {
+    threads.trim().parse().ok().filter(|threads| *threads > 0)
+}
+
+/// Roughly how many multiply-adds a thread should get before splitting is worth a spawn.
+const MIN_WORK_PER_THREAD: usize = 1 << 15;
+
+/// GEMMs with fewer multiply-adds than this (64 x 64 x 64) run on the caller's thread, where
+/// spawning would cost about as long as the whole product. `Gemm::serial_threshold` changes it
+/// per call.
+pub(crate) const SERIAL_THRESHOLD: usize = 1 << 18;
+
+/// Run `f(first_row, rows)` over contiguous chunks of whole rows of `out`, one chunk per thread.
+///
+/// Rows start `row_len` values apart, the last possibly shorter, and cost about `work_per_row`
+/// multiply-adds each. Problems too small to be worth a thread run inline on the caller.
+pub(crate) fn for_each_row_chunk<T, F>(out: &mut [T], row_len: usize, work_per_row: usize, f: F)
+where
+    T: Send,
+    F: Fn(usize, &mut [T]) + Sync,
+{
+    for_each_row_chunk_with(num_threads(), out, row_len, work_per_row, f)
+}
+
+/// `for_each_row_chunk` on at most `max_threads` threads.
+pub(crate) fn for_each_row_chunk_with<T, F>(
+    max_threads: usize,
+    out: &mut [T],
+    row_len: usize,
+    work_per_row: usize,
+    f: F,
+) where
+    T: Send,
+    F: Fn(usize, &mut [T]) + Sync,
+{
+    let rows = out.len().div_ceil(row_len.max(1));
+    let min_rows = MIN_WORK_PER_THREAD.div_ceil(work_per_row.max(1));
+    let threads = max_threads.min(rows / min_rows.max(1)).max(1);
+
+    if threads == 1 {
+        f(0, out);
+        return;
+    }
+
+    let rows_per_thread = rows.div_ceil(threads);
+    spawn_chunks(out, rows_per_thread * row_len, |chunk_idx, chunk| {
+        f(chunk_idx * rows_per_thread, chunk)
+    });
+}
+
+/// Run `f(first, items)` over about equal contiguous chunks of `items`, one chunk per thread,
+/// the items costing about `work` multiply-adds between them.
+///
+/// Less work than `SERIAL_THRESHOLD` runs inline on the caller, and each thread gets at least
+/// `MIN_WORK_PER_THREAD` of it. The threads a short list leaves over are shared out among the
+/// chunks, so the kernels `f` calls on each item split across its share.
+pub(crate) fn for_each_chunk<T, F>(items: &mut [T], work: usize, f: F)
+where
+    T: Send,
+    F: Fn(usize, &mut [T]) + Sync,
+{
+    let threads = match work < SERIAL_THRESHOLD {
+        true => 1,
+        false => num_threads()
+            .min(items.len())
+            .min(work / MIN_WORK_PER_THREAD)
+            .max(1),
+    };
+    if threads == 1 {
+        f(0, items);
+        return;
+    }
+
+    let per_thread = items.len().div_ceil(threads);
+    let context = installed()
+        .unwrap_or(AmlContext::new(1))
+        .with_threads(num_threads() / threads);
+    spawn_chunks(items, per_thread, |chunk_idx, chunk| {
+        context.install(|| f(chunk_idx * per_thread, chunk))
+    });
+}
+
+/// Rows and columns of a grid of blocks over a `rows` x `cols` output, each output costing
+/// about `work_per_value` multiply-adds and blocks cut only every `col_step` columns.
+///
+/// At most `max_threads` blocks, each worth a spawn, and as many as the shape allows; among
+/// grids with that many blocks, the one with the squarest blocks, which share the most of `a`
+/// and `b` between neighbouring outputs.
+pub(crate) fn grid(
+    max_threads: usize,
+    rows: usize,
+    cols: usize,
+    col_step: usize,
+    work_per_value: usize,
+) -> (usize, usize) {
+    let work = rows.saturating_mul(cols).saturating_mul(work_per_value);
+    let threads = max_threads.min(work / MIN_WORK_PER_THREAD).max(1);
+    let steps = cols.div_ceil(col_step.max(1)).max(1);
+    let skew = |(grid_rows, grid_cols): (usize, usize)| {
+        let block_rows = rows.div_ceil(grid_rows);
+        let block_cols = (steps.div_ceil(grid_cols) * col_step).min(cols);
+        block_rows.max(block_cols) as f64 / block_rows.min(block_cols).max(1) as f64
+    };
+
+    let mut best = (1, 1);
+    for grid_rows in 1..=threads.min(rows.max(1)) {
+        let candidate = (grid_rows, (threads / grid_rows).min(steps));
+        let (blocks, best_blocks) = (candidate.0 * candidate.1, best.0 * best.1);
+        if blocks > best_blocks || (blocks == best_blocks && skew(candidate) < skew(best)) {
+            best = candidate;
+        }
+    }
+
+    best
+}
+
+/// `f(chunk_idx, chunk)` for every `chunk_len` long chunk of `out`, each on a scoped thread
+/// with the caller's `AmlContext` installed. The only place threads are spawned; without the
+/// `parallel` feature the chunks run in turn.
+pub(crate) fn spawn_chunks<T, F>(out: &mut [T], chunk_len: usize, f: F)
+where
+    T: Send,
+    F: Fn(usize, &mut [T]) + Sync,
+{
+    #[cfg(feature = "parallel")]
+    {
+        let context = installed();
+        std::thread::scope(|scope| {
+            for (chunk_idx, chunk) in out.chunks_mut(chunk_len).enumerate() {
+                let f = &f;
+                scope.spawn(move || match context {
+                    Some(context) => {
+                        if context.pin_threads {
+                            crate::affinity::pin_to_core(chunk_idx);
+                        }
+                        context.install(|| f(chunk_idx, chunk))
+                    }
+                    None => f(chunk_idx, chunk),
+                });
+            }
+        });
+    }
+
+    #[cfg(not(feature = "parallel"))]
+    for (chunk_idx, chunk) in out.chunks_mut(chunk_len).enumerate() {
+        f(chunk_idx, chunk);
+    }
+}
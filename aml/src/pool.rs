@@ -0,0 +1,105 @@
+//! Recycled tensor storage for servers that run one inference per request.
+//!
+//! Every request needs the same handful of output and scratch buffers. Handing them back to a
+//! `BufferPool` when a request is done, instead of dropping them, lets the next request reuse
+//! them without going back to the allocator.
+
+use crate::{AmlError, Element, Shape, Tensor, TensorAllocator};
+use std::collections::BTreeMap;
+use std::sync::{Mutex, MutexGuard};
+
+/// Buffers kept per size class unless `with_limit` says otherwise
+const DEFAULT_LIMIT: usize = 16;
+
+/// Free buffers grouped by size class, shared by every thread that holds a reference.
+///
+/// A class is a power of two: a buffer for `len` values comes from class
+/// `len.next_power_of_two()` and is allocated with that capacity, so one buffer serves every
+/// length up to it. Buffers released beyond the limit of their class are dropped.
+#[derive(Debug)]
+pub struct BufferPool<T: Element = f32> {
+    free: Mutex<BTreeMap<usize, Vec<Vec<T>>>>,
+    limit: usize,
+}
+
+impl<T: Element> BufferPool<T> {
+    pub fn new() -> BufferPool<T> {
+        BufferPool::with_limit(DEFAULT_LIMIT)
+    }
+
+    /// Keep at most `limit` free buffers in each size class
+    pub fn with_limit(limit: usize) -> BufferPool<T> {
+        BufferPool {
+            free: Mutex::new(BTreeMap::new()),
+            limit,
+        }
+    }
+
+    /// A row-major tensor of zeros, in a recycled buffer if one of its size class is free
+    pub fn acquire(&self, shape: impl Into<Shape>) -> Tensor<T> {
+        self.try_acquire(shape).unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    /// Fallible version of `acquire`
+    pub fn try_acquire(&self, shape: impl Into<Shape>) -> Result<Tensor<T>, AmlError> {
+        Tensor::try_zeros_in(shape, self)
+    }
+
+    /// Return a tensor's storage to the pool, whatever its shape or strides
+    pub fn release(&self, tensor: Tensor<T>) {
+        tensor.release_in(self)
+    }
+
+    /// Free buffers held, across every size class
+    pub fn len(&self) -> usize {
+        self.free().values().map(Vec::len).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Drop every free buffer
+    pub fn clear(&self) {
+        self.free().clear()
+    }
+
+    // a panic while the lock was held cannot leave the map half updated
+    fn free(&self) -> MutexGuard<'_, BTreeMap<usize, Vec<Vec<T>>>> {
+        self.free.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl<T: Element> Default for BufferPool<T> {
+    fn default() -> BufferPool<T> {
+        BufferPool::new()
+    }
+}
+
+impl<T: Element> TensorAllocator<T> for BufferPool<T> {
+    fn allocate(&self, len: usize) -> Vec<T> {
+        let class = size_class(len);
+        let recycled = self.free().get_mut(&class).and_then(Vec::pop);
+        let mut values = recycled.unwrap_or_else(|| Vec::with_capacity(class));
+        values.clear();
+        values.resize(len, T::ZERO);
+        values
+    }
+
+    fn release(&self, values: Vec<T>) {
+        // the largest class the capacity covers whole, so buffers never serve longer lengths
+        let class = match values.capacity() {
+            0 => return,
+            capacity => 1 << capacity.ilog2(),
+        };
+        let mut free = self.free();
+        let buffers = free.entry(class).or_default();
+        if buffers.len() < self.limit {
+            buffers.push(values);
+        }
+    }
+}
+
+fn size_class(len: usize) -> usize {
+    len.max(1).next_power_of_two()
+}
@@ -0,0 +1,316 @@
+//! Reductions of f32 tensors: `sum`, `mean`, `max`, `min` and `argmax`, of every value as one
+//! scalar or along one axis into a tensor without that axis.
+//!
+//! Sums are pairwise: each half of a long run is summed apart before the two are added, down
+//! to blocks the kernels sum in the 8 lanes of a vector, so rounding error grows with the log
+//! of the length instead of the length. `max` and `min` skip NaNs, as `f32::max` does. A run of
+//! values is reduced by AVX on x86_64 when the CPU has it; along an axis whose values are not
+//! adjacent, whole rows of partial results are updated at once instead. Reductions along an
+//! axis split their outputs across threads once there is enough work.
+
+use crate::dispatch::kernels;
+use crate::{parallel, AmlError, AsTensorRef, Layout, Shape, Tensor, TensorRef};
+
+#[cfg(target_arch = "x86_64")]
+use std::arch::x86_64::*;
+
+/// Values summed by the kernel in one go before a sum is split in halves
+const PAIRWISE_BLOCK: usize = 256;
+
+/// How values are combined
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Reduce {
+    Sum,
+    Max,
+    Min,
+}
+
+impl Reduce {
+    /// The result over no values
+    fn identity(self) -> f32 {
+        match self {
+            Reduce::Sum => 0f32,
+            Reduce::Max => f32::NEG_INFINITY,
+            Reduce::Min => f32::INFINITY,
+        }
+    }
+
+    fn apply(self, acc: f32, x: f32) -> f32 {
+        match self {
+            Reduce::Sum => acc + x,
+            Reduce::Max => acc.max(x),
+            Reduce::Min => acc.min(x),
+        }
+    }
+
+    /// `_mm256_max_ps` returns its second operand if either is NaN, so with `acc` second a NaN
+    /// `x` is skipped as `apply` skips it
+    #[cfg(target_arch = "x86_64")]
+    #[target_feature(enable = "avx")]
+    unsafe fn apply_avx(self, acc: __m256, x: __m256) -> __m256 {
+        match self {
+            Reduce::Sum => _mm256_add_ps(acc, x),
+            Reduce::Max => _mm256_max_ps(x, acc),
+            Reduce::Min => _mm256_min_ps(x, acc),
+        }
+    }
+}
+
+/// Sum of every value, pairwise
+pub fn sum(a: &impl AsTensorRef<f32>) -> f32 {
+    reduce(Reduce::Sum, &a.as_tensor_ref().dense())
+}
+
+/// Mean of every value, NaN if there are none
+pub fn mean(a: &impl AsTensorRef<f32>) -> f32 {
+    let a = a.as_tensor_ref();
+    sum(&a) / a.shape.iter().product::<usize>() as f32
+}
+
+/// Largest value, skipping NaNs. Negative infinity if there are none.
+pub fn max(a: &impl AsTensorRef<f32>) -> f32 {
+    reduce(Reduce::Max, &a.as_tensor_ref().dense())
+}
+
+/// Smallest value, skipping NaNs. Infinity if there are none.
+pub fn min(a: &impl AsTensorRef<f32>) -> f32 {
+    reduce(Reduce::Min, &a.as_tensor_ref().dense())
+}
+
+/// Index of the first largest value, counted row-major over every axis as in NumPy, skipping
+/// NaNs. `None` if there are no values; 0 if they are all NaN.
+pub fn argmax(a: &impl AsTensorRef<f32>) -> Option<usize> {
+    let a = a.as_tensor_ref();
+    let values = a.dense_in(Layout::RowMajor);
+    match values.is_empty() {
+        true => None,
+        false => Some(position(&values, reduce(Reduce::Max, &values))),
+    }
+}
+
+/// Sums along `axis`, a tensor of the other axes in the layout of `a`
+pub fn sum_axis(a: &impl AsTensorRef<f32>, axis: usize) -> Tensor<f32> {
+    try_sum_axis(a, axis).unwrap_or_else(|e| panic!("{}", e))
+}
+
+/// Fallible version of `sum_axis`.
+pub fn try_sum_axis(a: &impl AsTensorRef<f32>, axis: usize) -> Result<Tensor<f32>, AmlError> {
+    reduce_axis(Reduce::Sum, &a.as_tensor_ref(), axis)
+}
+
+/// Means along `axis`, as `sum_axis`. NaN where `axis` is empty.
+pub fn mean_axis(a: &impl AsTensorRef<f32>, axis: usize) -> Tensor<f32> {
+    try_mean_axis(a, axis).unwrap_or_else(|e| panic!("{}", e))
+}
+
+/// Fallible version of `mean_axis`.
+pub fn try_mean_axis(a: &impl AsTensorRef<f32>, axis: usize) -> Result<Tensor<f32>, AmlError> {
+    let a = a.as_tensor_ref();
+    let mut sums = reduce_axis(Reduce::Sum, &a, axis)?;
+    let len = a.shape[axis] as f32;
+    sums.values.iter_mut().for_each(|v| *v /= len);
+    Ok(sums)
+}
+
+/// Largest values along `axis`, as `sum_axis` and skipping NaNs
+pub fn max_axis(a: &impl AsTensorRef<f32>, axis: usize) -> Tensor<f32> {
+    try_max_axis(a, axis).unwrap_or_else(|e| panic!("{}", e))
+}
+
+/// Fallible version of `max_axis`.
+pub fn try_max_axis(a: &impl AsTensorRef<f32>, axis: usize) -> Result<Tensor<f32>, AmlError> {
+    reduce_axis(Reduce::Max, &a.as_tensor_ref(), axis)
+}
+
+/// Smallest values along `axis`, as `sum_axis` and skipping NaNs
+pub fn min_axis(a: &impl AsTensorRef<f32>, axis: usize) -> Tensor<f32> {
+    try_min_axis(a, axis).unwrap_or_else(|e| panic!("{}", e))
+}
+
+/// Fallible version of `min_axis`.
+pub fn try_min_axis(a: &impl AsTensorRef<f32>, axis: usize) -> Result<Tensor<f32>, AmlError> {
+    reduce_axis(Reduce::Min, &a.as_tensor_ref(), axis)
+}
+
+/// Index along `axis` of the first largest value, as `argmax`, for each position of the other
+/// axes in row-major order, e.g. the predicted class of each row of `[batch, classes]` logits
+/// with `axis` 1.
+pub fn argmax_axis(a: &impl AsTensorRef<f32>, axis: usize) -> Vec<usize> {
+    try_argmax_axis(a, axis).unwrap_or_else(|e| panic!("{}", e))
+}
+
+/// Fallible version of `argmax_axis`. `InvalidAxis` if `axis` is empty while the other axes
+/// are not, as there is no index to give.
+pub fn try_argmax_axis(a: &impl AsTensorRef<f32>, axis: usize) -> Result<Vec<usize>, AmlError> {
+    let a = a.as_tensor_ref();
+    let (outer, len, inner) = split(&a.shape, axis, Layout::RowMajor)?;
+    if len == 0 && outer * inner != 0 {
+        return Err(AmlError::InvalidAxis {
+            axis,
+            shape: a.shape.to_vec(),
+        });
+    }
+    let values = a.dense_in(Layout::RowMajor);
+
+    let mut indices = vec![0; outer * inner];
+    let mut largest = vec![0f32; inner];
+    for (block, indices) in values
+        .chunks_exact((len * inner).max(1))
+        .zip(indices.chunks_exact_mut(inner.max(1)))
+    {
+        match inner {
+            1 => indices[0] = position(block, reduce(Reduce::Max, block)),
+            _ => {
+                reduce_rows(Reduce::Max, block, &mut largest);
+                // each index is the first row holding that column's largest value
+                let mut found = vec![false; inner];
+                for (i, row) in block.chunks_exact(inner).enumerate() {
+                    for (j, value) in row.iter().enumerate() {
+                        if !found[j] && *value == largest[j] {
+                            (indices[j], found[j]) = (i, true);
+                        }
+                    }
+                }
+            }
+        }
+    }
+    Ok(indices)
+}
+
+/// `op` over the contiguous `values`, pairwise for sums
+fn reduce(op: Reduce, values: &[f32]) -> f32 {
+    match op == Reduce::Sum && values.len() > PAIRWISE_BLOCK {
+        true => {
+            let (lhs, rhs) = values.split_at(values.len() / 2);
+            reduce(op, lhs) + reduce(op, rhs)
+        }
+        false => (kernels().reduce)(op, values),
+    }
+}
+
+/// `out = op` over the `rows.len() / out.len()` rows of `rows`, each as long as `out`, pairwise
+/// for sums as `reduce`
+fn reduce_rows(op: Reduce, rows: &[f32], out: &mut [f32]) {
+    let len = rows.len() / out.len().max(1);
+    match op == Reduce::Sum && len > PAIRWISE_BLOCK {
+        true => {
+            let (lhs, rhs) = rows.split_at(len / 2 * out.len());
+            let mut rhs_sums = vec![0f32; out.len()];
+            reduce_rows(op, lhs, out);
+            reduce_rows(op, rhs, &mut rhs_sums);
+            (kernels().reduce_into)(op, &rhs_sums, out);
+        }
+        false => {
+            out.fill(op.identity());
+            for row in rows.chunks_exact(out.len().max(1)) {
+                (kernels().reduce_into)(op, row, out);
+            }
+        }
+    }
+}
+
+/// `op` along `axis` of `a`, which is dropped from the result
+fn reduce_axis(op: Reduce, a: &TensorRef<f32>, axis: usize) -> Result<Tensor<f32>, AmlError> {
+    let (outer, len, inner) = split(&a.shape, axis, a.layout)?;
+    let values = a.dense();
+    let mut shape = a.shape.to_vec();
+    shape.remove(axis);
+
+    let mut out = vec![0f32; outer * inner];
+    if !out.is_empty() {
+        parallel::for_each_row_chunk(&mut out, inner, len * inner, |first, out| {
+            for (o, out) in (first..).zip(out.chunks_exact_mut(inner)) {
+                let block = &values[o * len * inner..(o + 1) * len * inner];
+                match inner {
+                    1 => out[0] = reduce(op, block),
+                    _ => reduce_rows(op, block, out),
+                }
+            }
+        });
+    }
+    Ok(Tensor::new(out, shape).with_layout(a.layout))
+}
+
+/// Values stored in `layout` order as `outer` blocks of `len` rows of `inner` values, `len`
+/// being the length of `axis`
+fn split(shape: &Shape, axis: usize, layout: Layout) -> Result<(usize, usize, usize), AmlError> {
+    if axis >= shape.rank() {
+        return Err(AmlError::InvalidAxis {
+            axis,
+            shape: shape.to_vec(),
+        });
+    }
+    let (before, after) = (&shape[..axis], &shape[axis + 1..]);
+    let (slower, faster) = match layout {
+        Layout::RowMajor => (before, after),
+        Layout::ColMajor => (after, before),
+    };
+    Ok((
+        slower.iter().product(),
+        shape[axis],
+        faster.iter().product(),
+    ))
+}
+
+/// Index of the first of `values` equal to `largest`, or 0 if there is none
+fn position(values: &[f32], largest: f32) -> usize {
+    values.iter().position(|v| *v == largest).unwrap_or(0)
+}
+
+/// 8 independent lanes, as `sdot_scalar`
+pub(crate) fn reduce_scalar(op: Reduce, x: &[f32]) -> f32 {
+    let mut lanes = [op.identity(); 8];
+    let chunks = x.chunks_exact(8);
+    let tail = chunks
+        .remainder()
+        .iter()
+        .fold(op.identity(), |acc, v| op.apply(acc, *v));
+
+    for x8 in chunks {
+        for lane in 0..8 {
+            lanes[lane] = op.apply(lanes[lane], x8[lane]);
+        }
+    }
+
+    lanes.iter().fold(tail, |acc, v| op.apply(acc, *v))
+}
+
+pub(crate) fn reduce_into_scalar(op: Reduce, x: &[f32], acc: &mut [f32]) {
+    for (acc, x) in acc.iter_mut().zip(x) {
+        *acc = op.apply(*acc, *x);
+    }
+}
+
+/// Two accumulators so consecutive steps do not wait on each other, as `sdot_avx`.
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "avx")]
+pub(crate) unsafe fn reduce_avx(op: Reduce, x: &[f32]) -> f32 {
+    let n16 = x.len() / 16 * 16;
+    let mut acc0 = _mm256_set1_ps(op.identity());
+    let mut acc1 = acc0;
+
+    for i in (0..n16).step_by(16) {
+        acc0 = op.apply_avx(acc0, _mm256_loadu_ps(x.as_ptr().add(i)));
+        acc1 = op.apply_avx(acc1, _mm256_loadu_ps(x.as_ptr().add(i + 8)));
+    }
+
+    let mut lanes = [0f32; 8];
+    _mm256_storeu_ps(lanes.as_mut_ptr(), op.apply_avx(acc0, acc1));
+    let acc = reduce_scalar(op, &lanes);
+    op.apply(acc, reduce_scalar(op, &x[n16..]))
+}
+
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "avx")]
+pub(crate) unsafe fn reduce_into_avx(op: Reduce, x: &[f32], acc: &mut [f32]) {
+    assert_eq!(x.len(), acc.len());
+    let n8 = acc.len() / 8 * 8;
+
+    for i in (0..n8).step_by(8) {
+        let acc8 = _mm256_loadu_ps(acc.as_ptr().add(i));
+        let x8 = _mm256_loadu_ps(x.as_ptr().add(i));
+        _mm256_storeu_ps(acc.as_mut_ptr().add(i), op.apply_avx(acc8, x8));
+    }
+
+    reduce_into_scalar(op, &x[n8..], &mut acc[n8..]);
+}
@@ -0,0 +1,188 @@
+//! bfloat16 matrix multiply, accumulating and storing in f32.
+
+use half::bf16;
+
+#[cfg(target_arch = "x86_64")]
+use std::arch::x86_64::*;
+
+#[cfg(all(feature = "amx", target_arch = "x86_64", target_os = "linux"))]
+use crate::amx;
+use crate::dispatch::kernels;
+use crate::{
+    check_gemm, gemm_operands, op_a_rows, parallel, AmlError, BF16Tensor, F32Tensor, GemmParams,
+};
+
+/// Matrix multiply of bf16 inputs into an f32 `c`: `op(a) @ op(b)`, overwriting `c`.
+///
+/// op(BF16(m, k)) @ op(BF16(k, n)) --> F32(m, n). Both operands are laid out along k (`b` is
+/// gathered into (n, k) once if it is not already transposed), so every output is one bf16 dot
+/// product. With AVX-512 BF16 those run on `vdpbf16ps`, two multiply-adds per lane per
+/// instruction; otherwise bf16 is widened to f32 (a 16 bit shift) and summed with AVX2/FMA, or
+/// plain scalar code.
+///
+/// With the `amx` feature on a CPU with AMX, whole 16 x 16 blocks of `c` are computed with
+/// `tdpbf16ps` on tile registers instead.
+///
+/// `vdpbf16ps` and `tdpbf16ps` flush denormals to zero, so results can differ from the
+/// fallbacks in the last bits for inputs that small.
+pub fn sbgemm(
+    a: &BF16Tensor,
+    a_transpose: bool,
+    b: &BF16Tensor,
+    b_transpose: bool,
+    c: &mut F32Tensor,
+) {
+    try_sbgemm(a, a_transpose, b, b_transpose, c).unwrap_or_else(|e| panic!("{}", e))
+}
+
+/// Fallible version of `sbgemm`. `c` is left untouched on error.
+pub fn try_sbgemm(
+    a: &BF16Tensor,
+    a_transpose: bool,
+    b: &BF16Tensor,
+    b_transpose: bool,
+    c: &mut F32Tensor,
+) -> Result<(), AmlError> {
+    try_sbgemm_with(a, a_transpose, b, b_transpose, GemmParams::default(), c)
+}
+
+/// `c = alpha * (op(a) @ op(b)) + beta * c`
+pub fn sbgemm_with(
+    a: &BF16Tensor,
+    a_transpose: bool,
+    b: &BF16Tensor,
+    b_transpose: bool,
+    params: GemmParams,
+    c: &mut F32Tensor,
+) {
+    try_sbgemm_with(a, a_transpose, b, b_transpose, params, c).unwrap_or_else(|e| panic!("{}", e))
+}
+
+/// Fallible version of `sbgemm_with`. `c` is left untouched on error.
+pub fn try_sbgemm_with(
+    a: &BF16Tensor,
+    a_transpose: bool,
+    b: &BF16Tensor,
+    b_transpose: bool,
+    params: GemmParams,
+    c: &mut F32Tensor,
+) -> Result<(), AmlError> {
+    check_gemm(&a.shape, a_transpose, &b.shape, b_transpose, &c.shape)?;
+    c.check_blas("c")?;
+    let (a_copy, b_copy) = (a.blas_copy(), b.blas_copy());
+    let (a, b) = (a_copy.as_ref().unwrap_or(a), b_copy.as_ref().unwrap_or(b));
+    let (a, a_transpose, b, b_transpose) = gemm_operands(c.layout, a, a_transpose, b, b_transpose);
+    let c = c.view_mut().row_major();
+
+    let n = c.shape[1];
+    let k = match a_transpose {
+        true => a.shape[0],
+        false => a.shape[1],
+    };
+    let a_transpose = a.stored_transpose(a_transpose);
+    let b_transpose = b.stored_transpose(b_transpose);
+    let ldc = c.ld();
+    // rows of op(b)^T, i.e. columns of op(b)
+    let b_cols = op_a_rows(&b.values, !b_transpose, k, b.ld(), 0, n);
+
+    #[cfg(all(feature = "amx", target_arch = "x86_64", target_os = "linux"))]
+    if kernels().amx {
+        let packed_b = amx::pack_b(&b_cols, n, k);
+        parallel::for_each_row_chunk(c.values, ldc, n * k, |first_row, c_rows| {
+            let rows = c_rows.len().div_ceil(ldc);
+            let a_rows = op_a_rows(&a.values, a_transpose, k, a.ld(), first_row, rows);
+            let mut acc = vec![0f32; rows * n];
+            amx::tile_gemm(&a_rows, &packed_b, n, k, &mut acc);
+            crate::store_rows(&acc, n, c_rows, ldc, |acc, c| *c = params.apply(acc, *c));
+        });
+        return Ok(());
+    }
+
+    parallel::for_each_row_chunk(c.values, ldc, n * k, |first_row, c_rows| {
+        let rows = c_rows.len().div_ceil(ldc);
+        let a_rows = op_a_rows(&a.values, a_transpose, k, a.ld(), first_row, rows);
+
+        for (a_i, c_row) in a_rows.chunks_exact(k).zip(c_rows.chunks_mut(ldc)) {
+            for (c_val, b_j) in c_row.iter_mut().zip(b_cols.chunks_exact(k)) {
+                *c_val = params.apply(sbdot(a_i, b_j), *c_val);
+            }
+        }
+    });
+
+    Ok(())
+}
+
+/// bf16 dot product summed in f32
+pub(crate) fn sbdot(x: &[bf16], y: &[bf16]) -> f32 {
+    (kernels().sbdot)(x, y)
+}
+
+pub(crate) fn sbdot_scalar(x: &[bf16], y: &[bf16]) -> f32 {
+    x.iter().zip(y).map(|(x, y)| x.to_f32() * y.to_f32()).sum()
+}
+
+/// 32 bf16 values per `vdpbf16ps`, with the tail run through a zero padded copy.
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "avx512f,avx512bf16")]
+pub(crate) unsafe fn sbdot_avx512bf16(x: &[bf16], y: &[bf16]) -> f32 {
+    assert_eq!(x.len(), y.len());
+    let n32 = x.len() / 32 * 32;
+    let mut acc = _mm512_setzero_ps();
+
+    for i in (0..n32).step_by(32) {
+        let x32 = _mm512_loadu_si512(x.as_ptr().add(i) as *const _);
+        let y32 = _mm512_loadu_si512(y.as_ptr().add(i) as *const _);
+        acc = _mm512_dpbf16_ps(
+            acc,
+            std::mem::transmute::<__m512i, __m512bh>(x32),
+            std::mem::transmute::<__m512i, __m512bh>(y32),
+        );
+    }
+
+    if n32 < x.len() {
+        // zero padding contributes nothing to the sum
+        let mut x_tail = [bf16::ZERO; 32];
+        let mut y_tail = [bf16::ZERO; 32];
+        x_tail[..x.len() - n32].copy_from_slice(&x[n32..]);
+        y_tail[..y.len() - n32].copy_from_slice(&y[n32..]);
+        let x32 = _mm512_loadu_si512(x_tail.as_ptr() as *const _);
+        let y32 = _mm512_loadu_si512(y_tail.as_ptr() as *const _);
+        acc = _mm512_dpbf16_ps(
+            acc,
+            std::mem::transmute::<__m512i, __m512bh>(x32),
+            std::mem::transmute::<__m512i, __m512bh>(y32),
+        );
+    }
+
+    _mm512_reduce_add_ps(acc)
+}
+
+/// Widen 8 bf16 values starting at `ptr`: bf16 is the top half of an f32.
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "avx2")]
+unsafe fn load_bf16_avx2(ptr: *const bf16) -> __m256 {
+    let halves = _mm_loadu_si128(ptr as *const __m128i);
+    _mm256_castsi256_ps(_mm256_slli_epi32(_mm256_cvtepu16_epi32(halves), 16))
+}
+
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "avx2,fma")]
+pub(crate) unsafe fn sbdot_avx2(x: &[bf16], y: &[bf16]) -> f32 {
+    assert_eq!(x.len(), y.len());
+    let n16 = x.len() / 16 * 16;
+    let mut acc0 = _mm256_setzero_ps();
+    let mut acc1 = _mm256_setzero_ps();
+
+    for i in (0..n16).step_by(16) {
+        let x0 = load_bf16_avx2(x.as_ptr().add(i));
+        let y0 = load_bf16_avx2(y.as_ptr().add(i));
+        let x1 = load_bf16_avx2(x.as_ptr().add(i + 8));
+        let y1 = load_bf16_avx2(y.as_ptr().add(i + 8));
+        acc0 = _mm256_fmadd_ps(x0, y0, acc0);
+        acc1 = _mm256_fmadd_ps(x1, y1, acc1);
+    }
+
+    let mut lanes = [0f32; 8];
+    _mm256_storeu_ps(lanes.as_mut_ptr(), _mm256_add_ps(acc0, acc1));
+    lanes.iter().sum::<f32>() + sbdot_scalar(&x[n16..], &y[n16..])
+}
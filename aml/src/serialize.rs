@@ -0,0 +1,220 @@
+//! `Serialize` and `Deserialize` for the owned tensors, behind the `serde` feature.
+//!
+//! A tensor is its shape and values, with the element type and strides for `Tensor`. Binary
+//! formats (bincode, postcard, ..) get the values as one little endian byte string through
+//! `serde_bytes`, so they are copied rather than encoded one by one. Human readable formats
+//! (JSON, TOML, ..) get a list of numbers instead: f64 for `f64` tensors, f32 for the others,
+//! either of which converts back to the same value.
+//!
+//! Values are converted to the element type asked for on reading, as in `io`, so an
+//! `F64Tensor` deserializes into an `F32Tensor`. Shapes and strides are checked as by the
+//! `try_*` constructors, and a mismatch is the format's error wrapping the `AmlError`.
+
+use crate::io::{self, DType};
+use crate::{AmlError, Element, I4TensorOwned, I8Tensor, Pod, Tensor};
+use half::f16;
+use serde::de::{self, Deserializer};
+use serde::ser::Serializer;
+use serde::{Deserialize, Serialize};
+
+/// Values of any element type, written as bytes or numbers depending on the format
+struct Values<'a, T>(&'a [T]);
+
+impl<T: Element + Pod> Serialize for Values<'_, T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match (serializer.is_human_readable(), DType::of::<T>()) {
+            (false, _) => serde_bytes::Bytes::new(&io::encode(self.0)).serialize(serializer),
+            (true, DType::F64) => serializer.collect_seq(self.0.iter().map(|v| v.to_f64())),
+            (true, _) => serializer.collect_seq(self.0.iter().map(|v| v.to_f32())),
+        }
+    }
+}
+
+/// What `Values` reads back, before it is converted to the element type asked for
+enum StoredValues {
+    Bytes(Vec<u8>),
+    Numbers(Vec<f64>),
+}
+
+impl<'de> Deserialize<'de> for StoredValues {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<StoredValues, D::Error> {
+        match deserializer.is_human_readable() {
+            true => Vec::deserialize(deserializer).map(StoredValues::Numbers),
+            false => serde_bytes::ByteBuf::deserialize(deserializer)
+                .map(|bytes| StoredValues::Bytes(bytes.into_vec())),
+        }
+    }
+}
+
+impl StoredValues {
+    /// The values, stored as `dtype`, converted to `T`
+    fn decode<T: Element, E: de::Error>(self, dtype: DType) -> Result<Vec<T>, E> {
+        match self {
+            StoredValues::Bytes(bytes) => match bytes.len() % dtype.size() {
+                0 => Ok(io::decode(&bytes, dtype, true)),
+                _ => Err(E::custom(format!(
+                    "{} bytes are not a whole number of {:?} values.",
+                    bytes.len(),
+                    dtype
+                ))),
+            },
+            StoredValues::Numbers(numbers) => Ok(numbers.into_iter().map(T::from_f64).collect()),
+        }
+    }
+}
+
+/// i8 values, written as bytes or numbers depending on the format
+struct I8Values<'a>(&'a [i8]);
+
+impl Serialize for I8Values<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match serializer.is_human_readable() {
+            true => self.0.serialize(serializer),
+            false => {
+                let bytes: Vec<u8> = self.0.iter().map(|v| *v as u8).collect();
+                serde_bytes::Bytes::new(&bytes).serialize(serializer)
+            }
+        }
+    }
+}
+
+/// What `I8Values` reads back
+struct StoredI8Values(Vec<i8>);
+
+impl<'de> Deserialize<'de> for StoredI8Values {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<StoredI8Values, D::Error> {
+        match deserializer.is_human_readable() {
+            true => Vec::deserialize(deserializer).map(StoredI8Values),
+            false => serde_bytes::ByteBuf::deserialize(deserializer)
+                .map(|bytes| StoredI8Values(bytes.iter().map(|v| *v as i8).collect())),
+        }
+    }
+}
+
+fn invalid<E: de::Error>(e: AmlError) -> E {
+    E::custom(e)
+}
+
+#[derive(Serialize)]
+#[serde(rename = "Tensor", bound = "T: Element + Pod")]
+struct TensorRepr<'a, T> {
+    dtype: DType,
+    shape: &'a [usize],
+    strides: &'a [usize],
+    values: Values<'a, T>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename = "Tensor")]
+struct StoredTensor {
+    dtype: DType,
+    shape: Vec<usize>,
+    strides: Vec<usize>,
+    values: StoredValues,
+}
+
+impl<T: Element + Pod> Serialize for Tensor<T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        TensorRepr {
+            dtype: DType::of::<T>(),
+            shape: &self.shape,
+            strides: &self.strides,
+            values: Values(&self.values),
+        }
+        .serialize(serializer)
+    }
+}
+
+impl<'de, T: Element> Deserialize<'de> for Tensor<T> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Tensor<T>, D::Error> {
+        let stored = StoredTensor::deserialize(deserializer)?;
+        let values = stored.values.decode(stored.dtype)?;
+        Tensor::try_new_with_strides(values, stored.shape, stored.strides).map_err(invalid)
+    }
+}
+
+#[derive(Serialize)]
+#[serde(rename = "I8Tensor")]
+struct I8TensorRepr<'a> {
+    shape: &'a [usize],
+    scale: f32,
+    zero_point: i8,
+    values: I8Values<'a>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename = "I8Tensor")]
+struct StoredI8Tensor {
+    shape: Vec<usize>,
+    scale: f32,
+    zero_point: i8,
+    values: StoredI8Values,
+}
+
+impl Serialize for I8Tensor {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        I8TensorRepr {
+            shape: &self.shape,
+            scale: self.scale,
+            zero_point: self.zero_point,
+            values: I8Values(&self.values),
+        }
+        .serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for I8Tensor {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<I8Tensor, D::Error> {
+        let stored = StoredI8Tensor::deserialize(deserializer)?;
+        I8Tensor::try_new(
+            stored.values.0,
+            stored.shape,
+            stored.scale,
+            stored.zero_point,
+        )
+        .map_err(invalid)
+    }
+}
+
+#[derive(Serialize)]
+#[serde(rename = "I4Tensor")]
+struct I4TensorRepr<'a> {
+    shape: &'a [usize],
+    scales: Values<'a, f16>,
+    zeros: I8Values<'a>,
+    nibbles: I8Values<'a>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename = "I4Tensor")]
+struct StoredI4Tensor {
+    shape: Vec<usize>,
+    scales: StoredValues,
+    zeros: StoredI8Values,
+    nibbles: StoredI8Values,
+}
+
+impl Serialize for I4TensorOwned {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        I4TensorRepr {
+            shape: &self.shape,
+            scales: Values(&self.scales),
+            zeros: I8Values(&self.zeros),
+            nibbles: I8Values(&self.nibbles),
+        }
+        .serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for I4TensorOwned {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<I4TensorOwned, D::Error> {
+        let stored = StoredI4Tensor::deserialize(deserializer)?;
+        I4TensorOwned::try_from_vec(
+            stored.scales.decode(DType::F16)?,
+            stored.zeros.0,
+            stored.nibbles.0,
+            stored.shape,
+        )
+        .map_err(invalid)
+    }
+}
@@ -0,0 +1,1834 @@
+//! Dense f32 matrix multiply.
+
+use crate::autotune::{self, TuneConfig};
+#[cfg(feature = "backend-blas")]
+use crate::backend;
+use crate::dispatch::{kernels, scalar_kernels, Kernels};
+use crate::microkernel::{Microkernel, ISA_NAMES};
+use crate::workspace::{self, PackScratch, Workspace};
+use crate::{
+    block_sizes, check_gemm, check_rank, check_row_major, gemm_operands, op_a_rows, parallel,
+    store_rows, strided_rows, Accuracy, AmlContext, AmlError, AsTensorMut, AsTensorRef, BlockSizes,
+    CancelToken, F32TensorRef, GemmParams, Layout, Shape, TensorMut,
+};
+use std::borrow::Cow;
+use std::ops::Range;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+
+/// Matrix multiply in f32: `op(a) @ op(b)` stored in `c`, overwriting it.
+///
+/// op(F32(m, k)) @ op(F32(k, n)) --> F32(m, n). Threads get blocks of `c` from a grid as square
+/// as the thread count allows, so flat outputs split across columns too; a grid of one column
+/// splits by rows as in `dgemm`. `b` is packed into panels once and each thread packs blocks of
+/// its rows of `a` (see `sgemm_packed`), so the inner loop, a register tiled microkernel, reads
+/// both contiguously. The microkernel is 14 x 32 with AVX-512F, 6 x 16 with AVX2 and FMA,
+/// 8 x 12 with NEON, 4 x 8 with wasm SIMD128, 4 x 16 in `core::simd` with the `portable-simd`
+/// feature or 4 x 16 in plain Rust with the `generic-kernel` feature, and otherwise the BLAS1
+/// kernels. With `Accuracy::High` in `sgemm_with`, each output is summed in f64 and rounded once.
+///
+/// Any operand may be a window of a larger matrix from `Tensor::view` or `Tensor::window_mut`,
+/// which is read and written in place.
+pub fn sgemm(
+    a: &impl AsTensorRef<f32>,
+    a_transpose: bool,
+    b: &impl AsTensorRef<f32>,
+    b_transpose: bool,
+    c: &mut impl AsTensorMut<f32>,
+) {
+    try_sgemm(a, a_transpose, b, b_transpose, c).unwrap_or_else(|e| panic!("{}", e))
+}
+
+/// Fallible version of `sgemm`. `c` is left untouched on error.
+pub fn try_sgemm(
+    a: &impl AsTensorRef<f32>,
+    a_transpose: bool,
+    b: &impl AsTensorRef<f32>,
+    b_transpose: bool,
+    c: &mut impl AsTensorMut<f32>,
+) -> Result<(), AmlError> {
+    try_sgemm_with(a, a_transpose, b, b_transpose, GemmParams::default(), c)
+}
+
+/// `c = alpha * (op(a) @ op(b)) + beta * c`
+pub fn sgemm_with(
+    a: &impl AsTensorRef<f32>,
+    a_transpose: bool,
+    b: &impl AsTensorRef<f32>,
+    b_transpose: bool,
+    params: GemmParams,
+    c: &mut impl AsTensorMut<f32>,
+) {
+    try_sgemm_with(a, a_transpose, b, b_transpose, params, c).unwrap_or_else(|e| panic!("{}", e))
+}
+
+/// Fallible version of `sgemm_with`. `c` is left untouched on error.
+pub fn try_sgemm_with(
+    a: &impl AsTensorRef<f32>,
+    a_transpose: bool,
+    b: &impl AsTensorRef<f32>,
+    b_transpose: bool,
+    params: GemmParams,
+    c: &mut impl AsTensorMut<f32>,
+) -> Result<(), AmlError> {
+    let epilogue = Epilogue::default();
+    try_sgemm_fused(a, a_transpose, b, b_transpose, params, epilogue, c)
+}
+
+/// Independent `sgemm`s over matching lists of `a`, `b` and `c`, e.g. the heads of an attention
+/// layer.
+///
+/// Once the batch holds enough multiply-adds in all to be worth a spawn, its entries are split
+/// across threads, each thread computing its share one after another. A batch shorter than the
+/// thread count leaves threads over, and those split the GEMM of each entry; a batch too small
+/// for any threads runs on the caller's. Every entry is checked before any is computed, so on
+/// error no `c` has been written.
+pub fn sgemm_batched<A, B, C>(a: &[A], a_transpose: bool, b: &[B], b_transpose: bool, c: &mut [C])
+where
+    A: AsTensorRef<f32> + Sync,
+    B: AsTensorRef<f32> + Sync,
+    C: AsTensorMut<f32> + Send,
+{
+    try_sgemm_batched(a, a_transpose, b, b_transpose, c).unwrap_or_else(|e| panic!("{}", e))
+}
+
+/// Fallible version of `sgemm_batched`
+pub fn try_sgemm_batched<A, B, C>(
+    a: &[A],
+    a_transpose: bool,
+    b: &[B],
+    b_transpose: bool,
+    c: &mut [C],
+) -> Result<(), AmlError>
+where
+    A: AsTensorRef<f32> + Sync,
+    B: AsTensorRef<f32> + Sync,
+    C: AsTensorMut<f32> + Send,
+{
+    if a.len() != b.len() || a.len() != c.len() {
+        return Err(AmlError::BatchSizeMismatch {
+            a: a.len(),
+            b: b.len(),
+            c: c.len(),
+        });
+    }
+    let mut work = 0usize;
+    for ((a, b), c) in a.iter().zip(b).zip(c.iter_mut()) {
+        let (a, b, c) = (a.as_tensor_ref(), b.as_tensor_ref(), c.as_tensor_mut());
+        check_gemm(&a.shape, a_transpose, &b.shape, b_transpose, &c.shape)?;
+        c.check_blas("c")?;
+        let k = match a_transpose {
+            true => a.shape[0],
+            false => a.shape[1],
+        };
+        work = work.saturating_add((c.shape[0] * c.shape[1]).saturating_mul(k));
+    }
+
+    parallel::for_each_chunk(c, work, |first, c| {
+        for ((a, b), c) in a[first..].iter().zip(&b[first..]).zip(c) {
+            sgemm(a, a_transpose, b, b_transpose, c);
+        }
+    });
+    Ok(())
+}
+
+/// `c = epilogue(alpha * (op(a) @ op(b)) + beta * c)`: `sgemm_with`, with the epilogue applied
+/// to each output as it is stored rather than in another pass over `c`. `c` must be row-major
+/// unless the epilogue is empty.
+pub fn sgemm_fused(
+    a: &impl AsTensorRef<f32>,
+    a_transpose: bool,
+    b: &impl AsTensorRef<f32>,
+    b_transpose: bool,
+    params: GemmParams,
+    epilogue: Epilogue,
+    c: &mut impl AsTensorMut<f32>,
+) {
+    try_sgemm_fused(a, a_transpose, b, b_transpose, params, epilogue, c)
+        .unwrap_or_else(|e| panic!("{}", e))
+}
+
+/// Fallible version of `sgemm_fused`. `c` is left untouched on error.
+pub fn try_sgemm_fused(
+    a: &impl AsTensorRef<f32>,
+    a_transpose: bool,
+    b: &impl AsTensorRef<f32>,
+    b_transpose: bool,
+    params: GemmParams,
+    epilogue: Epilogue,
+    c: &mut impl AsTensorMut<f32>,
+) -> Result<(), AmlError> {
+    let (a, b, c) = (a.as_tensor_ref(), b.as_tensor_ref(), c.as_tensor_mut());
+    check_gemm(&a.shape, a_transpose, &b.shape, b_transpose, &c.shape)?;
+    c.check_blas("c")?;
+    if !epilogue.is_empty() {
+        check_row_major("c", c.layout)?;
+    }
+    if let Some(bias) = epilogue.bias {
+        if bias.len() != c.shape[1] {
+            return Err(AmlError::SizeMismatch {
+                expected: c.shape[1],
+                found: bias.len(),
+            });
+        }
+    }
+    let (a_copy, b_copy) = (a.blas_copy(), b.blas_copy());
+    let a = a_copy.as_ref().map_or(a, AsTensorRef::as_tensor_ref);
+    let b = b_copy.as_ref().map_or(b, AsTensorRef::as_tensor_ref);
+    let (a, a_transpose, b, b_transpose) =
+        gemm_operands(c.layout, &a, a_transpose, &b, b_transpose);
+    let mut c = c.row_major();
+    #[cfg(feature = "backend-blas")]
+    if backend::use_blas(&params) && backend::sgemm(a, a_transpose, b, b_transpose, params, &mut c)
+    {
+        let ldc = c.ld();
+        for c_row in c.values.chunks_mut(ldc.max(1)) {
+            for (j, value) in c_row.iter_mut().take(c.shape[1]).enumerate() {
+                *value = epilogue.apply(j, *value);
+            }
+        }
+        return Ok(());
+    }
+    sgemm_fused_kernel(
+        a,
+        a_transpose,
+        OpB::Tensor(b, b_transpose),
+        params,
+        epilogue,
+        None,
+        kernels(),
+        &mut c,
+    );
+    Ok(())
+}
+
+/// Elementwise steps `sgemm_fused` applies to each output of a GEMM before storing it.
+///
+/// The default is empty. A bias is added to every row, one value per column of `c`, as in a
+/// linear layer's `x @ w + b`, and the activation is applied after it.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Epilogue<'a> {
+    pub bias: Option<&'a [f32]>,
+    pub activation: Activation,
+}
+
+/// The nonlinearity at the end of an `Epilogue`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Activation {
+    #[default]
+    None,
+    /// `max(x, 0)`
+    Relu,
+    /// `x * Φ(x)` in the tanh approximation GPT-2 and most checkpoints since were trained with
+    Gelu,
+    /// `x * sigmoid(x)`, also called swish
+    Silu,
+}
+
+impl Activation {
+    pub(crate) fn apply(self, x: f32) -> f32 {
+        match self {
+            Activation::None => x,
+            Activation::Relu => x.max(0f32),
+            Activation::Gelu => {
+                0.5 * x * (1. + (GELU_SCALE as f32 * (x + 0.044715 * x * x * x)).tanh())
+            }
+            Activation::Silu => x / (1. + (-x).exp()),
+        }
+    }
+
+    fn apply_f64(self, x: f64) -> f64 {
+        match self {
+            Activation::None => x,
+            Activation::Relu => x.max(0f64),
+            Activation::Gelu => 0.5 * x * (1. + (GELU_SCALE * (x + 0.044715 * x * x * x)).tanh()),
+            Activation::Silu => x / (1. + (-x).exp()),
+        }
+    }
+}
+
+/// sqrt(2 / pi)
+const GELU_SCALE: f64 = 0.797_884_560_802_865_4;
+
+impl<'a> Epilogue<'a> {
+    pub fn new() -> Epilogue<'a> {
+        Epilogue::default()
+    }
+
+    /// The same steps, adding `bias` to each row
+    pub fn with_bias(self, bias: &'a [f32]) -> Epilogue<'a> {
+        Epilogue {
+            bias: Some(bias),
+            ..self
+        }
+    }
+
+    /// The same steps, ending in `activation`
+    pub fn with_activation(self, activation: Activation) -> Epilogue<'a> {
+        Epilogue { activation, ..self }
+    }
+
+    /// Whether this leaves the outputs as they are
+    pub fn is_empty(&self) -> bool {
+        self.bias.is_none() && self.activation == Activation::None
+    }
+
+    /// The steps applied to `value` in column `j`
+    fn apply(&self, j: usize, value: f32) -> f32 {
+        let value = match self.bias {
+            Some(bias) => value + bias[j],
+            None => value,
+        };
+        self.activation.apply(value)
+    }
+
+    /// `apply` in f64, for `Accuracy::High`
+    fn apply_f64(&self, j: usize, value: f64) -> f64 {
+        let value = match self.bias {
+            Some(bias) => value + bias[j] as f64,
+            None => value,
+        };
+        self.activation.apply_f64(value)
+    }
+
+    /// `params.apply` and then the steps, from `acc` into the rows of `c` starting at column
+    /// `j0`, as `store_rows`
+    fn store_rows(
+        &self,
+        params: GemmParams,
+        acc: &[f32],
+        j0: usize,
+        n: usize,
+        c: &mut [f32],
+        ldc: usize,
+    ) {
+        for (c_row, acc_row) in c.chunks_mut(ldc.max(1)).zip(acc.chunks(n.max(1))) {
+            for (j, (c_val, acc_val)) in c_row.iter_mut().zip(acc_row).enumerate() {
+                *c_val = self.apply(j0 + j, params.apply(*acc_val, *c_val));
+            }
+        }
+    }
+}
+
+/// `sgemm_with`, calling `progress(done, total)` as the work gets done, so a long multiply can
+/// report how far it has got.
+///
+/// Work is counted in outputs times `kc` deep panels of `b`, so `total` does not depend on how
+/// threads split `c`. Each thread adds to the count as it finishes a macro-tile of the packed
+/// kernel (see `sgemm_cancellable`), and calls `progress` unless another thread already is:
+/// calls never overlap, `done` only grows, and the last call has `done == total`, even with no
+/// rows. `b` is packed once, and with the microkernel the result is bit for bit `sgemm_with`'s.
+pub fn sgemm_with_progress(
+    a: &impl AsTensorRef<f32>,
+    a_transpose: bool,
+    b: &impl AsTensorRef<f32>,
+    b_transpose: bool,
+    params: GemmParams,
+    c: &mut impl AsTensorMut<f32>,
+    progress: impl FnMut(usize, usize) + Send,
+) {
+    try_sgemm_with_progress(a, a_transpose, b, b_transpose, params, c, progress)
+        .unwrap_or_else(|e| panic!("{}", e))
+}
+
+/// Fallible version of `sgemm_with_progress`. `c` is left untouched on error, and `progress`
+/// is not called.
+pub fn try_sgemm_with_progress(
+    a: &impl AsTensorRef<f32>,
+    a_transpose: bool,
+    b: &impl AsTensorRef<f32>,
+    b_transpose: bool,
+    params: GemmParams,
+    c: &mut impl AsTensorMut<f32>,
+    mut progress: impl FnMut(usize, usize) + Send,
+) -> Result<(), AmlError> {
+    sgemm_watched(
+        a,
+        a_transpose,
+        b,
+        b_transpose,
+        params,
+        c,
+        None,
+        &mut progress,
+    )
+}
+
+/// `sgemm_with` that stops early once `cancel` is cancelled.
+///
+/// `b` is packed once, and every thread checks `cancel` before each macro-tile of the packed
+/// kernel: an `mc` tall block of its rows against an `nc` wide block of `b` through one `kc`
+/// deep panel. So even a short, wide multiply on one thread stops within one macro-tile of
+/// being cancelled. Without a microkernel, or with `Accuracy::High`, there are no macro-tiles
+/// and the check is before each block of rows as `sgemm_with_progress` splits them.
+///
+/// A cancelled call returns `AmlError::Cancelled` with the number of rows of `c` it had
+/// finished: those rows hold their result and the rest are as they were. Shape errors are
+/// returned before any work, as by `try_sgemm_with`.
+pub fn sgemm_cancellable(
+    a: &impl AsTensorRef<f32>,
+    a_transpose: bool,
+    b: &impl AsTensorRef<f32>,
+    b_transpose: bool,
+    params: GemmParams,
+    c: &mut impl AsTensorMut<f32>,
+    cancel: &CancelToken,
+) -> Result<(), AmlError> {
+    sgemm_watched(
+        a,
+        a_transpose,
+        b,
+        b_transpose,
+        params,
+        c,
+        Some(cancel),
+        &mut |_, _| {},
+    )
+}
+
+/// The progress callback and the count it was last called with
+type Progress<'a> = (usize, &'a mut (dyn FnMut(usize, usize) + Send));
+
+/// What `sgemm_packed` checks before each macro-tile and counts after it, shared by the threads
+/// of one `sgemm_watched`
+pub(crate) struct Watch<'a> {
+    cancel: Option<&'a CancelToken>,
+    /// Work finished so far, in outputs times `kc` deep panels, out of `total`
+    done: AtomicUsize,
+    total: usize,
+    progress: Mutex<Progress<'a>>,
+}
+
+impl Watch<'_> {
+    /// Whether the call should stop where it is
+    fn stopped(&self) -> bool {
+        self.cancel.is_some_and(CancelToken::is_cancelled)
+    }
+
+    /// Count `work` more as done, and report it unless another thread is reporting
+    fn finish(&self, work: usize) {
+        self.done.fetch_add(work, Ordering::Relaxed);
+        if let Ok(mut progress) = self.progress.try_lock() {
+            self.report(&mut progress);
+        }
+    }
+
+    /// Call back with the count if it has grown since the last call
+    fn report(&self, (reported, progress): &mut Progress) {
+        let done = self.done.load(Ordering::Relaxed).min(self.total);
+        if done > *reported {
+            *reported = done;
+            progress(done, self.total);
+        }
+    }
+}
+
+/// `try_sgemm_with`, checking `cancel` before each macro-tile and calling `progress` after:
+/// `b` packed once and `c` computed in blocks of a grid, as `sgemm_grid`, each stopping at the
+/// macro-tile where `cancel` stops it. The leading bands of rows whose every block finished
+/// are stored and the rest of `c` is left as it was.
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn sgemm_watched(
+    a: &impl AsTensorRef<f32>,
+    a_transpose: bool,
+    b: &impl AsTensorRef<f32>,
+    b_transpose: bool,
+    params: GemmParams,
+    c: &mut impl AsTensorMut<f32>,
+    cancel: Option<&CancelToken>,
+    progress: &mut (dyn FnMut(usize, usize) + Send),
+) -> Result<(), AmlError> {
+    let (a, b, mut c) = (a.as_tensor_ref(), b.as_tensor_ref(), c.as_tensor_mut());
+    check_gemm(&a.shape, a_transpose, &b.shape, b_transpose, &c.shape)?;
+    c.check_blas("c")?;
+    let (m, n) = (c.shape[0], c.shape[1]);
+    let k = match a_transpose {
+        true => a.shape[0],
+        false => a.shape[1],
+    };
+    let config = default_config(m, n, k);
+    let watch = Watch {
+        cancel,
+        done: AtomicUsize::new(0),
+        total: (m * n)
+            .saturating_mul(k.div_ceil(config.block_sizes.kc))
+            .max(1),
+        progress: Mutex::new((0, progress)),
+    };
+    let operands = (a, a_transpose, b, b_transpose);
+    let result = sgemm_watched_kernel(operands, params, config, &mut c, &watch);
+    if result.is_ok() {
+        // a call with no work still reports its end
+        watch.done.store(watch.total, Ordering::Relaxed);
+        let mut progress = watch.progress.lock().unwrap_or_else(|e| e.into_inner());
+        watch.report(&mut progress);
+    }
+    result
+}
+
+/// Body of `sgemm_watched`, on checked shapes
+fn sgemm_watched_kernel(
+    (a, a_transpose, b, b_transpose): (F32TensorRef, bool, F32TensorRef, bool),
+    params: GemmParams,
+    TuneConfig {
+        block_sizes,
+        threads,
+    }: TuneConfig,
+    c: &mut TensorMut<f32>,
+    watch: &Watch,
+) -> Result<(), AmlError> {
+    let kernel = match (kernels().sgemm, params.accuracy) {
+        (Some(kernel), Accuracy::Fast) => kernel,
+        _ => {
+            let operands = (&a, a_transpose, &b, b_transpose);
+            return sgemm_blocks(operands, params, block_sizes, c, watch);
+        }
+    };
+    if watch.stopped() {
+        return Err(AmlError::Cancelled { rows: 0 });
+    }
+
+    let (a_copy, b_copy) = (a.blas_copy(), b.blas_copy());
+    let a = a_copy.as_ref().map_or(a, AsTensorRef::as_tensor_ref);
+    let b = b_copy.as_ref().map_or(b, AsTensorRef::as_tensor_ref);
+    let (m, n, ldc) = (c.shape[0], c.shape[1], c.ld());
+    let k = match a_transpose {
+        true => a.shape[0],
+        false => a.shape[1],
+    };
+    let b_transpose = b.stored_transpose(b_transpose);
+    let tile = (kernel.tile().1, kernel.name());
+    let packed_b = PackedB::pack(b.values, b_transpose, b.ld(), k, n, block_sizes.kc, tile);
+    let a_block = ABlock {
+        values: a.values,
+        a_transpose: a.stored_transpose(a_transpose),
+        m,
+        ld: a.ld(),
+        first_row: 0,
+    };
+
+    let grid = parallel::grid(threads, m, n, tile.0, k);
+    let blocks = grid_blocks(kernel, a_block, &packed_b, block_sizes, grid, Some(watch));
+    let rows = blocks
+        .iter()
+        .filter(|block| !block.finished)
+        .map(|block| block.rows.start)
+        .min()
+        .unwrap_or(m);
+    for block in blocks.iter().filter(|block| block.rows.start < rows) {
+        let (j0, j1) = (
+            block.strips.start * tile.0,
+            (block.strips.end * tile.0).min(n),
+        );
+        for (i, acc_row) in block.rows.clone().zip(block.acc.chunks(j1 - j0)) {
+            for (j, acc) in (j0..j1).zip(acc_row) {
+                let c = match c.layout {
+                    Layout::RowMajor => &mut c.values[i * ldc + j],
+                    Layout::ColMajor => &mut c.values[j * ldc + i],
+                };
+                *c = params.apply(*acc, *c);
+            }
+        }
+    }
+    match rows == m {
+        true => Ok(()),
+        false => Err(AmlError::Cancelled { rows }),
+    }
+}
+
+/// `sgemm_watched` without macro-tiles: `try_sgemm_with` one block of rows of `c` at a time,
+/// as many rows as every thread takes in one `mc` pass, checking `watch` before each and
+/// counting it after
+fn sgemm_blocks(
+    (a, a_transpose, b, b_transpose): (&F32TensorRef, bool, &F32TensorRef, bool),
+    params: GemmParams,
+    BlockSizes { mc, kc, .. }: BlockSizes,
+    c: &mut TensorMut<f32>,
+    watch: &Watch,
+) -> Result<(), AmlError> {
+    let (m, n) = (c.shape[0], c.shape[1]);
+    let k = match a_transpose {
+        true => a.shape[0],
+        false => a.shape[1],
+    };
+    let block_rows = (mc * parallel::num_threads()).max(1);
+    for i0 in (0..m).step_by(block_rows) {
+        let rows = i0..(i0 + block_rows).min(m);
+        if watch.stopped() {
+            return Err(AmlError::Cancelled { rows: i0 });
+        }
+        let a_rows = match a_transpose {
+            true => a.try_view(0..k, rows.clone())?,
+            false => a.try_view(rows.clone(), 0..k)?,
+        };
+        let work = rows.len() * n * k.div_ceil(kc);
+        let mut c_rows = c.try_window_mut(rows, 0..n)?;
+        try_sgemm_with(&a_rows, a_transpose, b, b_transpose, params, &mut c_rows)?;
+        watch.finish(work);
+    }
+    Ok(())
+}
+
+/// `sgemm` against a `b` packed ahead of time by `pack_b`, for a weight matrix reused across
+/// many calls. With the microkernel this is bit for bit `sgemm` on the tensor it was packed from;
+/// the BLAS1 fallback may sum a transposed `b` in another order. `c` must be row-major.
+pub fn sgemm_prepacked(
+    a: &impl AsTensorRef<f32>,
+    a_transpose: bool,
+    b: &PackedB,
+    c: &mut impl AsTensorMut<f32>,
+) {
+    try_sgemm_prepacked(a, a_transpose, b, c).unwrap_or_else(|e| panic!("{}", e))
+}
+
+/// Fallible version of `sgemm_prepacked`. `c` is left untouched on error.
+pub fn try_sgemm_prepacked(
+    a: &impl AsTensorRef<f32>,
+    a_transpose: bool,
+    b: &PackedB,
+    c: &mut impl AsTensorMut<f32>,
+) -> Result<(), AmlError> {
+    try_sgemm_prepacked_with(a, a_transpose, b, GemmParams::default(), c)
+}
+
+/// `c = alpha * (op(a) @ b) + beta * c` with a prepacked `b`
+pub fn sgemm_prepacked_with(
+    a: &impl AsTensorRef<f32>,
+    a_transpose: bool,
+    b: &PackedB,
+    params: GemmParams,
+    c: &mut impl AsTensorMut<f32>,
+) {
+    try_sgemm_prepacked_with(a, a_transpose, b, params, c).unwrap_or_else(|e| panic!("{}", e))
+}
+
+/// Fallible version of `sgemm_prepacked_with`. `c` is left untouched on error.
+pub fn try_sgemm_prepacked_with(
+    a: &impl AsTensorRef<f32>,
+    a_transpose: bool,
+    b: &PackedB,
+    params: GemmParams,
+    c: &mut impl AsTensorMut<f32>,
+) -> Result<(), AmlError> {
+    let (a, mut c) = (a.as_tensor_ref(), c.as_tensor_mut());
+    check_gemm(
+        &a.shape,
+        a_transpose,
+        &Shape::from([b.k, b.n]),
+        false,
+        &c.shape,
+    )?;
+    check_row_major("c", c.layout)?;
+    c.check_blas("c")?;
+    let a_copy = a.blas_copy();
+    let a = a_copy.as_ref().map_or(a, AsTensorRef::as_tensor_ref);
+    sgemm_kernel(
+        &a,
+        a_transpose,
+        OpB::Packed(b),
+        params,
+        None,
+        kernels(),
+        &mut c,
+    );
+    Ok(())
+}
+
+/// `sgemm_with` on the caller's thread, with every buffer taken from `workspace`, so a loop
+/// over the same shapes allocates nothing after its first call (or none at all, with a
+/// workspace from `Workspace::for_sgemm`). Strided operands are still copied, and
+/// `Accuracy::High` or a machine without a microkernel still allocate as `sgemm_with` does.
+pub fn sgemm_in(
+    a: &impl AsTensorRef<f32>,
+    a_transpose: bool,
+    b: &impl AsTensorRef<f32>,
+    b_transpose: bool,
+    params: GemmParams,
+    workspace: &mut Workspace,
+    c: &mut impl AsTensorMut<f32>,
+) {
+    try_sgemm_in(a, a_transpose, b, b_transpose, params, workspace, c)
+        .unwrap_or_else(|e| panic!("{}", e))
+}
+
+/// Fallible version of `sgemm_in`. `c` is left untouched on error.
+pub fn try_sgemm_in(
+    a: &impl AsTensorRef<f32>,
+    a_transpose: bool,
+    b: &impl AsTensorRef<f32>,
+    b_transpose: bool,
+    params: GemmParams,
+    workspace: &mut Workspace,
+    c: &mut impl AsTensorMut<f32>,
+) -> Result<(), AmlError> {
+    let (a, b, c) = (a.as_tensor_ref(), b.as_tensor_ref(), c.as_tensor_mut());
+    check_gemm(&a.shape, a_transpose, &b.shape, b_transpose, &c.shape)?;
+    c.check_blas("c")?;
+    let (a_copy, b_copy) = (a.blas_copy(), b.blas_copy());
+    let a = a_copy.as_ref().map_or(a, AsTensorRef::as_tensor_ref);
+    let b = b_copy.as_ref().map_or(b, AsTensorRef::as_tensor_ref);
+    let (a, a_transpose, b, b_transpose) =
+        gemm_operands(c.layout, &a, a_transpose, &b, b_transpose);
+    sgemm_serial(
+        a,
+        a_transpose,
+        OpB::Tensor(b, b_transpose),
+        params,
+        workspace,
+        &mut c.row_major(),
+    );
+    Ok(())
+}
+
+/// `sgemm_prepacked_with` on the caller's thread with buffers from `workspace`, as `sgemm_in`
+pub fn sgemm_prepacked_in(
+    a: &impl AsTensorRef<f32>,
+    a_transpose: bool,
+    b: &PackedB,
+    params: GemmParams,
+    workspace: &mut Workspace,
+    c: &mut impl AsTensorMut<f32>,
+) {
+    try_sgemm_prepacked_in(a, a_transpose, b, params, workspace, c)
+        .unwrap_or_else(|e| panic!("{}", e))
+}
+
+/// Fallible version of `sgemm_prepacked_in`. `c` is left untouched on error.
+pub fn try_sgemm_prepacked_in(
+    a: &impl AsTensorRef<f32>,
+    a_transpose: bool,
+    b: &PackedB,
+    params: GemmParams,
+    workspace: &mut Workspace,
+    c: &mut impl AsTensorMut<f32>,
+) -> Result<(), AmlError> {
+    let (a, mut c) = (a.as_tensor_ref(), c.as_tensor_mut());
+    check_gemm(
+        &a.shape,
+        a_transpose,
+        &Shape::from([b.k, b.n]),
+        false,
+        &c.shape,
+    )?;
+    check_row_major("c", c.layout)?;
+    c.check_blas("c")?;
+    let a_copy = a.blas_copy();
+    let a = a_copy.as_ref().map_or(a, AsTensorRef::as_tensor_ref);
+    sgemm_serial(&a, a_transpose, OpB::Packed(b), params, workspace, &mut c);
+    Ok(())
+}
+
+/// Pack op(b) for the microkernel this machine runs, so `sgemm_prepacked` can skip packing it
+/// on every call.
+///
+/// The panels are cut for the current `block_sizes().kc`. Changing the block sizes afterwards
+/// is safe but the panel depth stays as packed.
+pub fn pack_b(b: &impl AsTensorRef<f32>, b_transpose: bool) -> PackedB {
+    try_pack_b(b, b_transpose).unwrap_or_else(|e| panic!("{}", e))
+}
+
+/// Fallible version of `pack_b`.
+pub fn try_pack_b(b: &impl AsTensorRef<f32>, b_transpose: bool) -> Result<PackedB, AmlError> {
+    let b = b.as_tensor_ref();
+    check_rank("b", &b.shape, 2)?;
+    let b_copy = b.blas_copy();
+    let b = b_copy.as_ref().map_or(b, AsTensorRef::as_tensor_ref);
+    let (k, n) = match b_transpose {
+        true => (b.shape[1], b.shape[0]),
+        false => (b.shape[0], b.shape[1]),
+    };
+
+    let b_transpose = b.stored_transpose(b_transpose);
+    Ok(match kernels().sgemm {
+        Some(kernel) => PackedB::pack(
+            b.values,
+            b_transpose,
+            b.ld(),
+            k,
+            n,
+            block_sizes().kc,
+            (kernel.tile().1, kernel.name()),
+        ),
+        // one panel of one strip is op(b) itself, which the BLAS1 path reads without a copy
+        None => PackedB::pack(
+            b.values,
+            b_transpose,
+            b.ld(),
+            k,
+            n,
+            k.max(1),
+            (n.max(1), "blas1"),
+        ),
+    })
+}
+
+/// An `sgemm` with every knob set per call instead of from the process wide defaults:
+/// `Gemm::builder().threads(1).simd(false).run(&a, &b, &mut c)`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Gemm {
+    a_transpose: bool,
+    b_transpose: bool,
+    params: GemmParams,
+    block_sizes: Option<BlockSizes>,
+    threads: Option<usize>,
+    serial_threshold: usize,
+    pin_threads: bool,
+    simd: bool,
+}
+
+impl Gemm {
+    /// `c = a @ b` with the same blocking, threading and kernels as `sgemm`
+    pub fn builder() -> Gemm {
+        Gemm {
+            a_transpose: false,
+            b_transpose: false,
+            params: GemmParams::default(),
+            block_sizes: None,
+            threads: None,
+            serial_threshold: parallel::SERIAL_THRESHOLD,
+            pin_threads: false,
+            simd: true,
+        }
+    }
+
+    /// Multiply by op(a) = a^T instead of a
+    pub fn a_transpose(self, a_transpose: bool) -> Gemm {
+        Gemm {
+            a_transpose,
+            ..self
+        }
+    }
+
+    /// Multiply by op(b) = b^T instead of b. Ignored by `run_prepacked`.
+    pub fn b_transpose(self, b_transpose: bool) -> Gemm {
+        Gemm {
+            b_transpose,
+            ..self
+        }
+    }
+
+    /// Scale of the product, 1 by default
+    pub fn alpha(self, alpha: f32) -> Gemm {
+        let params = GemmParams {
+            alpha,
+            ..self.params
+        };
+        Gemm { params, ..self }
+    }
+
+    /// Scale of the old `c` added in, 0 by default
+    pub fn beta(self, beta: f32) -> Gemm {
+        let params = GemmParams {
+            beta,
+            ..self.params
+        };
+        Gemm { params, ..self }
+    }
+
+    /// How each output is accumulated, `Accuracy::Fast` by default
+    pub fn accuracy(self, accuracy: Accuracy) -> Gemm {
+        let params = self.params.with_accuracy(accuracy);
+        Gemm { params, ..self }
+    }
+
+    /// Block sizes for this call only, instead of `block_sizes()` or a tuned choice
+    pub fn block_sizes(self, block_sizes: BlockSizes) -> Gemm {
+        Gemm {
+            block_sizes: Some(block_sizes),
+            ..self
+        }
+    }
+
+    /// Most threads to split over, instead of one per core. 0 is taken as 1.
+    pub fn threads(self, threads: usize) -> Gemm {
+        Gemm {
+            threads: Some(threads),
+            ..self
+        }
+    }
+
+    /// Run on the caller's thread when `m * n * k` is below `multiply_adds`, 64^3 by default.
+    /// 0 always allows threads.
+    pub fn serial_threshold(self, multiply_adds: usize) -> Gemm {
+        Gemm {
+            serial_threshold: multiply_adds,
+            ..self
+        }
+    }
+
+    /// Pin the worker threads to separate physical cores, as `AmlContext::pin_threads`
+    pub fn pin_threads(self, pin_threads: bool) -> Gemm {
+        Gemm {
+            pin_threads,
+            ..self
+        }
+    }
+
+    /// With `false`, run only the portable kernels, e.g. to compare against the SIMD ones
+    pub fn simd(self, simd: bool) -> Gemm {
+        Gemm { simd, ..self }
+    }
+
+    /// `c = alpha * (op(a) @ op(b)) + beta * c` with these settings
+    pub fn run(
+        &self,
+        a: &impl AsTensorRef<f32>,
+        b: &impl AsTensorRef<f32>,
+        c: &mut impl AsTensorMut<f32>,
+    ) {
+        self.try_run(a, b, c).unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    /// Fallible version of `run`. `c` is left untouched on error.
+    pub fn try_run(
+        &self,
+        a: &impl AsTensorRef<f32>,
+        b: &impl AsTensorRef<f32>,
+        c: &mut impl AsTensorMut<f32>,
+    ) -> Result<(), AmlError> {
+        let (a, b, c) = (a.as_tensor_ref(), b.as_tensor_ref(), c.as_tensor_mut());
+        check_gemm(
+            &a.shape,
+            self.a_transpose,
+            &b.shape,
+            self.b_transpose,
+            &c.shape,
+        )?;
+        c.check_blas("c")?;
+        let (a_copy, b_copy) = (a.blas_copy(), b.blas_copy());
+        let a = a_copy.as_ref().map_or(a, AsTensorRef::as_tensor_ref);
+        let b = b_copy.as_ref().map_or(b, AsTensorRef::as_tensor_ref);
+        let (a, a_transpose, b, b_transpose) =
+            gemm_operands(c.layout, &a, self.a_transpose, &b, self.b_transpose);
+        let c = &mut c.row_major();
+        self.try_run_op(a, a_transpose, OpB::Tensor(b, b_transpose), c)
+    }
+
+    /// `run` against a `b` from `pack_b`, into a row-major `c`
+    pub fn run_prepacked(
+        &self,
+        a: &impl AsTensorRef<f32>,
+        b: &PackedB,
+        c: &mut impl AsTensorMut<f32>,
+    ) {
+        self.try_run_prepacked(a, b, c)
+            .unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    /// Fallible version of `run_prepacked`. `c` is left untouched on error.
+    pub fn try_run_prepacked(
+        &self,
+        a: &impl AsTensorRef<f32>,
+        b: &PackedB,
+        c: &mut impl AsTensorMut<f32>,
+    ) -> Result<(), AmlError> {
+        let (a, mut c) = (a.as_tensor_ref(), c.as_tensor_mut());
+        check_gemm(
+            &a.shape,
+            self.a_transpose,
+            &Shape::from([b.k, b.n]),
+            false,
+            &c.shape,
+        )?;
+        check_row_major("c", c.layout)?;
+        c.check_blas("c")?;
+        let a_copy = a.blas_copy();
+        let a = a_copy.as_ref().map_or(a, AsTensorRef::as_tensor_ref);
+        self.try_run_op(&a, self.a_transpose, OpB::Packed(b), &mut c)
+    }
+
+    fn try_run_op(
+        &self,
+        a: &F32TensorRef,
+        a_transpose: bool,
+        b: OpB,
+        c: &mut TensorMut<f32>,
+    ) -> Result<(), AmlError> {
+        if let Some(BlockSizes { mc, kc, nc }) = self.block_sizes {
+            if mc == 0 || kc == 0 || nc == 0 {
+                return Err(AmlError::ZeroBlockSize { mc, kc, nc });
+            }
+        }
+
+        // anything left unset falls back to what a plain `sgemm` of this shape would use
+        let (m, n) = (c.shape[0], c.shape[1]);
+        let k = match a_transpose {
+            true => a.shape[0],
+            false => a.shape[1],
+        };
+        let config = match (self.block_sizes, self.threads) {
+            (Some(block_sizes), Some(threads)) => TuneConfig {
+                block_sizes,
+                threads,
+            },
+            _ => {
+                let default = autotune::sgemm_config(m, n, k);
+                TuneConfig {
+                    block_sizes: self.block_sizes.unwrap_or(default.block_sizes),
+                    threads: self.threads.unwrap_or(default.threads),
+                }
+            }
+        };
+        let config = serial_below(config, (m, n, k), self.serial_threshold);
+        let kernels = match self.simd {
+            true => kernels(),
+            false => scalar_kernels(),
+        };
+
+        // keeps the policies of any context the caller installed
+        let context = AmlContext::new(config.threads)
+            .pin_threads(self.pin_threads)
+            .numa(parallel::numa())
+            .deterministic(parallel::deterministic());
+        context.install(|| sgemm_kernel(a, a_transpose, b, self.params, Some(config), kernels, c));
+        Ok(())
+    }
+}
+
+impl Default for Gemm {
+    fn default() -> Gemm {
+        Gemm::builder()
+    }
+}
+
+/// The `b` operand of `sgemm_kernel`
+#[derive(Clone, Copy)]
+pub(crate) enum OpB<'a> {
+    Tensor(&'a F32TensorRef<'a>, bool),
+    Packed(&'a PackedB),
+}
+
+impl OpB<'_> {
+    /// `b` values, transpose flag and leading dimension for the paths that do not use the
+    /// microkernel
+    fn plain(&self) -> (Cow<'_, [f32]>, bool, usize) {
+        match self {
+            OpB::Tensor(b, b_transpose) => (
+                Cow::Borrowed(b.values),
+                b.stored_transpose(*b_transpose),
+                b.ld(),
+            ),
+            OpB::Packed(b) => (b.unpack(), false, b.n.max(1)),
+        }
+    }
+}
+
+/// Unchecked body of the `sgemm` family. Shapes must already have passed `check_gemm`, and `c`
+/// is written row-major whatever its layout.
+///
+/// Blocking and threading come from `config`, or from `autotune::sgemm_config` without one, on
+/// one thread below `parallel::SERIAL_THRESHOLD`.
+pub(crate) fn sgemm_kernel(
+    a: &F32TensorRef,
+    a_transpose: bool,
+    b: OpB,
+    params: GemmParams,
+    config: Option<TuneConfig>,
+    kernels: &Kernels,
+    c: &mut TensorMut<f32>,
+) {
+    let epilogue = Epilogue::default();
+    sgemm_fused_kernel(a, a_transpose, b, params, epilogue, config, kernels, c)
+}
+
+/// `sgemm_kernel` with `epilogue` applied to each output as it is stored
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn sgemm_fused_kernel(
+    a: &F32TensorRef,
+    a_transpose: bool,
+    b: OpB,
+    params: GemmParams,
+    epilogue: Epilogue,
+    config: Option<TuneConfig>,
+    kernels: &Kernels,
+    c: &mut TensorMut<f32>,
+) {
+    let (m, n) = (c.shape[0], c.shape[1]);
+    let k = match a_transpose {
+        true => a.shape[0],
+        false => a.shape[1],
+    };
+    let a_transpose = a.stored_transpose(a_transpose);
+    let TuneConfig {
+        block_sizes,
+        threads,
+    } = config.unwrap_or_else(|| default_config(m, n, k));
+
+    // b is packed once and shared by every thread; each packs its own blocks of a
+    let microkernel = match params.accuracy {
+        Accuracy::Fast => kernels.sgemm,
+        Accuracy::High => None,
+    };
+    let packed_b = match (microkernel, b) {
+        // the tile width is checked too, as a buffer from `from_bytes` may claim any
+        (Some(kernel), OpB::Packed(packed))
+            if packed.isa == kernel.name() && packed.nr == kernel.tile().1 =>
+        {
+            Some(Cow::Borrowed(packed))
+        }
+        (Some(kernel), _) => {
+            let (values, b_transpose, ldb) = b.plain();
+            let kc = block_sizes.kc;
+            Some(Cow::Owned(PackedB::pack(
+                &values,
+                b_transpose,
+                ldb,
+                k,
+                n,
+                kc,
+                (kernel.tile().1, kernel.name()),
+            )))
+        }
+        (None, _) => None,
+    };
+    let b_plain = match packed_b {
+        Some(_) => None,
+        None => Some(b.plain()),
+    };
+
+    #[cfg(feature = "parallel")]
+    if let (Some(kernel), Some(packed_b), true) = (microkernel, &packed_b, parallel::numa()) {
+        let nodes = crate::affinity::numa_nodes();
+        if nodes.len() > 1 {
+            let a_block = ABlock {
+                values: a.values,
+                a_transpose,
+                m,
+                ld: a.ld(),
+                first_row: 0,
+            };
+            let config = TuneConfig {
+                block_sizes,
+                threads,
+            };
+            sgemm_numa(
+                kernel,
+                a_block,
+                packed_b,
+                config,
+                nodes,
+                (params, epilogue),
+                c,
+            );
+            return;
+        }
+    }
+
+    // flat shapes split across columns as well, so every thread gets work
+    if let (Some(kernel), Some(packed_b)) = (microkernel, &packed_b) {
+        let grid = parallel::grid(threads, m, n, kernel.tile().1, k);
+        if grid.1 > 1 {
+            let a_block = ABlock {
+                values: a.values,
+                a_transpose,
+                m,
+                ld: a.ld(),
+                first_row: 0,
+            };
+            sgemm_grid(
+                kernel,
+                a_block,
+                packed_b,
+                block_sizes,
+                grid,
+                (params, epilogue),
+                c,
+            );
+            return;
+        }
+    }
+
+    let ldc = c.ld();
+    parallel::for_each_row_chunk_with(threads, c.values, ldc, n * k, |first_row, c_rows| {
+        let rows = c_rows.len().div_ceil(ldc);
+
+        match params.accuracy {
+            Accuracy::Fast => {
+                let mut acc = vec![0f32; rows * n];
+                match (microkernel, &packed_b, &b_plain) {
+                    (Some(kernel), Some(packed_b), _) => {
+                        let a_block = ABlock {
+                            values: a.values,
+                            a_transpose,
+                            m,
+                            ld: a.ld(),
+                            first_row,
+                        };
+                        let strips = 0..n.div_ceil(kernel.tile().1);
+                        let scratch = &mut PackScratch::default();
+                        sgemm_packed(
+                            kernel,
+                            a_block,
+                            packed_b,
+                            strips,
+                            block_sizes,
+                            &mut acc,
+                            scratch,
+                            None,
+                        );
+                    }
+                    (_, _, Some((b_values, b_transpose, ldb))) => {
+                        let a_rows = op_a_rows(a.values, a_transpose, k, a.ld(), first_row, rows);
+                        sgemm_avx(
+                            kernels,
+                            &a_rows,
+                            (b_values, *b_transpose, *ldb),
+                            n,
+                            k,
+                            block_sizes.kc,
+                            &mut acc,
+                        )
+                    }
+                    _ => unreachable!("b is either packed or plain"),
+                }
+                epilogue.store_rows(params, &acc, 0, n, c_rows, ldc);
+            }
+            Accuracy::High => {
+                let a_rows = op_a_rows(a.values, a_transpose, k, a.ld(), first_row, rows);
+                let (b_values, b_transpose, ldb) = b_plain.as_ref().expect("High never packs b");
+                let kc = block_sizes.kc;
+                let mut acc = vec![0f64; rows * n];
+                match b_transpose {
+                    true => {
+                        for (a_i, acc_row) in a_rows.chunks_exact(k).zip(acc.chunks_exact_mut(n)) {
+                            for (acc_val, b_j) in
+                                acc_row.iter_mut().zip(strided_rows(b_values, *ldb, k))
+                            {
+                                *acc_val = (kernels.dsdot)(a_i, b_j);
+                            }
+                        }
+                    }
+                    false => {
+                        for p0 in (0..k).step_by(kc) {
+                            let p1 = (p0 + kc).min(k);
+                            let b_panel = &b_values[p0 * ldb..];
+                            for (a_i, acc_row) in
+                                a_rows.chunks_exact(k).zip(acc.chunks_exact_mut(n))
+                            {
+                                for (a_ip, b_p) in
+                                    a_i[p0..p1].iter().zip(strided_rows(b_panel, *ldb, n))
+                                {
+                                    (kernels.dsaxpy)(*a_ip as f64, b_p, acc_row);
+                                }
+                            }
+                        }
+                    }
+                }
+                // scale and finish in f64 too, so the only rounding is the final one to f32
+                let params64 = GemmParams::new(params.alpha as f64, params.beta as f64);
+                for (c_row, acc_row) in c_rows.chunks_mut(ldc.max(1)).zip(acc.chunks(n.max(1))) {
+                    for (j, (c_val, acc_val)) in c_row.iter_mut().zip(acc_row).enumerate() {
+                        let value = params64.apply(*acc_val, *c_val as f64);
+                        *c_val = epilogue.apply_f64(j, value) as f32;
+                    }
+                }
+            }
+        }
+    });
+}
+
+/// `autotune::sgemm_config` for an (m, n, k) product, on one thread below
+/// `parallel::SERIAL_THRESHOLD`
+fn default_config(m: usize, n: usize, k: usize) -> TuneConfig {
+    serial_below(
+        autotune::sgemm_config(m, n, k),
+        (m, n, k),
+        parallel::SERIAL_THRESHOLD,
+    )
+}
+
+/// `config` on one thread when an (m, n, k) product has fewer than `threshold` multiply-adds.
+/// The count saturates, so a product too large to count is never taken for a small one.
+pub(crate) fn serial_below(
+    config: TuneConfig,
+    (m, n, k): (usize, usize, usize),
+    threshold: usize,
+) -> TuneConfig {
+    match m.saturating_mul(n).saturating_mul(k) < threshold {
+        true => TuneConfig {
+            threads: 1,
+            ..config
+        },
+        false => config,
+    }
+}
+
+/// Body of `sgemm_in`: `sgemm_kernel` on one thread, with `b` packed and `c` accumulated in
+/// `workspace`. Paths without the microkernel go to `sgemm_kernel` as they are.
+fn sgemm_serial(
+    a: &F32TensorRef,
+    a_transpose: bool,
+    b: OpB,
+    params: GemmParams,
+    workspace: &mut Workspace,
+    c: &mut TensorMut<f32>,
+) {
+    let (m, n) = (c.shape[0], c.shape[1]);
+    let k = match a_transpose {
+        true => a.shape[0],
+        false => a.shape[1],
+    };
+    let block_sizes = block_sizes();
+    let kernels = kernels();
+    let kernel = match (kernels.sgemm, params.accuracy) {
+        (Some(kernel), Accuracy::Fast) => kernel,
+        _ => {
+            let config = TuneConfig {
+                block_sizes,
+                threads: 1,
+            };
+            return sgemm_kernel(a, a_transpose, b, params, Some(config), kernels, c);
+        }
+    };
+
+    let Workspace {
+        acc,
+        packed_b,
+        scratch,
+    } = workspace;
+    let tile = (kernel.tile().1, kernel.name());
+    let repacked = match b {
+        OpB::Packed(packed) if packed.isa == tile.1 && packed.nr == tile.0 => None,
+        _ => {
+            // the packing takes the buffer and hands it back below
+            let (values, b_transpose, ldb) = b.plain();
+            let plain = (&*values, b_transpose, ldb);
+            let buffer = std::mem::take(packed_b);
+            Some(PackedB::pack_into(
+                buffer,
+                plain,
+                k,
+                n,
+                block_sizes.kc,
+                tile,
+            ))
+        }
+    };
+    let packed = match (&repacked, b) {
+        (Some(packed), _) | (None, OpB::Packed(packed)) => packed,
+        (None, OpB::Tensor(..)) => unreachable!("a plain b is always packed"),
+    };
+
+    let a_block = ABlock {
+        values: a.values,
+        a_transpose: a.stored_transpose(a_transpose),
+        m,
+        ld: a.ld(),
+        first_row: 0,
+    };
+    let acc = workspace::zeroed(acc, m * n);
+    let strips = 0..n.div_ceil(tile.0);
+    sgemm_packed(
+        kernel,
+        a_block,
+        packed,
+        strips,
+        block_sizes,
+        acc,
+        scratch,
+        None,
+    );
+    let ldc = c.ld();
+    store_rows(acc, n, c.values, ldc, |acc, c| *c = params.apply(acc, *c));
+
+    if let Some(repacked) = repacked {
+        *packed_b = repacked.values;
+    }
+}
+
+/// `acc = a_rows @ op(b)` with the BLAS1 kernels: `sdot` against the rows of a transposed `b`,
+/// otherwise `kc` deep panels of `saxpy`.
+fn sgemm_avx(
+    kernels: &Kernels,
+    a_rows: &[f32],
+    (b, b_transpose, ldb): (&[f32], bool, usize),
+    n: usize,
+    k: usize,
+    kc: usize,
+    acc: &mut [f32],
+) {
+    match b_transpose {
+        true => {
+            for (a_i, acc_row) in a_rows.chunks_exact(k).zip(acc.chunks_exact_mut(n)) {
+                for (acc_val, b_j) in acc_row.iter_mut().zip(strided_rows(b, ldb, k)) {
+                    *acc_val = (kernels.sdot)(a_i, b_j);
+                }
+            }
+        }
+        false => {
+            for p0 in (0..k).step_by(kc) {
+                let p1 = (p0 + kc).min(k);
+                let b_panel = &b[p0 * ldb..];
+                for (a_i, acc_row) in a_rows.chunks_exact(k).zip(acc.chunks_exact_mut(n)) {
+                    for (a_ip, b_p) in a_i[p0..p1].iter().zip(strided_rows(b_panel, ldb, n)) {
+                        (kernels.saxpy)(*a_ip, b_p, acc_row);
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// op(b), (k, n), packed for a microkernel `nr` columns wide. Made by `pack_b`.
+///
+/// op(b) is cut into `kc` deep panels, and each panel into `nr` wide column strips, zero padded
+/// on the right. A strip holds its `kc` rows of `nr` values back to back, the order the
+/// microkernel reads them in, and the strips of a panel follow each other.
+///
+/// `to_bytes` writes the packing out so a model loader can skip `pack_b` at startup. The buffer
+/// is, all little endian: the magic `AMLPACKB`, a u32 format version, the ISA packed for as
+/// 8 zero padded ASCII bytes, `k`, `n`, `kc` and `nr` as u64, then the f32 values.
+#[derive(Debug, Clone)]
+pub struct PackedB {
+    pub(crate) values: Vec<f32>,
+    pub(crate) k: usize,
+    pub(crate) n: usize,
+    pub(crate) kc: usize,
+    pub(crate) nr: usize,
+    pub(crate) isa: &'static str,
+}
+
+const PACKED_B_MAGIC: &[u8; 8] = b"AMLPACKB";
+const PACKED_B_VERSION: u32 = 1;
+const PACKED_B_HEADER: usize = 8 + 4 + 8 + 4 * 8;
+
+impl PackedB {
+    /// Pack op(b), (k, n), straight from `b` in either layout, its stored rows `ldb` apart, for
+    /// a microkernel `(nr, isa)`.
+    pub(crate) fn pack(
+        b: &[f32],
+        b_transpose: bool,
+        ldb: usize,
+        k: usize,
+        n: usize,
+        kc: usize,
+        tile: (usize, &'static str),
+    ) -> PackedB {
+        PackedB::pack_into(Vec::new(), (b, b_transpose, ldb), k, n, kc, tile)
+    }
+
+    /// `pack`, reusing the allocation of `values`
+    pub(crate) fn pack_into(
+        mut values: Vec<f32>,
+        (b, b_transpose, ldb): (&[f32], bool, usize),
+        k: usize,
+        n: usize,
+        kc: usize,
+        (nr, isa): (usize, &'static str),
+    ) -> PackedB {
+        let n_padded = n.div_ceil(nr) * nr;
+        workspace::zeroed(&mut values, k * n_padded);
+
+        for p0 in (0..k).step_by(kc) {
+            let p1 = (p0 + kc).min(k);
+            let panel = &mut values[p0 * n_padded..p1 * n_padded];
+            for (strip_idx, strip) in panel.chunks_exact_mut((p1 - p0) * nr).enumerate() {
+                let j0 = strip_idx * nr;
+                let cols = (n - j0).min(nr);
+                for (p, strip_p) in (p0..p1).zip(strip.chunks_exact_mut(nr)) {
+                    match b_transpose {
+                        true => {
+                            for (j, value) in strip_p[..cols].iter_mut().enumerate() {
+                                *value = b[(j0 + j) * ldb + p];
+                            }
+                        }
+                        false => {
+                            strip_p[..cols].copy_from_slice(&b[p * ldb + j0..p * ldb + j0 + cols])
+                        }
+                    }
+                }
+            }
+        }
+
+        PackedB {
+            values,
+            k,
+            n,
+            kc,
+            nr,
+            isa,
+        }
+    }
+
+    /// Shape of op(b), (k, n)
+    pub fn shape(&self) -> [usize; 2] {
+        [self.k, self.n]
+    }
+
+    /// Microkernel the panels were cut for, or `blas1` if there was none
+    pub fn isa(&self) -> &str {
+        self.isa
+    }
+
+    /// Whether this packing matches what `pack_b` would make on this machine. `sgemm_prepacked`
+    /// accepts any packing but repacks a foreign one on every call.
+    pub fn is_native(&self) -> bool {
+        match kernels().sgemm {
+            Some(kernel) => self.isa == kernel.name() && self.nr == kernel.tile().1,
+            None => self.isa == "blas1",
+        }
+    }
+
+    /// Header and values as described on `PackedB`
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(PACKED_B_HEADER + 4 * self.values.len());
+        bytes.extend_from_slice(PACKED_B_MAGIC);
+        bytes.extend_from_slice(&PACKED_B_VERSION.to_le_bytes());
+        let mut isa = [0u8; 8];
+        isa[..self.isa.len()].copy_from_slice(self.isa.as_bytes());
+        bytes.extend_from_slice(&isa);
+        for dim in [self.k, self.n, self.kc, self.nr] {
+            bytes.extend_from_slice(&(dim as u64).to_le_bytes());
+        }
+        for value in &self.values {
+            bytes.extend_from_slice(&value.to_le_bytes());
+        }
+        bytes
+    }
+
+    /// Read back a buffer written by `to_bytes`.
+    pub fn from_bytes(bytes: &[u8]) -> PackedB {
+        PackedB::try_from_bytes(bytes).unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    /// Fallible version of `from_bytes`.
+    pub fn try_from_bytes(bytes: &[u8]) -> Result<PackedB, AmlError> {
+        let invalid = |reason| AmlError::InvalidPackedB { reason };
+        if bytes.len() < PACKED_B_HEADER || &bytes[..8] != PACKED_B_MAGIC {
+            return Err(invalid("missing header"));
+        }
+        if bytes[8..12] != PACKED_B_VERSION.to_le_bytes() {
+            return Err(invalid("unsupported version"));
+        }
+        let isa_bytes = &bytes[12..20];
+        let isa_len = isa_bytes.iter().position(|v| *v == 0).unwrap_or(8);
+        let isa = ISA_NAMES
+            .into_iter()
+            .find(|name| name.as_bytes() == &isa_bytes[..isa_len])
+            .ok_or(invalid("unknown ISA"))?;
+
+        let mut dims = bytes[20..PACKED_B_HEADER]
+            .chunks_exact(8)
+            .map(|v| usize::try_from(u64::from_le_bytes(v.try_into().unwrap())));
+        let mut dim = || {
+            dims.next()
+                .unwrap()
+                .map_err(|_| invalid("dimension too large"))
+        };
+        let (k, n, kc, nr) = (dim()?, dim()?, dim()?, dim()?);
+        if kc == 0 || nr == 0 {
+            return Err(invalid("zero panel size"));
+        }
+
+        let len = n
+            .div_ceil(nr)
+            .checked_mul(nr)
+            .and_then(|n_padded| n_padded.checked_mul(k))
+            .ok_or(invalid("dimension too large"))?;
+        let values = &bytes[PACKED_B_HEADER..];
+        if len.checked_mul(4) != Some(values.len()) {
+            return Err(invalid("value count does not match the header"));
+        }
+
+        Ok(PackedB {
+            values: values
+                .chunks_exact(4)
+                .map(|v| f32::from_le_bytes(v.try_into().unwrap()))
+                .collect(),
+            k,
+            n,
+            kc,
+            nr,
+            isa,
+        })
+    }
+
+    /// op(b) as k x n rows, borrowed when the packing is a single full width strip
+    fn unpack(&self) -> Cow<'_, [f32]> {
+        if self.kc >= self.k && self.nr == self.n {
+            return Cow::Borrowed(&self.values);
+        }
+        let mut rows = vec![0f32; self.k * self.n];
+        for p0 in (0..self.k).step_by(self.kc) {
+            for (strip_idx, j0) in (0..self.n).step_by(self.nr).enumerate() {
+                let cols = (self.n - j0).min(self.nr);
+                for (p, strip_p) in (p0..).zip(self.strip(p0, strip_idx).chunks_exact(self.nr)) {
+                    rows[p * self.n + j0..p * self.n + j0 + cols].copy_from_slice(&strip_p[..cols]);
+                }
+            }
+        }
+        Cow::Owned(rows)
+    }
+
+    /// Strip `strip_idx` of the panel starting at row `p0`
+    fn strip(&self, p0: usize, strip_idx: usize) -> &[f32] {
+        let depth = (p0 + self.kc).min(self.k) - p0;
+        let start = p0 * self.n.div_ceil(self.nr) * self.nr + strip_idx * depth * self.nr;
+        &self.values[start..start + depth * self.nr]
+    }
+}
+
+/// The rows of op(a) one thread computes, read in place from `a`.
+#[derive(Clone, Copy)]
+pub(crate) struct ABlock<'a> {
+    pub(crate) values: &'a [f32],
+    pub(crate) a_transpose: bool,
+    pub(crate) m: usize,
+    /// Values between the starts of the stored rows of `a`
+    pub(crate) ld: usize,
+    pub(crate) first_row: usize,
+}
+
+impl ABlock<'_> {
+    /// Pack rows `rows` and columns `p0..p1` of this block into `mr` tall strips, each `p1 - p0`
+    /// columns of `mr` values, zero padded at the bottom.
+    fn pack(&self, rows: std::ops::Range<usize>, p0: usize, p1: usize, mr: usize, out: &mut [f32]) {
+        let depth = p1 - p0;
+        for (strip_idx, strip) in out[..rows.len().div_ceil(mr) * mr * depth]
+            .chunks_exact_mut(mr * depth)
+            .enumerate()
+        {
+            strip.fill(0f32);
+            let i0 = rows.start + strip_idx * mr;
+            for r in 0..(rows.end - i0).min(mr) {
+                let i = self.first_row + i0 + r;
+                for (p, value) in (p0..p1).zip(strip[r..].iter_mut().step_by(mr)) {
+                    *value = match self.a_transpose {
+                        true => self.values[p * self.ld + i],
+                        false => self.values[i * self.ld + p],
+                    };
+                }
+            }
+        }
+    }
+}
+
+/// `acc = op(a) @ op(b)` for the rows of `a_block`, in the BLIS loop order.
+///
+/// Around the microkernel, from the outside in: `nc` wide column blocks of the packed `b`,
+/// `kc` deep panels, and `mc` tall row blocks of `a`, each packed once into `mr` tall strips
+/// and then swept against every `b` strip of the column block. Tiles cut by the edge of `c`
+/// are copied into a zero padded scratch tile, computed whole and copied back.
+///
+/// `kc` is the depth `b` was packed with; `block_sizes` supplies `mc` and `nc`. The packed
+/// strips of `a` and the edge tile live in `scratch`. Each `mc` block of rows in each panel
+/// is a macro-tile, and `watch` is checked before each and counts it after; returns whether
+/// every one ran.
+#[allow(clippy::too_many_arguments)]
+fn sgemm_packed(
+    kernel: Microkernel,
+    a_block: ABlock,
+    packed_b: &PackedB,
+    strips: Range<usize>,
+    block_sizes: BlockSizes,
+    acc: &mut [f32],
+    scratch: &mut PackScratch,
+    watch: Option<&Watch>,
+) -> bool {
+    let (mr, nr) = kernel.tile();
+    let (k, n, kc) = (packed_b.k, packed_b.n, packed_b.kc);
+    let width = (strips.end * nr).min(n) - strips.start * nr;
+    let rows = acc.len() / width.max(1);
+    let BlockSizes { mc, nc, .. } = block_sizes;
+    let mc = (mc / mr).max(1) * mr;
+    let nc_strips = (nc / nr).max(1);
+
+    let a_packed = workspace::zeroed(&mut scratch.a_packed, mc * kc.min(k));
+    let scratch = workspace::zeroed(&mut scratch.tile, mr * nr);
+
+    for js_c in strips.clone().step_by(nc_strips) {
+        let block_cols = ((js_c + nc_strips).min(strips.end) * nr).min(n) - js_c * nr;
+        for p0 in (0..k).step_by(kc) {
+            let p1 = (p0 + kc).min(k);
+            let depth = p1 - p0;
+            for i_c in (0..rows).step_by(mc) {
+                if watch.is_some_and(Watch::stopped) {
+                    return false;
+                }
+                let i_end = (i_c + mc).min(rows);
+                a_block.pack(i_c..i_end, p0, p1, mr, a_packed);
+
+                for js in js_c..(js_c + nc_strips).min(strips.end) {
+                    let b_strip = packed_b.strip(p0, js);
+                    let j0 = (js - strips.start) * nr;
+                    let cols = (n - js * nr).min(nr);
+                    for (is, a_strip) in a_packed[..(i_end - i_c).div_ceil(mr) * mr * depth]
+                        .chunks_exact(mr * depth)
+                        .enumerate()
+                    {
+                        let i0 = i_c + is * mr;
+                        let tile_rows = (rows - i0).min(mr);
+                        match tile_rows == mr && cols == nr {
+                            true => {
+                                let c_tile = &mut acc[i0 * width + j0..];
+                                kernel.run(depth, a_strip, b_strip, c_tile, width)
+                            }
+                            // the partial tile is summed in the same order as a whole one, so
+                            // results never depend on where blocks and threads cut `c`
+                            false => {
+                                scratch.fill(0f32);
+                                for (acc_row, scratch_row) in acc[i0 * width..]
+                                    .chunks(width)
+                                    .zip(scratch.chunks_exact_mut(nr))
+                                    .take(tile_rows)
+                                {
+                                    scratch_row[..cols].copy_from_slice(&acc_row[j0..j0 + cols]);
+                                }
+                                kernel.run(depth, a_strip, b_strip, scratch, nr);
+                                for (acc_row, scratch_row) in acc[i0 * width..]
+                                    .chunks_mut(width)
+                                    .zip(scratch.chunks_exact(nr))
+                                    .take(tile_rows)
+                                {
+                                    acc_row[j0..j0 + cols].copy_from_slice(&scratch_row[..cols]);
+                                }
+                            }
+                        }
+                    }
+                }
+                if let Some(watch) = watch {
+                    watch.finish((i_end - i_c) * block_cols);
+                }
+            }
+        }
+    }
+    true
+}
+
+/// `sgemm_packed` with one node's share of the threads on each NUMA node, each on rows of `c`
+/// against its own copy of the packed `b`.
+///
+/// Each node's first thread is bound to the node's CPUs before copying `b`, so first touch puts
+/// the copy in that node's memory, and the threads it spawns inherit the binding.
+#[cfg(feature = "parallel")]
+pub(crate) fn sgemm_numa(
+    kernel: Microkernel,
+    a_block: ABlock,
+    packed_b: &PackedB,
+    config: TuneConfig,
+    nodes: &[Vec<usize>],
+    (params, epilogue): (GemmParams, Epilogue),
+    c: &mut TensorMut<f32>,
+) {
+    let (m, n, ldc) = (a_block.m, packed_b.n, c.ld());
+    let rows_per_node = m.div_ceil(nodes.len()).max(1);
+    let threads_per_node = config.threads.div_ceil(nodes.len());
+
+    parallel::spawn_chunks(c.values, rows_per_node * ldc, |node, c_node| {
+        crate::affinity::bind_current_thread(&nodes[node]);
+        let local_b = packed_b.clone();
+        let node_first_row = node * rows_per_node;
+
+        // a plain context, so per-core pinning cannot move threads off the node
+        AmlContext::new(threads_per_node).install(|| {
+            parallel::for_each_row_chunk(c_node, ldc, n * packed_b.k, |first_row, c_rows| {
+                let mut acc = vec![0f32; c_rows.len().div_ceil(ldc) * n];
+                let a_block = ABlock {
+                    first_row: node_first_row + first_row,
+                    ..a_block
+                };
+                let strips = 0..n.div_ceil(kernel.tile().1);
+                sgemm_packed(
+                    kernel,
+                    a_block,
+                    &local_b,
+                    strips,
+                    config.block_sizes,
+                    &mut acc,
+                    &mut PackScratch::default(),
+                    None,
+                );
+                epilogue.store_rows(params, &acc, 0, n, c_rows, ldc);
+            })
+        });
+    });
+}
+
+/// `c = params(op(a) @ op(b), c)` over a grid of blocks of `c`, one per thread, for shapes too
+/// short to give every thread its own rows. Blocks are whole `b` strips wide; each is computed
+/// into its own buffer and written into `c` once every thread is done.
+fn sgemm_grid(
+    kernel: Microkernel,
+    a_block: ABlock,
+    packed_b: &PackedB,
+    block_sizes: BlockSizes,
+    grid: (usize, usize),
+    (params, epilogue): (GemmParams, Epilogue),
+    c: &mut TensorMut<f32>,
+) {
+    let (n, nr, ldc) = (packed_b.n, kernel.tile().1, c.ld());
+    for block in grid_blocks(kernel, a_block, packed_b, block_sizes, grid, None) {
+        let (j0, j1) = (block.strips.start * nr, (block.strips.end * nr).min(n));
+        let c_block = &mut c.values[block.rows.start * ldc + j0..];
+        epilogue.store_rows(params, &block.acc, j0, j1 - j0, c_block, ldc);
+    }
+}
+
+/// A block of `c` computed by `grid_blocks`
+struct GridBlock {
+    rows: Range<usize>,
+    /// Strips of the packed `b` it is as wide as
+    strips: Range<usize>,
+    /// `op(a) @ op(b)` over the block, row-major
+    acc: Vec<f32>,
+    /// Whether every macro-tile ran, rather than `watch` stopping it
+    finished: bool,
+}
+
+/// The blocks of a `grid_rows` x `grid_cols` grid over `c`, each computed by its own thread
+fn grid_blocks(
+    kernel: Microkernel,
+    a_block: ABlock,
+    packed_b: &PackedB,
+    block_sizes: BlockSizes,
+    (grid_rows, grid_cols): (usize, usize),
+    watch: Option<&Watch>,
+) -> Vec<GridBlock> {
+    let (m, n, nr) = (a_block.m, packed_b.n, kernel.tile().1);
+    let n_strips = n.div_ceil(nr);
+    let rows_per_block = m.div_ceil(grid_rows).max(1);
+    let strips_per_block = n_strips.div_ceil(grid_cols).max(1);
+
+    let mut blocks = Vec::new();
+    for i0 in (0..m).step_by(rows_per_block) {
+        for js0 in (0..n_strips).step_by(strips_per_block) {
+            blocks.push(GridBlock {
+                rows: i0..(i0 + rows_per_block).min(m),
+                strips: js0..(js0 + strips_per_block).min(n_strips),
+                acc: Vec::new(),
+                finished: false,
+            });
+        }
+    }
+
+    let run = |_, block: &mut [GridBlock]| {
+        let GridBlock {
+            rows,
+            strips,
+            acc,
+            finished,
+        } = &mut block[0];
+        let width = (strips.end * nr).min(n) - strips.start * nr;
+        *acc = vec![0f32; rows.len() * width];
+        let a_block = ABlock {
+            first_row: rows.start,
+            ..a_block
+        };
+        let scratch = &mut PackScratch::default();
+        *finished = sgemm_packed(
+            kernel,
+            a_block,
+            packed_b,
+            strips.clone(),
+            block_sizes,
+            acc,
+            scratch,
+            watch,
+        );
+    };
+    match blocks.len() > 1 {
+        true => parallel::spawn_chunks(&mut blocks, 1, run),
+        false => blocks.chunks_mut(1).for_each(|block| run(0, block)),
+    }
+    blocks
+}
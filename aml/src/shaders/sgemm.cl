@@ -0,0 +1,69 @@
+// c = alpha * op(a) @ op(b) + beta * c over row-major matrices, one 16 by 16 tile of c per
+// work-group, as in sgemm.wgsl. Each step stages a 16 wide slice of k from a and b in local
+// memory, so every value loaded from the buffers is used 16 times. OpenCL C 1.2.
+
+#define TILE 16
+
+// op(a)[i, p], or 0 past its edge
+static float load_a(__global const float* a, uint m, uint k, uint a_transpose, uint i, uint p) {
+    if (i >= m || p >= k) {
+        return 0.0f;
+    }
+    if (a_transpose != 0) {
+        return a[p * m + i];
+    }
+    return a[i * k + p];
+}
+
+// op(b)[p, j], or 0 past its edge
+static float load_b(__global const float* b, uint n, uint k, uint b_transpose, uint p, uint j) {
+    if (p >= k || j >= n) {
+        return 0.0f;
+    }
+    if (b_transpose != 0) {
+        return b[j * k + p];
+    }
+    return b[p * n + j];
+}
+
+__kernel __attribute__((reqd_work_group_size(TILE, TILE, 1)))
+void sgemm(
+    __global const float* a,
+    __global const float* b,
+    __global float* c,
+    uint m,
+    uint n,
+    uint k,
+    uint a_transpose,
+    uint b_transpose,
+    float alpha,
+    float beta
+) {
+    __local float a_tile[TILE][TILE];
+    __local float b_tile[TILE][TILE];
+
+    uint i = get_global_id(1);
+    uint j = get_global_id(0);
+    uint local_i = get_local_id(1);
+    uint local_j = get_local_id(0);
+    float acc = 0.0f;
+    for (uint p0 = 0; p0 < k; p0 += TILE) {
+        a_tile[local_i][local_j] = load_a(a, m, k, a_transpose, i, p0 + local_j);
+        b_tile[local_i][local_j] = load_b(b, n, k, b_transpose, p0 + local_i, j);
+        barrier(CLK_LOCAL_MEM_FENCE);
+        for (uint p = 0; p < TILE; p++) {
+            acc += a_tile[local_i][p] * b_tile[p][local_j];
+        }
+        barrier(CLK_LOCAL_MEM_FENCE);
+    }
+
+    if (i < m && j < n) {
+        uint index = i * n + j;
+        // as on the CPU, c is not read when beta is 0
+        if (beta == 0.0f) {
+            c[index] = alpha * acc;
+        } else {
+            c[index] = alpha * acc + beta * c[index];
+        }
+    }
+}
@@ -0,0 +1,76 @@
+// c = alpha * op(a) @ op(b) + beta * c over row-major matrices, one 16 by 16 tile of c per
+// threadgroup, as in sgemm.wgsl. Each step stages a 16 wide slice of k from a and b in
+// threadgroup memory, so every value loaded from the buffers is used 16 times.
+
+#include <metal_stdlib>
+using namespace metal;
+
+struct Params {
+    uint m;
+    uint n;
+    uint k;
+    uint a_transpose;
+    uint b_transpose;
+    float alpha;
+    float beta;
+    uint pad;
+};
+
+constant uint TILE = 16;
+
+// op(a)[i, p], or 0 past its edge
+static float load_a(device const float* a, constant Params& params, uint i, uint p) {
+    if (i >= params.m || p >= params.k) {
+        return 0.0f;
+    }
+    if (params.a_transpose != 0) {
+        return a[p * params.m + i];
+    }
+    return a[i * params.k + p];
+}
+
+// op(b)[p, j], or 0 past its edge
+static float load_b(device const float* b, constant Params& params, uint p, uint j) {
+    if (p >= params.k || j >= params.n) {
+        return 0.0f;
+    }
+    if (params.b_transpose != 0) {
+        return b[j * params.k + p];
+    }
+    return b[p * params.n + j];
+}
+
+kernel void sgemm(
+    device const float* a [[buffer(0)]],
+    device const float* b [[buffer(1)]],
+    device float* c [[buffer(2)]],
+    constant Params& params [[buffer(3)]],
+    uint2 global [[thread_position_in_grid]],
+    uint2 local [[thread_position_in_threadgroup]]
+) {
+    threadgroup float a_tile[16][16];
+    threadgroup float b_tile[16][16];
+
+    uint i = global.y;
+    uint j = global.x;
+    float acc = 0.0f;
+    for (uint p0 = 0; p0 < params.k; p0 += TILE) {
+        a_tile[local.y][local.x] = load_a(a, params, i, p0 + local.x);
+        b_tile[local.y][local.x] = load_b(b, params, p0 + local.y, j);
+        threadgroup_barrier(mem_flags::mem_threadgroup);
+        for (uint p = 0; p < TILE; p++) {
+            acc += a_tile[local.y][p] * b_tile[p][local.x];
+        }
+        threadgroup_barrier(mem_flags::mem_threadgroup);
+    }
+
+    if (i < params.m && j < params.n) {
+        uint index = i * params.n + j;
+        // as on the CPU, c is not read when beta is 0
+        if (params.beta == 0.0f) {
+            c[index] = params.alpha * acc;
+        } else {
+            c[index] = params.alpha * acc + params.beta * c[index];
+        }
+    }
+}
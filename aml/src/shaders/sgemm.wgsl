@@ -0,0 +1,75 @@
+// c = alpha * op(a) @ op(b) + beta * c over row-major matrices, one 16 by 16 tile of c per
+// workgroup. Each step stages a 16 wide slice of k from a and b in workgroup memory, so every
+// value loaded from the buffers is used 16 times.
+
+struct Params {
+    m: u32,
+    n: u32,
+    k: u32,
+    a_transpose: u32,
+    b_transpose: u32,
+    alpha: f32,
+    beta: f32,
+    pad: u32,
+}
+
+@group(0) @binding(0) var<storage, read> a: array<f32>;
+@group(0) @binding(1) var<storage, read> b: array<f32>;
+@group(0) @binding(2) var<storage, read_write> c: array<f32>;
+@group(0) @binding(3) var<uniform> params: Params;
+
+const TILE: u32 = 16u;
+
+var<workgroup> a_tile: array<array<f32, 16>, 16>;
+var<workgroup> b_tile: array<array<f32, 16>, 16>;
+
+// op(a)[i, p], or 0 past its edge
+fn load_a(i: u32, p: u32) -> f32 {
+    if (i >= params.m || p >= params.k) {
+        return 0.0;
+    }
+    if (params.a_transpose != 0u) {
+        return a[p * params.m + i];
+    }
+    return a[i * params.k + p];
+}
+
+// op(b)[p, j], or 0 past its edge
+fn load_b(p: u32, j: u32) -> f32 {
+    if (p >= params.k || j >= params.n) {
+        return 0.0;
+    }
+    if (params.b_transpose != 0u) {
+        return b[j * params.k + p];
+    }
+    return b[p * params.n + j];
+}
+
+@compute @workgroup_size(16, 16)
+fn main(
+    @builtin(global_invocation_id) global: vec3<u32>,
+    @builtin(local_invocation_id) local: vec3<u32>,
+) {
+    let i = global.y;
+    let j = global.x;
+    var acc = 0.0;
+    for (var p0 = 0u; p0 < params.k; p0 += TILE) {
+        a_tile[local.y][local.x] = load_a(i, p0 + local.x);
+        b_tile[local.y][local.x] = load_b(p0 + local.y, j);
+        workgroupBarrier();
+        for (var p = 0u; p < TILE; p++) {
+            acc += a_tile[local.y][p] * b_tile[p][local.x];
+        }
+        workgroupBarrier();
+    }
+
+    if (i < params.m && j < params.n) {
+        let index = i * params.n + j;
+        // as on the CPU, c is not read when beta is 0
+        if (params.beta == 0.0) {
+            c[index] = params.alpha * acc;
+        } else {
+            c[index] = params.alpha * acc + params.beta * c[index];
+        }
+    }
+}
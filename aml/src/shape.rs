@@ -0,0 +1,169 @@
+use crate::{check_rank, AmlError};
+use std::ops::Deref;
+
+/// Length of each axis of a tensor, outermost first.
+///
+/// Reads as a `&[usize]` wherever one is expected, and compares equal to the arrays and vectors
+/// it is built from, so `t.shape == [m, n]` and `t.shape[1]` work as they would on a `Vec`. The
+/// shape checks of every kernel go through it, so they all report mismatches the same way.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+pub struct Shape(Vec<usize>);
+
+impl Shape {
+    pub fn new(dims: Vec<usize>) -> Shape {
+        Shape(dims)
+    }
+
+    /// Number of values a tensor of this shape holds: the product of the axes, 1 for rank 0.
+    /// Shapes read from a file or a caller's bytes go through `checked_numel` first.
+    pub fn numel(&self) -> usize {
+        self.0.iter().product()
+    }
+
+    /// `numel`, or `None` if the product of the axes does not fit in a `usize`.
+    pub fn checked_numel(&self) -> Option<usize> {
+        self.0.iter().try_fold(1usize, |n, &dim| n.checked_mul(dim))
+    }
+
+    /// Number of bytes `numel` values of `size` bytes each take up, or `ShapeOverflow` if that
+    /// does not fit in a `usize`.
+    pub fn byte_len(&self, size: usize) -> Result<usize, AmlError> {
+        self.checked_numel()
+            .and_then(|n| n.checked_mul(size))
+            .ok_or_else(|| AmlError::ShapeOverflow {
+                shape: self.to_vec(),
+            })
+    }
+
+    pub fn rank(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn to_vec(&self) -> Vec<usize> {
+        self.0.clone()
+    }
+
+    /// Whether `op(self) @ op(other)` is defined, `op` transposing a matrix if asked to.
+    pub fn is_compatible_for_matmul(
+        &self,
+        transpose: bool,
+        other: &Shape,
+        other_transpose: bool,
+    ) -> bool {
+        self.try_matmul(transpose, other, other_transpose).is_ok()
+    }
+
+    /// Shape of `op(self) @ op(other)`: (m, n) for an (m, k) by (k, n) product.
+    pub fn matmul(&self, transpose: bool, other: &Shape, other_transpose: bool) -> Shape {
+        self.try_matmul(transpose, other, other_transpose)
+            .unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    /// Fallible version of `matmul`. `self` is the `a` and `other` the `b` of the errors.
+    pub fn try_matmul(
+        &self,
+        transpose: bool,
+        other: &Shape,
+        other_transpose: bool,
+    ) -> Result<Shape, AmlError> {
+        check_rank("a", self, 2)?;
+        check_rank("b", other, 2)?;
+
+        let (m, k) = match transpose {
+            true => (self[1], self[0]),
+            false => (self[0], self[1]),
+        };
+        let (other_k, n) = match other_transpose {
+            true => (other[1], other[0]),
+            false => (other[0], other[1]),
+        };
+        match k == other_k {
+            true => Ok(Shape(vec![m, n])),
+            false => Err(AmlError::InnerDimMismatch { a: k, b: other_k }),
+        }
+    }
+
+    /// Shape both broadcast to, NumPy style: axes line up from the last, a missing axis counts
+    /// as length 1, and an axis of length 1 stretches to match the other.
+    pub fn broadcast(&self, other: &Shape) -> Shape {
+        self.try_broadcast(other)
+            .unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    /// Fallible version of `broadcast`
+    pub fn try_broadcast(&self, other: &Shape) -> Result<Shape, AmlError> {
+        let rank = self.rank().max(other.rank());
+        let axis = |shape: &Shape, i: usize| match (i + shape.rank()).checked_sub(rank) {
+            Some(axis) => shape[axis],
+            None => 1,
+        };
+
+        (0..rank)
+            .map(|i| match (axis(self, i), axis(other, i)) {
+                (a, b) if a == b || b == 1 => Ok(a),
+                (1, b) => Ok(b),
+                _ => Err(AmlError::BroadcastMismatch {
+                    a: self.to_vec(),
+                    b: other.to_vec(),
+                }),
+            })
+            .collect::<Result<Vec<usize>, AmlError>>()
+            .map(Shape)
+    }
+}
+
+impl Deref for Shape {
+    type Target = [usize];
+
+    fn deref(&self) -> &[usize] {
+        &self.0
+    }
+}
+
+impl From<Vec<usize>> for Shape {
+    fn from(dims: Vec<usize>) -> Shape {
+        Shape(dims)
+    }
+}
+
+impl From<&[usize]> for Shape {
+    fn from(dims: &[usize]) -> Shape {
+        Shape(dims.to_vec())
+    }
+}
+
+impl<const N: usize> From<[usize; N]> for Shape {
+    fn from(dims: [usize; N]) -> Shape {
+        Shape(dims.to_vec())
+    }
+}
+
+impl From<Shape> for Vec<usize> {
+    fn from(shape: Shape) -> Vec<usize> {
+        shape.0
+    }
+}
+
+impl PartialEq<[usize]> for Shape {
+    fn eq(&self, other: &[usize]) -> bool {
+        self.0 == other
+    }
+}
+
+impl<const N: usize> PartialEq<[usize; N]> for Shape {
+    fn eq(&self, other: &[usize; N]) -> bool {
+        self.0 == other
+    }
+}
+
+impl PartialEq<Vec<usize>> for Shape {
+    fn eq(&self, other: &Vec<usize>) -> bool {
+        &self.0 == other
+    }
+}
+
+impl PartialEq<Shape> for Vec<usize> {
+    fn eq(&self, other: &Shape) -> bool {
+        self == &other.0
+    }
+}
@@ -0,0 +1,111 @@
+//! Tensors whose values live behind an `Arc`, for weights shared across threads and sessions.
+
+use crate::AmlError;
+use crate::{
+    check_len, dense_strides, window, AsTensorRef, Element, Layout, Shape, Tensor, TensorRef,
+};
+use std::ops::Range;
+use std::sync::Arc;
+
+/// A read-only tensor that owns its values jointly with its clones.
+///
+/// Cloning and `view` only bump a reference count, so a model can hand the same weights to
+/// every worker thread without borrowing them from a struct that outlives the workers. The
+/// kernels read it through `AsTensorRef` like any other tensor.
+#[derive(Clone)]
+pub struct SharedTensor<T: Element = f32> {
+    values: Arc<[T]>,
+    /// The part of `values` this tensor covers, all of it unless it is a view
+    range: Range<usize>,
+    pub shape: Shape,
+    pub layout: Layout,
+    /// Values between neighbours along each axis, as `Tensor::strides`
+    pub strides: Vec<usize>,
+}
+
+impl<T: Element> SharedTensor<T> {
+    /// A row-major tensor over `values`, as `Tensor::new`
+    pub fn new(values: impl Into<Arc<[T]>>, shape: impl Into<Shape>) -> SharedTensor<T> {
+        SharedTensor::try_new(values, shape).unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    /// Fallible version of `new`
+    pub fn try_new(
+        values: impl Into<Arc<[T]>>,
+        shape: impl Into<Shape>,
+    ) -> Result<SharedTensor<T>, AmlError> {
+        let (values, shape): (Arc<[T]>, Shape) = (values.into(), shape.into());
+        let strides = dense_strides(&shape, Layout::RowMajor);
+        check_len(values.len(), &shape, &strides)?;
+
+        Ok(SharedTensor {
+            range: 0..values.len(),
+            values,
+            shape,
+            layout: Layout::RowMajor,
+            strides,
+        })
+    }
+
+    /// The values this tensor covers, laid out as `strides` say
+    pub fn values(&self) -> &[T] {
+        &self.values[self.range.clone()]
+    }
+
+    /// Rows `rows` and columns `cols` of a matrix, sharing its values, as `Tensor::view`
+    pub fn view(&self, rows: Range<usize>, cols: Range<usize>) -> SharedTensor<T> {
+        self.try_view(rows, cols)
+            .unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    /// Fallible version of `view`
+    pub fn try_view(
+        &self,
+        rows: Range<usize>,
+        cols: Range<usize>,
+    ) -> Result<SharedTensor<T>, AmlError> {
+        let (range, shape) = window(&self.shape, &self.strides, rows, cols)?;
+        Ok(SharedTensor {
+            values: self.values.clone(),
+            range: self.range.start + range.start..self.range.start + range.end,
+            shape,
+            layout: self.layout,
+            strides: self.strides.clone(),
+        })
+    }
+
+    /// Whether both hold the same allocation, e.g. one is a clone or view of the other
+    pub fn shares_values(&self, other: &SharedTensor<T>) -> bool {
+        Arc::ptr_eq(&self.values, &other.values)
+    }
+
+    /// Copy into an owned contiguous tensor, e.g. to modify it
+    pub fn to_tensor(&self) -> Tensor<T> {
+        self.as_tensor_ref().to_contiguous()
+    }
+}
+
+/// Moves the values into one shared allocation, keeping the shape, layout and strides.
+impl<T: Element> From<Tensor<T>> for SharedTensor<T> {
+    fn from(tensor: Tensor<T>) -> SharedTensor<T> {
+        let values: Arc<[T]> = tensor.values.into();
+        SharedTensor {
+            range: 0..values.len(),
+            values,
+            shape: tensor.shape,
+            layout: tensor.layout,
+            strides: tensor.strides,
+        }
+    }
+}
+
+impl<T: Element> AsTensorRef<T> for SharedTensor<T> {
+    fn as_tensor_ref(&self) -> TensorRef<'_, T> {
+        TensorRef {
+            values: self.values(),
+            shape: self.shape.clone(),
+            layout: self.layout,
+            strides: self.strides.clone(),
+        }
+    }
+}
@@ -0,0 +1,296 @@
+//! Softmax over the rows of a matrix, alone or straight out of a GEMM.
+//!
+//! Each row has its largest value subtracted before it is exponentiated, so no value overflows
+//! however large the logits. The exponential is a polynomial, within 1 ulp of `f32::exp` over
+//! the range a softmax feeds it, run 8 values at a time with AVX2 when the CPU has it; the
+//! portable version does the same arithmetic in the same order, so every CPU gets the same
+//! result. Rows are split across threads once there are enough of them.
+
+use crate::autotune::{self, TuneConfig};
+use crate::dispatch::kernels;
+use crate::reduce::Reduce;
+use crate::sgemm::{sgemm_kernel, OpB};
+use crate::{
+    block_sizes, check_gemm, check_rank, check_row_major, parallel, AmlError, AsTensorMut,
+    AsTensorRef, BlockSizes, GemmParams, Layout, Tensor, TensorMut,
+};
+
+#[cfg(target_arch = "x86_64")]
+use std::arch::x86_64::*;
+
+/// An exponential costs about as much as this many multiply-adds, to size the row split
+const EXP_WORK: usize = 8;
+
+/// Below this `exp` is under the smallest normal f32, and is 0
+const EXP_MIN: f32 = -87.336_55;
+/// Above this `exp` would round its power of two past the largest f32
+const EXP_MAX: f32 = 88.376_26;
+/// `ln(2)` in two parts, the first exact in few bits, so `n * ln(2)` is subtracted exactly
+const LN2_HI: f32 = 0.693_359_4;
+const LN2_LO: f32 = -2.121_944_4e-4;
+/// Cephes' `expf` polynomial for `e^r` on `|r| <= ln(2) / 2`, highest power first
+const EXP_POLY: [f32; 6] = [
+    1.987_569_1e-4,
+    1.398_199_9e-3,
+    8.333_452e-3,
+    4.166_579_6e-2,
+    1.666_666_5e-1,
+    5e-1,
+];
+
+/// Softmax of each row of `a`, its last axis, as a new row-major tensor of the same shape:
+/// `e^(x - max) / sum(e^(x - max))` over the row. A row of only negative infinities, or with a
+/// NaN, comes out NaN.
+pub fn softmax(a: &impl AsTensorRef<f32>) -> Tensor<f32> {
+    let a = a.as_tensor_ref();
+    let mut out = a.dense_in(Layout::RowMajor).into_owned();
+    let n = a.shape.last().copied().unwrap_or(1);
+    if n != 0 {
+        parallel::for_each_row_chunk(&mut out, n, n * EXP_WORK, |_, rows| {
+            rows.chunks_exact_mut(n).for_each(softmax_row)
+        });
+    }
+    Tensor::new(out, a.shape.clone())
+}
+
+/// Softmax of each row of the matrix `a` in place, as `softmax`. `a` must be row-major.
+pub fn softmax_in_place(a: &mut impl AsTensorMut<f32>) {
+    try_softmax_in_place(a).unwrap_or_else(|e| panic!("{}", e))
+}
+
+/// Fallible version of `softmax_in_place`. `a` is left untouched on error.
+pub fn try_softmax_in_place(a: &mut impl AsTensorMut<f32>) -> Result<(), AmlError> {
+    let a = a.as_tensor_mut();
+    check_rank("a", &a.shape, 2)?;
+    check_row_major("a", a.layout)?;
+    a.check_blas("a")?;
+
+    let (n, ld) = (a.shape[1], a.ld());
+    if a.shape[0] != 0 && n != 0 {
+        parallel::for_each_row_chunk(a.values, ld, n * EXP_WORK, |_, rows| {
+            rows.chunks_mut(ld)
+                .for_each(|row| softmax_row(&mut row[..n]))
+        });
+    }
+    Ok(())
+}
+
+/// `c = softmax(scale * (op(a) @ op(b)))` over each row of `c`, as attention scores are
+/// computed from queries and keys, e.g. `sgemm_softmax(&q, false, &k, true, 1. / d.sqrt(), c)`.
+///
+/// The softmax is taken online, as FlashAttention takes it: each thread multiplies a block of
+/// `mc` rows by `nc` columns of `b` at a time into a buffer of its own, exponentiates the
+/// scores there against the largest value of each row so far and writes them to `c`, keeping
+/// a running sum that is rescaled whenever a later block raises the maximum. Once the last
+/// block of columns is in, one pass over the block's rows of `c`, still in cache, brings every
+/// value to the final maximum and divides by the sum. So the scratch is `mc * nc` values per
+/// thread however long the rows, and no score is computed twice. The result matches
+/// `sgemm_with` followed by a softmax of each row to within rounding. `c` must be row-major.
+pub fn sgemm_softmax(
+    a: &impl AsTensorRef<f32>,
+    a_transpose: bool,
+    b: &impl AsTensorRef<f32>,
+    b_transpose: bool,
+    scale: f32,
+    c: &mut impl AsTensorMut<f32>,
+) {
+    try_sgemm_softmax(a, a_transpose, b, b_transpose, scale, c).unwrap_or_else(|e| panic!("{}", e))
+}
+
+/// Fallible version of `sgemm_softmax`. `c` is left untouched on error.
+pub fn try_sgemm_softmax(
+    a: &impl AsTensorRef<f32>,
+    a_transpose: bool,
+    b: &impl AsTensorRef<f32>,
+    b_transpose: bool,
+    scale: f32,
+    c: &mut impl AsTensorMut<f32>,
+) -> Result<(), AmlError> {
+    let (a, b, c) = (a.as_tensor_ref(), b.as_tensor_ref(), c.as_tensor_mut());
+    check_gemm(&a.shape, a_transpose, &b.shape, b_transpose, &c.shape)?;
+    check_row_major("c", c.layout)?;
+    c.check_blas("c")?;
+    let (a_copy, b_copy) = (a.blas_copy(), b.blas_copy());
+    let a = a_copy.as_ref().map_or(a, AsTensorRef::as_tensor_ref);
+    let b = b_copy.as_ref().map_or(b, AsTensorRef::as_tensor_ref);
+
+    let (m, n, ldc) = (c.shape[0], c.shape[1], c.ld());
+    let k = match a_transpose {
+        true => a.shape[0],
+        false => a.shape[1],
+    };
+    let config = TuneConfig {
+        threads: 1,
+        ..autotune::sgemm_config(m, n, k)
+    };
+    let BlockSizes { mc, nc, .. } = block_sizes();
+    let (mc, nc) = (mc.max(1), nc.clamp(1, n.max(1)));
+    let params = GemmParams::new(scale, 0f32);
+    if n == 0 {
+        return Ok(());
+    }
+
+    let kernels = kernels();
+    let col_blocks = n.div_ceil(nc);
+    parallel::for_each_row_chunk(c.values, ldc, n * k, |first_row, c_rows| {
+        let rows = c_rows.len().div_ceil(ldc);
+        let mut scores = vec![0f32; mc.min(rows) * nc];
+        // per row, the maximum each block of columns was exponentiated against, the running
+        // maximum and the running sum of exponentials against it
+        let mut block_max = vec![0f32; mc.min(rows) * col_blocks];
+        let (mut max, mut sum) = (vec![0f32; mc.min(rows)], vec![0f32; mc.min(rows)]);
+        for i0 in (0..rows).step_by(mc) {
+            let i1 = (i0 + mc).min(rows);
+            let a_rows = match a_transpose {
+                true => a.view(0..k, first_row + i0..first_row + i1),
+                false => a.view(first_row + i0..first_row + i1, 0..k),
+            };
+            let c_block = &mut c_rows[i0 * ldc..];
+            max.fill(f32::NEG_INFINITY);
+            sum.fill(0f32);
+
+            for (col_block, j0) in (0..n).step_by(nc).enumerate() {
+                let j1 = (j0 + nc).min(n);
+                let b_cols = match b_transpose {
+                    true => b.view(j0..j1, 0..k),
+                    false => b.view(0..k, j0..j1),
+                };
+                let scores = &mut scores[..(i1 - i0) * (j1 - j0)];
+                let mut block = TensorMut::new(scores, vec![i1 - i0, j1 - j0]);
+                sgemm_kernel(
+                    &a_rows,
+                    a_transpose,
+                    OpB::Tensor(&b_cols, b_transpose),
+                    params,
+                    Some(config),
+                    kernels,
+                    &mut block,
+                );
+                let rows = block.values.chunks_exact_mut(j1 - j0);
+                for (i, (row, c_row)) in rows.zip(c_block.chunks_mut(ldc)).enumerate() {
+                    let row_max = max[i].max((kernels.reduce)(Reduce::Max, row));
+                    // a row of only negative infinities so far has nothing to shift by
+                    let shift = match row_max == f32::NEG_INFINITY {
+                        true => 0f32,
+                        false => row_max,
+                    };
+                    let block_sum = (kernels.exp_sum)(shift, row);
+                    sum[i] = sum[i] * rescale(max[i], row_max) + block_sum;
+                    max[i] = row_max;
+                    block_max[i * col_blocks + col_block] = row_max;
+                    c_row[j0..j1].copy_from_slice(row);
+                }
+            }
+
+            let c_rows = c_block.chunks_mut(ldc).take(i1 - i0);
+            for (i, c_row) in c_rows.enumerate() {
+                for (col_block, j0) in (0..n).step_by(nc).enumerate() {
+                    let shift = block_max[i * col_blocks + col_block];
+                    let factor = rescale(shift, max[i]) / sum[i];
+                    (kernels.sscal)(factor, &mut c_row[j0..(j0 + nc).min(n)]);
+                }
+            }
+        }
+    });
+    Ok(())
+}
+
+/// `e^(from - to)`, which takes exponentials against the maximum `from` to ones against `to`;
+/// 0 from a maximum of negative infinity, whose exponentials are all 0
+fn rescale(from: f32, to: f32) -> f32 {
+    match from == f32::NEG_INFINITY {
+        true => 0f32,
+        false => exp(from - to),
+    }
+}
+
+/// Softmax of `row` in place: less its largest value, exponentiated and normalized
+pub(crate) fn softmax_row(row: &mut [f32]) {
+    let kernels = kernels();
+    let max = (kernels.reduce)(Reduce::Max, row);
+    let sum = (kernels.exp_sum)(max, row);
+    (kernels.sscal)(1f32 / sum, row);
+}
+
+/// `e^x` by the polynomial, 0 below `EXP_MIN` and NaN for NaN
+fn exp(x: f32) -> f32 {
+    if x < EXP_MIN {
+        return 0f32;
+    }
+    let x = match x > EXP_MAX {
+        true => EXP_MAX,
+        false => x,
+    };
+    // e^x = 2^n * e^r, with r = x - n ln(2) small
+    let n = (x * std::f32::consts::LOG2_E).round_ties_even();
+    let r = x - n * LN2_HI - n * LN2_LO;
+    let p = EXP_POLY[1..].iter().fold(EXP_POLY[0], |p, c| p * r + c);
+    let scale = f32::from_bits(((n as i32 + 127) as u32) << 23);
+    (p * r * r + r + 1f32) * scale
+}
+
+/// `x = e^(x - max)` for each value, returning their sum, added up in 8 lanes as
+/// `exp_sum_avx2` adds them
+pub(crate) fn exp_sum_scalar(max: f32, x: &mut [f32]) -> f32 {
+    let mut lanes = [0f32; 8];
+    let mut chunks = x.chunks_exact_mut(8);
+    for x8 in &mut chunks {
+        for (lane, value) in lanes.iter_mut().zip(x8) {
+            *value = exp(*value - max);
+            *lane += *value;
+        }
+    }
+    let tail = chunks.into_remainder().iter_mut().fold(0f32, |sum, value| {
+        *value = exp(*value - max);
+        sum + *value
+    });
+
+    lanes.iter().sum::<f32>() + tail
+}
+
+/// `exp` on 8 lanes, in the same steps
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "avx2")]
+unsafe fn exp_avx2(x: __m256) -> __m256 {
+    let underflow = _mm256_cmp_ps(x, _mm256_set1_ps(EXP_MIN), _CMP_LT_OQ);
+    // with `x` second, a NaN passes through
+    let x = _mm256_min_ps(_mm256_set1_ps(EXP_MAX), x);
+    let n = _mm256_round_ps(
+        _mm256_mul_ps(x, _mm256_set1_ps(std::f32::consts::LOG2_E)),
+        _MM_FROUND_TO_NEAREST_INT | _MM_FROUND_NO_EXC,
+    );
+    let r = _mm256_sub_ps(
+        _mm256_sub_ps(x, _mm256_mul_ps(n, _mm256_set1_ps(LN2_HI))),
+        _mm256_mul_ps(n, _mm256_set1_ps(LN2_LO)),
+    );
+    let mut p = _mm256_set1_ps(EXP_POLY[0]);
+    for c in &EXP_POLY[1..] {
+        p = _mm256_add_ps(_mm256_mul_ps(p, r), _mm256_set1_ps(*c));
+    }
+    let e_r = _mm256_add_ps(
+        _mm256_add_ps(_mm256_mul_ps(_mm256_mul_ps(p, r), r), r),
+        _mm256_set1_ps(1f32),
+    );
+    let exponent = _mm256_add_epi32(_mm256_cvtps_epi32(n), _mm256_set1_epi32(127));
+    let scale = _mm256_castsi256_ps(_mm256_slli_epi32(exponent, 23));
+    _mm256_andnot_ps(underflow, _mm256_mul_ps(e_r, scale))
+}
+
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "avx2")]
+pub(crate) unsafe fn exp_sum_avx2(max: f32, x: &mut [f32]) -> f32 {
+    let n8 = x.len() / 8 * 8;
+    let max8 = _mm256_set1_ps(max);
+    let mut acc = _mm256_setzero_ps();
+
+    for i in (0..n8).step_by(8) {
+        let x8 = _mm256_loadu_ps(x.as_ptr().add(i));
+        let e8 = exp_avx2(_mm256_sub_ps(x8, max8));
+        _mm256_storeu_ps(x.as_mut_ptr().add(i), e8);
+        acc = _mm256_add_ps(acc, e8);
+    }
+
+    let mut lanes = [0f32; 8];
+    _mm256_storeu_ps(lanes.as_mut_ptr(), acc);
+    lanes.iter().sum::<f32>() + exp_sum_scalar(max, &mut x[n8..])
+}
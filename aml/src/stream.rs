@@ -0,0 +1,152 @@
+//! GEMM over operands too large to hold in memory, read a panel at a time.
+
+use crate::{
+    check_rank, sgemm_with, AmlError, AsTensorMut, AsTensorRef, GemmParams, Layout, Tensor,
+};
+use std::io::{self, Read, Seek, SeekFrom};
+use std::ops::Range;
+
+/// A row-major f32 matrix that can be read a block at a time, e.g. from a file.
+///
+/// Every `AsTensorRef<f32>` is one, so an operand that does fit in memory, or one from
+/// `Tensor::from_mmap`, can be mixed with a `MatrixReader` over a file.
+pub trait MatrixSource {
+    /// (rows, cols) of the whole matrix, or `RankMismatch` for a source that is not a matrix
+    fn shape(&self) -> Result<[usize; 2], AmlError>;
+
+    /// Write rows `rows` and columns `cols` into `out`, row-major, `cols.len()` values per row.
+    /// The ranges are inside `shape` and `out` holds exactly the block.
+    fn read_block(
+        &mut self,
+        rows: Range<usize>,
+        cols: Range<usize>,
+        out: &mut [f32],
+    ) -> io::Result<()>;
+}
+
+impl<A: AsTensorRef<f32>> MatrixSource for A {
+    fn shape(&self) -> Result<[usize; 2], AmlError> {
+        let tensor = self.as_tensor_ref();
+        check_rank("source", &tensor.shape, 2)?;
+        Ok([tensor.shape[0], tensor.shape[1]])
+    }
+
+    fn read_block(
+        &mut self,
+        rows: Range<usize>,
+        cols: Range<usize>,
+        out: &mut [f32],
+    ) -> io::Result<()> {
+        let block = self.as_tensor_ref().view(rows, cols);
+        let (width, ld) = (block.shape[1], block.ld());
+        match (block.layout, block.strides[1]) {
+            // each row of a row-major window is a slice, whatever the gaps between them
+            (Layout::RowMajor, 1) => {
+                for (i, out_row) in out.chunks_exact_mut(width.max(1)).enumerate() {
+                    out_row.copy_from_slice(&block.values[i * ld..][..width]);
+                }
+            }
+            _ => out.copy_from_slice(&block.dense_in(Layout::RowMajor)),
+        }
+        Ok(())
+    }
+}
+
+/// A row-major (rows, cols) f32 matrix stored in native byte order at byte `offset` of a
+/// reader, read on demand: a `File`, or a `BufReader` of one for many small blocks.
+#[derive(Debug)]
+pub struct MatrixReader<R: Read + Seek> {
+    reader: R,
+    shape: [usize; 2],
+    offset: u64,
+}
+
+impl<R: Read + Seek> MatrixReader<R> {
+    pub fn new(reader: R, shape: [usize; 2], offset: u64) -> MatrixReader<R> {
+        MatrixReader {
+            reader,
+            shape,
+            offset,
+        }
+    }
+
+    pub fn into_inner(self) -> R {
+        self.reader
+    }
+}
+
+impl<R: Read + Seek> MatrixSource for MatrixReader<R> {
+    fn shape(&self) -> Result<[usize; 2], AmlError> {
+        Ok(self.shape)
+    }
+
+    fn read_block(
+        &mut self,
+        rows: Range<usize>,
+        cols: Range<usize>,
+        out: &mut [f32],
+    ) -> io::Result<()> {
+        let size = std::mem::size_of::<f32>();
+        let mut bytes = vec![0u8; cols.len() * size];
+        for (i, out_row) in rows.zip(out.chunks_exact_mut(cols.len().max(1))) {
+            let start = (i * self.shape[1] + cols.start) * size;
+            self.reader
+                .seek(SeekFrom::Start(self.offset + start as u64))?;
+            self.reader.read_exact(&mut bytes)?;
+            for (value, value_bytes) in out_row.iter_mut().zip(bytes.chunks_exact(size)) {
+                *value = f32::from_ne_bytes(value_bytes.try_into().unwrap());
+            }
+        }
+        Ok(())
+    }
+}
+
+/// `c = a @ b` reading `a` and `b` a K-panel at a time: columns `p0..p0 + depth` of `a` and
+/// the same rows of `b`. Only those two panels, `m * depth + depth * n` values, are held on top
+/// of `c`, so `a` and `b` can be far larger than memory. For a `c` that is too large as well,
+/// run it on row blocks of `a` with a `c` window each.
+///
+/// Each panel's product is added to `c` by `sgemm`, so the sums are split at every `depth`
+/// and may round differently from a single `sgemm`. Shape errors are `InvalidInput` wrapping
+/// the `AmlError`; reading errors are passed on.
+pub fn sgemm_streamed(
+    a: &mut impl MatrixSource,
+    b: &mut impl MatrixSource,
+    depth: usize,
+    c: &mut impl AsTensorMut<f32>,
+) -> io::Result<()> {
+    let invalid = |e: AmlError| io::Error::new(io::ErrorKind::InvalidInput, e);
+    let ([m, k], [b_k, n]) = (a.shape().map_err(invalid)?, b.shape().map_err(invalid)?);
+    if k != b_k {
+        return Err(invalid(AmlError::InnerDimMismatch { a: k, b: b_k }));
+    }
+    let mut c = c.as_tensor_mut();
+    if c.shape != [m, n] {
+        return Err(invalid(AmlError::OutputShapeMismatch {
+            expected: vec![m, n],
+            found: c.shape.to_vec(),
+        }));
+    }
+
+    // one empty panel when k is 0, which zeroes `c`
+    let depth = depth.clamp(1, k.max(1));
+    let mut a_panel = Tensor::zeros(vec![m, depth.min(k)]);
+    let mut b_panel = Tensor::zeros(vec![depth.min(k), n]);
+    for p0 in (0..k.max(1)).step_by(depth) {
+        let p1 = (p0 + depth).min(k);
+        // only the last panel may be thinner
+        if p1 - p0 != a_panel.shape[1] {
+            a_panel = Tensor::zeros(vec![m, p1 - p0]);
+            b_panel = Tensor::zeros(vec![p1 - p0, n]);
+        }
+        a.read_block(0..m, p0..p1, &mut a_panel.values)?;
+        b.read_block(p0..p1, 0..n, &mut b_panel.values)?;
+        let beta = match p0 {
+            0 => 0f32,
+            _ => 1f32,
+        };
+        let params = GemmParams::new(1f32, beta);
+        sgemm_with(&a_panel, false, &b_panel, false, params, &mut c);
+    }
+    Ok(())
+}
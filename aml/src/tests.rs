@@ -1,16 +1,16 @@
 #[cfg(test)]
 use crate::*;
 #[cfg(test)]
-use half::f16;
+use half::{bf16, f16};
 
 #[test]
 pub fn dot_correctness_sm() {
     let zeros: Vec<i8> = vec![0x0F];
     let nibbles: Vec<i8> = vec![0xAAu8 as i8, 0x36];
-    let scales: Vec<f16> = vec![3f32, 5f32].iter().map(|v| f16::from_f32(*v)).collect();
+    let scales: Vec<f16> = [3f32, 5f32].iter().map(|v| f16::from_f32(*v)).collect();
     let a = I4Tensor::new(&scales, &zeros, &nibbles, vec![4]);
 
-    let values: Vec<f16> = vec![0f32, 4f32, 5f32, 8f32]
+    let values: Vec<f16> = [0f32, 4f32, 5f32, 8f32]
         .iter()
         .map(|v| f16::from_f32(*v))
         .collect();
@@ -31,14 +31,14 @@ pub fn dot_correctness_sm() {
 #[test]
 pub fn qgemv_correctness_sm() {
     let a = F16Tensor::new(
-        vec![0f32, 4f32, 5f32, 8f32]
+        [0f32, 4f32, 5f32, 8f32]
             .iter()
             .map(|v| f16::from_f32(*v))
             .collect(),
         vec![4],
     );
 
-    let scales: Vec<f16> = vec![3f32, 5f32, 3f32, 5f32, 3f32, 5f32]
+    let scales: Vec<f16> = [3f32, 5f32, 3f32, 5f32, 3f32, 5f32]
         .iter()
         .map(|v| f16::from_f32(*v))
         .collect();
@@ -56,17 +56,17 @@ pub fn qgemv_correctness_sm() {
 
 #[test]
 pub fn qgemm_correctness_sm() {
-    let values: Vec<f16> = vec![0f32, 4f32, 5f32, 8f32, 0f32, 4f32, 6f32, 3f32]
+    let values: Vec<f16> = [0f32, 4f32, 5f32, 8f32, 0f32, 4f32, 6f32, 3f32]
         .iter()
         .map(|v| f16::from_f32(*v))
         .collect();
     let a = F16Tensor::new(values, vec![2, 4]);
     // [
     //    [0, 4, 5, 8],
-    //    [0, 4, 5, 8],
+    //    [0, 4, 6, 3],
     // ]
 
-    let scales: Vec<f16> = vec![3f32, 5f32, 3f32, 5f32]
+    let scales: Vec<f16> = [3f32, 5f32, 3f32, 5f32]
         .iter()
         .map(|v| f16::from_f32(*v))
         .collect();
@@ -74,16 +74,4854 @@ pub fn qgemm_correctness_sm() {
     let nibbles: Vec<i8> = vec![0xAAu8 as i8, 0x36, 0xAAu8 as i8, 0x36];
     let b = I4Tensor::new(&scales, &zeros, &nibbles, vec![2, 4]);
     // [
-    //    [-18, -18, 20, 30],
-    //    [-18, -18, 20, 30],
+    //    [-18, -18, 20, 35],
+    //    [-18, -18, 20, 35],
     // [
 
     let mut c = F16Tensor::zeros(vec![2, 2]);
 
     qgemm(&a, false, &b, true, &mut c);
 
-    let expected = vec![308, 308, 308, 308];
-    for i in 0..expected.len() {
-        assert!(c.values[i].to_f32() as i32 == 308);
+    let expected = [308, 308, 153, 153];
+    for (actual, expected) in c.values.iter().zip(expected) {
+        assert!(actual.to_f32() as i32 == expected);
+    }
+}
+
+/// Plain f32 reference for `qgemm`, indexing straight through the transposes.
+#[cfg(test)]
+fn gemm_reference(
+    a: &[f32],
+    a_shape: [usize; 2],
+    a_transpose: bool,
+    b: &[f32],
+    b_shape: [usize; 2],
+    b_transpose: bool,
+) -> Vec<f32> {
+    let a_at = |i: usize, p: usize| match a_transpose {
+        true => a[p * a_shape[1] + i],
+        false => a[i * a_shape[1] + p],
+    };
+    let b_at = |p: usize, j: usize| match b_transpose {
+        true => b[j * b_shape[1] + p],
+        false => b[p * b_shape[1] + j],
+    };
+    let (m, k) = match a_transpose {
+        true => (a_shape[1], a_shape[0]),
+        false => (a_shape[0], a_shape[1]),
+    };
+    let n = match b_transpose {
+        true => b_shape[0],
+        false => b_shape[1],
+    };
+
+    let mut c = vec![0f32; m * n];
+    for i in 0..m {
+        for j in 0..n {
+            c[i * n + j] = (0..k).map(|p| a_at(i, p) * b_at(p, j)).sum();
+        }
+    }
+    c
+}
+
+#[test]
+pub fn qgemm_transpose_correctness_sm() {
+    let a_values = [0f32, 4f32, 5f32, 8f32, 0f32, 4f32, 6f32, 3f32];
+
+    let scales: Vec<f16> = [3f32, 5f32, 3f32, 5f32]
+        .iter()
+        .map(|v| f16::from_f32(*v))
+        .collect();
+    let zeros: Vec<i8> = vec![0x0F, 0x00];
+    let nibbles: Vec<i8> = vec![0xAAu8 as i8, 0x36, 0xAAu8 as i8, 0x36];
+    let b = I4Tensor::new(&scales, &zeros, &nibbles, vec![2, 4]);
+    let b_values = [-18f32, -18f32, 20f32, 35f32, -18f32, -18f32, 15f32, 30f32];
+
+    // (a shape, a_transpose, b_transpose) for each op(a) @ op(b) that fits b (2, 4)
+    let cases = [
+        ([4, 2], false, false),
+        ([2, 4], true, false),
+        ([2, 4], false, true),
+        ([4, 2], true, true),
+    ];
+
+    for (a_shape, a_transpose, b_transpose) in cases {
+        let a = F16Tensor::new(
+            a_values.iter().map(|v| f16::from_f32(*v)).collect(),
+            a_shape.to_vec(),
+        );
+        let expected = gemm_reference(
+            &a_values,
+            a_shape,
+            a_transpose,
+            &b_values,
+            [2, 4],
+            b_transpose,
+        );
+
+        let c_shape = vec![
+            match a_transpose {
+                true => a_shape[1],
+                false => a_shape[0],
+            },
+            match b_transpose {
+                true => 2,
+                false => 4,
+            },
+        ];
+        let mut c = F16Tensor::zeros(c_shape);
+
+        qgemm(&a, a_transpose, &b, b_transpose, &mut c);
+
+        for (i, (actual, expected)) in c.values.iter().zip(&expected).enumerate() {
+            assert!(
+                actual.to_f32() == *expected,
+                "a_transpose={} b_transpose={} index {}: {} != {}",
+                a_transpose,
+                b_transpose,
+                i,
+                actual,
+                expected
+            );
+        }
+    }
+}
+
+#[test]
+pub fn try_qgemm_reports_shape_errors() {
+    let a = F16Tensor::zeros(vec![2, 4]);
+
+    let scales: Vec<f16> = [3f32, 5f32, 3f32, 5f32]
+        .iter()
+        .map(|v| f16::from_f32(*v))
+        .collect();
+    let zeros: Vec<i8> = vec![0x0F, 0x0F];
+    let nibbles: Vec<i8> = vec![0xAAu8 as i8, 0x36, 0xAAu8 as i8, 0x36];
+    let b = I4Tensor::new(&scales, &zeros, &nibbles, vec![2, 4]);
+
+    let mut c = F16Tensor::zeros(vec![2, 2]);
+    assert_eq!(
+        try_qgemm(&a, false, &b, false, &mut c),
+        Err(AmlError::InnerDimMismatch { a: 4, b: 2 })
+    );
+
+    let mut c = F16Tensor::zeros(vec![2, 3]);
+    assert_eq!(
+        try_qgemm(&a, false, &b, true, &mut c),
+        Err(AmlError::OutputShapeMismatch {
+            expected: vec![2, 2],
+            found: vec![2, 3]
+        })
+    );
+
+    assert!(matches!(
+        F16Tensor::try_new(vec![f16::from_f32(0f32); 3], vec![2, 2]),
+        Err(AmlError::SizeMismatch {
+            expected: 4,
+            found: 3
+        })
+    ));
+    assert!(matches!(
+        I4Tensor::try_new(&scales, &zeros[..1], &nibbles, vec![2, 4]),
+        Err(AmlError::ScalesZerosMismatch {
+            scales: 4,
+            zeros: 1
+        })
+    ));
+}
+
+/// Pack signed 4 bit values two per byte, high nibble first, padding the last byte with zero.
+#[cfg(test)]
+fn pack_i4(values: &[i8]) -> Vec<i8> {
+    values
+        .chunks(2)
+        .map(|pair| (pair[0] << 4) | (pair.get(1).copied().unwrap_or(0) & 0x0F))
+        .collect()
+}
+
+#[test]
+pub fn qgemm_correctness_odd_shapes() {
+    // b (3, 5) in 5 blocks of 3, so neither rows nor blocks line up with bytes
+    let q: Vec<i8> = (0..15).map(|i| (i % 16) as i8 - 8).collect();
+    let block_zeros: Vec<i8> = vec![0, -1, 2, 1, -3];
+    let block_scales = [0.5f32, 1f32, 2f32, 0.25f32, 1.5f32];
+
+    let nibbles = pack_i4(&q);
+    let zeros = pack_i4(&block_zeros);
+    let scales: Vec<f16> = block_scales.iter().map(|v| f16::from_f32(*v)).collect();
+    let b = I4Tensor::new(&scales, &zeros, &nibbles, vec![3, 5]);
+    let b_values: Vec<f32> = q
+        .iter()
+        .enumerate()
+        .map(|(i, v)| block_scales[i / 3] * (v - block_zeros[i / 3]) as f32)
+        .collect();
+
+    let a_values: Vec<f32> = (0..15).map(|i| (i % 7) as f32 - 3f32).collect();
+
+    // (a shape, a_transpose, b_transpose) for each op(a) @ op(b) that fits b (3, 5)
+    let cases = [
+        ([5, 3], false, false),
+        ([3, 5], true, false),
+        ([3, 5], false, true),
+        ([5, 3], true, true),
+    ];
+
+    for (a_shape, a_transpose, b_transpose) in cases {
+        let a = F16Tensor::new(
+            a_values.iter().map(|v| f16::from_f32(*v)).collect(),
+            a_shape.to_vec(),
+        );
+        let expected = gemm_reference(
+            &a_values,
+            a_shape,
+            a_transpose,
+            &b_values,
+            [3, 5],
+            b_transpose,
+        );
+
+        let mut c = F16Tensor::zeros(vec![
+            match a_transpose {
+                true => a_shape[1],
+                false => a_shape[0],
+            },
+            match b_transpose {
+                true => 3,
+                false => 5,
+            },
+        ]);
+
+        qgemm(&a, a_transpose, &b, b_transpose, &mut c);
+
+        for (actual, expected) in c.values.iter().zip(&expected) {
+            assert!((actual.to_f32() - expected).abs() <= expected.abs() * 1e-3);
+        }
+    }
+
+    let x = F16Tensor::new(
+        a_values[..5].iter().map(|v| f16::from_f32(*v)).collect(),
+        vec![5],
+    );
+    let y = qgemv(&x, &b);
+    for (row, actual) in y.values.iter().enumerate() {
+        let expected: f32 = (0..5).map(|j| a_values[j] * b_values[row * 5 + j]).sum();
+        assert!((actual.to_f32() - expected).abs() <= expected.abs() * 1e-3);
+    }
+}
+
+#[test]
+pub fn i4_owned_views_match_borrowed() {
+    let a = F16Tensor::new(vec![f16::from_f32(1f32); 6], vec![6]);
+
+    let zeros = I4TensorOwned::zeros(vec![4, 6], 3);
+    assert!(zeros.view().block_size == 3);
+    assert!(qgemv(&a, &zeros.view())
+        .values
+        .iter()
+        .all(|v| v.to_f32() == 0f32));
+
+    let b = I4TensorOwned::random(vec![4, 6], 3, 7);
+    assert!(b.nibbles == I4TensorOwned::random(vec![4, 6], 3, 7).nibbles);
+
+    let borrowed = I4Tensor::new(&b.scales, &b.zeros, &b.nibbles, vec![4, 6]);
+    let expected = qgemv(&a, &borrowed);
+    let actual = qgemv(&a, &b.view());
+    assert!(actual.values == expected.values);
+}
+
+#[test]
+pub fn i4_get_row_needs_aligned_rows() {
+    let a = F16Tensor::new(vec![f16::from_f32(1f32); 6], vec![6]);
+    let b = I4TensorOwned::random(vec![4, 6], 3, 7);
+    let view = b.view();
+    let rows = qgemv(&a, &view);
+    for i in 0..4 {
+        let row = view.get_row(i);
+        assert!(f16::from_f32(qdot(&a, &row)) == rows.values[i]);
+    }
+    assert_eq!(
+        b.view().try_get_row(4).err(),
+        Some(AmlError::IndexOutOfBounds {
+            index: vec![4],
+            shape: vec![4, 6]
+        })
+    );
+
+    // one block per row would hand row 1 the zero point of row 0
+    let b = I4TensorOwned::random(vec![4, 6], 6, 7);
+    assert_eq!(
+        b.view().try_get_row(1).err(),
+        Some(AmlError::UnalignedRows {
+            n: 6,
+            block_size: 6
+        })
+    );
+    // odd rows, and blocks straddling rows
+    let b = I4TensorOwned::random(vec![2, 5], 5, 7);
+    assert!(b.view().try_get_row(0).is_err());
+    let b = I4TensorOwned::random(vec![3, 4], 3, 7);
+    assert!(b.view().try_get_row(0).is_err());
+}
+
+#[test]
+pub fn qgemm_into_checks_output_shape() {
+    let a = F16Tensor::new(vec![f16::from_f32(1f32); 8], vec![2, 4]);
+    let b = I4TensorOwned::random(vec![3, 4], 2, 11);
+
+    // c lives in the middle of a bigger buffer
+    let mut buffer = [f16::from_f32(-1f32); 10];
+    let mut c = F16TensorMut::new(&mut buffer[2..8], vec![3, 2]);
+    assert_eq!(
+        try_qgemm_into(&a, false, &b.view(), true, &mut c),
+        Err(AmlError::OutputShapeMismatch {
+            expected: vec![2, 3],
+            found: vec![3, 2]
+        })
+    );
+
+    let mut c = F16TensorMut::new(&mut buffer[2..8], vec![2, 3]);
+    qgemm_into(&a, false, &b.view(), true, &mut c);
+
+    let mut expected = F16Tensor::zeros(vec![2, 3]);
+    qgemm(&a, false, &b.view(), true, &mut expected);
+    assert!(buffer[2..8] == expected.values[..]);
+    assert!(buffer[0].to_f32() == -1f32 && buffer[9].to_f32() == -1f32);
+
+    let x = F16Tensor::new(vec![f16::from_f32(1f32); 4], vec![4]);
+    let mut y = F16TensorMut::new(&mut buffer[..3], vec![3]);
+    qgemv_into(&x, &b.view(), &mut y);
+    assert!(buffer[..3] == qgemv(&x, &b.view()).values[..]);
+}
+
+#[test]
+pub fn qgemm_alpha_beta() {
+    let a = F16Tensor::new(
+        [1f32, 2f32, 3f32, 4f32, 5f32, 6f32, 7f32, 8f32]
+            .iter()
+            .map(|v| f16::from_f32(*v))
+            .collect(),
+        vec![2, 4],
+    );
+    let b = I4TensorOwned::random(vec![2, 4], 2, 3);
+
+    let mut ab = F16Tensor::zeros(vec![2, 2]);
+    qgemm(&a, false, &b.view(), true, &mut ab);
+
+    let mut c = F16Tensor::new(vec![f16::from_f32(2f32); 4], vec![2, 2]);
+    qgemm_with(
+        &a,
+        false,
+        &b.view(),
+        true,
+        GemmParams::new(0.5f32, -1f32),
+        &mut c.view_mut(),
+    );
+    for (actual, ab) in c.values.iter().zip(&ab.values) {
+        assert!(actual.to_f32() == f16::from_f32(0.5f32 * ab.to_f32() - 2f32).to_f32());
+    }
+
+    // beta = 0 must not read c, even if it holds NaN
+    let mut c = F16Tensor::new(vec![f16::NAN; 4], vec![2, 2]);
+    qgemm_with(
+        &a,
+        false,
+        &b.view(),
+        true,
+        GemmParams::new(2f32, 0f32),
+        &mut c.view_mut(),
+    );
+    assert!(c.values.iter().all(|v| !v.is_nan()));
+
+    let x = F16Tensor::new(a.values[..4].to_vec(), vec![4]);
+    let mut y = F16Tensor::new(vec![f16::from_f32(1f32); 2], vec![2]);
+    qgemv_with(
+        &x,
+        &b.view(),
+        GemmParams::new(1f32, 1f32),
+        &mut y.view_mut(),
+    );
+    for (actual, ab) in y.values.iter().zip(&ab.values[..2]) {
+        assert!(actual.to_f32() == f16::from_f32(ab.to_f32() + 1f32).to_f32());
+    }
+}
+
+#[test]
+pub fn accumulate_modes_agree_across_paths() {
+    let a = F16Tensor::new(
+        (0..12).map(|v| f16::from_f32(v as f32 - 5f32)).collect(),
+        vec![3, 4],
+    );
+    let b = I4TensorOwned::random(vec![4, 4], 2, 5);
+    let b = b.view();
+
+    for (a_transpose, b_transpose) in [(false, false), (false, true), (true, false), (true, true)] {
+        let mut a = F16Tensor::new(a.values.clone(), a.shape.clone());
+        if a_transpose {
+            a.reshape(vec![4, 3]);
+        }
+
+        let mut once = F16Tensor::zeros(vec![3, 4]);
+        qgemm_with(
+            &a,
+            a_transpose,
+            &b,
+            b_transpose,
+            Accumulate::Overwrite.into(),
+            &mut once.view_mut(),
+        );
+
+        let mut twice = F16Tensor::zeros(vec![3, 4]);
+        for _ in 0..2 {
+            qgemm_with(
+                &a,
+                a_transpose,
+                &b,
+                b_transpose,
+                Accumulate::Add.into(),
+                &mut twice.view_mut(),
+            );
+        }
+
+        for (once, twice) in once.values.iter().zip(&twice.values) {
+            assert!(twice.to_f32() == f16::from_f32(2f32 * once.to_f32()).to_f32());
+        }
+    }
+}
+
+#[test]
+pub fn qgemm_batched_matches_qgemm() {
+    let weights: Vec<I4TensorOwned> = (0..5)
+        .map(|seed| I4TensorOwned::random(vec![6, 4], 2, seed))
+        .collect();
+    let b: Vec<I4Tensor> = weights.iter().map(|w| w.view()).collect();
+    let a: Vec<F16Tensor> = (0..5)
+        .map(|i| {
+            F16Tensor::new(
+                (0..12)
+                    .map(|v| f16::from_f32((v * i) as f32 / 8f32))
+                    .collect(),
+                vec![3, 4],
+            )
+        })
+        .collect();
+
+    let mut c: Vec<F16Tensor> = (0..5).map(|_| F16Tensor::zeros(vec![3, 6])).collect();
+    qgemm_batched(&a, false, &b, true, &mut c);
+
+    for ((a, b), c) in a.iter().zip(&b).zip(&c) {
+        let mut expected = F16Tensor::zeros(vec![3, 6]);
+        qgemm(a, false, b, true, &mut expected);
+        assert!(c.values == expected.values);
+    }
+
+    assert_eq!(
+        try_qgemm_batched(&a, false, &b[..4], true, &mut c),
+        Err(AmlError::BatchSizeMismatch { a: 5, b: 4, c: 5 })
+    );
+}
+
+#[test]
+pub fn sgemm_batched_matches_sgemm() {
+    // a batch too small to split, one split across the threads and one as long as the threads
+    for (batch, m, threads) in [(3, 5, 4), (6, 64, 4), (2, 96, 4)] {
+        let (n, k) = (m + 3, m - 1);
+        let a: Vec<Tensor<f32>> = (0..batch)
+            .map(|i| {
+                Tensor::new(
+                    (0..m * k).map(|v| ((v + i) % 7) as f32).collect(),
+                    vec![m, k],
+                )
+            })
+            .collect();
+        let b: Vec<Tensor<f32>> = (0..batch)
+            .map(|i| {
+                Tensor::new(
+                    (0..n * k).map(|v| ((v * i) % 5) as f32).collect(),
+                    vec![n, k],
+                )
+            })
+            .collect();
+
+        let mut c: Vec<Tensor<f32>> = (0..batch).map(|_| Tensor::zeros(vec![m, n])).collect();
+        AmlContext::new(threads).install(|| sgemm_batched(&a, false, &b, true, &mut c));
+
+        for ((a, b), c) in a.iter().zip(&b).zip(&c) {
+            let mut expected = Tensor::zeros(vec![m, n]);
+            sgemm(a, false, b, true, &mut expected);
+            assert!(c.values == expected.values);
+        }
+    }
+
+    // views work as well as owned tensors, and a bad entry stops the whole batch
+    let a = Tensor::new((0..6).map(|v| v as f32).collect(), vec![2, 3]);
+    let b = Tensor::new((0..12).map(|v| v as f32).collect(), vec![3, 4]);
+    let (mut c0, mut c1) = ([-1f32; 8], [-1f32; 8]);
+    let mut c = [
+        TensorMut::new(&mut c0[..], vec![2, 4]),
+        TensorMut::new(&mut c1[..], vec![2, 4]),
+    ];
+    let b_views = [b.view(0..3, 0..4), b.view(0..3, 0..4)];
+    assert_eq!(
+        try_sgemm_batched(
+            &[a.view(0..2, 0..3), a.view(0..2, 0..2)],
+            false,
+            &b_views,
+            false,
+            &mut c
+        ),
+        Err(AmlError::InnerDimMismatch { a: 2, b: 3 })
+    );
+    assert!(c.iter().flat_map(|c| c.values.iter()).all(|v| *v == -1f32));
+    assert_eq!(
+        try_sgemm_batched(&[a.view(0..2, 0..3)], false, &b_views, false, &mut c),
+        Err(AmlError::BatchSizeMismatch { a: 1, b: 2, c: 2 })
+    );
+}
+
+#[test]
+pub fn sgemv_correctness() {
+    // big enough to be split across threads; halves keep every sum exact
+    let (m, n) = (301, 517);
+    let x: Vec<f32> = (0..n).map(|i| ((i % 13) as f32 - 6f32) / 4f32).collect();
+    let a_values: Vec<f32> = (0..m * n).map(|i| ((i % 7) as f32 - 3f32) / 2f32).collect();
+    let expected: Vec<f32> = (0..m)
+        .map(|i| (0..n).map(|j| a_values[i * n + j] * x[j]).sum())
+        .collect();
+
+    // the same matrix stored by rows, by columns and as a window of a wider one
+    let row_major = Tensor::new(a_values.clone(), vec![m, n]);
+    let transposed = Tensor::new_with_ld(a_values.clone(), vec![n, m], Layout::ColMajor, n);
+    let wide: Vec<f32> = a_values
+        .chunks_exact(n)
+        .flat_map(|row| row.iter().copied().chain([f32::NAN; 3]))
+        .collect();
+    let wide = Tensor::new(wide, vec![m, n + 3]);
+    let col_values: Vec<f32> = (0..m * n).map(|v| a_values[v % m * n + v / m]).collect();
+    let col_major = Tensor::new_with_ld(col_values, vec![m, n], Layout::ColMajor, m);
+    let row_transposed = Tensor::new(col_major.values.clone(), vec![n, m]);
+
+    assert_eq!(sgemv(&row_major, false, &x), expected);
+    assert_eq!(sgemv(&transposed, true, &x), expected);
+    assert_eq!(sgemv(&wide.view(0..m, 0..n), false, &x), expected);
+    assert_eq!(sgemv(&col_major, false, &x), expected);
+    assert_eq!(sgemv(&row_transposed, true, &x), expected);
+
+    let mut y = vec![1f32; m];
+    sgemv_with(&row_major, false, &x, GemmParams::new(2f32, 3f32), &mut y);
+    assert!(y.iter().zip(&expected).all(|(y, e)| *y == 2f32 * e + 3f32));
+
+    assert_eq!(
+        try_sgemv(&row_major, true, &x),
+        Err(AmlError::InnerDimMismatch { a: m, b: n })
+    );
+    assert_eq!(
+        try_sgemv_with(&row_major, false, &x, GemmParams::default(), &mut y[1..]),
+        Err(AmlError::SizeMismatch {
+            expected: m,
+            found: m - 1
+        })
+    );
+    assert_eq!(
+        sgemv(&Tensor::<f32>::zeros(vec![2, 0]), false, &[]),
+        [0f32; 2]
+    );
+}
+
+#[test]
+pub fn hgemv_correctness() {
+    // big enough to be split across threads
+    let (m, n) = (301, 517);
+    let a_values: Vec<f32> = (0..n).map(|i| ((i % 13) as f32 - 6f32) / 4f32).collect();
+    let b_values: Vec<f32> = (0..m * n).map(|i| ((i % 7) as f32 - 3f32) / 2f32).collect();
+    let a = F16Tensor::new(
+        a_values.iter().map(|v| f16::from_f32(*v)).collect(),
+        vec![n],
+    );
+    let b = F16Tensor::new(
+        b_values.iter().map(|v| f16::from_f32(*v)).collect(),
+        vec![m, n],
+    );
+
+    let actual = hgemv(&a, &b);
+    for (row, actual) in actual.values.iter().enumerate() {
+        let expected: f32 = (0..n).map(|j| a_values[j] * b_values[row * n + j]).sum();
+        assert!(actual.to_f32() == f16::from_f32(expected).to_f32());
+    }
+
+    // the split qgemv must match the single threaded qgemm path row for row
+    let q = I4TensorOwned::random(vec![m, n], 11, 1);
+    let actual = qgemv(&a, &q.view());
+    let mut expected = F16Tensor::zeros(vec![1, m]);
+    qgemm(
+        &F16Tensor::new(a.values.clone(), vec![1, n]),
+        false,
+        &q.view(),
+        true,
+        &mut expected,
+    );
+    assert!(actual.values == expected.values);
+}
+
+#[test]
+pub fn blas1_simd_matches_scalar() {
+    let x: Vec<f32> = (0..37).map(|i| (i as f32 * 0.37f32).sin()).collect();
+    let y: Vec<f32> = (0..37).map(|i| (i as f32 * 0.11f32).cos()).collect();
+    let close = |a: f32, b: f32| (a - b).abs() <= 1e-5 * b.abs().max(1f32);
+
+    assert!(close(sdot(&x, &y), crate::blas1::sdot_scalar(&x, &y)));
+    assert!(close(sasum(&x), crate::blas1::sasum_scalar(&x)));
+    assert!(close(snrm2(&x), crate::blas1::snrm2_scalar(&x)));
+    assert!(close(snrm2(&x), sdot(&x, &x).sqrt()));
+
+    let mut actual = y.clone();
+    saxpy(-2f32, &x, &mut actual);
+    let mut expected = y.clone();
+    crate::blas1::saxpy_scalar(-2f32, &x, &mut expected);
+    assert!(actual == expected);
+
+    sscal(0.5f32, &mut actual);
+    crate::blas1::sscal_scalar(0.5f32, &mut expected);
+    assert!(actual == expected);
+
+    // f32 squares of these overflow, the norm does not
+    assert!(close(snrm2(&[3e30f32; 9]), 9e30f32));
+
+    assert_eq!(
+        try_sdot(&x, &y[1..]),
+        Err(AmlError::SizeMismatch {
+            expected: 37,
+            found: 36
+        })
+    );
+}
+
+#[test]
+pub fn sger_correctness() {
+    let (m, n) = (67, 45);
+    let x: Vec<f32> = (0..m).map(|i| i as f32 - 30f32).collect();
+    let y: Vec<f32> = (0..n).map(|j| (j % 5) as f32 / 4f32).collect();
+    let mut a = F32Tensor::new((0..m * n).map(|v| v as f32).collect(), vec![m, n]);
+
+    sger(0.5f32, &x, &y, &mut a);
+
+    for (i, x_val) in x.iter().enumerate() {
+        for (j, y_val) in y.iter().enumerate() {
+            assert!(a.values[i * n + j] == (i * n + j) as f32 + 0.5f32 * x_val * y_val);
+        }
+    }
+
+    assert_eq!(
+        try_sger(1f32, &y, &y, &mut a),
+        Err(AmlError::SizeMismatch {
+            expected: m,
+            found: n
+        })
+    );
+}
+
+#[test]
+pub fn ssyrk_correctness() {
+    let (n, k) = (9, 13);
+    let a = F32Tensor::new(
+        (0..n * k)
+            .map(|v| ((v % 11) as f32 - 5f32) / 2f32)
+            .collect(),
+        vec![n, k],
+    );
+    let a_values: Vec<f32> = a.values.clone();
+
+    for uplo in [Uplo::Upper, Uplo::Lower] {
+        for a_transpose in [false, true] {
+            let a = match a_transpose {
+                true => F32Tensor::new(a_values.clone(), vec![k, n]),
+                false => F32Tensor::new(a_values.clone(), vec![n, k]),
+            };
+            let a_shape = [a.shape[0], a.shape[1]];
+            let aat = gemm_reference(
+                &a.values,
+                a_shape,
+                a_transpose,
+                &a.values,
+                a_shape,
+                !a_transpose,
+            );
+
+            let mut c = F32Tensor::new(vec![1f32; n * n], vec![n, n]);
+            ssyrk(uplo, &a, a_transpose, GemmParams::new(2f32, 3f32), &mut c);
+
+            for i in 0..n {
+                for j in 0..n {
+                    let in_triangle = match uplo {
+                        Uplo::Upper => j >= i,
+                        Uplo::Lower => j <= i,
+                    };
+                    let expected = match in_triangle {
+                        true => 2f32 * aat[i * n + j] + 3f32,
+                        false => 1f32,
+                    };
+                    assert!(c.values[i * n + j] == expected);
+                }
+            }
+        }
+    }
+}
+
+/// Copy of `a` (m, m) with everything outside the `uplo` triangle zeroed, and ones on the
+/// diagonal for `Diag::Unit`.
+#[cfg(test)]
+fn triangle_of(a: &[f32], m: usize, uplo: Uplo, diag: Diag) -> Vec<f32> {
+    let mut t = vec![0f32; m * m];
+    for i in 0..m {
+        for j in 0..m {
+            t[i * m + j] = match (i == j, diag, uplo) {
+                (true, Diag::Unit, _) => 1f32,
+                (true, Diag::NonUnit, _) => a[i * m + j],
+                (false, _, Uplo::Lower) if j < i => a[i * m + j],
+                (false, _, Uplo::Upper) if j > i => a[i * m + j],
+                _ => 0f32,
+            };
+        }
+    }
+    t
+}
+
+#[test]
+pub fn strmm_correctness() {
+    let (m, n) = (7, 5);
+    // garbage everywhere, including the half strmm must ignore
+    let a_values: Vec<f32> = (0..m * m).map(|v| ((v % 9) as f32 - 4f32) / 2f32).collect();
+    let b_values: Vec<f32> = (0..m * n).map(|v| ((v % 5) as f32 - 2f32) / 4f32).collect();
+    let a = F32Tensor::new(a_values.clone(), vec![m, m]);
+
+    for uplo in [Uplo::Upper, Uplo::Lower] {
+        for diag in [Diag::NonUnit, Diag::Unit] {
+            let t = triangle_of(&a_values, m, uplo, diag);
+            let expected = gemm_reference(&t, [m, m], false, &b_values, [m, n], false);
+
+            let mut b = F32Tensor::new(b_values.clone(), vec![m, n]);
+            strmm(uplo, diag, -2f32, &a, &mut b);
+
+            for (actual, expected) in b.values.iter().zip(&expected) {
+                assert!(*actual == -2f32 * expected);
+            }
+        }
+    }
+}
+
+#[test]
+pub fn strsm_correctness() {
+    // more rows than one diagonal block, so the trailing update runs
+    let (m, n) = (150, 6);
+    for side in [Side::Left, Side::Right] {
+        let t_dim = match side {
+            Side::Left => m,
+            Side::Right => n,
+        };
+        // dominant diagonal keeps the solve well conditioned
+        let a_values: Vec<f32> = (0..t_dim * t_dim)
+            .map(|v| match v % (t_dim + 1) {
+                0 => 4f32,
+                _ => ((v % 7) as f32 - 3f32) / (4f32 * t_dim as f32),
+            })
+            .collect();
+        let a = F32Tensor::new(a_values.clone(), vec![t_dim, t_dim]);
+        let b_values: Vec<f32> = (0..m * n)
+            .map(|v| ((v % 13) as f32 - 6f32) / 3f32)
+            .collect();
+
+        for uplo in [Uplo::Upper, Uplo::Lower] {
+            for diag in [Diag::NonUnit, Diag::Unit] {
+                let mut x = F32Tensor::new(b_values.clone(), vec![m, n]);
+                strsm(side, uplo, diag, 0.5f32, &a, &mut x);
+
+                let t = triangle_of(&a_values, t_dim, uplo, diag);
+                let back = match side {
+                    Side::Left => gemm_reference(&t, [m, m], false, &x.values, [m, n], false),
+                    Side::Right => gemm_reference(&x.values, [m, n], false, &t, [n, n], false),
+                };
+                for (back, b) in back.iter().zip(&b_values) {
+                    assert!((back - 0.5f32 * b).abs() < 1e-4);
+                }
+            }
+        }
+    }
+}
+
+#[test]
+pub fn dgemm_correctness() {
+    // k past one KC panel and enough work to split across threads
+    let (m, n, k) = (37, 29, 300);
+    let a_values: Vec<f64> = (0..m * k)
+        .map(|v| ((v % 17) as f64 - 8f64) / 3f64)
+        .collect();
+    let b_values: Vec<f64> = (0..k * n)
+        .map(|v| ((v % 11) as f64 - 5f64) / 7f64)
+        .collect();
+
+    for a_transpose in [false, true] {
+        for b_transpose in [false, true] {
+            let a_shape = match a_transpose {
+                true => vec![k, m],
+                false => vec![m, k],
+            };
+            let b_shape = match b_transpose {
+                true => vec![n, k],
+                false => vec![k, n],
+            };
+            let a = F64Tensor::new(a_values.clone(), a_shape.clone());
+            let b = F64Tensor::new(b_values.clone(), b_shape.clone());
+
+            let mut c = F64Tensor::new(vec![1f64; m * n], vec![m, n]);
+            dgemm_with(
+                &a,
+                a_transpose,
+                &b,
+                b_transpose,
+                GemmParams::new(2f64, -1f64),
+                &mut c,
+            );
+
+            for i in 0..m {
+                for j in 0..n {
+                    let expected: f64 = (0..k)
+                        .map(|p| {
+                            let a_ip = match a_transpose {
+                                true => a_values[p * m + i],
+                                false => a_values[i * k + p],
+                            };
+                            let b_pj = match b_transpose {
+                                true => b_values[j * k + p],
+                                false => b_values[p * n + j],
+                            };
+                            a_ip * b_pj
+                        })
+                        .sum();
+                    assert!((c.values[i * n + j] - (2f64 * expected - 1f64)).abs() < 1e-9);
+                }
+            }
+        }
+    }
+}
+
+#[test]
+pub fn hgemm_correctness() {
+    let (m, n, k) = (19, 23, 600);
+    let a_values: Vec<f32> = (0..m * k).map(|v| ((v % 9) as f32 - 4f32) / 8f32).collect();
+    let b_values: Vec<f32> = (0..k * n).map(|v| ((v % 5) as f32 - 2f32) / 4f32).collect();
+
+    for (a_transpose, b_transpose) in [(false, false), (false, true), (true, false), (true, true)] {
+        let a_shape = match a_transpose {
+            true => [k, m],
+            false => [m, k],
+        };
+        let b_shape = match b_transpose {
+            true => [n, k],
+            false => [k, n],
+        };
+        let a = F16Tensor::new(
+            a_values.iter().map(|v| f16::from_f32(*v)).collect(),
+            a_shape.to_vec(),
+        );
+        let b = F16Tensor::new(
+            b_values.iter().map(|v| f16::from_f32(*v)).collect(),
+            b_shape.to_vec(),
+        );
+        let expected = gemm_reference(
+            &a_values,
+            a_shape,
+            a_transpose,
+            &b_values,
+            b_shape,
+            b_transpose,
+        );
+
+        let mut c = F16Tensor::zeros(vec![m, n]);
+        hgemm(&a, a_transpose, &b, b_transpose, &mut c);
+
+        // all partial sums are exact in f32, so only the final rounding to f16 differs
+        for (actual, expected) in c.values.iter().zip(&expected) {
+            assert!(*actual == f16::from_f32(*expected));
+        }
+    }
+
+    let x: Vec<f16> = a_values[..37].iter().map(|v| f16::from_f32(*v)).collect();
+    let y: Vec<f16> = b_values[..37].iter().map(|v| f16::from_f32(*v)).collect();
+    assert!(crate::hgemm::hdot(&x, &y) == crate::hgemm::hdot_scalar(&x, &y));
+}
+
+#[test]
+pub fn sbgemm_correctness() {
+    let (m, n, k) = (13, 11, 77);
+    let a_values: Vec<f32> = (0..m * k).map(|v| ((v % 9) as f32 - 4f32) / 8f32).collect();
+    let b_values: Vec<f32> = (0..k * n).map(|v| ((v % 5) as f32 - 2f32) / 4f32).collect();
+
+    for (a_transpose, b_transpose) in [(false, false), (false, true), (true, false), (true, true)] {
+        let a_shape = match a_transpose {
+            true => [k, m],
+            false => [m, k],
+        };
+        let b_shape = match b_transpose {
+            true => [n, k],
+            false => [k, n],
+        };
+        let a = BF16Tensor::new(
+            a_values.iter().map(|v| bf16::from_f32(*v)).collect(),
+            a_shape.to_vec(),
+        );
+        let b = BF16Tensor::new(
+            b_values.iter().map(|v| bf16::from_f32(*v)).collect(),
+            b_shape.to_vec(),
+        );
+        let expected = gemm_reference(
+            &a_values,
+            a_shape,
+            a_transpose,
+            &b_values,
+            b_shape,
+            b_transpose,
+        );
+
+        let mut c = F32Tensor::zeros(vec![m, n]);
+        sbgemm(&a, a_transpose, &b, b_transpose, &mut c);
+
+        // inputs are exact in bf16 and every partial sum is exact in f32
+        assert!(c.values == expected);
+    }
+
+    let x: Vec<bf16> = a_values[..45].iter().map(|v| bf16::from_f32(*v)).collect();
+    let y: Vec<bf16> = b_values[..45].iter().map(|v| bf16::from_f32(*v)).collect();
+    let expected = crate::sbgemm::sbdot_scalar(&x, &y);
+    #[cfg(target_arch = "x86_64")]
+    if is_x86_feature_detected!("avx2") && is_x86_feature_detected!("fma") {
+        assert!(unsafe { crate::sbgemm::sbdot_avx2(&x, &y) } == expected);
+    }
+    assert!(crate::sbgemm::sbdot(&x, &y) == expected);
+}
+
+#[test]
+pub fn igemm_correctness() {
+    let (m, n, k) = (7, 9, 53);
+    let a_q: Vec<i8> = (0..m * k).map(|v| (v * 37 % 256) as u8 as i8).collect();
+    let b_q: Vec<i8> = (0..k * n).map(|v| (v * 91 % 256) as u8 as i8).collect();
+    let (a_scale, a_zero, b_scale, b_zero) = (0.5f32, -3i8, 0.25f32, 7i8);
+    let a_values: Vec<f32> = a_q
+        .iter()
+        .map(|q| a_scale * (*q as f32 - a_zero as f32))
+        .collect();
+    let b_values: Vec<f32> = b_q
+        .iter()
+        .map(|q| b_scale * (*q as f32 - b_zero as f32))
+        .collect();
+
+    for (a_transpose, b_transpose) in [(false, false), (false, true), (true, false), (true, true)] {
+        let a_shape = match a_transpose {
+            true => [k, m],
+            false => [m, k],
+        };
+        let b_shape = match b_transpose {
+            true => [n, k],
+            false => [k, n],
+        };
+        let a = I8Tensor::new(a_q.clone(), a_shape.to_vec(), a_scale, a_zero);
+        let b = I8Tensor::new(b_q.clone(), b_shape.to_vec(), b_scale, b_zero);
+        let expected = gemm_reference(
+            &a_values,
+            a_shape,
+            a_transpose,
+            &b_values,
+            b_shape,
+            b_transpose,
+        );
+
+        let mut c = F32Tensor::zeros(vec![m, n]);
+        igemm(&a, a_transpose, &b, b_transpose, &mut c);
+
+        assert!(c.values == expected);
+    }
+
+    // full range, with the i8::MIN x i8::MIN pairs that saturate vpmaddubsw inside the SIMD body
+    let x: Vec<i8> = [i8::MIN; 2]
+        .into_iter()
+        .chain((0..203).map(|v| (v * 53 % 256) as u8 as i8))
+        .collect();
+    let y: Vec<i8> = [i8::MIN; 2]
+        .into_iter()
+        .chain((0..203).map(|v| (v * 29 % 256) as u8 as i8))
+        .collect();
+    let expected = crate::igemm::idot_scalar(&x, &y);
+    #[cfg(target_arch = "x86_64")]
+    {
+        if is_x86_feature_detected!("avx512vnni") && is_x86_feature_detected!("avx512f") {
+            assert!(unsafe { crate::igemm::idot_vnni(&x, &y) } == expected);
+        }
+        if is_x86_feature_detected!("avx2") {
+            assert!(unsafe { crate::igemm::idot_avx2(&x, &y) } == expected);
+        }
+    }
+    assert!(crate::igemm::idot(&x, &y) == expected);
+}
+
+#[cfg(target_arch = "x86_64")]
+#[test]
+pub fn idot_vnni_and_avx2_match_scalar() {
+    let vnni = is_x86_feature_detected!("avx512vnni") && is_x86_feature_detected!("avx512f");
+    let avx2 = is_x86_feature_detected!("avx2");
+    let check = |x: &[i8], y: &[i8]| {
+        let expected = crate::igemm::idot_scalar(x, y);
+        if vnni {
+            assert_eq!(unsafe { crate::igemm::idot_vnni(x, y) }, expected);
+        }
+        if avx2 {
+            assert_eq!(unsafe { crate::igemm::idot_avx2(x, y) }, expected);
+        }
+    };
+
+    // every length around the 16 and 64 value bodies, so each tail length is summed
+    let x: Vec<i8> = (0..200).map(|v| (v * 53 % 256) as u8 as i8).collect();
+    let y: Vec<i8> = (0..200).map(|v| (v * 29 % 256 + 7) as u8 as i8).collect();
+    for len in 0..=x.len() {
+        check(&x[..len], &y[..len]);
+    }
+
+    // the corners of the range, over as many values as one i32 run in igemm holds, where the
+    // u8 bias of vpdpbusd comes closest to overflowing
+    let len = 1 << 16;
+    for (x, y) in [
+        (i8::MIN, i8::MIN),
+        (i8::MIN, i8::MAX),
+        (i8::MAX, i8::MIN),
+        (i8::MAX, i8::MAX),
+    ] {
+        check(&vec![x; len], &vec![y; len]);
+    }
+}
+
+#[test]
+pub fn igemm_long_k_with_extreme_zero_points() {
+    // 255 * 255 * 2^16 is past i32, though every dot product of k values fits
+    let k = 1 << 16;
+    let a = I8Tensor::new(vec![i8::MIN; k], vec![1, k], 1f32, i8::MAX);
+    let b = I8Tensor::new(vec![i8::MIN; k], vec![k, 1], 1f32, i8::MAX);
+    let mut c = F32Tensor::zeros(vec![1, 1]);
+    igemm(&a, false, &b, false, &mut c);
+    assert_eq!(c.values, [4261478400f32]);
+
+    let a = I8Tensor::new(vec![i8::MAX; k], vec![1, k], 1f32, i8::MIN);
+    igemm(&a, false, &b, false, &mut c);
+    assert_eq!(c.values, [-4261478400f32]);
+
+    // and the dot product alone past i32, over several runs
+    let k = (1 << 17) + 3;
+    let a = I8Tensor::new(vec![i8::MIN; 2 * k], vec![2, k], 1f32, 0);
+    let b = I8Tensor::new(vec![i8::MIN; k], vec![1, k], 1f32, 0);
+    let mut c = F32Tensor::zeros(vec![2, 1]);
+    igemm(&a, false, &b, true, &mut c);
+    assert_eq!(c.values, [16384f32 * k as f32; 2]);
+}
+
+#[test]
+pub fn i4_block_dot_simd_matches_scalar() {
+    #[cfg(target_arch = "x86_64")]
+    if crate::i4::has_avx2() {
+        let q = I4TensorOwned::random(vec![1, 75], 75, 9);
+        let a: Vec<f16> = (0..75)
+            .map(|v| f16::from_f32((v % 11) as f32 / 4f32 - 1f32))
+            .collect();
+        for zero in [-8i8, -1, 0, 5, 7] {
+            let expected = crate::i4::block_dot_scalar(&a, &q.nibbles, zero);
+            let actual = unsafe { crate::i4::block_dot_avx2(&a, &q.nibbles, zero) };
+            assert!((actual - expected).abs() <= 1e-4 * expected.abs().max(1f32));
+        }
+    }
+
+    // a large qgemv, against the scalar kernel block by block
+    let (m, n, block_size) = (64, 4096, 32);
+    let b = I4TensorOwned::random(vec![m, n], block_size, 2);
+    let x: Vec<f16> = (0..n)
+        .map(|v| f16::from_f32((v % 7) as f32 / 8f32))
+        .collect();
+    let y = qgemv(&F16Tensor::new(x.clone(), vec![n]), &b.view());
+    for (row, actual) in y.values.iter().enumerate() {
+        let expected: f32 = (0..n / block_size)
+            .map(|block_in_row| {
+                let block = row * n / block_size + block_in_row;
+                let zero = match block % 2 {
+                    0 => b.zeros[block / 2] >> 4,
+                    _ => (b.zeros[block / 2] << 4) >> 4,
+                };
+                let a_run = &x[block_in_row * block_size..(block_in_row + 1) * block_size];
+                let nibbles = &b.nibbles[block * block_size / 2..];
+                b.scales[block].to_f32() * crate::i4::block_dot_scalar(a_run, nibbles, zero)
+            })
+            .sum();
+        assert!((actual.to_f32() - expected).abs() <= 1e-2 * expected.abs().max(1f32));
+    }
+}
+
+#[test]
+pub fn sgemm_correctness_and_accuracy() {
+    let (m, n, k) = (21, 17, 300);
+    let a_values: Vec<f32> = (0..m * k)
+        .map(|v| ((v % 17) as f32 - 8f32) / 4f32)
+        .collect();
+    let b_values: Vec<f32> = (0..k * n)
+        .map(|v| ((v % 11) as f32 - 5f32) / 8f32)
+        .collect();
+
+    for (a_transpose, b_transpose) in [(false, false), (false, true), (true, false), (true, true)] {
+        let a_shape = match a_transpose {
+            true => [k, m],
+            false => [m, k],
+        };
+        let b_shape = match b_transpose {
+            true => [n, k],
+            false => [k, n],
+        };
+        let a = F32Tensor::new(a_values.clone(), a_shape.to_vec());
+        let b = F32Tensor::new(b_values.clone(), b_shape.to_vec());
+        let expected = gemm_reference(
+            &a_values,
+            a_shape,
+            a_transpose,
+            &b_values,
+            b_shape,
+            b_transpose,
+        );
+
+        for accuracy in [Accuracy::Fast, Accuracy::High] {
+            let mut c = F32Tensor::zeros(vec![m, n]);
+            sgemm_with(
+                &a,
+                a_transpose,
+                &b,
+                b_transpose,
+                GemmParams::default().with_accuracy(accuracy),
+                &mut c,
+            );
+            assert!(c.values == expected);
+        }
+    }
+
+    // 1 + 1e8 - 1e8 cancels the 1 away in f32 but not in f64
+    let a = F32Tensor::new(vec![1f32, 1e8f32, -1e8f32], vec![1, 3]);
+    let b = F32Tensor::new(vec![1f32; 3], vec![3, 1]);
+    let mut c = F32Tensor::zeros(vec![1, 1]);
+    sgemm_with(
+        &a,
+        false,
+        &b,
+        false,
+        GemmParams::default().with_accuracy(Accuracy::High),
+        &mut c,
+    );
+    assert!(c.values[0] == 1f32);
+    sgemm_with(
+        &a,
+        false,
+        &F32Tensor::new(vec![1f32; 3], vec![1, 3]),
+        true,
+        GemmParams::default().with_accuracy(Accuracy::High),
+        &mut c,
+    );
+    assert!(c.values[0] == 1f32);
+    assert!(dsdot(&a.values, &b.values) == 1f64);
+}
+
+#[cfg(test)]
+fn generic_dot<T: Element>(len: usize) -> f64 {
+    let mut x = Tensor::<T>::zeros(vec![len]);
+    let y = Tensor::new(vec![T::ONE; len], vec![len]);
+    for (i, x_val) in x.values.iter_mut().enumerate() {
+        *x_val = T::from_f32((i % 5) as f32 - 2f32);
+    }
+    x.reshape(vec![1, len]);
+
+    T::dot(&x.values, &y.values)
+}
+
+#[test]
+pub fn generic_tensor_element() {
+    // small integers are exact in every element type, so all dispatch paths must agree
+    let expected = (0..37).map(|i| (i % 5) as f64 - 2f64).sum::<f64>();
+    assert!(generic_dot::<f16>(37) == expected);
+    assert!(generic_dot::<bf16>(37) == expected);
+    assert!(generic_dot::<f32>(37) == expected);
+    assert!(generic_dot::<f64>(37) == expected);
+
+    let mut buffer = vec![f32::ONE; 6];
+    let view = TensorMut::new(&mut buffer, vec![2, 3]);
+    assert!(view.values.iter().all(|v| v.to_f64() == 1f64));
+    assert!(F64Tensor::try_new(vec![0f64; 5], vec![2, 3]).is_err());
+}
+
+#[test]
+pub fn sgemm_tile_edges() {
+    // shapes around the 14 x 32 and 6 x 16 tiles, including zero padded partial tiles, with
+    // both operands packed from either layout
+    for (m, n, k) in [
+        (14, 32, 5),
+        (29, 47, 9),
+        (6, 16, 1),
+        (5, 15, 3),
+        (43, 70, 260),
+    ] {
+        let a_values: Vec<f32> = (0..m * k)
+            .map(|v| ((v % 13) as f32 - 6f32) / 2f32)
+            .collect();
+        let b_values: Vec<f32> = (0..k * n).map(|v| ((v % 7) as f32 - 3f32) / 4f32).collect();
+        for (a_transpose, b_transpose) in [(false, false), (true, true)] {
+            let a_shape = match a_transpose {
+                true => [k, m],
+                false => [m, k],
+            };
+            let b_shape = match b_transpose {
+                true => [n, k],
+                false => [k, n],
+            };
+            let a = F32Tensor::new(a_values.clone(), a_shape.to_vec());
+            let b = F32Tensor::new(b_values.clone(), b_shape.to_vec());
+            let mut c = F32Tensor::new(vec![f32::NAN; m * n], vec![m, n]);
+
+            sgemm(&a, a_transpose, &b, b_transpose, &mut c);
+            let expected = gemm_reference(
+                &a_values,
+                a_shape,
+                a_transpose,
+                &b_values,
+                b_shape,
+                b_transpose,
+            );
+            assert!(c.values == expected);
+        }
+    }
+}
+
+#[cfg(all(feature = "amx", target_arch = "x86_64", target_os = "linux"))]
+#[test]
+pub fn amx_tiles_match_scalar() {
+    if !crate::amx::available() {
+        return;
+    }
+
+    // several 16 x 16 output tiles, with partial tiles on every edge including k
+    let (rows, n, k) = (37, 21, 70);
+    let a: Vec<i8> = (0..rows * k).map(|v| (v * 37 % 256) as u8 as i8).collect();
+    let b_cols: Vec<i8> = (0..n * k).map(|v| (v * 91 % 256) as u8 as i8).collect();
+    let mut acc = vec![0i32; rows * n];
+    crate::amx::tile_gemm(&a, &crate::amx::pack_b(&b_cols, n, k), n, k, &mut acc);
+    for (a_i, acc_row) in a.chunks_exact(k).zip(acc.chunks_exact(n)) {
+        for (b_j, acc_val) in b_cols.chunks_exact(k).zip(acc_row) {
+            assert!(*acc_val == crate::igemm::idot_scalar(a_i, b_j));
+        }
+    }
+
+    let a: Vec<bf16> = a.iter().map(|v| bf16::from_f32(*v as f32 / 8f32)).collect();
+    let b_cols: Vec<bf16> = b_cols.iter().map(|v| bf16::from_f32(*v as f32)).collect();
+    let mut acc = vec![0f32; rows * n];
+    crate::amx::tile_gemm(&a, &crate::amx::pack_b(&b_cols, n, k), n, k, &mut acc);
+    for (a_i, acc_row) in a.chunks_exact(k).zip(acc.chunks_exact(n)) {
+        for (b_j, acc_val) in b_cols.chunks_exact(k).zip(acc_row) {
+            assert!(*acc_val == crate::sbgemm::sbdot_scalar(a_i, b_j));
+        }
+    }
+}
+
+/// One tile of a packed panel pair through `kernel`, added to a c with a wider row stride
+#[cfg(all(test, any(target_arch = "x86_64", feature = "generic-kernel")))]
+fn check_microkernel(kernel: crate::microkernel::Microkernel) {
+    let ((mr, nr), kc) = (kernel.tile(), 23);
+    let ldc = nr + 3;
+    let a: Vec<f32> = (0..kc * mr).map(|v| (v % 7) as f32 - 3f32).collect();
+    let b: Vec<f32> = (0..kc * nr).map(|v| (v % 5) as f32).collect();
+    let mut c: Vec<f32> = (0..mr * ldc).map(|v| v as f32).collect();
+    let mut expected = c.clone();
+    for i in 0..mr {
+        for j in 0..nr {
+            expected[i * ldc + j] += (0..kc).map(|p| a[p * mr + i] * b[p * nr + j]).sum::<f32>();
+        }
+    }
+    kernel.run(kc, &a, &b, &mut c, ldc);
+    assert!(c == expected);
+}
+
+#[cfg(feature = "generic-kernel")]
+#[test]
+pub fn generic_microkernel_matches_reference() {
+    check_microkernel(crate::microkernel::Microkernel::Generic);
+}
+
+#[cfg(target_arch = "x86_64")]
+#[test]
+pub fn fma_microkernel_matches_reference() {
+    use crate::microkernel::Microkernel;
+
+    // `detect` prefers AVX-512 where it can, so the 6 x 16 kernel is run by hand
+    if is_x86_feature_detected!("avx2") && is_x86_feature_detected!("fma") {
+        assert!(Microkernel::Fma.tile() == (6, 16));
+        check_microkernel(Microkernel::Fma);
+    }
+    if is_x86_feature_detected!("avx512f") {
+        check_microkernel(Microkernel::Avx512);
+    }
+}
+
+#[cfg(feature = "backend-blas")]
+#[test]
+pub fn blas_backend_matches_aml() {
+    assert!(crate::backend::parse_backend(" BLAS\n") == Some(Backend::Blas));
+    assert!(crate::backend::parse_backend("mkl").is_none());
+
+    // a transposed a and a column-major c, so CBLAS is handed every layout aml takes
+    let (m, n, k) = (13, 9, 21);
+    let a = F32Tensor::new(
+        (0..k * m).map(|v| (v % 7) as f32 - 3f32).collect(),
+        vec![k, m],
+    );
+    let b = F32Tensor::new((0..k * n).map(|v| (v % 5) as f32).collect(), vec![k, n]);
+    let c_values: Vec<f32> = (0..m * n).map(|v| v as f32).collect();
+    let params = GemmParams::new(0.5f32, 2f32);
+    let mut expected = F32Tensor::new(c_values.clone(), vec![m, n]).with_layout(Layout::ColMajor);
+    sgemm_with(&a, true, &b, false, params, &mut expected);
+
+    set_backend(Some(Backend::Blas));
+    assert!(backend() == Backend::Blas);
+    let mut c = F32Tensor::new(c_values, vec![m, n]).with_layout(Layout::ColMajor);
+    sgemm_with(&a, true, &b, false, params, &mut c);
+    let a = F64Tensor::new((0..m * k).map(|v| (v % 3) as f64).collect(), vec![m, k]);
+    let b = F64Tensor::new((0..n * k).map(|v| (v % 4) as f64).collect(), vec![n, k]);
+    let mut d = F64Tensor::zeros(vec![m, n]);
+    dgemm(&a, false, &b, true, &mut d);
+    set_backend(None);
+
+    assert!(c.values == expected.values);
+    let mut d_expected = F64Tensor::zeros(vec![m, n]);
+    dgemm(&a, false, &b, true, &mut d_expected);
+    assert!(d.values == d_expected.values);
+}
+
+#[test]
+pub fn dispatched_kernels_match_scalar() {
+    let best = crate::dispatch::kernels();
+    let scalar = crate::dispatch::Kernels::scalar();
+
+    // small integers keep every sum exact, whatever the order
+    let x: Vec<f32> = (0..77).map(|v| (v % 9) as f32 - 4f32).collect();
+    let y: Vec<f32> = (0..77).map(|v| (v % 5) as f32 - 2f32).collect();
+    assert!((best.sdot)(&x, &y) == (scalar.sdot)(&x, &y));
+    assert!((best.dsdot)(&x, &y) == (scalar.dsdot)(&x, &y));
+    assert!((best.sasum)(&x) == (scalar.sasum)(&x));
+    let (mut y_best, mut y_scalar) = (y.clone(), y.clone());
+    (best.saxpy)(3f32, &x, &mut y_best);
+    (scalar.saxpy)(3f32, &x, &mut y_scalar);
+    assert!(y_best == y_scalar);
+
+    let x16: Vec<f16> = x.iter().map(|v| f16::from_f32(*v)).collect();
+    let y16: Vec<f16> = y.iter().map(|v| f16::from_f32(*v)).collect();
+    assert!((best.hdot)(&x16, &y16) == (scalar.hdot)(&x16, &y16));
+    let xb: Vec<bf16> = x.iter().map(|v| bf16::from_f32(*v)).collect();
+    let yb: Vec<bf16> = y.iter().map(|v| bf16::from_f32(*v)).collect();
+    assert!((best.sbdot)(&xb, &yb) == (scalar.sbdot)(&xb, &yb));
+    let xi: Vec<i8> = x.iter().map(|v| *v as i8).collect();
+    let yi: Vec<i8> = y.iter().map(|v| *v as i8).collect();
+    assert!((best.idot)(&xi, &yi) == (scalar.idot)(&xi, &yi));
+}
+
+#[test]
+pub fn cache_blocking_override() {
+    let cache = cache_info();
+    assert!(cache.l1d > 0 && cache.l2 >= cache.l1d && cache.l3 > 0);
+    let derived = BlockSizes::for_cache(&CacheInfo::FALLBACK);
+    assert!(derived.kc == 256 && derived.mc == 128 && derived.nc == 4096);
+
+    assert!(try_set_block_sizes(Some(BlockSizes {
+        mc: 0,
+        kc: 4,
+        nc: 4
+    }))
+    .is_err());
+
+    // blocks smaller than a register tile still produce the right answer
+    let (m, n, k) = (31, 45, 23);
+    let a_values: Vec<f32> = (0..m * k).map(|v| (v % 7) as f32 - 3f32).collect();
+    let b_values: Vec<f32> = (0..k * n).map(|v| (v % 5) as f32 - 2f32).collect();
+    let a = F32Tensor::new(a_values.clone(), vec![m, k]);
+    let b = F32Tensor::new(b_values.clone(), vec![k, n]);
+    let expected = gemm_reference(&a_values, [m, k], false, &b_values, [k, n], false);
+
+    let detected = block_sizes();
+    set_block_sizes(Some(BlockSizes {
+        mc: 1,
+        kc: 3,
+        nc: 5,
+    }));
+    assert!(block_sizes().kc == 3);
+    let mut c = F32Tensor::zeros(vec![m, n]);
+    sgemm(&a, false, &b, false, &mut c);
+    set_block_sizes(None);
+
+    assert!(c.values == expected);
+    assert!(block_sizes() == detected);
+}
+
+#[test]
+pub fn prepacked_sgemm_matches_sgemm() {
+    let (n, k) = (37, 300);
+    let w_values: Vec<f32> = (0..k * n)
+        .map(|v| ((v % 11) as f32 - 5f32) / 8f32)
+        .collect();
+    let w = F32Tensor::new(w_values, vec![n, k]);
+    let packed = pack_b(&w, true);
+    assert!(packed.shape() == [k, n]);
+
+    // the same packed weights against several inputs, each matching packing per call
+    for m in [1, 6, 29] {
+        let a_values: Vec<f32> = (0..m * k).map(|v| ((v % 9) as f32 - 4f32) / 3f32).collect();
+        let a = F32Tensor::new(a_values, vec![m, k]);
+        for params in [
+            GemmParams::new(0.5f32, 0f32),
+            GemmParams::new(1f32, 2f32).with_accuracy(Accuracy::High),
+        ] {
+            let mut expected = F32Tensor::new(vec![1f32; m * n], vec![m, n]);
+            sgemm_with(&a, false, &w, true, params, &mut expected);
+            let mut c = F32Tensor::new(vec![1f32; m * n], vec![m, n]);
+            sgemm_prepacked_with(&a, false, &packed, params, &mut c);
+            // exact with a microkernel; the BLAS1 fallback may sum in another order
+            let close = |(c, e): (&f32, &f32)| (c - e).abs() <= 1e-5 * e.abs().max(1f32);
+            assert!(c.values.iter().zip(&expected.values).all(close));
+        }
+    }
+
+    let a = F32Tensor::zeros(vec![2, k + 1]);
+    let mut c = F32Tensor::zeros(vec![2, n]);
+    assert!(try_sgemm_prepacked(&a, false, &packed, &mut c).is_err());
+    assert!(try_pack_b(&F32Tensor::zeros(vec![k]), false).is_err());
+}
+
+#[test]
+pub fn packed_b_bytes_round_trip() {
+    let (k, n) = (40, 21);
+    let b_values: Vec<f32> = (0..k * n).map(|v| v as f32 / 7f32).collect();
+    let b = F32Tensor::new(b_values, vec![k, n]);
+    let packed = pack_b(&b, false);
+    assert!(packed.is_native());
+
+    let bytes = packed.to_bytes();
+    let loaded = PackedB::from_bytes(&bytes);
+    assert!(loaded.shape() == [k, n] && loaded.isa() == packed.isa() && loaded.is_native());
+
+    let a = F32Tensor::new((0..3 * k).map(|v| v as f32).collect(), vec![3, k]);
+    let mut expected = F32Tensor::zeros(vec![3, n]);
+    sgemm(&a, false, &b, false, &mut expected);
+    let mut c = F32Tensor::zeros(vec![3, n]);
+    sgemm_prepacked(&a, false, &loaded, &mut c);
+    assert!(c.values == expected.values);
+
+    // a packing for another ISA still multiplies correctly
+    let mut foreign = bytes.clone();
+    let isa = match &foreign[12..18] == b"avx512" {
+        true => b"fma\0\0\0\0\0",
+        false => b"avx512\0\0",
+    };
+    foreign[12..20].copy_from_slice(isa);
+    let foreign = PackedB::from_bytes(&foreign);
+    assert!(!foreign.is_native());
+    sgemm_prepacked(&a, false, &foreign, &mut c);
+    assert!(c.values == expected.values);
+
+    assert!(PackedB::try_from_bytes(&bytes[..bytes.len() - 1]).is_err());
+    assert!(PackedB::try_from_bytes(&bytes[1..]).is_err());
+    let mut unknown = bytes.clone();
+    unknown[12] = b'x';
+    assert!(PackedB::try_from_bytes(&unknown).is_err());
+}
+
+#[test]
+pub fn fused_bias_matches_separate_pass() {
+    // tall shapes split by rows and a flat one split over a grid, with either accuracy
+    for (m, n, k) in [(301, 41, 67), (4, 520, 256)] {
+        let a = F32Tensor::new(
+            (0..m * k).map(|v| (v % 7) as f32 - 3f32).collect(),
+            vec![m, k],
+        );
+        let b = F32Tensor::new((0..n * k).map(|v| (v % 5) as f32).collect(), vec![n, k]);
+        let bias: Vec<f32> = (0..n).map(|v| v as f32 * 0.5f32).collect();
+        let c_values: Vec<f32> = (0..m * n).map(|v| (v % 3) as f32).collect();
+        for accuracy in [Accuracy::Fast, Accuracy::High] {
+            let params = GemmParams::new(2f32, -1f32).with_accuracy(accuracy);
+            let mut expected = F32Tensor::new(c_values.clone(), vec![m, n]);
+            sgemm_with(&a, false, &b, true, params, &mut expected);
+            for (idx, value) in expected.values.iter_mut().enumerate() {
+                *value += bias[idx % n];
+            }
+
+            let mut c = F32Tensor::new(c_values.clone(), vec![m, n]);
+            AmlContext::new(4).install(|| {
+                let epilogue = Epilogue::new().with_bias(&bias);
+                sgemm_fused(&a, false, &b, true, params, epilogue, &mut c)
+            });
+            assert!(c.values == expected.values);
+        }
+    }
+
+    let a = F32Tensor::zeros(vec![4, 3]);
+    let b = F32Tensor::zeros(vec![3, 5]);
+    let epilogue = Epilogue::new().with_bias(&[1f32; 4]);
+    let mut c = F32Tensor::zeros(vec![4, 5]);
+    assert!(matches!(
+        try_sgemm_fused(
+            &a,
+            false,
+            &b,
+            false,
+            GemmParams::default(),
+            epilogue,
+            &mut c
+        ),
+        Err(AmlError::SizeMismatch {
+            expected: 5,
+            found: 4
+        })
+    ));
+    let epilogue = Epilogue::new().with_bias(&[1f32; 5]);
+    let mut c = F32Tensor::zeros(vec![4, 5]).with_layout(Layout::ColMajor);
+    assert!(matches!(
+        try_sgemm_fused(
+            &a,
+            false,
+            &b,
+            false,
+            GemmParams::default(),
+            epilogue,
+            &mut c
+        ),
+        Err(AmlError::UnsupportedLayout { operand: "c" })
+    ));
+    assert!(Epilogue::new().is_empty() && !epilogue.is_empty());
+}
+
+#[test]
+pub fn fused_activations() {
+    assert!(Activation::Relu.apply(-2f32) == 0f32 && Activation::Relu.apply(3f32) == 3f32);
+    assert!((Activation::Gelu.apply(1f32) - 0.841192).abs() < 1e-5);
+    assert!((Activation::Gelu.apply(-3f32) + 0.003637).abs() < 1e-5);
+    assert!((Activation::Silu.apply(1f32) - 0.731059).abs() < 1e-5);
+    assert!(Activation::Silu.apply(-100f32) == 0f32);
+
+    let (m, n, k) = (37, 19, 23);
+    let a = F32Tensor::new(
+        (0..m * k).map(|v| (v % 7) as f32 - 3f32).collect(),
+        vec![m, k],
+    );
+    let b = F32Tensor::new(
+        (0..k * n).map(|v| (v % 5) as f32 - 2f32).collect(),
+        vec![k, n],
+    );
+    let bias: Vec<f32> = (0..n).map(|v| v as f32 * 0.25f32 - 2f32).collect();
+    let mut product = F32Tensor::zeros(vec![m, n]);
+    sgemm(&a, false, &b, false, &mut product);
+    for activation in [
+        Activation::None,
+        Activation::Relu,
+        Activation::Gelu,
+        Activation::Silu,
+    ] {
+        let epilogue = Epilogue::new().with_bias(&bias).with_activation(activation);
+        let mut c = F32Tensor::zeros(vec![m, n]);
+        sgemm_fused(
+            &a,
+            false,
+            &b,
+            false,
+            GemmParams::default(),
+            epilogue,
+            &mut c,
+        );
+        let expected = product
+            .values
+            .iter()
+            .enumerate()
+            .map(|(idx, v)| activation.apply(v + bias[idx % n]));
+        assert!(c.values.iter().copied().eq(expected));
+
+        // an activation alone is an epilogue too, and the lazy graph folds it in
+        let epilogue = Epilogue::new().with_activation(activation);
+        assert!(epilogue.is_empty() == (activation == Activation::None));
+        let lazy = Expr::new(&a)
+            .matmul(Expr::new(&b))
+            .activation(activation)
+            .eval();
+        let expected = product.values.iter().map(|v| activation.apply(*v));
+        assert!(lazy.values.iter().copied().eq(expected));
+    }
+}
+
+#[test]
+pub fn lazy_expressions_fuse() {
+    let (m, n, k) = (9, 6, 11);
+    let x_values: Vec<f32> = (0..m * k).map(|v| (v % 7) as f32 - 3f32).collect();
+    let w_values: Vec<f32> = (0..k * n).map(|v| (v % 5) as f32 - 2f32).collect();
+    let x = F32Tensor::new(x_values.clone(), vec![m, k]);
+    let w = F32Tensor::new(w_values.clone(), vec![k, n]);
+    let bias = F32Tensor::new((0..n).map(|v| v as f32 - 2f32).collect(), vec![n]);
+    let residual = F32Tensor::new((0..m * n).map(|v| (v % 4) as f32).collect(), vec![m, n]);
+    let product = gemm_reference(&x_values, [m, k], false, &w_values, [k, n], false);
+
+    // the residual is folded into the GEMM, the bias and relu applied after it
+    let y = ((Expr::new(&x).matmul(Expr::new(&w)) + Expr::new(&residual)) + Expr::new(&bias))
+        .relu()
+        .eval();
+    let expected: Vec<f32> = (0..m * n)
+        .map(|idx| (product[idx] + residual.values[idx] + bias.values[idx % n]).max(0f32))
+        .collect();
+    assert!(y.shape == [m, n] && y.values == expected);
+
+    // a column-major weight, a [1, n] bias and a second layer on top
+    let w_col = F32Tensor::new(w_values, vec![k, n]).with_layout(Layout::ColMajor);
+    let bias_row = F32Tensor::new(bias.values.clone(), vec![1, n]);
+    let w2 = F32Tensor::eye(n);
+    let z = (Expr::new(&x).matmul(Expr::new(&w_col)) + Expr::new(&bias_row))
+        .matmul(Expr::new(&w2))
+        .eval();
+    let w_col_values: Vec<f32> = (0..k * n).map(|idx| w_col[[idx / n, idx % n]]).collect();
+    let product = gemm_reference(&x_values, [m, k], false, &w_col_values, [k, n], false);
+    let expected: Vec<f32> = (0..m * n)
+        .map(|idx| product[idx] + bias.values[idx % n])
+        .collect();
+    assert!(z.values == expected);
+
+    assert!(Expr::new(&x).eval().values == x.values);
+    assert!(matches!(
+        (Expr::new(&x).matmul(Expr::new(&w)) + Expr::new(&x)).try_eval(),
+        Err(AmlError::BroadcastMismatch { .. })
+    ));
+    assert!(matches!(
+        Expr::new(&x).matmul(Expr::new(&x)).try_eval(),
+        Err(AmlError::InnerDimMismatch { .. })
+    ));
+}
+
+#[test]
+pub fn sgemm_reports_progress() {
+    // more rows than two macro-tiles, and a transposed a whose row blocks are column windows
+    let rows = block_sizes().mc.max(1);
+    let (m, n, k) = (2 * rows + 3, 11, 19);
+    let a_values: Vec<f32> = (0..k * m).map(|v| (v % 7) as f32 - 3f32).collect();
+    let b_values: Vec<f32> = (0..k * n).map(|v| (v % 5) as f32).collect();
+    let a = F32Tensor::new(a_values.clone(), vec![k, m]);
+    let b = F32Tensor::new(b_values.clone(), vec![k, n]);
+    let expected = gemm_reference(&a_values, [k, m], true, &b_values, [k, n], false);
+    for threads in [1, 2] {
+        let mut c = F32Tensor::new(vec![f32::NAN; m * n], vec![m, n]);
+        let mut calls = Vec::new();
+        AmlContext::new(threads).install(|| {
+            sgemm_with_progress(
+                &a,
+                true,
+                &b,
+                false,
+                GemmParams::default(),
+                &mut c,
+                |done, total| calls.push((done, total)),
+            )
+        });
+        // growing counts of one total, ending on it; three macro-tiles at least on one thread
+        let total = m * n * k.div_ceil(block_sizes().kc);
+        assert!(calls.windows(2).all(|pair| pair[0].0 < pair[1].0));
+        assert!(calls.iter().all(|call| call.1 == total) && calls.last() == Some(&(total, total)));
+        assert!(threads > 1 || calls.len() >= 3);
+        assert!(c.values == expected);
+    }
+
+    // a shape error touches nothing and reports nothing
+    let mut c = F32Tensor::zeros(vec![m, n + 1]);
+    let result = try_sgemm_with_progress(
+        &a,
+        true,
+        &b,
+        false,
+        GemmParams::default(),
+        &mut c,
+        |_, _| panic!("no progress on error"),
+    );
+    assert!(result.is_err() && c.values.iter().all(|v| *v == 0f32));
+
+    let mut calls = 0;
+    let mut c = F32Tensor::zeros(vec![0, n]);
+    let a = F32Tensor::zeros(vec![0, k]);
+    sgemm_with_progress(
+        &a,
+        false,
+        &b,
+        false,
+        GemmParams::default(),
+        &mut c,
+        |_, _| calls += 1,
+    );
+    assert!(calls == 1);
+}
+
+#[test]
+pub fn cancelled_sgemm_stops_between_tiles() {
+    let rows = block_sizes().mc.max(1);
+    let (m, n, k) = (3 * rows + 1, 7, 13);
+    let a_values: Vec<f32> = (0..m * k).map(|v| (v % 7) as f32 - 3f32).collect();
+    let b_values: Vec<f32> = (0..k * n).map(|v| (v % 5) as f32).collect();
+    let a = F32Tensor::new(a_values.clone(), vec![m, k]);
+    let b = F32Tensor::new(b_values.clone(), vec![k, n]);
+    let expected = gemm_reference(&a_values, [m, k], false, &b_values, [k, n], false);
+    let params = GemmParams::default();
+
+    let cancel = CancelToken::new();
+    for threads in [1, 3] {
+        let mut c = F32Tensor::zeros(vec![m, n]);
+        AmlContext::new(threads)
+            .install(|| sgemm_cancellable(&a, false, &b, false, params, &mut c, &cancel))
+            .unwrap();
+        assert!(c.values == expected);
+    }
+    // a column-major c is written in place too, scaled in with beta
+    let mut c = F32Tensor::new(vec![1f32; m * n], vec![m, n]).with_layout(Layout::ColMajor);
+    let scaled = GemmParams::new(1f32, 2f32);
+    sgemm_cancellable(&a, false, &b, false, scaled, &mut c, &cancel).unwrap();
+    assert!((0..m * n).all(|idx| c[[idx / n, idx % n]] == expected[idx] + 2f32));
+
+    // cancelled as the first macro-tile, or block of rows without the microkernel, ends: the
+    // rows finished are written and the rest untouched
+    let rows = match crate::dispatch::kernels().sgemm {
+        Some(_) => 0,
+        None => rows,
+    };
+    let mut c = F32Tensor::new(vec![f32::NAN; m * n], vec![m, n]);
+    let result = AmlContext::new(1).install(|| {
+        crate::sgemm::sgemm_watched(
+            &a,
+            false,
+            &b,
+            false,
+            params,
+            &mut c,
+            Some(&cancel),
+            &mut |_, _| cancel.cancel(),
+        )
+    });
+    assert!(result == Err(AmlError::Cancelled { rows }));
+    assert!(c.values[..rows * n] == expected[..rows * n]);
+    assert!(c.values[rows * n..].iter().all(|v| v.is_nan()));
+
+    // a token cancelled beforehand stops the call before it writes anything
+    let mut c = F32Tensor::new(vec![f32::NAN; m * n], vec![m, n]);
+    let result = sgemm_cancellable(&a, false, &b, false, params, &mut c, &cancel.clone());
+    assert!(result == Err(AmlError::Cancelled { rows: 0 }));
+    assert!(c.values.iter().all(|v| v.is_nan()));
+}
+
+#[test]
+pub fn async_sgemm_completes() {
+    use std::future::Future;
+    use std::pin::pin;
+    use std::task::{Context, Poll, Waker};
+
+    let (m, n, k) = (31, 17, 45);
+    let a_values: Vec<f32> = (0..m * k).map(|v| (v % 7) as f32 - 3f32).collect();
+    let b_values: Vec<f32> = (0..k * n).map(|v| (v % 5) as f32).collect();
+    let a = SharedTensor::new(a_values.clone(), vec![m, k]);
+    let expected = gemm_reference(&a_values, [m, k], false, &b_values, [k, n], false);
+
+    // waited on, under the context it was started in
+    let b = F32Tensor::new(b_values.clone(), vec![k, n]);
+    let handle = AmlContext::new(2).install(|| {
+        sgemm_async(
+            a.clone(),
+            false,
+            b,
+            false,
+            GemmParams::default(),
+            F32Tensor::zeros(vec![m, n]),
+        )
+    });
+    assert!(handle.wait().unwrap().values == expected);
+
+    // polled as a future, with no runtime
+    let b = F32Tensor::new(b_values, vec![k, n]);
+    let c = F32Tensor::zeros(vec![m, n]);
+    let mut handle = pin!(sgemm_async(
+        a.clone(),
+        false,
+        b,
+        false,
+        GemmParams::default(),
+        c
+    ));
+    let mut cx = Context::from_waker(Waker::noop());
+    let c = loop {
+        if let Poll::Ready(c) = handle.as_mut().poll(&mut cx) {
+            break c.unwrap();
+        }
+        std::thread::yield_now();
+    };
+    assert!(c.values == expected);
+
+    // errors come back from the handle
+    let b = F32Tensor::zeros(vec![k + 1, n]);
+    let c = F32Tensor::zeros(vec![m, n]);
+    let mut handle = sgemm_async(a, false, b, false, GemmParams::default(), c);
+    while !handle.is_finished() {
+        std::thread::yield_now();
+    }
+    assert!(matches!(
+        handle.try_wait(),
+        Some(Err(AmlError::InnerDimMismatch { .. }))
+    ));
+}
+
+#[test]
+pub fn autotune_decisions_persist() {
+    let (m, n, k) = (24, 40, 33);
+    let config = autotune(m, n, k);
+    assert!(config.threads >= 1 && config.block_sizes.kc > 0);
+    assert!(autotune(m, n, k) == config);
+
+    // a tuned config computes the same product
+    let a_values: Vec<f32> = (0..m * k).map(|v| (v % 5) as f32).collect();
+    let b_values: Vec<f32> = (0..k * n).map(|v| (v % 3) as f32 - 1f32).collect();
+    let a = F32Tensor::new(a_values.clone(), vec![m, k]);
+    let b = F32Tensor::new(b_values.clone(), vec![k, n]);
+    let mut c = F32Tensor::zeros(vec![m, n]);
+    crate::sgemm::sgemm_kernel(
+        &a.as_tensor_ref(),
+        false,
+        crate::sgemm::OpB::Tensor(&b.as_tensor_ref(), false),
+        GemmParams::default(),
+        Some(config),
+        crate::dispatch::kernels(),
+        &mut c.view_mut(),
+    );
+    assert!(c.values == gemm_reference(&a_values, [m, k], false, &b_values, [k, n], false));
+
+    // lines from another CPU model survive a save and are skipped on load
+    let path = std::env::temp_dir().join(format!("aml-autotune-{}.tsv", std::process::id()));
+    std::fs::write(&path, "Some Other CPU\t1\t2\t3\t4\t5\t6\t7\n").unwrap();
+    save_autotune(&path).unwrap();
+    let text = std::fs::read_to_string(&path).unwrap();
+    assert!(text.starts_with("Some Other CPU\t"));
+    assert!(load_autotune(&path).unwrap() == text.lines().count() - 1);
+    assert!(autotune(m, n, k) == config);
+
+    std::fs::write(
+        &path,
+        format!("{}\t1\t2\t3\n", crate::autotune::cpu_model()),
+    )
+    .unwrap();
+    assert!(load_autotune(&path).is_err());
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+pub fn gemm_builder_settings() {
+    let (m, n, k) = (19, 23, 70);
+    let a_values: Vec<f32> = (0..m * k).map(|v| (v % 7) as f32 - 3f32).collect();
+    let b_values: Vec<f32> = (0..k * n).map(|v| (v % 4) as f32).collect();
+    let a = F32Tensor::new(a_values.clone(), vec![k, m]);
+    let b = F32Tensor::new(b_values.clone(), vec![k, n]);
+    let expected = gemm_reference(&a_values, [k, m], true, &b_values, [k, n], false);
+
+    let small_blocks = BlockSizes {
+        mc: 5,
+        kc: 8,
+        nc: 9,
+    };
+    for gemm in [
+        Gemm::builder().a_transpose(true),
+        Gemm::builder().a_transpose(true).simd(false).threads(1),
+        Gemm::builder().a_transpose(true).block_sizes(small_blocks),
+        Gemm::builder()
+            .a_transpose(true)
+            .accuracy(Accuracy::High)
+            .threads(3),
+    ] {
+        let mut c = F32Tensor::new(vec![f32::NAN; m * n], vec![m, n]);
+        gemm.run(&a, &b, &mut c);
+        assert!(c.values == expected);
+    }
+
+    // alpha, beta and a prepacked b
+    let packed = pack_b(&b, false);
+    let mut c = F32Tensor::new(vec![1f32; m * n], vec![m, n]);
+    let gemm = Gemm::builder().a_transpose(true).alpha(2f32).beta(-1f32);
+    gemm.run_prepacked(&a, &packed, &mut c);
+    assert!(c
+        .values
+        .iter()
+        .zip(&expected)
+        .all(|(c, e)| *c == 2f32 * e - 1f32));
+
+    let zero_blocks = BlockSizes {
+        mc: 4,
+        kc: 0,
+        nc: 4,
+    };
+    let gemm = Gemm::builder().a_transpose(true).block_sizes(zero_blocks);
+    assert!(gemm.try_run(&a, &b, &mut c).is_err());
+}
+
+#[test]
+pub fn environment_settings_parse() {
+    assert!(crate::parallel::parse_threads(" 4 ") == Some(4));
+    assert!(crate::parallel::parse_threads("0").is_none());
+    assert!(crate::parallel::parse_threads("many").is_none());
+
+    let sizes = crate::blocking::parse_block_sizes("128, 256,4096");
+    assert!(
+        sizes
+            == Some(BlockSizes {
+                mc: 128,
+                kc: 256,
+                nc: 4096
+            })
+    );
+    assert!(crate::blocking::parse_block_sizes("128,256").is_none());
+    assert!(crate::blocking::parse_block_sizes("128,0,4096").is_none());
+
+    assert!(crate::dispatch::parse_flag("1") && crate::dispatch::parse_flag("true"));
+    assert!(!crate::dispatch::parse_flag("0") && !crate::dispatch::parse_flag(""));
+}
+
+#[cfg(feature = "parallel")]
+#[test]
+pub fn context_limits_threads() {
+    use crate::parallel::num_threads;
+
+    let default = num_threads();
+    let outer = AmlContext::new(3);
+    let inner = AmlContext::new(0);
+    assert!(inner.threads() == 1);
+
+    outer.install(|| {
+        assert!(num_threads() == 3);
+        inner.install(|| assert!(num_threads() == 1));
+        assert!(num_threads() == 3);
+
+        // other threads are not affected
+        std::thread::scope(|scope| {
+            scope.spawn(|| assert!(num_threads() == default));
+        });
+    });
+    let caught = std::panic::catch_unwind(|| inner.install(|| panic!("kernel failed")));
+    assert!(caught.is_err() && num_threads() == default);
+
+    // a single threaded context runs the kernel on the calling thread
+    let caller = std::thread::current().id();
+    let mut rows = vec![0usize; 1 << 12];
+    AmlContext::new(1).install(|| {
+        crate::parallel::for_each_row_chunk(&mut rows, 1, 1 << 12, |_, chunk| {
+            assert!(std::thread::current().id() == caller);
+            chunk.fill(1);
+        })
+    });
+    assert!(rows.iter().all(|v| *v == 1));
+
+    // threads a kernel spawns keep the caller's context, and those spawned outside it do not
+    let context = AmlContext::new(2).deterministic(true);
+    let mut seen = vec![None; 4];
+    context.install(|| {
+        crate::parallel::spawn_chunks(&mut seen, 1, |_, seen| {
+            seen[0] = crate::parallel::installed();
+        })
+    });
+    assert!(seen.iter().all(|seen| *seen == Some(context)));
+    crate::parallel::spawn_chunks(&mut seen, 1, |_, seen| {
+        seen[0] = crate::parallel::installed();
+    });
+    assert!(seen.iter().all(|seen| seen.is_none()));
+}
+
+#[test]
+pub fn grid_partitioning() {
+    use crate::parallel::grid;
+
+    // tall outputs split by rows, flat ones by columns, square ones both ways
+    assert!(grid(8, 4096, 16, 1, 512) == (8, 1));
+    assert!(grid(8, 4, 4096, 1, 512) == (1, 8));
+    assert!(grid(4, 1024, 1024, 1, 512) == (2, 2));
+    assert!(grid(8, 4, 4, 1, 4) == (1, 1));
+    // column blocks are whole strips, leaving the other threads to rows
+    assert!(grid(8, 64, 96, 32, 512) == (4, 2));
+
+    // a flat sgemm on more threads than rows matches the reference; it is small enough to run
+    // serially unless the threshold is lowered
+    let (m, n, k) = (3, 301, 200);
+    assert!(m * n * k < crate::parallel::SERIAL_THRESHOLD);
+    let a_values: Vec<f32> = (0..m * k).map(|v| (v % 7) as f32 - 3f32).collect();
+    let b_values: Vec<f32> = (0..k * n).map(|v| (v % 5) as f32 - 2f32).collect();
+    let a = F32Tensor::new(a_values.clone(), vec![m, k]);
+    let b = F32Tensor::new(b_values.clone(), vec![k, n]);
+    let expected = gemm_reference(&a_values, [m, k], false, &b_values, [k, n], false);
+
+    let mut c = F32Tensor::new(vec![1f32; m * n], vec![m, n]);
+    Gemm::builder()
+        .threads(8)
+        .serial_threshold(0)
+        .beta(1f32)
+        .run(&a, &b, &mut c);
+    assert!(c.values.iter().zip(&expected).all(|(c, e)| *c == e + 1f32));
+}
+
+#[cfg(feature = "parallel")]
+#[test]
+pub fn pinned_threads() {
+    use crate::affinity::{parse_cpu_list, physical_cores, pin_current_thread};
+
+    assert!(parse_cpu_list("0-3,8,10-11\n") == vec![0, 1, 2, 3, 8, 10, 11]);
+    assert!(parse_cpu_list("").is_empty());
+
+    if cfg!(target_os = "linux") {
+        let cores = physical_cores();
+        assert!(!cores.is_empty());
+        let first = cores[0];
+        assert!(std::thread::spawn(move || pin_current_thread(first))
+            .join()
+            .unwrap());
+    }
+
+    let (m, n, k) = (64, 40, 300);
+    let a_values: Vec<f32> = (0..m * k).map(|v| (v % 3) as f32).collect();
+    let b_values: Vec<f32> = (0..k * n).map(|v| (v % 5) as f32 - 2f32).collect();
+    let a = F32Tensor::new(a_values.clone(), vec![m, k]);
+    let b = F32Tensor::new(b_values.clone(), vec![k, n]);
+    let mut c = F32Tensor::zeros(vec![m, n]);
+    Gemm::builder()
+        .threads(4)
+        .pin_threads(true)
+        .run(&a, &b, &mut c);
+    assert!(c.values == gemm_reference(&a_values, [m, k], false, &b_values, [k, n], false));
+}
+
+#[cfg(feature = "parallel")]
+#[test]
+pub fn numa_split_matches_reference() {
+    use crate::sgemm::{sgemm_numa, ABlock};
+
+    if cfg!(target_os = "linux") {
+        assert!(!crate::affinity::numa_nodes().is_empty());
+    }
+
+    // two pretend nodes sharing cpu 0, each with its own copy of b
+    let Some(kernel) = crate::dispatch::kernels().sgemm else {
+        return;
+    };
+    let (m, n, k) = (45, 50, 130);
+    let a_values: Vec<f32> = (0..m * k).map(|v| (v % 7) as f32 - 3f32).collect();
+    let b_values: Vec<f32> = (0..k * n).map(|v| (v % 4) as f32).collect();
+    let packed = crate::sgemm::PackedB::pack(
+        &b_values,
+        false,
+        n,
+        k,
+        n,
+        64,
+        (kernel.tile().1, kernel.name()),
+    );
+    let a_block = ABlock {
+        values: &a_values,
+        a_transpose: false,
+        m,
+        ld: k,
+        first_row: 0,
+    };
+    let config = TuneConfig {
+        block_sizes: block_sizes(),
+        threads: 4,
+    };
+    let mut c = vec![f32::NAN; m * n];
+    let nodes = [vec![0], vec![0]];
+    sgemm_numa(
+        kernel,
+        a_block,
+        &packed,
+        config,
+        &nodes,
+        (GemmParams::default(), Epilogue::default()),
+        &mut TensorMut::new(&mut c, vec![m, n]),
+    );
+    assert!(c == gemm_reference(&a_values, [m, k], false, &b_values, [k, n], false));
+
+    // the policy reaches sgemm through the context, a no-op on one node
+    let a = F32Tensor::new(a_values.clone(), vec![m, k]);
+    let b = F32Tensor::new(b_values.clone(), vec![k, n]);
+    let mut c = F32Tensor::zeros(vec![m, n]);
+    AmlContext::new(2)
+        .numa(true)
+        .install(|| sgemm(&a, false, &b, false, &mut c));
+    assert!(c.values == gemm_reference(&a_values, [m, k], false, &b_values, [k, n], false));
+}
+
+#[test]
+pub fn packed_panels_and_macro_kernel_blocks() {
+    use crate::sgemm::PackedB;
+
+    // kc deep panels of nr wide strips, each strip kc rows of nr values, zero past column n
+    let (k, n, kc, nr) = (5, 7, 2, 4);
+    let b: Vec<f32> = (0..k * n).map(|v| v as f32 + 1f32).collect();
+    let b_t: Vec<f32> = (0..n * k).map(|v| b[(v % k) * n + v / k]).collect();
+    let packed = PackedB::pack(&b, false, n, k, n, kc, (nr, "blas1"));
+    assert!(packed.values == PackedB::pack(&b_t, true, k, k, n, kc, (nr, "blas1")).values);
+    assert!(packed.values.len() == k * 8);
+    let mut expected = vec![];
+    for p0 in (0..k).step_by(kc) {
+        for j0 in (0..n).step_by(nr) {
+            for p in p0..(p0 + kc).min(k) {
+                expected.extend((j0..j0 + nr).map(|j| match j < n {
+                    true => b[p * n + j],
+                    false => 0f32,
+                }));
+            }
+        }
+    }
+    assert!(packed.values == expected);
+
+    // blocks smaller than the tiles and shapes that cut every loop short, so each level of the
+    // macro-kernel runs several times and ends on a partial block
+    let (m, n, k) = (23, 37, 11);
+    let a_values: Vec<f32> = (0..m * k).map(|v| (v % 7) as f32 - 3f32).collect();
+    let b_values: Vec<f32> = (0..k * n).map(|v| (v % 5) as f32 - 2f32).collect();
+    let a = F32Tensor::new(a_values.clone(), vec![m, k]);
+    let b = F32Tensor::new(b_values.clone(), vec![k, n]);
+    let expected = gemm_reference(&a_values, [m, k], false, &b_values, [k, n], false);
+    for (mc, kc, nc) in [(1, 1, 1), (5, 3, 7), (20, 4, 40), (64, 64, 64)] {
+        let mut c = F32Tensor::new(vec![f32::NAN; m * n], vec![m, n]);
+        Gemm::builder()
+            .threads(1)
+            .block_sizes(BlockSizes { mc, kc, nc })
+            .run(&a, &b, &mut c);
+        assert!(c.values == expected);
+    }
+}
+
+#[test]
+pub fn parallel_sgemm_writes_disjoint_rows() {
+    use std::sync::Mutex;
+
+    // each call gets whole rows of its own, and between them every row exactly once
+    let (rows, row_len) = (37, 5);
+    let mut out = vec![usize::MAX; rows * row_len - 2];
+    let chunks = Mutex::new(vec![]);
+    crate::parallel::for_each_row_chunk_with(4, &mut out, row_len, 1 << 20, |first_row, chunk| {
+        chunk.fill(first_row);
+        chunks.lock().unwrap().push((first_row, chunk.len()));
+    });
+    let mut chunks = chunks.into_inner().unwrap();
+    chunks.sort();
+    assert!(cfg!(not(feature = "parallel")) || chunks.len() == 4);
+    let mut next = 0;
+    for (first_row, len) in chunks {
+        assert!(first_row * row_len == next);
+        assert!(out[next..next + len].iter().all(|v| *v == first_row));
+        next += len;
+    }
+    assert!(next == out.len());
+
+    // threads write c through its rows only, leaving the padding between them alone
+    let (m, n, k, ld) = (61, 45, 70, 50);
+    let a = F32Tensor::new((0..m * k).map(|v| (v % 7) as f32).collect(), vec![m, k]);
+    let b = F32Tensor::new((0..k * n).map(|v| (v % 3) as f32).collect(), vec![k, n]);
+    let run = |threads: usize| {
+        let mut values = vec![-1f32; m * ld];
+        let window = &mut values[..(m - 1) * ld + n];
+        let mut c = TensorMut::new_with_ld(window, vec![m, n], Layout::RowMajor, ld);
+        Gemm::builder()
+            .threads(threads)
+            .serial_threshold(0)
+            .run(&a, &b, &mut c);
+        values
+    };
+    let serial = run(1);
+    assert!(serial
+        .chunks(ld)
+        .all(|row| row[n..].iter().all(|v| *v == -1f32)));
+    for threads in [2, 4, 8] {
+        assert!(run(threads) == serial);
+    }
+}
+
+#[test]
+pub fn serial_threshold_dispatch() {
+    use crate::sgemm::serial_below;
+
+    let config = TuneConfig {
+        block_sizes: block_sizes(),
+        threads: 8,
+    };
+    let threshold = crate::parallel::SERIAL_THRESHOLD;
+    assert!(serial_below(config, (16, 16, 16), threshold).threads == 1);
+    assert!(serial_below(config, (64, 64, 64), threshold) == config);
+    assert!(serial_below(config, (16, 16, 16), 0) == config);
+    assert!(serial_below(config, (0, 1 << 40, 1 << 40), 1).threads == 1);
+    // 2^66 multiply-adds would wrap to 0 and look small
+    assert!(serial_below(config, (1 << 22, 1 << 22, 1 << 22), threshold) == config);
+
+    // on or off the caller's thread, the same values
+    let (m, n, k) = (19, 33, 40);
+    let a = F32Tensor::new(
+        (0..m * k).map(|v| (v % 9) as f32 / 8f32).collect(),
+        vec![m, k],
+    );
+    let b = F32Tensor::new(
+        (0..k * n).map(|v| (v % 7) as f32 / 3f32).collect(),
+        vec![k, n],
+    );
+    let run = |threshold: usize| {
+        let mut c = F32Tensor::zeros(vec![m, n]);
+        Gemm::builder()
+            .threads(4)
+            .serial_threshold(threshold)
+            .run(&a, &b, &mut c);
+        c.values
+    };
+    assert!(run(0) == run(usize::MAX));
+    assert!(run(m * n * k) == run(m * n * k + 1));
+}
+
+#[test]
+pub fn sgemm_independent_of_threads() {
+    let (m, n, k) = (53, 71, 150);
+    let a_values: Vec<f32> = (0..m * k)
+        .map(|v| ((v % 13) as f32 - 6f32) / 7f32)
+        .collect();
+    let b_values: Vec<f32> = (0..k * n)
+        .map(|v| ((v % 11) as f32 - 5f32) / 3f32)
+        .collect();
+    let a = F32Tensor::new(a_values, vec![m, k]);
+    let b = F32Tensor::new(b_values, vec![k, n]);
+
+    let run = |threads: usize| {
+        let mut c = F32Tensor::new(vec![0.5f32; m * n], vec![m, n]);
+        AmlContext::new(threads).deterministic(true).install(|| {
+            // several kc panels, so edge tiles accumulate into partial sums
+            Gemm::builder()
+                .threads(threads)
+                .serial_threshold(0)
+                .block_sizes(BlockSizes {
+                    mc: 28,
+                    kc: 40,
+                    nc: 64,
+                })
+                .beta(1f32)
+                .run(&a, &b, &mut c)
+        });
+        c.values
+    };
+    let serial = run(1);
+    for threads in [2, 3, 7, 16] {
+        assert!(run(threads) == serial);
+    }
+}
+
+#[test]
+pub fn col_major_layouts() {
+    let (m, n, k) = (13, 17, 29);
+    let a_values: Vec<f32> = (0..m * k).map(|v| (v % 7) as f32 - 3f32).collect();
+    let b_values: Vec<f32> = (0..k * n).map(|v| (v % 5) as f32 - 2f32).collect();
+    let expected = gemm_reference(&a_values, [m, k], false, &b_values, [k, n], false);
+
+    // the values of a row-major (rows, cols) matrix in the given layout, transposed or not
+    let matrix = |values: &[f32], [rows, cols]: [usize; 2], transpose: bool, layout: Layout| {
+        let at = |i: usize, j: usize| match transpose {
+            true => values[j * cols + i],
+            false => values[i * cols + j],
+        };
+        let (rows, cols) = match transpose {
+            true => (cols, rows),
+            false => (rows, cols),
+        };
+        let values = match layout {
+            Layout::RowMajor => (0..rows * cols).map(|v| at(v / cols, v % cols)).collect(),
+            Layout::ColMajor => (0..rows * cols).map(|v| at(v % rows, v / rows)).collect(),
+        };
+        F32Tensor::new(values, vec![rows, cols]).with_layout(layout)
+    };
+    let layouts = [Layout::RowMajor, Layout::ColMajor];
+
+    for (a_layout, b_layout, c_layout) in layouts.iter().flat_map(|a| {
+        layouts
+            .iter()
+            .flat_map(move |b| layouts.map(|c| (*a, *b, c)))
+    }) {
+        let want = matrix(&expected, [m, n], false, c_layout);
+        for (a_transpose, b_transpose) in
+            [(false, false), (true, false), (false, true), (true, true)]
+        {
+            let a = matrix(&a_values, [m, k], a_transpose, a_layout);
+            let b = matrix(&b_values, [k, n], b_transpose, b_layout);
+            let mut c = F32Tensor::zeros(vec![m, n]).with_layout(c_layout);
+            sgemm(&a, a_transpose, &b, b_transpose, &mut c);
+            assert!(c.values == want.values);
+
+            let mut c = F32Tensor::zeros(vec![m, n]).with_layout(c_layout);
+            Gemm::builder()
+                .a_transpose(a_transpose)
+                .b_transpose(b_transpose)
+                .run(&a, &b, &mut c);
+            assert!(c.values == want.values);
+        }
+
+        let a = matrix(&a_values, [m, k], false, a_layout);
+        let b = matrix(&b_values, [k, n], false, b_layout);
+        let to_f64 = |t: &F32Tensor| {
+            F64Tensor::new(
+                t.values.iter().map(|v| *v as f64).collect(),
+                t.shape.clone(),
+            )
+            .with_layout(t.layout)
+        };
+        let mut c = F64Tensor::zeros(vec![m, n]).with_layout(c_layout);
+        dgemm(&to_f64(&a), false, &to_f64(&b), false, &mut c);
+        assert!(c
+            .values
+            .iter()
+            .zip(&want.values)
+            .all(|(x, y)| *x == *y as f64));
+
+        let mut c = F32Tensor::zeros(vec![m, n]);
+        sgemm_prepacked(&a, false, &pack_b(&b, false), &mut c);
+        assert!(c.values == expected);
+    }
+
+    // a column-major `a` updated in place holds the same matrix as a row-major one
+    let x: Vec<f32> = (0..m).map(|v| v as f32).collect();
+    let y: Vec<f32> = (0..k).map(|v| (v % 3) as f32).collect();
+    let mut row = matrix(&a_values, [m, k], false, Layout::RowMajor);
+    let mut col = matrix(&a_values, [m, k], false, Layout::ColMajor);
+    sger(2f32, &x, &y, &mut row);
+    sger(2f32, &x, &y, &mut col);
+    assert!(matrix(&row.values, [m, k], false, Layout::ColMajor).values == col.values);
+
+    // ssyrk writes the requested triangle of the matrix, whichever half of `values` that is
+    let a = matrix(&a_values, [m, k], false, Layout::ColMajor);
+    let mut row = F32Tensor::zeros(vec![m, m]);
+    let mut col = F32Tensor::zeros(vec![m, m]).with_layout(Layout::ColMajor);
+    ssyrk(Uplo::Upper, &a, false, GemmParams::default(), &mut row);
+    ssyrk(Uplo::Upper, &a, false, GemmParams::default(), &mut col);
+    assert!(matrix(&row.values, [m, m], false, Layout::ColMajor).values == col.values);
+
+    // kernels that only write row-major say so instead of writing the wrong order
+    let a = F32Tensor::zeros(vec![m, k]);
+    let b = F32Tensor::zeros(vec![k, n]);
+    let mut c = F32Tensor::zeros(vec![m, n]).with_layout(Layout::ColMajor);
+    assert!(
+        try_sgemm_prepacked(&a, false, &pack_b(&b, false), &mut c)
+            == Err(AmlError::UnsupportedLayout { operand: "c" })
+    );
+    let square = F32Tensor::zeros(vec![m, m]).with_layout(Layout::ColMajor);
+    let mut b = F32Tensor::zeros(vec![m, n]);
+    assert!(
+        try_strmm(Uplo::Lower, Diag::NonUnit, 1f32, &square, &mut b)
+            == Err(AmlError::UnsupportedLayout { operand: "a" })
+    );
+}
+
+#[test]
+pub fn leading_dimension_gemm() {
+    let (m, n, k) = (67, 75, 90);
+    let a_values: Vec<f32> = (0..m * k).map(|v| (v % 7) as f32 - 3f32).collect();
+    let b_values: Vec<f32> = (0..k * n).map(|v| (v % 5) as f32 - 2f32).collect();
+    let expected = gemm_reference(&a_values, [m, k], false, &b_values, [k, n], false);
+
+    // a (rows, cols) block at (1, 2) of a buffer `pad` columns wider, cut where the block ends
+    let pad = 5;
+    let block = |values: &[f32], [rows, cols]: [usize; 2], fill: f32| {
+        let ld = cols + 2 + pad;
+        let mut buffer = vec![fill; (rows + 2) * ld];
+        for (i, row) in values.chunks_exact(cols).enumerate() {
+            buffer[(i + 1) * ld + 2..][..cols].copy_from_slice(row);
+        }
+        let start = ld + 2;
+        (buffer, start, start + (rows - 1) * ld + cols, ld)
+    };
+    let padded = |values: &[f32], shape: [usize; 2]| {
+        let (buffer, start, end, ld) = block(values, shape, f32::NAN);
+        F32Tensor::new_with_ld(
+            buffer[start..end].to_vec(),
+            shape.to_vec(),
+            Layout::RowMajor,
+            ld,
+        )
+    };
+    let a = padded(&a_values, [m, k]);
+    let b = padded(&b_values, [k, n]);
+    assert!(!a.is_contiguous());
+
+    // every path writes only the `n` values of each row of `c`, leaving its padding alone
+    let check = |run: &dyn Fn(&mut F32Tensor)| {
+        let (buffer, start, end, ld) = block(&vec![0f32; m * n], [m, n], -1f32);
+        let mut c = F32Tensor::new_with_ld(
+            buffer[start..end].to_vec(),
+            vec![m, n],
+            Layout::RowMajor,
+            ld,
+        );
+        run(&mut c);
+        for (idx, value) in c.values.iter().enumerate() {
+            match idx % ld < n {
+                true => assert!(*value == expected[idx / ld * n + idx % ld]),
+                false => assert!(*value == -1f32),
+            }
+        }
+    };
+    check(&|c| try_sgemm_with(&a, false, &b, false, GemmParams::default(), c).unwrap());
+    check(&|c| {
+        let params = GemmParams::default().with_accuracy(Accuracy::High);
+        try_sgemm_with(&a, false, &b, false, params, c).unwrap()
+    });
+    check(&|c| {
+        AmlContext::new(4)
+            .install(|| try_sgemm_with(&a, false, &b, false, GemmParams::default(), c).unwrap())
+    });
+    check(&|c| {
+        try_sgemm_prepacked_with(&a, false, &pack_b(&b, false), GemmParams::default(), c).unwrap()
+    });
+
+    // transposed operands read their stored rows `ld` apart too
+    let transpose = |values: &[f32], rows: usize, cols: usize| -> Vec<f32> {
+        (0..rows * cols)
+            .map(|v| values[(v % rows) * cols + v / rows])
+            .collect()
+    };
+    let at = padded(&transpose(&a_values, m, k), [k, m]);
+    let bt = padded(&transpose(&b_values, k, n), [n, k]);
+    let mut c = F32Tensor::zeros(vec![m, n]);
+    sgemm(&at, true, &bt, true, &mut c);
+    assert!(c.values == expected);
+
+    let to_f64 = |values: &[f32]| values.iter().map(|v| *v as f64).collect::<Vec<f64>>();
+    let (a_buffer, start, end, lda) = block(&a_values, [m, k], 0f32);
+    let a64 = F64Tensor::new_with_ld(
+        to_f64(&a_buffer[start..end]),
+        vec![m, k],
+        Layout::RowMajor,
+        lda,
+    );
+    let mut c = F64Tensor::zeros(vec![m, n]);
+    dgemm(
+        &a64,
+        false,
+        &F64Tensor::new(to_f64(&b_values), vec![k, n]),
+        false,
+        &mut c,
+    );
+    assert!(c.values == to_f64(&expected));
+
+    // only a dense tensor can be reshaped, and `ld` must cover a whole row
+    let mut a = a;
+    assert!(
+        a.try_reshape(vec![k, m])
+            == Err(AmlError::NotContiguous {
+                shape: vec![m, k],
+                strides: vec![k + 2 + pad, 1],
+            })
+    );
+    assert!(
+        F32Tensor::try_new_with_ld(vec![0f32; 10], vec![2, 5], Layout::RowMajor, 4).err()
+            == Some(AmlError::InvalidLeadingDim { ld: 4, min: 5 })
+    );
+    assert!(F32Tensor::try_new_with_ld(vec![0f32; 10], vec![2, 5], Layout::RowMajor, 6).is_err());
+}
+
+#[test]
+pub fn sub_matrix_views() {
+    let (m, n, k) = (50, 44, 70);
+    let a_values: Vec<f32> = (0..m * k).map(|v| (v % 7) as f32 - 3f32).collect();
+    let b_values: Vec<f32> = (0..k * n).map(|v| (v % 5) as f32 - 2f32).collect();
+    let expected = gemm_reference(&a_values, [m, k], false, &b_values, [k, n], false);
+    let a = F32Tensor::new(a_values.clone(), vec![m, k]);
+    let b = F32Tensor::new(b_values.clone(), vec![k, n]);
+
+    // a blocked GEMM written on top of the crate, every tile used in place
+    let tile = 16;
+    let mut c = F32Tensor::zeros(vec![m, n]);
+    for i0 in (0..m).step_by(tile) {
+        for j0 in (0..n).step_by(tile) {
+            let (rows, cols) = (i0..(i0 + tile).min(m), j0..(j0 + tile).min(n));
+            let mut c_tile = c.window_mut(rows.clone(), cols.clone());
+            for p0 in (0..k).step_by(tile) {
+                let depth = p0..(p0 + tile).min(k);
+                let a_tile = a.view(rows.clone(), depth.clone());
+                let b_tile = b.view(depth, cols.clone());
+                let beta = match p0 {
+                    0 => 0f32,
+                    _ => 1f32,
+                };
+                sgemm_with(
+                    &a_tile,
+                    false,
+                    &b_tile,
+                    false,
+                    GemmParams::new(1f32, beta),
+                    &mut c_tile,
+                );
+            }
+        }
+    }
+    assert!(c.values == expected);
+
+    // a window of a window, a column-major window and a packed window all read the same values
+    let a_rows = a.view(10..40, 0..k);
+    let a_block = a_rows.view(5..25, 20..50);
+    let b_block = b.view(20..50, 4..40);
+    let block_expected = gemm_reference(
+        &(15..35)
+            .flat_map(|i| a_values[i * k + 20..i * k + 50].to_vec())
+            .collect::<Vec<f32>>(),
+        [20, 30],
+        false,
+        &(20..50)
+            .flat_map(|p| b_values[p * n + 4..p * n + 40].to_vec())
+            .collect::<Vec<f32>>(),
+        [30, 36],
+        false,
+    );
+    let mut c = F32Tensor::zeros(vec![20, 36]);
+    sgemm(&a_block, false, &b_block, false, &mut c);
+    assert!(c.values == block_expected);
+
+    let mut c = F32Tensor::zeros(vec![20, 36]);
+    sgemm_prepacked(&a_block, false, &pack_b(&b_block, false), &mut c);
+    assert!(c.values == block_expected);
+
+    let a_t = F32Tensor::new(a_values.clone(), vec![k, m]).with_layout(Layout::ColMajor);
+    let mut c = F32Tensor::zeros(vec![20, 36]);
+    sgemm(&a_t.view(20..50, 15..35), true, &b_block, false, &mut c);
+    assert!(c.values == block_expected);
+
+    // a window of `c` leaves the rest of it alone
+    let mut c = F32Tensor::new(vec![-1f32; m * n], vec![m, n]);
+    sgemm(
+        &a_block,
+        false,
+        &b_block,
+        false,
+        &mut c.window_mut(3..23, 5..41),
+    );
+    for (idx, value) in c.values.iter().enumerate() {
+        let (i, j) = (idx / n, idx % n);
+        match (3..23).contains(&i) && (5..41).contains(&j) {
+            true => assert!(*value == block_expected[(i - 3) * 36 + j - 5]),
+            false => assert!(*value == -1f32),
+        }
+    }
+
+    assert!(
+        a.try_view(0..m + 1, 0..k).err()
+            == Some(AmlError::ViewOutOfBounds {
+                rows: 0..m + 1,
+                cols: 0..k,
+                shape: vec![m, k],
+            })
+    );
+    assert!(a.view(7..7, 3..9).shape == [0, 6]);
+}
+
+#[test]
+pub fn strided_tensors() {
+    let (m, n, k) = (30, 26, 40);
+    let a_values: Vec<f32> = (0..m * k).map(|v| (v % 7) as f32 - 3f32).collect();
+    let b_values: Vec<f32> = (0..k * n).map(|v| (v % 5) as f32 - 2f32).collect();
+    let expected = gemm_reference(&a_values, [m, k], false, &b_values, [k, n], false);
+
+    // every other column of a buffer twice as wide
+    let mut wide = vec![9f32; m * 2 * k];
+    for (idx, value) in a_values.iter().enumerate() {
+        wide[(idx / k) * 2 * k + (idx % k) * 2] = *value;
+    }
+    wide.truncate((m - 1) * 2 * k + (k - 1) * 2 + 1);
+    let a = F32Tensor::new_with_strides(wide, vec![m, k], vec![2 * k, 2]);
+    assert!(!a.is_contiguous() && a.to_contiguous().values == a_values);
+
+    let mut c = F32Tensor::zeros(vec![m, n]);
+    sgemm(
+        &a,
+        false,
+        &F32Tensor::new(b_values.clone(), vec![k, n]),
+        false,
+        &mut c,
+    );
+    assert!(c.values == expected);
+
+    // a column-major `b` given by its strides keeps its layout
+    let to_f64 = |values: &[f32]| values.iter().map(|v| *v as f64).collect::<Vec<f64>>();
+    let mut b_cols = vec![0f32; k * n];
+    for (idx, value) in b_values.iter().enumerate() {
+        b_cols[(idx % n) * k + idx / n] = *value;
+    }
+    let b = F64Tensor::new_with_strides(to_f64(&b_cols), vec![k, n], vec![1, k]);
+    assert!(b.layout == Layout::ColMajor && b.is_contiguous());
+    let a64 = F64Tensor::new_with_strides(to_f64(&a.values), vec![m, k], vec![2 * k, 2]);
+    let mut c = F64Tensor::zeros(vec![m, n]);
+    dgemm(&a64, false, &b, false, &mut c);
+    assert!(c.values == to_f64(&expected));
+
+    // axes of a (2, 3, 4) tensor stored in the order (4, 2, 3)
+    let values: Vec<f32> = (0..24).map(|v| v as f32).collect();
+    let permuted = F32Tensor::new_with_strides(values.clone(), vec![2, 3, 4], vec![3, 1, 6]);
+    let gathered = permuted.to_contiguous();
+    for (idx, value) in gathered.values.iter().enumerate() {
+        let (i, j, l) = (idx / 12, idx / 4 % 3, idx % 4);
+        assert!(*value == values[l * 6 + i * 3 + j]);
+    }
+    let mut permuted = permuted;
+    assert!(
+        permuted.try_reshape(vec![6, 4])
+            == Err(AmlError::NotContiguous {
+                shape: vec![2, 3, 4],
+                strides: vec![3, 1, 6],
+            })
+    );
+
+    // a strided `c` cannot be written in place
+    let mut c = F32Tensor::new_with_strides(vec![0f32; 2 * m * n - 1], vec![m, n], vec![2 * n, 2]);
+    assert!(
+        try_sgemm(
+            &a,
+            false,
+            &F32Tensor::new(b_values, vec![k, n]),
+            false,
+            &mut c
+        ) == Err(AmlError::UnsupportedStrides {
+            operand: "c",
+            strides: vec![2 * n, 2],
+        })
+    );
+
+    assert!(
+        F32Tensor::try_new_with_strides(vec![0f32; 7], vec![2, 4], vec![3, 1]).err()
+            == Some(AmlError::InvalidStrides {
+                shape: vec![2, 4],
+                strides: vec![3, 1],
+            })
+    );
+}
+
+#[test]
+pub fn zero_copy_reshape() {
+    let values: Vec<f32> = (0..24).map(|v| v as f32).collect();
+    let mut t = F32Tensor::new(values.clone(), vec![4, 6]);
+
+    let view = t.as_tensor_ref().reshape(vec![2, 3, 4]);
+    assert!(view.values.as_ptr() == t.values.as_ptr() && view.strides == [12, 4, 1]);
+    let matrix = view.unsqueeze(0).squeeze(0).reshape(vec![6, 4]);
+    let mut c = F32Tensor::zeros(vec![6, 6]);
+    sgemm(&matrix, false, &matrix, true, &mut c);
+    let expected = gemm_reference(&values, [6, 4], false, &values, [6, 4], true);
+    assert!(c.values == expected);
+
+    // a new axis takes the stride a contiguous tensor would have, in either layout
+    t.unsqueeze(2);
+    assert!(t.shape == [4, 6, 1] && t.is_contiguous());
+    t.squeeze(2);
+    let mut cols = F32Tensor::new(values.clone(), vec![4, 6]).with_layout(Layout::ColMajor);
+    cols.unsqueeze(1);
+    assert!(cols.strides == [1, 4, 4] && cols.is_contiguous());
+
+    assert!(
+        t.try_squeeze(1)
+            == Err(AmlError::InvalidAxis {
+                axis: 1,
+                shape: vec![4, 6]
+            })
+            && t.try_unsqueeze(3)
+                == Err(AmlError::InvalidAxis {
+                    axis: 3,
+                    shape: vec![4, 6]
+                })
+    );
+
+    // a window has gaps, so reshaping it would need a copy
+    assert!(
+        t.view(0..2, 1..4).try_reshape(vec![6]).err()
+            == Some(AmlError::NotContiguous {
+                shape: vec![2, 3],
+                strides: vec![6, 1],
+            })
+    );
+    // squeezing only drops a stride, gaps or not
+    let row = t.view(1..2, 1..4).squeeze(0);
+    assert!(row.shape == [3] && row.values == [7f32, 8f32, 9f32]);
+}
+
+#[test]
+pub fn overlapping_views() {
+    // every length-k window of a signal, one per row, for a convolution as a GEMM
+    let (m, n, k) = (40, 3, 9);
+    let signal: Vec<f32> = (0..m + k - 1).map(|v| (v % 11) as f32 - 5f32).collect();
+    let filters: Vec<f32> = (0..k * n).map(|v| (v % 4) as f32 - 1f32).collect();
+    let windows = unsafe {
+        F32TensorRef::from_raw_parts_with_strides(signal.as_ptr(), vec![m, k], vec![1, 1])
+    };
+    assert!(windows.values.len() == signal.len() && !windows.is_contiguous());
+
+    let copied = windows.to_contiguous();
+    for (idx, value) in copied.values.iter().enumerate() {
+        assert!(*value == signal[idx / k + idx % k]);
+    }
+    let mut c = F32Tensor::zeros(vec![m, n]);
+    sgemm(
+        &windows,
+        false,
+        &F32Tensor::new(filters.clone(), vec![k, n]),
+        false,
+        &mut c,
+    );
+    let expected = gemm_reference(&copied.values, [m, k], false, &filters, [k, n], false);
+    assert!(c.values == expected);
+
+    // a stride of 0 repeats one row down a whole matrix
+    let row = [1f32, 2f32, 3f32];
+    let repeated =
+        unsafe { F32TensorRef::from_raw_parts_with_strides(row.as_ptr(), vec![4, 3], vec![0, 1]) };
+    assert!(repeated.values.len() == 3 && repeated.to_contiguous().values == row.repeat(4));
+    let mut c = F32Tensor::zeros(vec![3, 3]);
+    sgemm(&repeated, true, &repeated, false, &mut c);
+    let expected: Vec<f32> = (0..9)
+        .map(|idx| 4f32 * row[idx / 3] * row[idx % 3])
+        .collect();
+    assert!(c.values == expected);
+}
+
+#[test]
+pub fn index_by_position() {
+    let values: Vec<f32> = (0..24).map(|v| v as f32).collect();
+    let mut t = F32Tensor::new(values.clone(), vec![4, 6]);
+    assert!(t[[2, 5]] == 17f32 && t.view(1..3, 2..5)[[1, 2]] == 16f32);
+
+    t[[3, 0]] = -1f32;
+    t.window_mut(0..2, 3..6)[[1, 1]] = -2f32;
+    assert!(t.values[18] == -1f32 && t.values[10] == -2f32);
+
+    let cols = F32Tensor::new(values.clone(), vec![6, 4]).with_layout(Layout::ColMajor);
+    assert!(cols[[5, 1]] == 11f32);
+    let cube = F32Tensor::new(values, vec![2, 3, 4]);
+    assert!(cube[[1, 2, 3]] == 23f32);
+}
+
+#[test]
+#[cfg(target_arch = "x86_64")]
+pub fn simd_kernels_check_lengths() {
+    use std::panic::catch_unwind;
+    if !is_x86_feature_detected!("avx") {
+        return;
+    }
+    // one value short of a whole vector on one side, which raw loads would read past
+    let (x, y) = (vec![1f32; 16], vec![1f32; 15]);
+    let mut out = vec![0f32; 16];
+    assert!(catch_unwind(|| unsafe { crate::blas1::sdot_avx(&x, &y) }).is_err());
+    assert!(catch_unwind(|| unsafe {
+        crate::elementwise::binary_avx(crate::elementwise::Binary::Add, &x, &y, &mut out.clone())
+    })
+    .is_err());
+    assert!(catch_unwind(|| unsafe {
+        crate::norm::normalize_avx(&x, 0f32, 1f32, &y, &[], &mut out.clone())
+    })
+    .is_err());
+    unsafe { crate::elementwise::binary_avx(crate::elementwise::Binary::Add, &x, &x, &mut out) };
+    assert!(out == [2f32; 16]);
+}
+
+#[test]
+#[cfg(debug_assertions)]
+#[should_panic(expected = "Index [1, 6] is not inside shape [4, 6].")]
+pub fn index_out_of_bounds() {
+    let t = F32Tensor::zeros(vec![4, 6]);
+    let _ = t[[1, 6]];
+}
+
+#[test]
+pub fn row_and_column_iterators() {
+    let values: Vec<f32> = (0..35).map(|v| v as f32).collect();
+    for t in [
+        F32Tensor::new(values.clone(), vec![5, 7]),
+        F32Tensor::new(values.clone(), vec![5, 7]).with_layout(Layout::ColMajor),
+    ] {
+        assert!(t.rows().count() == 5 && t.cols().count() == 7);
+        for (i, row) in t.rows().enumerate() {
+            assert!(row.shape == [7] && (0..7).all(|j| row[[j]] == t[[i, j]]));
+        }
+        for (j, col) in t.cols().enumerate() {
+            assert!(col.shape == [5] && (0..5).all(|i| col[[i]] == t[[i, j]]));
+        }
+
+        let chunks: Vec<Vec<usize>> = t.row_chunks(2).map(|chunk| chunk.shape.to_vec()).collect();
+        assert!(chunks == [vec![2, 7], vec![2, 7], vec![1, 7]]);
+        let last = t.row_chunks(2).last().unwrap();
+        assert!((0..7).all(|j| last[[0, j]] == t[[4, j]]));
+        assert!(t.try_row_chunks(0).err() == Some(AmlError::ZeroChunkSize));
+        assert!(t.view(0..0, 0..7).try_row_chunks(0).err() == Some(AmlError::ZeroChunkSize));
+    }
+
+    // rows of a window skip the values outside it
+    let t = F32Tensor::new(values.clone(), vec![5, 7]);
+    let sums: Vec<f32> = t
+        .view(1..3, 2..5)
+        .rows()
+        .map(|row| row.to_contiguous().values.iter().sum())
+        .collect();
+    assert!(sums == [30f32, 51f32]);
+    assert!(
+        F32Tensor::new(values, vec![35]).try_rows().err()
+            == Some(AmlError::RankMismatch {
+                operand: "values",
+                expected: 2,
+                found: 1,
+            })
+    );
+}
+
+#[test]
+pub fn shape_helpers() {
+    let (a, b) = (Shape::from([3, 5]), Shape::from([4, 5]));
+    assert!(a.numel() == 15 && Shape::default().numel() == 1 && a == [3, 5]);
+    assert!(a.matmul(false, &b, true) == [3, 4] && b.matmul(false, &a, true) == [4, 3]);
+    assert!(!a.is_compatible_for_matmul(false, &b, false));
+    assert!(a.try_matmul(false, &b, false) == Err(AmlError::InnerDimMismatch { a: 5, b: 4 }));
+    assert!(
+        Shape::from([3]).try_matmul(false, &a, false)
+            == Err(AmlError::RankMismatch {
+                operand: "a",
+                expected: 2,
+                found: 1,
+            })
+    );
+
+    let bias = Shape::from([5]);
+    assert!(a.broadcast(&bias) == [3, 5] && Shape::from([2, 1, 5]).broadcast(&a) == [2, 3, 5]);
+    assert!(
+        a.try_broadcast(&b)
+            == Err(AmlError::BroadcastMismatch {
+                a: vec![3, 5],
+                b: vec![4, 5],
+            })
+    );
+
+    // the GEMMs report a bad `b` through the same checks
+    let mut c = F32Tensor::zeros(vec![3, 4]);
+    let err = try_sgemm(
+        &F32Tensor::zeros(a.clone()),
+        false,
+        &F32Tensor::zeros(b.clone()),
+        false,
+        &mut c,
+    );
+    assert!(err == a.try_matmul(false, &b, false).map(|_| ()));
+}
+
+#[test]
+pub fn tensor_constructors() {
+    let ones = F32Tensor::ones(vec![2, 3]);
+    assert!(ones.shape == [2, 3] && ones.values == [1f32; 6] && ones.is_contiguous());
+    assert!(F64Tensor::full(vec![4], 2.5).values == [2.5; 4]);
+    assert!(BF16Tensor::zeros(vec![0, 3]).values.is_empty());
+
+    let eye = F32Tensor::eye(3);
+    assert!(eye.values == [1f32, 0f32, 0f32, 0f32, 1f32, 0f32, 0f32, 0f32, 1f32]);
+    let mut a = F32Tensor::arange(12);
+    assert!(a.shape == [12] && a[[11]] == 11f32);
+    a.reshape(vec![4, 3]);
+    let mut c = F32Tensor::zeros(vec![4, 3]);
+    sgemm(&a, false, &eye, false, &mut c);
+    assert!(c.values == a.values);
+}
+
+#[test]
+pub fn seeded_random_tensors() {
+    let a = F32Tensor::rand_uniform(vec![64, 64], 7);
+    assert!(a.values == F32Tensor::rand_uniform(vec![64, 64], 7).values);
+    assert!(a.values != F32Tensor::rand_uniform(vec![64, 64], 8).values);
+    assert!(a.values.iter().all(|v| (0f32..1f32).contains(v)));
+    let mean = a.values.iter().sum::<f32>() / a.values.len() as f32;
+    assert!((mean - 0.5).abs() < 0.02);
+
+    let n = F64Tensor::rand_normal(vec![101, 99], 3.0, 2.0, 11);
+    assert!(
+        n.shape == [101, 99]
+            && n.values == F64Tensor::rand_normal(vec![101, 99], 3.0, 2.0, 11).values
+    );
+    let mean = n.values.iter().sum::<f64>() / n.values.len() as f64;
+    let var = n.values.iter().map(|v| (v - mean).powi(2)).sum::<f64>() / n.values.len() as f64;
+    assert!((mean - 3.0).abs() < 0.1 && (var.sqrt() - 2.0).abs() < 0.1);
+}
+
+#[test]
+pub fn allclose_reports() {
+    let a = F32Tensor::rand_uniform(vec![20, 30], 3);
+    let b = F32Tensor::new(a.values.clone(), vec![30, 20]);
+    assert!(
+        allclose(&a, &b, 0.0, 0.0)
+            == Err(MismatchReport::Shape {
+                a: vec![20, 30],
+                b: vec![30, 20]
+            })
+    );
+
+    // the same values by index, stored the other way round
+    let mut cols = vec![0f32; 600];
+    for (idx, value) in a.values.iter().enumerate() {
+        cols[(idx % 30) * 20 + idx / 30] = *value;
+    }
+    let cols = F32Tensor::new_with_strides(cols, vec![20, 30], vec![1, 20]);
+    assert!(allclose(&a, &cols, 0.0, 0.0) == Ok(()));
+
+    let mut b = a.to_contiguous();
+    b[[2, 4]] += 1e-6;
+    b[[5, 1]] += 1f32;
+    b[[9, 9]] = f32::NAN;
+    assert!(
+        allclose(&a, &b, 1e-5, 1e-5).is_err()
+            && allclose(&a, &b.view(0..2, 0..30), 0.0, 0.0).is_err()
+    );
+    match allclose(&a, &b, 1e-5, 1e-5) {
+        Err(MismatchReport::Values {
+            count,
+            first,
+            worst,
+        }) => {
+            assert!(count == 2 && first.index == [5, 1] && first.a == a[[5, 1]] as f64);
+            assert!(worst.index == [9, 9] && worst.b.is_nan());
+        }
+        other => panic!("{:?}", other),
+    }
+}
+
+#[test]
+pub fn pretty_printing() {
+    let t = F32Tensor::arange(6);
+    assert!(format!("{:.1}", t) == "[0.0, 1.0, 2.0, 3.0, 4.0, 5.0]");
+
+    let mut t = F32Tensor::new(vec![1f32, -2.5, 30f32, 4f32], vec![2, 2]);
+    assert!(format!("{:.2}", t) == "[[ 1.00, -2.50],\n [30.00,  4.00]]");
+    t = t.with_layout(Layout::ColMajor);
+    assert!(format!("{}", t) == "[[ 1.0000, 30.0000],\n [-2.5000,  4.0000]]");
+
+    let cube = F64Tensor::arange(8)
+        .as_tensor_ref()
+        .reshape(vec![2, 2, 2])
+        .to_contiguous();
+    assert!(format!("{:.0}", cube) == "[[[0, 1],\n  [2, 3]],\n\n [[4, 5],\n  [6, 7]]]");
+
+    // a large matrix prints its corners only, unless asked for everything
+    let mut big = F32Tensor::arange(1024 * 1024);
+    big.reshape(vec![1024, 1024]);
+    let text = format!("{:.0}", big);
+    assert!(text.lines().count() == 7 && text.len() < 400);
+    assert!(text.starts_with("[[      0,       1,       2, ...,    1021,    1022,    1023],"));
+    assert!(text.contains("\n ...,\n"));
+    assert!(format!("{:#.0}", big.view(0..40, 0..40)).lines().count() == 40);
+    assert!(format!("{:?}", big).starts_with("Tensor { shape: [1024, 1024], layout: RowMajor"));
+}
+
+#[test]
+pub fn tensors_over_bytes() {
+    // the bytes of f32 values, as read from a file, in storage aligned for f32
+    let values: Vec<f32> = (0..24).map(|v| v as f32 - 7.5).collect();
+    let storage = values.clone();
+    let bytes = unsafe { std::slice::from_raw_parts(storage.as_ptr().cast::<u8>(), 96) };
+
+    let t = F32TensorRef::from_bytes(bytes, vec![4, 6]);
+    assert!(t.values.as_ptr() == storage.as_ptr());
+    assert!(t.values == values.as_slice() && t[[3, 5]] == 15.5);
+    let halves = vec![f16::ONE; 4];
+    let half_bytes = unsafe { std::slice::from_raw_parts(halves.as_ptr().cast::<u8>(), 8) };
+    assert!(TensorRef::<f16>::from_bytes(half_bytes, vec![2, 2]).values == halves.as_slice());
+
+    assert!(
+        F32TensorRef::try_from_bytes(&bytes[1..29], vec![7]).err()
+            == Some(AmlError::MisalignedBytes { align: 4 })
+    );
+    assert!(
+        F32TensorRef::try_from_bytes(&bytes[..28], vec![2, 4]).err()
+            == Some(AmlError::SizeMismatch {
+                expected: 32,
+                found: 28,
+            })
+    );
+    // the byte count of a huge shape wraps around instead of matching any buffer
+    let huge = vec![1 << 62, 4];
+    assert!(Shape::from(huge.clone()).checked_numel().is_none());
+    assert!(
+        F32TensorRef::try_from_bytes(&bytes[..0], huge.clone()).err()
+            == Some(AmlError::ShapeOverflow { shape: huge })
+    );
+    assert!(Shape::from([1 << 61]).byte_len(8).is_err());
+    assert!(Shape::from([3, 5]).byte_len(4) == Ok(60));
+}
+
+#[test]
+pub fn dtype_conversion() {
+    let mut src = vec![
+        0f32,
+        -0f32,
+        1.5,
+        -2.75,
+        65504f32,
+        70000f32,
+        1e-8,
+        1e-40,
+        f32::MAX,
+        f32::INFINITY,
+        f32::NEG_INFINITY,
+        f32::NAN,
+        -f32::NAN,
+        1.0 + 2f32.powi(-11),
+        1.0 + 2f32.powi(-8),
+        3.0 + 2f32.powi(-8),
+    ];
+    src.extend(F32Tensor::rand_normal(vec![1001], 0.0, 100.0, 5).values);
+    let same = |a: f32, b: f32| a.to_bits() == b.to_bits() || (a.is_nan() && b.is_nan());
+
+    // one rounding to nearest even, as the scalar conversions do, whichever kernel runs
+    let mut halves = vec![f16::ZERO; src.len()];
+    convert(&src, &mut halves);
+    assert!(src
+        .iter()
+        .zip(&halves)
+        .all(|(s, h)| same(h.to_f32(), f16::from_f32(*s).to_f32())));
+    let mut brains = vec![bf16::ZERO; src.len()];
+    convert(&src, &mut brains);
+    assert!(src
+        .iter()
+        .zip(&brains)
+        .all(|(s, b)| b.to_bits() == bf16::from_f32(*s).to_bits()));
+
+    let mut back = vec![0f32; src.len()];
+    convert(&brains, &mut back);
+    assert!(brains.iter().zip(&back).all(|(b, s)| same(b.to_f32(), *s)));
+    convert(&halves, &mut back);
+    assert!(halves.iter().zip(&back).all(|(h, s)| same(h.to_f32(), *s)));
+    let mut from_half = vec![bf16::ZERO; src.len()];
+    convert(&halves, &mut from_half);
+    assert!(halves
+        .iter()
+        .zip(&from_half)
+        .all(|(h, b)| same(b.to_f32(), bf16::from_f32(h.to_f32()).to_f32())));
+
+    // f64 converts value by value, as `from_f64` does
+    let wide: Vec<f64> = src.iter().map(|v| *v as f64 * 1.0001).collect();
+    convert(&wide, &mut halves);
+    assert!(wide
+        .iter()
+        .zip(&halves)
+        .all(|(w, h)| same(h.to_f32(), f16::from_f64(*w).to_f32())));
+    let mut widened = vec![0f64; src.len()];
+    convert(&brains, &mut widened);
+    assert!(brains
+        .iter()
+        .zip(&widened)
+        .all(|(b, w)| b.to_f64().to_bits() == w.to_bits()));
+
+    let values = [
+        0.5f32,
+        1.5,
+        2.5,
+        -2.5,
+        1e9,
+        -1e9,
+        f32::NAN,
+        0.26,
+        3.0,
+        -7.74,
+    ];
+    let mut q = [0i8; 10];
+    quantize_i8(&values, 0.5, 3, &mut q);
+    assert!(q == [4, 6, 8, -2, 127, -128, -128, 4, 9, -12]);
+    let mut deq = [0f32; 10];
+    dequantize_i8(&q, 0.5, 3, &mut deq);
+    assert!(deq == [0.5f32, 1.5, 2.5, -2.5, 62.0, -65.5, -65.5, 0.5, 3.0, -7.5]);
+    let many: Vec<f32> = src.iter().map(|v| v / 10.0).collect();
+    let mut q = vec![0i8; many.len()];
+    let mut expected = vec![0i8; many.len()];
+    quantize_i8(&many, 0.75, -5, &mut q);
+    crate::convert::quantize_i8_scalar(&many, 0.75, -5, &mut expected);
+    assert!(q == expected);
+
+    assert!(
+        try_convert(&src, &mut halves[1..])
+            == Err(AmlError::SizeMismatch {
+                expected: src.len(),
+                found: src.len() - 1,
+            })
+    );
+}
+
+#[test]
+pub fn aligned_buffers() {
+    let mut x = alloc_aligned_f32(100, 64);
+    assert!(x.len() == 100 && x.align() == 64 && x.iter().all(|v| *v == 0f32));
+    assert!((x.as_ptr() as usize).is_multiple_of(64));
+    let values = F32Tensor::rand_uniform(vec![100], 3).values;
+    x.copy_from_slice(&values);
+    let mut y = x.clone();
+    assert!((y.as_ptr() as usize).is_multiple_of(64) && y[..] == x[..]);
+
+    // aligned and unaligned starts give the same sums
+    let close = |a: f32, b: f32| (a - b).abs() <= 1e-4 * b.abs().max(1f32);
+    for offset in [0, 1] {
+        let (x, y) = (&x[offset..], &y[offset..]);
+        assert!(close(sdot(x, y), crate::blas1::sdot_scalar(x, y)));
+        assert!(close(sasum(x), crate::blas1::sasum_scalar(x)));
+    }
+    let mut expected = y.to_vec();
+    crate::blas1::saxpy_scalar(2f32, &x, &mut expected);
+    saxpy(2f32, &x, &mut y);
+    assert!(y[..] == expected[..]);
+    sscal(0.5f32, &mut y[..96]);
+    crate::blas1::sscal_scalar(0.5f32, &mut expected[..96]);
+    assert!(y[..] == expected[..]);
+
+    let t = F32TensorRef::new_with_ld(&x[..96], vec![8, 12], Layout::RowMajor, 12);
+    assert!(
+        t.is_aligned(64)
+            && !F32TensorRef::new_with_ld(&x[1..97], vec![8, 12], Layout::RowMajor, 12)
+                .is_aligned(32)
+    );
+    assert!((alloc_aligned_f32(0, 4096).as_ptr() as usize).is_multiple_of(4096));
+    assert!(try_alloc_aligned_f32(8, 24).unwrap_err() == AmlError::InvalidAlignment { align: 24 });
+    assert!(try_alloc_aligned_f32(8, 2).is_err());
+
+    // sizes past what a layout can describe, or what the allocator has, are errors too
+    assert!(
+        try_alloc_aligned_f32(usize::MAX / 2, 64).unwrap_err()
+            == AmlError::ShapeOverflow {
+                shape: vec![usize::MAX / 2]
+            }
+    );
+    let len = isize::MAX as usize / 8;
+    assert!(
+        try_alloc_aligned_f32(len, 64).unwrap_err()
+            == AmlError::AllocationFailed {
+                bytes: len * 4,
+                align: 64
+            }
+    );
+}
+
+#[test]
+pub fn tensor_allocators() {
+    use std::cell::RefCell;
+
+    /// Keeps released buffers and hands them out again
+    struct Recycler(RefCell<Vec<Vec<f32>>>);
+    impl TensorAllocator<f32> for Recycler {
+        fn allocate(&self, len: usize) -> Vec<f32> {
+            match self.0.borrow_mut().pop() {
+                Some(mut values) => {
+                    values.resize(len, 0f32);
+                    values
+                }
+                None => vec![0f32; len],
+            }
+        }
+        fn release(&self, values: Vec<f32>) {
+            self.0.borrow_mut().push(values)
+        }
+    }
+
+    let recycler = Recycler(RefCell::new(Vec::new()));
+    let t = F32Tensor::full_in(vec![2, 3], 7f32, &recycler);
+    assert!(t.values == [7f32; 6] && t.shape == [2, 3]);
+    let ptr = t.values.as_ptr();
+    t.release_in(&recycler);
+    let t = F32Tensor::zeros_in(vec![3, 2], &recycler);
+    assert!(t.values.as_ptr() == ptr && t.values == [0f32; 6]);
+
+    let calls = RefCell::new(0);
+    let callback = |len: usize| {
+        *calls.borrow_mut() += 1;
+        vec![1f32; len]
+    };
+    assert!(F32Tensor::zeros_in(vec![4], &callback).values == [0f32; 4] && *calls.borrow() == 1);
+    assert!(F64Tensor::zeros_in(vec![2, 2], &SystemAllocator).values == [0f64; 4]);
+
+    let short = |len: usize| vec![0f32; len - 1];
+    assert!(
+        F32Tensor::try_zeros_in(vec![2, 2], &short).unwrap_err()
+            == AmlError::SizeMismatch {
+                expected: 4,
+                found: 3
+            }
+    );
+}
+
+#[test]
+pub fn gemm_workspaces() {
+    let a = F32Tensor::rand_uniform(vec![37, 50], 1);
+    let b = F32Tensor::rand_uniform(vec![29, 50], 2);
+    let c0 = F32Tensor::rand_uniform(vec![37, 29], 3);
+    let params = GemmParams::new(1.5f32, -0.5f32);
+
+    let mut expected = F32Tensor::new(c0.values.clone(), vec![37, 29]);
+    sgemm_with(&a, false, &b, true, params, &mut expected);
+    let mut workspace = Workspace::for_sgemm(37, 29, 50);
+    let capacity = workspace.capacity();
+    for _ in 0..2 {
+        let mut c = F32Tensor::new(c0.values.clone(), vec![37, 29]);
+        sgemm_in(&a, false, &b, true, params, &mut workspace, &mut c);
+        assert!(c.values == expected.values);
+        assert!(workspace.capacity() == capacity);
+    }
+
+    // a grown workspace serves smaller calls as they are
+    let packed = pack_b(&b, true);
+    let mut c = F32Tensor::new(c0.values.clone(), vec![37, 29]);
+    sgemm_prepacked_in(&a, false, &packed, params, &mut workspace, &mut c);
+    let mut expected = F32Tensor::new(c0.values.clone(), vec![37, 29]);
+    sgemm_prepacked_with(&a, false, &packed, params, &mut expected);
+    assert!(c.values == expected.values && workspace.capacity() == capacity);
+    let mut small = F32Tensor::zeros(vec![4, 29]);
+    let a_small = a.view(0..4, 0..50);
+    sgemm_in(
+        &a_small,
+        false,
+        &b,
+        true,
+        GemmParams::default(),
+        &mut workspace,
+        &mut small,
+    );
+    assert!(workspace.capacity() == capacity);
+    let mut expected = F32Tensor::zeros(vec![4, 29]);
+    sgemm(&a_small, false, &b, true, &mut expected);
+    assert!(small.values == expected.values);
+
+    let high = GemmParams::default().with_accuracy(Accuracy::High);
+    let mut c = F32Tensor::zeros(vec![37, 29]);
+    let mut expected = F32Tensor::zeros(vec![37, 29]);
+    sgemm_in(&a, false, &b, true, high, &mut Workspace::new(), &mut c);
+    sgemm_with(&a, false, &b, true, high, &mut expected);
+    assert!(c.values == expected.values);
+
+    assert!(try_sgemm_in(&a, false, &b, false, params, &mut workspace, &mut c).is_err());
+}
+
+#[test]
+pub fn buffer_pool() {
+    let pool: pool::BufferPool = pool::BufferPool::with_limit(2);
+    let mut t = pool.acquire(vec![3, 5]);
+    assert!(t.shape == [3, 5] && t.values == [0f32; 15] && t.values.capacity() == 16);
+    t.values.fill(4f32);
+    let ptr = t.values.as_ptr();
+    pool.release(t);
+    assert!(pool.len() == 1);
+
+    // any length of the same class reuses the buffer, zeroed again
+    let t = pool.acquire(vec![4, 4]);
+    assert!(t.values.as_ptr() == ptr && t.values == [0f32; 16] && pool.is_empty());
+    let other = pool.acquire(vec![9]);
+    assert!(other.values.capacity() == 16 && other.values.as_ptr() != ptr);
+    let large = pool.acquire(vec![17]);
+    assert!(large.values.capacity() == 32);
+    let empty = pool.acquire(vec![0]);
+    for t in [t, other, large, empty] {
+        pool.release(t);
+    }
+    assert!(pool.len() == 4);
+    pool.release(F32Tensor::zeros(vec![16]));
+    assert!(pool.len() == 4);
+
+    // shared between threads, and usable wherever an allocator is
+    std::thread::scope(|s| {
+        for _ in 0..4 {
+            s.spawn(|| {
+                let t = F32Tensor::zeros_in(vec![2, 8], &pool);
+                pool.release(t);
+            });
+        }
+    });
+    assert!(pool.len() <= 5);
+    pool.clear();
+    assert!(pool.is_empty());
+}
+
+#[test]
+pub fn shared_tensors() {
+    let tensor = F32Tensor::rand_uniform(vec![6, 8], 4);
+    let weights = SharedTensor::from(F32Tensor::new(tensor.values.clone(), vec![6, 8]));
+    let copy = weights.clone();
+    assert!(copy.shares_values(&weights) && copy.values() == tensor.values);
+
+    let block = weights.view(2..5, 1..7);
+    assert!(block.shares_values(&weights) && block.shape == [3, 6]);
+    assert!(block.to_tensor().values == tensor.view(2..5, 1..7).to_contiguous().values);
+    let inner = block.view(1..3, 2..4);
+    assert!(inner.to_tensor().values == tensor.view(3..5, 3..5).to_contiguous().values);
+    assert!(block.try_view(0..4, 0..1).is_err());
+
+    // each thread multiplies by the same weights without borrowing them
+    let x = F32Tensor::rand_uniform(vec![4, 8], 5);
+    let mut expected = F32Tensor::zeros(vec![4, 6]);
+    sgemm(&x, false, &tensor, true, &mut expected);
+    let workers: Vec<_> = (0..3)
+        .map(|_| {
+            let (weights, x) = (
+                weights.clone(),
+                F32Tensor::new(x.values.clone(), vec![4, 8]),
+            );
+            std::thread::spawn(move || {
+                let mut c = F32Tensor::zeros(vec![4, 6]);
+                sgemm(&x, false, &weights, true, &mut c);
+                c.values
+            })
+        })
+        .collect();
+    for worker in workers {
+        assert!(worker.join().unwrap() == expected.values);
+    }
+
+    let shared = SharedTensor::<f64>::new(vec![1f64, 2f64, 3f64, 4f64], vec![2, 2]);
+    assert!(!shared.shares_values(&SharedTensor::new(vec![1f64; 4], vec![2, 2])));
+    assert!(format!("{}", shared) == "[[1.0000, 2.0000],\n [3.0000, 4.0000]]");
+    assert!(SharedTensor::<f32>::try_new(vec![0f32; 3], vec![2, 2]).is_err());
+}
+
+#[test]
+pub fn copy_on_write_tensors() {
+    let weights = F32Tensor::rand_uniform(vec![6, 4], 6);
+    let mut layers: Vec<CowTensor<f32>> = (0..3).map(|_| CowTensor::from(&weights)).collect();
+
+    // reading, also by a kernel, copies nothing
+    let x = F32Tensor::rand_uniform(vec![2, 6], 7);
+    let mut y = F32Tensor::zeros(vec![2, 4]);
+    sgemm(&x, false, &layers[0], false, &mut y);
+    assert!(layers.iter().all(CowTensor::is_borrowed));
+
+    // a LoRA merge writes the one layer it patches
+    let (down, up) = (
+        F32Tensor::rand_uniform(vec![6, 2], 8),
+        F32Tensor::rand_uniform(vec![2, 4], 9),
+    );
+    sgemm_with(
+        &down,
+        false,
+        &up,
+        false,
+        GemmParams::new(1f32, 1f32),
+        &mut layers[1],
+    );
+    layers[2].to_mut()[[0, 0]] = 100f32;
+    assert!(layers[0].is_borrowed() && layers[1].is_owned() && layers[2].is_owned());
+    let mut merged = F32Tensor::new(weights.values.clone(), vec![6, 4]);
+    sgemm_with(
+        &down,
+        false,
+        &up,
+        false,
+        GemmParams::new(1f32, 1f32),
+        &mut merged,
+    );
+    assert!(layers[1].as_tensor_ref().to_contiguous().values == merged.values);
+    assert!(layers[2].as_tensor_ref()[[0, 0]] == 100f32 && weights[[0, 0]] != 100f32);
+
+    // a strided borrow is copied contiguous
+    let mut block = CowTensor::from(weights.view(1..4, 1..3));
+    block.to_mut()[[2, 1]] = -1f32;
+    let block = block.into_owned();
+    assert!(block.is_contiguous() && block.shape == [3, 2] && block[[0, 0]] == weights[[1, 1]]);
+    assert!(CowTensor::from(F32Tensor::zeros(vec![2])).is_owned());
+}
+
+#[test]
+pub fn memory_mapped_tensors() {
+    let weights = F32Tensor::rand_uniform(vec![3, 4], 10);
+    let mut file = b"AMLTEST\0".to_vec();
+    file.extend(weights.values.iter().flat_map(|v| v.to_ne_bytes()));
+    let path = std::env::temp_dir().join(format!("aml-mmap-{}.bin", std::process::id()));
+    std::fs::write(&path, &file).unwrap();
+
+    // nothing else writes to the file while it is mapped
+    let mapped = unsafe { F32Tensor::from_mmap(&path, vec![3, 4], 8) }.unwrap();
+    assert!(mapped.is_mapped() == cfg!(all(unix, feature = "mmap")));
+    assert!(mapped.shape() == &[3, 4] && mapped.as_tensor_ref().values == weights.values);
+    let mut c = F32Tensor::zeros(vec![3, 3]);
+    let mut expected = F32Tensor::zeros(vec![3, 3]);
+    sgemm(&mapped, false, &weights, true, &mut c);
+    sgemm(&weights, false, &weights, true, &mut expected);
+    assert!(c.values == expected.values);
+    let tail = unsafe { F32Tensor::from_mmap(&path, vec![2], 48) }.unwrap();
+    assert!(tail.as_tensor_ref().values == &weights.values[10..]);
+    assert!(unsafe { F32Tensor::from_mmap(&path, vec![0], 56) }
+        .unwrap()
+        .as_tensor_ref()
+        .values
+        .is_empty());
+
+    let inner = |e: std::io::Error| e.into_inner().unwrap().downcast::<AmlError>().map(|e| *e);
+    let misaligned = unsafe { F32Tensor::from_mmap(&path, vec![2], 6) }.unwrap_err();
+    assert!(inner(misaligned).unwrap() == AmlError::MisalignedBytes { align: 4 });
+    let past_end = unsafe { F32Tensor::from_mmap(&path, vec![3, 4], 12) }.unwrap_err();
+    assert!(
+        inner(past_end).unwrap()
+            == AmlError::SizeMismatch {
+                expected: 60,
+                found: 56
+            }
+    );
+    let huge = unsafe { F32Tensor::from_mmap(&path, vec![1 << 62, 4], 8) }.unwrap_err();
+    assert!(
+        inner(huge).unwrap()
+            == AmlError::ShapeOverflow {
+                shape: vec![1 << 62, 4]
+            }
+    );
+    drop(mapped);
+    std::fs::remove_file(&path).unwrap();
+    let missing = unsafe { F64Tensor::from_mmap(&path, vec![1], 0) }.unwrap_err();
+    assert!(missing.kind() == std::io::ErrorKind::NotFound);
+}
+
+#[test]
+pub fn streamed_gemm() {
+    let (m, k, n) = (7, 23, 5);
+    let a = F32Tensor::rand_uniform(vec![m, k], 11);
+    let b = F32Tensor::rand_uniform(vec![k, n], 12);
+    let mut expected = F32Tensor::zeros(vec![m, n]);
+    sgemm(&a, false, &b, false, &mut expected);
+
+    // `a` from a file behind a header, `b` from memory
+    let mut file = vec![0u8; 16];
+    file.extend(a.values.iter().flat_map(|v| v.to_ne_bytes()));
+    let mut a_file = MatrixReader::new(std::io::Cursor::new(file), [m, k], 16);
+    for depth in [1, 4, 23, 100] {
+        let mut c = F32Tensor::full(vec![m, n], f32::NAN);
+        sgemm_streamed(&mut a_file, &mut b.as_tensor_ref(), depth, &mut c).unwrap();
+        assert!(allclose(&c, &expected, 1e-5, 1e-6).is_ok());
+    }
+
+    // a column-major operand and a window of `c`
+    let b_values: Vec<f32> = (0..n * k).map(|i| b[[i % k, i / k]]).collect();
+    let mut b_col = F32Tensor::new(b_values, vec![k, n]).with_layout(Layout::ColMajor);
+    let mut big = F32Tensor::zeros(vec![m + 1, n + 2]);
+    let mut window = big.window_mut(1..m + 1, 2..n + 2);
+    sgemm_streamed(&mut a.as_tensor_ref(), &mut b_col, 6, &mut window).unwrap();
+    assert!(
+        big.view(1..m + 1, 2..n + 2).to_contiguous().values == {
+            let mut c = F32Tensor::zeros(vec![m, n]);
+            sgemm_streamed(&mut a.as_tensor_ref(), &mut b.as_tensor_ref(), 6, &mut c).unwrap();
+            c.values
+        }
+    );
+
+    let mut empty = F32Tensor::full(vec![m, n], 1f32);
+    let zero_k = F32Tensor::zeros(vec![m, 0]);
+    sgemm_streamed(
+        &mut zero_k.as_tensor_ref(),
+        &mut F32Tensor::zeros(vec![0, n]),
+        8,
+        &mut empty,
+    )
+    .unwrap();
+    assert!(empty.values.iter().all(|v| *v == 0f32));
+    let error = sgemm_streamed(
+        &mut a.as_tensor_ref(),
+        &mut a.as_tensor_ref(),
+        4,
+        &mut empty,
+    )
+    .unwrap_err();
+    assert!(error.kind() == std::io::ErrorKind::InvalidInput);
+
+    // a tensor that is not a matrix is a rank error, not an empty matrix
+    let cube = F32Tensor::zeros(vec![m, k, 1]);
+    assert!(
+        cube.shape().unwrap_err()
+            == AmlError::RankMismatch {
+                operand: "source",
+                expected: 2,
+                found: 3
+            }
+    );
+    let error = sgemm_streamed(
+        &mut cube.as_tensor_ref(),
+        &mut b.as_tensor_ref(),
+        4,
+        &mut empty,
+    )
+    .unwrap_err();
+    assert!(error.kind() == std::io::ErrorKind::InvalidInput);
+
+    // blocks come out of row-major windows and column-major tensors alike
+    let mut block = vec![0f32; 6];
+    b.view(1..4, 0..n)
+        .as_tensor_ref()
+        .read_block(1..3, 2..5, &mut block)
+        .unwrap();
+    assert!(
+        block
+            == [
+                b[[2, 2]],
+                b[[2, 3]],
+                b[[2, 4]],
+                b[[3, 2]],
+                b[[3, 3]],
+                b[[3, 4]]
+            ]
+    );
+    b_col.read_block(1..3, 2..5, &mut block).unwrap();
+    assert!(
+        block
+            == [
+                b[[1, 2]],
+                b[[1, 3]],
+                b[[1, 4]],
+                b[[2, 2]],
+                b[[2, 3]],
+                b[[2, 4]]
+            ]
+    );
+}
+
+#[test]
+pub fn npy_files() {
+    use crate::io::npy;
+
+    // byte for byte what `numpy.save` writes for `arange(6, dtype='<f4').reshape(2, 3)`
+    let mut bytes = Vec::new();
+    npy::write(
+        &mut bytes,
+        &F32Tensor::new(F32Tensor::arange(6).values, vec![2, 3]),
+    )
+    .unwrap();
+    let header = "{'descr': '<f4', 'fortran_order': False, 'shape': (2, 3), }";
+    assert!(bytes.len() == 128 + 24 && &bytes[..10] == b"\x93NUMPY\x01\x00\x76\x00");
+    assert!(bytes[10..128].starts_with(header.as_bytes()) && bytes[127] == b'\n');
+    let back: F32Tensor = npy::read(&bytes[..]).unwrap();
+    assert!(back.shape == [2, 3] && back.values == F32Tensor::arange(6).values);
+
+    // Fortran order stays column-major, strided views are gathered, f64 loads as f32
+    let col = F64Tensor::rand_uniform(vec![3, 4], 13).with_layout(Layout::ColMajor);
+    let path = std::env::temp_dir().join(format!("aml-npy-{}.npy", std::process::id()));
+    npy::save(&path, &col).unwrap();
+    let back: F64Tensor = npy::load(&path).unwrap();
+    assert!(back.layout == Layout::ColMajor && back.values == col.values);
+    let narrow: F32Tensor = npy::load(&path).unwrap();
+    assert!(narrow
+        .values
+        .iter()
+        .zip(&col.values)
+        .all(|(n, w)| *n == *w as f32));
+    npy::save(&path, &col.view(1..3, 0..3)).unwrap();
+    let back: F64Tensor = npy::load(&path).unwrap();
+    assert!(back.shape == [2, 3] && back[[1, 2]] == col[[2, 2]]);
+    std::fs::remove_file(&path).unwrap();
+
+    for shape in [vec![], vec![5], vec![2, 0, 3], vec![2, 2, 2]] {
+        let t = F16Tensor::rand_uniform(shape.clone(), 14);
+        let mut bytes = Vec::new();
+        npy::write(&mut bytes, &t).unwrap();
+        let back: F16Tensor = npy::read(&bytes[..]).unwrap();
+        assert!(back.shape == shape && back.values == t.values);
+    }
+
+    // a version 2 header over big endian values
+    let header = "{'descr': '>f8', 'fortran_order': False, 'shape': (2,), }\n";
+    let mut bytes = b"\x93NUMPY\x02\x00".to_vec();
+    bytes.extend((header.len() as u32).to_le_bytes());
+    bytes.extend(header.as_bytes());
+    bytes.extend(
+        1.5f64
+            .to_be_bytes()
+            .into_iter()
+            .chain((-2f64).to_be_bytes()),
+    );
+    let back: F32Tensor = npy::read(&bytes[..]).unwrap();
+    assert!(back.values == [1.5f32, -2f32]);
+
+    let header = header.replace(">f8", "<i8");
+    let mut unsupported = b"\x93NUMPY\x02\x00".to_vec();
+    unsupported.extend((header.len() as u32).to_le_bytes());
+    unsupported.extend(header.as_bytes());
+    unsupported.extend([0u8; 16]);
+    let error = npy::read::<f32>(&unsupported[..]).unwrap_err();
+    assert!(error.kind() == std::io::ErrorKind::InvalidData);
+    assert!(npy::read::<f32>(&b"PK\x03\x04 not numpy"[..]).is_err());
+
+    // malformed or hostile headers are errors, not panics or huge allocations
+    let with_header = |header: &str| {
+        let mut bytes = b"\x93NUMPY\x01\x00".to_vec();
+        bytes.extend((header.len() as u16).to_le_bytes());
+        bytes.extend(header.as_bytes());
+        bytes.extend([0u8; 8]);
+        npy::read::<f32>(&bytes[..]).map(|t| t.shape.to_vec())
+    };
+    let header = "{'descr': '<f4', 'fortran_order': False, 'shape': (2,), }";
+    assert!(with_header(header).unwrap() == [2]);
+    for (field, bad) in [
+        ("'<f4'", "''"),
+        ("'<f4'", ""),
+        ("(2,)", ")"),
+        ("(2,)", "("),
+        ("(2,)", "(4611686018427387904, 4)"),
+    ] {
+        let error = with_header(&header.replace(field, bad)).unwrap_err();
+        assert!(error.kind() == std::io::ErrorKind::InvalidData);
+    }
+    let truncated = with_header(&header.replace("(2,)", "(1000000000000,)")).unwrap_err();
+    assert!(truncated.kind() == std::io::ErrorKind::UnexpectedEof);
+    let error = npy::write(Vec::new(), &BF16Tensor::zeros(vec![2])).unwrap_err();
+    assert!(error.kind() == std::io::ErrorKind::InvalidInput);
+}
+
+#[test]
+pub fn npz_archives() {
+    use crate::io::npz;
+    use std::collections::HashMap;
+    use std::io::Cursor;
+
+    // `numpy.savez_compressed` of an (8, 16) f4 `weights` and a (3,) f8 `bias`: zip64 local
+    // headers, `weights` a dynamic Huffman block and `bias` a fixed one
+    let archive: &[u8] = &[
+        0x50, 0x4b, 0x03, 0x04, 0x2d, 0x00, 0x00, 0x00, 0x08, 0x00, 0x00, 0x00, 0x21, 0x00, 0xaf,
+        0xf9, 0xf4, 0x22, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x0b, 0x00, 0x14, 0x00,
+        0x77, 0x65, 0x69, 0x67, 0x68, 0x74, 0x73, 0x2e, 0x6e, 0x70, 0x79, 0x01, 0x00, 0x10, 0x00,
+        0x80, 0x02, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0xd6, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x00, 0x9d, 0x50, 0xb1, 0x0a, 0x02, 0x31, 0x0c, 0x8d, 0xab, 0x5f, 0xd1, 0xad, 0x0a, 0xb7,
+        0x08, 0x22, 0x22, 0xc2, 0x75, 0x72, 0x53, 0x5c, 0x1c, 0x9c, 0xe4, 0xf0, 0x7a, 0x38, 0x88,
+        0x27, 0x3d, 0x71, 0x11, 0xa1, 0xff, 0xe0, 0x0f, 0xf4, 0x53, 0xf2, 0x29, 0xf7, 0x29, 0x5e,
+        0xcf, 0xc6, 0xc6, 0x8e, 0x06, 0x42, 0x78, 0x49, 0xfa, 0x5e, 0xfa, 0x5e, 0x9b, 0xdd, 0x7a,
+        0xbb, 0x1f, 0xc0, 0x1d, 0x1e, 0xb2, 0xd4, 0xcd, 0xd1, 0xc8, 0x85, 0x90, 0xcb, 0x6a, 0x2a,
+        0x33, 0x21, 0xab, 0xda, 0xdc, 0x4c, 0x71, 0x39, 0xd4, 0xa6, 0xd4, 0xbe, 0xbf, 0x2a, 0xce,
+        0x8d, 0xee, 0xfa, 0xcd, 0xa9, 0xb8, 0xea, 0x0e, 0x8f, 0xe6, 0x99, 0x98, 0xcc, 0xc6, 0x99,
+        0x78, 0x8a, 0x7f, 0x63, 0x08, 0x80, 0xea, 0x93, 0x36, 0x07, 0x50, 0x2a, 0x26, 0x84, 0x6a,
+        0xc3, 0xdc, 0x75, 0xd9, 0x06, 0x6c, 0xc3, 0x7e, 0x9b, 0x54, 0xe2, 0x82, 0xb0, 0x4b, 0x89,
+        0x2a, 0xee, 0xf8, 0x99, 0x0f, 0xcb, 0x30, 0x26, 0xba, 0x8e, 0x66, 0x10, 0xf5, 0x5c, 0xc2,
+        0xe7, 0xd8, 0x3e, 0x24, 0xfb, 0xd4, 0xc7, 0x44, 0x9b, 0x6e, 0xe5, 0x3c, 0xa4, 0xed, 0xc3,
+        0x05, 0x5e, 0xfe, 0x16, 0x98, 0x07, 0xc8, 0xfe, 0xee, 0x18, 0x37, 0x32, 0x7d, 0x1f, 0x8a,
+        0xff, 0x37, 0xf0, 0x52, 0xed, 0xef, 0x08, 0x9a, 0x8e, 0xf9, 0xdc, 0xdf, 0x9e, 0x33, 0xac,
+        0x7e, 0x35, 0x20, 0xb9, 0x2d, 0x7d, 0x43, 0xfe, 0x5b, 0xc5, 0x3c, 0xce, 0x23, 0x46, 0xe6,
+        0x2b, 0x7e, 0x3d, 0x78, 0x03, 0x50, 0x4b, 0x03, 0x04, 0x2d, 0x00, 0x00, 0x00, 0x08, 0x00,
+        0x00, 0x00, 0x21, 0x00, 0xe8, 0x97, 0x64, 0xfb, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff,
+        0xff, 0x08, 0x00, 0x14, 0x00, 0x62, 0x69, 0x61, 0x73, 0x2e, 0x6e, 0x70, 0x79, 0x01, 0x00,
+        0x10, 0x00, 0x98, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x50, 0x00, 0x00, 0x00, 0x00,
+        0x00, 0x00, 0x00, 0x9b, 0xec, 0x17, 0xea, 0x1b, 0x10, 0xc9, 0xc8, 0x50, 0xc6, 0x50, 0xad,
+        0x9e, 0x92, 0x5a, 0x9c, 0x5c, 0xa4, 0x6e, 0xa5, 0xa0, 0x6e, 0x93, 0x66, 0xa1, 0xae, 0xa3,
+        0xa0, 0x9e, 0x96, 0x5f, 0x54, 0x52, 0x94, 0x98, 0x17, 0x9f, 0x5f, 0x94, 0x92, 0x0a, 0x12,
+        0x77, 0x4b, 0xcc, 0x29, 0x4e, 0x05, 0x8a, 0x17, 0x67, 0x24, 0x16, 0xa4, 0x02, 0xf9, 0x1a,
+        0xc6, 0x3a, 0x9a, 0x3a, 0x0a, 0xb5, 0x0a, 0x14, 0x00, 0x2e, 0x06, 0x30, 0x78, 0x60, 0x0f,
+        0xa1, 0x3f, 0xec, 0x87, 0xd0, 0x0c, 0x0e, 0x00, 0x50, 0x4b, 0x01, 0x02, 0x2d, 0x03, 0x2d,
+        0x00, 0x00, 0x00, 0x08, 0x00, 0x00, 0x00, 0x21, 0x00, 0xaf, 0xf9, 0xf4, 0x22, 0xd6, 0x00,
+        0x00, 0x00, 0x80, 0x02, 0x00, 0x00, 0x0b, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x00, 0x00, 0x00, 0x80, 0x01, 0x00, 0x00, 0x00, 0x00, 0x77, 0x65, 0x69, 0x67, 0x68, 0x74,
+        0x73, 0x2e, 0x6e, 0x70, 0x79, 0x50, 0x4b, 0x01, 0x02, 0x2d, 0x03, 0x2d, 0x00, 0x00, 0x00,
+        0x08, 0x00, 0x00, 0x00, 0x21, 0x00, 0xe8, 0x97, 0x64, 0xfb, 0x50, 0x00, 0x00, 0x00, 0x98,
+        0x00, 0x00, 0x00, 0x08, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x80, 0x01, 0x13, 0x01, 0x00, 0x00, 0x62, 0x69, 0x61, 0x73, 0x2e, 0x6e, 0x70, 0x79, 0x50,
+        0x4b, 0x05, 0x06, 0x00, 0x00, 0x00, 0x00, 0x02, 0x00, 0x02, 0x00, 0x6f, 0x00, 0x00, 0x00,
+        0x9d, 0x01, 0x00, 0x00, 0x00, 0x00,
+    ];
+    let mut expected = Vec::new();
+    let mut x = 1u64;
+    for _ in 0..128 {
+        x = (x * 1103515245 + 12345) % (1 << 31);
+        expected.push(((x >> 16) % 8) as f32);
+    }
+    let arrays: HashMap<String, F32Tensor> = npz::read(Cursor::new(archive)).unwrap();
+    assert!(arrays.len() == 2);
+    assert!(arrays["weights"].shape == [8, 16] && arrays["weights"].values == expected);
+    assert!(arrays["bias"].shape == [3] && arrays["bias"].values == [0.5, -1., 2.]);
+    assert!(npz::names(Cursor::new(archive)).unwrap() == ["weights", "bias"]);
+    let bias: F64Tensor = npz::read_array(Cursor::new(archive), "bias").unwrap();
+    assert!(bias.values == [0.5, -1., 2.]);
+    let missing = npz::read_array::<f32>(Cursor::new(archive), "scale").unwrap_err();
+    assert!(missing.kind() == std::io::ErrorKind::NotFound);
+
+    // a directory claiming 2 GiB for `weights` is caught by the size check, not trusted up front
+    let mut inflated = archive.to_vec();
+    inflated[437..441].copy_from_slice(&0x7fff_ffffu32.to_le_bytes());
+    let error = npz::read_array::<f32>(Cursor::new(inflated), "weights").unwrap_err();
+    assert!(error.kind() == std::io::ErrorKind::InvalidData);
+
+    // round trip through a stored archive, views gathered and layouts kept
+    let w = F32Tensor::rand_uniform(vec![3, 4], 15).with_layout(Layout::ColMajor);
+    let b = F32Tensor::rand_uniform(vec![4], 16);
+    let path = std::env::temp_dir().join(format!("aml-npz-{}.npz", std::process::id()));
+    npz::save(
+        &path,
+        &[
+            ("w", w.as_tensor_ref()),
+            ("b", b.as_tensor_ref()),
+            ("v", w.view(1..3, 0..2)),
+        ],
+    )
+    .unwrap();
+    let back: HashMap<String, F32Tensor> = npz::load(&path).unwrap();
+    assert!(back["w"].layout == Layout::ColMajor && back["w"].values == w.values);
+    assert!(back["b"].shape == [4] && back["b"].values == b.values);
+    assert!(back["v"].shape == [2, 2] && back["v"][[1, 1]] == w[[2, 1]]);
+    let v: F32Tensor = npz::load_array(&path, "v").unwrap();
+    assert!(v.values == back["v"].values);
+    std::fs::remove_file(&path).unwrap();
+
+    let mut bytes = Vec::new();
+    let twice = npz::write(
+        &mut bytes,
+        &[("w", w.as_tensor_ref()), ("w", b.as_tensor_ref())],
+    );
+    assert!(twice.unwrap_err().kind() == std::io::ErrorKind::InvalidData);
+
+    // a flipped bit fails the CRC
+    let mut bytes = Vec::new();
+    npz::write(&mut bytes, &[("b", b)]).unwrap();
+    let last_value = bytes.len() - 22 - 46 - 5 - 1;
+    bytes[last_value] ^= 1;
+    let corrupt = npz::read::<f32>(Cursor::new(&bytes)).unwrap_err();
+    assert!(corrupt.kind() == std::io::ErrorKind::InvalidData);
+    let not_zip = npz::read::<f32>(Cursor::new(&b"not a zip"[..])).unwrap_err();
+    assert!(not_zip.kind() == std::io::ErrorKind::InvalidData);
+}
+
+#[test]
+pub fn safetensors_files() {
+    use crate::io::{safetensors, DType};
+
+    fn file(header: &str, data: &[u8], tag: &str) -> std::path::PathBuf {
+        let path = std::env::temp_dir().join(format!(
+            "aml-safetensors-{}-{}.safetensors",
+            std::process::id(),
+            tag
+        ));
+        let mut bytes = (header.len() as u64).to_le_bytes().to_vec();
+        bytes.extend(header.as_bytes());
+        bytes.extend(data);
+        std::fs::write(&path, bytes).unwrap();
+        path
+    }
+
+    let a = F32Tensor::rand_uniform(vec![2, 3], 17);
+    let mut data: Vec<u8> = a.values.iter().flat_map(|v| v.to_le_bytes()).collect();
+    data.extend(
+        [1.5f32, -2.]
+            .iter()
+            .flat_map(|v| bf16::from_f32(*v).to_le_bytes()),
+    );
+    data.extend([0u8; 4]);
+    data.extend(0.25f64.to_le_bytes());
+    let header = concat!(
+        r#"{"__metadata__": {"format": "pt"}, "#,
+        r#""a": {"dtype": "F32", "shape": [2, 3], "data_offsets": [0, 24]}, "#,
+        r#""bé": {"dtype": "BF16", "shape": [2], "data_offsets": [24, 28]}, "#,
+        r#""c": {"dtype": "F64", "shape": [], "data_offsets": [32, 40]}}"#,
+    );
+    // padded so the values start on 8 bytes, as the writers do
+    let padding = header.len().next_multiple_of(8) - header.len();
+    let header = format!("{}{}", header, " ".repeat(padding));
+    let path = file(&header, &data, "ok");
+    let views = safetensors::load(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    assert!(views.len() == 3);
+    let view = &views["a"];
+    assert!(view.dtype() == DType::F32 && view.shape() == &[2, 3]);
+    assert!(view.is_mapped() == cfg!(all(unix, feature = "mmap")));
+    let values = view.tensor_ref::<f32>();
+    assert!(values.shape == [2, 3] && values.values == a.values);
+    assert!(std::ptr::eq(
+        values.values.as_ptr().cast(),
+        view.bytes().as_ptr()
+    ));
+    assert!(view.to_tensor::<f64>()[[1, 2]] == a[[1, 2]] as f64);
+    assert!(
+        view.try_tensor_ref::<f16>().unwrap_err()
+            == AmlError::DTypeMismatch {
+                expected: DType::F16,
+                found: DType::F32
+            }
+    );
+    assert!(views["bé"].to_tensor::<f32>().values == [1.5, -2.]);
+    assert!(views["bé"].tensor_ref::<bf16>().values[1] == bf16::from_f32(-2.));
+    assert!(views["c"].shape().is_empty() && views["c"].tensor_ref::<f64>().values == [0.25]);
+
+    // views keep the file mapped after the map is dropped
+    let c = views["c"].clone();
+    drop(views);
+    assert!(c.to_tensor::<f32>().values == [0.25]);
+
+    // one byte of header off: still readable, but not in place
+    let header = format!("{} ", header);
+    let path = file(&header, &data, "misaligned");
+    let views = safetensors::load(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    assert!(
+        views["a"].try_tensor_ref::<f32>().unwrap_err() == AmlError::MisalignedBytes { align: 4 }
+    );
+    assert!(views["a"].to_tensor::<f32>().values == a.values);
+
+    for (header, tag) in [
+        (
+            r#"{"a": {"dtype": "I64", "shape": [1], "data_offsets": [0, 8]}}"#,
+            "int",
+        ),
+        (
+            r#"{"a": {"dtype": "F32", "shape": [3], "data_offsets": [0, 8]}}"#,
+            "short",
+        ),
+        (
+            r#"{"a": {"dtype": "F32", "shape": [4], "data_offsets": [32, 48]}}"#,
+            "past",
+        ),
+        (
+            r#"{"a": {"dtype": "F32", "shape": [4611686018427387904, 4], "data_offsets": [0, 0]}}"#,
+            "huge",
+        ),
+        (
+            r#"{"a": {"dtype": "F32", "shape": [2], "data_offsets": [0, 8]},}"#,
+            "comma",
+        ),
+        (r#"["a"]"#, "array"),
+    ] {
+        let path = file(header, &data, tag);
+        let error = safetensors::load(&path).unwrap_err();
+        std::fs::remove_file(&path).unwrap();
+        assert!(error.kind() == std::io::ErrorKind::InvalidData);
+    }
+}
+
+#[test]
+pub fn onnx_initializers() {
+    use crate::io::onnx;
+    use std::collections::HashMap;
+
+    fn varint(mut value: u64) -> Vec<u8> {
+        let mut out = Vec::new();
+        while value >= 0x80 {
+            out.push(value as u8 | 0x80);
+            value >>= 7;
+        }
+        out.push(value as u8);
+        out
+    }
+    fn bytes(field: u64, payload: &[u8]) -> Vec<u8> {
+        let mut out = varint(field << 3 | 2);
+        out.extend(varint(payload.len() as u64));
+        out.extend(payload);
+        out
+    }
+    fn number(field: u64, value: u64) -> Vec<u8> {
+        let mut out = varint(field << 3);
+        out.extend(varint(value));
+        out
+    }
+    // a `TensorProto` with unpacked dims
+    fn tensor(name: &str, data_type: u64, dims: &[u64], data: Vec<u8>) -> Vec<u8> {
+        let mut out: Vec<u8> = dims.iter().flat_map(|&dim| number(1, dim)).collect();
+        out.extend(number(2, data_type));
+        out.extend(bytes(8, name.as_bytes()));
+        out.extend(data);
+        bytes(5, &out)
+    }
+
+    let w = F32Tensor::rand_uniform(vec![2, 3], 18);
+    let raw: Vec<u8> = w.values.iter().flat_map(|v| v.to_le_bytes()).collect();
+    let packed: Vec<u8> = [0.5f32, -1., 2.]
+        .iter()
+        .flat_map(|v| v.to_le_bytes())
+        .collect();
+    let halves: Vec<u8> = [1.5f32, -0.25]
+        .iter()
+        .flat_map(|v| varint(f16::from_f32(*v).to_bits() as u64))
+        .collect();
+    // unpacked doubles, each its own fixed 64 bit field
+    let doubles: Vec<u8> = [3.0f64, 4.0]
+        .iter()
+        .flat_map(|v| varint(10 << 3 | 1).into_iter().chain(v.to_le_bytes()))
+        .collect();
+    let mut graph = bytes(1, &bytes(4, b"Gemm"));
+    graph.extend(bytes(2, b"main"));
+    graph.extend(tensor("w", 1, &[2, 3], bytes(9, &raw)));
+    graph.extend(tensor("b", 1, &[3], bytes(4, &packed)));
+    graph.extend(tensor("h", 10, &[2], bytes(5, &halves)));
+    graph.extend(tensor("d", 11, &[2], doubles));
+    graph.extend(tensor("shape", 7, &[2], bytes(7, &[1, 2])));
+    let mut external = bytes(
+        13,
+        &[bytes(1, b"location"), bytes(2, b"weights.bin")].concat(),
+    );
+    external.extend(bytes(13, &[bytes(1, b"offset"), bytes(2, b"4")].concat()));
+    external.extend(bytes(13, &[bytes(1, b"length"), bytes(2, b"8")].concat()));
+    external.extend(number(14, 1));
+    graph.extend(tensor("e", 1, &[2], external));
+    let mut model = number(1, 8);
+    model.extend(bytes(2, b"pytorch"));
+    model.extend(bytes(7, &graph));
+
+    let dir = std::env::temp_dir().join(format!("aml-onnx-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(dir.join("model.onnx"), &model).unwrap();
+    let external: Vec<u8> = [9f32, 6., 7., 9.]
+        .iter()
+        .flat_map(|v| v.to_le_bytes())
+        .collect();
+    std::fs::write(dir.join("weights.bin"), external).unwrap();
+    let tensors: HashMap<String, F32Tensor> = onnx::load(dir.join("model.onnx")).unwrap();
+
+    // external data may not leave the model's directory
+    let outside = dir.join("weights.bin").to_string_lossy().into_owned();
+    for location in ["../weights.bin", "data/../../weights.bin", outside.as_str()] {
+        let mut external = bytes(
+            13,
+            &[bytes(1, b"location"), bytes(2, location.as_bytes())].concat(),
+        );
+        external.extend(number(14, 1));
+        let graph = tensor("e", 1, &[4], external);
+        std::fs::write(dir.join("escape.onnx"), bytes(7, &graph)).unwrap();
+        let error = onnx::load::<f32>(dir.join("escape.onnx")).unwrap_err();
+        assert!(error.kind() == std::io::ErrorKind::InvalidData);
+    }
+    std::fs::remove_dir_all(&dir).unwrap();
+    assert!(tensors.len() == 5 && !tensors.contains_key("shape"));
+    assert!(tensors["w"].shape == [2, 3] && tensors["w"].values == w.values);
+    assert!(tensors["b"].values == [0.5, -1., 2.]);
+    assert!(tensors["h"].values == [1.5, -0.25]);
+    assert!(tensors["d"].values == [3., 4.]);
+    assert!(tensors["e"].values == [6., 7.]);
+
+    // from a reader there is no directory for external data
+    let error = onnx::read::<f32>(&model[..]).unwrap_err();
+    assert!(error.kind() == std::io::ErrorKind::InvalidData);
+    let mut short = bytes(2, b"main");
+    short.extend(tensor("w", 1, &[2, 4], bytes(9, &raw)));
+    let error = onnx::read::<f32>(&bytes(7, &short)[..]).unwrap_err();
+    assert!(error.kind() == std::io::ErrorKind::InvalidData);
+    let huge = tensor("w", 1, &[1 << 62, 4], bytes(9, &[]));
+    let error = onnx::read::<f32>(&bytes(7, &huge)[..]).unwrap_err();
+    assert!(error.kind() == std::io::ErrorKind::InvalidData);
+    let error = onnx::read::<f32>(&model[..model.len() - 1]).unwrap_err();
+    assert!(error.kind() == std::io::ErrorKind::InvalidData);
+}
+
+#[test]
+pub fn matrix_market_files() {
+    use crate::io::mtx;
+
+    let general = "%%MatrixMarket matrix coordinate real general\n\
+                   % a comment\n\
+                   \n\
+                   3 4 4\n\
+                   1 1 1.5\n\
+                   3 4 -2e1\n\
+                   2 2 3\n\
+                   2 2 1\n";
+    let dense: F32Tensor = mtx::read(general.as_bytes()).unwrap();
+    assert!(dense.shape == [3, 4] && dense.layout == Layout::RowMajor);
+    assert!(dense[[0, 0]] == 1.5 && dense[[2, 3]] == -20. && dense[[1, 1]] == 4.);
+    assert!(dense.values.iter().filter(|v| **v != 0.).count() == 3);
+    let coordinates: mtx::Coordinates<f64> = mtx::read_coordinates(general.as_bytes()).unwrap();
+    assert!(coordinates.shape == [3, 4] && coordinates.entries[1] == (2, 3, -20.));
+
+    let symmetric = "%%MatrixMarket MATRIX Coordinate Pattern Symmetric\n3 3 2\n1 1\n3 1\n";
+    let dense: F64Tensor = mtx::read(symmetric.as_bytes()).unwrap();
+    assert!(dense.values == [1., 0., 1., 0., 0., 0., 1., 0., 0.]);
+
+    // column by column, lower triangles only
+    let array = "%%MatrixMarket matrix array integer general\n2 3\n1\n2\n3\n4\n5\n6\n";
+    let dense: F32Tensor = mtx::read(array.as_bytes()).unwrap();
+    assert!(dense.layout == Layout::ColMajor && dense[[1, 0]] == 2. && dense[[0, 2]] == 5.);
+    let skew = "%%MatrixMarket matrix array real skew-symmetric\n3 3\n1\n2\n3\n";
+    let dense: F32Tensor = mtx::read(skew.as_bytes()).unwrap();
+    assert!(dense[[1, 0]] == 1. && dense[[0, 1]] == -1. && dense[[2, 1]] == 3.);
+    assert!(dense[[1, 2]] == -3. && dense[[1, 1]] == 0.);
+    let symmetric = "%%MatrixMarket matrix array real symmetric\n2 2\n1\n2\n3\n";
+    let dense: F32Tensor = mtx::read(symmetric.as_bytes()).unwrap();
+    assert!(dense.to_contiguous().values == [1., 2., 2., 3.]);
+
+    let path = std::env::temp_dir().join(format!("aml-mtx-{}.mtx", std::process::id()));
+    std::fs::write(&path, general).unwrap();
+    assert!(
+        mtx::load::<f32>(&path).unwrap().values
+            == mtx::read::<f32>(general.as_bytes()).unwrap().values
+    );
+    assert!(mtx::load_coordinates::<f32>(&path).unwrap().entries.len() == 4);
+    std::fs::remove_file(&path).unwrap();
+
+    for bad in [
+        "%%MatrixMarket matrix coordinate complex general\n1 1 1\n1 1 1 0\n",
+        "%%MatrixMarket matrix coordinate real general\n2 2 1\n3 1 1\n",
+        "%%MatrixMarket matrix coordinate real general\n2 2 2\n1 1 1\n",
+        "%%MatrixMarket matrix coordinate real general\n2 2 1\n1 1 x\n",
+        "%%MatrixMarket matrix array real symmetric\n2 3\n1\n",
+        "not a matrix\n",
+    ] {
+        let error = mtx::read::<f32>(bad.as_bytes()).unwrap_err();
+        assert!(error.kind() == std::io::ErrorKind::InvalidData);
+    }
+    assert!(mtx::read_coordinates::<f32>(array.as_bytes()).is_err());
+
+    // sizes the file does not back, or that overflow, ask for no memory up front
+    let huge = "%%MatrixMarket matrix array real general\n3000000 3000000\n";
+    let error = mtx::read::<f32>(huge.as_bytes()).unwrap_err();
+    assert!(error.kind() == std::io::ErrorKind::InvalidData);
+    for wrapping in [
+        "%%MatrixMarket matrix coordinate real general\n4294967296 4294967296 0\n",
+        "%%MatrixMarket matrix array real general\n4294967296 4294967296\n1\n",
+    ] {
+        let error = mtx::read::<f32>(wrapping.as_bytes()).unwrap_err();
+        assert!(error.kind() == std::io::ErrorKind::InvalidData);
+        assert!(error.to_string().contains("more values than fit"));
+    }
+    let error = mtx::read_coordinates::<f32>(
+        "%%MatrixMarket matrix coordinate real general\n4294967296 4294967296 0\n".as_bytes(),
+    )
+    .unwrap_err();
+    assert!(error.kind() == std::io::ErrorKind::InvalidData);
+    let coordinates = mtx::Coordinates::<f32> {
+        shape: [1 << 40, 1 << 20],
+        entries: vec![],
+    };
+    assert!(matches!(
+        coordinates.try_to_tensor(),
+        Err(AmlError::AllocationFailed { .. })
+    ));
+    let coordinates = mtx::Coordinates::<f32> {
+        shape: [2, 2],
+        entries: vec![(2, 0, 1f32)],
+    };
+    assert!(matches!(
+        coordinates.try_to_tensor(),
+        Err(AmlError::IndexOutOfBounds { .. })
+    ));
+}
+
+#[test]
+pub fn delimited_text() {
+    use crate::io::csv;
+
+    let text = "x,y,z\n1, 2.5 ,\"-3\"\r\n\n4,,6e-1\n";
+    let t: F32Tensor =
+        csv::read(text.as_bytes(), csv::Options::default().with_header_rows(1)).unwrap();
+    assert!(t.shape == [2, 3] && t.layout == Layout::RowMajor);
+    assert!(t.values[..4] == [1., 2.5, -3., 4.] && t.values[4].is_nan() && t.values[5] == 0.6);
+
+    let text = "1\t2\n3\t4\n5\t6\n";
+    let path = std::env::temp_dir().join(format!("aml-csv-{}.tsv", std::process::id()));
+    std::fs::write(&path, text).unwrap();
+    let t: F64Tensor = csv::load(&path, csv::Options::tsv()).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    assert!(t.shape == [3, 2] && t.values == [1., 2., 3., 4., 5., 6.]);
+    let t: F32Tensor = csv::read(&b""[..], csv::Options::default()).unwrap();
+    assert!(t.shape == [0, 0]);
+
+    // ragged rows, and text where a number should be
+    for bad in ["1,2\n3\n", "1,2\n3,x\n", "1;2\n"] {
+        let error = csv::read::<f32>(bad.as_bytes(), csv::Options::default()).unwrap_err();
+        assert!(error.kind() == std::io::ErrorKind::InvalidData);
+    }
+    let t: F32Tensor = csv::read(&b"1;2\n"[..], csv::Options::new(b';')).unwrap();
+    assert!(t.values == [1., 2.]);
+}
+
+#[test]
+pub fn sgemm_softmax_matches_separate_pass() {
+    for (m, n, k) in [(301, 67, 41), (5, 1, 3), (0, 4, 2)] {
+        let q = F32Tensor::new(
+            (0..m * k).map(|v| (v % 7) as f32 - 3f32).collect(),
+            vec![m, k],
+        );
+        let keys = F32Tensor::new(
+            (0..n * k).map(|v| (v % 5) as f32 - 2f32).collect(),
+            vec![n, k],
+        );
+        let scale = 0.25f32;
+        let mut expected = F32Tensor::zeros(vec![m, n]);
+        sgemm_with(
+            &q,
+            false,
+            &keys,
+            true,
+            GemmParams::new(scale, 0f32),
+            &mut expected,
+        );
+        for row in expected.values.chunks_exact_mut(n) {
+            let max = row.iter().copied().fold(f32::NEG_INFINITY, f32::max);
+            row.iter_mut().for_each(|v| *v = (*v - max).exp());
+            let sum: f32 = row.iter().sum();
+            row.iter_mut().for_each(|v| *v /= sum);
+        }
+
+        let mut c = F32Tensor::zeros(vec![m, n]);
+        sgemm_softmax(&q, false, &keys, true, scale, &mut c);
+        assert!(c
+            .values
+            .iter()
+            .zip(&expected.values)
+            .all(|(c, e)| (c - e).abs() < 1e-6));
+        for row in c.values.chunks_exact(n) {
+            assert!((row.iter().sum::<f32>() - 1f32).abs() < 1e-5);
+        }
+
+        // with the queries stored transposed
+        let q_t = F32Tensor::new(
+            (0..k * m)
+                .map(|idx| q.values[(idx % m) * k + idx / m])
+                .collect(),
+            vec![k, m],
+        );
+        let mut c = F32Tensor::zeros(vec![m, n]);
+        sgemm_softmax(&q_t, true, &keys, true, scale, &mut c);
+        assert!(c
+            .values
+            .iter()
+            .zip(&expected.values)
+            .all(|(c, e)| (c - e).abs() < 1e-6));
+    }
+
+    // rows longer than `nc`, whose maximum rises from one block of columns to the next, so the
+    // sums and earlier exponentials are rescaled
+    let (m, n, k) = (7, 3 * block_sizes().nc + 5, 3);
+    assert!(n > block_sizes().nc);
+    let q = F32Tensor::new(
+        (0..m * k).map(|v| (v % 4) as f32 / 4f32).collect(),
+        vec![m, k],
+    );
+    let keys = F32Tensor::new(
+        (0..n * k)
+            .map(|v| (v / k) as f32 / n as f32 * 8f32 - (v % 3) as f32)
+            .collect(),
+        vec![n, k],
+    );
+    let mut expected = F32Tensor::zeros(vec![m, n]);
+    sgemm(&q, false, &keys, true, &mut expected);
+    for row in expected.values.chunks_exact_mut(n) {
+        let max = row.iter().copied().fold(f32::NEG_INFINITY, f32::max);
+        row.iter_mut().for_each(|v| *v = (*v - max).exp());
+        let sum: f32 = row.iter().sum();
+        row.iter_mut().for_each(|v| *v /= sum);
+    }
+    let mut c = F32Tensor::zeros(vec![m, n]);
+    sgemm_softmax(&q, false, &keys, true, 1f32, &mut c);
+    for (c, e) in c.values.iter().zip(&expected.values) {
+        assert!((c - e).abs() <= 1e-6 * e.max(1e-3), "{} {}", c, e);
+    }
+
+    let a = F32Tensor::zeros(vec![2, 3]);
+    let mut c = F32Tensor::zeros(vec![2, 2]).with_layout(Layout::ColMajor);
+    assert!(try_sgemm_softmax(&a, false, &a, true, 1f32, &mut c).is_err());
+    let mut c = F32Tensor::zeros(vec![2, 3]);
+    assert!(try_sgemm_softmax(&a, false, &a, true, 1f32, &mut c).is_err());
+}
+
+#[test]
+pub fn elementwise_ops() {
+    // large enough to split across threads, with a tail past the last full vector
+    let (m, n) = (301, 299);
+    let a = F32Tensor::new(
+        (0..m * n).map(|v| (v % 13) as f32 - 6f32).collect(),
+        vec![m, n],
+    );
+    let b = F32Tensor::new(
+        (0..m * n).map(|v| (v % 7) as f32 + 1f32).collect(),
+        vec![m, n],
+    );
+    let c = F32Tensor::new((0..m * n).map(|v| (v % 5) as f32).collect(), vec![m, n]);
+    let expect = |t: &F32Tensor, f: &dyn Fn(usize) -> f32| {
+        t.shape[..] == [m, n] && t.values.iter().enumerate().all(|(idx, v)| *v == f(idx))
+    };
+    let (x, y, z) = (&a.values, &b.values, &c.values);
+
+    assert!(expect(&add(&a, &b), &|i| x[i] + y[i]));
+    assert!(expect(&sub(&a, &b), &|i| x[i] - y[i]));
+    assert!(expect(&mul(&a, &b), &|i| x[i] * y[i]));
+    assert!(expect(&div(&a, &b), &|i| x[i] / y[i]));
+    assert!(expect(&fma(&a, &b, &c), &|i| x[i] * y[i] + z[i]));
+    assert!(expect(&add_scalar(&a, 2.5), &|i| x[i] + 2.5));
+    assert!(expect(&sub_scalar(&a, 2.5), &|i| x[i] - 2.5));
+    assert!(expect(&mul_scalar(&a, -3.), &|i| x[i] * -3.));
+    assert!(expect(&div_scalar(&a, 3.), &|i| x[i] / 3.));
+
+    // views and tensors stored column-major are read in the layout of `a`
+    let b_t = F32Tensor::new(
+        (0..m * n).map(|idx| y[(idx % m) * n + idx / m]).collect(),
+        vec![m, n],
+    )
+    .with_layout(Layout::ColMajor);
+    assert!(expect(&add(&a, &b_t), &|i| x[i] + y[i]));
+    let window = a.view(1..3, 2..5);
+    let sum = add(&window, &window);
+    assert!(sum.shape[..] == [2, 3] && sum.values == [-8., -6., -4., -8., -6., -4.]);
+
+    let short = F32Tensor::zeros(vec![m, n - 1]);
+    assert!(try_add(&a, &short).is_err());
+    assert!(try_fma(&a, &b, &short).is_err());
+}
+
+#[test]
+pub fn elementwise_broadcasting() {
+    let (m, n) = (301, 299);
+    let a = F32Tensor::new(
+        (0..m * n).map(|v| (v % 13) as f32 - 6f32).collect(),
+        vec![m, n],
+    );
+    let row: Vec<f32> = (0..n).map(|v| (v % 7) as f32 + 1f32).collect();
+    let col: Vec<f32> = (0..m).map(|v| (v % 5) as f32 - 2f32).collect();
+    let x = &a.values;
+    let expect = |t: &F32Tensor, shape: &[usize], f: &dyn Fn(usize, usize) -> f32| {
+        t.shape[..] == *shape && (0..shape[0]).all(|i| (0..shape[1]).all(|j| t[[i, j]] == f(i, j)))
+    };
+
+    // a bias over the columns, as [1, n] or [n], and one over the rows as [m, 1]
+    let bias = F32Tensor::new(row.clone(), vec![1, n]);
+    assert!(expect(&add(&a, &bias), &[m, n], &|i, j| x[i * n + j] + row[j]));
+    let bias = F32Tensor::new(row.clone(), vec![n]);
+    assert!(expect(&sub(&a, &bias), &[m, n], &|i, j| x[i * n + j] - row[j]));
+    let column = F32Tensor::new(col.clone(), vec![m, 1]);
+    assert!(expect(&div(&a, &bias), &[m, n], &|i, j| x[i * n + j] / row[j]));
+    assert!(expect(&mul(&a, &column), &[m, n], &|i, j| x[i * n + j] * col[i]));
+
+    // either side may be stretched, along different axes, and `sub` keeps its order
+    assert!(expect(&sub(&column, &bias), &[m, n], &|i, j| col[i] - row[j]));
+    assert!(expect(&sub(&bias, &a), &[m, n], &|i, j| row[j] - x[i * n + j]));
+    let scalar = F32Tensor::new(vec![2f32], vec![1, 1]);
+    assert!(expect(&div(&scalar, &bias), &[1, n], &|_, j| 2f32 / row[j]));
+    assert!(expect(&fma(&column, &bias, &a), &[m, n], &|i, j| col[i]
+        * row[j]
+        + x[i * n + j]));
+
+    // a column-major `a` gives a column-major result
+    let a_t = F32Tensor::new(
+        (0..m * n).map(|idx| x[(idx % m) * n + idx / m]).collect(),
+        vec![m, n],
+    )
+    .with_layout(Layout::ColMajor);
+    let sum = add(&a_t, &bias);
+    assert!(sum.layout == Layout::ColMajor);
+    assert!(expect(&sum, &[m, n], &|i, j| x[i * n + j] + row[j]));
+
+    // more axes, aligned from the last
+    let cube = F32Tensor::new((0..24).map(|v| v as f32).collect(), vec![2, 3, 4]);
+    let middle = F32Tensor::new(vec![100f32, 200f32, 300f32], vec![3, 1]);
+    let sum = add(&cube, &middle);
+    assert!(sum.shape[..] == [2, 3, 4]);
+    assert!(sum
+        .values
+        .iter()
+        .enumerate()
+        .all(|(idx, v)| *v == (idx as f32) + 100f32 * ((idx / 4 % 3) + 1) as f32));
+
+    let short = F32Tensor::zeros(vec![n - 1]);
+    assert!(matches!(
+        try_add(&a, &short),
+        Err(AmlError::BroadcastMismatch { .. })
+    ));
+    assert!(try_fma(&a, &bias, &short).is_err());
+}
+
+#[test]
+pub fn reductions() {
+    let (m, n) = (301, 299);
+    let values: Vec<f32> = (0..m * n).map(|v| (v % 13) as f32 - 6f32).collect();
+    let a = F32Tensor::new(values.clone(), vec![m, n]);
+    let (rows, cols) = (0..m, 0..n);
+    let at = |i: usize, j: usize| values[i * n + j];
+    let total: f32 = values.iter().sum();
+
+    assert!(sum(&a) == total);
+    assert!(mean(&a) == total / (m * n) as f32);
+    assert!(max(&a) == 6f32 && min(&a) == -6f32);
+    assert!(argmax(&a) == Some(12));
+
+    let sums = sum_axis(&a, 0);
+    assert!(sums.shape[..] == [n]);
+    assert!(cols
+        .clone()
+        .all(|j| sums[[j]] == rows.clone().map(|i| at(i, j)).sum::<f32>()));
+    let sums = sum_axis(&a, 1);
+    assert!(sums.shape[..] == [m]);
+    assert!(rows
+        .clone()
+        .all(|i| sums[[i]] == cols.clone().map(|j| at(i, j)).sum::<f32>()));
+    let means = mean_axis(&a, 1);
+    assert!(rows.clone().all(|i| means[[i]] == sums[[i]] / n as f32));
+    let largest = max_axis(&a, 0);
+    assert!(cols
+        .clone()
+        .all(|j| largest[[j]] == rows.clone().map(|i| at(i, j)).fold(f32::MIN, f32::max)));
+    let smallest = min_axis(&a, 1);
+    assert!(rows
+        .clone()
+        .all(|i| smallest[[i]] == cols.clone().map(|j| at(i, j)).fold(f32::MAX, f32::min)));
+
+    // the first largest along each axis, row-major whatever the layout
+    let first_max = |i: usize| (0..n).find(|j| at(i, *j) == 6f32).unwrap();
+    assert!(argmax_axis(&a, 1) == rows.clone().map(first_max).collect::<Vec<_>>());
+    let a_t = F32Tensor::new(
+        (0..m * n)
+            .map(|idx| values[(idx % m) * n + idx / m])
+            .collect(),
+        vec![m, n],
+    )
+    .with_layout(Layout::ColMajor);
+    assert!(argmax_axis(&a_t, 1) == argmax_axis(&a, 1));
+    assert!(argmax_axis(&a_t, 0) == argmax_axis(&a, 0));
+    assert!(argmax(&a_t) == Some(12));
+    let col_sums = sum_axis(&a_t, 0);
+    assert!(col_sums.layout == Layout::ColMajor && col_sums.values == sum_axis(&a, 0).values);
+
+    // a sum of many small values stays close, pairwise, where a running sum drifts
+    let ones = F32Tensor::full(vec![1 << 24 | 3], 0.1f32);
+    assert!((sum(&ones) - 0.1 * (1 << 24 | 3) as f32).abs() < 1f32);
+    let column = F32Tensor::full(vec![1 << 20, 2], 0.1f32);
+    assert!(sum_axis(&column, 0)
+        .values
+        .iter()
+        .all(|v| (v - 104857.6).abs() < 1f32));
+
+    // NaNs are skipped by max and min
+    let nan = F32Tensor::new(vec![1f32, f32::NAN, 3f32, f32::NAN, -2f32], vec![5]);
+    assert!(max(&nan) == 3f32 && min(&nan) == -2f32 && argmax(&nan) == Some(2));
+
+    let empty = F32Tensor::zeros(vec![0, 3]);
+    assert!(sum(&empty) == 0f32 && argmax(&empty).is_none());
+    assert!(max_axis(&empty, 0).values == [f32::NEG_INFINITY; 3]);
+    assert!(argmax_axis(&empty, 1).is_empty());
+    assert!(try_argmax_axis(&empty, 0).is_err());
+    assert!(try_sum_axis(&a, 2).is_err());
+}
+
+#[test]
+pub fn softmax_rows() {
+    // logits far past where e^x overflows, as a softmax must take them
+    let (m, n) = (301, 131);
+    let logits: Vec<f32> = (0..m * n)
+        .map(|v| ((v * 7919) % 211) as f32 * 10f32 - 1000f32)
+        .collect();
+    let reference: Vec<f32> = logits
+        .chunks_exact(n)
+        .flat_map(|row| {
+            let max = row
+                .iter()
+                .fold(f64::NEG_INFINITY, |max, v| max.max(*v as f64));
+            let exps: Vec<f64> = row.iter().map(|v| (*v as f64 - max).exp()).collect();
+            let sum: f64 = exps.iter().sum();
+            exps.into_iter().map(move |e| (e / sum) as f32)
+        })
+        .collect();
+    let close = |values: &[f32]| {
+        values
+            .iter()
+            .zip(&reference)
+            .all(|(v, r)| (v - r).abs() < 1e-6)
+    };
+
+    let a = F32Tensor::new(logits.clone(), vec![m, n]);
+    let probs = softmax(&a);
+    assert!(probs.shape[..] == [m, n] && close(&probs.values));
+    assert!(probs
+        .values
+        .chunks_exact(n)
+        .all(|row| (row.iter().sum::<f32>() - 1f32).abs() < 1e-5));
+
+    // a column-major input comes out row-major, and more axes are more rows
+    let a_t = F32Tensor::new(
+        (0..m * n)
+            .map(|idx| logits[(idx % m) * n + idx / m])
+            .collect(),
+        vec![m, n],
+    )
+    .with_layout(Layout::ColMajor);
+    assert!(softmax(&a_t).values == probs.values);
+    let cube = F32Tensor::new(logits.clone(), vec![1, m, n]);
+    assert!(softmax(&cube).values == probs.values);
+
+    // in place, inside a wider matrix whose other columns are left alone
+    let mut wide = F32Tensor::new(
+        logits
+            .chunks_exact(n)
+            .flat_map(|row| row.iter().copied().chain([5f32; 3]))
+            .collect(),
+        vec![m, n + 3],
+    );
+    softmax_in_place(&mut wide.window_mut(0..m, 0..n));
+    for (row, expected) in wide
+        .values
+        .chunks_exact(n + 3)
+        .zip(probs.values.chunks_exact(n))
+    {
+        assert!(row[..n] == *expected && row[n..] == [5f32; 3]);
+    }
+    let mut col_major = F32Tensor::zeros(vec![2, 2]).with_layout(Layout::ColMajor);
+    assert!(try_softmax_in_place(&mut col_major).is_err());
+
+    // masked logits get nothing, and a NaN poisons only its own row
+    let masked = F32Tensor::new(
+        vec![
+            0f32,
+            f32::NEG_INFINITY,
+            0f32,
+            f32::NEG_INFINITY,
+            1f32,
+            f32::NAN,
+        ],
+        vec![2, 3],
+    );
+    let probs = softmax(&masked);
+    assert!(probs.values[..3] == [0.5, 0., 0.5]);
+    assert!(probs.values[3..].iter().all(|v| v.is_nan()));
+}
+
+#[test]
+pub fn layer_and_rms_norm() {
+    // rows offset far from 0, where the mean of squares less the squared mean cancels badly
+    let (m, n) = (301, 131);
+    let x: Vec<f32> = (0..m * n)
+        .map(|v| ((v * 7919) % 211) as f32 * 0.25f32 + 10_000f32)
+        .collect();
+    let gamma: Vec<f32> = (0..n).map(|j| (j % 5) as f32 * 0.5f32 + 0.5f32).collect();
+    let beta: Vec<f32> = (0..n).map(|j| (j % 3) as f32 - 1f32).collect();
+    let eps = 1e-5f32;
+    let reference = |center: bool, shift: bool| -> Vec<f32> {
+        x.chunks_exact(n)
+            .flat_map(|row| {
+                let row: Vec<f64> = row.iter().map(|v| *v as f64).collect();
+                let mean = match center {
+                    true => row.iter().sum::<f64>() / n as f64,
+                    false => 0f64,
+                };
+                let var = row.iter().map(|v| (v - mean) * (v - mean)).sum::<f64>() / n as f64;
+                let scale = 1f64 / (var + eps as f64).sqrt();
+                (0..n)
+                    .map(|j| {
+                        let y = (row[j] - mean) * scale * gamma[j] as f64;
+                        match shift {
+                            true => (y + beta[j] as f64) as f32,
+                            false => y as f32,
+                        }
+                    })
+                    .collect::<Vec<f32>>()
+            })
+            .collect()
+    };
+    let close = |values: &[f32], expected: &[f32], tol: f32| {
+        values.len() == expected.len()
+            && values
+                .iter()
+                .zip(expected)
+                .all(|(v, e)| (v - e).abs() <= tol * e.abs().max(1f32))
+    };
+
+    let a = F32Tensor::new(x.clone(), vec![m, n]);
+    let normed = layer_norm(&a, &gamma, &beta, eps);
+    assert!(normed.shape[..] == [m, n]);
+    assert!(close(&normed.values, &reference(true, true), 1e-3));
+    let rms = rms_norm(&a, &gamma, eps);
+    assert!(close(&rms.values, &reference(false, false), 1e-5));
+
+    // a column-major input comes out row-major, and more axes are more rows
+    let a_t = F32Tensor::new(
+        (0..m * n).map(|idx| x[(idx % m) * n + idx / m]).collect(),
+        vec![m, n],
+    )
+    .with_layout(Layout::ColMajor);
+    assert!(layer_norm(&a_t, &gamma, &beta, eps).values == normed.values);
+    let cube = F32Tensor::new(x.clone(), vec![m, 1, n]);
+    assert!(rms_norm(&cube, &gamma, eps).values == rms.values);
+
+    // a constant row normalizes to `beta`
+    let flat = F32Tensor::full(vec![2, n], 3f32);
+    assert!(layer_norm(&flat, &gamma, &beta, eps)
+        .values
+        .chunks_exact(n)
+        .all(|row| row == beta));
+
+    assert!(matches!(
+        try_layer_norm(&a, &gamma[1..], &beta, eps),
+        Err(AmlError::SizeMismatch { .. })
+    ));
+    assert!(try_layer_norm(&a, &gamma, &beta[1..], eps).is_err());
+    assert!(try_rms_norm(&a, &[1f32; 3], eps).is_err());
+}
+
+#[cfg(feature = "serde")]
+#[test]
+pub fn serde_round_trips() {
+    use serde::{Deserialize, Serialize};
+
+    #[derive(Serialize, Deserialize)]
+    struct Checkpoint {
+        step: u32,
+        weights: F32Tensor,
+        bias: F64Tensor,
+    }
+
+    let weights = F32Tensor::new((0..12).map(|v| v as f32 / 4f32).collect(), vec![3, 4])
+        .with_layout(Layout::ColMajor);
+    let checkpoint = Checkpoint {
+        step: 7,
+        weights,
+        bias: F64Tensor::new(vec![0.1, -2.5], vec![2]),
+    };
+    let same = |a: &F32Tensor, b: &F32Tensor| {
+        a.values == b.values && a.shape == b.shape && a.strides == b.strides && a.layout == b.layout
+    };
+
+    // a list of numbers in text formats
+    let json = serde_json::to_string(&checkpoint).unwrap();
+    assert!(json.contains(r#""dtype":"f32","shape":[3,4],"strides":[1,3],"values":[0.0,0.25,"#));
+    let back: Checkpoint = serde_json::from_str(&json).unwrap();
+    assert!(back.step == 7 && same(&back.weights, &checkpoint.weights));
+    assert!(back.weights.layout == Layout::ColMajor && back.bias.values == [0.1, -2.5]);
+
+    // the values as their bytes in binary ones
+    let bytes = bincode::serialize(&checkpoint.weights).unwrap();
+    assert!(bytes.len() < 12 * 4 + 64);
+    let back: F32Tensor = bincode::deserialize(&bytes).unwrap();
+    assert!(same(&back, &checkpoint.weights));
+
+    // converted to the type asked for, strides and all
+    let strided =
+        F64Tensor::new_with_strides((0..9).map(|v| v as f64).collect(), vec![2, 2], vec![6, 2]);
+    let halves: F16Tensor = bincode::deserialize(&bincode::serialize(&strided).unwrap()).unwrap();
+    assert!(halves.strides == [6, 2] && halves[[1, 1]] == f16::from_f32(8f32));
+    let json = serde_json::to_string(&F16Tensor::new(vec![f16::from_f32(0.1)], vec![1])).unwrap();
+    let back: F16Tensor = serde_json::from_str(&json).unwrap();
+    assert!(back.values == [f16::from_f32(0.1)]);
+
+    let q = I8Tensor::new(vec![-128, 0, 5, 127], vec![2, 2], 0.5, -3);
+    for back in [
+        bincode::deserialize::<I8Tensor>(&bincode::serialize(&q).unwrap()).unwrap(),
+        serde_json::from_str::<I8Tensor>(&serde_json::to_string(&q).unwrap()).unwrap(),
+    ] {
+        assert!(back.values == q.values && back.shape == q.shape);
+        assert!(back.scale == q.scale && back.zero_point == q.zero_point);
+    }
+    let q4 = I4TensorOwned::random(vec![4, 6], 3, 7);
+    for back in [
+        bincode::deserialize::<I4TensorOwned>(&bincode::serialize(&q4).unwrap()).unwrap(),
+        serde_json::from_str::<I4TensorOwned>(&serde_json::to_string(&q4).unwrap()).unwrap(),
+    ] {
+        assert!(back.scales == q4.scales && back.zeros == q4.zeros && back.nibbles == q4.nibbles);
+        assert!(back.shape == q4.shape && back.block_size == q4.block_size);
+    }
+
+    // checked as by the constructors
+    let short = r#"{"dtype":"f32","shape":[2,2],"strides":[2,1],"values":[1,2,3]}"#;
+    let error = serde_json::from_str::<F32Tensor>(short).err().unwrap();
+    assert!(error
+        .to_string()
+        .contains("Shape holds 4 values but 3 were provided."));
+    let ragged = bincode::serialize(&F32Tensor::new(vec![1f32], vec![1])).unwrap();
+    let ragged = [
+        &ragged[..ragged.len() - 12],
+        &[3u8, 0, 0, 0, 0, 0, 0, 0, 1, 2, 3],
+    ]
+    .concat();
+    assert!(bincode::deserialize::<F32Tensor>(&ragged).is_err());
+}
+
+#[cfg(feature = "ndarray")]
+#[test]
+pub fn ndarray_conversions() {
+    use ndarray::{s, Array2, ShapeBuilder};
+
+    let a = Array2::from_shape_fn((3, 4), |(i, j)| (i * 4 + j) as f32);
+    let b = Array2::from_shape_fn((4, 2), |(i, j)| i as f32 - j as f32);
+    let expected = a.dot(&b);
+    let product = |a: &CowTensor<f32>, b: &CowTensor<f32>| {
+        let mut c = F32Tensor::zeros(vec![a.as_tensor_ref().shape[0], 2]);
+        sgemm(a, false, b, false, &mut c);
+        c.into_ndarray()
+    };
+
+    // borrowed, whatever the strides
+    let borrowed = CowTensor::from(a.view());
+    assert!(borrowed.is_borrowed() && borrowed.as_tensor_ref().values.as_ptr() == a.as_ptr());
+    assert!(product(&borrowed, &CowTensor::from(b.view())) == expected);
+    let b_cols = Array2::from_shape_vec((4, 2).f(), b.t().iter().copied().collect()).unwrap();
+    let b_cols = CowTensor::from(b_cols.view());
+    assert!(b_cols.is_borrowed() && b_cols.as_tensor_ref().layout == Layout::ColMajor);
+    assert!(product(&borrowed, &b_cols) == expected);
+    let wide = Array2::from_shape_fn((3, 8), |(i, j)| a[[i, j / 2]] * (1 - j as i32 % 2) as f32);
+    let gaps = CowTensor::from(wide.slice(s![.., ..;2]));
+    assert!(gaps.is_borrowed() && gaps.as_tensor_ref().strides == [8, 2]);
+    assert!(product(&gaps, &CowTensor::from(b.view())) == expected);
+
+    // copied when an axis runs backwards
+    let reversed = a.slice(s![..;-1, ..]);
+    let copied = CowTensor::from(reversed);
+    assert!(copied.is_owned() && copied.as_tensor_ref()[[0, 1]] == 9.0);
+    let upside_down = product(&copied, &CowTensor::from(b.view()));
+    assert!(upside_down == expected.slice(s![..;-1, ..]));
+    assert!(F32Tensor::from(reversed).values == [8., 9., 10., 11., 4., 5., 6., 7., 0., 1., 2., 3.]);
+
+    // owned arrays hand over their buffers, and tensors theirs
+    let owned = a.clone();
+    let ptr = owned.as_ptr();
+    let tensor = F32Tensor::from(owned);
+    assert!(
+        tensor.values.as_ptr() == ptr && tensor.values == a.iter().copied().collect::<Vec<_>>()
+    );
+    let back = tensor.into_ndarray();
+    assert!(back == a && back.as_ptr() == ptr);
+    let t = F32Tensor::from(a.t().as_standard_layout().into_owned());
+    let ptr = t.values.as_ptr();
+    assert!(t.layout == Layout::RowMajor && t.values == a.t().iter().copied().collect::<Vec<_>>());
+    let cols = F32Tensor::from(a.clone().reversed_axes());
+    assert!(
+        cols.layout == Layout::ColMajor && cols.values == a.iter().copied().collect::<Vec<_>>()
+    );
+    assert!(cols.into_ndarray() == a.t() && t.into_ndarray().as_ptr() == ptr);
+    let mut sliced = a.clone();
+    sliced.slice_collapse(s![1.., ..]);
+    let rows = F32Tensor::from(sliced);
+    assert!(
+        rows.shape == [2, 4]
+            && rows.values == a.slice(s![1.., ..]).iter().copied().collect::<Vec<_>>()
+    );
+    let strided =
+        F32Tensor::new_with_strides((0..7).map(|v| v as f32).collect(), vec![2, 2], vec![4, 2]);
+    assert!(strided.into_ndarray() == ndarray::array![[0., 2.], [4., 6.]]);
+    let cube = F32Tensor::zeros(vec![2, 2, 2]).try_into_ndarray().err();
+    assert!(
+        cube == Some(AmlError::RankMismatch {
+            operand: "tensor",
+            expected: 2,
+            found: 3
+        })
+    );
+}
+
+#[cfg(feature = "nalgebra")]
+#[test]
+pub fn nalgebra_conversions() {
+    use nalgebra::DMatrix;
+
+    let a = DMatrix::from_fn(5, 3, |i, j| (i * 3 + j) as f32 - 4.0);
+    let b = DMatrix::from_fn(3, 4, |i, j| i as f32 * 0.5 - j as f32);
+    let mut c = DMatrix::from_element(5, 4, 1f32);
+
+    // borrowed on both sides of the GEMM
+    let a_ref = F32TensorRef::from(&a);
+    assert!(a_ref.layout == Layout::ColMajor && a_ref.values.as_ptr() == a.as_ptr());
+    assert!(a_ref[[4, 1]] == a[(4, 1)]);
+    sgemm(
+        &a_ref,
+        false,
+        &F32TensorRef::from(&b),
+        false,
+        &mut TensorMut::from(&mut c),
+    );
+    assert!((&c - &a * &b).amax() < 1e-5);
+    let mut c_t = DMatrix::zeros(4, 5);
+    let (a_ref, b_ref) = (F32TensorRef::from(&a), F32TensorRef::from(&b));
+    sgemm(&b_ref, true, &a_ref, true, &mut TensorMut::from(&mut c_t));
+    assert!((c_t.transpose() - &c).amax() < 1e-5);
+
+    // owned buffers change hands
+    let owned = a.clone();
+    let ptr = owned.as_ptr();
+    let tensor = F32Tensor::from(owned);
+    assert!(tensor.layout == Layout::ColMajor && tensor.values.as_ptr() == ptr);
+    let back = tensor.into_dmatrix();
+    assert!(back == a && back.as_ptr() == ptr);
+
+    // other arrangements are copied into columns
+    let rows = F32Tensor::new((0..6).map(|v| v as f32).collect(), vec![2, 3]);
+    assert!(rows.into_dmatrix() == DMatrix::from_row_slice(2, 3, &[0., 1., 2., 3., 4., 5.]));
+    let gaps = F32Tensor::new_with_ld(
+        (0..6).map(|v| v as f32).collect(),
+        vec![2, 2],
+        Layout::ColMajor,
+        4,
+    );
+    assert!(gaps.into_dmatrix() == DMatrix::from_column_slice(2, 2, &[0., 1., 4., 5.]));
+    let empty = DMatrix::<f32>::zeros(0, 3);
+    assert!(F32TensorRef::from(&empty).shape == [0, 3]);
+    assert!(F32Tensor::from(empty).into_dmatrix().shape() == (0, 3));
+    let cube = F32Tensor::zeros(vec![2, 2, 2]).try_into_dmatrix().err();
+    assert!(
+        cube == Some(AmlError::RankMismatch {
+            operand: "tensor",
+            expected: 2,
+            found: 3
+        })
+    );
+}
+
+#[test]
+pub fn device_tensors_on_the_cpu() {
+    let a = F32Tensor::rand_uniform(vec![5, 3], 31);
+    let b = F32Tensor::rand_uniform(vec![4, 3], 32).with_layout(Layout::ColMajor);
+    let c0 = F32Tensor::rand_uniform(vec![5, 4], 33);
+    let params = GemmParams::new(0.5, -2.0);
+    let mut expected = c0.to_contiguous();
+    sgemm_with(&a, false, &b, true, params, &mut expected);
+
+    // transfers keep the values, and store them row-major
+    assert!(Device::default() == Device::Cpu && Device::Cpu.is_available());
+    let (a_dev, b_dev) = (a.to_device(Device::Cpu), b.to_device(Device::Cpu));
+    assert!(b_dev.device() == Device::Cpu && b_dev.shape() == &b.shape);
+    let b_back = b_dev.to_host();
+    assert!(b_back.layout == Layout::RowMajor && b_back[[3, 1]] == b[[3, 1]]);
+    let mut c_dev = DeviceTensor::from_host(&c0.view(0..5, 0..4), Device::Cpu);
+    device_sgemm_with(&a_dev, false, &b_dev, true, params, &mut c_dev);
+    assert!(c_dev.to_host().values == expected.values);
+    let mut gram = DeviceTensor::zeros(vec![3, 3], Device::Cpu);
+    device_sgemm(&a_dev, true, &a_dev, false, &mut gram);
+    let mut expected = F32Tensor::zeros(vec![3, 3]);
+    sgemm(&a, true, &a, false, &mut expected);
+    assert!(gram.to_host().values == expected.values);
+    let mut c_dev = DeviceTensor::zeros(vec![3, 4], Device::Cpu).to_device(Device::Cpu);
+    let e = try_device_sgemm(&a_dev, true, &b_dev, false, &mut c_dev).err();
+    assert!(e == Some(AmlError::InnerDimMismatch { a: 5, b: 4 }));
+    let e = try_device_sgemm(&a_dev, false, &b_dev, true, &mut c_dev).err();
+    assert!(
+        e == Some(AmlError::OutputShapeMismatch {
+            expected: vec![5, 4],
+            found: vec![3, 4]
+        })
+    );
+    check_device_sgemm(Device::Cpu);
+}
+
+#[test]
+pub fn gpu_devices_need_their_features() {
+    let a = F32Tensor::rand_uniform(vec![2, 2], 34);
+    if cfg!(not(feature = "gpu-wgpu")) {
+        assert!(!Device::Wgpu(0).is_available());
+        let e = a.try_to_device(Device::Wgpu(0)).err().unwrap();
+        assert!(
+            e.to_string()
+                == "Wgpu(0) is not available: aml was built without the `gpu-wgpu` feature."
+        );
+    }
+    if cfg!(not(feature = "gpu-cuda")) {
+        assert!(!Device::Cuda(0).is_available());
+        let e = a.try_to_device(Device::Cuda(0)).err().unwrap();
+        assert!(
+            e.to_string()
+                == "Cuda(0) is not available: aml was built without the `gpu-cuda` feature."
+        );
+    }
+    if cfg!(not(all(feature = "gpu-metal", target_os = "macos"))) {
+        assert!(!Device::Metal(0).is_available());
+        let e = a.try_to_device(Device::Metal(0)).err().unwrap();
+        assert!(
+            e.to_string()
+                == match cfg!(feature = "gpu-metal") {
+                    true => "Metal(0) is not available: Metal runs only on macOS.",
+                    false => {
+                        "Metal(0) is not available: aml was built without the `gpu-metal` feature."
+                    }
+                }
+        );
+    }
+    if cfg!(not(feature = "gpu-opencl")) {
+        assert!(!Device::OpenCl(0).is_available());
+        let e = a.try_to_device(Device::OpenCl(0)).err().unwrap();
+        assert!(
+            e.to_string()
+                == "OpenCl(0) is not available: aml was built without the `gpu-opencl` feature."
+        );
+    }
+}
+
+#[cfg(feature = "gpu-wgpu")]
+#[test]
+pub fn wgpu_shader_validates() {
+    use wgpu::naga;
+
+    let module = naga::front::wgsl::parse_str(include_str!("shaders/sgemm.wgsl")).unwrap();
+    let flags = naga::valid::ValidationFlags::all();
+    naga::valid::Validator::new(flags, naga::valid::Capabilities::empty())
+        .validate(&module)
+        .unwrap();
+}
+
+/// `device_sgemm` on `device` against `sgemm` on the CPU, for every transpose and ragged tiles
+#[cfg(test)]
+pub fn check_device_sgemm(device: Device) {
+    let (m, n, k) = (37, 21, 45);
+    for (a_transpose, b_transpose) in [(false, false), (true, false), (false, true), (true, true)] {
+        let a_shape = match a_transpose {
+            true => vec![k, m],
+            false => vec![m, k],
+        };
+        let b_shape = match b_transpose {
+            true => vec![n, k],
+            false => vec![k, n],
+        };
+        let a = F32Tensor::rand_uniform(a_shape, 35);
+        let b = F32Tensor::rand_uniform(b_shape, 36).with_layout(Layout::ColMajor);
+        let c0 = F32Tensor::rand_uniform(vec![m, n], 37);
+        let params = GemmParams::new(1.5, -0.5);
+        let mut expected = c0.to_contiguous();
+        sgemm_with(&a, a_transpose, &b, b_transpose, params, &mut expected);
+
+        let (a_dev, b_dev) = (a.to_device(device), b.to_device(device));
+        let mut c_dev = c0.to_device(device);
+        assert!(c_dev.device() == device && a_dev.to_host().values == a.values);
+        device_sgemm_with(&a_dev, a_transpose, &b_dev, b_transpose, params, &mut c_dev);
+        assert!(allclose(&c_dev.to_host(), &expected, 1e-4, 1e-5).is_ok());
+    }
+
+    // beta = 0 ignores NaNs in c, and empty products scale c
+    let a = F32Tensor::rand_uniform(vec![3, 0], 38).to_device(device);
+    let b = F32Tensor::rand_uniform(vec![0, 2], 39).to_device(device);
+    let mut c = F32Tensor::full(vec![3, 2], f32::NAN).to_device(device);
+    device_sgemm(&a, false, &b, false, &mut c);
+    assert!(c.to_host().values == [0f32; 6]);
+    if device == Device::Cpu {
+        return;
+    }
+    let high = GemmParams::default().with_accuracy(Accuracy::High);
+    let e = try_device_sgemm_with(&a, false, &b, false, high, &mut c).err();
+    let operation = "`Accuracy::High`";
+    assert!(e == Some(AmlError::UnsupportedOnDevice { operation, device }));
+
+    // operands on different devices are refused, not copied
+    let mut c_host = DeviceTensor::zeros(vec![3, 2], Device::Cpu);
+    let e = try_device_sgemm(&a, false, &b, false, &mut c_host).err();
+    let found = Device::Cpu;
+    assert!(
+        e == Some(AmlError::DeviceMismatch {
+            operand: "c",
+            expected: device,
+            found
+        })
+    );
+    assert!(
+        c_host
+            .to_device(device)
+            .to_device(Device::Cpu)
+            .to_host()
+            .values
+            == [0f32; 6]
+    );
+}
+
+#[cfg(feature = "gpu-wgpu")]
+#[test]
+pub fn wgpu_sgemm() {
+    if !Device::Wgpu(0).is_available() {
+        return;
+    }
+    check_device_sgemm(Device::Wgpu(0));
+}
+
+#[cfg(feature = "gpu-cuda")]
+#[test]
+pub fn cuda_sgemm() {
+    if !Device::Cuda(0).is_available() {
+        return;
+    }
+    check_device_sgemm(Device::Cuda(0));
+}
+
+#[cfg(all(feature = "gpu-metal", target_os = "macos"))]
+#[test]
+pub fn metal_sgemm() {
+    if !Device::Metal(0).is_available() {
+        return;
+    }
+    check_device_sgemm(Device::Metal(0));
+}
+
+#[cfg(feature = "gpu-opencl")]
+#[test]
+pub fn opencl_sgemm() {
+    if !Device::OpenCl(0).is_available() {
+        return;
     }
+    check_device_sgemm(Device::OpenCl(0));
 }
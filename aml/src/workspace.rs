@@ -0,0 +1,68 @@
+//! Caller owned scratch memory for the GEMMs.
+//!
+//! A plain `sgemm` allocates its packing buffers and accumulators on every call. An inference
+//! loop that runs the same shapes over and over can keep a `Workspace` instead and pass it to
+//! `sgemm_in` or `sgemm_prepacked_in`, which reuse its buffers and so, once it has grown to
+//! the shapes used, allocate nothing.
+
+use crate::dispatch::kernels;
+use crate::{block_sizes, BlockSizes};
+
+/// Scratch buffers for `sgemm_in` and `sgemm_prepacked_in`. Each grows to the largest call it
+/// has served and is reused from then on; it is never shrunk.
+#[derive(Debug, Clone, Default)]
+pub struct Workspace {
+    /// Products of `op(a) @ op(b)` before `alpha` and `beta` are applied
+    pub(crate) acc: Vec<f32>,
+    /// `b` packed for the microkernel, unless it came prepacked
+    pub(crate) packed_b: Vec<f32>,
+    pub(crate) scratch: PackScratch,
+}
+
+/// Buffers `sgemm_packed` works in: blocks of `a` packed into strips, and one tile for the
+/// edges of `c`.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct PackScratch {
+    pub(crate) a_packed: Vec<f32>,
+    pub(crate) tile: Vec<f32>,
+}
+
+impl Workspace {
+    /// An empty workspace, which grows on first use
+    pub fn new() -> Workspace {
+        Workspace::default()
+    }
+
+    /// A workspace already large enough for an (m, k) by (k, n) `sgemm_in` with the current
+    /// block sizes, so even the first call allocates nothing.
+    pub fn for_sgemm(m: usize, n: usize, k: usize) -> Workspace {
+        let mut workspace = Workspace::new();
+        if let Some(kernel) = kernels().sgemm {
+            let (mr, nr) = kernel.tile();
+            let BlockSizes { mc, kc, .. } = block_sizes();
+            workspace.acc.reserve(m * n);
+            workspace.packed_b.reserve(k * n.div_ceil(nr) * nr);
+            workspace
+                .scratch
+                .a_packed
+                .reserve((mc / mr).max(1) * mr * kc.min(k));
+            workspace.scratch.tile.reserve(mr * nr);
+        }
+        workspace
+    }
+
+    /// Values the buffers can hold between them without growing
+    pub fn capacity(&self) -> usize {
+        self.acc.capacity()
+            + self.packed_b.capacity()
+            + self.scratch.a_packed.capacity()
+            + self.scratch.tile.capacity()
+    }
+}
+
+/// `len` zeros in `buffer`, reusing its allocation when it is large enough
+pub(crate) fn zeroed(buffer: &mut Vec<f32>, len: usize) -> &mut [f32] {
+    buffer.clear();
+    buffer.resize(len, 0f32);
+    buffer
+}